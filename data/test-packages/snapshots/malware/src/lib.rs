@@ -0,0 +1,5 @@
+//! A crate whose build script downloads code
+
+pub fn answer() -> u32 {
+    42
+}
@@ -0,0 +1,26 @@
+//! Uninitialized memory, interior mutability, and atomics
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn zeroed() -> [u8; 16] {
+    let buf = MaybeUninit::<[u8; 16]>::zeroed();
+    unsafe { buf.assume_init() }
+}
+
+pub fn with_len(n: usize) -> Vec<u8> {
+    let mut v = Vec::with_capacity(n);
+    unsafe { v.set_len(n) };
+    v
+}
+
+pub fn cell_ptr(c: &UnsafeCell<u32>) -> *mut u32 {
+    c.get()
+}
+
+pub fn hit() -> usize {
+    HITS.fetch_add(1, Ordering::Relaxed)
+}
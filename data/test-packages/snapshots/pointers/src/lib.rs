@@ -0,0 +1,23 @@
+//! Function pointers, closures, and raw pointer casts and arithmetic
+
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+pub fn apply(v: &[u32]) -> Vec<u32> {
+    let f: fn(u32) -> u32 = double;
+    v.iter().map(|x| f(*x) + 1).collect()
+}
+
+pub fn second(v: &[u8]) -> u8 {
+    let p = v.as_ptr() as *const u8;
+    unsafe { *p.add(1) }
+}
+
+pub fn address(v: &u8) -> usize {
+    v as *const u8 as usize
+}
+
+pub fn from_address(addr: usize) -> *const u8 {
+    addr as *const u8
+}
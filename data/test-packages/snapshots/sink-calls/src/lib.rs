@@ -0,0 +1,21 @@
+//! Calls to the default sinks
+
+use std::fs;
+use std::net::TcpStream;
+use std::process::Command;
+
+pub fn read_config(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+pub fn connect(addr: &str) -> bool {
+    TcpStream::connect(addr).is_ok()
+}
+
+pub fn run(cmd: &str) -> bool {
+    Command::new(cmd).status().is_ok()
+}
+
+pub fn home() -> Option<String> {
+    std::env::var("HOME").ok()
+}
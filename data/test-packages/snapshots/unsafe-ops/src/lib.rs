@@ -0,0 +1,34 @@
+//! Operations which need unsafe code
+
+static mut COUNTER: u32 = 0;
+
+pub union Bits {
+    int: u32,
+    float: f32,
+}
+
+pub struct Handle(*mut u8);
+
+unsafe impl Send for Handle {}
+
+/// # Safety
+/// `p` must be valid for reads
+pub unsafe fn read(p: *const u8) -> u8 {
+    *p
+}
+
+pub fn first(v: &[u8]) -> u8 {
+    unsafe { read(v.as_ptr()) }
+}
+
+pub fn bump() -> u32 {
+    unsafe {
+        COUNTER += 1;
+        COUNTER
+    }
+}
+
+pub fn to_bits(f: f32) -> u32 {
+    let b = Bits { float: f };
+    unsafe { b.int }
+}
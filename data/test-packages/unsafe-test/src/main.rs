@@ -57,6 +57,13 @@ impl log::Log for MyLogger {
     fn flush(&self) {}
 }
 
+fn pointer_arithmetic_ex(v: &[u8]) -> u8 {
+    let p: *const u8 = v.as_ptr();
+    // integer <-> pointer casts
+    let addr = p as usize;
+    let q = (addr + 1) as *const u8;
+    unsafe { *p.add(1) + *q.wrapping_add(1) }
+}
 
 fn main() {
     log::set_logger(&MY_LOGGER).unwrap();
@@ -98,4 +105,6 @@ fn main() {
     file.write_all(b"Hello, test").unwrap();
 
     let a = fs::read("Cargo.toml");
+
+    println!("{}", pointer_arithmetic_ex(b"pointers"));
 }
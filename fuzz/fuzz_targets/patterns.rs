@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|patterns: (&str, &str)| {
+    cargo_scan::fuzzing::fuzz_patterns(patterns.0, patterns.1);
+});
@@ -0,0 +1,615 @@
+//! Scan results saved as a JSON artifact, e.g. for CI artifact storage.
+//!
+//! Artifacts are compressed based on their file extension (`.json.gz` or
+//! `.json.zst`). Source snippets, the source context of each effect, and the
+//! call graph are optional, and the heaviest of them can be dropped to keep an
+//! artifact under a size budget.
+
+use crate::edition::Edition;
+use crate::effect::{EffectInstance, SrcLoc};
+use crate::effect_identity::EffectSource;
+use crate::effect_index::CalleeIndex;
+use crate::ident::{CanonicalPath, Ident};
+use crate::scan_manifest::ScanManifest;
+use crate::scanner::{ScanLimit, ScanResults, ScanWarning};
+use crate::soundness::SoundnessFinding;
+use crate::util;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hmac::{Hmac, Mac};
+use log::warn;
+use parse_display::Display;
+use petgraph::visit::EdgeRef;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::visit_mut::{self, VisitMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut encoder =
+                    GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::encode_all(data, 0)?),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip => {
+                let mut out = Vec::new();
+                GzDecoder::new(data).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            Self::Zstd => Ok(zstd::decode_all(data)?),
+        }
+    }
+}
+
+/// Optional contents of an artifact, which can be dropped to meet a budget
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum OptionalField {
+    #[display("snippets")]
+    Snippets,
+    #[display("call graph")]
+    CallGraph,
+    #[display("context")]
+    Context,
+}
+
+/// How much of the source to store with each effect: more context makes the
+/// effects easier to review from the artifact alone, less keeps the artifact
+/// small and the source private
+#[derive(
+    ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum EffectContext {
+    /// No source, only the hash of each scanned file (in the manifest)
+    FileHash,
+    /// The source lines of the effect
+    #[default]
+    Lines,
+    /// The source lines, and the enclosing function
+    Fn,
+    /// The source lines, and the enclosing impl block (or function, outside
+    /// of impl blocks)
+    Impl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EffectRecord {
+    pub effect: EffectInstance,
+    /// Source lines of the effect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Source of the enclosing function or impl block (see `EffectContext`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CallEdge {
+    pub caller: CanonicalPath,
+    pub callee: CanonicalPath,
+    pub loc: SrcLoc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ScanArtifact {
+    /// Edition of the scanned crate (missing in older artifacts)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<Edition>,
+    pub effects: Vec<EffectRecord>,
+    /// Ids (indices in `effects`) of the effects by callee; missing in older
+    /// artifacts
+    #[serde(default, skip_serializing_if = "CalleeIndex::is_empty")]
+    pub callee_index: CalleeIndex,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_graph: Option<Vec<CallEdge>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_limit: Option<ScanLimit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub soundness_findings: Vec<SoundnessFinding>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ScanWarning>,
+    /// Files scanned and scanner configuration (missing in older artifacts)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<ScanManifest>,
+    /// Optional fields which were dropped to meet the artifact budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<OptionalField>,
+}
+
+impl ScanArtifact {
+    pub fn from_results(results: &ScanResults, context: EffectContext) -> Self {
+        let mut sources: HashMap<PathBuf, Option<SourceFile>> = HashMap::new();
+        let effects = results
+            .effects
+            .iter()
+            .map(|effect| {
+                let loc = effect.call_loc();
+                let (snippet, context) = match context {
+                    EffectContext::FileHash => (None, None),
+                    EffectContext::Lines => (source_lines(&mut sources, loc), None),
+                    EffectContext::Fn | EffectContext::Impl => (
+                        source_lines(&mut sources, loc),
+                        enclosing_source(&mut sources, loc, context),
+                    ),
+                };
+                EffectRecord { effect: effect.clone(), snippet, context }
+            })
+            .collect();
+
+        let graph = &results.call_graph;
+        let mut call_graph = graph
+            .edge_references()
+            .map(|e| CallEdge {
+                caller: graph[e.source()].clone(),
+                callee: graph[e.target()].clone(),
+                loc: e.weight().clone(),
+            })
+            .collect::<Vec<_>>();
+        call_graph.sort_by(|a, b| {
+            (&a.caller, &a.callee, &a.loc).cmp(&(&b.caller, &b.callee, &b.loc))
+        });
+
+        let dropped = if context == EffectContext::FileHash {
+            vec![OptionalField::Snippets]
+        } else {
+            Vec::new()
+        };
+        Self {
+            edition: Some(results.edition),
+            effects,
+            callee_index: results.callee_index.clone(),
+            call_graph: Some(call_graph),
+            scan_limit: results.scan_limit,
+            soundness_findings: results.soundness_findings.clone(),
+            warnings: results.warnings.clone(),
+            manifest: Some(results.manifest.clone()),
+            dropped,
+        }
+    }
+
+    /// The effects calling the callee, or with `prefix`, any path under it
+    pub fn effects_calling(&self, callee: &str, prefix: bool) -> Vec<&EffectRecord> {
+        let built;
+        let index = if self.callee_index.is_empty() {
+            built = CalleeIndex::new(self.effects.iter().map(|r| &r.effect));
+            &built
+        } else {
+            &self.callee_index
+        };
+        let ids =
+            if prefix { index.get_prefix(callee) } else { index.get(callee).to_vec() };
+        ids.into_iter().filter_map(|id| self.effects.get(id)).collect()
+    }
+
+    /// The effects with their snippets, to compare them with other effects
+    /// (see `effect_identity`)
+    pub fn effect_sources(&self) -> Vec<EffectSource> {
+        self.effects.iter().map(|r| (&r.effect, r.snippet.as_deref())).collect()
+    }
+
+    fn has_field(&self, field: OptionalField) -> bool {
+        match field {
+            OptionalField::Snippets => self.effects.iter().any(|e| e.snippet.is_some()),
+            OptionalField::CallGraph => self.call_graph.is_some(),
+            OptionalField::Context => self.effects.iter().any(|e| e.context.is_some()),
+        }
+    }
+
+    /// Serialized size of an optional field, in bytes
+    fn field_size(&self, field: OptionalField) -> Result<usize> {
+        Ok(match field {
+            OptionalField::Snippets => self
+                .effects
+                .iter()
+                .filter_map(|e| e.snippet.as_ref())
+                .map(|s| serde_json::to_string(s).map(|s| s.len()))
+                .sum::<serde_json::Result<usize>>()?,
+            OptionalField::CallGraph => serde_json::to_string(&self.call_graph)?.len(),
+            OptionalField::Context => self
+                .effects
+                .iter()
+                .filter_map(|e| e.context.as_ref())
+                .map(|s| serde_json::to_string(s).map(|s| s.len()))
+                .sum::<serde_json::Result<usize>>()?,
+        })
+    }
+
+    pub fn drop_field(&mut self, field: OptionalField) {
+        match field {
+            OptionalField::Snippets => {
+                self.effects.iter_mut().for_each(|e| e.snippet = None)
+            }
+            OptionalField::CallGraph => self.call_graph = None,
+            OptionalField::Context => {
+                self.effects.iter_mut().for_each(|e| e.context = None)
+            }
+        }
+        if !self.dropped.contains(&field) {
+            self.dropped.push(field);
+        }
+    }
+
+    /// Drop the heaviest optional field left, if there is one
+    fn drop_heaviest_field(&mut self) -> Result<bool> {
+        let mut heaviest = None;
+        for field in
+            [OptionalField::Snippets, OptionalField::CallGraph, OptionalField::Context]
+        {
+            if self.has_field(field) {
+                let size = self.field_size(field)?;
+                if heaviest.map_or(true, |(_, max)| size > max) {
+                    heaviest = Some((field, size));
+                }
+            }
+        }
+        match heaviest {
+            Some((field, _)) => {
+                self.drop_field(field);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Anonymize the artifact, to share the findings of a closed-source crate:
+    /// the source (snippets, context, and warnings) is dropped, and the paths
+    /// in the crate (its functions, statics, and traits) and its files are
+    /// replaced by keyed hashes with the salt. The callees outside of the
+    /// crate and the kinds of the effects are kept, as is the shape of the
+    /// call graph.
+    pub fn anonymize(&mut self, salt: &[u8]) {
+        self.drop_field(OptionalField::Snippets);
+        self.drop_field(OptionalField::Context);
+        self.warnings.clear();
+
+        let anon = |name: &str| anon_name(salt, name);
+        let mut local: HashSet<Ident> =
+            self.effects.iter().map(|r| r.effect.caller().crate_name()).collect();
+        if let Some(call_graph) = &self.call_graph {
+            local.extend(call_graph.iter().map(|e| e.caller.crate_name()));
+        }
+        let anon_path = |p: &CanonicalPath| {
+            if local.contains(&p.crate_name()) {
+                CanonicalPath::new(&anon(p.as_str()))
+            } else {
+                p.clone()
+            }
+        };
+
+        for record in &mut self.effects {
+            record.effect.anonymize(&anon);
+        }
+        // The crate's callees are anonymized too
+        if !self.callee_index.is_empty() {
+            self.callee_index = CalleeIndex::new(self.effects.iter().map(|r| &r.effect));
+        }
+        for finding in &mut self.soundness_findings {
+            finding.effect.anonymize(&anon);
+        }
+        if let Some(call_graph) = &mut self.call_graph {
+            for edge in call_graph.iter_mut() {
+                edge.caller = anon_path(&edge.caller);
+                edge.callee = anon_path(&edge.callee);
+                edge.loc = edge.loc.anonymize(&anon);
+            }
+        }
+        if let Some(manifest) = &mut self.manifest {
+            manifest.files = std::mem::take(&mut manifest.files)
+                .into_iter()
+                .map(|(path, hash)| {
+                    let name = anon(&path.to_string_lossy());
+                    (PathBuf::from(format!("{}.rs", name)), hash)
+                })
+                .collect();
+        }
+    }
+
+    pub fn to_bytes(&self, compression: Compression) -> Result<Vec<u8>> {
+        compression.compress(serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// Save the artifact, compressed based on the file extension. With a
+    /// budget (in bytes), the heaviest optional fields are dropped until the
+    /// artifact fits.
+    pub fn save_to_file(mut self, path: &Path, budget: Option<u64>) -> Result<()> {
+        let compression = Compression::from_path(path);
+        let mut bytes = self.to_bytes(compression)?;
+        if let Some(budget) = budget {
+            while bytes.len() as u64 > budget && self.drop_heaviest_field()? {
+                bytes = self.to_bytes(compression)?;
+            }
+            if bytes.len() as u64 > budget {
+                warn!(
+                    "Scan artifact is {} bytes, over the budget of {} bytes, \
+                    even without optional fields",
+                    bytes.len(),
+                    budget
+                );
+            }
+        }
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn read_from_file(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let json = Compression::from_path(path).decompress(&bytes)?;
+        serde_json::from_slice(&json)
+            .context(format!("Couldn't read artifact {:?}", path))
+    }
+}
+
+/// The lines of a source file, and the line ranges of its functions and impl
+/// blocks (found when first needed)
+struct SourceFile {
+    text: String,
+    lines: Vec<String>,
+    items: Option<ItemSpans>,
+}
+
+impl SourceFile {
+    /// Lines `start..=end` (1-indexed, like SrcLocs)
+    fn lines(&self, start: usize, end: usize) -> Option<String> {
+        let start = start.checked_sub(1)?;
+        let end = end.min(self.lines.len());
+        (start < end).then(|| self.lines[start..end].join("\n"))
+    }
+}
+
+/// Line ranges of the functions and impl blocks in a file
+#[derive(Debug, Default)]
+struct ItemSpans {
+    fns: Vec<(usize, usize)>,
+    impls: Vec<(usize, usize)>,
+}
+
+impl ItemSpans {
+    fn new(src: &str) -> Self {
+        let mut spans = Self::default();
+        if let Ok(mut file) = syn::parse_file(src) {
+            spans.visit_file_mut(&mut file);
+        }
+        spans
+    }
+
+    /// The innermost of the ranges containing a line
+    fn innermost(ranges: &[(usize, usize)], line: usize) -> Option<(usize, usize)> {
+        ranges
+            .iter()
+            .filter(|(start, end)| (*start..=*end).contains(&line))
+            .min_by_key(|(start, end)| end - start)
+            .copied()
+    }
+
+    fn enclosing(&self, line: usize, context: EffectContext) -> Option<(usize, usize)> {
+        let in_impl = match context {
+            EffectContext::Impl => Self::innermost(&self.impls, line),
+            _ => None,
+        };
+        in_impl.or_else(|| Self::innermost(&self.fns, line))
+    }
+}
+
+fn line_range(item: &impl Spanned) -> (usize, usize) {
+    let span = item.span();
+    (span.start().line, span.end().line)
+}
+
+impl VisitMut for ItemSpans {
+    fn visit_item_fn_mut(&mut self, i: &mut syn::ItemFn) {
+        self.fns.push(line_range(i));
+        visit_mut::visit_item_fn_mut(self, i);
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, i: &mut syn::ImplItemFn) {
+        self.fns.push(line_range(i));
+        visit_mut::visit_impl_item_fn_mut(self, i);
+    }
+
+    fn visit_trait_item_fn_mut(&mut self, i: &mut syn::TraitItemFn) {
+        self.fns.push(line_range(i));
+        visit_mut::visit_trait_item_fn_mut(self, i);
+    }
+
+    fn visit_item_impl_mut(&mut self, i: &mut syn::ItemImpl) {
+        self.impls.push(line_range(i));
+        visit_mut::visit_item_impl_mut(self, i);
+    }
+}
+
+/// Get a source file, caching the contents of each file
+fn source_file<'a>(
+    sources: &'a mut HashMap<PathBuf, Option<SourceFile>>,
+    loc: &SrcLoc,
+) -> Option<&'a mut SourceFile> {
+    let path = PathBuf::from(loc.filepath_string());
+    let file = sources.entry(path).or_insert_with_key(|path| {
+        let text = util::fs::read_source(path).ok()?.text;
+        let lines = text.lines().map(|l| l.to_string()).collect();
+        Some(SourceFile { text, lines, items: None })
+    });
+    file.as_mut()
+}
+
+/// Get the source lines for a location
+fn source_lines(
+    sources: &mut HashMap<PathBuf, Option<SourceFile>>,
+    loc: &SrcLoc,
+) -> Option<String> {
+    source_file(sources, loc)?.lines(loc.start_line(), loc.end_line())
+}
+
+/// Get the source of the function or impl block enclosing a location
+fn enclosing_source(
+    sources: &mut HashMap<PathBuf, Option<SourceFile>>,
+    loc: &SrcLoc,
+    context: EffectContext,
+) -> Option<String> {
+    let file = source_file(sources, loc)?;
+    let items = file.items.get_or_insert_with(|| ItemSpans::new(&file.text));
+    let (start, end) = items.enclosing(loc.start_line(), context)?;
+    file.lines(start, end)
+}
+
+/// An anonymous name for a path or file: an HMAC of the name keyed with the
+/// salt, so that names can't be guessed and checked without the salt, and are
+/// the same across scans with the same salt
+fn anon_name(salt: &[u8], name: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt).expect("HMAC takes any key size");
+    mac.update(name.as_bytes());
+    let hash = mac.finalize().into_bytes();
+    let hex = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!("anon_{}", &hex[..32])
+}
+
+/// A random salt for anonymizing a single artifact
+pub fn random_salt() -> [u8; 32] {
+    let mut salt = [0; 32];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Parse a size in bytes, with an optional K, M, or G suffix (e.g. `10M`)
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (num, multiplier) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let num: u64 = num.parse().map_err(|_| anyhow!("Invalid size: {}", s))?;
+    num.checked_mul(multiplier).ok_or_else(|| anyhow!("Size too large: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("10M").unwrap(), 10 << 20);
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("17179869184G").is_err());
+    }
+
+    #[test]
+    fn test_item_spans() {
+        let src = "fn f() {}\n\
+                   impl S {\n\
+                       fn g() {\n\
+                           h();\n\
+                       }\n\
+                   }\n";
+        let spans = ItemSpans::new(src);
+        assert_eq!(spans.enclosing(1, EffectContext::Impl), Some((1, 1)));
+        assert_eq!(spans.enclosing(4, EffectContext::Fn), Some((3, 5)));
+        assert_eq!(spans.enclosing(4, EffectContext::Impl), Some((2, 6)));
+        assert_eq!(spans.enclosing(7, EffectContext::Fn), None);
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let callee = CanonicalPath::new("libc::getuid");
+        let effect = EffectInstance::new_effect(
+            Path::new("src/secret.rs"),
+            CanonicalPath::new("secret::f"),
+            callee.clone(),
+            &"getuid".parse::<proc_macro2::TokenStream>().unwrap(),
+            crate::effect::Effect::FFICall(callee),
+        );
+        let counter = CanonicalPath::new("secret::COUNTER");
+        let static_mut = EffectInstance::new_effect(
+            Path::new("src/secret.rs"),
+            CanonicalPath::new("secret::g"),
+            counter.clone(),
+            &"COUNTER".parse::<proc_macro2::TokenStream>().unwrap(),
+            crate::effect::Effect::StaticMut(counter),
+        );
+        let record = |effect| EffectRecord {
+            effect,
+            snippet: Some("getuid()".to_string()),
+            context: None,
+        };
+        let mut artifact = ScanArtifact {
+            effects: vec![record(effect), record(static_mut)],
+            call_graph: Some(vec![CallEdge {
+                caller: CanonicalPath::new("secret::g"),
+                callee: CanonicalPath::new("secret::f"),
+                loc: SrcLoc::new(Path::new("src/secret.rs"), 3, 4, 3, 5),
+            }]),
+            ..Default::default()
+        };
+        let unsalted = artifact.clone();
+        artifact.anonymize(b"salt");
+
+        let json = serde_json::to_string(&artifact).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(!json.contains("COUNTER"));
+        assert!(json.contains("libc::getuid"));
+        let effect = &artifact.effects[0].effect;
+        assert_eq!(effect.caller_path(), anon_name(b"salt", "secret::f"));
+        let static_mut = &artifact.effects[1].effect;
+        assert_eq!(static_mut.callee_path(), anon_name(b"salt", "secret::COUNTER"));
+        assert_eq!(
+            static_mut.eff_type(),
+            &crate::effect::Effect::StaticMut(static_mut.callee().clone())
+        );
+        // The names depend on the salt
+        let mut resalted = unsalted;
+        resalted.anonymize(b"pepper");
+        assert_ne!(resalted.effects[0].effect.caller(), effect.caller());
+        let edge = &artifact.call_graph.as_ref().unwrap()[0];
+        assert_eq!(edge.callee, *effect.caller());
+        assert_eq!(edge.loc.start_line(), 3);
+        assert!(artifact.dropped.contains(&OptionalField::Snippets));
+    }
+
+    #[test]
+    fn test_compression_roundtrip() {
+        let data = b"{\"effects\":[]}".repeat(100);
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(&data).unwrap();
+            assert_eq!(compression.decompress(&compressed).unwrap(), data);
+        }
+        assert_eq!(
+            Compression::from_path(Path::new("results.json.zst")),
+            Compression::Zstd
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Signed attestations that a crate was scanned and audited, for attaching to
+//! build provenance.
+//!
+//! An attestation is an [in-toto statement](https://github.com/in-toto/attestation)
+//! whose subject is the crate (by package URL, with a SHA-256 digest of the
+//! path, length, and contents of each of its files, see [`crate_digest`]) and
+//! whose predicate holds the audit results. The statement is signed with an Ed25519 key and wrapped in
+//! a DSSE envelope, as SLSA provenance is.
+//!
+//! Keys are stored base64-encoded: the signing key (private) in one file,
+//! readable only by its owner, and the verifying key (public) in the same
+//! file with a `.pub` extension.
+
+use crate::audit_file::AuditFile;
+use crate::auditing::util::hash_dir;
+use crate::effect::EffectType;
+use crate::report::CrateSummary;
+use crate::sbom::purl;
+use crate::scanner::ScanLimit;
+use crate::util::CrateId;
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature as Ed25519Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://github.com/PLSysSec/cargo-scan/audit/v1";
+pub const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subject {
+    pub name: String,
+    pub digest: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scanner {
+    pub name: String,
+    pub version: String,
+}
+
+/// The audit results asserted by an attestation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditPredicate {
+    pub crate_name: String,
+    pub version: String,
+    pub scanner: Scanner,
+    pub scanned_effects: Vec<EffectType>,
+    pub summary: CrateSummary,
+    /// Number of annotated locations of each annotation
+    pub annotations: BTreeMap<String, usize>,
+    /// Content hash of the audit file, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_file_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_scan: Option<ScanLimit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub predicate: AuditPredicate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub keyid: String,
+    pub sig: String,
+}
+
+/// A DSSE envelope holding a signed statement
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub payload_type: String,
+    /// The base64-encoded statement
+    pub payload: String,
+    pub signatures: Vec<Signature>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Id of a verifying key: the hex SHA-256 hash of the key
+pub fn key_id(key: &VerifyingKey) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// DSSE pre-authentication encoding of a payload, which is what gets signed
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut out =
+        format!("DSSEv1 {} {} {} ", payload_type.len(), payload_type, payload.len())
+            .into_bytes();
+    out.extend_from_slice(payload);
+    out
+}
+
+fn public_key_path(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".pub");
+    PathBuf::from(p)
+}
+
+fn read_key_bytes(path: &Path) -> Result<[u8; 32]> {
+    let s = std::fs::read_to_string(path)
+        .context(format!("Failed to read key {:?}", path))?;
+    BASE64
+        .decode(s.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid key in {:?}", path))
+}
+
+/// Write a new file, failing if anything (even a dangling symlink) is at the
+/// path already. Private files are only readable by their owner.
+fn write_new_file(path: &Path, contents: &str, private: bool) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if private { 0o600 } else { 0o644 });
+    }
+    let mut file = options
+        .open(path)
+        .context(format!("Failed to create {:?}; it may already exist", path))?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Generate a new signing key and save it to the path, and its verifying key
+/// to the path with a `.pub` extension
+pub fn generate_key(path: &Path) -> Result<VerifyingKey> {
+    let public_path = public_key_path(path);
+    if public_path.symlink_metadata().is_ok() {
+        return Err(anyhow!("Something already exists at {:?}", public_path));
+    }
+    let key = SigningKey::generate(&mut OsRng);
+    write_new_file(path, &BASE64.encode(key.to_bytes()), true)?;
+    write_new_file(&public_path, &BASE64.encode(key.verifying_key().as_bytes()), false)?;
+    Ok(key.verifying_key())
+}
+
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path)?))
+}
+
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&read_key_bytes(path)?)
+        .map_err(|e| anyhow!("Invalid verifying key in {:?}: {}", path, e))
+}
+
+/// Digest of the files of a crate: the relative path, length, and contents of
+/// each file, in order of path. Symlinks are hashed as the path they point to
+/// rather than followed, so the digest doesn't depend on the symlink policy.
+pub fn crate_digest(crate_path: &Path) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(crate_path).follow_links(false).sort_by_file_name() {
+        let entry = entry?;
+        let (kind, data) = if entry.file_type().is_file() {
+            (b'f', std::fs::read(entry.path())?)
+        } else if entry.file_type().is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            (b'l', target.to_string_lossy().into_owned().into_bytes())
+        } else {
+            continue;
+        };
+        let path = entry.path().strip_prefix(crate_path)?.to_string_lossy().into_owned();
+        hasher.update([kind]);
+        hasher.update((path.len() as u64).to_le_bytes());
+        hasher.update(path.as_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(&data);
+    }
+    Ok(hasher.finalize().into())
+}
+
+impl Statement {
+    /// A statement of the audit results for the crate, whose files have the
+    /// given digest
+    pub fn new(crate_id: &CrateId, crate_hash: &[u8; 32], audit: &AuditFile) -> Self {
+        let version = crate_id.version.to_string();
+        let subject = Subject {
+            name: purl(&crate_id.crate_name, &version),
+            digest: BTreeMap::from([("sha256".to_string(), to_hex(crate_hash))]),
+        };
+        let predicate = AuditPredicate {
+            crate_name: crate_id.crate_name.clone(),
+            version,
+            scanner: Scanner {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            scanned_effects: audit.scanned_effects.clone(),
+            summary: CrateSummary::from_audit_file(crate_id.to_string(), audit),
+            annotations: audit
+                .annotation_counts()
+                .into_iter()
+                .map(|(a, n)| (a.to_string(), n))
+                .collect(),
+            audit_file_hash: audit.content_hash.clone(),
+            partial_scan: audit.partial_scan,
+        };
+        Self {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: vec![subject],
+            predicate_type: PREDICATE_TYPE.to_string(),
+            predicate,
+        }
+    }
+
+    /// The statement for a crate and its audit, checking that the audit is
+    /// for the current contents of the crate
+    pub fn for_crate(
+        crate_path: &Path,
+        crate_id: &CrateId,
+        audit: &AuditFile,
+    ) -> Result<Self> {
+        if hash_dir(crate_path)? != audit.hash {
+            return Err(anyhow!(
+                "Crate has changed since the audit; re-run the audit first"
+            ));
+        }
+        Ok(Self::new(crate_id, &crate_digest(crate_path)?, audit))
+    }
+
+    /// Check that the subject of the statement is the crate directory
+    pub fn check_subject(&self, crate_path: &Path) -> Result<()> {
+        let hash = to_hex(&crate_digest(crate_path)?);
+        let matches = self.subject.iter().any(|s| s.digest.get("sha256") == Some(&hash));
+        if !matches {
+            return Err(anyhow!(
+                "The attestation is for different contents than the crate at {:?}",
+                crate_path
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Envelope {
+    pub fn sign(statement: &Statement, key: &SigningKey) -> Result<Self> {
+        let payload = serde_json::to_vec(statement)?;
+        let sig = key.sign(&pae(PAYLOAD_TYPE, &payload));
+        Ok(Self {
+            payload_type: PAYLOAD_TYPE.to_string(),
+            payload: BASE64.encode(&payload),
+            signatures: vec![Signature {
+                keyid: key_id(&key.verifying_key()),
+                sig: BASE64.encode(sig.to_bytes()),
+            }],
+        })
+    }
+
+    /// Verify that the envelope was signed with the key, and return the
+    /// statement in it
+    pub fn verify(&self, key: &VerifyingKey) -> Result<Statement> {
+        if self.payload_type != PAYLOAD_TYPE {
+            return Err(anyhow!("Unexpected payload type: {}", self.payload_type));
+        }
+        let payload = BASE64.decode(&self.payload).context("Invalid payload encoding")?;
+        let message = pae(&self.payload_type, &payload);
+        let id = key_id(key);
+        let verified = self.signatures.iter().filter(|s| s.keyid == id).any(|s| {
+            BASE64
+                .decode(&s.sig)
+                .ok()
+                .and_then(|sig| Ed25519Signature::from_slice(&sig).ok())
+                .map_or(false, |sig| key.verify_strict(&message, &sig).is_ok())
+        });
+        if !verified {
+            return Err(anyhow!("No valid signature by key {}", id));
+        }
+
+        let statement: Statement =
+            serde_json::from_slice(&payload).context("Invalid statement")?;
+        if statement.statement_type != STATEMENT_TYPE
+            || statement.predicate_type != PREDICATE_TYPE
+        {
+            return Err(anyhow!(
+                "Not a cargo-scan audit statement: {} / {}",
+                statement.statement_type,
+                statement.predicate_type
+            ));
+        }
+        Ok(statement)
+    }
+
+    pub fn read_from_file(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        serde_json::from_str(&s).context(format!("Invalid attestation {:?}", path))
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let audit = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        let crate_id = CrateId::new("mycrate".to_string(), "1.2.3".parse().unwrap());
+        let statement = Statement::new(&crate_id, &[7; 32], &audit);
+        assert_eq!(statement.subject[0].name, "pkg:cargo/mycrate@1.2.3");
+
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let envelope = Envelope::sign(&statement, &key).unwrap();
+        assert_eq!(envelope.verify(&key.verifying_key()).unwrap(), statement);
+
+        // Another key, or a tampered payload, don't verify
+        let other = SigningKey::from_bytes(&[2; 32]);
+        assert!(envelope.verify(&other.verifying_key()).is_err());
+        let mut tampered = statement.clone();
+        tampered.predicate.crate_name = "othercrate".to_string();
+        let mut forged = envelope.clone();
+        forged.payload = BASE64.encode(serde_json::to_vec(&tampered).unwrap());
+        assert!(forged.verify(&key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("audit.key");
+        let key = generate_key(&path).unwrap();
+        assert_eq!(read_verifying_key(&public_key_path(&path)).unwrap(), key);
+        assert_eq!(read_signing_key(&path).unwrap().verifying_key(), key);
+        assert!(generate_key(&path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_key_unix() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("audit.key");
+        generate_key(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Keys aren't written through symlinks, even dangling ones
+        let link = dir.join("link.key");
+        std::os::unix::fs::symlink(dir.join("elsewhere"), &link).unwrap();
+        assert!(generate_key(&link).is_err());
+        assert!(!dir.join("elsewhere").exists());
+    }
+
+    #[test]
+    fn test_crate_digest() {
+        let digest = |files: &[(&str, &str)]| {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir_all(dir.path().join("src")).unwrap();
+            for (name, contents) in files {
+                std::fs::write(dir.path().join(name), contents).unwrap();
+            }
+            crate_digest(dir.path()).unwrap()
+        };
+        let original = digest(&[("src/a.rs", "fn a() {}"), ("src/b.rs", "fn b() {}")]);
+        assert_eq!(
+            original,
+            digest(&[("src/a.rs", "fn a() {}"), ("src/b.rs", "fn b() {}")])
+        );
+        // Moving contents between files, or renaming them, changes the digest
+        assert_ne!(
+            original,
+            digest(&[("src/a.rs", "fn a() {}fn b() {}"), ("src/b.rs", "")])
+        );
+        assert_ne!(
+            original,
+            digest(&[("src/a.rs", "fn a() {}"), ("src/c.rs", "fn b() {}")])
+        );
+    }
+}
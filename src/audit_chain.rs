@@ -1,14 +1,15 @@
 use anyhow::{anyhow, Context, Result};
-use cargo::core::source::MaybePackage;
-use cargo::ops::{fetch, FetchOptions};
 use cargo::{core::Workspace, ops::generate_lockfile, util::config};
 use cargo_lock::{Dependency, Lockfile, Package};
 use cargo_toml::Manifest;
 use clap::Args as ClapArgs;
+use log::{info, warn};
 use petgraph::graph::{DiGraph, NodeIndex};
-use petgraph::visit::DfsPostOrder;
+use petgraph::visit::{Dfs, DfsPostOrder, Reversed};
+use rayon::prelude::*;
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs::{create_dir_all, remove_file, File};
 use std::io::Write;
 use std::iter::IntoIterator;
@@ -18,17 +19,36 @@ use std::str::FromStr;
 use toml;
 
 use crate::audit_file::{AuditFile, AuditVersion, DefaultAuditType};
+use crate::auditing::check::{CoverageRequirements, VersionTrust};
+use crate::dep_cache::DepGraph;
 use crate::effect::EffectType;
 use crate::ident::{CanonicalPath, IdentPath};
-use crate::util::{load_cargo_toml, CrateId};
+use crate::metrics::metrics;
+use crate::name_check::check_packages;
+use crate::progress;
+use crate::scanner::{ScanConfig, ScanLimit, ScanResults};
+use crate::util::{load_cargo_toml, sorted, CrateId};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AuditChain {
     #[serde(skip)]
     manifest_path: PathBuf,
     crate_path: PathBuf,
+    #[serde(serialize_with = "sorted::serialize_map")]
     crate_policies: HashMap<CrateId, (PathBuf, AuditVersion)>,
     scanned_effects: Vec<EffectType>,
+    /// Audit coverage required by `chain check`
+    #[serde(default, skip_serializing_if = "CoverageRequirements::is_empty")]
+    coverage: CoverageRequirements,
+    /// Crates trusted as safe wrappers (e.g. flate2 for libz): their public
+    /// caller-checked functions aren't sinks for the crates depending on them,
+    /// so the chain stops at the wrapper
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    trusted_wrappers: BTreeSet<String>,
+    /// Audits which stay valid for a range of versions of their crate while
+    /// the effects don't change, evaluated by `chain check`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    version_trusts: Vec<VersionTrust>,
 }
 
 impl AuditChain {
@@ -42,9 +62,133 @@ impl AuditChain {
             crate_path,
             crate_policies: HashMap::new(),
             scanned_effects,
+            coverage: CoverageRequirements::default(),
+            trusted_wrappers: BTreeSet::new(),
+            version_trusts: Vec::new(),
         }
     }
 
+    pub fn coverage_requirements(&self) -> &CoverageRequirements {
+        &self.coverage
+    }
+
+    pub fn set_coverage_requirements(&mut self, coverage: CoverageRequirements) {
+        self.coverage = coverage;
+    }
+
+    pub fn trusted_wrappers(&self) -> &BTreeSet<String> {
+        &self.trusted_wrappers
+    }
+
+    /// Whether the crate is trusted as a wrapper, so that the chain stops at it
+    pub fn is_trusted_wrapper(&self, crate_id: &CrateId) -> bool {
+        self.trusted_wrappers.contains(&crate_id.crate_name)
+    }
+
+    /// Trust all versions of the crate as a wrapper, and remove the effects
+    /// of calls to its public caller-checked functions (and the effects which
+    /// only reached public functions through them) from the other crates in
+    /// the chain. Effects which were already decided on are kept, with their
+    /// decisions. Returns the number of effects removed.
+    pub fn trust_wrapper(&mut self, crate_name: &str) -> Result<usize> {
+        let wrappers = self.matching_crates_no_version(crate_name);
+        if wrappers.is_empty() {
+            return Err(anyhow!("No crate named {} in the chain", crate_name));
+        }
+        self.trusted_wrappers.insert(crate_name.to_string());
+
+        let mut removed_fns = HashSet::new();
+        for wrapper in &wrappers {
+            let audit_file = self
+                .read_audit_file(wrapper)?
+                .context(format!("Couldn't find audit for {}", wrapper))?;
+            removed_fns.extend(audit_file.pub_caller_checked.into_keys());
+        }
+
+        // Removing effects can leave more public functions without effects,
+        // so repeat until nothing changes
+        let mut removed_effects = 0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let crate_ids = self.all_crates().into_iter().cloned().collect::<Vec<_>>();
+            for crate_id in crate_ids.iter().filter(|c| !wrappers.contains(c)) {
+                let mut audit_file = self
+                    .read_audit_file(crate_id)?
+                    .context(format!("Couldn't find audit for {}", crate_id))?;
+                let removed = audit_file.remove_undecided_sinks_from_tree(&removed_fns);
+                if removed.is_empty() {
+                    continue;
+                }
+                let starting_pub_caller_checked =
+                    audit_file.pub_caller_checked.keys().cloned().collect::<HashSet<_>>();
+                audit_file.recalc_pub_caller_checked(&starting_pub_caller_checked);
+                removed_fns.extend(
+                    starting_pub_caller_checked
+                        .into_iter()
+                        .filter(|f| !audit_file.pub_caller_checked.contains_key(f)),
+                );
+                audit_file.version += 1;
+                self.save_audit_file(crate_id, &audit_file)?;
+                removed_effects += removed.len();
+                changed = true;
+            }
+        }
+        Ok(removed_effects)
+    }
+
+    /// Stop trusting the crate as a wrapper. This doesn't restore the effects
+    /// removed from the crates depending on it; their audits have to be
+    /// created again. Returns whether the crate was trusted.
+    pub fn untrust_wrapper(&mut self, crate_name: &str) -> bool {
+        self.trusted_wrappers.remove(crate_name)
+    }
+
+    pub fn version_trusts(&self) -> &[VersionTrust] {
+        &self.version_trusts
+    }
+
+    /// Trust the audit of the crate in the chain for a range of its versions
+    /// (e.g. `1` for every 1.x release), as long as the effects (optionally
+    /// only those calling `callee`) are unchanged. If several versions of
+    /// the crate are in the range, the latest one's audit is trusted.
+    pub fn trust_versions(
+        &mut self,
+        crate_name: &str,
+        versions: &str,
+        callee: Option<String>,
+    ) -> Result<VersionTrust> {
+        let req = VersionReq::parse(versions)
+            .context(format!("Invalid version requirement: {}", versions))?;
+        let crate_id = self
+            .matching_crates_no_version(crate_name)
+            .into_iter()
+            .filter(|c| req.matches(&c.version))
+            .max()
+            .ok_or_else(|| {
+                anyhow!("No version of {} in the chain matches {}", crate_name, versions)
+            })?;
+        let audit_file = self
+            .read_audit_file(&crate_id)?
+            .context(format!("Couldn't find audit for {}", crate_id))?;
+        let trust = VersionTrust::new(&crate_id, &audit_file, versions, callee)?;
+        self.version_trusts.retain(|t| {
+            (&t.crate_name, &t.versions, &t.callee)
+                != (&trust.crate_name, &trust.versions, &trust.callee)
+        });
+        self.version_trusts.push(trust.clone());
+        Ok(trust)
+    }
+
+    /// Remove the version trusts of the crate for the versions. Returns the
+    /// number removed.
+    pub fn untrust_versions(&mut self, crate_name: &str, versions: &str) -> usize {
+        let before = self.version_trusts.len();
+        self.version_trusts
+            .retain(|t| !(t.crate_name == crate_name && t.versions == versions));
+        before - self.version_trusts.len()
+    }
+
     pub fn all_crates(&self) -> Vec<&CrateId> {
         self.crate_policies.keys().collect::<Vec<_>>()
     }
@@ -70,11 +214,52 @@ impl AuditChain {
         }
     }
 
+    /// Path of the checkpoint saved while creating the chain at the manifest
+    /// path, so that an interrupted `create` can be resumed
+    pub fn checkpoint_path(manifest_path: &Path) -> PathBuf {
+        let mut path = manifest_path.as_os_str().to_owned();
+        path.push(".partial");
+        PathBuf::from(path)
+    }
+
+    /// Save the crates audited so far to the checkpoint. The checkpoint is
+    /// written to a temporary file first so an interruption can't leave it
+    /// half-written.
+    fn save_checkpoint(&self) -> Result<()> {
+        let path = Self::checkpoint_path(&self.manifest_path);
+        let tmp_path = path.with_extension("partial.tmp");
+        std::fs::write(&tmp_path, toml::to_string(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Load the checkpoint of an interrupted `create`, if there is one
+    fn read_checkpoint(manifest_path: &Path) -> Result<Option<AuditChain>> {
+        let checkpoint = Self::read_audit_chain(Self::checkpoint_path(manifest_path))?;
+        Ok(checkpoint.map(|mut chain| {
+            chain.manifest_path = manifest_path.to_path_buf();
+            chain
+        }))
+    }
+
+    fn has_crate(&self, crate_id: &CrateId) -> bool {
+        match self.crate_policies.get(crate_id) {
+            Some((audit_file_path, _)) => audit_file_path.is_file(),
+            None => false,
+        }
+    }
+
     pub fn save_to_file(mut self) -> Result<()> {
         let path = mem::take(&mut self.manifest_path);
-        let mut f = File::create(path)?;
+        let mut f = File::create(&path)?;
         let toml = toml::to_string(&self)?;
         f.write_all(toml.as_bytes())?;
+
+        // The complete manifest supersedes any checkpoint
+        let checkpoint_path = Self::checkpoint_path(&path);
+        if checkpoint_path.is_file() {
+            remove_file(checkpoint_path)?;
+        }
         Ok(())
     }
 
@@ -169,7 +354,7 @@ impl AuditChain {
         if let Ok(l) = Lockfile::load(&crate_path) {
             Ok(l)
         } else {
-            println!("Lockfile missing: generating new lockfile");
+            progress!("Lockfile missing: generating new lockfile");
             let config = config::Config::default()?;
             crate_path.pop();
             crate_path.push("Cargo.toml");
@@ -258,6 +443,93 @@ impl AuditChain {
         Ok(removed_fns)
     }
 
+    /// Returns a shortest dependency path from the root crate to each crate it
+    /// depends on. Each path starts with the root crate and ends with the
+    /// dependency.
+    pub fn dependency_paths(&self) -> Result<HashMap<CrateId, Vec<CrateId>>> {
+        let lockfile = self.load_lockfile()?;
+        let dep_tree = lockfile.dependency_tree()?;
+        let dep_nodes = dep_tree.nodes();
+        let dep_graph = dep_tree.graph();
+
+        let root_package =
+            lookup_package_from_name(&self.root_crate()?, lockfile.packages)?;
+        let root_node = *dep_nodes
+            .get(&Dependency::from(&root_package))
+            .context("Missing root package in the dependency graph")?;
+
+        // Breadth-first search from the root so the paths are the shortest
+        let mut paths = HashMap::new();
+        paths.insert(root_node, vec![CrateId::from(&dep_graph[root_node])]);
+        let mut queue = VecDeque::from([root_node]);
+        while let Some(n) = queue.pop_front() {
+            for next in dep_graph.neighbors(n) {
+                if !paths.contains_key(&next) {
+                    let mut path = paths[&n].clone();
+                    path.push(CrateId::from(&dep_graph[next]));
+                    paths.insert(next, path);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        Ok(paths.into_values().map(|path| (path.last().unwrap().clone(), path)).collect())
+    }
+
+    /// Returns the crates of the chain which (transitively) depend on the
+    /// crate, not including the crate itself
+    pub fn dependents(&self, crate_id: &CrateId) -> Result<Vec<CrateId>> {
+        let lockfile = self.load_lockfile()?;
+        let dep_tree = lockfile.dependency_tree()?;
+        let dep_nodes = dep_tree.nodes();
+        let dep_graph = dep_tree.graph();
+
+        let package = lookup_package_from_name(crate_id, lockfile.packages)?;
+        let node = *dep_nodes
+            .get(&Dependency::from(&package))
+            .context(format!("Missing package {:?} in the dependency graph", package))?;
+
+        let dependents = Reversed(dep_graph);
+        let mut dfs = Dfs::new(dependents, node);
+        let mut crates = Vec::new();
+        while let Some(n) = dfs.next(dependents) {
+            let dependent = CrateId::from(&dep_graph[n]);
+            if n != node && self.has_crate(&dependent) {
+                crates.push(dependent);
+            }
+        }
+        Ok(crates)
+    }
+
+    /// Scan the crates of the chain depending on the crate (in parallel), e.g.
+    /// for the fan-in of its functions. Crates which fail to scan are skipped.
+    pub fn scan_dependents(
+        &mut self,
+        crate_id: &CrateId,
+        config: &ScanConfig,
+    ) -> Result<Vec<ScanResults>> {
+        let mut audits = Vec::new();
+        for dependent in self.dependents(crate_id)? {
+            if let Some(audit_file) = self.read_audit_file(&dependent)? {
+                audits.push((dependent, audit_file));
+            }
+        }
+        Ok(audits
+            .par_iter()
+            .filter_map(|(dependent, audit_file)| {
+                audit_file
+                    .rescan(&audit_file.base_dir, config)
+                    .map_err(|e| warn!("Failed to scan {}: {}", dependent, e))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Path to the root crate
+    pub fn crate_path(&self) -> &Path {
+        &self.crate_path
+    }
+
     /// Gets the root crate id
     pub fn root_crate(&self) -> Result<CrateId> {
         let root_package = Manifest::from_path(format!(
@@ -297,6 +569,17 @@ pub struct Create {
     #[clap(short = 'v', long)]
     pub download_version: Option<String>,
 
+    /// Resume an interrupted `create` from its checkpoint, skipping the crates
+    /// which have already been scanned. Errors if there is no checkpoint.
+    #[clap(long, default_value_t = false)]
+    pub resume: bool,
+
+    /// Crates to trust as safe wrappers (e.g. flate2 for libz), so that the
+    /// effects of their public functions aren't passed on to the crates using
+    /// them
+    #[clap(long = "trust", value_delimiter = ',')]
+    pub trusted_wrappers: Vec<String>,
+
     /// The types of Effects the audit should track. Defaults to all unsafe
     /// behavior.
     #[clap(long, value_parser, num_args = 1.., default_values_t = [
@@ -330,6 +613,8 @@ impl Create {
             force_overwrite,
             download_root_crate,
             download_version,
+            resume: false,
+            trusted_wrappers: Vec::new(),
             effect_types,
         }
     }
@@ -382,6 +667,32 @@ fn make_dependency_graph(
     (graph, package_map, root_idx)
 }
 
+/// Split the crates to scan (in post-order) into waves which can each be
+/// scanned in parallel: the crates of a wave only depend on crates of earlier
+/// waves, or crates which aren't pending
+fn dependency_waves(
+    graph: &DiGraph<String, ()>,
+    mut pending: Vec<NodeIndex>,
+) -> Vec<Vec<NodeIndex>> {
+    let mut waves = Vec::new();
+    while !pending.is_empty() {
+        let pending_set = pending.iter().copied().collect::<HashSet<_>>();
+        let mut ready = pending
+            .iter()
+            .copied()
+            .filter(|&node| graph.neighbors(node).all(|d| !pending_set.contains(&d)))
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            // A dependency cycle (through dev-dependencies); break it where a
+            // sequential scan would
+            ready = pending[..1].to_vec();
+        }
+        pending.retain(|node| !ready.contains(node));
+        waves.push(ready);
+    }
+    waves
+}
+
 fn collect_dependency_sinks(
     chain: &mut AuditChain,
     deps: &Vec<Dependency>,
@@ -389,6 +700,10 @@ fn collect_dependency_sinks(
     let mut sinks = HashSet::new();
     for dep in deps {
         let dep_id = CrateId::from(dep);
+        if chain.is_trusted_wrapper(&dep_id) {
+            info!("Not adding sinks for trusted wrapper {}", dep_id);
+            continue;
+        }
         let audit_file = chain.read_audit_file(&dep_id)?.context(
             "couldnt read dependency audit file (maybe created it out of order)",
         )?;
@@ -398,17 +713,65 @@ fn collect_dependency_sinks(
     Ok(sinks)
 }
 
-/// Creates a new default audit file for the given package and returns the path to
-/// the saved audit file
-fn make_new_audit_file(
+/// A package to create a new default audit file for, with the sinks from its
+/// dependencies, so that it can be scanned without the chain
+struct NewAuditFile<'a> {
+    package: &'a Package,
+    audit_file_path: PathBuf,
+    package_path: PathBuf,
+    sinks: HashSet<CanonicalPath>,
+    audit_type: DefaultAuditType,
+    /// The existing audit file, if its scan is up to date
+    existing: Option<AuditFile>,
+}
+
+impl NewAuditFile<'_> {
+    fn scan(
+        &self,
+        relevant_effects: &[EffectType],
+        config: &ScanConfig,
+    ) -> Result<AuditFile> {
+        if let Some(audit_file) = &self.existing {
+            return Ok(audit_file.clone());
+        }
+        AuditFile::new_default_with_sinks(
+            &self.package_path,
+            self.sinks.clone(),
+            self.audit_type,
+            relevant_effects,
+            config,
+        )
+    }
+
+    /// Save the scanned audit file and add it to the chain. Returns the scan
+    /// limit the package hit, if any.
+    fn add_to_chain(
+        self,
+        chain: &mut AuditChain,
+        audit_file: AuditFile,
+    ) -> Result<Option<ScanLimit>> {
+        audit_file.save_to_file(self.audit_file_path.clone())?;
+        chain.add_crate_audit_file(
+            self.package,
+            self.audit_file_path,
+            audit_file.version,
+        );
+        Ok(audit_file.partial_scan)
+    }
+}
+
+/// Prepares a new default audit file for the given package, once the audit
+/// files of its dependencies are in the chain. An existing audit file is kept
+/// if its scan is up to date (see `ScanManifest::is_up_to_date`).
+fn prepare_new_audit_file<'a>(
     chain: &mut AuditChain,
-    package: &Package,
+    package: &'a Package,
     root_name: &str,
     args: &Create,
     crate_path: &Path,
     audit_type: DefaultAuditType,
-    relevant_effects: &[EffectType],
-) -> Result<()> {
+    config: &ScanConfig,
+) -> Result<NewAuditFile<'a>> {
     let audit_file_path = PathBuf::from(format!(
         "{}/{}-{}.audit",
         args.audit_path,
@@ -428,104 +791,178 @@ fn make_new_audit_file(
     if audit_file_path.is_dir() {
         return Err(anyhow!("Audit path is a directory"));
     }
+    let sinks = collect_dependency_sinks(chain, &package.dependencies)?;
+    let mut existing = None;
     if audit_file_path.is_file() {
-        if args.force_overwrite {
+        // An audit whose scan had the same inputs is kept, with its
+        // decisions, rather than scanned again
+        let audit_file =
+            AuditFile::read_audit_file(audit_file_path.clone()).ok().flatten();
+        let ident_sinks = sinks.iter().map(|f| f.clone().to_path()).collect();
+        let up_to_date = match audit_file.as_ref().and_then(|a| a.scan_manifest.as_ref())
+        {
+            Some(manifest) if !args.force_overwrite => manifest.is_up_to_date(
+                &package_path,
+                ident_sinks,
+                &args.effect_types,
+                config,
+            )?,
+            _ => false,
+        };
+        // When resuming, an audit file of a crate which isn't in the
+        // checkpoint was left behind by the interrupted run
+        let left_behind = args.resume && !chain.has_crate(&CrateId::from(package));
+        if up_to_date {
+            progress!(
+                "Keeping the audit of {} v{} (up to date)",
+                package.name,
+                package.version
+            );
+            existing = audit_file;
+        } else if args.force_overwrite || left_behind {
             remove_file(audit_file_path.clone())?;
         } else {
             return Err(anyhow!("Audit file already exists"));
         }
     }
 
-    let sinks = collect_dependency_sinks(chain, &package.dependencies)?;
-    let audit_file = AuditFile::new_default_with_sinks(
-        &package_path,
+    Ok(NewAuditFile {
+        package,
+        audit_file_path,
+        package_path,
         sinks,
         audit_type,
-        relevant_effects,
-    )?;
-    audit_file.save_to_file(audit_file_path.clone())?;
-
-    chain.add_crate_audit_file(package, audit_file_path, audit_file.version);
-
-    Ok(())
+        existing,
+    })
 }
 
 pub fn create_new_audit_chain(
     args: Create,
     crate_download_path: &str,
+    config: &ScanConfig,
 ) -> Result<AuditChain> {
-    println!("Creating audit chain");
-    let mut chain = AuditChain::new(
-        PathBuf::from(&args.manifest_path),
-        PathBuf::from(&args.crate_path),
-        args.effect_types.clone(),
-    );
+    progress!("Creating audit chain");
+    let manifest_path = PathBuf::from(&args.manifest_path);
+    let checkpoint = if args.resume {
+        let checkpoint = AuditChain::read_checkpoint(&manifest_path)?;
+        Some(checkpoint.ok_or_else(|| {
+            anyhow!(
+                "Can't resume: no checkpoint at {:?}",
+                AuditChain::checkpoint_path(&manifest_path)
+            )
+        })?)
+    } else {
+        None
+    };
+    let mut chain = match checkpoint {
+        Some(chain) => {
+            if chain.scanned_effects != args.effect_types {
+                return Err(anyhow!(
+                    "Can't resume: the checkpoint was scanned for different effect types"
+                ));
+            }
+            progress!(
+                "Resuming from checkpoint ({} crates done)",
+                chain.crate_policies.len()
+            );
+            chain
+        }
+        None => AuditChain::new(
+            manifest_path,
+            PathBuf::from(&args.crate_path),
+            args.effect_types.clone(),
+        ),
+    };
+
+    chain.trusted_wrappers.extend(args.trusted_wrappers.iter().cloned());
 
     create_audit_chain_dirs(&args, crate_download_path)?;
 
-    println!("Loading audit package lockfile");
+    progress!("Loading audit package lockfile");
     // If the lockfile doesn't exist, generate it
     let lockfile = chain.load_lockfile()?;
+    let name_warnings = check_packages(&lockfile.packages);
+    if !name_warnings.is_empty() {
+        progress!("Suspicious dependency names:");
+        for warning in &name_warnings {
+            progress!("  - {}", warning);
+        }
+    }
 
-    let mut crate_path_buf = Path::new(&args.crate_path).canonicalize()?;
+    let crate_path_buf = Path::new(&args.crate_path).canonicalize()?;
     let crate_data = load_cargo_toml(&crate_path_buf)?;
 
     let root_name = format!("{}-{}", crate_data.crate_name, crate_data.version);
 
-    let config = config::Config::default()?;
-    let _lock = config.acquire_package_cache_lock();
-    let set = HashSet::new();
-    crate_path_buf.push("Cargo.toml");
-    let workspace = Workspace::new(Path::new(&crate_path_buf), &config)?;
-    let fetch_options = FetchOptions { config: &config, targets: Vec::new() };
-    let (resolve, _package_set) = fetch(&workspace, &fetch_options)?;
-    let crate_paths: HashMap<CrateId, PathBuf> =
-        HashMap::from_iter(resolve.iter().filter_map(|p| {
-            // NOTE: We should return Some for every element here
-            let source_id = p.source_id();
-            let Ok(mut source) = source_id.load(&config, &set) else {
-                return None;
-            };
-            match source.download(p) {
-                Ok(MaybePackage::Ready(pkg)) => {
-                    let crate_id =
-                        CrateId::new(p.name().to_string(), p.version().clone());
-                    Some((crate_id, pkg.root().to_path_buf()))
-                }
-                _ => None,
-            }
-        }));
+    let crate_paths: HashMap<CrateId, PathBuf> = DepGraph::load(&crate_path_buf)?
+        .crates
+        .into_iter()
+        .map(|c| (c.crate_id, c.path))
+        .collect();
 
-    println!("Creating dependency graph");
+    progress!("Creating dependency graph");
     let (graph, package_map, root_node) =
         make_dependency_graph(&lockfile.packages, &root_name);
     let mut traverse = DfsPostOrder::new(&graph, root_node);
+    let mut pending = Vec::new();
     while let Some(node) = traverse.next(&graph) {
         let package = package_map.get(&node).unwrap();
-        println!("Making default audit for {} v{}", package.name, package.version);
+        let scanned = chain.has_crate(&CrateId::from(package));
+        metrics().cache_lookup("chain", scanned);
+        if scanned {
+            progress!("Skipping {} v{} (already scanned)", package.name, package.version);
+            continue;
+        }
+        pending.push(node);
+    }
 
-        let audit_type = if node == root_node {
-            DefaultAuditType::Empty
-        } else {
-            DefaultAuditType::CallerChecked
-        };
+    // The crates of each wave are scanned in parallel, then added to the
+    // chain in order
+    let mut partial_scans = Vec::new();
+    for wave in dependency_waves(&graph, pending) {
+        let mut new_audit_files = Vec::new();
+        for &node in &wave {
+            let package = package_map.get(&node).unwrap();
+            progress!("Making default audit for {} v{}", package.name, package.version);
+            let audit_type = if node == root_node {
+                DefaultAuditType::Empty
+            } else {
+                DefaultAuditType::CallerChecked
+            };
+            let crate_download_path = crate_paths
+                .get(&CrateId::from(package))
+                .context("Unresolved path for a crate")?;
+            new_audit_files.push(prepare_new_audit_file(
+                &mut chain,
+                package,
+                &root_name,
+                &args,
+                crate_download_path,
+                audit_type,
+                config,
+            )?);
+        }
 
-        let crate_download_path = crate_paths
-            .get(&CrateId::from(package))
-            .context("Unresolved path for a crate")?;
-
-        make_new_audit_file(
-            &mut chain,
-            package,
-            &root_name,
-            &args,
-            crate_download_path,
-            audit_type,
-            &args.effect_types,
-        )?;
+        let audit_files = new_audit_files
+            .par_iter()
+            .map(|new| new.scan(&args.effect_types, config))
+            .collect::<Vec<_>>();
+        for (new, audit_file) in new_audit_files.into_iter().zip(audit_files) {
+            let crate_id = CrateId::from(new.package);
+            if let Some(limit) = new.add_to_chain(&mut chain, audit_file?)? {
+                partial_scans.push((crate_id, limit));
+            }
+            chain.save_checkpoint()?;
+        }
     }
 
-    println!("Finished creating audit chain");
+    if !partial_scans.is_empty() {
+        progress!("Crates only partially scanned (effects may be missing):");
+        for (crate_id, limit) in partial_scans {
+            progress!("  - {} (hit the scan {})", crate_id, limit);
+        }
+    }
+    progress!("Finished creating audit chain");
     Ok(chain)
 }
 
@@ -552,3 +989,29 @@ where
 
     Err(anyhow!("Couldn't find package in workspace"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_waves() {
+        // app -> (http -> libc, log), with libc already scanned
+        let mut graph = DiGraph::new();
+        let [app, http, libc, log] =
+            ["app", "http", "libc", "log"].map(|n| graph.add_node(n.to_string()));
+        graph.add_edge(app, http, ());
+        graph.add_edge(app, log, ());
+        graph.add_edge(http, libc, ());
+        graph.add_edge(http, log, ());
+        let waves = dependency_waves(&graph, vec![log, http, app]);
+        assert_eq!(waves, [vec![log], vec![http], vec![app]]);
+        let waves = dependency_waves(&graph, vec![libc, log, http]);
+        assert_eq!(waves, [vec![libc, log], vec![http]]);
+
+        // A cycle is broken at the first pending crate
+        graph.add_edge(log, app, ());
+        let waves = dependency_waves(&graph, vec![log, http, app]);
+        assert_eq!(waves, [vec![log], vec![http], vec![app]]);
+    }
+}
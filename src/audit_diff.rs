@@ -0,0 +1,147 @@
+//! Differences between the effects of two audit files, or of an audit file
+//! and a fresh scan (`scan audit-diff`), e.g.
+//! ```text
+//! 1 new, 1 removed, 1 relocated effects
+//! + mycrate::fetch -> libc::recv [FFI] (src/net.rs:40:13..40:23)
+//! - mycrate::init -> std::env::var std::env (src/lib.rs:12:5..12:18)
+//! ~ mycrate::run -> libc::getuid [FFI] (src/lib.rs:30:9..30:21 -> src/lib.rs:34:9..34:21)
+//! ```
+//! Effects are matched by caller, callee, and kind (see
+//! [`EffectIdentity::Path`]) rather than by location, so code moving around
+//! in a refactor shows up as relocated effects instead of new and removed
+//! ones.
+
+use crate::effect::EffectInstance;
+use crate::effect_identity::{match_effects, EffectIdentity, EffectSource};
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EffectDiff<'a> {
+    /// Effects only in the new effects
+    pub added: Vec<&'a EffectInstance>,
+    /// Effects only in the old effects
+    pub removed: Vec<&'a EffectInstance>,
+    /// Effects in both, at a different location, as (old, new)
+    pub relocated: Vec<(&'a EffectInstance, &'a EffectInstance)>,
+    /// Number of effects in both at the same location
+    pub unchanged: usize,
+}
+
+impl<'a> EffectDiff<'a> {
+    pub fn new<I, J>(old: I, new: J) -> Self
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+        J: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let sources = |effects: Vec<&'a EffectInstance>| {
+            let mut sources = effects.into_iter().map(|e| (e, None)).collect::<Vec<_>>();
+            sources.sort();
+            sources
+        };
+        let old: Vec<EffectSource> = sources(old.into_iter().collect());
+        let new: Vec<EffectSource> = sources(new.into_iter().collect());
+        let pairs = match_effects(EffectIdentity::Path, &old, &new);
+
+        let mut diff = Self::default();
+        let (mut old_matched, mut new_matched) =
+            (vec![false; old.len()], vec![false; new.len()]);
+        for (i, j) in pairs {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            if old[i].0.call_loc() == new[j].0.call_loc() {
+                diff.unchanged += 1;
+            } else {
+                diff.relocated.push((old[i].0, new[j].0));
+            }
+        }
+        diff.added =
+            new.iter().zip(new_matched).filter(|(_, m)| !m).map(|(e, _)| e.0).collect();
+        diff.removed =
+            old.iter().zip(old_matched).filter(|(_, m)| !m).map(|(e, _)| e.0).collect();
+        diff
+    }
+
+    /// Whether the effects are the same, up to their locations
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn write_effect(f: &mut fmt::Formatter, e: &EffectInstance) -> fmt::Result {
+    write!(f, "{} -> {} {}", e.caller(), e.callee(), e.eff_type().simple_str())
+}
+
+impl fmt::Display for EffectDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} removed, {} relocated effects",
+            self.added.len(),
+            self.removed.len(),
+            self.relocated.len()
+        )?;
+        for (sign, effects) in [('+', &self.added), ('-', &self.removed)] {
+            for e in effects {
+                write!(f, "\n{} ", sign)?;
+                write_effect(f, e)?;
+                write!(f, " ({})", e.call_loc())?;
+            }
+        }
+        for (old, new) in &self.relocated {
+            write!(f, "\n~ ")?;
+            write_effect(f, new)?;
+            write!(f, " ({} -> {})", old.call_loc(), new.call_loc())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::Path;
+
+    fn effects(callers: &[&str], src: &str) -> Vec<EffectInstance> {
+        let tokens = src.parse::<proc_macro2::TokenStream>().unwrap();
+        tokens
+            .into_iter()
+            .zip(callers)
+            .map(|(t, caller)| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new(caller),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_effect_diff() {
+        let old = effects(&["lib::f", "lib::g", "lib::h"], "getuid fork\nsetuid");
+        // g's call moved to the next line, and h's call moved to i
+        let new = effects(&["lib::f", "lib::g", "lib::i"], "getuid\nfork setuid");
+        let diff = EffectDiff::new(&old, &new);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.relocated, [(&old[1], &new[1])]);
+        assert_eq!(diff.added, [&new[2]]);
+        assert_eq!(diff.removed, [&old[2]]);
+        assert!(!diff.is_empty());
+        let text = diff.to_string();
+        assert!(text.starts_with(
+            "1 new, 1 removed, 1 relocated effects\n+ lib::i -> libc::setuid [FFI]"
+        ));
+        assert!(text.contains("\n~ lib::g -> libc::fork [FFI] (src/lib.rs:1:"));
+
+        let diff = EffectDiff::new(&old, &old);
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged, 3);
+    }
+}
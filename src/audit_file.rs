@@ -1,44 +1,103 @@
 use super::effect::{EffectInstance, SrcLoc};
+use crate::auditing::decision_log;
 use crate::auditing::util::hash_dir;
-use crate::effect::{Effect, EffectType};
+use crate::effect::{Effect, EffectType, Severity};
+use crate::effect_identity::{self, EffectIdentity};
 use crate::ident::CanonicalPath;
+use crate::scan_manifest::ScanManifest;
 use crate::scanner;
-use crate::scanner::ScanResults;
+use crate::scanner::{ScanConfig, ScanLimit, ScanResults};
+use crate::util::sorted;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path as FilePath;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use sha2::{Digest, Sha256};
 
-/// SafetyAnnotation is really a lattice with `Skipped` as the top element, and
-/// `Unsafe` as the bottom element.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+/// The audit state of an effect.
+///
+/// SafetyAnnotation is really a lattice with `Unreviewed` as the top element,
+/// and `Unsafe` as the bottom element. `Deferred` effects have been looked at
+/// but not decided on, and `FalsePositive` effects are not real effects.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
 pub enum SafetyAnnotation {
-    Skipped,
+    // NOTE: Older audit files call this state `Skipped`
+    #[serde(alias = "Skipped")]
+    Unreviewed,
     Safe,
+    /// The effect is rejected
     Unsafe,
     CallerChecked,
+    Deferred,
+    FalsePositive,
+}
+
+impl SafetyAnnotation {
+    pub const ALL: &'static [SafetyAnnotation] = &[
+        SafetyAnnotation::Unreviewed,
+        SafetyAnnotation::Safe,
+        SafetyAnnotation::Unsafe,
+        SafetyAnnotation::CallerChecked,
+        SafetyAnnotation::Deferred,
+        SafetyAnnotation::FalsePositive,
+    ];
+
+    /// Whether the annotation must come with a justification from the auditor.
+    /// Justifications are optional for the other annotations.
+    pub fn requires_justification(&self) -> bool {
+        matches!(
+            self,
+            SafetyAnnotation::Unsafe
+                | SafetyAnnotation::Deferred
+                | SafetyAnnotation::FalsePositive
+        )
+    }
+
+    /// Whether an effect with this annotation has been decided on
+    pub fn is_audited(&self) -> bool {
+        !matches!(self, SafetyAnnotation::Unreviewed | SafetyAnnotation::Deferred)
+    }
 }
 
 impl fmt::Display for SafetyAnnotation {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SafetyAnnotation::Skipped => write!(f, "Skipped"),
+            SafetyAnnotation::Unreviewed => write!(f, "Unreviewed"),
             SafetyAnnotation::Safe => write!(f, "Safe"),
             SafetyAnnotation::Unsafe => write!(f, "Unsafe"),
             SafetyAnnotation::CallerChecked => write!(f, "Caller-checked"),
+            SafetyAnnotation::Deferred => write!(f, "Deferred"),
+            SafetyAnnotation::FalsePositive => write!(f, "False-positive"),
         }
     }
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Hash, Eq)]
+impl FromStr for SafetyAnnotation {
+    type Err = anyhow::Error;
+
+    /// Parses either the variant name or the displayed name, in any case
+    /// (e.g. `caller-checked`)
+    fn from_str(s: &str) -> Result<Self> {
+        SafetyAnnotation::ALL
+            .iter()
+            .find(|a| a.to_string().eq_ignore_ascii_case(s) || format!("{:?}", a) == s)
+            .copied()
+            .ok_or_else(|| anyhow!("Invalid safety annotation: {}", s))
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialOrd, Ord)]
 pub struct EffectInfo {
     pub caller_path: CanonicalPath,
     pub callee_loc: SrcLoc,
@@ -99,19 +158,138 @@ pub type AuditVersion = u32;
 
 // TODO: Include information about crate/version
 // TODO: We should include more information from the ScanResult
-#[serde_as]
+// NOTE: The maps are serialized in sorted order so that saved audit files are
+//       deterministic
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AuditFile {
-    #[serde_as(as = "Vec<(_, _)>")]
+    #[serde(
+        serialize_with = "sorted::serialize_pairs",
+        deserialize_with = "sorted::deserialize_pairs"
+    )]
     pub audit_trees: HashMap<EffectInstance, EffectTree>,
     /// Contains a map from public functions marked caller-checked to a set of
     /// all base EffectInstances that flow into that function
+    #[serde(
+        serialize_with = "sorted::serialize_set_pairs",
+        deserialize_with = "sorted::deserialize_pairs"
+    )]
     pub pub_caller_checked: HashMap<CanonicalPath, HashSet<EffectInstance>>,
     // TODO: Make the base_dir a crate instead
     pub base_dir: PathBuf,
     pub hash: [u8; 32],
     pub version: AuditVersion,
     pub scanned_effects: Vec<EffectType>,
+    /// SHA-256 hash of the canonical JSON of the audit file (computed with
+    /// this field empty), used to detect hand-edits or merge damage on load.
+    /// Older audit files don't have a hash.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Justifications given by the auditor for annotations, keyed by the
+    /// annotated location
+    #[serde(
+        default,
+        serialize_with = "sorted::serialize_pairs",
+        deserialize_with = "sorted::deserialize_pairs"
+    )]
+    pub justifications: HashMap<EffectInfo, String>,
+    /// External references (e.g. JIRA or GitHub issue URLs) tracking the
+    /// decisions for annotated locations
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted::serialize_pairs",
+        deserialize_with = "sorted::deserialize_pairs"
+    )]
+    pub references: HashMap<EffectInfo, Vec<String>>,
+    /// Set if the crate went over its scan budget and was only partially
+    /// scanned, in which case some effects may be missing or unresolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_scan: Option<ScanLimit>,
+    /// Base effects which were missing from the crate when `audit gc` last
+    /// ran, with when they were first found missing (seconds since the Unix
+    /// epoch). They are archived once the retention period is over.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted::serialize_pairs",
+        deserialize_with = "sorted::deserialize_pairs"
+    )]
+    pub stale_since: HashMap<EffectInstance, u64>,
+    /// Entries removed by `audit gc`, with the decisions made for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archive: Vec<ArchivedEntry>,
+    /// Inputs of the scan the audit was created from (missing in older audit
+    /// files), to check that it is up-to-date with the crate's sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_manifest: Option<ScanManifest>,
+    /// Functions of the crate's dependencies scanned as sinks (e.g. their
+    /// public caller-checked functions in a chain), so the crate can be
+    /// rescanned the same way
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub dependency_sinks: BTreeSet<CanonicalPath>,
+}
+
+/// An audit entry whose effect is no longer in the crate
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchivedEntry {
+    pub effect: EffectInstance,
+    pub tree: EffectTree,
+    /// Justifications for the locations in the tree
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub justifications: Vec<(EffectInfo, String)>,
+    /// External references for the locations in the tree
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<(EffectInfo, Vec<String>)>,
+    /// When the entry was archived, in seconds since the Unix epoch
+    pub archived_at: u64,
+}
+
+/// Check that an external reference is an http(s) URL to a page on a host,
+/// e.g. https://github.com/org/repo/issues/12 or
+/// https://example.atlassian.net/browse/SEC-34
+pub fn validate_reference(url: &str) -> Result<()> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| anyhow!("Reference is not an http(s) URL: {}", url))?;
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(anyhow!("Reference contains whitespace: {:?}", url));
+    }
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host_name = host.split(':').next().unwrap_or(host);
+    let valid_host = !host_name.is_empty()
+        && host_name.split('.').all(|l| {
+            !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid_host {
+        return Err(anyhow!("Reference has an invalid host: {}", url));
+    }
+    if path.trim_end_matches('/').is_empty() {
+        return Err(anyhow!("Reference should link to an issue, not a site: {}", url));
+    }
+    Ok(())
+}
+
+/// Key of the content hash in the serialized audit file
+const CONTENT_HASH_KEY: &str = "content_hash";
+
+/// Canonical JSON for a value: compact, with object keys sorted
+/// NOTE: serde_json::Value stores objects in a BTreeMap, so its keys are
+///       always sorted
+fn canonical_json(value: &serde_json::Value) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+/// Compute the content hash of a serialized audit file, ignoring any
+/// existing content hash
+fn compute_content_hash(value: &serde_json::Value) -> Result<String> {
+    let mut value = value.clone();
+    value
+        .as_object_mut()
+        .context("Audit file is not a JSON object")?
+        .insert(CONTENT_HASH_KEY.to_string(), serde_json::Value::Null);
+    let hash = Sha256::digest(canonical_json(&value)?.as_bytes());
+    Ok(format!("{:x}", hash))
 }
 
 impl AuditFile {
@@ -124,6 +302,14 @@ impl AuditFile {
             hash,
             version: 0,
             scanned_effects: relevant_effects,
+            content_hash: None,
+            justifications: HashMap::new(),
+            references: HashMap::new(),
+            partial_scan: None,
+            stale_since: HashMap::new(),
+            archive: Vec::new(),
+            scan_manifest: None,
+            dependency_sinks: BTreeSet::new(),
         })
     }
 
@@ -138,35 +324,135 @@ impl AuditFile {
                     x.clone(),
                     EffectTree::Leaf(
                         EffectInfo::from_instance(x),
-                        SafetyAnnotation::Skipped,
+                        SafetyAnnotation::Unreviewed,
                     ),
                 )
             })
             .collect::<HashMap<_, _>>();
     }
 
+    /// Carry the decisions on the base effects of a previous audit of the crate
+    /// over to the same effects in this audit (see `effect_identity`; audit
+    /// files don't keep the source of effects, so a snippet identity compares
+    /// them by path). Caller-checked effects aren't carried over, as their
+    /// callers may have changed. Returns the number of carried decisions.
+    pub fn carry_over_decisions(
+        &mut self,
+        prev: &AuditFile,
+        identity: EffectIdentity,
+    ) -> usize {
+        let prev_effects = prev.audit_trees.keys().map(|e| (e, None)).collect::<Vec<_>>();
+        let effects = self.audit_trees.keys().cloned().collect::<Vec<_>>();
+        let new_effects = effects.iter().map(|e| (e, None)).collect::<Vec<_>>();
+
+        let mut carried = 0;
+        for (i, j) in
+            effect_identity::match_effects(identity, &prev_effects, &new_effects)
+        {
+            let EffectTree::Leaf(prev_info, annotation) =
+                &prev.audit_trees[prev_effects[i].0]
+            else {
+                continue;
+            };
+            if matches!(
+                annotation,
+                SafetyAnnotation::Unreviewed | SafetyAnnotation::CallerChecked
+            ) {
+                continue;
+            }
+            let Some(EffectTree::Leaf(info, a)) = self.audit_trees.get_mut(&effects[j])
+            else {
+                continue;
+            };
+            *a = *annotation;
+            if let Some(justification) = prev.justifications.get(prev_info) {
+                self.justifications.insert(info.clone(), justification.clone());
+            }
+            if let Some(references) = prev.references.get(prev_info) {
+                self.references.insert(info.clone(), references.clone());
+            }
+            carried += 1;
+        }
+        carried
+    }
+
+    /// Serialize the audit file to canonical JSON, including its content hash
+    pub fn to_canonical_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        let hash = compute_content_hash(&value)?;
+        value
+            .as_object_mut()
+            .context("Audit file is not a JSON object")?
+            .insert(CONTENT_HASH_KEY.to_string(), serde_json::Value::String(hash));
+        canonical_json(&value)
+    }
+
+    /// Parse an audit file from JSON, checking the content hash if there is one
+    pub fn from_json(json_string: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json_string)?;
+        if let Some(expected) = value.get(CONTENT_HASH_KEY).and_then(|h| h.as_str()) {
+            let actual = compute_content_hash(&value)?;
+            if actual != expected {
+                return Err(anyhow!(
+                    "Audit file content hash mismatch (expected {}, found {}): \
+                    the file may have been edited by hand or damaged in a merge",
+                    expected,
+                    actual
+                ));
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Save the audit file, and log the decisions made since the saved
+    /// version (see `auditing::decision_log`)
     pub fn save_to_file(&self, p: PathBuf) -> Result<()> {
-        let json = serde_json::to_string(self)?;
-        let mut f = File::create(p)?;
+        let previous =
+            std::fs::read_to_string(&p).ok().and_then(|json| Self::from_json(&json).ok());
+        let json = self.to_canonical_json()?;
+        // The decisions are logged before they are saved, and the audit file
+        // is replaced at once, so no saved decision is missing from the log
+        decision_log::record(&p, previous.as_ref(), self)?;
+        let mut tmp = p.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut f = File::create(&tmp)?;
         f.write_all(json.as_bytes())?;
+        f.sync_all()?;
+        std::fs::rename(&tmp, &p)?;
         Ok(())
     }
 
     /// Returns Some audit file if it exists, or None if we should create a new one.
-    /// Errors if the audit filepath is invalid or if we can't read an existing
-    /// audit file
+    /// Errors if the audit filepath is invalid, if we can't read an existing
+    /// audit file, or if the audit file's content hash doesn't match
     pub fn read_audit_file(path: PathBuf) -> Result<Option<AuditFile>> {
         if path.is_dir() {
             Err(anyhow!("Audit path is a directory"))
         } else if path.is_file() {
             let json_string = std::fs::read_to_string(path.as_path())?;
-            let audit_file = serde_json::from_str(&json_string)?;
+            let audit_file = Self::from_json(&json_string)
+                .context(format!("Couldn't load audit file {:?}", path))?;
             Ok(Some(audit_file))
         } else {
             Ok(None)
         }
     }
 
+    /// Load an existing audit file, checking its content hash. Unlike
+    /// `read_audit_file`, a missing file is an error.
+    pub fn load(path: &FilePath) -> Result<Self> {
+        Self::read_audit_file(path.to_path_buf())?
+            .ok_or_else(|| anyhow!("No audit file at {:?}", path))
+    }
+
+    /// Save the audit file as canonical JSON (see `to_canonical_json`), so
+    /// that it loads back with the same content hash
+    pub fn save(&self, path: &FilePath) -> Result<()> {
+        self.save_to_file(path.to_path_buf())
+            .context(format!("Couldn't save audit file {:?}", path))
+    }
+
     /// Mark caller-checked functions but don't add a caller to the tree more
     /// than once (so we don't get an infinite cycle).
     fn mark_caller_checked_recurse(
@@ -186,13 +472,13 @@ impl AuditFile {
             }
 
             let mut callers = scan_res
-                .get_callers(&effect_info.caller_path)?
+                .get_sorted_callers(&effect_info.caller_path)?
                 .into_iter()
                 .filter_map(|e| {
                     if prev_callers.contains(&e.caller_path) {
                         None
                     } else {
-                        Some(EffectTree::Leaf(e, SafetyAnnotation::Skipped))
+                        Some(EffectTree::Leaf(e, SafetyAnnotation::Unreviewed))
                     }
                 })
                 .collect::<Vec<_>>();
@@ -255,7 +541,9 @@ impl AuditFile {
             }
             EffectTree::Leaf(_, SafetyAnnotation::Safe)
             | EffectTree::Leaf(_, SafetyAnnotation::Unsafe)
-            | EffectTree::Leaf(_, SafetyAnnotation::Skipped) => (),
+            | EffectTree::Leaf(_, SafetyAnnotation::Unreviewed)
+            | EffectTree::Leaf(_, SafetyAnnotation::Deferred)
+            | EffectTree::Leaf(_, SafetyAnnotation::FalsePositive) => (),
             EffectTree::Branch(info, next_trees) => {
                 if pub_fns.contains(&info.caller_path) {
                     pub_caller_checked
@@ -326,11 +614,34 @@ impl AuditFile {
         self.audit_trees.values().any(tree_walk)
     }
 
-    /// Returns the total number of unaudited leaf nodes.
-    fn total_unaudited_effects(t: &EffectTree) -> usize {
+    /// Returns the locations marked unsafe along with the base effect whose
+    /// tree they are in, sorted by base effect
+    pub fn rejected_effects(&self) -> Vec<(&EffectInstance, &EffectInfo)> {
+        fn tree_walk<'a>(tree: &'a EffectTree, rejected: &mut Vec<&'a EffectInfo>) {
+            match tree {
+                EffectTree::Leaf(info, SafetyAnnotation::Unsafe) => rejected.push(info),
+                EffectTree::Leaf(_, _) => (),
+                EffectTree::Branch(_, ts) => {
+                    ts.iter().for_each(|t| tree_walk(t, rejected))
+                }
+            }
+        }
+        let mut res = Vec::new();
+        for (e, t) in self.audit_trees.iter() {
+            let mut rejected = Vec::new();
+            tree_walk(t, &mut rejected);
+            res.extend(rejected.into_iter().map(|info| (e, info)));
+        }
+        res.sort();
+        res
+    }
+
+    /// Returns the total number of unaudited (unreviewed or deferred) leaf
+    /// nodes.
+    pub fn total_unaudited_effects(t: &EffectTree) -> usize {
         let mut total = 0;
         match t {
-            EffectTree::Leaf(_, SafetyAnnotation::Skipped) => {
+            EffectTree::Leaf(_, a) if !a.is_audited() => {
                 total += 1;
             }
             EffectTree::Leaf(_, _) => (),
@@ -359,8 +670,186 @@ impl AuditFile {
         (unaudited_base, unaudited_total)
     }
 
+    /// Returns the number of audited leaf nodes and the total number of leaf
+    /// nodes, only counting trees for effects of the given severity if any
+    pub fn audit_coverage(&self, severity: Option<Severity>) -> (usize, usize) {
+        let (mut audited, mut total) = (0, 0);
+        for (e, t) in self.audit_trees.iter() {
+            if severity.map_or(true, |s| e.eff_type().severity() == s) {
+                let unaudited = Self::total_unaudited_effects(t);
+                let leaves = Self::total_leaves(t);
+                audited += leaves - unaudited;
+                total += leaves;
+            }
+        }
+        (audited, total)
+    }
+
+    fn total_leaves(t: &EffectTree) -> usize {
+        match t {
+            EffectTree::Leaf(_, _) => 1,
+            EffectTree::Branch(_, ts) => ts.iter().map(Self::total_leaves).sum(),
+        }
+    }
+
+    /// Returns the number of leaf nodes with each annotation
+    pub fn annotation_counts(&self) -> BTreeMap<SafetyAnnotation, usize> {
+        fn tree_walk(tree: &EffectTree, counts: &mut BTreeMap<SafetyAnnotation, usize>) {
+            match tree {
+                EffectTree::Leaf(_, a) => *counts.entry(*a).or_insert(0) += 1,
+                EffectTree::Branch(_, ts) => ts.iter().for_each(|t| tree_walk(t, counts)),
+            }
+        }
+        let mut counts = BTreeMap::new();
+        for t in self.audit_trees.values() {
+            tree_walk(t, &mut counts);
+        }
+        counts
+    }
+
+    /// Record the annotation for a location along with its justification.
+    /// Errors if the annotation requires a justification and none is given.
+    pub fn set_justification(
+        justifications: &mut HashMap<EffectInfo, String>,
+        info: &EffectInfo,
+        annotation: SafetyAnnotation,
+        justification: Option<String>,
+    ) -> Result<()> {
+        match justification.filter(|j| !j.trim().is_empty()) {
+            Some(j) => {
+                justifications.insert(info.clone(), j);
+            }
+            None if annotation.requires_justification() => {
+                return Err(anyhow!(
+                    "Annotation {} requires a justification",
+                    annotation
+                ));
+            }
+            None => {
+                justifications.remove(info);
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach an external reference to a location, if it isn't already
+    pub fn add_reference(&mut self, info: &EffectInfo, url: &str) -> Result<()> {
+        validate_reference(url)?;
+        let refs = self.references.entry(info.clone()).or_default();
+        if !refs.iter().any(|r| r == url) {
+            refs.push(url.to_string());
+        }
+        Ok(())
+    }
+
+    /// The base effects with their trees, sorted by location. Indices into
+    /// this list are used as effect ids.
+    pub fn base_effects_by_location(&self) -> Vec<(&EffectInstance, &EffectTree)> {
+        let mut effects = self.audit_trees.iter().collect::<Vec<_>>();
+        effects.sort_by(|(e1, _), (e2, _)| e1.cmp(e2));
+        effects
+    }
+
+    /// Remove justifications for locations which are no longer annotated leaves
+    /// in any effect tree (e.g. after an annotation is reset)
+    pub fn prune_justifications(&mut self) {
+        fn tree_walk<'a>(tree: &'a EffectTree, leaves: &mut HashSet<&'a EffectInfo>) {
+            match tree {
+                EffectTree::Leaf(_, SafetyAnnotation::Unreviewed) => (),
+                EffectTree::Leaf(info, _) => {
+                    leaves.insert(info);
+                }
+                EffectTree::Branch(_, ts) => ts.iter().for_each(|t| tree_walk(t, leaves)),
+            }
+        }
+        let mut leaves = HashSet::new();
+        for t in self.audit_trees.values() {
+            tree_walk(t, &mut leaves);
+        }
+        self.justifications.retain(|info, _| leaves.contains(info));
+    }
+
+    /// Move the entries whose effects aren't in the latest scan to the archive,
+    /// once they have been missing for the retention period. Entries are
+    /// marked stale the first time they are missing, and unmarked if their
+    /// effects come back. Returns the number of archived entries.
+    pub fn gc(
+        &mut self,
+        scan_effects: &HashSet<&EffectInstance>,
+        retention: Duration,
+        now: u64,
+    ) -> usize {
+        fn tree_infos<'a>(tree: &'a EffectTree, infos: &mut Vec<&'a EffectInfo>) {
+            match tree {
+                EffectTree::Leaf(info, _) => infos.push(info),
+                EffectTree::Branch(info, ts) => {
+                    infos.push(info);
+                    ts.iter().for_each(|t| tree_infos(t, infos));
+                }
+            }
+        }
+
+        self.stale_since.retain(|e, _| !scan_effects.contains(e));
+        let mut missing = self
+            .audit_trees
+            .keys()
+            .filter(|e| !scan_effects.contains(e))
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+
+        let mut archived = Vec::new();
+        for effect in missing {
+            let since = *self.stale_since.entry(effect.clone()).or_insert(now);
+            if now.saturating_sub(since) < retention.as_secs() {
+                continue;
+            }
+            self.stale_since.remove(&effect);
+            if let Some(tree) = self.audit_trees.remove(&effect) {
+                archived.push((effect, tree));
+            }
+        }
+
+        // Locations still in a live tree keep their justifications and
+        // references
+        let mut live = Vec::new();
+        for t in self.audit_trees.values() {
+            tree_infos(t, &mut live);
+        }
+        let live = live.into_iter().cloned().collect::<HashSet<_>>();
+
+        let count = archived.len();
+        for (effect, tree) in archived {
+            let mut infos = Vec::new();
+            tree_infos(&tree, &mut infos);
+            let justifications = infos
+                .iter()
+                .filter_map(|&i| Some((i.clone(), self.justifications.get(i)?.clone())))
+                .collect();
+            let references = infos
+                .iter()
+                .filter_map(|&i| Some((i.clone(), self.references.get(i)?.clone())))
+                .collect();
+            self.archive.push(ArchivedEntry {
+                effect,
+                tree,
+                justifications,
+                references,
+                archived_at: now,
+            });
+        }
+        self.justifications.retain(|info, _| live.contains(info));
+        self.references.retain(|info, _| live.contains(info));
+        for effects in self.pub_caller_checked.values_mut() {
+            effects.retain(|e| self.audit_trees.contains_key(e));
+        }
+        self.pub_caller_checked.retain(|_, effects| !effects.is_empty());
+        count
+    }
+
     /// Print information about the audit:
     /// - total base effects
+    /// - number of locations with each annotation
     /// - unaudited
     /// - unsafe (if any)
     /// - if fully audited, if package is fully safe and how many caller-checked
@@ -371,6 +860,18 @@ impl AuditFile {
 
         println!("Audit file info:");
         println!("  - total base effects: {}", self.audit_trees.len());
+        if let Some(limit) = self.partial_scan {
+            println!("  - package only partially scanned (hit the scan {})", limit);
+        }
+        for (a, count) in self.annotation_counts() {
+            println!("  - locations marked {}: {}", a, count);
+        }
+        if !self.archive.is_empty() {
+            println!(
+                "  - archived entries (effects no longer in the crate): {}",
+                self.archive.len()
+            );
+        }
         if self.has_unsafe_effect() {
             println!("  - package marked UNSAFE");
         }
@@ -400,6 +901,47 @@ impl AuditFile {
         &mut self,
         sinks_to_remove: &HashSet<CanonicalPath>,
     ) -> Vec<EffectInstance> {
+        self.remove_sinks_where(sinks_to_remove, |_| true)
+    }
+
+    /// Whether a reviewer decided on the tree: a leaf of it is annotated other
+    /// than unreviewed or caller-checked (which default audits start from), or
+    /// has a justification
+    pub fn has_decisions(&self, tree: &EffectTree) -> bool {
+        match tree {
+            EffectTree::Leaf(info, a) => {
+                !matches!(
+                    a,
+                    SafetyAnnotation::Unreviewed | SafetyAnnotation::CallerChecked
+                ) || self.justifications.contains_key(info)
+            }
+            EffectTree::Branch(_, ts) => ts.iter().any(|t| self.has_decisions(t)),
+        }
+    }
+
+    /// Like `remove_sinks_from_tree`, but keeps the effects a reviewer
+    /// decided on (see `has_decisions`)
+    pub fn remove_undecided_sinks_from_tree(
+        &mut self,
+        sinks_to_remove: &HashSet<CanonicalPath>,
+    ) -> Vec<EffectInstance> {
+        let decided = self
+            .audit_trees
+            .iter()
+            .filter(|(_, t)| self.has_decisions(t))
+            .map(|(e, _)| e.clone())
+            .collect::<HashSet<_>>();
+        self.remove_sinks_where(sinks_to_remove, |e| !decided.contains(e))
+    }
+
+    fn remove_sinks_where<F>(
+        &mut self,
+        sinks_to_remove: &HashSet<CanonicalPath>,
+        removable: F,
+    ) -> Vec<EffectInstance>
+    where
+        F: Fn(&EffectInstance) -> bool,
+    {
         // Replace the audit tree with a temporary value so we can use a filter
         // map to drop effects
         let audit_trees = std::mem::take(&mut self.audit_trees);
@@ -412,7 +954,9 @@ impl AuditFile {
             .map(|(e, tree)| {
                 // Remove effects that match our sinks to remove
                 if let Effect::SinkCall(s) = e.eff_type() {
-                    if sinks_to_remove.contains(&CanonicalPath::new(s.as_str())) {
+                    if sinks_to_remove.contains(&CanonicalPath::new(s.as_str()))
+                        && removable(&e)
+                    {
                         (None, vec![e])
                     } else {
                         (Some((e, tree)), vec![])
@@ -427,25 +971,41 @@ impl AuditFile {
         removed_effects.into_iter().flatten().collect::<Vec<_>>()
     }
 
+    /// Scan the crate at `crate_path` (e.g. a new version of the audited
+    /// crate) the way the audit was created: for the same effects, with the
+    /// same dependency sinks
+    pub fn rescan(
+        &self,
+        crate_path: &FilePath,
+        config: &ScanConfig,
+    ) -> Result<ScanResults> {
+        let sinks = self.dependency_sinks.iter().map(|f| f.clone().to_path()).collect();
+        scanner::scan_crate_with_sinks(crate_path, sinks, &self.scanned_effects, config)
+    }
+
     pub fn new_caller_checked_default(
         crate_path: &FilePath,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<AuditFile> {
         Self::new_caller_checked_default_with_sinks(
             crate_path,
             HashSet::new(),
             relevant_effects,
+            config,
         )
     }
 
     pub fn new_caller_checked_default_with_results(
         crate_path: &FilePath,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<(AuditFile, ScanResults)> {
         Self::new_caller_checked_default_with_sinks_and_results(
             crate_path,
             HashSet::new(),
             relevant_effects,
+            config,
         )
     }
 
@@ -453,11 +1013,13 @@ impl AuditFile {
         crate_path: &FilePath,
         sinks: HashSet<CanonicalPath>,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<AuditFile> {
         Self::new_caller_checked_default_with_sinks_and_results(
             crate_path,
             sinks,
             relevant_effects,
+            config,
         )
         .map(|x| x.0)
     }
@@ -466,15 +1028,23 @@ impl AuditFile {
         crate_path: &FilePath,
         sinks: HashSet<CanonicalPath>,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<(AuditFile, ScanResults)> {
         let mut audit_file =
             AuditFile::empty(crate_path.to_path_buf(), relevant_effects.to_vec())?;
         let ident_sinks =
             sinks.iter().map(|x| x.clone().to_path()).collect::<HashSet<_>>();
-        let scan_res =
-            scanner::scan_crate_with_sinks(crate_path, ident_sinks, relevant_effects)?;
+        let scan_res = scanner::scan_crate_with_sinks(
+            crate_path,
+            ident_sinks,
+            relevant_effects,
+            config,
+        )?;
         let mut pub_caller_checked = HashMap::new();
         audit_file.set_base_audit_trees(scan_res.effects_set());
+        audit_file.partial_scan = scan_res.scan_limit;
+        audit_file.scan_manifest = Some(scan_res.manifest.clone());
+        audit_file.dependency_sinks = sinks.into_iter().collect();
 
         for (e, t) in audit_file.audit_trees.iter_mut() {
             AuditFile::mark_caller_checked(e, t, &mut pub_caller_checked, &scan_res)?;
@@ -489,14 +1059,22 @@ impl AuditFile {
         crate_path: &FilePath,
         sinks: HashSet<CanonicalPath>,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<AuditFile> {
         let mut audit_file =
             AuditFile::empty(crate_path.to_path_buf(), relevant_effects.to_vec())?;
         let ident_sinks =
             sinks.iter().map(|x| x.clone().to_path()).collect::<HashSet<_>>();
-        let scan_res =
-            scanner::scan_crate_with_sinks(crate_path, ident_sinks, relevant_effects)?;
+        let scan_res = scanner::scan_crate_with_sinks(
+            crate_path,
+            ident_sinks,
+            relevant_effects,
+            config,
+        )?;
         audit_file.set_base_audit_trees(scan_res.effects_set());
+        audit_file.partial_scan = scan_res.scan_limit;
+        audit_file.scan_manifest = Some(scan_res.manifest.clone());
+        audit_file.dependency_sinks = sinks.into_iter().collect();
 
         Ok(audit_file)
     }
@@ -506,6 +1084,7 @@ impl AuditFile {
         sinks: HashSet<CanonicalPath>,
         audit_type: DefaultAuditType,
         relevant_effects: &[EffectType],
+        config: &ScanConfig,
     ) -> Result<AuditFile> {
         match audit_type {
             DefaultAuditType::CallerChecked => {
@@ -513,11 +1092,15 @@ impl AuditFile {
                     crate_path,
                     sinks,
                     relevant_effects,
+                    config,
                 )
             }
-            DefaultAuditType::Empty => {
-                Self::new_empty_default_with_sinks(crate_path, sinks, relevant_effects)
-            }
+            DefaultAuditType::Empty => Self::new_empty_default_with_sinks(
+                crate_path,
+                sinks,
+                relevant_effects,
+                config,
+            ),
             // TODO: belo
             DefaultAuditType::Safe => unimplemented!(),
         }
@@ -535,3 +1118,114 @@ impl AuditFile {
             .collect::<HashSet<CanonicalPath>>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ident::PatternSet;
+    use crate::sink::Sink;
+
+    #[test]
+    fn test_content_hash() {
+        let audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        let json = audit_file.to_canonical_json().unwrap();
+        let loaded = AuditFile::from_json(&json).unwrap();
+        assert_eq!(loaded.to_canonical_json().unwrap(), json);
+
+        // Editing the file by hand invalidates the hash
+        let edited = json.replace("\"version\":0", "\"version\":1");
+        assert_ne!(edited, json);
+        assert!(AuditFile::from_json(&edited).is_err());
+    }
+
+    #[test]
+    fn test_gc() {
+        let tokens = "getuid fork".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    FilePath::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (kept, removed) = (&effects[0], &effects[1]);
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees([kept, removed]);
+        let info = EffectInfo::from_instance(removed);
+        audit_file.justifications.insert(info.clone(), "only in tests".to_string());
+
+        let scan = HashSet::from([kept]);
+        let day = Duration::from_secs(24 * 60 * 60);
+        // Missing effects are kept until the retention period is over
+        assert_eq!(audit_file.gc(&scan, day, 0), 0);
+        assert!(audit_file.stale_since.contains_key(removed));
+        assert_eq!(audit_file.gc(&scan, day, day.as_secs()), 1);
+        assert_eq!(audit_file.audit_trees.len(), 1);
+        assert!(audit_file.stale_since.is_empty());
+        assert!(audit_file.justifications.is_empty());
+        assert_eq!(&audit_file.archive[0].effect, removed);
+        assert_eq!(
+            audit_file.archive[0].justifications,
+            [(info, "only in tests".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_remove_undecided_sinks() {
+        let patterns =
+            PatternSet::from_strs(["wrapper::getuid", "wrapper::fork", "wrapper::kill"]);
+        let tokens = "getuid fork kill".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("wrapper::{}", t));
+                EffectInstance::new_effect(
+                    FilePath::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::SinkCall(Sink::new_match(&callee, &patterns).unwrap()),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (undecided, safe, justified) = (&effects[0], &effects[1], &effects[2]);
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees([undecided, safe, justified]);
+        audit_file.audit_trees.insert(
+            safe.clone(),
+            EffectTree::Leaf(EffectInfo::from_instance(safe), SafetyAnnotation::Safe),
+        );
+        audit_file
+            .justifications
+            .insert(EffectInfo::from_instance(justified), "checked".to_string());
+
+        let sinks = ["getuid", "fork", "kill"]
+            .iter()
+            .map(|f| CanonicalPath::new(&format!("wrapper::{}", f)))
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            audit_file.remove_undecided_sinks_from_tree(&sinks),
+            [undecided.clone()]
+        );
+        assert_eq!(audit_file.audit_trees.len(), 2);
+        assert_eq!(audit_file.remove_sinks_from_tree(&sinks).len(), 2);
+        assert!(audit_file.audit_trees.is_empty());
+    }
+
+    #[test]
+    fn test_validate_reference() {
+        assert!(validate_reference("https://github.com/org/repo/issues/12").is_ok());
+        assert!(validate_reference("https://example.atlassian.net/browse/SEC-34").is_ok());
+        assert!(validate_reference("http://jira.internal:8080/browse/SEC-1").is_ok());
+        assert!(validate_reference("SEC-34").is_err());
+        assert!(validate_reference("ftp://example.com/issue/1").is_err());
+        assert!(validate_reference("https://github.com/").is_err());
+        assert!(validate_reference("https://git hub.com/org/repo/issues/1").is_err());
+    }
+}
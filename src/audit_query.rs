@@ -0,0 +1,277 @@
+//! Library API for programs which read and update audit files (e.g. registry
+//! services or review bots), without depending on the audit file format:
+//! ```no_run
+//! use cargo_scan::audit_file::{AuditFile, SafetyAnnotation};
+//! use cargo_scan::audit_query::AuditQuery;
+//! use cargo_scan::effect::EffectType;
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = Path::new("audits/libc-0.2.147.audit");
+//! let mut audit = AuditFile::load(path)?;
+//! let query = AuditQuery::new()
+//!     .in_file("src/unix")
+//!     .effect_type(EffectType::FFICall)
+//!     .status(SafetyAnnotation::Unreviewed);
+//! let ids = audit.query(&query).iter().map(|e| e.id).collect::<Vec<_>>();
+//! for id in ids {
+//!     let note = Some("needs an FFI expert".to_string());
+//!     audit.annotate(id, SafetyAnnotation::Deferred, note)?;
+//! }
+//! audit.save(path)?;
+//! # Ok(())
+//! # }
+//! ```
+//! Entries are the base effects of the audit, and their ids are the same as
+//! in `audit list` and `audit decide`. Changes go through the same checks
+//! as decisions made with the audit tool (e.g. unsafe annotations need a
+//! justification), and audits are saved in the canonical format, with their
+//! content hash.
+
+use crate::audit_file::{AuditFile, EffectInfo, EffectTree, SafetyAnnotation};
+use crate::auditing::decide::{apply_decisions, Decision};
+use crate::effect::{EffectInstance, EffectType};
+use crate::ident::{CanonicalPath, Pattern};
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// A base effect of an audit, with its audit tree
+#[derive(Debug, Clone, Copy)]
+pub struct AuditEntry<'a> {
+    /// Id of the effect in the audit (see the module docs)
+    pub id: usize,
+    pub effect: &'a EffectInstance,
+    pub tree: &'a EffectTree,
+}
+
+impl<'a> AuditEntry<'a> {
+    /// The annotation of the effect; effects whose callers are audited
+    /// instead are caller-checked
+    pub fn status(&self) -> SafetyAnnotation {
+        self.tree.get_leaf_annotation().unwrap_or(SafetyAnnotation::CallerChecked)
+    }
+
+    pub fn effect_type(&self) -> EffectType {
+        EffectType::from(self.effect.eff_type())
+    }
+
+    /// The location the annotation, justification, and references are for
+    pub fn info(&self) -> &'a EffectInfo {
+        match self.tree {
+            EffectTree::Leaf(info, _) | EffectTree::Branch(info, _) => info,
+        }
+    }
+
+    pub fn caller(&self) -> &'a CanonicalPath {
+        self.effect.caller()
+    }
+
+    pub fn callee(&self) -> &'a CanonicalPath {
+        self.effect.callee()
+    }
+
+    /// The file of the effect, as scanned
+    pub fn file(&self) -> PathBuf {
+        let loc = self.effect.call_loc();
+        loc.dir().join(loc.file())
+    }
+}
+
+/// Which entries of an audit to select. Each filter narrows the selection,
+/// and filters of the same kind are alternatives (e.g. two effect types
+/// select the effects of either type).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    files: Vec<PathBuf>,
+    callers: Vec<Pattern>,
+    callees: Vec<Pattern>,
+    effect_types: Vec<EffectType>,
+    statuses: Vec<SafetyAnnotation>,
+}
+
+impl AuditQuery {
+    /// A query selecting every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Effects in a file or directory. Paths are matched against the end of
+    /// the scanned path, so they can be given relative to the crate (e.g.
+    /// `src/net`).
+    pub fn in_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.files.push(path.into());
+        self
+    }
+
+    /// Effects in functions matching the pattern (e.g. `my_crate::net`)
+    pub fn caller(mut self, pattern: &str) -> Self {
+        self.callers.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Effects of calls matching the pattern (e.g. `std::process`)
+    pub fn callee(mut self, pattern: &str) -> Self {
+        self.callees.push(Pattern::new(pattern));
+        self
+    }
+
+    pub fn effect_type(mut self, effect_type: EffectType) -> Self {
+        self.effect_types.push(effect_type);
+        self
+    }
+
+    /// Effects with the annotation (see `AuditEntry::status`)
+    pub fn status(mut self, annotation: SafetyAnnotation) -> Self {
+        self.statuses.push(annotation);
+        self
+    }
+
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let file = entry.file();
+        let in_file = |p: &PathBuf| file.ancestors().any(|a| a.ends_with(p));
+        (self.files.is_empty() || self.files.iter().any(in_file))
+            && (self.callers.is_empty()
+                || self.callers.iter().any(|p| entry.caller().matches(p)))
+            && (self.callees.is_empty()
+                || self.callees.iter().any(|p| entry.callee().matches(p)))
+            && (self.effect_types.is_empty()
+                || self.effect_types.contains(&entry.effect_type()))
+            && (self.statuses.is_empty() || self.statuses.contains(&entry.status()))
+    }
+}
+
+impl AuditFile {
+    /// The base effects of the audit with their ids, sorted by location
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.base_effects_by_location()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (effect, tree))| AuditEntry { id, effect, tree })
+            .collect()
+    }
+
+    pub fn entry(&self, id: usize) -> Option<AuditEntry> {
+        self.entries().into_iter().nth(id)
+    }
+
+    /// The entries selected by the query, sorted by location
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.entries().into_iter().filter(|e| query.matches(e)).collect()
+    }
+
+    /// Annotate an entry, with a justification, which annotations such as
+    /// `Unsafe` require. Marking an effect caller-checked adds its callers,
+    /// which needs a scan of the crate, so it is done with
+    /// `auditing::decide::apply_decisions` instead.
+    pub fn annotate(
+        &mut self,
+        id: usize,
+        annotation: SafetyAnnotation,
+        justification: Option<String>,
+    ) -> Result<()> {
+        let decision =
+            Decision { id, verdict: annotation, note: justification, refs: vec![] };
+        apply_decisions(self, &[decision], None).map(|_| ())
+    }
+
+    /// Attach an external reference (e.g. an issue URL) to an entry
+    pub fn add_entry_reference(&mut self, id: usize, url: &str) -> Result<()> {
+        let info = self
+            .entry(id)
+            .ok_or_else(|| anyhow!("No effect with id {} in the audit", id))?
+            .info()
+            .clone();
+        self.add_reference(&info, url)
+    }
+
+    /// Reset an entry to unreviewed, dropping its callers and justification
+    pub fn reset(&mut self, id: usize) -> Result<()> {
+        self.annotate(id, SafetyAnnotation::Unreviewed, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    fn ffi_audit() -> AuditFile {
+        let tokens = "getuid fork".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .zip(["src/lib.rs", "src/net/tcp.rs"])
+            .map(|(t, file)| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new(file),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit.set_base_audit_trees(effects.iter());
+        audit
+    }
+
+    #[test]
+    fn test_audit_query() {
+        let mut audit = ffi_audit();
+        assert_eq!(audit.query(&AuditQuery::new()).len(), 2);
+        let net = AuditQuery::new().in_file("src/net");
+        let ids = audit.query(&net).iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(audit.entry(ids[0]).unwrap().callee().as_str(), "libc::fork");
+        assert!(audit.query(&AuditQuery::new().callee("libc::fork_")).is_empty());
+        assert!(audit
+            .query(&AuditQuery::new().effect_type(EffectType::SinkCall))
+            .is_empty());
+
+        assert!(audit.annotate(ids[0], SafetyAnnotation::Unsafe, None).is_err());
+        audit
+            .annotate(ids[0], SafetyAnnotation::Unsafe, Some("forks".to_string()))
+            .unwrap();
+        audit
+            .add_entry_reference(ids[0], "https://github.com/org/repo/issues/1")
+            .unwrap();
+        let unsafe_effects = AuditQuery::new().status(SafetyAnnotation::Unsafe);
+        assert_eq!(audit.query(&unsafe_effects).len(), 1);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.audit");
+        audit.save(&path).unwrap();
+        let mut loaded = AuditFile::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            loaded.to_canonical_json().unwrap(),
+            audit.to_canonical_json().unwrap()
+        );
+        loaded.reset(ids[0]).unwrap();
+        assert!(loaded.query(&unsafe_effects).is_empty());
+        assert!(loaded.justifications.is_empty());
+        assert!(AuditFile::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_reset_caller_checked() {
+        let mut audit = ffi_audit();
+        let entry = audit.entry(0).unwrap();
+        let (effect, info) = (entry.effect.clone(), entry.info().clone());
+        // A public function with no callers in the crate
+        let caller = info.caller_path.clone();
+        audit.audit_trees.insert(
+            effect.clone(),
+            EffectTree::Leaf(info, SafetyAnnotation::CallerChecked),
+        );
+        audit.recalc_pub_caller_checked(&HashSet::from([caller.clone()]));
+        assert!(audit.pub_caller_checked[&caller].contains(&effect));
+
+        audit.reset(0).unwrap();
+        assert!(audit.pub_caller_checked.is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+//! Historical analytics of an audit store, from the git history of its audit
+//! files: how many effects each reviewer audited, how long effects of each
+//! severity waited to be audited, and how the backlog of unaudited effects
+//! changed over time.
+//!
+//! Audit files don't record when decisions were made, so each revision of an
+//! audit file is dated by its commit. An effect is audited in the first
+//! revision where all of its tree is audited, by the author of that commit,
+//! and waited from the first revision it appeared in. Effects which were
+//! already audited when they were first committed count for their reviewer,
+//! but not for the time to audit, since when they appeared is unknown.
+
+use crate::audit_file::AuditFile;
+use crate::effect::{EffectInstance, Severity};
+use crate::progress;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const SECS_PER_WEEK: i64 = 7 * SECS_PER_DAY;
+
+/// A committed revision of an audit file
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub author: String,
+    /// Commit time, in seconds since the Unix epoch
+    pub time: i64,
+    /// Severity of each base effect, and whether it is audited
+    pub effects: HashMap<EffectInstance, (Severity, bool)>,
+}
+
+impl Revision {
+    pub fn new(author: String, time: i64, audit: &AuditFile) -> Self {
+        let effects = audit
+            .audit_trees
+            .iter()
+            .map(|(e, t)| {
+                let audited = AuditFile::total_unaudited_effects(t) == 0;
+                (e.clone(), (e.eff_type().severity(), audited))
+            })
+            .collect();
+        Self { author, time, effects }
+    }
+
+    fn unaudited(&self) -> usize {
+        self.effects.values().filter(|(_, audited)| !audited).count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewerStats {
+    pub reviewer: String,
+    pub audited: usize,
+    /// Times of the reviewer's first and last commits auditing effects
+    pub first: i64,
+    pub last: i64,
+}
+
+impl ReviewerStats {
+    /// Effects audited per week, over the weeks the reviewer was active (at
+    /// least one)
+    pub fn per_week(&self) -> f64 {
+        let weeks = ((self.last - self.first) as f64 / SECS_PER_WEEK as f64).max(1.0);
+        self.audited as f64 / weeks
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacklogPoint {
+    /// The day, as YYYY-MM-DD
+    pub date: String,
+    pub unaudited: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Analytics {
+    /// Reviewers, most effects audited first
+    pub reviewers: Vec<ReviewerStats>,
+    /// The number of effects of each severity audited after they appeared,
+    /// and the average seconds they waited
+    pub time_to_audit: BTreeMap<Severity, (usize, f64)>,
+    /// Unaudited and total base effects in the store at the end of each day
+    /// with commits
+    pub backlog: Vec<BacklogPoint>,
+}
+
+/// The date of a Unix time, as YYYY-MM-DD (in UTC)
+pub fn date_string(time: i64) -> String {
+    // Days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = time.div_euclid(SECS_PER_DAY) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// RFC 3339 time (in UTC) of a Unix time
+pub fn timestamp(time: i64) -> String {
+    let secs = time.rem_euclid(SECS_PER_DAY);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date_string(time),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Analyze the histories of the audit files in a store, each in
+/// chronological order
+pub fn analyze(histories: &[Vec<Revision>]) -> Analytics {
+    let mut reviewers: HashMap<&str, ReviewerStats> = HashMap::new();
+    let mut waits: BTreeMap<Severity, Vec<i64>> = BTreeMap::new();
+
+    for history in histories {
+        // When each effect in the current revision was first unaudited (if it
+        // is still unaudited), or None if it is audited
+        let mut pending: HashMap<&EffectInstance, Option<i64>> = HashMap::new();
+        for rev in history {
+            let mut next = HashMap::new();
+            for (e, (severity, audited)) in &rev.effects {
+                let prev = pending.get(e).copied();
+                if !audited {
+                    next.insert(e, Some(prev.flatten().unwrap_or(rev.time)));
+                    continue;
+                }
+                next.insert(e, None);
+                if prev == Some(None) {
+                    continue;
+                }
+                let stats = reviewers.entry(rev.author.as_str()).or_insert_with(|| {
+                    ReviewerStats {
+                        reviewer: rev.author.clone(),
+                        audited: 0,
+                        first: rev.time,
+                        last: rev.time,
+                    }
+                });
+                stats.audited += 1;
+                stats.first = stats.first.min(rev.time);
+                stats.last = stats.last.max(rev.time);
+                if let Some(Some(since)) = prev {
+                    waits.entry(*severity).or_default().push(rev.time - since);
+                }
+            }
+            pending = next;
+        }
+    }
+
+    let mut reviewers = reviewers.into_values().collect::<Vec<_>>();
+    reviewers.sort_by(|r1, r2| {
+        r2.audited.cmp(&r1.audited).then_with(|| r1.reviewer.cmp(&r2.reviewer))
+    });
+    let time_to_audit = waits
+        .into_iter()
+        .map(|(s, w)| (s, (w.len(), w.iter().sum::<i64>() as f64 / w.len() as f64)))
+        .collect();
+
+    Analytics { reviewers, time_to_audit, backlog: backlog(histories) }
+}
+
+/// The store's backlog at the end of each day with commits
+fn backlog(histories: &[Vec<Revision>]) -> Vec<BacklogPoint> {
+    let mut revisions = histories
+        .iter()
+        .enumerate()
+        .flat_map(|(file, h)| h.iter().map(move |rev| (rev.time, file, rev)))
+        .collect::<Vec<_>>();
+    revisions.sort_by_key(|(time, file, _)| (*time, *file));
+
+    // The unaudited and total effects in the latest revision of each file
+    let mut current: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut points: Vec<BacklogPoint> = Vec::new();
+    for (time, file, rev) in revisions {
+        current.insert(file, (rev.unaudited(), rev.effects.len()));
+        let point = BacklogPoint {
+            date: date_string(time),
+            unaudited: current.values().map(|(u, _)| u).sum(),
+            total: current.values().map(|(_, t)| t).sum(),
+        };
+        match points.last_mut() {
+            Some(last) if last.date == point.date => *last = point,
+            _ => points.push(point),
+        }
+    }
+    points
+}
+
+fn format_duration(secs: f64) -> String {
+    let days = secs / SECS_PER_DAY as f64;
+    if days >= 1.0 {
+        format!("{:.1} days", days)
+    } else {
+        format!("{:.1} hours", secs / 3600.0)
+    }
+}
+
+impl Analytics {
+    pub fn print(&self) {
+        println!("Reviewer throughput:");
+        if self.reviewers.is_empty() {
+            println!("  - no audited effects");
+        }
+        for r in &self.reviewers {
+            println!(
+                "  - {}: {} effects audited ({:.1} per week)",
+                r.reviewer,
+                r.audited,
+                r.per_week()
+            );
+        }
+        println!("Average time to audit:");
+        if self.time_to_audit.is_empty() {
+            println!("  - no effects audited after they were committed");
+        }
+        for (severity, (count, secs)) in self.time_to_audit.iter().rev() {
+            println!("  - {}: {} ({} effects)", severity, format_duration(*secs), count);
+        }
+        println!("Backlog (unaudited of total base effects):");
+        for p in &self.backlog {
+            println!("  - {}: {} of {}", p.date, p.unaudited, p.total);
+        }
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The committed revisions of an audit file, in chronological order.
+/// Revisions which can't be parsed (e.g. from older versions of cargo-scan)
+/// are skipped.
+pub fn file_history(path: &Path) -> Result<Vec<Revision>> {
+    let dir =
+        path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("Invalid audit file path: {:?}", path))?;
+
+    let log = git(dir, &["log", "--reverse", "--format=%H%x09%at%x09%an", "--", name])?;
+    let mut history = Vec::new();
+    for line in log.lines() {
+        let fields = line.splitn(3, '\t').collect::<Vec<_>>();
+        let [commit, time, author] = fields[..] else {
+            continue;
+        };
+        let time = time.parse::<i64>().context("Invalid commit time from git")?;
+        let contents = git(dir, &["show", &format!("{}:./{}", commit, name)])?;
+        match AuditFile::from_json(&contents) {
+            Ok(audit) => history.push(Revision::new(author.to_string(), time, &audit)),
+            Err(e) => warn!("Skipping revision {} of {:?}: {}", commit, path, e),
+        }
+    }
+    Ok(history)
+}
+
+/// The audit files in a store: the path itself if it's a file, or the
+/// `.audit` files in it if it's a directory
+pub fn store_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let p = entry?.path();
+        if p.is_file() && p.extension().map_or(false, |e| e == "audit") {
+            files.push(p);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Analyze the history of the audit file or directory of audit files, which
+/// must be in a git repository
+pub fn analyze_store(path: &Path) -> Result<Analytics> {
+    let mut histories = Vec::new();
+    for file in store_files(path)? {
+        let history = file_history(&file)?;
+        if history.is_empty() {
+            warn!("No committed revisions of {:?}", file);
+        }
+        histories.push(history);
+    }
+    progress!(
+        "Read {} revisions of {} audit files",
+        histories.iter().map(|h| h.len()).sum::<usize>(),
+        histories.len()
+    );
+    Ok(analyze(&histories))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+
+    fn effect(callee: &str) -> EffectInstance {
+        let callee = CanonicalPath::new(callee);
+        let span = syn::parse_str::<syn::Ident>("f").unwrap();
+        EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("my_crate::f"),
+            callee.clone(),
+            &span,
+            Effect::FFICall(callee),
+        )
+    }
+
+    fn revision(author: &str, day: i64, effects: &[(&EffectInstance, bool)]) -> Revision {
+        Revision {
+            author: author.to_string(),
+            time: day * SECS_PER_DAY,
+            effects: effects
+                .iter()
+                .map(|(e, audited)| ((*e).clone(), (Severity::High, *audited)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_date_string() {
+        assert_eq!(date_string(0), "1970-01-01");
+        assert_eq!(date_string(951782400), "2000-02-29");
+        assert_eq!(date_string(1700000000), "2023-11-14");
+    }
+
+    #[test]
+    fn test_analyze() {
+        let (e1, e2, e3) = (effect("libc::a"), effect("libc::b"), effect("libc::c"));
+        let history = vec![
+            revision("alice", 0, &[(&e1, false), (&e2, true)]),
+            revision("bob", 2, &[(&e1, true), (&e2, true), (&e3, false)]),
+            revision("bob", 2, &[(&e1, true), (&e2, true), (&e3, false)]),
+            revision("alice", 6, &[(&e1, true), (&e2, true), (&e3, true)]),
+        ];
+        let analytics = analyze(&[history]);
+
+        let audited = analytics
+            .reviewers
+            .iter()
+            .map(|r| (r.reviewer.as_str(), r.audited))
+            .collect::<Vec<_>>();
+        assert_eq!(audited, [("alice", 2), ("bob", 1)]);
+        // e1 waited 2 days and e3 waited 4; e2 was audited when committed
+        let (count, secs) = analytics.time_to_audit[&Severity::High];
+        assert_eq!(count, 2);
+        assert_eq!(secs, (3 * SECS_PER_DAY) as f64);
+
+        let backlog = analytics
+            .backlog
+            .iter()
+            .map(|p| (p.date.as_str(), p.unaudited, p.total))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            backlog,
+            [("1970-01-01", 1, 2), ("1970-01-03", 1, 3), ("1970-01-07", 0, 3)]
+        );
+    }
+}
@@ -1,11 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::audit_chain::AuditChain;
 use crate::audit_file::{EffectInfo, EffectTree};
+use crate::auditing::cluster::{cluster_effects, EffectCluster};
+use crate::auditing::fan_in;
 use crate::auditing::info::*;
+use crate::auditing::keys::AuditAction;
+use crate::auditing::messages::{Locale, Msg};
+use crate::auditing::session::{AuditQueue, SessionCommand};
 use crate::effect::{Effect, EffectInstance};
 use crate::ident::CanonicalPath;
-use crate::scanner::scan_crate;
+use crate::scanner::{scan_crate, ScanConfig};
 use crate::sink::Sink;
 use crate::{
     audit_file::{AuditFile, SafetyAnnotation},
@@ -14,49 +19,293 @@ use crate::{
 use anyhow::{anyhow, Result};
 use inquire::{validator::Validation, Text};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AuditStatus {
     EarlyExit,
     ContinueAudit,
     AuditChildEffect,
     AuditParentEffect,
     ExpandContext,
+    /// The user entered a command changing the queue of effects to audit,
+    /// before deciding on the current effect
+    Command(SessionCommand),
+}
+
+enum PromptAnswer {
+    Action(AuditAction),
+    Command(SessionCommand),
+}
+
+// Prompts the user to pick one of the actions by its key, listing the keys
+// whenever they ask for help. If session commands are allowed, the user may
+// enter one instead.
+fn prompt_action(
+    header: String,
+    actions: &[AuditAction],
+    session_commands: bool,
+    config: &Config,
+) -> Result<PromptAnswer> {
+    let locale = config.locale();
+    let mut prompt = header;
+    prompt.push_str(&config.keys.options(actions, locale));
+    if session_commands {
+        prompt.push_str(Msg::SessionCommandsOption.text(locale));
+    }
+    loop {
+        let keys = config.keys.clone();
+        let valid_actions = actions.to_vec();
+        let ans = Text::new(&prompt)
+            .with_validator(move |x: &str| {
+                if session_commands && x.starts_with(':') {
+                    return match x.parse::<SessionCommand>() {
+                        Ok(_) => Ok(Validation::Valid),
+                        Err(e) => Ok(Validation::Invalid(
+                            Msg::InvalidCommand.fill(locale, &[&e]).into(),
+                        )),
+                    };
+                }
+                match keys.action(x, &valid_actions) {
+                    Some(_) => Ok(Validation::Valid),
+                    None => {
+                        Ok(Validation::Invalid(Msg::InvalidInput.text(locale).into()))
+                    }
+                }
+            })
+            .prompt()?;
+
+        if session_commands && ans.starts_with(':') {
+            return Ok(PromptAnswer::Command(ans.parse()?));
+        }
+        match config.keys.action(&ans, actions) {
+            Some(AuditAction::Help) => println!("{}", config.keys.help(actions, locale)),
+            Some(action) => return Ok(PromptAnswer::Action(action)),
+            None => return Err(anyhow!("Invalid annotation selection")),
+        }
+    }
 }
 
 // Returns Some SafetyAnnotation if the user selects one, None if the user
 // chooses to exit early, or an Error
 fn get_user_annotation(
-    allow_effect_origin: bool,
+    config: &Config,
 ) -> Result<(Option<SafetyAnnotation>, AuditStatus)> {
-    let ans = Text::new(&format!(
-        r#"Select how to mark this effect:
-  (s)afe, (u)nsafe, (c)aller checked,{} (e)xpand context, ask me (l)ater, e(x)it tool
-"#,
-        if allow_effect_origin { " audit effect (o)rigin," } else { "" }
-    ))
-    .with_validator(move |x: &str| match x {
-        "s" | "u" | "c" | "e" | "l" | "x" => Ok(Validation::Valid),
-        "o" if allow_effect_origin => Ok(Validation::Valid),
-        _ => Ok(Validation::Invalid("Invalid input".into())),
-    })
-    .prompt()
-    .unwrap();
-
-    match ans.as_str() {
-        "s" => Ok((Some(SafetyAnnotation::Safe), AuditStatus::ContinueAudit)),
-        "u" => Ok((Some(SafetyAnnotation::Unsafe), AuditStatus::ContinueAudit)),
-        "c" => Ok((Some(SafetyAnnotation::CallerChecked), AuditStatus::ContinueAudit)),
-        "l" => Ok((Some(SafetyAnnotation::Skipped), AuditStatus::ContinueAudit)),
-        "o" => Ok((None, AuditStatus::AuditChildEffect)),
-        "e" => Ok((None, AuditStatus::ExpandContext)),
-        "x" => Ok((None, AuditStatus::EarlyExit)),
+    let locale = config.locale();
+    let mut actions = vec![
+        AuditAction::Safe,
+        AuditAction::Unsafe,
+        AuditAction::CallerChecked,
+        AuditAction::Defer,
+        AuditAction::FalsePositive,
+    ];
+    if config.allow_effect_origin {
+        actions.push(AuditAction::Origin);
+    }
+    actions.extend([
+        AuditAction::Expand,
+        AuditAction::Later,
+        AuditAction::Exit,
+        AuditAction::Help,
+    ]);
+    let header = Msg::AnnotationPrompt.text(locale).to_string();
+    let action = match prompt_action(header, &actions, config.session_commands, config)? {
+        PromptAnswer::Action(action) => action,
+        PromptAnswer::Command(command) => {
+            return Ok((None, AuditStatus::Command(command)))
+        }
+    };
+
+    match action {
+        AuditAction::Safe => {
+            Ok((Some(SafetyAnnotation::Safe), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Unsafe => {
+            Ok((Some(SafetyAnnotation::Unsafe), AuditStatus::ContinueAudit))
+        }
+        AuditAction::CallerChecked => {
+            Ok((Some(SafetyAnnotation::CallerChecked), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Defer => {
+            Ok((Some(SafetyAnnotation::Deferred), AuditStatus::ContinueAudit))
+        }
+        AuditAction::FalsePositive => {
+            Ok((Some(SafetyAnnotation::FalsePositive), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Later => {
+            Ok((Some(SafetyAnnotation::Unreviewed), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Origin => Ok((None, AuditStatus::AuditChildEffect)),
+        AuditAction::Expand => Ok((None, AuditStatus::ExpandContext)),
+        AuditAction::Exit => Ok((None, AuditStatus::EarlyExit)),
+        AuditAction::Individually | AuditAction::Help => {
+            Err(anyhow!("Invalid annotation selection"))
+        }
+    }
+}
+
+// Prompts the user for a justification of the annotation. Unsafe, deferred, and
+// false positive annotations must be justified; for the others the user may
+// leave it empty.
+fn get_user_justification(
+    annotation: SafetyAnnotation,
+    locale: Locale,
+) -> Result<Option<String>> {
+    let required = annotation.requires_justification();
+    let optional = if required { "" } else { Msg::JustificationOptional.text(locale) };
+    let prompt = Msg::JustificationPrompt.fill(locale, &[&annotation, &optional]);
+    let ans = Text::new(&prompt)
+        .with_validator(move |x: &str| {
+            if required && x.trim().is_empty() {
+                let msg = Msg::JustificationRequired.text(locale);
+                Ok(Validation::Invalid(msg.into()))
+            } else {
+                Ok(Validation::Valid)
+            }
+        })
+        .prompt()?;
+
+    Ok(Some(ans).filter(|j| !j.trim().is_empty()))
+}
+
+// Returns Some SafetyAnnotation to mark the whole cluster with, None to audit
+// its effects individually, or EarlyExit if the user chooses to exit
+fn get_cluster_annotation(
+    cluster_size: usize,
+    config: &Config,
+) -> Result<(Option<SafetyAnnotation>, AuditStatus)> {
+    let actions = [
+        AuditAction::Safe,
+        AuditAction::Unsafe,
+        AuditAction::Defer,
+        AuditAction::FalsePositive,
+        AuditAction::Individually,
+        AuditAction::Exit,
+        AuditAction::Help,
+    ];
+    let header = Msg::ClusterPrompt.fill(config.locale(), &[&cluster_size]);
+    let action = match prompt_action(header, &actions, false, config)? {
+        PromptAnswer::Action(action) => action,
+        PromptAnswer::Command(_) => return Err(anyhow!("Invalid annotation selection")),
+    };
+
+    match action {
+        AuditAction::Safe => {
+            Ok((Some(SafetyAnnotation::Safe), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Unsafe => {
+            Ok((Some(SafetyAnnotation::Unsafe), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Defer => {
+            Ok((Some(SafetyAnnotation::Deferred), AuditStatus::ContinueAudit))
+        }
+        AuditAction::FalsePositive => {
+            Ok((Some(SafetyAnnotation::FalsePositive), AuditStatus::ContinueAudit))
+        }
+        AuditAction::Individually => Ok((None, AuditStatus::ContinueAudit)),
+        AuditAction::Exit => Ok((None, AuditStatus::EarlyExit)),
         _ => Err(anyhow!("Invalid annotation selection")),
     }
 }
 
+/// Show a cluster of similar unreviewed effects, with its first effect as an
+/// example, and mark all of them with the user's decision
+fn audit_cluster(
+    audit_file: &mut AuditFile,
+    cluster: &EffectCluster,
+    index: usize,
+    n_clusters: usize,
+    scan_res: &ScanResults,
+    config: &Config,
+) -> Result<AuditStatus> {
+    let locale = config.locale();
+    let common_context = if cluster.common_context.is_empty() {
+        String::new()
+    } else {
+        Msg::ClusterCommonContext.fill(locale, &[&cluster.common_context.join("`, `")])
+    };
+    println!();
+    println!(
+        "{}",
+        Msg::ClusterHeader.fill(
+            locale,
+            &[
+                &(index + 1),
+                &n_clusters,
+                &cluster.effects.len(),
+                &cluster.callee(),
+                &common_context
+            ]
+        )
+    );
+    for e in &cluster.effects {
+        println!("  - {}", e.call_loc());
+    }
+
+    let example = cluster.effects[0];
+    let info = EffectInfo::from_instance(example);
+    if print_effect_info(example, &info, &[], &scan_res.fn_locs, config).is_err() {
+        println!("{}", Msg::ErrorPrintingEffect.text(locale));
+    }
+
+    let annotation = match get_cluster_annotation(cluster.effects.len(), config)? {
+        (Some(a), _) => a,
+        (None, status) => return Ok(status),
+    };
+    let justification = get_user_justification(annotation, locale)?;
+    for e in &cluster.effects {
+        let Some(tree) = audit_file.audit_trees.get_mut(*e) else {
+            continue;
+        };
+        let info = match tree {
+            EffectTree::Leaf(info, _) | EffectTree::Branch(info, _) => info.clone(),
+        };
+        AuditFile::set_justification(
+            &mut audit_file.justifications,
+            &info,
+            annotation,
+            justification.clone(),
+        )?;
+        tree.set_annotation(annotation);
+    }
+    println!(
+        "{}",
+        Msg::ClusterMarked.fill(locale, &[&cluster.effects.len(), &annotation])
+    );
+    Ok(AuditStatus::ContinueAudit)
+}
+
+/// Offer to mark each cluster of similar unreviewed effects with a single
+/// decision, and then each cluster of deferred effects
+fn audit_clusters(
+    audit_file: &mut AuditFile,
+    scan_res: &ScanResults,
+    config: &Config,
+) -> Result<AuditStatus> {
+    let with_status = |status| {
+        audit_file
+            .audit_trees
+            .iter()
+            .filter(|(_, t)| t.get_leaf_annotation() == Some(status))
+            .map(|(e, _)| e.clone())
+            .collect::<Vec<_>>()
+    };
+    let unreviewed = with_status(SafetyAnnotation::Unreviewed);
+    let deferred = with_status(SafetyAnnotation::Deferred);
+    let mut clusters = cluster_effects(&unreviewed);
+    clusters.extend(cluster_effects(&deferred));
+    for (i, cluster) in clusters.iter().enumerate() {
+        let status =
+            audit_cluster(audit_file, cluster, i, clusters.len(), scan_res, config)?;
+        if status == AuditStatus::EarlyExit {
+            return Ok(status);
+        }
+    }
+    Ok(AuditStatus::ContinueAudit)
+}
+
 fn print_and_update_audit<'a>(
     orig_effect: &'a EffectInstance,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     effect_history: &[&'a EffectInfo],
     scan_res: &ScanResults,
     config: &Config,
@@ -74,13 +323,14 @@ fn print_and_update_audit<'a>(
     )
     .is_err()
     {
-        println!("Error printing effect information. Trying to continue...");
+        println!("{}", Msg::ErrorPrintingEffect.text(config.locale()));
     }
 
     match update_audit_from_input(
         orig_effect,
         scan_res,
         effect_tree,
+        justifications,
         effect_history,
         curr_effect,
         config,
@@ -91,6 +341,7 @@ fn print_and_update_audit<'a>(
             print_and_update_audit(
                 orig_effect,
                 effect_tree,
+                justifications,
                 effect_history,
                 scan_res,
                 &config,
@@ -103,16 +354,25 @@ fn print_and_update_audit<'a>(
 fn audit_leaf<'a>(
     orig_effect: &'a EffectInstance,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     effect_history: &[&'a EffectInfo],
     scan_res: &ScanResults,
     config: &Config,
 ) -> Result<AuditStatus> {
-    print_and_update_audit(orig_effect, effect_tree, effect_history, scan_res, config)
+    print_and_update_audit(
+        orig_effect,
+        effect_tree,
+        justifications,
+        effect_history,
+        scan_res,
+        config,
+    )
 }
 
 fn update_audit_child<'a>(
     orig_effect: &'a EffectInstance,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     effect_history: &[&'a EffectInfo],
     scan_res: &ScanResults,
     config: &Config,
@@ -133,13 +393,14 @@ fn update_audit_child<'a>(
     )
     .is_err()
     {
-        println!("Error printing effect information. Trying to continue...");
+        println!("{}", Msg::ErrorPrintingEffect.text(config.locale()));
     }
 
     match update_audit_from_input(
         orig_effect,
         scan_res,
         effect_tree,
+        justifications,
         effect_history,
         curr_effect,
         config,
@@ -150,6 +411,7 @@ fn update_audit_child<'a>(
             update_audit_child(
                 orig_effect,
                 effect_tree,
+                justifications,
                 effect_history,
                 scan_res,
                 &config,
@@ -162,6 +424,7 @@ fn update_audit_child<'a>(
 fn audit_branch<'a>(
     orig_effect: &'a EffectInstance,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     effect_history: &[&'a EffectInfo],
     scan_res: &ScanResults,
     config: &Config,
@@ -180,12 +443,13 @@ fn audit_branch<'a>(
                     match audit_branch(
                         orig_effect,
                         next_e,
+                        justifications,
                         &next_history,
                         scan_res,
                         config,
                     )? {
-                        AuditStatus::EarlyExit => {
-                            return Ok(AuditStatus::EarlyExit);
+                        s @ (AuditStatus::EarlyExit | AuditStatus::Command(_)) => {
+                            return Ok(s);
                         }
                         AuditStatus::AuditChildEffect => {
                             audit_child = true;
@@ -198,12 +462,13 @@ fn audit_branch<'a>(
                     match audit_leaf(
                         orig_effect,
                         next_e,
+                        justifications,
                         &next_history,
                         scan_res,
                         config,
                     )? {
-                        AuditStatus::EarlyExit => {
-                            return Ok(AuditStatus::EarlyExit);
+                        s @ (AuditStatus::EarlyExit | AuditStatus::Command(_)) => {
+                            return Ok(s);
                         }
                         AuditStatus::AuditChildEffect => {
                             audit_child = true;
@@ -216,7 +481,14 @@ fn audit_branch<'a>(
         }
 
         if audit_child {
-            update_audit_child(orig_effect, effect_tree, effect_history, scan_res, config)
+            update_audit_child(
+                orig_effect,
+                effect_tree,
+                justifications,
+                effect_history,
+                scan_res,
+                config,
+            )
         } else {
             Ok(AuditStatus::ContinueAudit)
         }
@@ -231,15 +503,16 @@ fn audit_branch<'a>(
 fn audit_effect_tree(
     orig_effect: &EffectInstance,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     scan_res: &ScanResults,
     config: &Config,
 ) -> Result<AuditStatus> {
     match effect_tree {
         e @ EffectTree::Leaf(..) => {
-            audit_leaf(orig_effect, e, &Vec::new(), scan_res, config)
+            audit_leaf(orig_effect, e, justifications, &Vec::new(), scan_res, config)
         }
         e @ EffectTree::Branch(..) => {
-            audit_branch(orig_effect, e, &Vec::new(), scan_res, config)
+            audit_branch(orig_effect, e, justifications, &Vec::new(), scan_res, config)
         }
     }
 }
@@ -253,64 +526,89 @@ fn audit_effect_tree(
 /// the auditing process on those effect trees. Will exit early if the user
 /// audits one of the root effects as needing to check its child effects, in
 /// which case we will return Ok with Some EffectInstance which contains the effect
-/// in the dependency crates that need to be audited.
+/// in the dependency crates that need to be audited. `downstream` holds the
+/// scans of the crates depending on it in a chain, whose callers count toward
+/// the fan-in (see `fan_in`).
 pub fn start_audit(
     audit_file: &mut AuditFile,
     scan_res: ScanResults,
+    downstream: &[ScanResults],
     config: &Config,
 ) -> Result<Option<EffectInstance>> {
     // We will set this to the root effect we need to audit if we audit an
     // effect tree and need to now traverse into the dependency packages.
     let mut dependency_audit_effect: Option<EffectInstance> = None;
 
+    let locale = config.locale();
     let (unaudited_base, unaudited_total) = audit_file.unaudited_effects();
     if unaudited_base > 0 {
-        println!("Total unaudited effects: {}", unaudited_base);
-        println!("Total unaudited locations: {}", unaudited_total);
+        println!("{}", Msg::TotalUnauditedEffects.fill(locale, &[&unaudited_base]));
+        println!("{}", Msg::TotalUnauditedLocations.fill(locale, &[&unaudited_total]));
     }
 
     if audit_file.has_unsafe_effect() {
-        println!("WARNING: package has been marked as unsafe");
+        println!("{}", Msg::UnsafePackageWarning.text(locale));
     }
 
-    // Iterate through the effects and prompt the user for if they're safe
-    for (e, t) in audit_file.audit_trees.iter_mut() {
-        match t.get_leaf_annotation() {
-            Some(SafetyAnnotation::Skipped) => {
-                match audit_effect_tree(e, t, &scan_res, config)? {
-                    AuditStatus::EarlyExit => {
-                        break;
-                    }
-                    AuditStatus::AuditChildEffect => {
-                        dependency_audit_effect = Some(e.clone());
-                        break;
+    if config.cluster
+        && audit_clusters(audit_file, &scan_res, config)? == AuditStatus::EarlyExit
+    {
+        println!("{}", Msg::NoMoreEffects.text(locale));
+        audit_file.recalc_pub_caller_checked(&scan_res.pub_fns);
+        return Ok(None);
+    }
+
+    // Go through the effects which need auditing, in order of location (or
+    // of fan-in), and prompt the user for if they're safe. The user can change the queue
+    // with commands as they go.
+    let mut config = config.clone();
+    config.session_commands = true;
+    let mut queue = AuditQueue::new(audit_file);
+    if config.fan_in_order {
+        let fan_in = fan_in::fan_in(&scan_res, downstream);
+        queue.prioritize(|e| {
+            audit_file.audit_trees.get(e).map_or(0, |t| fan_in::priority(t, &fan_in))
+        });
+        println!("{}", Msg::FanInOrder.text(locale));
+    }
+    while let Some(e) = queue.pop() {
+        let Some(t) = audit_file.audit_trees.get_mut(&e) else {
+            continue;
+        };
+        match audit_effect_tree(
+            &e,
+            t,
+            &mut audit_file.justifications,
+            &scan_res,
+            &config,
+        )? {
+            AuditStatus::EarlyExit => {
+                break;
+            }
+            AuditStatus::AuditChildEffect => {
+                dependency_audit_effect = Some(e);
+                break;
+            }
+            AuditStatus::AuditParentEffect => {
+                return Err(anyhow!("We should never return this status here"));
+            }
+            AuditStatus::Command(command) => {
+                queue.push_front(&e);
+                match queue.apply(&command, audit_file) {
+                    Ok(()) if command == SessionCommand::Help => {
+                        println!("{}", Msg::SessionHelp.text(locale))
                     }
-                    AuditStatus::AuditParentEffect => {
-                        return Err(anyhow!("We should never return this status here"));
+                    Ok(()) => {
+                        println!("{}", Msg::QueueUpdated.fill(locale, &[&queue.len()]))
                     }
-                    _ => (),
+                    Err(err) => println!("{}", Msg::InvalidCommand.fill(locale, &[&err])),
                 }
             }
-
-            Some(_) => (),
-
-            None => match audit_effect_tree(e, t, &scan_res, config)? {
-                AuditStatus::EarlyExit => {
-                    break;
-                }
-                AuditStatus::AuditChildEffect => {
-                    dependency_audit_effect = Some(e.clone());
-                    break;
-                }
-                AuditStatus::AuditParentEffect => {
-                    return Err(anyhow!("We should never return this status here"));
-                }
-                _ => (),
-            },
+            _ => (),
         }
     }
 
-    println!("No more effects to audit");
+    println!("{}", Msg::NoMoreEffects.text(locale));
 
     // NOTE: We recalculate the public functions here so we don't have to keep
     //       track of them during the audit. This is a bit slower, but simplifies
@@ -337,9 +635,9 @@ fn update_audit_annotation(
 
             // Add all call locations as parents of this effect
             let new_check_locs = scan_res
-                .get_callers(&curr_effect.caller_path)?
+                .get_sorted_callers(&curr_effect.caller_path)?
                 .into_iter()
-                .map(|e| EffectTree::Leaf(e, SafetyAnnotation::Skipped))
+                .map(|e| EffectTree::Leaf(e, SafetyAnnotation::Unreviewed))
                 .collect::<Vec<_>>();
 
             if new_check_locs.is_empty() {
@@ -366,16 +664,34 @@ fn update_audit_from_input(
     orig_effect: &EffectInstance,
     scan_res: &ScanResults,
     effect_tree: &mut EffectTree,
+    justifications: &mut HashMap<EffectInfo, String>,
     effect_history: &[&EffectInfo],
     curr_effect: EffectInfo,
     config: &Config,
 ) -> Result<AuditStatus> {
-    match get_user_annotation(config.allow_effect_origin) {
+    let locale = config.locale();
+    match get_user_annotation(config) {
         Ok((Some(a), AuditStatus::ContinueAudit)) => {
+            if a != SafetyAnnotation::Unreviewed && a != SafetyAnnotation::CallerChecked {
+                let justification = get_user_justification(a, locale)?;
+                AuditFile::set_justification(
+                    justifications,
+                    &curr_effect,
+                    a,
+                    justification,
+                )?;
+            }
             let update_status =
                 update_audit_annotation(a, scan_res, effect_tree, curr_effect)?;
             if update_status == AuditStatus::AuditParentEffect {
-                audit_branch(orig_effect, effect_tree, effect_history, scan_res, config)
+                audit_branch(
+                    orig_effect,
+                    effect_tree,
+                    justifications,
+                    effect_history,
+                    scan_res,
+                    config,
+                )
             } else {
                 Ok(AuditStatus::ContinueAudit)
             }
@@ -385,7 +701,8 @@ fn update_audit_from_input(
         )),
         Ok((_, s @ AuditStatus::AuditChildEffect))
         | Ok((_, s @ AuditStatus::EarlyExit))
-        | Ok((_, s @ AuditStatus::ExpandContext)) => Ok(s),
+        | Ok((_, s @ AuditStatus::ExpandContext))
+        | Ok((_, s @ AuditStatus::Command(_))) => Ok(s),
         Ok((_, AuditStatus::AuditParentEffect)) => {
             // TODO: This is for the case where we are walking down the effect
             //       stack for auditing child effects and the user decides they
@@ -394,7 +711,7 @@ fn update_audit_from_input(
             unimplemented!();
         }
         Err(_) => {
-            println!("Error accepting user input. Attempting to continue...");
+            println!("{}", Msg::ErrorAcceptingInput.text(locale));
             Ok(AuditStatus::ContinueAudit)
         }
     }
@@ -408,6 +725,7 @@ pub fn audit_pub_fn(
     chain: &mut AuditChain,
     sink_ident: &Sink,
     config: &Config,
+    scan_config: &ScanConfig,
 ) -> Result<HashSet<CanonicalPath>> {
     let sink_crate = sink_ident
         .first_ident()
@@ -420,8 +738,11 @@ pub fn audit_pub_fn(
     let mut new_audit_file = prev_audit_file.clone();
 
     // Find the public function associated with the sink
-    let scan_res =
-        scan_crate(&new_audit_file.base_dir, &prev_audit_file.scanned_effects)?;
+    let scan_res = scan_crate(
+        &new_audit_file.base_dir,
+        &prev_audit_file.scanned_effects,
+        scan_config,
+    )?;
     let sink_fn = CanonicalPath::new(sink_ident.as_str());
     loop {
         // Keep looping until we are done with auditing children
@@ -451,7 +772,7 @@ pub fn audit_pub_fn(
                         ))
                     }
                 };
-                audit_pub_fn(chain, child_sink, config)?;
+                audit_pub_fn(chain, child_sink, config, scan_config)?;
                 // We have to reload the new audit file because auditing child
                 // effects may have removed some base effects from the current
                 // crate
@@ -477,6 +798,9 @@ pub fn audit_pub_fn(
             (AuditStatus::ExpandContext, _) => {
                 return Err(anyhow!("Shouldn't return ExpandContext when auditing public function effects"));
             }
+            (AuditStatus::Command(_), _) => {
+                return Err(anyhow!("Session commands aren't available when auditing public function effects"));
+            }
         }
     }
 
@@ -509,7 +833,13 @@ fn audit_pub_fn_effect(
             })?;
 
         loop {
-            let res = audit_effect_tree(base_effect, effect_tree, scan_res, &config)?;
+            let res = audit_effect_tree(
+                base_effect,
+                effect_tree,
+                &mut audit_file.justifications,
+                scan_res,
+                &config,
+            )?;
             match res {
                 AuditStatus::ContinueAudit => break,
                 s @ AuditStatus::EarlyExit => {
@@ -524,6 +854,11 @@ fn audit_pub_fn_effect(
                 AuditStatus::ExpandContext => {
                     config.expand_context();
                 }
+                AuditStatus::Command(_) => {
+                    return Err(anyhow!(
+                        "Session commands aren't available when auditing public function effects"
+                    ));
+                }
             }
         }
     }
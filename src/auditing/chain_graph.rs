@@ -0,0 +1,171 @@
+//! The effect call chains of an audit as a graph, in the DOT (Graphviz) or
+//! Mermaid format, e.g. for `audit --export-chains chains.dot`.
+//!
+//! Each base effect is an edge from its caller to its callee, and each branch
+//! of its effect tree is an edge from a caller of a function to the function,
+//! so the paths into a callee are the chains through which its effect is
+//! reached. Edges are labeled with the kinds of the effects they lead to.
+
+use crate::audit_file::{AuditFile, EffectTree};
+use crate::effect::EffectInstance;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl GraphFormat {
+    /// Mermaid for `.mmd` and `.mermaid` files, DOT otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("mmd" | "mermaid") => Self::Mermaid,
+            _ => Self::Dot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainGraph {
+    /// Effect kinds of each edge, by caller and callee
+    edges: BTreeMap<(String, String), BTreeSet<String>>,
+}
+
+impl ChainGraph {
+    pub fn new<'a, I>(audit_trees: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a EffectInstance, &'a EffectTree)>,
+    {
+        let mut graph = Self::default();
+        for (effect, tree) in audit_trees {
+            let kind = effect.eff_type().simple_str();
+            graph.add_edge(effect.caller().as_str(), effect.callee().as_str(), &kind);
+            graph.add_tree(tree, &kind);
+        }
+        graph
+    }
+
+    pub fn from_audit_file(audit_file: &AuditFile) -> Self {
+        Self::new(&audit_file.audit_trees)
+    }
+
+    fn add_edge(&mut self, caller: &str, callee: &str, kind: &str) {
+        self.edges
+            .entry((caller.to_string(), callee.to_string()))
+            .or_default()
+            .insert(kind.to_string());
+    }
+
+    fn add_tree(&mut self, tree: &EffectTree, kind: &str) {
+        if let EffectTree::Branch(info, children) = tree {
+            for child in children {
+                let caller = match child {
+                    EffectTree::Leaf(i, _) | EffectTree::Branch(i, _) => &i.caller_path,
+                };
+                self.add_edge(caller.as_str(), info.caller_path.as_str(), kind);
+                self.add_tree(child, kind);
+            }
+        }
+    }
+
+    fn nodes(&self) -> BTreeSet<&str> {
+        self.edges.keys().flat_map(|(a, b)| [a.as_str(), b.as_str()]).collect()
+    }
+
+    fn label(kinds: &BTreeSet<String>) -> String {
+        kinds.iter().map(String::as_str).collect::<Vec<_>>().join(", ")
+    }
+
+    pub fn to_dot(&self) -> String {
+        let quote =
+            |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("digraph effects {\n");
+        for ((caller, callee), kinds) in &self.edges {
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label={}];",
+                quote(caller),
+                quote(callee),
+                quote(&Self::label(kinds))
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        // Mermaid ids can't contain `::`, so nodes get numbered ids
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "#quot;"));
+        let ids: BTreeMap<&str, usize> =
+            self.nodes().into_iter().enumerate().map(|(i, n)| (n, i)).collect();
+        let mut mermaid = String::from("flowchart LR\n");
+        for (node, id) in &ids {
+            let _ = writeln!(mermaid, "    n{}[{}]", id, quote(node));
+        }
+        for ((caller, callee), kinds) in &self.edges {
+            let _ = writeln!(
+                mermaid,
+                "    n{} -->|{}| n{}",
+                ids[caller.as_str()],
+                quote(&Self::label(kinds)),
+                ids[callee.as_str()]
+            );
+        }
+        mermaid
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit_file::{EffectInfo, SafetyAnnotation};
+    use crate::effect::{Effect, SrcLoc};
+    use crate::ident::CanonicalPath;
+
+    #[test]
+    fn test_chain_graph() {
+        let callee = CanonicalPath::new("libc::getuid");
+        let effect = EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("lib::f"),
+            callee.clone(),
+            &"getuid".parse::<proc_macro2::TokenStream>().unwrap(),
+            Effect::FFICall(callee),
+        );
+        let info =
+            |caller: &str| EffectInfo::new(CanonicalPath::new(caller), SrcLoc::default());
+        let tree = EffectTree::Branch(
+            info("lib::f"),
+            vec![EffectTree::Leaf(info("lib::g"), SafetyAnnotation::Unreviewed)],
+        );
+        let graph = ChainGraph::new([(&effect, &tree)]);
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph effects {\n    \
+                \"lib::f\" -> \"libc::getuid\" [label=\"[FFI]\"];\n    \
+                \"lib::g\" -> \"lib::f\" [label=\"[FFI]\"];\n\
+            }\n"
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            "flowchart LR\n    \
+                n0[\"lib::f\"]\n    \
+                n1[\"lib::g\"]\n    \
+                n2[\"libc::getuid\"]\n    \
+                n0 -->|\"[FFI]\"| n2\n    \
+                n1 -->|\"[FFI]\"| n0\n"
+        );
+    }
+}
@@ -0,0 +1,727 @@
+//! Check an audit chain before building, e.g. in CI.
+//!
+//! An effect marked unsafe rejects the crate it is in. The rejection blocks
+//! every crate on the dependency path from the root crate to the rejecting
+//! crate, and the check fails if the root crate is blocked.
+//!
+//! The check also fails if a crate's audit coverage (the percentage of
+//! locations that have been audited) is below the coverage required in the
+//! chain manifest, e.g.
+//! ```toml
+//! [coverage]
+//! min_audit_coverage = 80
+//! [coverage.crates]
+//! libc = 95
+//! [coverage.severity]
+//! High = 100
+//! ```
+//!
+//! Audits can also stay valid for a range of versions of a crate (see
+//! `VersionTrust`), so that e.g. a patch release whose effects are unchanged
+//! doesn't need auditing again. The decisions of the trusted audit carry over
+//! to the same effects of the crate for the coverage requirements; effects
+//! marked unsafe still reject it.
+//!
+//! Audits created from a scan record the scanned files with their hashes (see
+//! `ScanManifest`), and the check also fails if the sources of a crate changed
+//! since its audit was created, as the audit may be missing effects. Crates
+//! whose sources are no longer on disk aren't checked.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use anyhow::{anyhow, Context, Result};
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::audit_chain::AuditChain;
+use crate::audit_file::{AuditFile, EffectInfo, EffectTree, SafetyAnnotation};
+use crate::effect::{EffectInstance, Severity};
+use crate::ident::Pattern;
+use crate::scan_manifest::ChangedFile;
+use crate::scanner::ScanConfig;
+use crate::util::CrateId;
+
+/// Minimum audit coverage percentages. Requirements for a specific crate
+/// override `min_audit_coverage`; requirements for a severity class apply to
+/// the effects of that severity in every crate.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CoverageRequirements {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_audit_coverage: Option<f64>,
+    /// Keyed by crate name, with or without the version
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crates: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub severity: BTreeMap<Severity, f64>,
+}
+
+impl CoverageRequirements {
+    pub fn is_empty(&self) -> bool {
+        self.min_audit_coverage.is_none()
+            && self.crates.is_empty()
+            && self.severity.is_empty()
+    }
+
+    /// The coverage required for all effects in a crate, if any
+    pub fn crate_requirement(&self, crate_id: &CrateId) -> Option<f64> {
+        self.crates
+            .get(&crate_id.to_string())
+            .or_else(|| self.crates.get(&crate_id.crate_name))
+            .copied()
+            .or(self.min_audit_coverage)
+    }
+
+    /// Returns the coverage failures for a crate's audit
+    fn check_audit(
+        &self,
+        crate_id: &CrateId,
+        audit_file: &AuditFile,
+    ) -> Vec<CoverageFailure> {
+        let mut requirements = Vec::new();
+        if let Some(required) = self.crate_requirement(crate_id) {
+            requirements.push((None, required));
+        }
+        requirements.extend(self.severity.iter().map(|(s, r)| (Some(*s), *r)));
+
+        requirements
+            .into_iter()
+            .filter_map(|(severity, required)| {
+                let (audited, total) = audit_file.audit_coverage(severity);
+                let coverage = coverage_percent(audited, total);
+                (coverage < required).then(|| CoverageFailure {
+                    crate_id: crate_id.clone(),
+                    severity,
+                    coverage,
+                    required,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A decision that the audit of a crate stays valid for a range of its
+/// versions, e.g. "trusted for 1.x as long as the effects are unchanged".
+/// It applies to a version in the range only if the fingerprint of that
+/// version's effects is the one recorded for the audited version.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VersionTrust {
+    pub crate_name: String,
+    /// Semver requirement for the trusted versions, e.g. `1` or `~0.2.150`
+    pub versions: String,
+    /// Only trust the effects whose callee matches this pattern (e.g.
+    /// `libc::getuid`), instead of all of the crate's effects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callee: Option<String>,
+    /// The version whose audit the decision was made from
+    pub audited_version: String,
+    /// Fingerprint of the trusted effects (see `effects_fingerprint`)
+    pub fingerprint: String,
+    /// The audited trees of the trusted effects, in order of location
+    #[serde(default)]
+    pub verdicts: Vec<TrustedVerdict>,
+}
+
+/// The audit of one of the trusted effects, identified like in the
+/// fingerprint by its caller, callee, and kind
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrustedVerdict {
+    pub caller: String,
+    pub callee: String,
+    pub kind: String,
+    /// The nodes of the effect's audit tree, in preorder
+    pub nodes: Vec<TrustedNode>,
+}
+
+/// A node of a trusted audit tree: the effect itself at depth 0, or a caller
+/// it was propagated to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrustedNode {
+    pub depth: usize,
+    pub info: EffectInfo,
+    /// The decision, or None for a caller-checked location whose callers
+    /// were audited instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<SafetyAnnotation>,
+}
+
+impl TrustedVerdict {
+    fn from_tree(effect: &EffectInstance, tree: &EffectTree) -> Self {
+        fn tree_walk(tree: &EffectTree, depth: usize, nodes: &mut Vec<TrustedNode>) {
+            match tree {
+                EffectTree::Leaf(info, a) => nodes.push(TrustedNode {
+                    depth,
+                    info: info.clone(),
+                    annotation: Some(*a),
+                }),
+                EffectTree::Branch(info, ts) => {
+                    nodes.push(TrustedNode {
+                        depth,
+                        info: info.clone(),
+                        annotation: None,
+                    });
+                    ts.iter().for_each(|t| tree_walk(t, depth + 1, nodes));
+                }
+            }
+        }
+        let mut nodes = Vec::new();
+        tree_walk(tree, 0, &mut nodes);
+        Self {
+            caller: effect.caller_path().to_string(),
+            callee: effect.callee_path().to_string(),
+            kind: effect.eff_type().simple_str().into_owned(),
+            nodes,
+        }
+    }
+
+    fn to_tree(&self) -> Option<EffectTree> {
+        fn subtree(nodes: &[TrustedNode], i: &mut usize) -> Option<EffectTree> {
+            let node = nodes.get(*i)?;
+            *i += 1;
+            if let Some(a) = node.annotation {
+                return Some(EffectTree::Leaf(node.info.clone(), a));
+            }
+            let mut ts = Vec::new();
+            while nodes.get(*i).map_or(false, |n| n.depth > node.depth) {
+                ts.push(subtree(nodes, i)?);
+            }
+            Some(EffectTree::Branch(node.info.clone(), ts))
+        }
+        subtree(&self.nodes, &mut 0)
+    }
+}
+
+impl VersionTrust {
+    /// Trust the audit of the crate for the versions, checking that the
+    /// trusted effects in it are all audited and none of them are rejected
+    pub fn new(
+        crate_id: &CrateId,
+        audit_file: &AuditFile,
+        versions: &str,
+        callee: Option<String>,
+    ) -> Result<Self> {
+        let req = VersionReq::parse(versions)
+            .context(format!("Invalid version requirement: {}", versions))?;
+        if !req.matches(&crate_id.version) {
+            return Err(anyhow!(
+                "{} is not in the trusted versions {}",
+                crate_id,
+                versions
+            ));
+        }
+        let trust = Self {
+            crate_name: crate_id.crate_name.clone(),
+            versions: versions.to_string(),
+            callee,
+            audited_version: crate_id.version.to_string(),
+            fingerprint: String::new(),
+            verdicts: Vec::new(),
+        };
+        let unaudited = audit_file
+            .audit_trees
+            .iter()
+            .filter(|(e, _)| trust.covers(e))
+            .map(|(_, t)| AuditFile::total_unaudited_effects(t))
+            .sum::<usize>();
+        if unaudited > 0 {
+            return Err(anyhow!(
+                "{} has {} unaudited locations to trust; audit them first",
+                crate_id,
+                unaudited
+            ));
+        }
+        let rejected = audit_file
+            .rejected_effects()
+            .into_iter()
+            .filter(|(e, _)| trust.covers(e))
+            .count();
+        if rejected > 0 {
+            return Err(anyhow!(
+                "{} has {} locations marked unsafe; a rejected audit can't be trusted",
+                crate_id,
+                rejected
+            ));
+        }
+        let fingerprint = trust.fingerprint(audit_file);
+        let verdicts = trust
+            .covered_effects(audit_file)
+            .into_iter()
+            .map(|e| TrustedVerdict::from_tree(e, &audit_file.audit_trees[e]))
+            .collect();
+        Ok(Self { fingerprint, verdicts, ..trust })
+    }
+
+    /// Whether the crate is one of the trusted versions
+    pub fn applies_to(&self, crate_id: &CrateId) -> bool {
+        crate_id.crate_name == self.crate_name
+            && VersionReq::parse(&self.versions)
+                .map_or(false, |req| req.matches(&crate_id.version))
+    }
+
+    fn covers(&self, effect: &EffectInstance) -> bool {
+        self.callee.as_ref().map_or(true, |c| effect.callee().matches(&Pattern::new(c)))
+    }
+
+    /// The trusted effects of the audit, sorted by location
+    fn covered_effects<'a>(&self, audit_file: &'a AuditFile) -> Vec<&'a EffectInstance> {
+        let mut effects =
+            audit_file.audit_trees.keys().filter(|e| self.covers(e)).collect::<Vec<_>>();
+        effects.sort();
+        effects
+    }
+
+    /// Fingerprint of the trusted effects in the audit: a hash of their
+    /// callers, callees, and kinds, so it doesn't change when code moves
+    /// around between versions
+    pub fn fingerprint(&self, audit_file: &AuditFile) -> String {
+        let mut effects = audit_file
+            .audit_trees
+            .keys()
+            .filter(|e| self.covers(e))
+            .map(|e| {
+                let kind = e.eff_type().simple_str();
+                format!("{}\t{}\t{}\n", e.caller_path(), e.callee_path(), kind)
+            })
+            .collect::<Vec<_>>();
+        effects.sort();
+        format!("{:x}", Sha256::digest(effects.concat().as_bytes()))
+    }
+
+    /// Carry the trusted decisions over to the same effects of the audit:
+    /// effects with the same caller, callee, and kind are paired up in order
+    /// of location, and the unaudited locations in each tree get the decision
+    /// on the same function in the trusted tree. Effects which were
+    /// caller-checked keep the trusted callers they were propagated to.
+    fn mark_audited(&self, audit_file: &mut AuditFile) {
+        fn carry(tree: &mut EffectTree, trusted: &EffectTree) {
+            match (&mut *tree, trusted) {
+                (EffectTree::Leaf(_, a), EffectTree::Leaf(_, t)) if !a.is_audited() => {
+                    *a = *t
+                }
+                (EffectTree::Leaf(info, a), EffectTree::Branch(_, ts))
+                    if !a.is_audited() =>
+                {
+                    *tree = EffectTree::Branch(info.clone(), ts.clone())
+                }
+                (EffectTree::Branch(_, ts), EffectTree::Branch(_, trusted_ts)) => {
+                    for t in ts {
+                        // The callers' definitions may have moved, so they
+                        // are compared by path
+                        let caller = tree_info(t).caller_path.as_str();
+                        if let Some(tt) = trusted_ts
+                            .iter()
+                            .find(|tt| tree_info(tt).caller_path.as_str() == caller)
+                        {
+                            carry(t, tt);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        fn tree_info(tree: &EffectTree) -> &EffectInfo {
+            match tree {
+                EffectTree::Leaf(info, _) | EffectTree::Branch(info, _) => info,
+            }
+        }
+
+        let mut verdicts: HashMap<_, VecDeque<_>> = HashMap::new();
+        for v in &self.verdicts {
+            let key = (v.caller.clone(), v.callee.clone(), v.kind.clone());
+            verdicts.entry(key).or_default().extend(v.to_tree());
+        }
+        let effects =
+            self.covered_effects(audit_file).into_iter().cloned().collect::<Vec<_>>();
+        for e in effects {
+            let key = (
+                e.caller_path().to_string(),
+                e.callee_path().to_string(),
+                e.eff_type().simple_str().into_owned(),
+            );
+            let Some(trusted) = verdicts.get_mut(&key).and_then(|ts| ts.pop_front())
+            else {
+                continue;
+            };
+            if let Some(tree) = audit_file.audit_trees.get_mut(&e) {
+                carry(tree, &trusted);
+            }
+        }
+    }
+}
+
+impl fmt::Display for VersionTrust {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.crate_name, self.versions)?;
+        if let Some(callee) = &self.callee {
+            write!(f, " (calls to {})", callee)?;
+        }
+        write!(f, ", audited at {}", self.audited_version)
+    }
+}
+
+/// Percentage of audited locations; crates without any effects are fully
+/// covered
+fn coverage_percent(audited: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * audited as f64 / total as f64
+    }
+}
+
+/// A crate whose audit coverage is below the required coverage
+#[derive(Debug, Clone)]
+pub struct CoverageFailure {
+    pub crate_id: CrateId,
+    /// The severity class the requirement is for, or None for all effects
+    pub severity: Option<Severity>,
+    pub coverage: f64,
+    pub required: f64,
+}
+
+/// A location marked unsafe in one of the crates in the chain
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub crate_id: CrateId,
+    /// The base effect whose tree contains the rejected location
+    pub effect: EffectInstance,
+    pub location: EffectInfo,
+    pub justification: Option<String>,
+    /// Crates from the root crate to the rejecting crate
+    pub dependency_path: Vec<CrateId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckResult {
+    pub rejections: Vec<Rejection>,
+    pub coverage_failures: Vec<CoverageFailure>,
+    /// Crates whose audit was carried over from another version by a
+    /// version trust
+    pub trusted: Vec<(CrateId, VersionTrust)>,
+    /// Crates in the range of a version trust which didn't apply, since
+    /// their effects changed
+    pub stale_trusts: Vec<(CrateId, VersionTrust)>,
+    /// Crates whose sources changed since their audit was created
+    pub stale_scans: Vec<(CrateId, Vec<ChangedFile>)>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.rejections.is_empty()
+            && self.coverage_failures.is_empty()
+            && self.stale_scans.is_empty()
+    }
+
+    /// Crates blocked by a rejection, i.e. the rejecting crates and every crate
+    /// depending on them on the way to the root crate
+    pub fn blocked_crates(&self) -> BTreeSet<&CrateId> {
+        self.rejections.iter().flat_map(|r| r.dependency_path.iter()).collect()
+    }
+
+    pub fn print_report(&self) {
+        if !self.trusted.is_empty() {
+            println!("Trusted from the audit of another version:");
+            for (c, t) in &self.trusted {
+                println!("  - {} ({})", c, t);
+            }
+        }
+        if !self.stale_trusts.is_empty() {
+            println!("Version trusts not applied, since the effects changed:");
+            for (c, t) in &self.stale_trusts {
+                println!("  - {} ({})", c, t);
+            }
+        }
+
+        if self.passed() {
+            println!("Audit check passed");
+            return;
+        }
+
+        println!(
+            "Audit check FAILED: {} rejected effect(s), {} coverage failure(s), \
+            {} stale audit(s)",
+            self.rejections.len(),
+            self.coverage_failures.len(),
+            self.stale_scans.len()
+        );
+        for r in &self.rejections {
+            println!();
+            println!("  crate: {}", r.crate_id);
+            println!(
+                "  dependency path: {}",
+                r.dependency_path
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            );
+            println!(
+                "  effect: {} in {}",
+                r.effect.eff_type().simple_str(),
+                r.effect.caller()
+            );
+            println!("  effect location: {}", r.effect.call_loc());
+            if &r.location.callee_loc != r.effect.call_loc() {
+                println!(
+                    "  rejected at: {} in {}",
+                    r.location.callee_loc, r.location.caller_path
+                );
+            }
+            match &r.justification {
+                Some(j) => println!("  justification: {}", j),
+                None => println!("  justification: (none given)"),
+            }
+        }
+
+        if !self.rejections.is_empty() {
+            println!();
+            println!("Blocked crates:");
+            for c in self.blocked_crates() {
+                println!("  - {}", c);
+            }
+        }
+
+        if !self.coverage_failures.is_empty() {
+            println!();
+            println!("Insufficient audit coverage:");
+            for f in &self.coverage_failures {
+                let effects = match f.severity {
+                    Some(s) => format!("{} severity effects", s),
+                    None => "all effects".to_string(),
+                };
+                println!(
+                    "  - {} ({}): {:.1}% audited, {:.1}% required",
+                    f.crate_id, effects, f.coverage, f.required
+                );
+            }
+        }
+
+        if !self.stale_scans.is_empty() {
+            println!();
+            println!("Crates changed since they were audited (audit them again):");
+            for (c, files) in &self.stale_scans {
+                println!("  - {}", c);
+                for f in files {
+                    println!("      {}", f);
+                }
+            }
+        }
+    }
+}
+
+/// Collect all rejected effects in the chain along with the dependency path
+/// that they block, all crates with insufficient audit coverage, and all
+/// crates changed since their audit was created
+pub fn check_chain(
+    chain: &mut AuditChain,
+    coverage: &CoverageRequirements,
+) -> Result<CheckResult> {
+    let dep_paths = chain.dependency_paths()?;
+    let mut crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+    crate_ids.sort();
+
+    let version_trusts = chain.version_trusts().to_vec();
+    // New files are those a scan with the root project's settings would read
+    let mut scan_config = ScanConfig::default();
+    scan_config.load_project(chain.crate_path())?;
+    let mut rejections = Vec::new();
+    let mut coverage_failures = Vec::new();
+    let mut trusted = Vec::new();
+    let mut stale_trusts = Vec::new();
+    let mut stale_scans = Vec::new();
+    for crate_id in crate_ids {
+        let mut audit_file = chain
+            .read_audit_file(&crate_id)?
+            .context(format!("Couldn't find audit for {}", crate_id))?;
+        let dependency_path =
+            dep_paths.get(&crate_id).cloned().unwrap_or_else(|| vec![crate_id.clone()]);
+        for (effect, location) in audit_file.rejected_effects() {
+            rejections.push(Rejection {
+                crate_id: crate_id.clone(),
+                effect: effect.clone(),
+                location: location.clone(),
+                justification: audit_file.justifications.get(location).cloned(),
+                dependency_path: dependency_path.clone(),
+            });
+        }
+
+        for trust in version_trusts.iter().filter(|t| t.applies_to(&crate_id)) {
+            let applies = trust.fingerprint(&audit_file) == trust.fingerprint;
+            if applies {
+                trust.mark_audited(&mut audit_file);
+            }
+            if crate_id.version.to_string() == trust.audited_version {
+                continue;
+            }
+            if applies {
+                trusted.push((crate_id.clone(), trust.clone()));
+            } else {
+                stale_trusts.push((crate_id.clone(), trust.clone()));
+            }
+        }
+        coverage_failures.extend(coverage.check_audit(&crate_id, &audit_file));
+
+        if let Some(manifest) = &audit_file.scan_manifest {
+            if audit_file.base_dir.is_dir() {
+                let changed = manifest.changed_files(
+                    &audit_file.base_dir,
+                    &audit_file.scanned_effects,
+                    &scan_config,
+                )?;
+                if !changed.is_empty() {
+                    stale_scans.push((crate_id.clone(), changed));
+                }
+            }
+        }
+    }
+
+    Ok(CheckResult { rejections, coverage_failures, trusted, stale_trusts, stale_scans })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use semver::Version;
+    use std::path::{Path, PathBuf};
+
+    fn krate(name: &str, version: &str) -> CrateId {
+        CrateId::new(name.to_string(), Version::parse(version).unwrap())
+    }
+
+    /// An audit of FFI calls to the tokens of the source, in order
+    fn audit(src: &str) -> AuditFile {
+        let tokens = src.parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("mycrate::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees(&effects);
+        audit_file
+    }
+
+    #[test]
+    fn test_crate_requirement() {
+        let reqs = CoverageRequirements {
+            min_audit_coverage: Some(50.0),
+            crates: BTreeMap::from([
+                ("libc".to_string(), 90.0),
+                ("libc-0.2.0".to_string(), 95.0),
+            ]),
+            severity: BTreeMap::new(),
+        };
+        assert_eq!(reqs.crate_requirement(&krate("libc", "0.2.0")), Some(95.0));
+        assert_eq!(reqs.crate_requirement(&krate("libc", "0.2.1")), Some(90.0));
+        assert_eq!(reqs.crate_requirement(&krate("memchr", "2.0.0")), Some(50.0));
+        assert_eq!(coverage_percent(0, 0), 100.0);
+        assert_eq!(coverage_percent(1, 4), 25.0);
+    }
+
+    #[test]
+    fn test_version_trust() {
+        let audited = krate("mycrate", "1.2.0");
+        let mut audit_file = audit("getuid\nfork");
+        assert!(VersionTrust::new(&audited, &audit_file, "1", None).is_err());
+        // Trusting only the audited callee works before the rest is audited
+        let getuid = audit_file
+            .audit_trees
+            .keys()
+            .find(|e| e.callee_path() == "libc::getuid")
+            .unwrap()
+            .clone();
+        let info = EffectInfo::from_instance(&getuid);
+        audit_file
+            .audit_trees
+            .insert(getuid, EffectTree::Leaf(info, SafetyAnnotation::Safe));
+        let callee_trust = VersionTrust::new(
+            &audited,
+            &audit_file,
+            "1",
+            Some("libc::getuid".to_string()),
+        )
+        .unwrap();
+        assert!(VersionTrust::new(&audited, &audit_file, "2", None).is_err());
+
+        assert!(callee_trust.applies_to(&krate("mycrate", "1.3.1")));
+        assert!(!callee_trust.applies_to(&krate("mycrate", "2.0.0")));
+        assert!(!callee_trust.applies_to(&krate("othercrate", "1.2.0")));
+
+        // Effects moving around don't change the fingerprint
+        let moved = audit("\n\ngetuid\nfork");
+        assert_eq!(callee_trust.fingerprint(&moved), callee_trust.fingerprint);
+        let changed = audit("getuid\ngetuid\nfork");
+        assert_ne!(callee_trust.fingerprint(&changed), callee_trust.fingerprint);
+        // Other callees aren't covered by the trust
+        let other = audit("getuid\nfork\nkill");
+        assert_eq!(callee_trust.fingerprint(&other), callee_trust.fingerprint);
+
+        let mut moved = moved;
+        callee_trust.mark_audited(&mut moved);
+        assert_eq!(moved.audit_coverage(None), (1, 2));
+    }
+
+    #[test]
+    fn test_version_trust_verdicts() {
+        let audited = krate("mycrate", "1.2.0");
+        let mut audit_file = audit("getuid\nfork");
+        let mut effects = audit_file.audit_trees.keys().cloned().collect::<Vec<_>>();
+        effects.sort();
+        // getuid is checked by the callers of f, and fork is rejected
+        let getuid_info = EffectInfo::from_instance(&effects[0]);
+        let caller = EffectInfo::new(
+            CanonicalPath::new("mycrate::g"),
+            getuid_info.callee_loc.clone(),
+        );
+        audit_file.audit_trees.insert(
+            effects[0].clone(),
+            EffectTree::Branch(
+                getuid_info,
+                vec![EffectTree::Leaf(caller, SafetyAnnotation::CallerChecked)],
+            ),
+        );
+        let fork_info = EffectInfo::from_instance(&effects[1]);
+        audit_file.audit_trees.insert(
+            effects[1].clone(),
+            EffectTree::Leaf(fork_info, SafetyAnnotation::Unsafe),
+        );
+        assert!(VersionTrust::new(&audited, &audit_file, "1", None).is_err());
+
+        let trust = VersionTrust::new(
+            &audited,
+            &audit_file,
+            "1",
+            Some("libc::getuid".to_string()),
+        )
+        .unwrap();
+        let toml = toml::to_string(&trust).unwrap();
+        assert_eq!(toml::from_str::<VersionTrust>(&toml).unwrap(), trust);
+
+        // The moved effect keeps its own location, and the trusted callers
+        let mut moved = audit("\n\ngetuid\nfork");
+        trust.mark_audited(&mut moved);
+        let getuid = moved.audit_trees.keys().find(|e| e.callee_path() == "libc::getuid");
+        match &moved.audit_trees[getuid.unwrap()] {
+            EffectTree::Branch(info, ts) => {
+                assert_eq!(info.callee_loc.start_line(), 3);
+                assert_eq!(
+                    ts[0].get_leaf_annotation(),
+                    Some(SafetyAnnotation::CallerChecked)
+                );
+            }
+            t => panic!("Expected the trusted callers, found {:?}", t),
+        }
+        assert_eq!(moved.audit_coverage(None), (1, 2));
+    }
+}
@@ -0,0 +1,254 @@
+//! Clusters of similar effects, so a reviewer can handle many near-identical
+//! effects (e.g. 23 calls to `slice::from_raw_parts`, each right after a
+//! length check) with one informed decision.
+//!
+//! Effects of the same kind with the same callee are compared by the source
+//! around them: the context is split into tokens, and two effects are similar
+//! if the Jaccard similarity of their sets of token shingles (runs of
+//! `SHINGLE_LEN` consecutive tokens) is at least `SIMILARITY_THRESHOLD`. Each
+//! effect joins the first cluster whose first effect it is similar to.
+//! Effects whose source can't be read have no context to compare, so they
+//! aren't clustered.
+
+use crate::effect::{EffectInstance, EffectType};
+use crate::util;
+
+use log::warn;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Number of consecutive tokens in a shingle
+const SHINGLE_LEN: usize = 3;
+
+/// Minimum similarity for an effect to join a cluster
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+/// Lines of source before and after an effect compared for similarity
+const CONTEXT_LINES: usize = 2;
+
+/// Number of context identifiers shown for a cluster, and the minimum length
+/// of those identifiers
+const MAX_COMMON_CONTEXT: usize = 5;
+const MIN_CONTEXT_IDENT_LEN: usize = 3;
+
+/// Identifiers too common to describe a cluster
+const COMMON_IDENTS: &[&str] =
+    &["let", "mut", "unsafe", "fn", "pub", "self", "return", "as", "ref", "in"];
+
+/// A group of similar effects, in source order
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectCluster<'a> {
+    pub effects: Vec<&'a EffectInstance>,
+    /// Identifiers in the context of every effect in the cluster, other than
+    /// the callee's
+    pub common_context: Vec<String>,
+}
+
+impl<'a> EffectCluster<'a> {
+    pub fn callee(&self) -> &'a str {
+        self.effects[0].callee_path()
+    }
+}
+
+/// Split source code into identifier, number, and punctuation tokens
+fn tokens(src: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in src.char_indices() {
+        if c.is_alphanumeric() || c == '_' {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            tokens.push(&src[s..i]);
+        }
+        if !c.is_whitespace() {
+            tokens.push(&src[i..i + c.len_utf8()]);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&src[s..]);
+    }
+    tokens
+}
+
+fn shingles<'s>(tokens: &[&'s str]) -> HashSet<Vec<&'s str>> {
+    if tokens.len() < SHINGLE_LEN {
+        return HashSet::from([tokens.to_vec()]);
+    }
+    tokens.windows(SHINGLE_LEN).map(|w| w.to_vec()).collect()
+}
+
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn is_ident(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_alphabetic() || c == '_')
+}
+
+/// Identifiers shared by the contexts, other than those in the callee
+fn common_context(callee: &str, contexts: &[&str]) -> Vec<String> {
+    let callee_segments = callee.split("::").collect::<HashSet<_>>();
+    let mut common: Option<BTreeSet<&str>> = None;
+    for context in contexts {
+        let idents = tokens(context)
+            .into_iter()
+            .filter(|t| is_ident(t) && t.len() >= MIN_CONTEXT_IDENT_LEN)
+            .filter(|t| !callee_segments.contains(t) && !COMMON_IDENTS.contains(t))
+            .collect::<BTreeSet<_>>();
+        common = Some(match common {
+            Some(c) => c.intersection(&idents).copied().collect(),
+            None => idents,
+        });
+    }
+    common
+        .unwrap_or_default()
+        .into_iter()
+        .take(MAX_COMMON_CONTEXT)
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Cluster the effects by the source context of each. Only clusters of at
+/// least two effects are returned, largest first.
+pub fn cluster_by_context<'a>(
+    effects: &[(&'a EffectInstance, String)],
+) -> Vec<EffectCluster<'a>> {
+    let mut groups: HashMap<(String, &str), Vec<&(&'a EffectInstance, String)>> =
+        HashMap::new();
+    for e in effects {
+        let kind = EffectType::from(e.0.eff_type()).to_string();
+        groups.entry((kind, e.0.callee_path())).or_default().push(e);
+    }
+
+    let mut clusters = Vec::new();
+    for group in groups.into_values() {
+        // Each cluster's effects, and the shingles of its first effect
+        let mut group_clusters: Vec<(Vec<&(&'a EffectInstance, String)>, HashSet<_>)> =
+            Vec::new();
+        for e in group {
+            let e_shingles = shingles(&tokens(&e.1));
+            match group_clusters
+                .iter_mut()
+                .find(|(_, s)| jaccard(s, &e_shingles) >= SIMILARITY_THRESHOLD)
+            {
+                Some((members, _)) => members.push(e),
+                None => group_clusters.push((vec![e], e_shingles)),
+            }
+        }
+        for (members, _) in group_clusters {
+            if members.len() < 2 {
+                continue;
+            }
+            let contexts = members.iter().map(|(_, c)| c.as_str()).collect::<Vec<_>>();
+            let mut effects = members.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+            effects.sort();
+            let common_context = common_context(effects[0].callee_path(), &contexts);
+            clusters.push(EffectCluster { effects, common_context });
+        }
+    }
+    clusters.sort_by(|c1, c2| {
+        c2.effects.len().cmp(&c1.effects.len()).then(c1.effects[0].cmp(c2.effects[0]))
+    });
+    clusters
+}
+
+/// Cluster the effects by the source around them. Effects without any source
+/// (e.g. in a file which was removed) are left out, rather than clustered
+/// together on their empty contexts.
+pub fn cluster_effects<'a, I>(effects: I) -> Vec<EffectCluster<'a>>
+where
+    I: IntoIterator<Item = &'a EffectInstance>,
+{
+    let mut sources: HashMap<PathBuf, Option<Vec<String>>> = HashMap::new();
+    let with_context = effects
+        .into_iter()
+        .filter_map(|e| {
+            let loc = e.call_loc();
+            let lines = sources
+                .entry(PathBuf::from(loc.filepath_string()))
+                .or_insert_with_key(|path| match util::fs::read_source(path) {
+                    Ok(src) => Some(src.text.lines().map(|l| l.to_string()).collect()),
+                    Err(err) => {
+                        warn!("Not clustering the effects in {:?} ({})", path, err);
+                        None
+                    }
+                })
+                .as_ref()?;
+            // SrcLocs are 1-indexed
+            let start = loc.start_line().saturating_sub(1 + CONTEXT_LINES);
+            let end = (loc.end_line() + CONTEXT_LINES).min(lines.len());
+            let context = lines.get(start..end).filter(|c| !c.is_empty())?.join("\n");
+            Some((e, context))
+        })
+        .collect::<Vec<_>>();
+    cluster_by_context(&with_context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::Path;
+
+    fn effect(callee: &str, line: &str) -> (EffectInstance, String) {
+        let callee = CanonicalPath::new(callee);
+        let span = syn::parse_str::<syn::Ident>("f").unwrap();
+        let e = EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("my_crate::f"),
+            callee.clone(),
+            &span,
+            Effect::UnsafeCall(callee),
+        );
+        (e, line.to_string())
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            tokens("a.len() >= n_1"),
+            ["a", ".", "len", "(", ")", ">", "=", "n_1"]
+        );
+    }
+
+    #[test]
+    fn test_cluster_by_context() {
+        let checked =
+            "assert!(i + n <= buf.len()); from_raw_parts(buf.as_ptr().add(i), n)";
+        let effects = [
+            effect("core::slice::from_raw_parts", checked),
+            effect("core::slice::from_raw_parts", checked),
+            effect("core::slice::from_raw_parts", "from_raw_parts(p, len)"),
+            effect("libc::getuid", checked),
+        ];
+        let effects = effects.iter().map(|(e, c)| (e, c.clone())).collect::<Vec<_>>();
+        let clusters = cluster_by_context(&effects);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].effects.len(), 2);
+        assert_eq!(clusters[0].callee(), "core::slice::from_raw_parts");
+        assert_eq!(clusters[0].common_context, ["add", "as_ptr", "assert", "buf", "len"]);
+    }
+
+    #[test]
+    fn test_cluster_unreadable_sources() {
+        let callee = CanonicalPath::new("core::slice::from_raw_parts");
+        let span = syn::parse_str::<syn::Ident>("f").unwrap();
+        let effects = ["f", "g"].map(|caller| {
+            EffectInstance::new_effect(
+                Path::new("missing/src/lib.rs"),
+                CanonicalPath::new(&format!("my_crate::{}", caller)),
+                callee.clone(),
+                &span,
+                Effect::UnsafeCall(callee.clone()),
+            )
+        });
+        assert!(cluster_effects(&effects).is_empty());
+    }
+}
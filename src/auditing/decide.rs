@@ -0,0 +1,224 @@
+//! Non-interactive audit decisions, for bots and scripts recording decisions
+//! made elsewhere (e.g. in a web review UI): `audit decide --id 3 --verdict
+//! safe --note "..."`, or a batch with `audit decide --from decisions.json`,
+//! e.g.
+//! ```json
+//! [
+//!   { "id": 3, "verdict": "safe", "note": "only reads the config file" },
+//!   { "id": 5, "verdict": "caller-checked" },
+//!   { "id": 8, "verdict": "unsafe", "note": "runs user input",
+//!     "refs": ["https://github.com/org/repo/issues/12"] }
+//! ]
+//! ```
+//! Effect ids are indices into the base effects sorted by source location
+//! (as in `audit list`). Marking an effect caller-checked adds its callers
+//! to the audit, unreviewed, from a fresh scan of the crate.
+
+use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+use crate::scanner::ScanResults;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Decision {
+    pub id: usize,
+    #[serde(deserialize_with = "deserialize_verdict")]
+    pub verdict: SafetyAnnotation,
+    /// Justification for the decision
+    #[serde(default)]
+    pub note: Option<String>,
+    /// External references (e.g. issue URLs) tracking the decision
+    #[serde(default)]
+    pub refs: Vec<String>,
+}
+
+fn deserialize_verdict<'de, D>(deserializer: D) -> Result<SafetyAnnotation, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Read a batch of decisions from JSON
+pub fn read_decisions(json: &str) -> Result<Vec<Decision>> {
+    serde_json::from_str(json).context("Invalid decisions")
+}
+
+/// Record the decisions in the audit. The decisions are all checked before
+/// any is recorded, so an invalid batch leaves the audit unchanged. The scan
+/// results are needed for caller-checked decisions. Returns the number of
+/// recorded decisions.
+pub fn apply_decisions(
+    audit: &mut AuditFile,
+    decisions: &[Decision],
+    scan_res: Option<&ScanResults>,
+) -> Result<usize> {
+    let effects = audit
+        .base_effects_by_location()
+        .into_iter()
+        .map(|(e, _)| e.clone())
+        .collect::<Vec<_>>();
+
+    let mut checked = audit.clone();
+    for d in decisions {
+        let effect = effects
+            .get(d.id)
+            .ok_or_else(|| anyhow!("No effect with id {} in the audit", d.id))?;
+        let info = match &checked.audit_trees[effect] {
+            EffectTree::Branch(info, _) | EffectTree::Leaf(info, _) => info.clone(),
+        };
+        let context = || format!("Invalid decision for effect {}", d.id);
+        for r in &d.refs {
+            checked.add_reference(&info, r).with_context(context)?;
+        }
+        AuditFile::set_justification(
+            &mut checked.justifications,
+            &info,
+            d.verdict,
+            d.note.clone(),
+        )
+        .with_context(context)?;
+
+        let tree = checked.audit_trees.get_mut(effect).unwrap();
+        if d.verdict != SafetyAnnotation::CallerChecked {
+            *tree = EffectTree::Leaf(info, d.verdict);
+            continue;
+        }
+        // An effect already marked caller-checked keeps its callers
+        if let EffectTree::Branch(..) = tree {
+            continue;
+        }
+        let scan_res = scan_res.ok_or_else(|| {
+            anyhow!("Marking effect {} caller-checked needs a scan of the crate", d.id)
+        })?;
+        let callers = scan_res
+            .get_sorted_callers(&info.caller_path)?
+            .into_iter()
+            .map(|e| EffectTree::Leaf(e, SafetyAnnotation::Unreviewed))
+            .collect::<Vec<_>>();
+        *tree = if callers.is_empty() {
+            EffectTree::Leaf(info, d.verdict)
+        } else {
+            EffectTree::Branch(info, callers)
+        };
+    }
+
+    match scan_res {
+        Some(scan_res) => checked.recalc_pub_caller_checked(&scan_res.pub_fns),
+        None => {
+            // Without a scan no effect gains callers, so the public functions
+            // already reached are the only ones which can still be
+            let reached = checked.pub_caller_checked.keys().cloned().collect();
+            checked.recalc_pub_caller_checked(&reached);
+        }
+    }
+    checked.prune_justifications();
+    *audit = checked;
+    Ok(decisions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::{Effect, EffectInstance};
+    use crate::ident::CanonicalPath;
+    use std::path::{Path, PathBuf};
+
+    /// An audit of FFI calls from `test::<name>` to `libc::<name>`, one per
+    /// line, with ids in the order of the names
+    fn audit(names: &str) -> AuditFile {
+        let effects = names
+            .parse::<proc_macro2::TokenStream>()
+            .unwrap()
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new(&format!("test::{}", t)),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit.set_base_audit_trees(&effects);
+        audit
+    }
+
+    /// The annotations of the base effects, by id
+    fn annotations(audit: &AuditFile) -> Vec<SafetyAnnotation> {
+        audit
+            .base_effects_by_location()
+            .into_iter()
+            .map(|(_, t)| match t {
+                EffectTree::Leaf(_, a) => *a,
+                EffectTree::Branch(..) => panic!("Unexpected callers in the audit"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_decisions() {
+        let mut audit = audit("getuid\nfork\nkill");
+        let decisions = read_decisions(
+            r#"[
+                {"id": 0, "verdict": "safe"},
+                {"id": 1, "verdict": "unsafe", "note": "forks",
+                 "refs": ["https://x.org/issues/1"]},
+                {"id": 2, "verdict": "caller-checked"}
+            ]"#,
+        )
+        .unwrap();
+        let scan_res = ScanResults::new();
+        assert_eq!(apply_decisions(&mut audit, &decisions, Some(&scan_res)).unwrap(), 3);
+        use SafetyAnnotation::*;
+        assert_eq!(annotations(&audit), [Safe, Unsafe, CallerChecked]);
+        assert_eq!(audit.justifications.values().collect::<Vec<_>>(), ["forks"]);
+        assert_eq!(audit.references.len(), 1);
+
+        // A later decision replaces the earlier one, and its justification
+        let decisions = read_decisions(r#"[{"id": 1, "verdict": "safe"}]"#).unwrap();
+        apply_decisions(&mut audit, &decisions, None).unwrap();
+        assert_eq!(annotations(&audit), [Safe, Safe, CallerChecked]);
+        assert!(audit.justifications.is_empty());
+
+        // A batch with an unknown id, a decision missing its justification, or
+        // a caller-checked decision without a scan is rejected as a whole
+        for batch in [
+            r#"[{"id": 0, "verdict": "unsafe", "note": "x"}, {"id": 3, "verdict": "safe"}]"#,
+            r#"[{"id": 0, "verdict": "unsafe", "note": "x"}, {"id": 1, "verdict": "unsafe"}]"#,
+            r#"[{"id": 0, "verdict": "unsafe", "note": "x"}, {"id": 1, "verdict": "caller-checked"}]"#,
+        ] {
+            let decisions = read_decisions(batch).unwrap();
+            assert!(apply_decisions(&mut audit, &decisions, None).is_err());
+            assert_eq!(annotations(&audit), [Safe, Safe, CallerChecked]);
+            assert!(audit.justifications.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_read_decisions() {
+        let decisions = read_decisions(
+            r#"[
+                {"id": 3, "verdict": "safe", "note": "only reads the config file"},
+                {"id": 5, "verdict": "caller-checked"},
+                {"id": 8, "verdict": "FalsePositive", "refs": ["https://x.org/issues/1"]}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(decisions.len(), 3);
+        assert_eq!(decisions[0].verdict, SafetyAnnotation::Safe);
+        assert_eq!(decisions[0].note.as_deref(), Some("only reads the config file"));
+        assert_eq!(decisions[1].verdict, SafetyAnnotation::CallerChecked);
+        assert_eq!(decisions[2].verdict, SafetyAnnotation::FalsePositive);
+        assert_eq!(decisions[2].refs, ["https://x.org/issues/1"]);
+
+        assert!(read_decisions(r#"[{"id": 1, "verdict": "fine"}]"#).is_err());
+        assert!(read_decisions(r#"[{"id": 1, "verdict": "safe", "x": 1}]"#).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! Append-only log of the decisions made in an audit, kept next to the audit
+//! file as `<audit file>.log`, one JSON entry per line, e.g.
+//! ```json
+//! {"time":1760611200,"auditor":"alice","effect":"nix::unistd::fork -> libc::fork [FFI]","callee":"libc::fork","caller":"nix::unistd::fork","location":"src/unistd.rs:12:5","old":"Unreviewed","new":"Unsafe","note":"forks the process"}
+//! ```
+//! Entries are recorded whenever an audit file is saved, from the changes
+//! since the saved version, so decisions made interactively, with `audit
+//! decide`, in the review server, or through the library API are all logged.
+//! The auditor is `$CARGO_SCAN_AUDITOR`, or the user name. `audit log` shows
+//! the log. Entries refer to the base effect by its identity (see
+//! `effect_identity`) rather than by its id, which changes as effects are
+//! added to or removed from the audit.
+//!
+//! The log is never rewritten, so it records who decided what even after an
+//! annotation is reset or the audit file is regenerated, and the logs of two
+//! branches merge by concatenation.
+
+use crate::audit_file::{AuditFile, EffectInfo, EffectTree, SafetyAnnotation};
+use crate::auditing::analytics::timestamp;
+use crate::effect::EffectInstance;
+use crate::effect_identity::EffectIdentity;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub auditor: String,
+    /// The base effect, by its caller, callee, and kind (see
+    /// `EffectIdentity::label`); empty in the entries of older logs, which
+    /// had its id instead
+    #[serde(default)]
+    pub effect: String,
+    pub callee: String,
+    /// The caller and location the decision is for, which is up the base
+    /// effect's tree for decisions on its callers
+    pub caller: String,
+    /// `file:line:col`
+    pub location: String,
+    /// The annotation before, or None if the location wasn't in the audit
+    pub old: Option<SafetyAnnotation>,
+    /// The annotation after, or None if the location was removed from the
+    /// audit
+    pub new: Option<SafetyAnnotation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let old = self.old.map_or("(new)".to_string(), |a| a.to_string());
+        let new = self.new.map_or("(removed)".to_string(), |a| a.to_string());
+        write!(
+            f,
+            "{} {} ({}) {} at {}: {} -> {}",
+            timestamp(self.time),
+            self.auditor,
+            self.effect,
+            self.caller,
+            self.location,
+            old,
+            new
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// The log of the audit file at the path
+pub fn log_path(audit_path: &Path) -> PathBuf {
+    let mut path = audit_path.as_os_str().to_owned();
+    path.push(".log");
+    PathBuf::from(path)
+}
+
+/// Who is making the decisions: `$CARGO_SCAN_AUDITOR`, or the user name
+pub fn auditor() -> String {
+    ["CARGO_SCAN_AUDITOR", "USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The base effect in log entries
+pub fn effect_label(effect: &EffectInstance) -> String {
+    EffectIdentity::Path.label(effect, None).unwrap_or_default()
+}
+
+/// The decided locations of an audit, keyed by base effect and location.
+/// Callers of a caller-checked location are decided on separately.
+fn decisions(
+    audit_file: &AuditFile,
+) -> HashMap<(&EffectInstance, &EffectInfo), SafetyAnnotation> {
+    fn tree_walk<'a>(
+        base: &'a EffectInstance,
+        tree: &'a EffectTree,
+        res: &mut HashMap<(&'a EffectInstance, &'a EffectInfo), SafetyAnnotation>,
+    ) {
+        match tree {
+            EffectTree::Leaf(info, a) => {
+                res.insert((base, info), *a);
+            }
+            EffectTree::Branch(info, children) => {
+                res.insert((base, info), SafetyAnnotation::CallerChecked);
+                for child in children {
+                    tree_walk(base, child, res);
+                }
+            }
+        }
+    }
+
+    let mut res = HashMap::new();
+    for (base, tree) in &audit_file.audit_trees {
+        tree_walk(base, tree, &mut res);
+    }
+    res
+}
+
+/// The entries for the changes from the previous version of an audit (None
+/// for a new audit): changed annotations and justifications. Locations added
+/// unreviewed, and removed while unreviewed, aren't decisions.
+pub fn changes(
+    old: Option<&AuditFile>,
+    new: &AuditFile,
+    auditor: &str,
+    time: i64,
+) -> Vec<LogEntry> {
+    let old_decisions = old.map(decisions).unwrap_or_default();
+    let new_decisions = decisions(new);
+    let justification = |audit: Option<&AuditFile>, info: &EffectInfo| {
+        audit.and_then(|a| a.justifications.get(info).cloned())
+    };
+
+    let mut keys = old_decisions.keys().chain(new_decisions.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    let mut entries = Vec::new();
+    for key in keys {
+        let (base, info) = key;
+        let old_a = old_decisions.get(key).copied();
+        let new_a = new_decisions.get(key).copied();
+        let old_note = justification(old, info);
+        let note = justification(Some(new), info);
+        let unreviewed = |a: Option<SafetyAnnotation>| {
+            matches!(a, None | Some(SafetyAnnotation::Unreviewed))
+        };
+        if (old_a == new_a && old_note == note)
+            || (unreviewed(old_a) && unreviewed(new_a))
+        {
+            continue;
+        }
+        let loc = &info.callee_loc;
+        entries.push(LogEntry {
+            time,
+            auditor: auditor.to_string(),
+            effect: effect_label(base),
+            callee: base.callee_path().to_string(),
+            caller: info.caller_path.to_string(),
+            location: format!(
+                "{}:{}:{}",
+                loc.filepath_string(),
+                loc.start_line(),
+                loc.start_col() + 1
+            ),
+            old: old_a,
+            new: new_a,
+            note,
+        });
+    }
+    entries
+}
+
+/// Append the changes to the audit file at the path to its log
+pub fn record(audit_path: &Path, old: Option<&AuditFile>, new: &AuditFile) -> Result<()> {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let entries = changes(old, new, &auditor(), time);
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let path = log_path(audit_path);
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open audit log {:?}", path))?;
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(&entry)?);
+        lines.push('\n');
+    }
+    f.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// The entries of the log of the audit file at the path, oldest first, or
+/// none if nothing was logged
+pub fn read_log(audit_path: &Path) -> Result<Vec<LogEntry>> {
+    let path = log_path(audit_path);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .context(format!("Failed to read audit log {:?}", path))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).context(format!(
+                "Invalid entry at {:?} line {}",
+                path,
+                i + 1
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+
+    #[test]
+    fn test_decision_log() {
+        let tokens = "fork getuid".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut old = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        old.set_base_audit_trees(effects.iter());
+        // A new audit of unreviewed effects has no decisions
+        assert!(changes(None, &old, "alice", 0).is_empty());
+
+        let mut new = old.clone();
+        new.annotate(0, SafetyAnnotation::Unsafe, Some("forks".to_string())).unwrap();
+        let entries = changes(Some(&old), &new, "alice", 86400);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.effect, "test::f -> libc::fork [FFI]");
+        assert_eq!(entry.callee, "libc::fork");
+        assert_eq!(entry.old, Some(SafetyAnnotation::Unreviewed));
+        assert_eq!(entry.new, Some(SafetyAnnotation::Unsafe));
+        assert_eq!(
+            entry.to_string(),
+            format!(
+                "1970-01-02T00:00:00Z alice (test::f -> libc::fork [FFI]) \
+                test::f at {}: Unreviewed -> Unsafe (forks)",
+                entry.location
+            )
+        );
+        assert!(changes(Some(&new), &new, "alice", 0).is_empty());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.audit");
+        record(&path, Some(&old), &new).unwrap();
+        record(&path, Some(&new), &old).unwrap();
+        let log = read_log(&path).unwrap();
+        std::fs::remove_file(log_path(&path)).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].new, Some(SafetyAnnotation::Unsafe));
+        assert_eq!(log[1].new, Some(SafetyAnnotation::Unreviewed));
+        assert_eq!(log[1].note, None);
+        assert!(read_log(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! Ordering of the effects to audit by fan-in, for the chain audit.
+//!
+//! The pending decisions of an effect are at the callers in its tree which
+//! are still unreviewed. Marking one of them caller-checked makes all of the
+//! caller's callers pending, and marking it safe or unsafe settles them, so
+//! the decisions with the most (transitive) callers in the crate's call graph
+//! unblock the largest part of the tree. In a chain, the callers of a public
+//! function also include the functions of the crates depending on it, whose
+//! decisions become pending once it is marked caller-checked. Effects are audited in decreasing
+//! order of the largest fan-in of their pending callers, and by location
+//! among equals.
+
+use crate::audit_file::{EffectTree, SafetyAnnotation};
+use crate::ident::CanonicalPath;
+use crate::scanner::ScanResults;
+
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{EdgeRef, Visitable};
+use petgraph::Direction;
+use std::collections::HashMap;
+
+#[derive(Default)]
+struct CallGraph<'a> {
+    graph: DiGraph<&'a CanonicalPath, ()>,
+    nodes: HashMap<&'a CanonicalPath, NodeIndex>,
+}
+
+impl<'a> CallGraph<'a> {
+    fn node(&mut self, f: &'a CanonicalPath) -> NodeIndex {
+        *self.nodes.entry(f).or_insert_with(|| self.graph.add_node(f))
+    }
+
+    fn add_call(&mut self, caller: &'a CanonicalPath, callee: &'a CanonicalPath) {
+        let (caller, callee) = (self.node(caller), self.node(callee));
+        self.graph.add_edge(caller, callee, ());
+    }
+}
+
+/// The number of functions which (transitively) call each function of the
+/// call graph: in the crate itself, and in the `downstream` crates of the
+/// chain depending on it, whose calls reach it through its public functions
+pub fn fan_in<'a>(
+    scan_res: &'a ScanResults,
+    downstream: &'a [ScanResults],
+) -> HashMap<&'a CanonicalPath, usize> {
+    // The call graphs of the crates, joined by their calls to each other
+    let mut graph = CallGraph::default();
+    let crates = std::iter::once(scan_res).chain(downstream);
+    for res in crates.clone() {
+        for f in res.node_idxs.keys() {
+            graph.node(f);
+        }
+        for e in res.call_graph.edge_references() {
+            let caller = &res.call_graph[e.source()];
+            graph.add_call(caller, &res.call_graph[e.target()]);
+        }
+    }
+    for res in crates {
+        for (caller, callee) in &res.external_calls {
+            // Only the calls to functions of the crates count
+            if graph.nodes.contains_key(callee) {
+                graph.add_call(caller, callee);
+            }
+        }
+    }
+    let graph = graph.graph;
+
+    // The functions in a cycle call each other, so the callers of each
+    // strongly connected component are collected once, from the callers
+    // down: tarjan_scc lists the components callees first
+    let sccs = tarjan_scc(&graph);
+    let mut scc_of = vec![0; graph.node_count()];
+    for (i, scc) in sccs.iter().enumerate() {
+        for n in scc {
+            scc_of[n.index()] = i;
+        }
+    }
+    // The calls left from each component to other components, after which
+    // its callers aren't needed anymore
+    let mut pending = vec![0; sccs.len()];
+    for e in graph.edge_references() {
+        let (caller, callee) = (scc_of[e.source().index()], scc_of[e.target().index()]);
+        if caller != callee {
+            pending[caller] += 1;
+        }
+    }
+    let mut callers = vec![None; sccs.len()];
+    let mut fan_in = HashMap::new();
+    for (i, scc) in sccs.iter().enumerate().rev() {
+        // The component's functions, and the functions calling them
+        let mut reach = graph.visit_map();
+        for &n in scc {
+            reach.insert(n.index());
+            for caller in graph.neighbors_directed(n, Direction::Incoming) {
+                let c = scc_of[caller.index()];
+                if c == i {
+                    continue;
+                }
+                reach.union_with(callers[c].as_ref().expect("callers come first"));
+                pending[c] -= 1;
+                if pending[c] == 0 {
+                    callers[c] = None;
+                }
+            }
+        }
+        let count = reach.count_ones(..) - 1;
+        fan_in.extend(scc.iter().map(|&n| (graph[n], count)));
+        if pending[i] > 0 {
+            callers[i] = Some(reach);
+        }
+    }
+    fan_in
+}
+
+/// The callers of the tree whose decisions are pending
+pub fn pending_callers(tree: &EffectTree) -> Vec<&CanonicalPath> {
+    match tree {
+        EffectTree::Leaf(info, SafetyAnnotation::Unreviewed) => vec![&info.caller_path],
+        EffectTree::Leaf(_, _) => vec![],
+        EffectTree::Branch(_, children) => {
+            children.iter().flat_map(pending_callers).collect()
+        }
+    }
+}
+
+/// The priority of auditing an effect tree: the largest fan-in of its pending
+/// callers, counting the caller itself, or 0 if nothing is pending
+pub fn priority(tree: &EffectTree, fan_in: &HashMap<&CanonicalPath, usize>) -> usize {
+    pending_callers(tree)
+        .into_iter()
+        .map(|f| 1 + fan_in.get(f).copied().unwrap_or(0))
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit_file::EffectInfo;
+    use crate::effect::SrcLoc;
+
+    #[test]
+    fn test_fan_in() {
+        // main -> run -> {read, write}, and test_write -> write
+        let mut scan_res = ScanResults::new();
+        let mut node = |name: &str| {
+            let f = CanonicalPath::new(name);
+            let n = scan_res.call_graph.add_node(f.clone());
+            scan_res.node_idxs.insert(f, n);
+            n
+        };
+        let [main, run, read, write, test_write] =
+            ["c::main", "c::run", "c::read", "c::write", "c::test_write"].map(&mut node);
+        for (caller, callee) in
+            [(main, run), (run, read), (run, write), (test_write, write)]
+        {
+            scan_res.call_graph.add_edge(caller, callee, SrcLoc::default());
+        }
+        let fan_in = fan_in(&scan_res, &[]);
+        assert_eq!(fan_in[&CanonicalPath::new("c::write")], 3);
+        assert_eq!(fan_in[&CanonicalPath::new("c::read")], 2);
+        assert_eq!(fan_in[&CanonicalPath::new("c::main")], 0);
+
+        let leaf = |name: &str, a| {
+            EffectTree::Leaf(
+                EffectInfo::new(CanonicalPath::new(name), SrcLoc::default()),
+                a,
+            )
+        };
+        let tree = EffectTree::Branch(
+            EffectInfo::new(CanonicalPath::new("c::f"), SrcLoc::default()),
+            vec![
+                leaf("c::read", SafetyAnnotation::Unreviewed),
+                leaf("c::write", SafetyAnnotation::Safe),
+            ],
+        );
+        assert_eq!(pending_callers(&tree), [&CanonicalPath::new("c::read")]);
+        assert_eq!(priority(&tree, &fan_in), 3);
+        assert_eq!(priority(&leaf("c::write", SafetyAnnotation::Safe), &fan_in), 0);
+
+        // A downstream crate calls c::run, and c::read through d::retry; c::read
+        // also calls itself
+        let mut downstream = ScanResults::new();
+        let [d_main, d_retry] = ["d::main", "d::retry"].map(|name| {
+            let f = CanonicalPath::new(name);
+            let n = downstream.call_graph.add_node(f.clone());
+            downstream.node_idxs.insert(f, n);
+            n
+        });
+        downstream.call_graph.add_edge(d_main, d_retry, SrcLoc::default());
+        for (caller, callee) in
+            [("d::main", "c::run"), ("d::retry", "c::read"), ("d::main", "std::println")]
+        {
+            downstream
+                .external_calls
+                .insert((CanonicalPath::new(caller), CanonicalPath::new(callee)));
+        }
+        scan_res.call_graph.add_edge(read, read, SrcLoc::default());
+        let downstream = [downstream];
+        let fan_in = super::fan_in(&scan_res, &downstream);
+        assert_eq!(fan_in[&CanonicalPath::new("c::write")], 4);
+        assert_eq!(fan_in[&CanonicalPath::new("c::read")], 4);
+        assert_eq!(fan_in[&CanonicalPath::new("c::run")], 2);
+        assert_eq!(fan_in[&CanonicalPath::new("d::main")], 0);
+        assert!(!fan_in.contains_key(&CanonicalPath::new("std::println")));
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::Path;
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
@@ -8,10 +8,15 @@ use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 
+use super::keys::KeyBindings;
+use super::messages::{Locale, Msg};
 use crate::ident::CanonicalPath;
 use crate::{
     audit_file::EffectInfo,
     effect::{Effect, EffectInstance, SrcLoc},
+    project::ProjectConfig,
+    remediation,
+    util::fs,
 };
 
 #[derive(Parser, Debug, Clone)]
@@ -27,6 +32,34 @@ pub struct Config {
     //       can now that chains are our primary auditing mechanism?)
     #[clap(default_value_t = false)]
     pub allow_effect_origin: bool,
+
+    #[clap(long, default_value_t = false)]
+    /// Print effects as plain text descriptions instead of annotated source
+    /// snippets (for screen readers)
+    pub plain: bool,
+
+    #[clap(long, default_value_t = false)]
+    /// Group similar effects (same kind and callee, similar surrounding code)
+    /// and offer to mark each group with one decision before the rest
+    pub cluster: bool,
+
+    #[clap(long, value_enum)]
+    /// Language of the audit prompts (defaults to $CARGO_SCAN_LOCALE or $LANG)
+    locale: Option<Locale>,
+
+    #[clap(skip)]
+    /// Accept commands changing the queue of effects to audit at the
+    /// annotation prompt (see `auditing::session`)
+    pub session_commands: bool,
+
+    #[clap(skip)]
+    /// Keys of the prompt options, from the project config
+    pub keys: KeyBindings,
+
+    #[clap(skip)]
+    /// Audit the effects whose pending callers have the most callers first,
+    /// rather than by location (see `auditing::fan_in`)
+    pub fan_in_order: bool,
 }
 
 impl Default for Config {
@@ -35,6 +68,12 @@ impl Default for Config {
             lines_before_effect: 4,
             lines_after_effect: 1,
             allow_effect_origin: false,
+            plain: false,
+            cluster: false,
+            locale: None,
+            session_commands: false,
+            keys: KeyBindings::default(),
+            fan_in_order: false,
         }
     }
 }
@@ -45,33 +84,147 @@ impl Config {
             lines_before_effect: lines_before,
             lines_after_effect: lines_after,
             allow_effect_origin,
+            plain: false,
+            cluster: false,
+            locale: None,
+            session_commands: false,
+            keys: KeyBindings::default(),
+            fan_in_order: false,
         }
     }
 
+    pub fn locale(&self) -> Locale {
+        self.locale.unwrap_or_else(Locale::from_env)
+    }
+
+    /// Read the settings of the root project which apply to its audits: the
+    /// keys of the prompts
+    pub fn load_project(&mut self, root: &Path) -> Result<()> {
+        if let Some(project) = ProjectConfig::read(root)? {
+            self.keys = project.keys;
+        }
+        Ok(())
+    }
+
     pub fn expand_context(&mut self) {
         self.lines_before_effect += 5;
         self.lines_after_effect += 5;
     }
 }
 
+fn effect_label(
+    effect_origin: &EffectInstance,
+    effect: &EffectInfo,
+    locale: Locale,
+) -> String {
+    if effect_origin.caller() == &effect.caller_path {
+        // We are in the original function, so print all the effects in the
+        // EffectInstance
+        let label = match effect_origin.eff_type() {
+            Effect::SinkCall(sink) => Msg::SinkCall.fill(locale, &[sink]),
+            Effect::FFICall(call) => Msg::FfiCall.fill(locale, &[call]),
+            Effect::UnsafeCall(call) => Msg::UnsafeCall.fill(locale, &[call]),
+            Effect::RawPointer(ptr) => Msg::RawPointer.fill(locale, &[ptr]),
+            Effect::UnionField(union) => Msg::UnionField.fill(locale, &[union]),
+            Effect::StaticMut(var) => Msg::StaticMut.fill(locale, &[var]),
+            Effect::StaticExt(var) => Msg::StaticExt.fill(locale, &[var]),
+            Effect::UnsafeTraitImpl(tr) => Msg::UnsafeTraitImpl.fill(locale, &[tr]),
+            Effect::FnPtrCreation => Msg::FnPtrCreation.fill(locale, &[]),
+            Effect::ClosureCreation => Msg::ClosureCreation.fill(locale, &[]),
+            Effect::RawPtrCast => Msg::RawPtrCast.fill(locale, &[]),
+            Effect::PtrArithmetic(method) => Msg::PtrArithmetic.fill(locale, &[method]),
+            Effect::Custom(c) => {
+                Msg::CustomCall.fill(locale, &[&c.kind, effect_origin.callee()])
+            }
+            Effect::Atomic(call, ordering) => Msg::Atomic.fill(locale, &[ordering, call]),
+            Effect::AliasEscape(p) => Msg::AliasEscape.fill(locale, &[p]),
+            Effect::UninitMemory(call) => Msg::UninitMemory.fill(locale, &[call]),
+            Effect::IntPtrCast(val) => Msg::IntPtrCast.fill(locale, &[val]),
+            Effect::ThreadSpawn(call) => Msg::ThreadSpawn.fill(locale, &[call]),
+            Effect::OsHook(call, hook) => Msg::OsHook.fill(locale, &[hook, call]),
+            Effect::MemoryMap(call, None) => Msg::MemoryMap.fill(locale, &[call]),
+            Effect::MemoryMap(call, Some(prot)) => {
+                Msg::MemoryMapProt.fill(locale, &[prot, call])
+            }
+            Effect::Malware(call, pattern) => Msg::Malware.fill(locale, &[pattern, call]),
+        };
+        let label = match effect_origin.receiver_ty() {
+            Some(ty) => Msg::OnReceiver.fill(locale, &[&label, ty]),
+            None => label,
+        };
+        let Some(closure) = effect_origin.closure() else {
+            return label;
+        };
+        let fn_name = effect_origin.caller().fn_name();
+        match &closure.adapter {
+            Some(adapter) => {
+                Msg::InAdapterClosure.fill(locale, &[&label, adapter, &fn_name])
+            }
+            None => {
+                Msg::InClosure.fill(locale, &[&label, &(closure.index + 1), &fn_name])
+            }
+        }
+    } else {
+        Msg::CallerCheckedLabel.fill(locale, &[])
+    }
+}
+
+/// The remediation hint for the effect, only shown at the effect itself and
+/// not at its callers
+fn remediation_note(
+    effect_origin: &EffectInstance,
+    effect: &EffectInfo,
+    locale: Locale,
+) -> Option<String> {
+    if effect_origin.caller() != &effect.caller_path {
+        return None;
+    }
+    let hint = remediation::remediation(effect_origin)?;
+    Some(Msg::Remediation.fill(locale, &[&hint]))
+}
+
+/// Notes shown below the effect: the macro it was expanded from, if it is in
+/// a call to one of the crate's macros, and the remediation hint
+fn effect_notes(
+    effect_origin: &EffectInstance,
+    effect: &EffectInfo,
+    locale: Locale,
+) -> Vec<String> {
+    let macro_note = effect_origin
+        .macro_origin()
+        .filter(|_| effect_origin.caller() == &effect.caller_path)
+        .map(|m| Msg::MacroExpansion.fill(locale, &[&m.name, &m.def_loc]));
+    macro_note
+        .into_iter()
+        .chain(remediation_note(effect_origin, effect, locale))
+        .collect()
+}
+
 pub fn print_effect_src(
     effect_origin: &EffectInstance,
     effect: &EffectInfo,
     fn_locs: &HashMap<CanonicalPath, SrcLoc>,
     config: &Config,
 ) -> Result<()> {
+    if config.plain {
+        return print_effect_src_plain(effect_origin, effect, fn_locs, config);
+    }
+
     // NOTE: The codespan lines are 0-indexed, but SrcLocs are 1-indexed
     let effect_loc = &effect.callee_loc.sub1();
     let mut full_path = effect_loc.dir().clone();
     full_path.push(effect_loc.file());
 
-    let src_contents = std::fs::read_to_string(full_path)?;
+    // Read the source as the scanner did, so the lines agree
+    let src_contents = fs::read_source(&full_path)?.text;
 
-    // Get the byte ranges for each line of the src file
+    // Get the byte ranges for each line of the src file (the last line may
+    // not end with a newline)
     let src_lines = src_contents.split('\n');
     let mut src_linenum_ranges = HashMap::new();
     src_lines.fold((0, 0), |(lineno, byte_count), line| {
-        src_linenum_ranges.insert(lineno, (byte_count, byte_count + line.len() + 1));
+        let line_end = std::cmp::min(byte_count + line.len() + 1, src_contents.len());
+        src_linenum_ranges.insert(lineno, (byte_count, line_end));
         (lineno + 1, byte_count + line.len() + 1)
     });
 
@@ -92,9 +245,7 @@ pub fn print_effect_src(
 
     // TODO: cache files?
     let mut files = SimpleFiles::new();
-    let mut file_path = PathBuf::from(effect_loc.dir());
-    file_path.push(effect_loc.file());
-    let file_id = files.add(format!("{}", file_path.to_string_lossy()), src_contents);
+    let file_id = files.add(effect_loc.display_path(), src_contents);
 
     // If the labels don't include the function signature, include it as
     // another label
@@ -134,37 +285,17 @@ pub fn print_effect_src(
         }
     };
 
-    let label_msg = if effect_origin.caller() == &effect.caller_path {
-        // We are in the original function, so print all the effects in the
-        // EffectInstance
-        match effect_origin.eff_type() {
-            Effect::SinkCall(sink) => format!("sink call: {}", sink),
-            Effect::FFICall(call) => format!("ffi call: {}", call),
-            Effect::UnsafeCall(call) => format!("unsafe call: {}", call),
-            Effect::RawPointer(ptr) => format!("raw pointer access: {}", ptr),
-            Effect::UnionField(union) => format!("union access: {}", union),
-            Effect::StaticMut(var) => format!("static mut access: {}", var),
-            Effect::StaticExt(var) => format!("static ffi variable access: {}", var),
-            Effect::FnPtrCreation => {
-                "function pointer creation (verify the function is always safe to call)"
-                    .to_string()
-            }
-            Effect::ClosureCreation => {
-                "closure creation (verify the closure is always safe to call)".to_string()
-            }
-            Effect::RawPtrCast => {
-                "Cast to a raw pointer (can't cause unsafe behavior on its own)"
-                    .to_string()
-            }
-        }
-    } else {
-        "call safety marked as caller-checked".to_string()
-    };
+    let locale = config.locale();
+    let label_msg = effect_label(effect_origin, effect, locale);
     let l = labels.remove(0);
     labels.insert(0, l.with_message(label_msg));
 
     // construct the codespan diagnostic
-    let diag = Diagnostic::help().with_code("Audit location").with_labels(labels);
+    let notes = effect_notes(effect_origin, effect, locale);
+    let diag = Diagnostic::help()
+        .with_code(Msg::AuditLocation.text(locale))
+        .with_labels(labels)
+        .with_notes(notes);
 
     let writer = StandardStream::stderr(ColorChoice::Always);
     let codespan_config = codespan_reporting::term::Config {
@@ -179,6 +310,113 @@ pub fn print_effect_src(
     Ok(())
 }
 
+/// A source line around an effect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcerptLine {
+    /// 1-indexed, as in SrcLocs
+    pub lineno: usize,
+    pub text: String,
+    /// Whether the line is part of the effect, or only context
+    pub in_effect: bool,
+}
+
+/// The lines of the effect at the location, with the given number of
+/// context lines before and after
+fn excerpt(
+    src_lines: &[&str],
+    effect_loc: &SrcLoc,
+    lines_before: usize,
+    lines_after: usize,
+) -> Vec<ExcerptLine> {
+    // SrcLoc lines are 1-indexed
+    let start_line = effect_loc.start_line();
+    let end_line = std::cmp::min(effect_loc.end_line(), src_lines.len());
+    let context_start = std::cmp::max(start_line.saturating_sub(lines_before), 1);
+    let context_end = std::cmp::min(end_line + lines_after, src_lines.len());
+    (context_start..=context_end)
+        .map(|lineno| ExcerptLine {
+            lineno,
+            text: src_lines[lineno - 1].trim_end().to_string(),
+            in_effect: (start_line..=end_line).contains(&lineno),
+        })
+        .collect()
+}
+
+/// Read the lines of the effect at the location from its source file, with
+/// the given number of context lines before and after
+pub fn effect_excerpt(
+    effect_loc: &SrcLoc,
+    lines_before: usize,
+    lines_after: usize,
+) -> Result<Vec<ExcerptLine>> {
+    let mut full_path = effect_loc.dir().clone();
+    full_path.push(effect_loc.file());
+    let src_contents = fs::read_source(&full_path)?.text;
+    let src_lines = src_contents.lines().collect::<Vec<_>>();
+    Ok(excerpt(&src_lines, effect_loc, lines_before, lines_after))
+}
+
+/// Print the effect as a textual description followed by the numbered
+/// context lines, without the box-drawing and underlines of the codespan
+/// output, so it reads well with a screen reader.
+fn print_effect_src_plain(
+    effect_origin: &EffectInstance,
+    effect: &EffectInfo,
+    fn_locs: &HashMap<CanonicalPath, SrcLoc>,
+    config: &Config,
+) -> Result<()> {
+    let locale = config.locale();
+    let effect_loc = &effect.callee_loc;
+    let mut full_path = effect_loc.dir().clone();
+    full_path.push(effect_loc.file());
+
+    let src_contents = fs::read_source(&full_path)?.text;
+    let src_lines = src_contents.lines().collect::<Vec<_>>();
+    let lines = excerpt(
+        &src_lines,
+        effect_loc,
+        config.lines_before_effect as usize,
+        config.lines_after_effect as usize,
+    );
+    let start_line = effect_loc.start_line();
+    let context_start = lines.first().map_or(start_line, |l| l.lineno);
+
+    println!(
+        "{}",
+        Msg::PlainEffectHeader.fill(
+            locale,
+            &[
+                &effect_loc.display_path(),
+                &start_line,
+                &effect_label(effect_origin, effect, locale),
+                &lines.len(),
+            ]
+        )
+    );
+
+    // Mention the enclosing function if its signature is before the context
+    if let Some(loc) = fn_locs.get(&effect.caller_path) {
+        if loc.start_line() < context_start {
+            let sig = src_lines.get(loc.start_line() - 1).map_or("", |l| l.trim());
+            println!(
+                "{}",
+                Msg::PlainFnSignature.fill(locale, &[&loc.start_line(), &sig])
+            );
+        }
+    }
+
+    for line in lines {
+        let msg =
+            if line.in_effect { Msg::PlainEffectLine } else { Msg::PlainContextLine };
+        println!("{}", msg.fill(locale, &[&line.lineno, &line.text]));
+    }
+    for note in effect_notes(effect_origin, effect, locale) {
+        println!("{}", note);
+    }
+
+    Ok(())
+}
+
 struct CallStackInfo {
     fn_string: Option<String>,
     filename: String,
@@ -195,7 +433,7 @@ fn fn_decl_info(fn_loc: &SrcLoc) -> Result<CallStackInfo> {
     let mut full_path = fn_loc.dir().clone();
     full_path.push(fn_loc.file());
 
-    let src_contents = std::fs::read_to_string(full_path)?;
+    let src_contents = fs::read_source(&full_path)?.text;
 
     // TODO: Print the full definition if it spans multiple lines
     let mut src_lines = src_contents.splitn(fn_loc.start_line() + 1, '\n');
@@ -206,23 +444,18 @@ fn fn_decl_info(fn_loc: &SrcLoc) -> Result<CallStackInfo> {
     // TODO: Capture just the function name
     let res = CallStackInfo::new(
         Some(src_fn_loc.split('{').next().unwrap().trim().to_string()),
-        format!("{}", fn_loc.dir().to_string_lossy()),
+        fn_loc.display_path(),
         fn_loc.start_line(),
     );
     Ok(res)
 }
 
 fn missing_fn_decl_info(effect_loc: &SrcLoc) -> CallStackInfo {
-    let mut path_list = effect_loc.dir().clone();
-    path_list.push(effect_loc.file());
-    let full_path = path_list.join("/");
-    let full_path_str = full_path.to_string_lossy().to_string();
-
-    CallStackInfo::new(None, full_path_str, effect_loc.start_line())
+    CallStackInfo::new(None, effect_loc.display_path(), effect_loc.start_line())
 }
 
-fn print_call_stack_infos(stack: Vec<CallStackInfo>) {
-    let missing_fn_str = "Missing fn decl";
+fn print_call_stack_infos(stack: Vec<CallStackInfo>, locale: Locale) {
+    let missing_fn_str = Msg::MissingFnDecl.text(locale);
     // TODO: Colorize
     for CallStackInfo { fn_string, filename, lineno } in stack {
         println!("{}:{}", filename, lineno + 1);
@@ -237,11 +470,12 @@ fn print_call_stack(
     curr_effect: &EffectInfo,
     effect_history: &[&EffectInfo],
     fn_locs: &HashMap<CanonicalPath, SrcLoc>,
+    locale: Locale,
 ) -> Result<()> {
     if !effect_history.is_empty() {
         let mut call_stack_infos = vec![];
         // TODO: Colorize
-        println!("EffectInstance call stack:");
+        println!("{}", Msg::CallStackHeader.text(locale));
         let call_info = match fn_locs.get(&curr_effect.caller_path) {
             Some(fn_loc) => fn_decl_info(fn_loc)?,
             None => missing_fn_decl_info(&curr_effect.callee_loc),
@@ -256,7 +490,7 @@ fn print_call_stack(
             call_stack_infos.push(call_info);
         }
 
-        print_call_stack_infos(call_stack_infos);
+        print_call_stack_infos(call_stack_infos, locale);
     }
 
     Ok(())
@@ -270,8 +504,10 @@ pub fn print_effect_info(
     config: &Config,
 ) -> Result<()> {
     println!();
-    println!("=================================================");
-    print_call_stack(curr_effect, effect_history, fn_locs)?;
+    if !config.plain {
+        println!("=================================================");
+    }
+    print_call_stack(curr_effect, effect_history, fn_locs, config.locale())?;
     println!();
     print_effect_src(orig_effect, curr_effect, fn_locs, config)?;
     Ok(())
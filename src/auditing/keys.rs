@@ -0,0 +1,212 @@
+//! Key bindings of the interactive audit prompts, configured in the `[keys]`
+//! table of the project config (see `project`), e.g.
+//! ```toml
+//! [keys]
+//! preset = "vim"
+//! safe = "y"
+//! unsafe = "n"
+//! ```
+//! Keys start from a preset, `default` (s, u, c, d, f, o, e, l, x, i, ?) or
+//! `vim` (which moves on with `j`, expands the context with `zo`, and quits
+//! with `q`), and each action can be bound to another key. Keys can't start
+//! with `:`, which is for session commands (see `session`). The help key
+//! (`?` by default) lists the bindings at any prompt.
+
+use crate::auditing::messages::{Locale, Msg};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditAction {
+    Safe,
+    Unsafe,
+    CallerChecked,
+    Defer,
+    FalsePositive,
+    /// Audit the effect in the crate it comes from
+    Origin,
+    Expand,
+    /// Leave the effect unreviewed
+    Later,
+    Exit,
+    /// Audit the effects of a cluster one at a time
+    Individually,
+    Help,
+}
+
+impl AuditAction {
+    pub const ALL: &'static [AuditAction] = &[
+        AuditAction::Safe,
+        AuditAction::Unsafe,
+        AuditAction::CallerChecked,
+        AuditAction::Defer,
+        AuditAction::FalsePositive,
+        AuditAction::Origin,
+        AuditAction::Expand,
+        AuditAction::Later,
+        AuditAction::Exit,
+        AuditAction::Individually,
+        AuditAction::Help,
+    ];
+
+    pub fn label(self) -> Msg {
+        match self {
+            AuditAction::Safe => Msg::KeySafe,
+            AuditAction::Unsafe => Msg::KeyUnsafe,
+            AuditAction::CallerChecked => Msg::KeyCallerChecked,
+            AuditAction::Defer => Msg::KeyDefer,
+            AuditAction::FalsePositive => Msg::KeyFalsePositive,
+            AuditAction::Origin => Msg::KeyOrigin,
+            AuditAction::Expand => Msg::KeyExpand,
+            AuditAction::Later => Msg::KeyLater,
+            AuditAction::Exit => Msg::KeyExit,
+            AuditAction::Individually => Msg::KeyIndividually,
+            AuditAction::Help => Msg::KeyHelp,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyPreset {
+    #[default]
+    Default,
+    Vim,
+}
+
+impl KeyPreset {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn key(self, action: AuditAction) -> &'static str {
+        match (self, action) {
+            (KeyPreset::Vim, AuditAction::Later) => "j",
+            (KeyPreset::Vim, AuditAction::Expand) => "zo",
+            (KeyPreset::Vim, AuditAction::Exit) => "q",
+            (_, AuditAction::Safe) => "s",
+            (_, AuditAction::Unsafe) => "u",
+            (_, AuditAction::CallerChecked) => "c",
+            (_, AuditAction::Defer) => "d",
+            (_, AuditAction::FalsePositive) => "f",
+            (_, AuditAction::Origin) => "o",
+            (_, AuditAction::Expand) => "e",
+            (_, AuditAction::Later) => "l",
+            (_, AuditAction::Exit) => "x",
+            (_, AuditAction::Individually) => "i",
+            (_, AuditAction::Help) => "?",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct KeyBindings {
+    #[serde(skip_serializing_if = "KeyPreset::is_default")]
+    pub preset: KeyPreset,
+    /// Keys overriding the preset's
+    #[serde(flatten)]
+    pub keys: BTreeMap<AuditAction, String>,
+}
+
+impl KeyBindings {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn key(&self, action: AuditAction) -> &str {
+        self.keys.get(&action).map_or(self.preset.key(action), String::as_str)
+    }
+
+    /// The action of the input among the actions of a prompt
+    pub fn action(&self, input: &str, actions: &[AuditAction]) -> Option<AuditAction> {
+        actions.iter().copied().find(|a| self.key(*a) == input.trim())
+    }
+
+    /// Check that the keys are nonempty, can't be mistaken for commands, and
+    /// are bound to one action each
+    pub fn validate(&self) -> Result<()> {
+        let mut bound = HashMap::new();
+        for action in AuditAction::ALL {
+            let key = self.key(*action);
+            if key.is_empty() || key.starts_with(':') || key.contains(char::is_whitespace)
+            {
+                return Err(anyhow!("Invalid key for {:?}: {:?}", action, key));
+            }
+            if let Some(other) = bound.insert(key, action) {
+                return Err(anyhow!(
+                    "Key {:?} is bound to both {:?} and {:?}",
+                    key,
+                    other,
+                    action
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The options of a prompt, e.g. `s: safe, u: unsafe, ...`, a few to a
+    /// line
+    pub fn options(&self, actions: &[AuditAction], locale: Locale) -> String {
+        let mut res = String::new();
+        for line in actions.chunks(5) {
+            let options = line
+                .iter()
+                .map(|a| format!("{}: {}", self.key(*a), a.label().text(locale)))
+                .collect::<Vec<_>>();
+            res.push_str(&format!("  {}\n", options.join(", ")));
+        }
+        res
+    }
+
+    /// The bindings of the actions, one to a line
+    pub fn help(&self, actions: &[AuditAction], locale: Locale) -> String {
+        let width = actions.iter().map(|a| self.key(*a).len()).max().unwrap_or(0);
+        let mut res = Msg::KeysHeader.text(locale).to_string();
+        for a in actions {
+            res.push_str(&format!(
+                "\n  {:width$}  {}",
+                self.key(*a),
+                a.label().text(locale),
+                width = width
+            ));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_bindings() {
+        let keys: KeyBindings = toml::from_str("preset = \"vim\"\nsafe = \"y\"").unwrap();
+        assert!(keys.validate().is_ok());
+        let actions = [AuditAction::Safe, AuditAction::Later, AuditAction::Exit];
+        assert_eq!(keys.action("y", &actions), Some(AuditAction::Safe));
+        assert_eq!(keys.action("j", &actions), Some(AuditAction::Later));
+        assert_eq!(keys.action("s", &actions), None);
+        assert_eq!(keys.action("zo", &actions), None);
+        assert_eq!(
+            keys.options(&actions, Locale::En),
+            "  y: safe, j: ask me later, q: exit tool\n"
+        );
+        assert_eq!(
+            toml::from_str::<KeyBindings>(&toml::to_string(&keys).unwrap()).unwrap(),
+            keys
+        );
+
+        assert!(KeyBindings::default().validate().is_ok());
+        let clash: KeyBindings = toml::from_str("unsafe = \"s\"").unwrap();
+        assert!(clash.validate().is_err());
+        let command: KeyBindings = toml::from_str("exit = \":q\"").unwrap();
+        assert!(command.validate().is_err());
+        assert!(toml::from_str::<KeyBindings>("accept = \"a\"").is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! Message catalog for the user-facing text of the interactive audit.
+//!
+//! Every prompt, label, and status line shown while auditing is looked up
+//! here by `Msg` key so reviewing sessions can be run in languages other than
+//! English. The locale is selected with `--locale`, or else from the
+//! `CARGO_SCAN_LOCALE` and `LANG` environment variables, falling back to
+//! English. Messages with arguments use `{}` placeholders which are filled
+//! in order by `Msg::fill`.
+//!
+//! The annotation keys (s, u, c, ... unless they are configured, see `keys`)
+//! are the same in every locale, so instructions and audit scripts work
+//! regardless of language.
+
+use clap::ValueEnum;
+use std::fmt;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Locale {
+    /// Parse a POSIX-style locale name such as `es_ES.UTF-8`. Only the
+    /// language part is used.
+    pub fn from_lang(lang: &str) -> Option<Self> {
+        let code = lang.split(['_', '.', '-', '@']).next()?.to_lowercase();
+        match code.as_str() {
+            "en" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    /// Pick the locale from the environment, defaulting to English.
+    pub fn from_env() -> Self {
+        ["CARGO_SCAN_LOCALE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find_map(|lang| Self::from_lang(&lang))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Msg {
+    // Effect labels shown at the audit location
+    SinkCall,
+    FfiCall,
+    UnsafeCall,
+    RawPointer,
+    UnionField,
+    StaticMut,
+    StaticExt,
+    UnsafeTraitImpl,
+    FnPtrCreation,
+    ClosureCreation,
+    RawPtrCast,
+    PtrArithmetic,
+    CustomCall,
+    Atomic,
+    AliasEscape,
+    UninitMemory,
+    IntPtrCast,
+    ThreadSpawn,
+    OsHook,
+    MemoryMap,
+    MemoryMapProt,
+    Malware,
+    OnReceiver,
+    InAdapterClosure,
+    InClosure,
+    CallerCheckedLabel,
+    AuditLocation,
+    Remediation,
+    MacroExpansion,
+    // Plain output
+    PlainEffectHeader,
+    PlainFnSignature,
+    PlainEffectLine,
+    PlainContextLine,
+    // Call stack
+    CallStackHeader,
+    MissingFnDecl,
+    // Prompts
+    AnnotationPrompt,
+    InvalidInput,
+    JustificationPrompt,
+    JustificationOptional,
+    JustificationRequired,
+    ClusterPrompt,
+    SessionCommandsOption,
+    SessionHelp,
+    InvalidCommand,
+    // Actions bound to keys
+    KeySafe,
+    KeyUnsafe,
+    KeyCallerChecked,
+    KeyDefer,
+    KeyFalsePositive,
+    KeyOrigin,
+    KeyExpand,
+    KeyLater,
+    KeyExit,
+    KeyIndividually,
+    KeyHelp,
+    KeysHeader,
+    // Status lines
+    TotalUnauditedEffects,
+    TotalUnauditedLocations,
+    UnsafePackageWarning,
+    NoMoreEffects,
+    ClusterHeader,
+    ClusterCommonContext,
+    ClusterMarked,
+    ErrorPrintingEffect,
+    ErrorAcceptingInput,
+    AuditAnnotation,
+    QueueUpdated,
+    FanInOrder,
+}
+
+impl Msg {
+    /// The message template in the given locale.
+    pub fn text(self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::En => self.en(),
+            Locale::Es => self.es(),
+        }
+    }
+
+    /// The message in the given locale with its `{}` placeholders replaced,
+    /// in order, by `args`.
+    pub fn fill(self, locale: Locale, args: &[&dyn fmt::Display]) -> String {
+        let mut res = String::new();
+        let mut args = args.iter();
+        let mut parts = self.text(locale).split("{}").peekable();
+        while let Some(part) = parts.next() {
+            res.push_str(part);
+            if parts.peek().is_some() {
+                if let Some(arg) = args.next() {
+                    res.push_str(&arg.to_string());
+                }
+            }
+        }
+        res
+    }
+
+    fn en(self) -> &'static str {
+        match self {
+            Msg::SinkCall => "sink call: {}",
+            Msg::FfiCall => "ffi call: {}",
+            Msg::UnsafeCall => "unsafe call: {}",
+            Msg::RawPointer => "raw pointer access: {}",
+            Msg::UnionField => "union access: {}",
+            Msg::StaticMut => "static mut access: {}",
+            Msg::StaticExt => "static ffi variable access: {}",
+            Msg::UnsafeTraitImpl => "unsafe trait impl: {}",
+            Msg::FnPtrCreation => {
+                "function pointer creation (verify the function is always safe to call)"
+            }
+            Msg::ClosureCreation => {
+                "closure creation (verify the closure is always safe to call)"
+            }
+            Msg::RawPtrCast => {
+                "Cast to a raw pointer (can't cause unsafe behavior on its own)"
+            }
+            Msg::PtrArithmetic => "pointer arithmetic: {}",
+            Msg::CustomCall => "{} call: {}",
+            Msg::Atomic => "atomic operation with {} ordering: {}",
+            Msg::AliasEscape => "mutable access to data behind a shared reference: {}",
+            Msg::UninitMemory => "uninitialized memory API call: {}",
+            Msg::IntPtrCast => "cast between integer and raw pointer: {}",
+            Msg::ThreadSpawn => "thread or task spawn: {}",
+            Msg::OsHook => {
+                "OS hook ({}; verify the code it runs is signal- or fork-safe): {}"
+            }
+            Msg::MemoryMap => "memory mapping: {}",
+            Msg::MemoryMapProt => "memory mapping with {} protection: {}",
+            Msg::Malware => "possible malware ({}): {}",
+            Msg::OnReceiver => "{} on `{}`",
+            Msg::InAdapterClosure => "{}, inside `.{}()` closure in `{}`",
+            Msg::InClosure => "{}, inside closure #{} in `{}`",
+            Msg::CallerCheckedLabel => "call safety marked as caller-checked",
+            Msg::AuditLocation => "Audit location",
+            Msg::Remediation => "suggestion: {}",
+            Msg::MacroExpansion => "in expansion of `{}!`, defined at {}",
+            Msg::PlainEffectHeader => {
+                "effect at {} line {}: {}, {} lines of context follow"
+            }
+            Msg::PlainFnSignature => "in the function declared at line {}: {}",
+            Msg::PlainEffectLine => "line {}, effect: {}",
+            Msg::PlainContextLine => "line {}: {}",
+            Msg::CallStackHeader => "EffectInstance call stack:",
+            Msg::MissingFnDecl => "Missing fn decl",
+            Msg::AnnotationPrompt => "Select how to mark this effect:\n",
+            Msg::InvalidInput => "Invalid input",
+            Msg::JustificationPrompt => "Justification for marking {}{}:",
+            Msg::JustificationOptional => " (optional)",
+            Msg::JustificationRequired => "A justification is required",
+            Msg::ClusterPrompt => "Select how to mark all {} effects in this cluster:\n",
+            Msg::SessionCommandsOption => "  or enter a command (:help to list them)\n",
+            Msg::SessionHelp => {
+                "  :filter effect=<kind> callee=<path> caller=<path> file=<path>\n    \
+                 only audit the remaining effects matching the filters (:filter alone \
+                 removes them)\n  :skip-crate <name>\n    skip the remaining effects \
+                 calling into the crate\n  :jump <id>\n    audit the effect with the id \
+                 (as in `audit list`) next"
+            }
+            Msg::InvalidCommand => "Invalid command: {}",
+            Msg::KeySafe => "safe",
+            Msg::KeyUnsafe => "unsafe",
+            Msg::KeyCallerChecked => "caller checked",
+            Msg::KeyDefer => "defer",
+            Msg::KeyFalsePositive => "false positive",
+            Msg::KeyOrigin => "audit effect origin",
+            Msg::KeyExpand => "expand context",
+            Msg::KeyLater => "ask me later",
+            Msg::KeyExit => "exit tool",
+            Msg::KeyIndividually => "audit them individually",
+            Msg::KeyHelp => "list the keys",
+            Msg::KeysHeader => "Keys:",
+            Msg::TotalUnauditedEffects => "Total unaudited effects: {}",
+            Msg::TotalUnauditedLocations => "Total unaudited locations: {}",
+            Msg::UnsafePackageWarning => "WARNING: package has been marked as unsafe",
+            Msg::NoMoreEffects => "No more effects to audit",
+            Msg::ClusterHeader => "Cluster {} of {}: {} similar `{}` effects{}",
+            Msg::ClusterCommonContext => " near `{}`",
+            Msg::ClusterMarked => "Marked {} effects as {}",
+            Msg::ErrorPrintingEffect => {
+                "Error printing effect information. Trying to continue..."
+            }
+            Msg::ErrorAcceptingInput => {
+                "Error accepting user input. Attempting to continue..."
+            }
+            Msg::AuditAnnotation => "Audit annotation: {}",
+            Msg::QueueUpdated => "{} effects left to audit",
+            Msg::FanInOrder => {
+                "Effects are ordered by how many functions depend on their pending \
+                 decisions, most first"
+            }
+        }
+    }
+
+    fn es(self) -> &'static str {
+        match self {
+            Msg::SinkCall => "llamada a sumidero: {}",
+            Msg::FfiCall => "llamada ffi: {}",
+            Msg::UnsafeCall => "llamada unsafe: {}",
+            Msg::RawPointer => "acceso a puntero crudo: {}",
+            Msg::UnionField => "acceso a union: {}",
+            Msg::StaticMut => "acceso a static mut: {}",
+            Msg::StaticExt => "acceso a variable ffi estática: {}",
+            Msg::UnsafeTraitImpl => "impl de trait unsafe: {}",
+            Msg::FnPtrCreation => {
+                "creación de puntero a función (verifique que la función siempre \
+                 sea segura de llamar)"
+            }
+            Msg::ClosureCreation => {
+                "creación de clausura (verifique que la clausura siempre sea segura \
+                 de llamar)"
+            }
+            Msg::RawPtrCast => {
+                "Conversión a puntero crudo (no puede causar comportamiento inseguro \
+                 por sí sola)"
+            }
+            Msg::PtrArithmetic => "aritmética de punteros: {}",
+            Msg::CustomCall => "llamada {}: {}",
+            Msg::Atomic => "operación atómica con orden {}: {}",
+            Msg::AliasEscape => {
+                "acceso mutable a datos detrás de una referencia compartida: {}"
+            }
+            Msg::UninitMemory => "llamada a API de memoria no inicializada: {}",
+            Msg::IntPtrCast => "conversión entre entero y puntero crudo: {}",
+            Msg::ThreadSpawn => "creación de hilo o tarea: {}",
+            Msg::OsHook => {
+                "gancho del sistema operativo ({}; verificar que el código que ejecuta \
+                 sea seguro en señales o tras fork): {}"
+            }
+            Msg::MemoryMap => "mapeo de memoria: {}",
+            Msg::MemoryMapProt => "mapeo de memoria con protección {}: {}",
+            Msg::Malware => "posible malware ({}): {}",
+            Msg::OnReceiver => "{} sobre `{}`",
+            Msg::InAdapterClosure => "{}, dentro del closure de `.{}()` en `{}`",
+            Msg::InClosure => "{}, dentro del closure #{} en `{}`",
+            Msg::CallerCheckedLabel => {
+                "seguridad de la llamada marcada como verificada por el llamador"
+            }
+            Msg::AuditLocation => "Ubicación de auditoría",
+            Msg::Remediation => "sugerencia: {}",
+            Msg::MacroExpansion => "en la expansión de `{}!`, definida en {}",
+            Msg::PlainEffectHeader => {
+                "efecto en {} línea {}: {}, siguen {} líneas de contexto"
+            }
+            Msg::PlainFnSignature => "en la función declarada en la línea {}: {}",
+            Msg::PlainEffectLine => "línea {}, efecto: {}",
+            Msg::PlainContextLine => "línea {}: {}",
+            Msg::CallStackHeader => "Pila de llamadas del EffectInstance:",
+            Msg::MissingFnDecl => "Declaración de fn no encontrada",
+            Msg::AnnotationPrompt => "Seleccione cómo marcar este efecto:\n",
+            Msg::InvalidInput => "Entrada no válida",
+            Msg::JustificationPrompt => "Justificación para marcar como {}{}:",
+            Msg::JustificationOptional => " (opcional)",
+            Msg::JustificationRequired => "Se requiere una justificación",
+            Msg::ClusterPrompt => {
+                "Seleccione cómo marcar los {} efectos de este grupo:\n"
+            }
+            Msg::SessionCommandsOption => {
+                "  o introduzca un comando (:help para verlos)\n"
+            }
+            Msg::SessionHelp => {
+                "  :filter effect=<tipo> callee=<ruta> caller=<ruta> file=<ruta>\n    \
+                 auditar solo los efectos restantes que cumplan los filtros (:filter \
+                 solo los quita)\n  :skip-crate <nombre>\n    omitir los efectos \
+                 restantes que llaman al crate\n  :jump <id>\n    auditar a \
+                 continuación el efecto con ese id (como en `audit list`)"
+            }
+            Msg::InvalidCommand => "Comando no válido: {}",
+            Msg::KeySafe => "seguro",
+            Msg::KeyUnsafe => "inseguro",
+            Msg::KeyCallerChecked => "verificado por el llamador",
+            Msg::KeyDefer => "diferir",
+            Msg::KeyFalsePositive => "falso positivo",
+            Msg::KeyOrigin => "auditar origen del efecto",
+            Msg::KeyExpand => "ampliar contexto",
+            Msg::KeyLater => "preguntar más tarde",
+            Msg::KeyExit => "salir",
+            Msg::KeyIndividually => "auditarlos individualmente",
+            Msg::KeyHelp => "mostrar las teclas",
+            Msg::KeysHeader => "Teclas:",
+            Msg::TotalUnauditedEffects => "Total de efectos sin auditar: {}",
+            Msg::TotalUnauditedLocations => "Total de ubicaciones sin auditar: {}",
+            Msg::UnsafePackageWarning => {
+                "ADVERTENCIA: el paquete ha sido marcado como inseguro"
+            }
+            Msg::NoMoreEffects => "No quedan efectos por auditar",
+            Msg::ClusterHeader => "Grupo {} de {}: {} efectos `{}` similares{}",
+            Msg::ClusterCommonContext => " cerca de `{}`",
+            Msg::ClusterMarked => "Se marcaron {} efectos como {}",
+            Msg::ErrorPrintingEffect => {
+                "Error al mostrar la información del efecto. Intentando continuar..."
+            }
+            Msg::ErrorAcceptingInput => {
+                "Error al aceptar la entrada del usuario. Intentando continuar..."
+            }
+            Msg::AuditAnnotation => "Anotación de auditoría: {}",
+            Msg::QueueUpdated => "Quedan {} efectos por auditar",
+            Msg::FanInOrder => {
+                "Los efectos están ordenados según cuántas funciones dependen de sus \
+                 decisiones pendientes, de mayor a menor"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_placeholders() {
+        let s = Msg::Atomic.fill(Locale::En, &[&"SeqCst", &"std::sync::atomic::fence"]);
+        assert_eq!(s, "atomic operation with SeqCst ordering: std::sync::atomic::fence");
+        let s = Msg::NoMoreEffects.fill(Locale::Es, &[]);
+        assert_eq!(s, "No quedan efectos por auditar");
+    }
+
+    #[test]
+    fn test_locale_from_lang() {
+        assert_eq!(Locale::from_lang("es_ES.UTF-8"), Some(Locale::Es));
+        assert_eq!(Locale::from_lang("en"), Some(Locale::En));
+        assert_eq!(Locale::from_lang("C"), None);
+    }
+
+    #[test]
+    fn test_placeholder_counts_match() {
+        let msgs = [
+            Msg::SinkCall,
+            Msg::CustomCall,
+            Msg::Atomic,
+            Msg::OsHook,
+            Msg::MemoryMapProt,
+            Msg::Malware,
+            Msg::OnReceiver,
+            Msg::InAdapterClosure,
+            Msg::InClosure,
+            Msg::AnnotationPrompt,
+            Msg::JustificationPrompt,
+            Msg::ClusterPrompt,
+            Msg::ClusterHeader,
+            Msg::ClusterCommonContext,
+            Msg::ClusterMarked,
+            Msg::PlainEffectHeader,
+            Msg::PlainFnSignature,
+            Msg::AuditAnnotation,
+            Msg::Remediation,
+            Msg::MacroExpansion,
+            Msg::InvalidCommand,
+            Msg::QueueUpdated,
+            Msg::FanInOrder,
+        ];
+        for m in msgs {
+            assert_eq!(
+                m.text(Locale::En).matches("{}").count(),
+                m.text(Locale::Es).matches("{}").count(),
+                "{:?}",
+                m
+            );
+        }
+    }
+}
@@ -1,5 +1,16 @@
+pub mod analytics;
 pub mod audit;
+pub mod chain_graph;
+pub mod check;
+pub mod cluster;
+pub mod decide;
+pub mod decision_log;
+pub mod fan_in;
 pub mod info;
+pub mod keys;
+pub mod messages;
+pub mod patch;
 pub mod reset;
 pub mod review;
+pub mod session;
 pub mod util;
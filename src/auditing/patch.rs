@@ -0,0 +1,295 @@
+//! Audit decisions as patch files, so that audits can be reviewed in normal
+//! code-review tools.
+//!
+//! Exporting inserts a comment above the site of each base effect of the
+//! audit, e.g.
+//! ```text
+//! // AUDITED(3): Safe: only reads the config file
+//! ```
+//! and produces the unified diff against the original source. Effect ids are
+//! indices into the base effects sorted by source location (as in the audit
+//! server), and the justification is optional for some annotations.
+//! Importing reads the comments back from the added lines of a (possibly
+//! edited) patch and records the decisions in the audit.
+
+use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+use crate::effect::EffectInstance;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const MARKER: &str = "// AUDITED(";
+
+/// Lines of context around the inserted comments
+const CONTEXT_LINES: usize = 3;
+
+/// An audit decision read from a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub id: usize,
+    /// The file the comment was added to, relative to the crate
+    pub file: Option<PathBuf>,
+    pub annotation: SafetyAnnotation,
+    pub justification: Option<String>,
+}
+
+fn decision_of<'a>(
+    audit: &'a AuditFile,
+    tree: &EffectTree,
+) -> (SafetyAnnotation, Option<&'a str>) {
+    match tree {
+        EffectTree::Leaf(info, a) => {
+            (*a, audit.justifications.get(info).map(|j| j.as_str()))
+        }
+        EffectTree::Branch(_, _) => (SafetyAnnotation::CallerChecked, None),
+    }
+}
+
+/// Path of the effect's file relative to the crate
+fn relative_file(effect: &EffectInstance, crate_path: &Path) -> PathBuf {
+    let loc = effect.call_loc();
+    let path = loc.dir().join(loc.file());
+    match path.strip_prefix(crate_path) {
+        Ok(p) => p.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+pub fn audit_comment(
+    id: usize,
+    annotation: SafetyAnnotation,
+    justification: Option<&str>,
+) -> String {
+    match justification {
+        Some(j) => format!("{}{}): {}: {}", MARKER, id, annotation, j),
+        None => format!("{}{}): {}", MARKER, id, annotation),
+    }
+}
+
+/// Parse an audit comment, returning None if the line isn't one
+pub fn parse_audit_comment(
+    line: &str,
+) -> Option<Result<(usize, SafetyAnnotation, Option<String>)>> {
+    let rest = line.trim().strip_prefix(MARKER)?;
+    Some((|| -> Result<_> {
+        let (id, rest) =
+            rest.split_once("):").ok_or_else(|| anyhow!("Expected `):` after the id"))?;
+        let id = id.trim().parse::<usize>().context("Invalid effect id")?;
+        let (annotation, justification) = match rest.trim().split_once(':') {
+            Some((a, j)) => (a, Some(j.trim().to_string())),
+            None => (rest.trim(), None),
+        };
+        let annotation = SafetyAnnotation::from_str(annotation.trim())?;
+        Ok((id, annotation, justification.filter(|j| !j.is_empty())))
+    })())
+}
+
+/// Unified diff of a file with the given lines inserted before the
+/// (0-indexed) lines
+fn insertion_diff(
+    file: &Path,
+    text: &str,
+    insertions: &BTreeMap<usize, Vec<String>>,
+) -> String {
+    let lines = text.lines().collect::<Vec<_>>();
+    let missing_newline = !text.is_empty() && !text.ends_with('\n');
+
+    // Merge the ranges of lines around the insertions into hunks
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &at in insertions.keys() {
+        let start = at.saturating_sub(CONTEXT_LINES);
+        let end = (at + CONTEXT_LINES).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let file = file.to_string_lossy();
+    writeln!(out, "--- a/{}", file).unwrap();
+    writeln!(out, "+++ b/{}", file).unwrap();
+    let mut inserted_before = 0;
+    for (start, end) in hunks {
+        let inserted =
+            insertions.range(start..end).map(|(_, ls)| ls.len()).sum::<usize>();
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            start + 1,
+            end - start,
+            start + inserted_before + 1,
+            end - start + inserted
+        )
+        .unwrap();
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            for new_line in insertions.get(&i).into_iter().flatten() {
+                writeln!(out, "+{}", new_line).unwrap();
+            }
+            writeln!(out, " {}", line).unwrap();
+            if missing_newline && i + 1 == lines.len() {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+        inserted_before += inserted;
+    }
+    out
+}
+
+/// Unified diff inserting an audit comment above each base effect of the
+/// audit, with paths relative to the crate
+pub fn export_patch(audit: &AuditFile, crate_path: &Path) -> Result<String> {
+    let mut files: BTreeMap<PathBuf, BTreeMap<usize, Vec<String>>> = BTreeMap::new();
+    for (id, (effect, tree)) in audit.base_effects_by_location().into_iter().enumerate() {
+        let (annotation, justification) = decision_of(audit, tree);
+        let line = effect.call_loc().start_line().saturating_sub(1);
+        files
+            .entry(relative_file(effect, crate_path))
+            .or_default()
+            .entry(line)
+            .or_default()
+            .push(audit_comment(id, annotation, justification));
+    }
+
+    let mut patch = String::new();
+    for (file, mut insertions) in files {
+        let text = std::fs::read_to_string(crate_path.join(&file))
+            .context(format!("Failed to read {:?}", file))?;
+        // Indent the comments like the lines they annotate
+        let lines = text.lines().collect::<Vec<_>>();
+        for (at, comments) in insertions.iter_mut() {
+            let line = lines.get(*at).copied().unwrap_or("");
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for c in comments.iter_mut() {
+                *c = format!("{}{}", indent, c);
+            }
+        }
+        patch.push_str(&insertion_diff(&file, &text, &insertions));
+    }
+    Ok(patch)
+}
+
+/// Read the audit decisions from the added lines of a patch
+pub fn read_decisions(patch: &str) -> Result<Vec<Decision>> {
+    let mut decisions = Vec::new();
+    let mut file = None;
+    for (i, line) in patch.lines().enumerate() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.split('\t').next().unwrap_or(path);
+            file = Some(PathBuf::from(path.strip_prefix("b/").unwrap_or(path)));
+        } else if let Some(added) = line.strip_prefix('+') {
+            if let Some(parsed) = parse_audit_comment(added) {
+                let (id, annotation, justification) =
+                    parsed.context(format!("Invalid audit comment on line {}", i + 1))?;
+                decisions.push(Decision {
+                    id,
+                    file: file.clone(),
+                    annotation,
+                    justification,
+                });
+            }
+        }
+    }
+    Ok(decisions)
+}
+
+/// Record the decisions of a patch in the audit, and return the number of
+/// effects whose decisions changed
+pub fn import_patch(
+    audit: &mut AuditFile,
+    crate_path: &Path,
+    patch: &str,
+) -> Result<usize> {
+    let effects = audit
+        .base_effects_by_location()
+        .into_iter()
+        .map(|(e, t)| (e.clone(), decision_of(audit, t)))
+        .map(|(e, (a, j))| (e, a, j.map(|j| j.to_string())))
+        .collect::<Vec<_>>();
+
+    let mut changed = 0;
+    for d in read_decisions(patch)? {
+        let Some((effect, annotation, justification)) = effects.get(d.id) else {
+            return Err(anyhow!("No effect with id {} in the audit", d.id));
+        };
+        let file = relative_file(effect, crate_path);
+        if d.file.as_ref().map_or(false, |f| f != &file) {
+            return Err(anyhow!(
+                "Effect {} is in {:?}, not {:?}; was the patch exported from another audit?",
+                d.id,
+                file,
+                d.file.unwrap()
+            ));
+        }
+        if (d.annotation, &d.justification) == (*annotation, justification) {
+            continue;
+        }
+        if d.annotation == SafetyAnnotation::CallerChecked {
+            warn!(
+                "Skipping effect {}: effects can't be marked caller-checked from a \
+                patch, as that adds the callers to the audit",
+                d.id
+            );
+            continue;
+        }
+
+        let tree = audit.audit_trees.get_mut(effect).unwrap();
+        let info = match tree {
+            EffectTree::Branch(info, _) | EffectTree::Leaf(info, _) => info.clone(),
+        };
+        AuditFile::set_justification(
+            &mut audit.justifications,
+            &info,
+            d.annotation,
+            d.justification,
+        )
+        .context(format!("Invalid decision for effect {}", d.id))?;
+        *tree = EffectTree::Leaf(info, d.annotation);
+        changed += 1;
+    }
+    audit.prune_justifications();
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_comment_roundtrip() {
+        let c = audit_comment(3, SafetyAnnotation::Unsafe, Some("reads: user input"));
+        assert_eq!(c, "// AUDITED(3): Unsafe: reads: user input");
+        let (id, a, j) = parse_audit_comment(&format!("    {}", c)).unwrap().unwrap();
+        assert_eq!(
+            (id, a, j.as_deref()),
+            (3, SafetyAnnotation::Unsafe, Some("reads: user input"))
+        );
+
+        let c = audit_comment(0, SafetyAnnotation::FalsePositive, None);
+        let (_, a, j) = parse_audit_comment(&c).unwrap().unwrap();
+        assert_eq!((a, j), (SafetyAnnotation::FalsePositive, None));
+
+        assert!(parse_audit_comment("// not an audit comment").is_none());
+        assert!(parse_audit_comment("// AUDITED(x): Safe").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_insertion_diff() {
+        let text = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let insertions = BTreeMap::from([
+            (1, vec!["// AUDITED(0): Safe".to_string()]),
+            (9, vec!["// AUDITED(1): Unreviewed".to_string()]),
+        ]);
+        let diff = insertion_diff(Path::new("src/lib.rs"), text, &insertions);
+        let patch = read_decisions(&diff).unwrap();
+        assert_eq!(patch.len(), 2);
+        assert_eq!(patch[1].file.as_deref(), Some(Path::new("src/lib.rs")));
+        assert!(diff.contains("@@ -1,4 +1,5 @@\n a\n+// AUDITED(0): Safe\n b\n"));
+        assert!(diff
+            .contains("@@ -7,4 +8,5 @@\n g\n h\n i\n+// AUDITED(1): Unreviewed\n j\n"));
+    }
+}
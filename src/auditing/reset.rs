@@ -68,11 +68,9 @@ pub fn reset_annotation(mut audit: AuditFile, audit_path: PathBuf) -> Result<()>
         .audit_trees
         .iter()
         .filter_map(|(block, t)| match t {
-            EffectTree::Leaf(_, ann @ SafetyAnnotation::CallerChecked)
-            | EffectTree::Leaf(_, ann @ SafetyAnnotation::Safe)
-            | EffectTree::Leaf(_, ann @ SafetyAnnotation::Unsafe) => Some((block, *ann)),
+            EffectTree::Leaf(_, SafetyAnnotation::Unreviewed) => None,
+            EffectTree::Leaf(_, ann) => Some((block, *ann)),
             EffectTree::Branch(_, _) => Some((block, SafetyAnnotation::CallerChecked)),
-            _ => None,
         })
         .collect::<Vec<_>>();
 
@@ -92,12 +90,13 @@ pub fn reset_annotation(mut audit: AuditFile, audit_path: PathBuf) -> Result<()>
                         info.clone()
                     }
                 };
-                *tree = EffectTree::Leaf(info, SafetyAnnotation::Skipped);
+                *tree = EffectTree::Leaf(info, SafetyAnnotation::Unreviewed);
             }
         }
     }
 
     audit.audit_trees = new_audit_trees;
+    audit.prune_justifications();
     audit.save_to_file(audit_path)?;
 
     println!("No more annotated effects to reset");
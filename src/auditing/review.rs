@@ -6,9 +6,10 @@ use super::info::Config;
 use super::util::is_audit_scan_valid;
 use crate::audit_file::{AuditFile, EffectInfo, EffectTree, SafetyAnnotation};
 use crate::auditing::info::print_effect_info;
+use crate::auditing::messages::Msg;
 use crate::effect::{EffectInstance, SrcLoc};
 use crate::ident::CanonicalPath;
-use crate::scanner;
+use crate::scanner::{self, ScanConfig};
 
 fn review_effect_tree_info_helper(
     orig_effect: &EffectInstance,
@@ -21,12 +22,16 @@ fn review_effect_tree_info_helper(
         EffectTree::Leaf(new_e, a) => {
             print_effect_info(orig_effect, new_e, effect_history, fn_locs, config)?;
             // TODO: Colorize
-            println!("Audit annotation: {}", a);
+            println!("{}", Msg::AuditAnnotation.fill(config.locale(), &[a]));
         }
         EffectTree::Branch(new_e, es) => {
             // TODO: Colorize
             print_effect_info(orig_effect, new_e, effect_history, fn_locs, config)?;
-            println!("Audit annotation: {}", SafetyAnnotation::CallerChecked);
+            println!(
+                "{}",
+                Msg::AuditAnnotation
+                    .fill(config.locale(), &[&SafetyAnnotation::CallerChecked])
+            );
             let mut new_history = effect_history.to_owned();
             new_history.push(new_e);
             for new_tree in es {
@@ -56,13 +61,15 @@ pub fn review_audit(
     audit_file: &AuditFile,
     crate_path: &Path,
     config: &Config,
+    scan_config: &ScanConfig,
 ) -> Result<()> {
     // TODO: Change this scan to use the simpler scan when we add it
     // NOTE: The original scan for the audit we're reviewing wasn't necesarilly created
     //       with the same set of effects we're scanning for now. However, we only use
     //       the scan results to get the function locations, so it doesn't matter.
     println!("Scanning crate...");
-    let scan_res = scanner::scan_crate(crate_path, &audit_file.scanned_effects)?;
+    let scan_res =
+        scanner::scan_crate(crate_path, &audit_file.scanned_effects, scan_config)?;
     if !is_audit_scan_valid(audit_file, crate_path)? {
         println!("Error: crate has changed since last audit file scan.");
         return Err(anyhow!("Invalid audit file during review"));
@@ -74,3 +81,46 @@ pub fn review_audit(
 
     Ok(())
 }
+
+/// List the base effects of the audit with their ids (as used by
+/// `audit add-ref`, `--export-patch`, and the audit server) and decisions, and
+/// optionally their external references
+pub fn list_effects(audit_file: &AuditFile, with_refs: bool) {
+    let mut missing_refs = 0;
+    for (id, (e, tree)) in audit_file.base_effects_by_location().into_iter().enumerate() {
+        let (info, annotation) = match tree {
+            EffectTree::Leaf(info, a) => (info, *a),
+            EffectTree::Branch(info, _) => (info, SafetyAnnotation::CallerChecked),
+        };
+        let loc = e.call_loc();
+        println!(
+            "{} ({}) {}:{}:{} {}",
+            id,
+            annotation,
+            loc.display_path(),
+            loc.start_line(),
+            loc.start_col(),
+            e.callee()
+        );
+        if !with_refs {
+            continue;
+        }
+        match audit_file.references.get(info) {
+            Some(refs) if !refs.is_empty() => {
+                for r in refs {
+                    println!("    {}", r);
+                }
+            }
+            _ => {
+                if annotation != SafetyAnnotation::Unreviewed {
+                    missing_refs += 1;
+                }
+                println!("    (no references)");
+            }
+        }
+    }
+    if with_refs && missing_refs > 0 {
+        println!();
+        println!("{} reviewed effects have no references", missing_refs);
+    }
+}
@@ -0,0 +1,319 @@
+//! Commands for changing the queue of effects left to audit in the middle of
+//! an interactive audit, entered at the annotation prompt:
+//! - `:filter effect=ffi callee=libc file=src/net` only audits the remaining
+//!   effects matching all of the filters (each can list alternatives, e.g.
+//!   `effect=ffi,unsafe`); `:filter` alone removes the filter;
+//! - `:skip-crate foo` skips the remaining effects calling into (or in) the
+//!   crate `foo`;
+//! - `:jump <id>` audits the effect with the id (as in `audit list`) next;
+//! - `:help` lists the commands.
+//!
+//! Skipped and filtered out effects stay unreviewed, so they come up again
+//! in the next audit.
+
+use crate::audit_file::{AuditFile, SafetyAnnotation};
+use crate::audit_query::{AuditEntry, AuditQuery};
+use crate::effect::{EffectInstance, EffectSelector};
+
+use anyhow::{anyhow, Result};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionCommand {
+    /// Replace the filter, or remove it if None
+    Filter(Option<AuditQuery>),
+    SkipCrate(String),
+    Jump(usize),
+    Help,
+}
+
+fn parse_filter(args: &[&str]) -> Result<AuditQuery> {
+    let mut query = AuditQuery::new();
+    for arg in args {
+        let (key, values) = arg
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected a filter like effect=ffi: {}", arg))?;
+        for value in values.split(',') {
+            query = match key {
+                "effect" => {
+                    let selector =
+                        value.parse::<EffectSelector>().map_err(|e| anyhow!(e))?;
+                    selector
+                        .effect_types()
+                        .into_iter()
+                        .fold(query, |q, t| q.effect_type(t))
+                }
+                "callee" => query.callee(value),
+                "caller" => query.caller(value),
+                "file" => query.in_file(value),
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown filter {}; expected effect, callee, caller, or file",
+                        key
+                    ))
+                }
+            };
+        }
+    }
+    Ok(query)
+}
+
+impl FromStr for SessionCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let words = s
+            .strip_prefix(':')
+            .ok_or_else(|| anyhow!("Commands start with ':'"))?
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        match words.as_slice() {
+            ["filter"] => Ok(Self::Filter(None)),
+            ["filter", args @ ..] => Ok(Self::Filter(Some(parse_filter(args)?))),
+            ["skip-crate", name] => Ok(Self::SkipCrate(name.to_string())),
+            ["jump", id] => {
+                let id = id.parse().map_err(|_| anyhow!("Invalid effect id: {}", id))?;
+                Ok(Self::Jump(id))
+            }
+            ["help"] => Ok(Self::Help),
+            _ => Err(anyhow!("Unknown command: {}", s)),
+        }
+    }
+}
+
+/// The effects left to audit, by id, in order
+#[derive(Debug, Clone)]
+pub struct AuditQueue {
+    /// The base effects of the audit, sorted by location, so that the index
+    /// of an effect is its id
+    effects: Vec<EffectInstance>,
+    pending: VecDeque<usize>,
+    /// Effects left to audit which the filter or skipped crates remove
+    deselected: BTreeSet<usize>,
+    filter: Option<AuditQuery>,
+    skipped_crates: HashSet<String>,
+    /// Priorities of the effects, if they are audited by priority (highest
+    /// first) rather than by location
+    priorities: Option<HashMap<usize, usize>>,
+    /// Effects deferred in an earlier session, which are audited after the
+    /// unreviewed ones
+    deferred: HashSet<usize>,
+}
+
+impl AuditQueue {
+    /// The queue of the effects of the audit which need auditing: the
+    /// unreviewed effects, and those marked caller-checked whose callers may
+    /// need auditing, followed by the deferred effects
+    pub fn new(audit_file: &AuditFile) -> Self {
+        let entries = audit_file.entries();
+        let effects = entries.iter().map(|e| e.effect.clone()).collect();
+        let deferred = entries
+            .iter()
+            .filter(|e| e.tree.get_leaf_annotation() == Some(SafetyAnnotation::Deferred))
+            .map(|e| e.id)
+            .collect();
+        let pending = entries
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.tree.get_leaf_annotation(),
+                    None | Some(SafetyAnnotation::Unreviewed)
+                )
+            })
+            .map(|e| e.id)
+            .collect::<Vec<_>>();
+        let mut queue = Self {
+            effects,
+            pending: VecDeque::new(),
+            deselected: BTreeSet::new(),
+            filter: None,
+            skipped_crates: HashSet::new(),
+            priorities: None,
+            deferred,
+        };
+        let ids = queue.deferred.iter().copied().chain(pending).collect::<Vec<_>>();
+        queue.pending = queue.ordered(ids).into();
+        queue
+    }
+
+    fn is_selected(&self, audit_file: &AuditFile, id: usize) -> bool {
+        let effect = &self.effects[id];
+        let Some(tree) = audit_file.audit_trees.get(effect) else {
+            return false;
+        };
+        let skipped = [effect.caller(), effect.callee()]
+            .iter()
+            .any(|p| self.skipped_crates.contains(p.crate_name().as_str()));
+        let entry = AuditEntry { id, effect, tree };
+        !skipped && self.filter.as_ref().map_or(true, |q| q.matches(&entry))
+    }
+
+    /// Select the effects left to audit again, in order, after the filter
+    /// or the skipped crates changed
+    fn reselect(&mut self, audit_file: &AuditFile) {
+        let mut ids = std::mem::take(&mut self.deselected);
+        ids.extend(self.pending.drain(..));
+        for id in self.ordered(ids) {
+            if self.is_selected(audit_file, id) {
+                self.pending.push_back(id);
+            } else {
+                self.deselected.insert(id);
+            }
+        }
+    }
+
+    /// The ids in the order to audit them
+    fn ordered(&self, ids: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut ids = ids.into_iter().collect::<Vec<_>>();
+        ids.sort();
+        if let Some(priorities) = &self.priorities {
+            ids.sort_by_key(|id| Reverse(priorities.get(id).copied().unwrap_or(0)));
+        }
+        ids.sort_by_key(|id| self.deferred.contains(id));
+        ids
+    }
+
+    /// Audit the effects in decreasing order of priority (stable, so by
+    /// location among equals)
+    pub fn prioritize(&mut self, mut priority: impl FnMut(&EffectInstance) -> usize) {
+        let priorities =
+            self.effects.iter().enumerate().map(|(id, e)| (id, priority(e))).collect();
+        self.priorities = Some(priorities);
+        let pending = std::mem::take(&mut self.pending);
+        self.pending = self.ordered(pending).into();
+    }
+
+    /// The next effect to audit
+    pub fn pop(&mut self) -> Option<EffectInstance> {
+        self.pending.pop_front().map(|id| self.effects[id].clone())
+    }
+
+    /// Put an effect back at the front of the queue (e.g. when a command is
+    /// entered before it was audited)
+    pub fn push_front(&mut self, effect: &EffectInstance) {
+        if let Some(id) = self.effects.iter().position(|e| e == effect) {
+            self.pending.retain(|i| *i != id);
+            self.deselected.remove(&id);
+            self.pending.push_front(id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Change the queue with the command. Jumping to an effect audits it
+    /// next even if it is filtered out or already audited. Help doesn't
+    /// change the queue.
+    pub fn apply(
+        &mut self,
+        command: &SessionCommand,
+        audit_file: &AuditFile,
+    ) -> Result<()> {
+        match command {
+            SessionCommand::Filter(query) => {
+                self.filter = query.clone();
+                self.reselect(audit_file);
+            }
+            SessionCommand::SkipCrate(name) => {
+                self.skipped_crates.insert(name.clone());
+                self.reselect(audit_file);
+            }
+            SessionCommand::Jump(id) => {
+                let effect = self
+                    .effects
+                    .get(*id)
+                    .ok_or_else(|| anyhow!("No effect with id {} in the audit", id))?
+                    .clone();
+                self.push_front(&effect);
+            }
+            SessionCommand::Help => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_audit_queue() {
+        let tokens = "getuid fork open".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let crate_name = if t.to_string() == "open" { "std::fs" } else { "libc" };
+                let callee = CanonicalPath::new(&format!("{}::{}", crate_name, t));
+                let eff = if crate_name == "libc" {
+                    Effect::FFICall(callee.clone())
+                } else {
+                    Effect::UnsafeCall(callee.clone())
+                };
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee,
+                    &t,
+                    eff,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees(effects.iter());
+        let mut queue = AuditQueue::new(&audit_file);
+        assert_eq!(queue.len(), 3);
+
+        let filter = ":filter effect=ffi".parse::<SessionCommand>().unwrap();
+        queue.apply(&filter, &audit_file).unwrap();
+        assert_eq!(queue.len(), 2);
+        let skip = ":skip-crate libc".parse::<SessionCommand>().unwrap();
+        queue.apply(&skip, &audit_file).unwrap();
+        assert!(queue.is_empty());
+
+        // Removing the filter brings back the effects which aren't skipped,
+        // and jumps put any effect first
+        queue.apply(&":filter".parse().unwrap(), &audit_file).unwrap();
+        assert_eq!(queue.len(), 1);
+        let fork = audit_file.query(&AuditQuery::new().callee("libc::fork"))[0].id;
+        let jump = format!(":jump {}", fork).parse::<SessionCommand>().unwrap();
+        queue.apply(&jump, &audit_file).unwrap();
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "libc::fork");
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "std::fs::open");
+        assert!(queue.apply(&SessionCommand::Jump(3), &audit_file).is_err());
+
+        // Prioritized effects come first, and stay first after filtering
+        let mut queue = AuditQueue::new(&audit_file);
+        queue.prioritize(|e| usize::from(e.callee().as_str() == "std::fs::open"));
+        queue.apply(&":skip-crate nix".parse().unwrap(), &audit_file).unwrap();
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "std::fs::open");
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "libc::getuid");
+
+        // Deferred effects come back after the unreviewed ones
+        let getuid = audit_file.query(&AuditQuery::new().callee("libc::getuid"))[0].id;
+        audit_file
+            .annotate(getuid, SafetyAnnotation::Deferred, Some("later".to_string()))
+            .unwrap();
+        let mut queue = AuditQueue::new(&audit_file);
+        queue.prioritize(|e| usize::from(e.callee().as_str() == "libc::getuid"));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "libc::fork");
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "std::fs::open");
+        assert_eq!(queue.pop().unwrap().callee().as_str(), "libc::getuid");
+
+        assert!(":filter effect=nothing".parse::<SessionCommand>().is_err());
+        assert!(":filter ffi".parse::<SessionCommand>().is_err());
+        assert!(":jump x".parse::<SessionCommand>().is_err());
+        assert!("filter".parse::<SessionCommand>().is_err());
+    }
+}
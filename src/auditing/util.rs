@@ -1,4 +1,5 @@
 use crate::audit_file::AuditFile;
+use crate::util::fs;
 
 use std::fs::File;
 use std::io::Read;
@@ -6,22 +7,27 @@ use std::path::Path;
 
 use anyhow::Result;
 use sha2::{Digest, Sha256};
-use walkdir::WalkDir;
 
 pub fn hash_dir<P>(p: P) -> Result<[u8; 32]>
 where
     P: AsRef<Path>,
 {
     let mut hasher = Sha256::new();
-    for entry in WalkDir::new(&p).sort_by_file_name() {
-        match entry {
-            Ok(ne) if ne.path().is_file() => {
-                let mut file = File::open(ne.path())?;
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf)?;
-                hasher.update(buf);
-            }
-            _ => (),
+    // Symlinks aren't followed, as in scans by default, so the hash doesn't
+    // depend on files outside the crate. Crates which are no longer on disk
+    // hash as empty.
+    let dir = p.as_ref().to_path_buf();
+    let files = if dir.is_dir() {
+        fs::walk_files(&dir, fs::SymlinkPolicy::default()).collect()
+    } else {
+        Vec::new()
+    };
+    for path in files {
+        if path.is_file() {
+            let mut file = File::open(path)?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            hasher.update(buf);
         }
     }
 
@@ -0,0 +1,137 @@
+//! SVG badges summarizing an audit, for crate authors to embed in their
+//! READMEs, e.g. `unsafe: 0` or `audited: 100%`. Written to files by
+//! `audit badge`, and served by `serve` at `/badges/<kind>.svg`, which gives
+//! them a stable URL that stays current as decisions are recorded.
+//!
+//! The badges follow the flat shields.io style: a grey label and a colored
+//! message.
+
+use crate::audit_file::AuditFile;
+
+use clap::ValueEnum;
+use std::fmt;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadgeKind {
+    /// Number of base effects which are unsafe Rust operations
+    Unsafe,
+    /// Percentage of effects with a decision
+    Audited,
+    /// Number of base effects
+    Effects,
+}
+
+impl fmt::Display for BadgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unsafe => write!(f, "unsafe"),
+            Self::Audited => write!(f, "audited"),
+            Self::Effects => write!(f, "effects"),
+        }
+    }
+}
+
+impl BadgeKind {
+    pub const ALL: [BadgeKind; 3] = [Self::Unsafe, Self::Audited, Self::Effects];
+}
+
+const GREEN: &str = "#4c1";
+const YELLOW_GREEN: &str = "#a4a61d";
+const YELLOW: &str = "#dfb317";
+const ORANGE: &str = "#fe7d37";
+const RED: &str = "#e05d44";
+const BLUE: &str = "#007ec6";
+const GREY: &str = "#555";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub label: String,
+    pub message: String,
+    pub color: &'static str,
+}
+
+impl Badge {
+    pub fn from_audit(kind: BadgeKind, audit_file: &AuditFile) -> Self {
+        let label = kind.to_string();
+        match kind {
+            BadgeKind::Unsafe => {
+                let n =
+                    audit_file.audit_trees.keys().filter(|e| e.is_rust_unsafe()).count();
+                let color = if n == 0 { GREEN } else { ORANGE };
+                Self { label, message: n.to_string(), color }
+            }
+            BadgeKind::Audited => {
+                let (audited, total) = audit_file.audit_coverage(None);
+                // An audit without effects has nothing left to audit
+                let percent = if total == 0 { 100 } else { audited * 100 / total };
+                let color = match percent {
+                    100 => GREEN,
+                    80..=99 => YELLOW_GREEN,
+                    50..=79 => YELLOW,
+                    _ => RED,
+                };
+                Self { label, message: format!("{}%", percent), color }
+            }
+            BadgeKind::Effects => {
+                let n = audit_file.audit_trees.len();
+                Self { label, message: n.to_string(), color: BLUE }
+            }
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let label_width = text_width(&self.label);
+        let message_width = text_width(&self.message);
+        let width = label_width + message_width;
+        let label = escape_xml(&self.label);
+        let message = escape_xml(&self.message);
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+<title>{label}: {message}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="{GREY}"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text>
+<text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="14">{message}</text>
+</g>
+</svg>
+"##,
+            color = self.color,
+            label_x = label_width / 2,
+            message_x = label_width + message_width / 2,
+        )
+    }
+}
+
+/// Approximate width in pixels of the text in 11px Verdana, with padding
+fn text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_badge_svg() {
+        let badge =
+            Badge { label: "unsafe".to_string(), message: "0".to_string(), color: GREEN };
+        let svg = badge.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"aria-label="unsafe: 0""#));
+        assert!(svg.contains(r#"width="69""#));
+        assert!(svg.contains(GREEN));
+
+        let badge =
+            Badge { label: "a<b".to_string(), message: "\"x\"".to_string(), color: RED };
+        assert!(badge.to_svg().contains("a&lt;b: &quot;x&quot;"));
+    }
+}
@@ -0,0 +1,155 @@
+/*
+    Signed in-toto attestations of audits, for attaching to build provenance.
+
+    `attest keygen <key>` generates a signing key (and <key>.pub),
+    `attest create <crate> <out>` signs a statement of the crate's audit
+    results, and `attest verify-attestation <attestation>` checks the
+    signature (and optionally that it's for the given crate). See
+    attestation.rs for the format.
+*/
+
+use cargo_scan::attestation::{self, Envelope, Statement};
+use cargo_scan::audit_file::AuditFile;
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, load_cargo_toml, Verbosity};
+
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use home::home_dir;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Keygen(Keygen),
+    Create(Create),
+    VerifyAttestation(VerifyAttestation),
+}
+
+/// Generate an Ed25519 signing key, and its verifying key with a .pub
+/// extension
+#[derive(ClapArgs, Debug)]
+struct Keygen {
+    /// Path to save the signing key to
+    key_path: PathBuf,
+}
+
+/// Sign an attestation that a crate was scanned and audited, with the results
+/// of the audit
+#[derive(ClapArgs, Debug)]
+struct Create {
+    /// Path to the audited crate
+    crate_path: PathBuf,
+
+    /// Path to save the attestation to
+    output_path: PathBuf,
+
+    /// Signing key made with `attest keygen`
+    #[clap(short, long)]
+    key: PathBuf,
+
+    /// Path to the audit file (defaults to the one in $HOME/.cargo_audits)
+    #[clap(short, long)]
+    audit_file_path: Option<PathBuf>,
+}
+
+/// Verify the signature of an attestation and print the audit results in it
+#[derive(ClapArgs, Debug)]
+struct VerifyAttestation {
+    /// Path to the attestation
+    attestation_path: PathBuf,
+
+    /// Verifying key (the .pub file made with `attest keygen`)
+    #[clap(short, long)]
+    key: PathBuf,
+
+    /// Also check that the attestation is for the current contents of the
+    /// crate
+    #[clap(long)]
+    crate_path: Option<PathBuf>,
+}
+
+impl Create {
+    fn run(self, summary: &mut Summary) -> Result<()> {
+        let crate_id = load_cargo_toml(&self.crate_path)?;
+        let audit_file_path = match self.audit_file_path {
+            Some(p) => p,
+            None => home_dir()
+                .ok_or_else(|| anyhow!("Couldn't find the home directory"))?
+                .join(".cargo_audits")
+                .join(format!("{}.audit", crate_id)),
+        };
+        let audit = AuditFile::read_audit_file(audit_file_path.clone())?
+            .ok_or_else(|| anyhow!("No audit file at {:?}", audit_file_path))?;
+        summary.add_audit(&audit);
+
+        let key = attestation::read_signing_key(&self.key)?;
+        let statement = Statement::for_crate(&self.crate_path, &crate_id, &audit)?;
+        Envelope::sign(&statement, &key)?.save_to_file(&self.output_path)
+    }
+}
+
+impl VerifyAttestation {
+    fn run(self, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let key = attestation::read_verifying_key(&self.key)?;
+        let envelope = Envelope::read_from_file(&self.attestation_path)?;
+        let statement = envelope.verify(&key)?;
+        if let Some(crate_path) = &self.crate_path {
+            statement.check_subject(crate_path)?;
+        }
+
+        let p = &statement.predicate;
+        summary.set_reason(format!(
+            "verified attestation for {}@{}",
+            p.crate_name, p.version
+        ));
+        if !quiet {
+            println!(
+                "Verified attestation for {} {} signed by key {}",
+                p.crate_name,
+                p.version,
+                attestation::key_id(&key)
+            );
+            println!(
+                "  {} effects, {} unaudited, {} unsafe, risk score {}",
+                p.summary.total_effects,
+                p.summary.unaudited_effects,
+                p.summary.unsafe_effects,
+                p.summary.risk_score
+            );
+            if let Some(limit) = p.partial_scan {
+                println!("  partially scanned (hit the scan {})", limit);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args.verbosity);
+    let quiet = args.verbosity.quiet;
+    let mut summary = Summary::new("attest");
+    let res = match args.command {
+        Command::Keygen(keygen) => {
+            attestation::generate_key(&keygen.key_path).map(|key| {
+                if !quiet {
+                    println!("Generated key {}", attestation::key_id(&key));
+                }
+            })
+        }
+        Command::Create(create) => create.run(&mut summary),
+        Command::VerifyAttestation(verify) => verify.run(quiet, &mut summary),
+    };
+    summary.print(&res);
+    res
+}
@@ -1,20 +1,34 @@
 use cargo_scan::audit_file::*;
+use cargo_scan::auditing::analytics::analyze_store;
 use cargo_scan::auditing::audit::start_audit;
+use cargo_scan::auditing::chain_graph::{ChainGraph, GraphFormat};
+use cargo_scan::auditing::decide::{self, Decision};
+use cargo_scan::auditing::decision_log;
 use cargo_scan::auditing::info::Config;
+use cargo_scan::auditing::patch;
 use cargo_scan::auditing::reset::reset_annotation;
-use cargo_scan::auditing::review::review_audit;
+use cargo_scan::auditing::review::{list_effects, review_audit};
 use cargo_scan::auditing::util::{hash_dir, is_audit_scan_valid};
-use cargo_scan::effect::{EffectInstance, EffectType, DEFAULT_EFFECT_TYPES};
-use cargo_scan::scanner::{self, scan_crate};
-use cargo_scan::util::load_cargo_toml;
+use cargo_scan::badge::{self, BadgeKind};
+use cargo_scan::db::ScanDb;
+use cargo_scan::effect::{EffectInstance, EffectSelector, EffectType};
+use cargo_scan::effect_identity::EffectIdentity;
+use cargo_scan::html_report::HtmlReport;
+use cargo_scan::progress;
+use cargo_scan::project::ProjectConfig;
+use cargo_scan::scanner::{self, scan_crate, ScanConfig};
+use cargo_scan::summary::Summary;
+use cargo_scan::template::{self, AuditContext};
+use cargo_scan::util::{init_logging, load_cargo_toml, Verbosity};
 
 use std::collections::HashMap;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
-use clap::{Parser, ValueEnum};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use home::home_dir;
 use inquire::{validator::Validation, Text};
 use petgraph::dot::Dot;
@@ -33,14 +47,20 @@ use petgraph::dot::Dot;
                         saved to the $HOME/.cargo_audits directory. \n\n\
                         Note that this tool only tracks effects which originate \
                         within the chosen crate. Effects originating in other \
-                        crates must be separately audited."
+                        crates must be separately audited.",
+    subcommand_negates_reqs = true
 )]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// path to crate
-    crate_path: PathBuf,
+    #[clap(required = true)]
+    crate_path: Option<PathBuf>,
 
-    #[clap(short, long)]
-    /// path to the audit file (will create a new one if it doesn't exist)
+    #[clap(short, long, global = true)]
+    /// path to the audit file (will create a new one if it doesn't exist). With
+    /// analytics, this can also be a directory of audit files
     audit_file_path: Option<PathBuf>,
 
     #[clap(flatten)]
@@ -51,6 +71,14 @@ struct Args {
     #[clap(long = "overwrite-audit", default_value_t = false)]
     overwrite_audit: bool,
 
+    /// When an effect in a new version of the crate is the same as one in the
+    /// audit file, whose decision is then kept: location, or path (same
+    /// caller, callee, and kind). Audit files don't keep the source of
+    /// effects, so snippet is the same as path here. Defaults to the project
+    /// config, or location.
+    #[clap(long, value_enum)]
+    effect_identity: Option<EffectIdentity>,
+
     /// Review the audit file without performing an audit
     #[clap(short, long, default_value_t = false)]
     review: bool,
@@ -59,6 +87,11 @@ struct Args {
     #[clap(long, default_value_t = ReviewInfo::PubFuns)]
     review_info: ReviewInfo,
 
+    /// Render the reviewed audit with a template (Jinja2 syntax) instead;
+    /// see template.rs for the data available to templates
+    #[clap(long)]
+    template: Option<PathBuf>,
+
     /// Preview the effects in a package without performing an audit or saving
     /// an audit file
     #[clap(short, long, default_value_t = false)]
@@ -68,6 +101,23 @@ struct Args {
     #[clap(long)]
     reset_annotation: bool,
 
+    /// Export the audit decisions as a patch which adds an
+    /// `// AUDITED(<id>): <annotation>: <justification>` comment above each
+    /// effect, to review the audit in code-review tools
+    #[clap(long, conflicts_with = "import_patch")]
+    export_patch: Option<PathBuf>,
+
+    /// Read the audit decisions back from a (reviewed) patch made by
+    /// --export-patch, and save them to the audit file
+    #[clap(long)]
+    import_patch: Option<PathBuf>,
+
+    /// Export the effect call chains of the audit as a graph, labeled with the
+    /// effect kinds; in the Mermaid format if the path ends in .mmd, and DOT
+    /// (Graphviz) otherwise
+    #[clap(long)]
+    export_chains: Option<PathBuf>,
+
     /// For debugging stuff
     #[clap(long, default_value_t = false)]
     debug: bool,
@@ -78,14 +128,313 @@ struct Args {
     #[clap(short, long, default_value_t = false)]
     ignore_hash: bool,
 
+    #[clap(flatten)]
+    scan_config: ScanConfig,
+
     /// Dump the callgraph to the specified file. Uses the DOT format.
     #[clap(long)]
     dump_callgraph: Option<String>,
 
-    /// The types of Effects the audit should track. Defaults to all unsafe
-    /// behavior.
-    #[clap(long, value_parser, num_args = 1.., default_values_t = DEFAULT_EFFECT_TYPES)]
+    /// The types of Effects the audit should track, or families of them
+    /// (default, ffi, unsafe, sinks, raw-pointer, malware, os-hooks). Defaults to
+    /// all unsafe behavior. Effects which aren't selected aren't scanned for at all.
+    #[clap(
+        long = "effect-types",
+        value_parser,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    effect_selection: Vec<EffectSelector>,
+
+    #[clap(skip)]
     effect_types: Vec<EffectType>,
+
+    /// Also save the audit to a SQLite database (created if it doesn't exist)
+    #[clap(long)]
+    db: Option<PathBuf>,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+impl Args {
+    /// The crate to audit, given directly or to the subcommand
+    fn crate_path(&self) -> &Path {
+        match &self.command {
+            Some(Command::Gc(gc)) => &gc.crate_path,
+            Some(Command::Decide(decide)) => &decide.crate_path,
+            Some(Command::Badge(badge)) => &badge.crate_path,
+            Some(Command::Log(log)) => &log.crate_path,
+            Some(Command::Report(report)) => &report.crate_path,
+            Some(Command::List(list)) => &list.crate_path,
+            Some(Command::AddRef(add_ref)) => &add_ref.crate_path,
+            Some(Command::Analytics(analytics)) => &analytics.crate_path,
+            None => self.crate_path.as_deref().expect("crate path is required"),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Gc(Gc),
+    Decide(Decide),
+    Badge(Badge),
+    Log(Log),
+    Report(Report),
+    List(List),
+    AddRef(AddRef),
+    Analytics(Analytics),
+}
+
+/// Report reviewer throughput, average time to audit per severity, and the
+/// backlog of unaudited effects over time, from the git history of the audit
+/// file (or directory of audit files)
+#[derive(ClapArgs, Debug)]
+struct Analytics {
+    /// path to crate
+    crate_path: PathBuf,
+}
+
+/// List the base effects of the audit with their ids and decisions
+#[derive(ClapArgs, Debug)]
+struct List {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// Also list the external references (e.g. issue URLs) of each effect
+    #[clap(long, default_value_t = false)]
+    with_refs: bool,
+}
+
+/// Attach an external reference (a JIRA or GitHub issue URL) to the decision
+/// for an effect
+#[derive(ClapArgs, Debug)]
+struct AddRef {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// The effect, by its id in `audit list`
+    id: usize,
+
+    /// The reference to attach
+    url: String,
+}
+
+/// Attach the reference to the decision for the effect with the id
+fn add_ref(
+    mut audit_file: AuditFile,
+    audit_file_path: PathBuf,
+    cmd: &AddRef,
+) -> Result<()> {
+    let info = match audit_file.base_effects_by_location().get(cmd.id) {
+        Some((_, EffectTree::Leaf(info, _) | EffectTree::Branch(info, _))) => {
+            info.clone()
+        }
+        None => return Err(anyhow!("Invalid effect id: {}", cmd.id)),
+    };
+    audit_file.add_reference(&info, &cmd.url)?;
+    progress!("Added reference to effect {}", cmd.id);
+    audit_file.save_to_file(audit_file_path)
+}
+
+/// Archive the audit entries whose effects are no longer in the crate, once
+/// they have been missing for the retention period
+#[derive(ClapArgs, Debug)]
+struct Gc {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// Days an entry's effect has to be missing (over several runs) before the
+    /// entry is archived; 0 archives it right away
+    #[clap(long, default_value_t = 30)]
+    retention_days: u64,
+}
+
+/// Record audit decisions without the interactive audit, e.g. from a bot or a
+/// web review UI; effect ids are the ones shown by `audit list`
+#[derive(ClapArgs, Debug)]
+struct Decide {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// The effect to decide on
+    #[clap(long, required_unless_present = "from", requires = "verdict")]
+    id: Option<usize>,
+
+    /// The decision: unreviewed, safe, unsafe, caller-checked, deferred, or
+    /// false-positive
+    #[clap(long, requires = "id")]
+    verdict: Option<SafetyAnnotation>,
+
+    /// Justification for the decision
+    #[clap(long, requires = "id")]
+    note: Option<String>,
+
+    /// External reference (e.g. an issue URL) tracking the decision
+    #[clap(long = "ref", requires = "id")]
+    reference: Option<String>,
+
+    /// Read a JSON list of decisions (see auditing/decide.rs) from this file,
+    /// or - for stdin
+    #[clap(long, conflicts_with = "id")]
+    from: Option<PathBuf>,
+}
+
+impl Decide {
+    fn decisions(&self) -> Result<Vec<Decision>> {
+        match (&self.from, self.id, self.verdict) {
+            (Some(path), _, _) => {
+                let json = if path.as_os_str() == "-" {
+                    std::io::read_to_string(std::io::stdin())?
+                } else {
+                    std::fs::read_to_string(path)
+                        .context(format!("Failed to read decisions {:?}", path))?
+                };
+                decide::read_decisions(&json)
+            }
+            (None, Some(id), Some(verdict)) => Ok(vec![Decision {
+                id,
+                verdict,
+                note: self.note.clone(),
+                refs: self.reference.iter().cloned().collect(),
+            }]),
+            _ => Err(anyhow!("Either --from or --id and --verdict are required")),
+        }
+    }
+}
+
+/// Record the decisions, scanning the crate if any marks an effect
+/// caller-checked (to add its callers to the audit)
+fn decide_audit(
+    mut audit_file: AuditFile,
+    audit_file_path: PathBuf,
+    decide: &Decide,
+    scan_config: &ScanConfig,
+    summary: &mut Summary,
+) -> Result<()> {
+    let decisions = decide.decisions()?;
+    let scan_res = if decisions
+        .iter()
+        .any(|d| d.verdict == SafetyAnnotation::CallerChecked)
+    {
+        progress!("Scanning crate...");
+        Some(scan_crate(&decide.crate_path, &audit_file.scanned_effects, scan_config)?)
+    } else {
+        None
+    };
+    let recorded =
+        decide::apply_decisions(&mut audit_file, &decisions, scan_res.as_ref())?;
+    progress!("Recorded {} decisions", recorded);
+    summary.add_audit(&audit_file);
+    audit_file.save_to_file(audit_file_path)
+}
+
+/// Render SVG badges (e.g. "unsafe: 0", "audited: 100%") from the audit, to
+/// embed in the crate's README
+#[derive(ClapArgs, Debug)]
+struct Badge {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// The badges to render; defaults to all of them
+    #[clap(long, value_enum, value_delimiter = ',')]
+    kind: Vec<BadgeKind>,
+
+    /// Directory to write the badges to, as <kind>.svg
+    #[clap(short, long, default_value = ".")]
+    out_dir: PathBuf,
+}
+
+/// Write a badge for each kind to the output directory
+fn write_badges(audit_file: &AuditFile, cmd: &Badge) -> Result<()> {
+    let kinds = if cmd.kind.is_empty() { &BadgeKind::ALL[..] } else { &cmd.kind };
+    create_dir_all(&cmd.out_dir)?;
+    for kind in kinds {
+        let path = cmd.out_dir.join(format!("{}.svg", kind));
+        std::fs::write(&path, badge::Badge::from_audit(*kind, audit_file).to_svg())
+            .context(format!("Failed to write badge {:?}", path))?;
+        progress!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Show the log of the decisions made in the audit: who made them, when,
+/// and how they changed the annotations
+#[derive(ClapArgs, Debug)]
+struct Log {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// Only show the decisions on the effect with this id (in the current
+    /// audit, as in `audit list`)
+    #[clap(long)]
+    id: Option<usize>,
+
+    /// Only show the decisions of this auditor
+    #[clap(long)]
+    auditor: Option<String>,
+
+    /// Print the entries as JSON lines
+    #[clap(long)]
+    json: bool,
+}
+
+/// Render the audit as a static HTML report: its badges, the effect counts per
+/// crate and effect kind, and each effect with its status and source
+#[derive(ClapArgs, Debug)]
+struct Report {
+    /// path to crate
+    crate_path: PathBuf,
+
+    /// File to write the report to
+    #[clap(short, long, default_value = "report.html")]
+    output: PathBuf,
+
+    /// Leave the source lines of the effects out of the report
+    #[clap(long, default_value_t = false)]
+    no_snippets: bool,
+}
+
+fn write_report(audit_file: &AuditFile, cmd: &Report) -> Result<()> {
+    let crate_id = load_cargo_toml(&cmd.crate_path)?.to_string();
+    let up_to_date = is_audit_scan_valid(audit_file, &cmd.crate_path)?;
+    let mut report = HtmlReport::new(crate_id, audit_file, up_to_date);
+    if cmd.no_snippets {
+        report = report.without_snippets();
+    }
+    std::fs::write(&cmd.output, report.to_html())
+        .context(format!("Failed to write report {:?}", cmd.output))?;
+    progress!("Wrote {}", cmd.output.display());
+    Ok(())
+}
+
+fn print_log(audit_file_path: &Path, cmd: &Log) -> Result<()> {
+    let entries = decision_log::read_log(audit_file_path)?;
+    // Entries refer to effects by identity, which outlives their ids
+    let effect = match cmd.id {
+        Some(id) => {
+            let audit_file = AuditFile::load(audit_file_path)?;
+            let effects = audit_file.base_effects_by_location();
+            let (effect, _) = effects
+                .get(id)
+                .ok_or_else(|| anyhow!("No effect with id {} in the audit", id))?;
+            Some(decision_log::effect_label(effect))
+        }
+        None => None,
+    };
+    let selected = entries.iter().filter(|e| {
+        effect.as_ref().map_or(true, |effect| &e.effect == effect)
+            && cmd.auditor.as_ref().map_or(true, |a| &e.auditor == a)
+    });
+    for entry in selected {
+        if cmd.json {
+            println!("{}", serde_json::to_string(entry)?);
+        } else {
+            println!("{}", entry);
+        }
+    }
+    Ok(())
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
@@ -109,6 +458,19 @@ enum ContinueStatus {
     ExitNow,
 }
 
+// Keeps the decisions of the previous audit for the effects which are still in
+// the crate
+fn carry_over_decisions(
+    audit_file: &mut AuditFile,
+    prev_audit_file: &AuditFile,
+    identity: EffectIdentity,
+) {
+    let carried = audit_file.carry_over_decisions(prev_audit_file, identity);
+    if carried > 0 {
+        println!("Kept the decisions for {} effects from the previous audit", carried);
+    }
+}
+
 // Asks the user how to handle the invalid audit file. If they continue with a
 // new file, will update the audit and audit_path and return Continue;
 // otherwise will return ExitNow.
@@ -124,6 +486,14 @@ where
     // TODO: Colorize
     println!("Crate has changed from last audit");
 
+    let prev_audit_file = audit_file.clone();
+    let identity = match args.effect_identity {
+        Some(identity) => identity,
+        None => ProjectConfig::read(args.crate_path())?
+            .map(|c| c.effect_identity)
+            .unwrap_or_default(),
+    };
+
     if args.overwrite_audit {
         println!("Generating new audit file");
 
@@ -134,11 +504,12 @@ where
                     effect_instance.clone(),
                     EffectTree::Leaf(
                         EffectInfo::from_instance(effect_instance),
-                        SafetyAnnotation::Skipped,
+                        SafetyAnnotation::Unreviewed,
                     ),
                 )
             })
             .collect::<HashMap<_, _>>();
+        carry_over_decisions(audit_file, &prev_audit_file, identity);
         audit_file.hash = hash_dir(audit_file.base_dir.clone())?;
 
         let mut audit_string = audit_file_path
@@ -176,11 +547,12 @@ where
                             effect_instance.clone(),
                             EffectTree::Leaf(
                                 EffectInfo::from_instance(effect_instance),
-                                SafetyAnnotation::Skipped,
+                                SafetyAnnotation::Unreviewed,
                             ),
                         )
                     })
                     .collect::<HashMap<_, _>>();
+                carry_over_decisions(audit_file, &prev_audit_file, identity);
                 audit_file.hash = hash_dir(audit_file.base_dir.clone())?;
 
                 let mut audit_file_string = audit_file_path
@@ -204,7 +576,11 @@ where
     }
 }
 
-fn audit_crate(args: Args, audit_file: Option<AuditFile>) -> Result<()> {
+fn audit_crate(
+    args: Args,
+    audit_file: Option<AuditFile>,
+    summary: &mut Summary,
+) -> Result<()> {
     let scan_res = {
         let relevant_effects = if let Some(p) = &audit_file {
             &p.scanned_effects
@@ -212,8 +588,8 @@ fn audit_crate(args: Args, audit_file: Option<AuditFile>) -> Result<()> {
             &args.effect_types
         };
 
-        println!("Scanning crate...");
-        scanner::scan_crate(&args.crate_path, relevant_effects)?
+        progress!("Scanning crate...");
+        scanner::scan_crate(args.crate_path(), relevant_effects, &args.scan_config)?
     };
     let scan_effects = scan_res.effects_set();
 
@@ -238,7 +614,7 @@ fn audit_crate(args: Args, audit_file: Option<AuditFile>) -> Result<()> {
         .context("Error: should have created a default audit file path by now")?;
     let mut audit_file = match audit_file {
         Some(mut pf) => {
-            if !args.ignore_hash && !is_audit_scan_valid(&pf, args.crate_path.clone())? {
+            if !args.ignore_hash && !is_audit_scan_valid(&pf, args.crate_path())? {
                 // TODO: If the audit file diverges from the effects at all, we
                 //       should enter incremental mode and detect what's changed
                 match handle_invalid_audit_file(
@@ -248,57 +624,141 @@ fn audit_crate(args: Args, audit_file: Option<AuditFile>) -> Result<()> {
                     &args,
                 ) {
                     Ok(ContinueStatus::Continue) => (),
-                    Ok(ContinueStatus::ExitNow) => return Ok(()),
+                    Ok(ContinueStatus::ExitNow) => {
+                        summary.set_reason("crate changed since the last audit");
+                        return Ok(());
+                    }
                     Err(e) => return Err(e),
                 };
             }
-            println!("Loaded audit file");
+            progress!("Loaded audit file");
             pf
         }
         None => {
             // No audit file yet, so make a new one
-            println!("Creating new audit file");
+            progress!("Creating new audit file");
 
             if let Some(parent_dir) = audit_file_path.parent() {
                 create_dir_all(parent_dir)?;
             }
             File::create(audit_file_path.clone())?;
 
-            let mut pf = AuditFile::empty(args.crate_path.clone(), args.effect_types)?;
+            let mut pf = AuditFile::empty(
+                args.crate_path().to_path_buf(),
+                args.effect_types.clone(),
+            )?;
             pf.set_base_audit_trees(scan_effects);
             pf
         }
     };
 
-    if start_audit(&mut audit_file, scan_res, &args.config)?.is_some() {
+    let mut config = args.config.clone();
+    config.load_project(args.crate_path())?;
+    if start_audit(&mut audit_file, scan_res, &[], &config)?.is_some() {
         // The user marked that they want to audit a child effect, but we aren't
         // able to do so in this mode.
         return Err(anyhow!("Can't audit dependency crate effects in this binary"));
     }
 
-    audit_file.print_audit_stats();
+    summary.add_audit(&audit_file);
+    if audit_file.unaudited_effects().1 > 0 {
+        summary.set_reason("exited with unaudited effects");
+    }
+    if !args.verbosity.quiet {
+        audit_file.print_audit_stats();
+        println!();
+    }
 
-    println!();
-    println!("Saving audit to file");
+    progress!("Saving audit to file");
     audit_file.save_to_file(audit_file_path)?;
 
+    if let Some(db_path) = &args.db {
+        progress!("Saving audit to database");
+        let mut db = ScanDb::open(db_path)?;
+        db.insert_audit(&audit_file)?;
+    }
+
     Ok(())
 }
 
-fn runner(args: Args) -> Result<()> {
+/// Archive the entries of the audit file whose effects aren't in the latest
+/// scan of the crate anymore. Refuses if the scan hit its budget: the effects
+/// it didn't get to would look gone.
+fn gc_audit(
+    mut audit_file: AuditFile,
+    audit_file_path: PathBuf,
+    crate_path: &Path,
+    gc: &Gc,
+    scan_config: &ScanConfig,
+    summary: &mut Summary,
+) -> Result<()> {
+    progress!("Scanning crate...");
+    let scan_res = scan_crate(crate_path, &audit_file.scanned_effects, scan_config)?;
+    if let Some(limit) = scan_res.scan_limit {
+        return Err(anyhow!(
+            "The scan hit the {}, so missing effects may still be in the crate; \
+            raise the scan budget to clean up the audit",
+            limit
+        ));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let retention = Duration::from_secs(gc.retention_days * 24 * 60 * 60);
+    let archived = audit_file.gc(&scan_res.effects_set(), retention, now);
+    progress!(
+        "Archived {} entries ({} more missing, within the retention period)",
+        archived,
+        audit_file.stale_since.len()
+    );
+    summary.add_audit(&audit_file);
+    audit_file.save_to_file(audit_file_path)
+}
+
+fn runner(mut args: Args, summary: &mut Summary) -> Result<()> {
+    args.scan_config.metrics.init()?;
+    let root = args.crate_path().to_path_buf();
+    args.scan_config.load_project(&root)?;
     let audit_file_path = args
         .audit_file_path
         .clone()
         .context("Error: should have created a default audit file path already")?;
+    if let Some(Command::Analytics(_)) = &args.command {
+        let analytics = analyze_store(&audit_file_path)?;
+        if !args.verbosity.quiet {
+            analytics.print();
+        }
+        return Ok(());
+    }
+    if let Some(Command::Log(cmd)) = &args.command {
+        return print_log(&audit_file_path, cmd);
+    }
     let audit_file = AuditFile::read_audit_file(audit_file_path.clone())?;
 
-    if args.preview {
-        println!("Previewing crate effects.");
-        println!("Scanning crate...");
-
-        let res = scan_crate(&args.crate_path, &args.effect_types)?;
-        for effect in res.effects {
-            println!("{}", effect.to_csv());
+    if let Some(Command::Gc(gc)) = &args.command {
+        let af =
+            audit_file.ok_or_else(|| anyhow!("Audit file to clean up doesn't exist"))?;
+        gc_audit(af, audit_file_path, args.crate_path(), gc, &args.scan_config, summary)
+    } else if let Some(Command::Decide(decide)) = &args.command {
+        let af =
+            audit_file.ok_or_else(|| anyhow!("Audit file to decide in doesn't exist"))?;
+        decide_audit(af, audit_file_path, decide, &args.scan_config, summary)
+    } else if let Some(Command::Badge(cmd)) = &args.command {
+        let af = audit_file.ok_or_else(|| anyhow!("Audit file doesn't exist"))?;
+        summary.add_audit(&af);
+        write_badges(&af, cmd)
+    } else if let Some(Command::Report(cmd)) = &args.command {
+        let af = audit_file.ok_or_else(|| anyhow!("Audit file doesn't exist"))?;
+        summary.add_audit(&af);
+        write_report(&af, cmd)
+    } else if args.preview {
+        progress!("Previewing crate effects.");
+        progress!("Scanning crate...");
+
+        let res = scan_crate(args.crate_path(), &args.effect_types, &args.scan_config)?;
+        summary.add_effects(&res.effects);
+        if !args.verbosity.quiet {
+            for effect in res.effects {
+                println!("{}", effect.to_csv());
+            }
         }
         Ok(())
     } else if args.reset_annotation {
@@ -306,12 +766,55 @@ fn runner(args: Args) -> Result<()> {
             None => Err(anyhow!("Audit file doesn't exist")),
             Some(pf) => reset_annotation(pf, audit_file_path),
         }
+    } else if let Some(Command::List(cmd)) = &args.command {
+        let af = audit_file.ok_or_else(|| anyhow!("Audit file to list doesn't exist"))?;
+        summary.add_audit(&af);
+        list_effects(&af, cmd.with_refs);
+        Ok(())
+    } else if let Some(Command::AddRef(cmd)) = &args.command {
+        let af = audit_file.ok_or_else(|| anyhow!("Audit file doesn't exist"))?;
+        add_ref(af, audit_file_path, cmd)
+    } else if let Some(patch_path) = &args.export_patch {
+        let af =
+            audit_file.ok_or_else(|| anyhow!("Audit file to export doesn't exist"))?;
+        summary.add_audit(&af);
+        std::fs::write(patch_path, patch::export_patch(&af, args.crate_path())?)?;
+        progress!("Exported the audit decisions to {:?}", patch_path);
+        Ok(())
+    } else if let Some(graph_path) = &args.export_chains {
+        let af =
+            audit_file.ok_or_else(|| anyhow!("Audit file to export doesn't exist"))?;
+        summary.add_audit(&af);
+        let graph = ChainGraph::from_audit_file(&af);
+        std::fs::write(graph_path, graph.render(GraphFormat::from_path(graph_path)))?;
+        progress!("Exported the effect chains to {:?}", graph_path);
+        Ok(())
+    } else if let Some(patch_path) = &args.import_patch {
+        let mut af = audit_file
+            .ok_or_else(|| anyhow!("Audit file to import into doesn't exist"))?;
+        let patch = std::fs::read_to_string(patch_path)
+            .context(format!("Failed to read patch {:?}", patch_path))?;
+        let changed = patch::import_patch(&mut af, args.crate_path(), &patch)?;
+        progress!("Imported {} changed decisions from {:?}", changed, patch_path);
+        summary.add_audit(&af);
+        af.save_to_file(audit_file_path)
     } else if args.review {
         match audit_file {
             None => Err(anyhow!("Audit file to review doesn't exist")),
             Some(af) => {
+                summary.add_audit(&af);
+                if let Some(template) = &args.template {
+                    let context = AuditContext::new(args.crate_path(), &af);
+                    print!("{}", template::render_file(template, context)?);
+                    return Ok(());
+                }
                 match args.review_info {
-                    ReviewInfo::All => review_audit(&af, &args.crate_path, &args.config),
+                    ReviewInfo::All => review_audit(
+                        &af,
+                        args.crate_path(),
+                        &args.config,
+                        &args.scan_config,
+                    ),
                     ReviewInfo::PubFuns => {
                         println!("Public functions marked caller-checked:");
                         for pub_fn in af.pub_caller_checked.keys() {
@@ -324,31 +827,36 @@ fn runner(args: Args) -> Result<()> {
             }
         }
     } else {
-        audit_crate(args, audit_file)
+        audit_crate(args, audit_file, summary)
     }
 }
 
 fn main() {
-    cargo_scan::util::init_logging();
     let mut args = Args::parse();
+    init_logging(&args.verbosity);
+    args.effect_types = EffectSelector::expand(&args.effect_selection);
     if args.audit_file_path.is_none() {
         if let Some(mut p) = home_dir() {
             p.push(".cargo_audits");
-            if let Ok(crate_id) = load_cargo_toml(&args.crate_path) {
+            if let Ok(crate_id) = load_cargo_toml(args.crate_path()) {
                 p.push(format!("{}.audit", crate_id));
             } else {
                 println!("Error: Couldn't load the Cargo.toml at the crate path");
                 return;
             }
             args.audit_file_path = Some(p);
+        } else {
+            println!("Error: couldn't find the home directory (required for default audit file path)");
+            return;
         }
-    } else {
-        println!("Error: couldn't find the home directory (required for default audit file path)");
-        return;
     }
 
-    match runner(args) {
-        Ok(_) => (),
-        Err(e) => println!("Error: {:?}", e),
+    let quiet = args.verbosity.quiet;
+    let mut summary = Summary::new("audit");
+    let res = runner(args, &mut summary);
+    summary.print(&res);
+    match res {
+        Err(e) if !quiet => println!("Error: {:?}", e),
+        _ => (),
     };
 }
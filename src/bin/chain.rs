@@ -1,16 +1,25 @@
 use cargo_scan::audit_chain::{create_new_audit_chain, AuditChain, Create};
 use cargo_scan::audit_file::AuditFile;
 use cargo_scan::auditing::audit::{audit_pub_fn, start_audit};
+use cargo_scan::auditing::check::check_chain;
 use cargo_scan::auditing::info::Config as AuditConfig;
 use cargo_scan::auditing::review::review_audit;
-use cargo_scan::effect::Effect;
+use cargo_scan::dep_tree::DepTree;
+use cargo_scan::effect::{Effect, Severity};
+use cargo_scan::osv;
+use cargo_scan::progress;
+use cargo_scan::report::{self, RepoSummary, Report, TOP_CALLEES};
+use cargo_scan::sbom::Sbom;
+use cargo_scan::scanner::ScanConfig;
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
 use cargo_scan::{download_crate, scanner};
 
 use anyhow::{anyhow, Context, Result};
 use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use std::collections::HashSet;
 use std::fs::create_dir_all;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug, Clone)]
 struct OuterArgs {
@@ -18,6 +27,26 @@ struct OuterArgs {
     /// Path to download crates to for auditing
     #[clap(short = 'd', long = "crate-download-path", default_value = ".audit_crates")]
     crate_download_path: String,
+
+    #[clap(flatten)]
+    scan_config: ScanConfig,
+
+    /// Number of crates to scan in parallel when creating a chain (by
+    /// default, one per CPU); crates are scanned once their dependencies are
+    #[clap(short = 'j', long)]
+    jobs: Option<usize>,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+impl OuterArgs {
+    /// The scan options, with the settings of the root crate's project
+    fn project_scan_config(&self, root: &Path) -> Result<ScanConfig> {
+        let mut config = self.scan_config.clone();
+        config.load_project(root)?;
+        Ok(config)
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -34,6 +63,15 @@ enum Command {
     Create(Create),
     Review(Review),
     Audit(Audit),
+    Report(ReportArgs),
+    MergeReports(MergeReports),
+    TopCallees(TopCallees),
+    Sbom(SbomArgs),
+    Osv(OsvArgs),
+    Check(Check),
+    Trust(Trust),
+    TrustVersions(TrustVersions),
+    Tree(Tree),
 }
 
 trait CommandRunner {
@@ -46,14 +84,25 @@ impl CommandRunner for Command {
             Self::Create(create) => create.run_command(args),
             Self::Review(review) => review.run_command(args),
             Self::Audit(audit) => audit.run_command(args),
+            Self::Report(report) => report.run_command(args),
+            Self::MergeReports(merge) => merge.run_command(args),
+            Self::TopCallees(top) => top.run_command(args),
+            Self::Sbom(sbom) => sbom.run_command(args),
+            Self::Osv(osv) => osv.run_command(args),
+            Self::Check(check) => check.run_command(args),
+            Self::Trust(trust) => trust.run_command(args),
+            Self::TrustVersions(trust) => trust.run_command(args),
+            Self::Tree(tree) => tree.run_command(args),
         }
     }
 }
 
 impl CommandRunner for Create {
     fn run_command(self, args: OuterArgs) -> Result<()> {
-        if let (Some(crate_name), Some(crate_version)) =
-            (&self.download_root_crate, &self.download_version)
+        // When resuming, the root crate has already been downloaded
+        let downloaded = self.resume && PathBuf::from(&self.crate_path).exists();
+        if let (Some(crate_name), Some(crate_version), false) =
+            (&self.download_root_crate, &self.download_version, downloaded)
         {
             let crate_path = PathBuf::from(self.crate_path.clone());
             if crate_path.exists() {
@@ -89,7 +138,8 @@ impl CommandRunner for Create {
             std::fs::rename(&tmp_path, &self.crate_path)?;
         }
 
-        let chain = create_new_audit_chain(self, &args.crate_download_path)?;
+        let config = args.project_scan_config(Path::new(&self.crate_path))?;
+        let chain = create_new_audit_chain(self, &args.crate_download_path, &config)?;
         chain.save_to_file()?;
         Ok(())
     }
@@ -148,6 +198,7 @@ impl CommandRunner for Review {
             return Ok(());
         }
 
+        let scan_config = args.project_scan_config(chain.crate_path())?;
         let crates_to_review = match self.review_target {
             None => chain.all_crates().into_iter().cloned().collect::<Vec<_>>(),
             Some(crate_name) => chain.matching_crates_no_version(&crate_name),
@@ -163,7 +214,12 @@ impl CommandRunner for Review {
             })?;
             let mut crate_path = PathBuf::from(&args.crate_download_path);
             crate_path.push(format!("{}", review_crate));
-            review_crate_audit_file(&audit_file, crate_path, self.review_info)?;
+            review_crate_audit_file(
+                &audit_file,
+                crate_path,
+                self.review_info,
+                &scan_config,
+            )?;
         }
         Ok(())
     }
@@ -179,11 +235,21 @@ struct Audit {
     manifest_path: String,
     /// Name of the crate to review (defaults to the root crate if none is provided)
     crate_name: Option<String>,
+    /// Print effects as plain text descriptions (for screen readers)
+    #[clap(long, default_value_t = false)]
+    plain: bool,
+    /// Offer to mark each group of similar effects with one decision first
+    #[clap(long, default_value_t = false)]
+    cluster: bool,
+    /// Audit the effects in order of location, instead of the effects whose
+    /// pending callers have the most callers (fan-in) first
+    #[clap(long, default_value_t = false)]
+    by_location: bool,
 }
 
 // TODO: print more info during auding (e.g. saving files)
 impl CommandRunner for Audit {
-    fn run_command(self, _args: OuterArgs) -> Result<()> {
+    fn run_command(self, args: OuterArgs) -> Result<()> {
         match AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path)) {
             Ok(Some(mut chain)) => {
                 let crate_id = match self.crate_name {
@@ -193,6 +259,7 @@ impl CommandRunner for Audit {
                     None => chain.root_crate()?,
                 };
 
+                let scan_config = args.project_scan_config(chain.crate_path())?;
                 // TODO: Handle more than one audit matching a crate
                 if let Some(orig_audit_file) = chain.read_audit_file(&crate_id)? {
                     let mut new_audit_file = orig_audit_file.clone();
@@ -200,19 +267,31 @@ impl CommandRunner for Audit {
 
                     // Iterate through the crate's dependencies and add the
                     // public functions to the scan sinks
-                    let scan_res = scanner::scan_crate(
-                        &crate_path,
-                        &orig_audit_file.scanned_effects,
-                    )?;
+                    let scan_res = orig_audit_file.rescan(&crate_path, &scan_config)?;
 
                     let mut audit_config = AuditConfig::default();
+                    audit_config.load_project(chain.crate_path())?;
                     audit_config.allow_effect_origin = true;
+                    audit_config.plain = self.plain;
+                    audit_config.cluster = self.cluster;
+                    audit_config.fan_in_order = !self.by_location;
+                    // Calls from the crates depending on this one count toward
+                    // the fan-in of its public functions
+                    let downstream = if audit_config.fan_in_order {
+                        chain.scan_dependents(&crate_id, &scan_config)?
+                    } else {
+                        Vec::new()
+                    };
 
                     // TODO: Mechanism for re-auditing the default policies
                     // NOTE: audit_res will contain an EffectBlock if the user
                     //       needs to audit a child package's effects
-                    let audit_res =
-                        start_audit(&mut new_audit_file, scan_res, &audit_config);
+                    let audit_res = start_audit(
+                        &mut new_audit_file,
+                        scan_res,
+                        &downstream,
+                        &audit_config,
+                    );
                     // Save the audit immediately after audit so we don't error
                     // out and forget to save
                     chain.save_audit_file(&crate_id, &new_audit_file)?;
@@ -220,9 +299,12 @@ impl CommandRunner for Audit {
                         // TODO: Print parents of an effect the user audits when
                         //       auditing children
                         match dep_effect.eff_type() {
-                            Effect::SinkCall(sink_ident) => {
-                                audit_pub_fn(&mut chain, sink_ident, &audit_config)?
-                            }
+                            Effect::SinkCall(sink_ident) => audit_pub_fn(
+                                &mut chain,
+                                sink_ident,
+                                &audit_config,
+                                &scan_config,
+                            )?,
                             _ => {
                                 return Err(anyhow!(
                                     "Can only audit dependency effects for sinks"
@@ -253,13 +335,288 @@ impl CommandRunner for Audit {
     }
 }
 
+/// Export a dashboard summary of all the audits in a chain
+#[derive(Clone, ClapArgs, Debug)]
+struct ReportArgs {
+    /// Path to manifest
+    manifest_path: String,
+    /// Path to save the report to
+    output_path: String,
+    /// Name of the repository in the report (defaults to the root crate)
+    #[clap(short, long)]
+    repo: Option<String>,
+}
+
+impl CommandRunner for ReportArgs {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+        let repo = match self.repo {
+            Some(repo) => repo,
+            None => chain.root_crate()?.crate_name,
+        };
+
+        let summary = RepoSummary::from_audit_chain(repo, &mut chain)?;
+        Report::new(vec![summary]).save_to_file(&PathBuf::from(&self.output_path))
+    }
+}
+
+/// Combine several reports into a single dataset
+#[derive(Clone, ClapArgs, Debug)]
+struct MergeReports {
+    /// Path to save the merged report to
+    output_path: String,
+    /// Reports to merge; later reports take precedence for the same repository
+    #[clap(required = true)]
+    report_paths: Vec<String>,
+}
+
+impl CommandRunner for MergeReports {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut merged = Report::default();
+        for path in &self.report_paths {
+            let report = Report::read_report(&PathBuf::from(path))
+                .context(format!("Couldn't read report {}", path))?;
+            merged.merge(report);
+        }
+        merged.save_to_file(&PathBuf::from(&self.output_path))
+    }
+}
+
+/// List the callees flagged most often across the crates in a chain, with
+/// their effect counts and crates
+#[derive(Clone, ClapArgs, Debug)]
+struct TopCallees {
+    /// Path to manifest
+    manifest_path: String,
+    /// Number of callees to list
+    #[clap(short, long, default_value_t = TOP_CALLEES)]
+    n: usize,
+}
+
+impl CommandRunner for TopCallees {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+        let top = report::top_callees_in_chain(&mut chain, self.n)?;
+        println!("{:>6} {:>10}  callee (crates)", "count", "unaudited");
+        for callee in top {
+            println!("{}", callee);
+        }
+        Ok(())
+    }
+}
+
+/// Export a CycloneDX SBOM of the crates in a chain, with their effect counts,
+/// audit status, and risk score as properties
+#[derive(Clone, ClapArgs, Debug)]
+struct SbomArgs {
+    /// Path to manifest
+    manifest_path: String,
+    /// Path to save the SBOM to
+    output_path: String,
+}
+
+impl CommandRunner for SbomArgs {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+        Sbom::from_audit_chain(&mut chain)?
+            .save_to_file(&PathBuf::from(&self.output_path))
+    }
+}
+
+/// Export the findings of a chain (effects marked unsafe, and optionally
+/// unaudited effects) as a JSON list of OSV entries
+#[derive(Clone, ClapArgs, Debug)]
+struct OsvArgs {
+    /// Path to manifest
+    manifest_path: String,
+    /// Path to save the entries to
+    output_path: String,
+    /// Also export the unaudited effects of at least this severity (Low,
+    /// Medium, High, Critical)
+    #[clap(long)]
+    min_severity: Option<Severity>,
+}
+
+impl CommandRunner for OsvArgs {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+        let entries = osv::from_audit_chain(&mut chain, self.min_severity)?;
+        osv::save_to_file(&entries, &PathBuf::from(&self.output_path))?;
+        progress!("Exported {} findings to {}", entries.len(), self.output_path);
+        Ok(())
+    }
+}
+
+/// Check that no effect in the chain has been marked unsafe, that the crates
+/// meet the required audit coverage, and that their sources haven't changed
+/// since they were audited, failing otherwise so that the check can block
+/// the build
+#[derive(Clone, ClapArgs, Debug)]
+struct Check {
+    /// Path to manifest
+    manifest_path: String,
+    /// Minimum percentage of audited locations in each crate, overriding
+    /// `min_audit_coverage` in the manifest
+    #[clap(long)]
+    min_audit_coverage: Option<f64>,
+}
+
+impl CommandRunner for Check {
+    fn run_command(self, args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+
+        let mut coverage = chain.coverage_requirements().clone();
+        if let Some(min) = self.min_audit_coverage {
+            coverage.min_audit_coverage = Some(min);
+        }
+
+        let result = check_chain(&mut chain, &coverage)?;
+        if !args.verbosity.quiet {
+            result.print_report();
+        }
+        if result.passed() {
+            Ok(())
+        } else {
+            Err(anyhow!("Audit check failed for {}", chain.root_crate()?))
+        }
+    }
+}
+
+/// Trust crates as safe wrappers (e.g. flate2 for libz), so that the chain
+/// stops at them instead of passing the effects of their public functions on
+/// to every crate using them
+#[derive(Clone, ClapArgs, Debug)]
+struct Trust {
+    /// Path to manifest
+    manifest_path: String,
+    /// Names of the wrapper crates
+    #[clap(required = true)]
+    crate_names: Vec<String>,
+    /// Stop trusting the crates instead. The effects already removed from the
+    /// crates depending on them are only restored by creating the chain again.
+    #[clap(long, default_value_t = false)]
+    remove: bool,
+}
+
+impl CommandRunner for Trust {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+
+        for crate_name in &self.crate_names {
+            if self.remove {
+                if chain.untrust_wrapper(crate_name) {
+                    progress!("{} is no longer trusted", crate_name);
+                } else {
+                    progress!("{} wasn't trusted", crate_name);
+                }
+            } else {
+                let removed = chain.trust_wrapper(crate_name)?;
+                progress!(
+                    "Trusted {} as a wrapper; removed {} effects from crates using it",
+                    crate_name,
+                    removed
+                );
+            }
+        }
+        chain.save_to_file()
+    }
+}
+
+/// Keep the audit of a crate valid for a range of its versions (e.g. `1` for
+/// every 1.x release), as long as its effects are unchanged, so that `check`
+/// passes for new releases without auditing them again
+#[derive(Clone, ClapArgs, Debug)]
+struct TrustVersions {
+    /// Path to manifest
+    manifest_path: String,
+    /// Name of the crate
+    crate_name: String,
+    /// Semver requirement for the trusted versions, e.g. `1` or `~0.2.150`;
+    /// the latest version of the crate in the chain which matches it must be
+    /// fully audited
+    versions: String,
+    /// Only trust the effects calling this callee (e.g. `libc::getuid`)
+    #[clap(long, conflicts_with = "remove")]
+    callee: Option<String>,
+    /// Remove the crate's trusts for these versions instead
+    #[clap(long, default_value_t = false)]
+    remove: bool,
+}
+
+impl CommandRunner for TrustVersions {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+
+        if self.remove {
+            let removed = chain.untrust_versions(&self.crate_name, &self.versions);
+            progress!("Removed {} version trusts", removed);
+        } else {
+            let trust =
+                chain.trust_versions(&self.crate_name, &self.versions, self.callee)?;
+            progress!("Trusted {}", trust);
+        }
+        chain.save_to_file()
+    }
+}
+
+/// Print the dependency tree of a chain like `cargo tree`, with the effect
+/// count and audit status of each crate
+#[derive(Clone, ClapArgs, Debug)]
+struct Tree {
+    /// Path to manifest
+    manifest_path: String,
+    /// Print the crates depending on this crate (`<crate>` or
+    /// `<crate>@<version>`) instead, i.e. the paths pulling it in
+    #[clap(short, long)]
+    invert: Option<String>,
+}
+
+impl CommandRunner for Tree {
+    fn run_command(self, _args: OuterArgs) -> Result<()> {
+        let mut chain = AuditChain::read_audit_chain(PathBuf::from(&self.manifest_path))?
+            .ok_or_else(|| {
+                anyhow!("Couldn't find audit chain manifest at {}", &self.manifest_path)
+            })?;
+        let tree = DepTree::from_audit_chain(&mut chain)?;
+        match &self.invert {
+            Some(spec) => print!("{}", tree.render_inverted(spec)?),
+            None => print!("{}", tree.render()),
+        }
+        Ok(())
+    }
+}
+
 fn review_crate_audit_file(
     audit_file: &AuditFile,
     crate_path: PathBuf,
     review_type: ReviewInfo,
+    scan_config: &ScanConfig,
 ) -> Result<()> {
     match review_type {
-        ReviewInfo::All => review_audit(audit_file, &crate_path, &AuditConfig::default()),
+        ReviewInfo::All => {
+            review_audit(audit_file, &crate_path, &AuditConfig::default(), scan_config)
+        }
         ReviewInfo::PubFuns => {
             println!("Public functions marked caller-checked:");
             for pub_fn in audit_file.pub_caller_checked.keys() {
@@ -276,11 +633,32 @@ fn review_crate_audit_file(
 }
 
 fn main() {
-    cargo_scan::util::init_logging();
     let args = Args::parse();
+    init_logging(&args.outer_args.verbosity);
+    let quiet = args.outer_args.verbosity.quiet;
+
+    if let Some(jobs) = args.outer_args.jobs {
+        if let Err(e) = scanner::set_scan_jobs(jobs) {
+            println!("Error setting scan jobs: {}", e);
+            std::process::exit(1);
+        }
+    }
 
-    match args.command.run_command(args.outer_args) {
-        Ok(()) => (),
-        Err(e) => println!("Error running command: {}", e),
+    let metrics = args.outer_args.scan_config.metrics.clone();
+    if let Err(e) = metrics.init() {
+        println!("Error exporting metrics: {}", e);
+        std::process::exit(1);
+    }
+
+    let res = args.command.run_command(args.outer_args);
+    if let Err(e) = metrics.write_file() {
+        eprintln!("Warning: {}", e);
+    }
+    Summary::new("chain").print(&res);
+    if let Err(e) = res {
+        if !quiet {
+            println!("Error running command: {}", e);
+        }
+        std::process::exit(1);
     }
 }
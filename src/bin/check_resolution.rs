@@ -3,7 +3,9 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
-use cargo_scan::{effect::SrcLoc, ident::Ident, name_resolution::Resolver};
+use cargo_scan::resolve::ResolverBackend;
+use cargo_scan::util::{init_logging, Verbosity};
+use cargo_scan::{effect::SrcLoc, ident::Ident};
 use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -19,13 +21,20 @@ struct Args {
     resolve_type: bool,
     #[arg(short, long, default_value = "src/main.rs")]
     file: PathBuf,
+
+    /// Name resolution backend to check
+    #[arg(long, value_enum, default_value_t = ResolverBackend::RustAnalyzer)]
+    resolver: ResolverBackend,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
 }
 
 pub fn main() -> Result<()> {
-    cargo_scan::util::init_logging();
     let args = Args::parse();
+    init_logging(&args.verbosity);
 
-    let res = Resolver::new(&args.crate_path).unwrap();
+    let res = args.resolver.load(&args.crate_path).unwrap();
     let mut filepath = std::path::PathBuf::from(&args.crate_path);
     filepath.push(&args.file);
 
@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+use cargo_scan::scanner::ScanConfig;
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
 use cargo_scan::{audit_file::AuditFile, effect::EffectType};
 
 use anyhow::{anyhow, Result};
@@ -18,9 +21,12 @@ struct Args {
     /// Ovewrite the audit file if a new version of the crate is detected
     #[clap(short = 'o', long = "overwrite-audit", default_value_t = false)]
     overwrite_audit: bool,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
 }
 
-fn runner(args: Args) -> Result<()> {
+fn runner(args: Args, summary: &mut Summary) -> Result<()> {
     if args.audit_file_path.is_dir() {
         return Err(anyhow!("Audit path is a directory"));
     }
@@ -32,7 +38,9 @@ fn runner(args: Args) -> Result<()> {
     let audit_file = AuditFile::new_caller_checked_default(
         &args.crate_path,
         &EffectType::unsafe_effects(),
+        &ScanConfig::default(),
     )?;
+    summary.add_audit(&audit_file);
 
     audit_file.save_to_file(args.audit_file_path)?;
 
@@ -40,10 +48,15 @@ fn runner(args: Args) -> Result<()> {
 }
 
 fn main() {
-    cargo_scan::util::init_logging();
     let args = Args::parse();
-
-    match runner(args) {
+    init_logging(&args.verbosity);
+    let quiet = args.verbosity.quiet;
+
+    let mut summary = Summary::new("default_audit");
+    let res = runner(args, &mut summary);
+    summary.print(&res);
+    match res {
+        _ if quiet => (),
         Ok(_) => println!("Created new default audit"),
         Err(e) => println!("Error: {:?}", e),
     };
@@ -0,0 +1,200 @@
+/*
+    Set up cargo-scan for a crate: write the project config (cargo-scan.toml),
+    create the audit chain with default audits for every dependency, and save
+    a baseline scan of the crate.
+
+    Settings which aren't given as flags are prompted for, unless running
+    with --ci.
+*/
+
+use cargo_scan::artifact::{EffectContext, ScanArtifact};
+use cargo_scan::audit_chain::{create_new_audit_chain, Create};
+use cargo_scan::effect::{EffectSelector, Severity};
+use cargo_scan::progress;
+use cargo_scan::project::ProjectConfig;
+use cargo_scan::scanner::{self, ScanConfig};
+use cargo_scan::summary::Summary;
+use cargo_scan::taxonomy::CustomEffects;
+use cargo_scan::util::{init_logging, Verbosity};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use inquire::{validator::Validation, Text};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Set up cargo-scan for a crate.", long_about = None)]
+struct Args {
+    /// Path to the crate
+    #[clap(default_value = ".")]
+    crate_path: PathBuf,
+
+    /// Directory for the audit chain and baseline, relative to the crate
+    #[clap(long)]
+    audit_dir: Option<PathBuf>,
+
+    /// The types of Effects to track, or families of them (default, ffi,
+    /// unsafe, sinks, raw-pointer, malware, os-hooks)
+    #[clap(long = "effect-types", value_parser, num_args = 1.., value_delimiter = ',')]
+    effect_selection: Option<Vec<EffectSelector>>,
+
+    /// Effects of this severity or higher must be fully audited
+    #[clap(long)]
+    severity_threshold: Option<Severity>,
+
+    /// Required percentage of audited locations in each crate
+    #[clap(long)]
+    min_audit_coverage: Option<f64>,
+
+    /// TOML file defining additional effect kinds (see `taxonomy`)
+    #[clap(long)]
+    custom_effects: Option<PathBuf>,
+
+    /// Set up for CI: don't prompt, use the defaults for any settings not
+    /// given as flags
+    #[clap(long, default_value_t = false)]
+    ci: bool,
+
+    /// Overwrite an existing project config
+    #[clap(short, long, default_value_t = false)]
+    force: bool,
+
+    /// Only write the config and baseline, without creating the audit chain
+    #[clap(long, default_value_t = false)]
+    skip_chain: bool,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+/// Prompt for a setting, unless running in CI mode
+fn prompt<T>(ci: bool, message: &str, default: &str) -> Result<T>
+where
+    T: FromStr + 'static,
+{
+    if ci {
+        return default
+            .parse()
+            .map_err(|_| anyhow!("Invalid default for setting: {}", default));
+    }
+    let ans = Text::new(message)
+        .with_default(default)
+        .with_validator(|x: &str| match x.parse::<T>() {
+            Ok(_) => Ok(Validation::Valid),
+            Err(_) => Ok(Validation::Invalid("Invalid input".into())),
+        })
+        .prompt()?;
+    ans.parse().map_err(|_| anyhow!("Invalid input: {}", ans))
+}
+
+fn make_config(args: &Args) -> Result<ProjectConfig> {
+    let audit_dir = match &args.audit_dir {
+        Some(dir) => dir.clone(),
+        None => prompt(args.ci, "Audit directory:", ".cargo-scan")?,
+    };
+    let effect_selection = match &args.effect_selection {
+        Some(selection) => selection.clone(),
+        None => {
+            let selection: String = prompt(
+                args.ci,
+                "Effect types to track (comma-separated; default, ffi, unsafe, sinks, \
+                raw-pointer, malware, os-hooks, or effect types):",
+                "default",
+            )?;
+            selection
+                .split(',')
+                .map(|s| s.trim().parse::<EffectSelector>().map_err(|e| anyhow!(e)))
+                .collect::<Result<Vec<_>>>()?
+        }
+    };
+    let severity_threshold = match args.severity_threshold {
+        Some(severity) => severity,
+        None => prompt(
+            args.ci,
+            "Severity which must always be fully audited (Low, Medium, High, Critical):",
+            "High",
+        )?,
+    };
+    let min_audit_coverage = match (args.min_audit_coverage, args.ci) {
+        (Some(coverage), _) => Some(coverage),
+        (None, true) => None,
+        (None, false) => {
+            let coverage: f64 = prompt(
+                false,
+                "Minimum percentage of audited locations in each crate (0 for none):",
+                "0",
+            )?;
+            Some(coverage).filter(|c| *c > 0.0)
+        }
+    };
+
+    let config = ProjectConfig {
+        audit_dir,
+        effect_types: EffectSelector::expand(&effect_selection),
+        severity_threshold,
+        min_audit_coverage,
+        ci: args.ci,
+        custom_effects: args.custom_effects.clone(),
+        effect_identity: Default::default(),
+        exclude: Vec::new(),
+        keys: Default::default(),
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+fn runner(args: Args, summary: &mut Summary) -> Result<()> {
+    let crate_path = &args.crate_path;
+    if ProjectConfig::read(crate_path)?.is_some() && !args.force {
+        return Err(anyhow!(
+            "Project config already exists at {:?} (use --force to overwrite)",
+            ProjectConfig::config_path(crate_path)
+        ));
+    }
+
+    let config = make_config(&args)?;
+    let custom_effects = config.custom_effects.as_deref().map(CustomEffects::load);
+    let mut scan_config =
+        ScanConfig { custom_effects: custom_effects.transpose()?, ..Default::default() };
+    scan_config.load_project(crate_path)?;
+    std::fs::create_dir_all(crate_path.join(&config.audit_dir))?;
+
+    if !args.skip_chain {
+        let create = Create::new(
+            crate_path.to_string_lossy().to_string(),
+            config.manifest_path(crate_path).to_string_lossy().to_string(),
+            config.audits_path(crate_path).to_string_lossy().to_string(),
+            args.force,
+            None,
+            None,
+            config.effect_types.clone(),
+        );
+        let crates_path = config.crates_path(crate_path);
+        let mut chain =
+            create_new_audit_chain(create, &crates_path.to_string_lossy(), &scan_config)?;
+        chain.set_coverage_requirements(config.coverage_requirements());
+        chain.save_to_file()?;
+    }
+
+    progress!("Saving baseline scan");
+    let results = scanner::scan_crate(crate_path, &config.effect_types, &scan_config)?;
+    summary.add_effects(&results.effects);
+    ScanArtifact::from_results(&results, EffectContext::FileHash)
+        .save_to_file(&config.baseline_path(crate_path), None)?;
+
+    config.save(crate_path)?;
+    progress!("Saved project config to {:?}", ProjectConfig::config_path(crate_path));
+    summary.set_reason("initialized");
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args.verbosity);
+    let mut summary = Summary::new("init");
+    let res = runner(args, &mut summary);
+    summary.print(&res);
+    res
+}
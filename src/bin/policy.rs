@@ -0,0 +1,215 @@
+/*
+    Review policy changes before applying them.
+
+    `policy simulate --with new-sinks.toml` re-evaluates the effects in a saved
+    scan artifact (by default, the baseline saved by `init`) against the
+    policy, and prints the effects whose classification or severity would
+    change, without re-scanning the crate. See policy.rs for the policy format.
+
+    `policy sinks list` shows the sink patterns scans look for, and `policy
+    sinks add/remove <pattern>` edits the project's sink file. See sink.rs.
+*/
+
+use cargo_scan::artifact::ScanArtifact;
+use cargo_scan::ident::Pattern;
+use cargo_scan::policy::{simulate, Policy};
+use cargo_scan::project::ProjectConfig;
+use cargo_scan::scanner::ScanConfig;
+use cargo_scan::sink::{self, Sink, SinkFile};
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
+
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Simulate(Box<Simulate>),
+    Sinks(Sinks),
+}
+
+/// Show how a policy would change the classification of the effects from the
+/// last scan
+#[derive(ClapArgs, Debug)]
+struct Simulate {
+    /// Policy file with the sink patterns and custom effect kinds to simulate
+    #[clap(long = "with")]
+    policy_path: PathBuf,
+
+    /// Scan artifact to re-evaluate (defaults to the baseline in the project
+    /// config)
+    #[clap(long)]
+    artifact: Option<PathBuf>,
+
+    /// Path to the crate with the project config
+    #[clap(long, default_value = ".")]
+    crate_path: PathBuf,
+
+    /// The options the artifact was scanned with, for its sinks
+    #[clap(flatten)]
+    scan_config: ScanConfig,
+}
+
+impl Simulate {
+    fn artifact_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.artifact {
+            return Ok(path.clone());
+        }
+        let config = ProjectConfig::read(&self.crate_path)?.ok_or_else(|| {
+            anyhow!(
+                "No project config at {:?}; pass --artifact or run `init` first",
+                ProjectConfig::config_path(&self.crate_path)
+            )
+        })?;
+        Ok(config.baseline_path(&self.crate_path))
+    }
+
+    fn run(self, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let policy = Policy::read_from_file(&self.policy_path)?;
+        let artifact = ScanArtifact::read_from_file(&self.artifact_path()?)?;
+
+        let mut scan_config = self.scan_config.clone();
+        scan_config.load_project(&self.crate_path)?;
+        let changes = simulate(&artifact, &policy, &self.crate_path, &scan_config)?;
+        let raised = changes
+            .iter()
+            .filter(|c| c.new_severity().map_or(false, |s| s > c.old_severity()))
+            .count();
+        let lowered = changes
+            .iter()
+            .filter(|c| c.new_severity().map_or(false, |s| s < c.old_severity()))
+            .count();
+        let removed = changes.iter().filter(|c| c.new_effect.is_none()).count();
+        summary.set_reason(format!(
+            "{} of {} effects reclassified ({} raised, {} lowered, {} removed)",
+            changes.len(),
+            artifact.effects.len(),
+            raised,
+            lowered,
+            removed
+        ));
+
+        if quiet {
+            return Ok(());
+        }
+        if changes.is_empty() {
+            println!("No effects would be reclassified by the policy");
+            return Ok(());
+        }
+        println!("Effects reclassified by the policy:");
+        for change in &changes {
+            println!("  {}", change);
+        }
+        println!();
+        println!(
+            "{} of {} effects reclassified: severity raised for {}, lowered for {}; \
+            {} would no longer be effects",
+            changes.len(),
+            artifact.effects.len(),
+            raised,
+            lowered,
+            removed
+        );
+        Ok(())
+    }
+}
+
+/// Show or edit the sink patterns
+#[derive(ClapArgs, Debug)]
+struct Sinks {
+    #[clap(subcommand)]
+    command: SinksCommand,
+
+    /// Path to the crate with the project config
+    #[clap(long, default_value = ".", global = true)]
+    crate_path: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+enum SinksCommand {
+    /// List the sink patterns scans look for: the built-in ones, the
+    /// project's, and those of the custom effect kinds
+    List,
+    /// Add a sink pattern to the project's sink file, or an exclusion from
+    /// the sinks written `!<pattern>`
+    Add { pattern: String },
+    /// Remove a sink pattern from the project's sink file
+    Remove { pattern: String },
+}
+
+impl Sinks {
+    fn sinks_path(&self) -> Result<PathBuf> {
+        let config = ProjectConfig::read(&self.crate_path)?.ok_or_else(|| {
+            anyhow!(
+                "No project config at {:?}; run `init` first",
+                ProjectConfig::config_path(&self.crate_path)
+            )
+        })?;
+        Ok(config.sinks_path(&self.crate_path))
+    }
+
+    fn run(self, quiet: bool, summary: &mut Summary) -> Result<()> {
+        match &self.command {
+            SinksCommand::List => {
+                let inventory = sink::project_inventory(&self.crate_path, None)?;
+                summary.set_reason(format!("{} sink patterns", inventory.len()));
+                if !quiet {
+                    let width = inventory.iter().map(|(p, _)| p.len()).max().unwrap_or(0);
+                    for (pattern, source) in &inventory {
+                        println!("{:width$}  {}", pattern, source, width = width);
+                    }
+                }
+            }
+            SinksCommand::Add { pattern } => {
+                let path = self.sinks_path()?;
+                let mut file = SinkFile::read(&path)?;
+                sink::validate_pattern(pattern)?;
+                if Sink::default_sinks().includes().contains(&Pattern::new(pattern)) {
+                    return Err(anyhow!("{} is already a built-in sink", pattern));
+                }
+                if !file.add(pattern)? {
+                    return Err(anyhow!("{} is already a sink of the project", pattern));
+                }
+                file.save(&path)?;
+                summary.set_reason(format!("added {} to {:?}", pattern, path));
+            }
+            SinksCommand::Remove { pattern } => {
+                let path = self.sinks_path()?;
+                let mut file = SinkFile::read(&path)?;
+                if !file.remove(pattern) {
+                    return Err(anyhow!(
+                        "{} is not a sink of the project (built-in sinks can't be \
+                        removed)",
+                        pattern
+                    ));
+                }
+                file.save(&path)?;
+                summary.set_reason(format!("removed {} from {:?}", pattern, path));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args.verbosity);
+    let mut summary = Summary::new("policy");
+    let res = match args.command {
+        Command::Simulate(simulate) => simulate.run(args.verbosity.quiet, &mut summary),
+        Command::Sinks(sinks) => sinks.run(args.verbosity.quiet, &mut summary),
+    };
+    summary.print(&res);
+    res
+}
@@ -0,0 +1,133 @@
+/*
+    Query a scan database created with `scan --db` or `audit --db`,
+    printing the matching effects to stdout (one per line) in CSV format.
+
+    `query index <callee> <artifact>..` instead looks up the calls to a path
+    in the callee index of scan artifacts saved with `scan --json`.
+*/
+
+use cargo_scan::artifact::ScanArtifact;
+use cargo_scan::db::{EffectQuery, EffectRow, ScanDb};
+use cargo_scan::effect::{EffectInstance, EffectType};
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
+
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the SQLite database
+    #[clap(required = true)]
+    db: Option<PathBuf>,
+
+    /// Only show effects in this crate
+    #[clap(long = "crate")]
+    crate_name: Option<String>,
+
+    /// Only show effects of this kind
+    #[clap(long)]
+    effect_kind: Option<EffectType>,
+
+    /// Only show effects whose callee is this path or under it (e.g. std::fs)
+    #[clap(long)]
+    callee_prefix: Option<String>,
+
+    /// Only show effects with this audit status (e.g. Unreviewed, Safe)
+    #[clap(long)]
+    audit_status: Option<String>,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Index(Index),
+}
+
+/// List the effects calling a path (e.g. libc::mmap), using the callee index
+/// of scan artifacts
+#[derive(ClapArgs, Debug)]
+struct Index {
+    /// The callee path
+    callee: String,
+
+    /// Scan artifacts saved with `scan --json`, e.g. one for each crate in the
+    /// dependency tree
+    #[clap(num_args = 1.., required = true)]
+    artifacts: Vec<PathBuf>,
+
+    /// Also match the paths under the callee (e.g. libc for every libc call)
+    #[clap(long, default_value_t = false)]
+    prefix: bool,
+}
+
+impl Index {
+    fn run(self, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let artifacts = self
+            .artifacts
+            .iter()
+            .map(|path| ScanArtifact::read_from_file(path))
+            .collect::<Result<Vec<_>>>()?;
+        let records = artifacts
+            .iter()
+            .flat_map(|a| a.effects_calling(&self.callee, self.prefix))
+            .collect::<Vec<_>>();
+        summary.set_reason(format!("{} calls to {}", records.len(), self.callee));
+        if quiet {
+            return Ok(());
+        }
+
+        println!("{}", EffectInstance::csv_header());
+        for record in records {
+            println!("{}", record.effect.to_csv());
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args.verbosity);
+    let mut summary = Summary::new("query");
+    let res = runner(args, &mut summary);
+    summary.print(&res);
+    res
+}
+
+fn runner(args: Args, summary: &mut Summary) -> Result<()> {
+    if let Some(Command::Index(index)) = args.command {
+        return index.run(args.verbosity.quiet, summary);
+    }
+    let db_path = args.db.unwrap();
+    if !db_path.is_file() {
+        return Err(anyhow!("Database doesn't exist: {:?}", db_path));
+    }
+    let db = ScanDb::open(&db_path)?;
+
+    let query = EffectQuery {
+        crate_name: args.crate_name,
+        effect_kind: args.effect_kind,
+        callee_prefix: args.callee_prefix,
+        audit_status: args.audit_status,
+    };
+
+    let rows = db.query_effects(&query)?;
+    summary.set_reason(format!("{} matching effects", rows.len()));
+    if args.verbosity.quiet {
+        return Ok(());
+    }
+
+    println!("{}", EffectRow::csv_header());
+    for row in rows {
+        println!("{}", row.to_csv());
+    }
+
+    Ok(())
+}
@@ -2,22 +2,80 @@
     Parse a Rust source file and find all potentially dangerous effects,
     printing them to stdout (one per line).
 
+    The output starts with a plain-language summary of what the crate can
+    do (see capability.rs), on stderr for the CSV and quickfix formats.
+
     Effects are printed in a CSV format -- run --bin csv_header to get
-    the header or see effect.rs.
+    the header or see effect.rs. With --format quickfix, they are printed
+    as file:line:col: message lines for the quickfix list in Vim or
+    compilation-mode in Emacs.
+
+    With --from-hir <dir>, the -Zunpretty=hir dumps in the directory are
+    scanned instead of a crate (see scanner::scan_hir_dumps).
+
+    With --baseline <artifact>, only the effects which aren't in the baseline
+    are listed (see effect_identity.rs for when two effects are the same).
+
+    `scan compare <crate>@<version> <crate>@<version> ..` instead compares
+    alternative crates side by side (see compare.rs).
+
+    `scan affected <workspace> --since origin/main` scans only the workspace
+    members affected by the changes since a git revision (see workspace.rs).
+
+    `scan features <crate>` suggests a minimal feature set for each direct
+    dependency, as Cargo.toml entries (see features.rs).
+
+    With --quick, the effects of the crate and each of its dependencies are
+    only estimated, from a pass over their tokens (see quick_scan.rs).
 */
 
+use cargo_scan::artifact::{parse_size, random_salt, EffectContext, ScanArtifact};
+use cargo_scan::audit_diff::EffectDiff;
 use cargo_scan::audit_file::AuditFile;
-use cargo_scan::effect::{EffectInstance, EffectType, DEFAULT_EFFECT_TYPES};
+use cargo_scan::capability::CapabilitySummary;
+use cargo_scan::compare::{self, ComparisonTable, CrateSpec};
+use cargo_scan::db::ScanDb;
+use cargo_scan::edition::UnsafeFnOpsPolicy;
+use cargo_scan::effect::{EffectInstance, EffectSelector, EffectType, UnsafeOp};
+use cargo_scan::effect_identity::{self, EffectIdentity};
+use cargo_scan::effect_index::CalleeIndex;
+use cargo_scan::features;
+use cargo_scan::heatmap::{Heatmap, HeatmapFormat};
+use cargo_scan::output::{HtmlSink, OutputFormat, OutputSink, OutputSpec, ScanOutput};
+use cargo_scan::progress;
+use cargo_scan::project::ProjectConfig;
+use cargo_scan::quick_scan;
+use cargo_scan::ratchet::{self, Ratchet};
+use cargo_scan::scanner::{self, ScanConfig};
+use cargo_scan::summary::Summary;
+use cargo_scan::template::{self, ScanContext};
+use cargo_scan::trait_summary;
+use cargo_scan::treemap::Treemap;
+use cargo_scan::util::{init_logging, load_cargo_toml, Verbosity};
+use cargo_scan::workspace;
 
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use rayon::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Path to crate directory; should contain a 'src' directory and a Cargo.toml file
-    crate_path: PathBuf,
+    #[clap(required_unless_present = "from_hir")]
+    crate_path: Option<PathBuf>,
+
+    /// Scan the `-Zunpretty=hir` dumps in this directory instead of a crate,
+    /// one `<crate>.hir` file per crate (from e.g. `cargo +nightly rustc --
+    /// -Zunpretty=hir > dumps/mycrate.hir`); effects in macro expansions are
+    /// found, and their locations are lines in the dumps
+    #[clap(long, conflicts_with = "crate_path")]
+    from_hir: Option<PathBuf>,
 
     /// Verbose output:
     /// In addition to effects, print metadata about total LoC scanned and ignored
@@ -32,27 +90,639 @@ struct Args {
     #[clap(short = 'd', long = "crate-download-path", default_value = ".stats_tmp")]
     crate_download_path: String,
 
-    /// The types of Effects the audit should track. Defaults to all unsafe
-    /// behavior.
-    #[clap(long, value_parser, num_args = 1.., default_values_t = DEFAULT_EFFECT_TYPES)]
+    /// The types of Effects the audit should track, or families of them
+    /// (default, ffi, unsafe, sinks, raw-pointer, malware, os-hooks). Defaults to
+    /// all unsafe behavior. Effects which aren't selected aren't scanned for at all.
+    #[clap(
+        long = "effect-types",
+        value_parser,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    effect_selection: Vec<EffectSelector>,
+
+    #[clap(skip)]
     effect_types: Vec<EffectType>,
+
+    /// Also save the scan results to a SQLite database (created if it doesn't exist)
+    #[clap(long)]
+    db: Option<PathBuf>,
+
+    #[clap(flatten)]
+    scan_config: ScanConfig,
+
+    /// Only estimate the effects of the crate and each of its dependencies,
+    /// from a quick pass counting unsafe keywords, extern blocks, and sink
+    /// paths (seconds instead of a full scan), to see where full scans are
+    /// worth the time
+    #[clap(long, default_value_t = false, conflicts_with = "from_hir")]
+    quick: bool,
+
+    /// List the operator trait (e.g. Index, Add) and Deref/DerefMut impls
+    /// which reach effects
+    #[clap(long, default_value_t = false)]
+    operator_impls: bool,
+
+    /// Summarize the effects reachable from the crate's impls of widely-used
+    /// traits (Read, Write, Iterator, Future, ...), or only of the given
+    /// trait, e.g. `--trait-impls Future`
+    #[clap(long, num_args = 0..=1, default_missing_value = "")]
+    trait_impls: Option<String>,
+
+    /// List the effects matching known unsoundness patterns (e.g.
+    /// `Vec::set_len` before the elements are initialized), with references
+    #[clap(long, default_value_t = false)]
+    soundness: bool,
+
+    /// List the enabled features whose disabling removes effects, e.g.
+    /// "disabling feature `vendored` removes 212 FFICall effects"
+    #[clap(long, default_value_t = false)]
+    feature_report: bool,
+
+    /// Also save the scan results as a JSON artifact; compressed if the path
+    /// ends in .gz or .zst (e.g. results.json.zst)
+    #[clap(long)]
+    json: Option<PathBuf>,
+
+    /// Leave source snippets out of the JSON artifact (same as
+    /// --effect-context file-hash)
+    #[clap(long, default_value_t = false)]
+    strip_snippets: bool,
+
+    /// How much source to store with each effect in the JSON artifact
+    #[clap(long, value_enum, default_value_t, conflicts_with = "strip_snippets")]
+    effect_context: EffectContext,
+
+    /// Anonymize the JSON artifact, to share it without the source: snippets
+    /// and warnings are left out, and the crate's paths and file names are
+    /// replaced by hashes
+    #[clap(long, default_value_t = false, requires = "json")]
+    anonymize: bool,
+
+    /// Secret salt for the anonymous names, to keep them the same across
+    /// scans; by default a random one is used, so they differ on each run
+    #[clap(long, requires = "anonymize")]
+    anonymize_salt: Option<String>,
+
+    /// How to treat unsafe operations in an `unsafe fn` outside of any unsafe
+    /// block, which need their own block from the 2024 edition. Defaults to
+    /// warn for 2024-edition crates and allow for the others.
+    #[clap(long, value_enum)]
+    unsafe_op_in_unsafe_fn: Option<UnsafeFnOpsPolicy>,
+
+    /// Size limit for the JSON artifact (e.g. 10M). Optional fields are dropped,
+    /// heaviest first, to stay under the limit.
+    #[clap(long, value_parser = parse_size)]
+    artifact_budget: Option<u64>,
+
+    /// Fail if the effect counts of any kind went over the bounds in the
+    /// ratchet file, and tighten the bounds if they went down. The file is
+    /// created with the current counts if it doesn't exist.
+    #[clap(long, num_args = 0..=1, default_missing_value = "cargo-scan-ratchet.toml")]
+    ratchet: Option<PathBuf>,
+
+    /// Only list the effects which aren't in this scan artifact, e.g. the
+    /// baseline saved by `init` (.cargo-scan/baseline.json)
+    #[clap(long)]
+    baseline: Option<PathBuf>,
+
+    /// When an effect is the same as one in the baseline: location, path
+    /// (same caller, callee, and kind), or snippet (same callee, kind, and
+    /// source text). Defaults to the project config, or location.
+    #[clap(long, value_enum, requires = "baseline")]
+    effect_identity: Option<EffectIdentity>,
+
+    /// Print a heatmap of the effects per directory and file instead of the
+    /// list of effects, in the terminal or as an HTML page (with a treemap of
+    /// the effects per module and function), or the effects per module and
+    /// function as JSON for d3 treemaps
+    #[clap(long, value_enum)]
+    heatmap: Option<HeatmapFormat>,
+
+    /// Render the scan results with a template (Jinja2 syntax) instead of
+    /// printing the list of effects; see template.rs for the data available
+    /// to templates
+    #[clap(long, conflicts_with = "heatmap")]
+    template: Option<PathBuf>,
+
+    /// Format of the list of effects: CSV, `file:line:col: message` lines
+    /// for Vim's quickfix list (`vim -q`) and Emacs' compilation-mode, or
+    /// the same lines under a header for each crate, with the paths relative
+    /// to the crate (see --absolute-paths), a SARIF 2.1.0 log for GitHub
+    /// code scanning, or JSON Lines streamed as the effects are found (which
+    /// keeps memory flat on large crates, but doesn't prune the calls from
+    /// unreachable functions), the results as JSON or as an HTML heatmap.
+    /// Repeat to write several formats in one scan, all but one of them to
+    /// files, e.g. --format csv --format sarif=results.sarif
+    #[clap(long = "format", default_value = "csv")]
+    formats: Vec<OutputSpec>,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Compare(Compare),
+    Affected(Affected),
+    Features(Features),
+    AuditDiff(AuditDiff),
+}
+
+/// Compare alternative crates (e.g. rustls@0.21.7 openssl@0.10.57) side by
+/// side: effects, effect kinds, dependencies with effects, and risk score
+#[derive(ClapArgs, Debug)]
+struct Compare {
+    /// The crates to compare, as <crate>@<version>
+    #[clap(num_args = 2.., required = true)]
+    crates: Vec<CrateSpec>,
+
+    /// Directory to download and audit the crates in
+    #[clap(short = 'd', long = "crate-download-path", default_value = ".compare_tmp")]
+    crate_download_path: PathBuf,
+
+    /// The types of Effects to compare, or families of them (default, ffi,
+    /// unsafe, sinks, raw-pointer, malware, os-hooks)
+    #[clap(
+        long = "effect-types",
+        value_parser,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    effect_selection: Vec<EffectSelector>,
+}
+
+impl Compare {
+    fn run(self, config: &ScanConfig, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let effect_types = EffectSelector::expand(&self.effect_selection);
+        let crates = self
+            .crates
+            .into_iter()
+            .map(|spec| {
+                compare::audit_crate(
+                    spec,
+                    &self.crate_download_path,
+                    &effect_types,
+                    config,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        summary.set_reason(format!("compared {} crates", crates.len()));
+        if !quiet {
+            print!("{}", ComparisonTable(&crates));
+        }
+        Ok(())
+    }
+}
+
+/// Scan only the workspace members affected by the changes since a git
+/// revision: the members with changed files, and the members depending on
+/// them. Prints the effects of all of them as one CSV.
+#[derive(ClapArgs, Debug)]
+struct Affected {
+    /// Path to the workspace root
+    workspace_path: PathBuf,
+
+    /// Git revision to diff against, e.g. the target branch of a pull request
+    #[clap(long, default_value = "HEAD")]
+    since: String,
+
+    /// The types of Effects to scan for, or families of them (default, ffi,
+    /// unsafe, sinks, raw-pointer, malware, os-hooks)
+    #[clap(
+        long = "effect-types",
+        value_parser,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    effect_selection: Vec<EffectSelector>,
+
+    /// Also save the scan results of each member as a JSON artifact,
+    /// <member>.json, in this directory
+    #[clap(long)]
+    json_dir: Option<PathBuf>,
+
+    /// Number of members to scan in parallel (by default, one per CPU)
+    #[clap(short = 'j', long)]
+    jobs: Option<usize>,
+}
+
+impl Affected {
+    fn run(self, config: &ScanConfig, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let effect_types = EffectSelector::expand(&self.effect_selection);
+        if let Some(jobs) = self.jobs {
+            scanner::set_scan_jobs(jobs)?;
+        }
+        let root = self.workspace_path.canonicalize()?;
+        let members = workspace::read_members(&root)?;
+        let changed = workspace::changed_files(&root, &self.since)?;
+        let affected = workspace::affected_members(&root, &members, &changed);
+        progress!(
+            "{} files changed since {}; scanning {} of {} workspace members",
+            changed.len(),
+            self.since,
+            affected.len(),
+            members.len()
+        );
+        if let Some(dir) = &self.json_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Members are scanned in parallel, and their results used in order
+        let all_results = affected
+            .par_iter()
+            .map(|member| {
+                progress!("Scanning {}...", member.name);
+                scanner::scan_crate(&member.path, &effect_types, config)
+            })
+            .collect::<Vec<_>>();
+        let mut effects = Vec::new();
+        for (member, results) in affected.iter().zip(all_results) {
+            let results = results?;
+            if let Some(scan_limit) = results.scan_limit {
+                eprintln!(
+                    "Warning: {} only partially scanned (hit the scan {})",
+                    member.name, scan_limit
+                );
+            }
+            if let Some(dir) = &self.json_dir {
+                ScanArtifact::from_results(&results, EffectContext::Lines)
+                    .save_to_file(&dir.join(format!("{}.json", member.name)), None)?;
+            }
+            summary.add_ignored_files(results.ignored_files);
+            effects.extend(results.effects);
+        }
+
+        summary.add_effects(&effects);
+        summary.set_reason(format!(
+            "scanned {} of {} workspace members",
+            affected.len(),
+            members.len()
+        ));
+        if !quiet {
+            println!("{}", EffectInstance::csv_header());
+            for effect in &effects {
+                println!("{}", effect.to_csv());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Suggest a minimal feature set for each direct dependency: the features
+/// gating its effects are dropped, unless the crate calls functions behind
+/// them. Prints the suggested entries for the `[dependencies]` of Cargo.toml.
+#[derive(ClapArgs, Debug)]
+struct Features {
+    /// Path to crate directory; should contain a 'src' directory and a Cargo.toml file
+    crate_path: PathBuf,
+
+    /// The types of Effects to scan for, or families of them (default, ffi,
+    /// unsafe, sinks, raw-pointer, malware, os-hooks)
+    #[clap(
+        long = "effect-types",
+        value_parser,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    effect_selection: Vec<EffectSelector>,
+}
+
+impl Features {
+    fn run(self, config: &ScanConfig, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let effect_types = EffectSelector::expand(&self.effect_selection);
+        let suggestions =
+            features::suggest_features(&self.crate_path, &effect_types, config)?;
+        summary.set_reason(format!(
+            "suggested smaller feature sets for {} dependencies",
+            suggestions.len()
+        ));
+        if !quiet {
+            if suggestions.is_empty() {
+                println!("No dependency features can be dropped");
+            }
+            for suggestion in &suggestions {
+                println!();
+                print!("{}", suggestion);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare the effects of two audit files, or of an audit file and a fresh
+/// scan of a crate. Effects are matched by caller, callee, and kind, so
+/// effects which only moved are reported as relocated, not as new and removed.
+#[derive(ClapArgs, Debug)]
+struct AuditDiff {
+    /// The old audit file
+    old: PathBuf,
+
+    /// The new audit file, or a crate directory to scan for the effects the
+    /// old audit file tracks
+    new: PathBuf,
+
+    /// Print the differences as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl AuditDiff {
+    fn run(self, config: &ScanConfig, quiet: bool, summary: &mut Summary) -> Result<()> {
+        let old = AuditFile::load(&self.old)?;
+        let new_effects = if self.new.is_dir() {
+            progress!("Scanning {}...", self.new.display());
+            old.rescan(&self.new, config)?.effects
+        } else {
+            AuditFile::load(&self.new)?.audit_trees.into_keys().collect()
+        };
+        let diff = EffectDiff::new(old.audit_trees.keys(), &new_effects);
+        summary.set_reason(format!(
+            "{} new, {} removed, {} relocated effects",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.relocated.len()
+        ));
+        if !quiet {
+            if self.json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                println!("{}", diff);
+            }
+        }
+        Ok(())
+    }
 }
 
 fn main() -> Result<()> {
-    cargo_scan::util::init_logging();
     let args = Args::parse();
+    init_logging(&args.verbosity);
+    let mut summary = Summary::new("scan");
+    let metrics = args.scan_config.metrics.clone();
+    let res = match metrics.init() {
+        Ok(()) => runner(args, &mut summary),
+        Err(e) => Err(e),
+    };
+    // The final metrics, e.g. with the crates which were skipped
+    if let Err(e) = metrics.write_file() {
+        eprintln!("Warning: {}", e);
+    }
+    summary.print(&res);
+    res
+}
 
-    let (audit, results) = AuditFile::new_caller_checked_default_with_results(
-        &args.crate_path,
-        &args.effect_types,
-    )?;
+/// Streamed effects aren't kept in the results, so the options which need all
+/// of the effects can't be used with `--format ndjson`
+fn check_streamable(args: &Args) -> Result<()> {
+    let conflicts = [
+        ("another --format", args.formats.len() > 1),
+        ("--db", args.db.is_some()),
+        ("--json", args.json.is_some()),
+        ("--baseline", args.baseline.is_some()),
+        ("--ratchet", args.ratchet.is_some()),
+        ("--template", args.template.is_some()),
+        ("--heatmap", args.heatmap.is_some()),
+        ("--from-hir", args.from_hir.is_some()),
+        ("--soundness", args.soundness),
+        ("--feature-report", args.feature_report),
+        ("--operator-impls", args.operator_impls),
+        ("--trait-impls", args.trait_impls.is_some()),
+        ("--unsafe-op-in-unsafe-fn", args.unsafe_op_in_unsafe_fn.is_some()),
+    ];
+    match conflicts.iter().find(|(_, used)| *used) {
+        Some((option, _)) => Err(anyhow!(
+            "--format ndjson doesn't keep the effects, so it can't be used with {}",
+            option
+        )),
+        None => Ok(()),
+    }
+}
+
+fn runner(mut args: Args, summary: &mut Summary) -> Result<()> {
+    match args.command {
+        Some(Command::Compare(compare)) => {
+            return compare.run(&args.scan_config, args.verbosity.quiet, summary);
+        }
+        Some(Command::Affected(affected)) => {
+            args.scan_config.load_project(&affected.workspace_path)?;
+            return affected.run(&args.scan_config, args.verbosity.quiet, summary);
+        }
+        Some(Command::Features(features)) => {
+            args.scan_config.load_project(&features.crate_path)?;
+            return features.run(&args.scan_config, args.verbosity.quiet, summary);
+        }
+        Some(Command::AuditDiff(diff)) => {
+            if diff.new.is_dir() {
+                args.scan_config.load_project(&diff.new)?;
+            }
+            return diff.run(&args.scan_config, args.verbosity.quiet, summary);
+        }
+        None => (),
+    }
+    let crate_path = args.crate_path.clone().or(args.from_hir.clone()).unwrap();
+    if args.from_hir.is_none() {
+        args.scan_config.load_project(&crate_path)?;
+    }
+    args.effect_types = EffectSelector::expand(&args.effect_selection);
+    if args.quick {
+        let quiet = args.verbosity.quiet;
+        return quick_estimates(&crate_path, &args.scan_config, quiet, summary);
+    }
+    args.scan_config.feature_report = args.feature_report;
+    OutputSpec::check_all(&args.formats)?;
+    let streaming = args.formats.iter().any(|s| s.format == OutputFormat::Ndjson);
+    if streaming {
+        check_streamable(&args)?;
+        args.scan_config.stream_effects = !args.verbosity.quiet;
+    }
+
+    let (audit, mut results) = match &args.from_hir {
+        Some(dir) => {
+            let results =
+                scanner::scan_hir_dumps(dir, &args.effect_types, &args.scan_config)?;
+            let mut audit = AuditFile::empty(dir.clone(), args.effect_types.clone())?;
+            audit.set_base_audit_trees(results.effects_set());
+            (audit, results)
+        }
+        None => AuditFile::new_caller_checked_default_with_results(
+            &crate_path,
+            &args.effect_types,
+            &args.scan_config,
+        )?,
+    };
 
     // Note: old version without default_audit:
     // scanner::scan_crate(&args.crate_path, &args.effect_types)?
 
-    println!("{}", EffectInstance::csv_header());
-    for effect in results.effects {
-        println!("{}", effect.to_csv());
+    if let Some(db_path) = &args.db {
+        let mut db = ScanDb::open(db_path)?;
+        db.insert_effects(&results.effects)?;
+    }
+
+    if let Some(json_path) = &args.json {
+        let context = if args.strip_snippets || args.anonymize {
+            EffectContext::FileHash
+        } else {
+            args.effect_context
+        };
+        let mut artifact = ScanArtifact::from_results(&results, context);
+        if args.anonymize {
+            match &args.anonymize_salt {
+                Some(salt) => artifact.anonymize(salt.as_bytes()),
+                None => artifact.anonymize(&random_salt()),
+            }
+        }
+        artifact.save_to_file(json_path, args.artifact_budget)?;
+    }
+
+    summary.add_effects(&results.effects);
+    if streaming {
+        summary.set_reason(format!("streamed {} effects", scanner::streamed_effects()));
+    }
+    summary.add_ignored_files(results.ignored_files);
+    if let Some(limit) = results.scan_limit {
+        eprintln!(
+            "Warning: crate only partially scanned (hit the scan {}); \
+            some effects may be missing",
+            limit
+        );
+        summary.set_reason(format!("partially scanned (hit the scan {})", limit));
+    }
+    for warning in &results.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let ratchet_res = match &args.ratchet {
+        Some(path) => check_ratchet(path, &results.effects, &args.effect_types),
+        None => Ok(()),
+    };
+    let unsafe_fn_policy = args
+        .unsafe_op_in_unsafe_fn
+        .unwrap_or_else(|| UnsafeFnOpsPolicy::edition_default(results.edition));
+    let unsafe_fn_res = check_unsafe_fn_ops(unsafe_fn_policy, &results.effects);
+    let res = ratchet_res.and(unsafe_fn_res);
+
+    let crate_name = load_cargo_toml(&crate_path).map_or_else(
+        |_| crate_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        |c| c.crate_name,
+    );
+    let capabilities = CapabilitySummary::from_results(crate_name, &results);
+
+    if let Some(path) = &args.baseline {
+        let identity = match args.effect_identity {
+            Some(identity) => identity,
+            None => ProjectConfig::read(&crate_path)?
+                .map(|c| c.effect_identity)
+                .unwrap_or_default(),
+        };
+        let baseline = ScanArtifact::read_from_file(path)?;
+        let context = match identity {
+            EffectIdentity::Snippet => EffectContext::Lines,
+            _ => EffectContext::FileHash,
+        };
+        let current = ScanArtifact::from_results(&results, context);
+        let new_ids = effect_identity::new_effects(
+            identity,
+            &baseline.effect_sources(),
+            &current.effect_sources(),
+        );
+        eprintln!(
+            "{} of {} effects are new since the baseline",
+            new_ids.len(),
+            results.effects.len()
+        );
+        results.effects =
+            new_ids.into_iter().map(|i| results.effects[i].clone()).collect();
+        results.callee_index = CalleeIndex::new(&results.effects);
+    }
+
+    if args.verbosity.quiet {
+        return res;
+    }
+
+    let output = ScanOutput {
+        results: &results,
+        crate_path: &crate_path,
+        capabilities: &capabilities,
+    };
+    if let Some(template) = &args.template {
+        let artifact = ScanArtifact::from_results(&results, EffectContext::Lines);
+        let context = ScanContext::new(&crate_path, &artifact);
+        print!("{}", template::render_file(template, context)?);
+    } else if let Some(format) = args.heatmap {
+        match format {
+            HeatmapFormat::Terminal => {
+                println!("{}\n", capabilities);
+                print!("{}", Heatmap::new(&results.effects, &crate_path).to_terminal())
+            }
+            HeatmapFormat::Html => HtmlSink.write_results(&output, &mut io::stdout())?,
+            HeatmapFormat::TreemapJson => {
+                let treemap = Treemap::new(&results.effects);
+                println!("{}", serde_json::to_string_pretty(&treemap)?)
+            }
+        }
+    } else {
+        // The summary goes to stderr so the other outputs stay
+        // machine-readable; the streamed effects have no summary
+        let formats = args.formats.iter().map(|s| s.format).collect::<Vec<_>>();
+        if !formats.iter().any(|f| f.shows_capabilities() || *f == OutputFormat::Ndjson) {
+            eprintln!("{}", capabilities)
+        }
+        for spec in &args.formats {
+            spec.write(&output)?;
+        }
+    }
+
+    if args.soundness {
+        println!();
+        println!("Soundness findings: {}", results.soundness_findings.len());
+        for finding in &results.soundness_findings {
+            println!("  {}", finding);
+        }
+    }
+
+    if let Some(report) = &results.feature_report {
+        println!();
+        println!("Effects gated by enabled features:");
+        if report.removed.is_empty() {
+            println!("  none");
+        }
+        for line in report.to_string().lines() {
+            println!("  {}", line);
+        }
+    }
+
+    if args.operator_impls {
+        println!();
+        println!("Operator impls reaching effects:");
+        for (meth, tr, effects) in results.effectful_operator_meths() {
+            println!("  {} ({}): {} effects", meth, tr, effects.len());
+            for e in effects {
+                println!("    {}", e.to_csv());
+            }
+        }
+    }
+
+    if let Some(trait_name) = &args.trait_impls {
+        println!();
+        println!("Trait impls:");
+        let summaries = trait_summary::summarize(&results);
+        let mut summaries = summaries
+            .iter()
+            .filter(|s| trait_name.is_empty() || s.trait_impl.trait_name() == trait_name)
+            .peekable();
+        if summaries.peek().is_none() {
+            println!("  none");
+        }
+        for summary in summaries {
+            for line in summary.to_string().lines() {
+                println!("  {}", line);
+            }
+        }
     }
 
     if args.extras {
@@ -93,7 +763,23 @@ fn main() -> Result<()> {
             pub_fns,
             pub_fns_with_effects,
             pub_total_effects,
-        )
+        );
+
+        // Breakdown of the unsafe effects by unsafe operation
+        let unsafe_ops = results.unsafe_op_counts();
+        println!();
+        println!(
+            "{}",
+            UnsafeOp::ALL.iter().map(|op| op.to_string()).collect::<Vec<_>>().join(", ")
+        );
+        println!(
+            "{}",
+            UnsafeOp::ALL
+                .iter()
+                .map(|op| unsafe_ops.get(op).unwrap_or(&0).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
 
         // println!("Total scanned, {}", results.total_loc.as_csv());
         // println!("Skipped macros, {}", results.skipped_macros.as_csv());
@@ -105,5 +791,86 @@ fn main() -> Result<()> {
         // println!("Unsafe trait impl keywords, {}", results.unsafe_impls.as_csv());
     }
 
+    res
+}
+
+/// Report the unsafe operations only allowed by the enclosing `unsafe fn`,
+/// failing if the policy denies them
+fn check_unsafe_fn_ops(
+    policy: UnsafeFnOpsPolicy,
+    effects: &[EffectInstance],
+) -> Result<()> {
+    let flagged = policy.flagged(effects);
+    if flagged.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "Warning: {} unsafe operations in an unsafe fn need their own unsafe block:",
+        flagged.len()
+    );
+    for e in &flagged {
+        eprintln!("  {}", e.to_text());
+    }
+    if policy == UnsafeFnOpsPolicy::Deny {
+        return Err(anyhow!(
+            "{} unsafe operations outside of an unsafe block (--unsafe-op-in-unsafe-fn deny)",
+            flagged.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Print the quick estimates of the effects of the crate and its
+/// dependencies, most effects first
+fn quick_estimates(
+    crate_path: &Path,
+    config: &ScanConfig,
+    quiet: bool,
+    summary: &mut Summary,
+) -> Result<()> {
+    let estimates = quick_scan::estimate_dependencies(crate_path, config)?;
+    let total = estimates.iter().map(|e| e.counts.estimate()).sum::<usize>();
+    summary.set_reason(format!(
+        "estimated ~{} effects in {} crates (quick pass, not a scan)",
+        total,
+        estimates.len()
+    ));
+    if !quiet {
+        println!(
+            "Estimated effects (from a quick pass over the tokens; run a full scan \
+            for the actual effects):"
+        );
+        for estimate in &estimates {
+            println!("  {}", estimate);
+        }
+    }
     Ok(())
 }
+
+fn check_ratchet(
+    path: &Path,
+    effects: &[EffectInstance],
+    effect_types: &[EffectType],
+) -> Result<()> {
+    let counts = ratchet::effect_counts(effects);
+    let Some(mut ratchet) = Ratchet::read_from_file(path)? else {
+        Ratchet::from_counts(counts).save_to_file(path)?;
+        eprintln!("Created ratchet file {:?} with the current effect counts", path);
+        return Ok(());
+    };
+
+    let outcome = ratchet.check(&counts, effect_types);
+    if !outcome.passed() {
+        for c in &outcome.increased {
+            eprintln!("{} effects increased: {} (bound {})", c.kind, c.count, c.bound);
+        }
+        return Err(anyhow!("Effect counts went over the bounds in {:?}", path));
+    }
+    for c in &outcome.tightened {
+        eprintln!(
+            "{} effects decreased: bound tightened from {} to {}",
+            c.kind, c.bound, c.count
+        );
+    }
+    ratchet.save_to_file(path)
+}
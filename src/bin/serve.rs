@@ -0,0 +1,47 @@
+/*
+    Serve an audit file over a small HTTP API, so custom dashboards and
+    other tooling can list effects, fetch source snippets, and record
+    audit decisions, and host a web UI for reviewing the audit in the
+    browser at /.
+
+    See server.rs for the list of endpoints.
+*/
+
+use cargo_scan::audit_file::AuditFile;
+use cargo_scan::server::AuditServer;
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the audit file to serve
+    audit_file_path: PathBuf,
+
+    /// Address to listen on
+    #[clap(short, long, default_value = "127.0.0.1:8080")]
+    addr: String,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logging(&args.verbosity);
+    let res = runner(args);
+    Summary::new("serve").print(&res);
+    res
+}
+
+fn runner(args: Args) -> Result<()> {
+    let audit_file = AuditFile::read_audit_file(args.audit_file_path.clone())?
+        .ok_or_else(|| anyhow!("Audit file doesn't exist: {:?}", args.audit_file_path))?;
+
+    let mut server = AuditServer::new(audit_file, args.audit_file_path);
+    server.serve(&args.addr)
+}
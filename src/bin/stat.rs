@@ -5,6 +5,9 @@ use anyhow::{anyhow, Context, Result};
 use cargo_scan::auditing::info::Config;
 use cargo_scan::download_crate;
 use cargo_scan::effect::EffectType;
+use cargo_scan::scanner::ScanConfig;
+use cargo_scan::summary::Summary;
+use cargo_scan::util::{init_logging, Verbosity};
 use cargo_scan::{
     audit_chain::{create_new_audit_chain, Create},
     auditing::review::review_audit,
@@ -12,6 +15,10 @@ use cargo_scan::{
 use clap::{Parser, ValueEnum};
 
 #[derive(Parser, Debug, Clone)]
+// -v is the download version here, so verbosity is only --verbose
+#[clap(mut_arg("verbose", |a| {
+    a.short(None).help("Print more information: --verbose for info logs, twice for debug logs")
+}))]
 struct Args {
     /// Path or name of the crate to audit. If downloading a crate, it will be saved here.
     crate_path: String,
@@ -44,10 +51,16 @@ struct Args {
         EffectType::UnionField,
         EffectType::StaticMut,
         EffectType::StaticExt,
+        EffectType::UnsafeTraitImpl,
         EffectType::FnPtrCreation,
         EffectType::ClosureCreation,
+        EffectType::PtrArithmetic,
+        EffectType::UninitMemory,
     ])]
     effect_types: Vec<EffectType>,
+
+    #[clap(flatten)]
+    verbosity: Verbosity,
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
@@ -69,9 +82,15 @@ impl std::fmt::Display for PathType {
 // TODO: Figure out who is responsible for clearing the audit path so we don't
 //       re-use audited policies.
 fn main() -> Result<()> {
-    cargo_scan::util::init_logging();
     let args = Args::parse();
+    init_logging(&args.verbosity);
+    let mut summary = Summary::new("stat");
+    let res = runner(args, &mut summary);
+    summary.print(&res);
+    res
+}
 
+fn runner(args: Args, summary: &mut Summary) -> Result<()> {
     if let (Some(crate_name), Some(crate_version)) =
         (&args.download_root_crate, &args.download_version)
     {
@@ -119,13 +138,18 @@ fn main() -> Result<()> {
         args.effect_types,
     );
 
-    let mut chain = create_new_audit_chain(create, &args.audit_file_path)?;
+    let scan_config = ScanConfig::default();
+    let mut chain = create_new_audit_chain(create, &args.audit_file_path, &scan_config)?;
     let root_crate = chain.root_crate()?;
     let root_audit_file = chain
         .read_audit_file(&root_crate)?
         .ok_or_else(|| anyhow!("Couldn't read root crate from the audit"))?;
-    let review_config = Config::new(0, 0, false);
-    review_audit(&root_audit_file, &PathBuf::from(&args.crate_path), &review_config)?;
+    summary.add_audit(&root_audit_file);
+    if !args.verbosity.quiet {
+        let review_config = Config::new(0, 0, false);
+        let crate_path = PathBuf::from(&args.crate_path);
+        review_audit(&root_audit_file, &crate_path, &review_config, &scan_config)?;
+    }
 
     remove_dir_all(&args.audit_file_path)?;
 
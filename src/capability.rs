@@ -0,0 +1,193 @@
+//! Plain-language summaries of what a crate can do, e.g.
+//! ```text
+//! mycrate can access the file system, spawn processes, and open network
+//! connections, and uses 12 unsafe blocks
+//! ```
+//! Capabilities are derived from the kinds of the crate's effects (sink calls
+//! by their sink pattern). Effects in functions which can't be reached from
+//! outside the crate (see `ScanResults::unreachable_fns`) don't count, since
+//! the crate can't be made to perform them.
+
+use crate::effect::{Effect, EffectInstance};
+use crate::scanner::ScanResults;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    FileSystem,
+    Processes,
+    Network,
+    Environment,
+    Io,
+    OsApis,
+    ForeignCode,
+    RawMemory,
+    /// Sink patterns which aren't built in, e.g. from a policy
+    Sink(String),
+    /// User-defined effect kinds (see `taxonomy`)
+    Custom(String),
+    Malware,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileSystem => write!(f, "access the file system"),
+            Self::Processes => write!(f, "spawn processes"),
+            Self::Network => write!(f, "open network connections"),
+            Self::Environment => write!(f, "read environment variables"),
+            Self::Io => write!(f, "read and write standard streams"),
+            Self::OsApis => write!(f, "call OS APIs directly"),
+            Self::ForeignCode => write!(f, "call foreign (C) code"),
+            Self::RawMemory => write!(f, "manipulate raw memory"),
+            Self::Sink(pattern) => write!(f, "call {}", pattern),
+            Self::Custom(kind) => write!(f, "have {} effects", kind),
+            Self::Malware => write!(f, "match malware heuristics"),
+        }
+    }
+}
+
+impl Capability {
+    /// The capability an effect gives the crate, if any (e.g. closure
+    /// creation doesn't give any)
+    pub fn of_effect(effect: &Effect) -> Option<Self> {
+        match effect {
+            Effect::SinkCall(sink) => Self::of_sink(sink.as_str()),
+            Effect::FFICall(_) | Effect::StaticExt(_) => Some(Self::ForeignCode),
+            Effect::RawPointer(_)
+            | Effect::PtrArithmetic(_)
+            | Effect::UninitMemory(_)
+            | Effect::UnionField(_)
+            | Effect::MemoryMap(_, _) => Some(Self::RawMemory),
+            Effect::OsHook(_, _) => Some(Self::OsApis),
+            Effect::Custom(c) => Some(Self::Custom(c.kind.clone())),
+            Effect::Malware(_, _) => Some(Self::Malware),
+            _ => None,
+        }
+    }
+
+    fn of_sink(pattern: &str) -> Option<Self> {
+        Some(match pattern {
+            "std::fs" | "std::path" | "tokio::fs" => Self::FileSystem,
+            "std::process" | "tokio::process" => Self::Processes,
+            "std::net" | "mio::net" | "tokio::net" | "hyper::client"
+            | "hyper::server" | "tokio_util::udp" | "tokio_util::net" | "socket2" => {
+                Self::Network
+            }
+            "std::env" => Self::Environment,
+            "std::io" | "tokio::io" => Self::Io,
+            "std::os" | "mio::unix" | "libc" | "winapi" => Self::OsApis,
+            "std::mem" | "std::intrinsics" => Self::RawMemory,
+            // Not capabilities in themselves
+            "std::arch" | "std::backtrace" | "std::ffi" | "std::panic" | "std::simd" => {
+                return None
+            }
+            _ => Self::Sink(pattern.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilitySummary {
+    pub crate_name: String,
+    /// Number of reachable effects giving each capability
+    pub capabilities: BTreeMap<Capability, usize>,
+    pub unsafe_blocks: usize,
+}
+
+impl CapabilitySummary {
+    pub fn new<'a, I>(crate_name: String, effects: I, unsafe_blocks: usize) -> Self
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let mut capabilities = BTreeMap::new();
+        for c in effects.into_iter().filter_map(|e| Capability::of_effect(e.eff_type())) {
+            *capabilities.entry(c).or_insert(0) += 1;
+        }
+        Self { crate_name, capabilities, unsafe_blocks }
+    }
+
+    /// The summary of a scanned crate, from its reachable effects
+    pub fn from_results(crate_name: String, results: &ScanResults) -> Self {
+        let unreachable = results.unreachable_fns();
+        let effects =
+            results.effects.iter().filter(|e| !unreachable.contains(e.caller()));
+        Self::new(crate_name, effects, results.unsafe_blocks.get_instances())
+    }
+}
+
+/// Join the items as a list in a sentence: `a`, `a and b`, `a, b, and c`
+fn join_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [a] => a.clone(),
+        [a, b] => format!("{} and {}", a, b),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+    }
+}
+
+impl fmt::Display for CapabilitySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let can = self.capabilities.keys().map(|c| c.to_string()).collect::<Vec<_>>();
+        let unsafe_blocks = match self.unsafe_blocks {
+            0 => None,
+            1 => Some("uses 1 unsafe block".to_string()),
+            n => Some(format!("uses {} unsafe blocks", n)),
+        };
+        match (can.is_empty(), unsafe_blocks) {
+            (true, None) => write!(f, "{} has no detected capabilities", self.crate_name),
+            (true, Some(u)) => write!(f, "{} {}", self.crate_name, u),
+            (false, None) => write!(f, "{} can {}", self.crate_name, join_list(&can)),
+            (false, Some(u)) => {
+                let sep = if can.len() > 1 { "," } else { "" };
+                write!(f, "{} can {}{} and {}", self.crate_name, join_list(&can), sep, u)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ident::{CanonicalPath, PatternSet};
+    use crate::sink::Sink;
+    use std::path::Path;
+
+    #[test]
+    fn test_capability_summary() {
+        let tokens =
+            "remove_file spawn getuid".parse::<proc_macro2::TokenStream>().unwrap();
+        let sinks = PatternSet::from_strs(["std::fs", "std::process"]);
+        let effects = tokens
+            .into_iter()
+            .zip(["std::fs::remove_file", "std::process::Command::spawn", "libc::getuid"])
+            .map(|(t, callee)| {
+                let callee = CanonicalPath::new(callee);
+                let eff_type = match Sink::new_match(&callee, &sinks) {
+                    Some(sink) => Effect::SinkCall(sink),
+                    None => Effect::FFICall(callee.clone()),
+                };
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("mycrate::f"),
+                    callee,
+                    &t,
+                    eff_type,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let summary = CapabilitySummary::new("mycrate".to_string(), &effects, 12);
+        assert_eq!(
+            summary.to_string(),
+            "mycrate can access the file system, spawn processes, and call foreign (C) \
+            code, and uses 12 unsafe blocks"
+        );
+        let summary = CapabilitySummary::new("mycrate".to_string(), &effects[..1], 0);
+        assert_eq!(summary.to_string(), "mycrate can access the file system");
+        let summary = CapabilitySummary::new("mycrate".to_string(), [], 1);
+        assert_eq!(summary.to_string(), "mycrate uses 1 unsafe block");
+    }
+}
@@ -0,0 +1,177 @@
+//! Side-by-side comparison of alternative crates, e.g. `rustls` vs `openssl`.
+//!
+//! Each crate is downloaded from crates.io and audited with a default audit
+//! chain (as `chain create` would), so the comparison covers the effects in
+//! the crate itself and in its transitive dependencies.
+
+use crate::audit_chain::{create_new_audit_chain, Create};
+use crate::download_crate;
+use crate::effect::EffectType;
+use crate::progress;
+use crate::report::{CrateSummary, RepoSummary};
+use crate::scanner::ScanConfig;
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A crate to compare, given as `name@version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateSpec {
+    pub name: String,
+    pub version: String,
+}
+
+impl FromStr for CrateSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, version) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Expected <crate>@<version>, got: {}", s))?;
+        semver::Version::parse(version)
+            .map_err(|e| anyhow!("Invalid version for {}: {}", name, e))?;
+        Ok(Self { name: name.to_string(), version: version.to_string() })
+    }
+}
+
+impl fmt::Display for CrateSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// The audit summary of one of the compared crates
+#[derive(Debug, Clone)]
+pub struct CrateComparison {
+    pub spec: CrateSpec,
+    /// Effects in the crate itself
+    pub own: CrateSummary,
+    /// Transitive dependencies which have effects
+    pub deps_with_effects: Vec<CrateSummary>,
+    /// Risk score of the crate and all its dependencies
+    pub total_risk_score: u64,
+}
+
+impl CrateComparison {
+    pub fn from_repo_summary(spec: CrateSpec, summary: RepoSummary) -> Result<Self> {
+        let root_id = format!("{}-{}", spec.name, spec.version);
+        let (own, deps): (Vec<_>, Vec<_>) =
+            summary.crates.into_iter().partition(|c| c.crate_id == root_id);
+        let own = own
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Missing audit for {} in its audit chain", spec))?;
+        let deps_with_effects =
+            deps.into_iter().filter(|c| c.total_effects > 0).collect();
+        Ok(Self { spec, own, deps_with_effects, total_risk_score: summary.risk_score })
+    }
+
+    pub fn deps_effects(&self) -> usize {
+        self.deps_with_effects.iter().map(|c| c.total_effects).sum()
+    }
+}
+
+/// Download and audit a crate in the working directory
+pub fn audit_crate(
+    spec: CrateSpec,
+    work_dir: &Path,
+    effect_types: &[EffectType],
+    config: &ScanConfig,
+) -> Result<CrateComparison> {
+    let crate_dir = work_dir.join(format!("{}-{}", spec.name, spec.version));
+    if !crate_dir.exists() {
+        progress!("Downloading {}", spec);
+        download_crate::download_crate_from_info(
+            &spec.name,
+            &spec.version,
+            &work_dir.to_string_lossy(),
+        )?;
+    }
+
+    let create = Create::new(
+        crate_dir.to_string_lossy().to_string(),
+        work_dir.join(format!("{}.manifest", spec)).to_string_lossy().to_string(),
+        work_dir.join("audits").to_string_lossy().to_string(),
+        true,
+        None,
+        None,
+        effect_types.to_vec(),
+    );
+    let crates_dir = work_dir.join("crates");
+    let mut chain =
+        create_new_audit_chain(create, &crates_dir.to_string_lossy(), config)?;
+    let summary = RepoSummary::from_audit_chain(spec.to_string(), &mut chain)?;
+    CrateComparison::from_repo_summary(spec, summary)
+}
+
+/// A side-by-side table of the compared crates
+pub struct ComparisonTable<'a>(pub &'a [CrateComparison]);
+
+impl fmt::Display for ComparisonTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let crates = self.0;
+        let kinds = crates
+            .iter()
+            .flat_map(|c| c.own.effects_by_type.keys())
+            .collect::<BTreeSet<_>>();
+
+        let mut rows: Vec<(String, Vec<String>)> = Vec::new();
+        rows.push(("".to_string(), crates.iter().map(|c| c.spec.to_string()).collect()));
+        let mut row = |label: &str, value: &dyn Fn(&CrateComparison) -> String| {
+            rows.push((label.to_string(), crates.iter().map(value).collect()));
+        };
+        row("effects", &|c| c.own.total_effects.to_string());
+        for kind in kinds {
+            row(&format!("  {}", kind), &|c| {
+                c.own.effects_by_type.get(kind).copied().unwrap_or(0).to_string()
+            });
+        }
+        row("deps with effects", &|c| c.deps_with_effects.len().to_string());
+        row("effects in deps", &|c| c.deps_effects().to_string());
+        row("risk score (crate)", &|c| c.own.risk_score.to_string());
+        row("risk score (total)", &|c| c.total_risk_score.to_string());
+
+        let label_width = rows.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+        let col_widths = (0..crates.len())
+            .map(|i| rows.iter().map(|(_, vs)| vs[i].len()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        for (label, values) in &rows {
+            write!(f, "{:width$}", label, width = label_width)?;
+            for (v, width) in values.iter().zip(&col_widths) {
+                write!(f, "  {:>width$}", v, width = width)?;
+            }
+            writeln!(f)?;
+        }
+
+        for c in crates.iter().filter(|c| !c.deps_with_effects.is_empty()) {
+            writeln!(f)?;
+            writeln!(f, "Dependencies of {} with effects:", c.spec)?;
+            for dep in &c.deps_with_effects {
+                writeln!(
+                    f,
+                    "  {} ({} effects, risk score {})",
+                    dep.crate_id, dep.total_effects, dep.risk_score
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crate_spec() {
+        let spec = "rustls@0.21.7".parse::<CrateSpec>().unwrap();
+        assert_eq!(spec.name, "rustls");
+        assert_eq!(spec.version, "0.21.7");
+        assert_eq!(spec.to_string(), "rustls@0.21.7");
+        assert!("rustls".parse::<CrateSpec>().is_err());
+        assert!("rustls@latest".parse::<CrateSpec>().is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! SQLite storage backend for scan results and audits.
+//!
+//! Scan results and audit annotations can be stored in a single database file
+//! (e.g. `--db scan.db`) so that large result sets can be sliced quickly and
+//! shared. Queries are indexed by crate, effect kind, callee prefix, and
+//! audit status.
+
+use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+use crate::effect::{EffectInstance, EffectType};
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS effects (
+        id INTEGER PRIMARY KEY,
+        crate TEXT NOT NULL,
+        caller TEXT NOT NULL,
+        callee TEXT NOT NULL,
+        effect_kind TEXT NOT NULL,
+        effect TEXT NOT NULL,
+        dir TEXT NOT NULL,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        col INTEGER NOT NULL,
+        audit_status TEXT,
+        UNIQUE (crate, caller, callee, effect, dir, file, line, col)
+    );
+    CREATE INDEX IF NOT EXISTS effects_crate ON effects (crate);
+    CREATE INDEX IF NOT EXISTS effects_kind ON effects (effect_kind);
+    CREATE INDEX IF NOT EXISTS effects_callee ON effects (callee);
+    CREATE INDEX IF NOT EXISTS effects_audit_status ON effects (audit_status);
+";
+
+/// A single row of the effects table
+#[derive(Debug, Clone)]
+pub struct EffectRow {
+    pub crate_name: String,
+    pub caller: String,
+    pub callee: String,
+    pub effect_kind: String,
+    pub effect: String,
+    pub dir: String,
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub audit_status: Option<String>,
+}
+
+impl EffectRow {
+    pub fn csv_header() -> &'static str {
+        "crate, fn_decl, callee, effect, dir, file, line, col, audit_status"
+    }
+
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{}, {}, {}, {}, {}, {}, {}, {}, {}",
+            self.crate_name,
+            self.caller,
+            self.callee,
+            self.effect,
+            self.dir,
+            self.file,
+            self.line,
+            self.col,
+            self.audit_status.as_deref().unwrap_or("None"),
+        )
+    }
+}
+
+/// Filters for querying effects; all filters which are set must match
+#[derive(Debug, Clone, Default)]
+pub struct EffectQuery {
+    pub crate_name: Option<String>,
+    pub effect_kind: Option<EffectType>,
+    /// A path prefix of the callee, matched by whole segments: `libc`
+    /// matches `libc::fork` but not `libcx::fork`
+    pub callee_prefix: Option<String>,
+    pub audit_status: Option<String>,
+}
+
+pub struct ScanDb {
+    conn: Connection,
+}
+
+impl ScanDb {
+    /// Open the database at the given path, creating it if it doesn't exist
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Insert the effects from a scan. Effects which are already in the
+    /// database keep their existing audit status.
+    pub fn insert_effects<'a, I>(&mut self, effects: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO effects
+                    (crate, caller, callee, effect_kind, effect, dir, file, line, col)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for e in effects {
+                let loc = e.call_loc();
+                stmt.execute(params![
+                    e.caller().crate_name().as_str(),
+                    e.caller_path(),
+                    e.callee_path(),
+                    EffectType::from(e.eff_type()).to_string(),
+                    e.eff_type().simple_str(),
+                    loc.dir().to_string_lossy(),
+                    loc.file().to_string_lossy(),
+                    loc.start_line(),
+                    loc.start_col(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Insert the base effects of an audit file along with their current
+    /// audit status
+    pub fn insert_audit(&mut self, audit_file: &AuditFile) -> Result<()> {
+        self.insert_effects(audit_file.audit_trees.keys())?;
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE effects SET audit_status = ?1
+                 WHERE crate = ?2 AND caller = ?3 AND callee = ?4 AND effect = ?5
+                    AND dir = ?6 AND file = ?7 AND line = ?8 AND col = ?9",
+            )?;
+            for (e, tree) in audit_file.audit_trees.iter() {
+                let loc = e.call_loc();
+                stmt.execute(params![
+                    audit_status(tree).to_string(),
+                    e.caller().crate_name().as_str(),
+                    e.caller_path(),
+                    e.callee_path(),
+                    e.eff_type().simple_str(),
+                    loc.dir().to_string_lossy(),
+                    loc.file().to_string_lossy(),
+                    loc.start_line(),
+                    loc.start_col(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Query the effects table, returning all rows matching the filters
+    pub fn query_effects(&self, query: &EffectQuery) -> Result<Vec<EffectRow>> {
+        let mut sql = "SELECT crate, caller, callee, effect_kind, effect, dir, file, \
+                       line, col, audit_status FROM effects WHERE 1 = 1"
+            .to_string();
+        let mut args: Vec<String> = Vec::new();
+        if let Some(c) = &query.crate_name {
+            args.push(c.clone());
+            sql.push_str(&format!(" AND crate = ?{}", args.len()));
+        }
+        if let Some(k) = &query.effect_kind {
+            args.push(k.to_string());
+            sql.push_str(&format!(" AND effect_kind = ?{}", args.len()));
+        }
+        if let Some(p) = &query.callee_prefix {
+            // Either the path itself or a path under it. Use a range query
+            // instead of LIKE so the callee index is used
+            let p = p.trim_end_matches("::");
+            args.push(p.to_string());
+            sql.push_str(&format!(" AND (callee = ?{}", args.len()));
+            args.push(format!("{}::", p));
+            sql.push_str(&format!(" OR callee >= ?{}", args.len()));
+            args.push(format!("{}::\u{10ffff}", p));
+            sql.push_str(&format!(" AND callee < ?{})", args.len()));
+        }
+        if let Some(s) = &query.audit_status {
+            args.push(s.clone());
+            sql.push_str(&format!(" AND audit_status = ?{}", args.len()));
+        }
+        sql.push_str(" ORDER BY crate, dir, file, line, col");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(args.iter()), |row| {
+            Ok(EffectRow {
+                crate_name: row.get(0)?,
+                caller: row.get(1)?,
+                callee: row.get(2)?,
+                effect_kind: row.get(3)?,
+                effect: row.get(4)?,
+                dir: row.get(5)?,
+                file: row.get(6)?,
+                line: row.get(7)?,
+                col: row.get(8)?,
+                audit_status: row.get(9)?,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+/// The audit status of a base effect: the annotation of a leaf, or
+/// caller-checked if the tree has been expanded into its callers.
+fn audit_status(tree: &EffectTree) -> SafetyAnnotation {
+    match tree {
+        EffectTree::Leaf(_, a) => *a,
+        EffectTree::Branch(_, _) => SafetyAnnotation::CallerChecked,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_insert_audit() {
+        let effects = ["getuid", "fork"]
+            .iter()
+            .map(|f| {
+                let callee = CanonicalPath::new(&format!("libc::{}", f));
+                let ident = syn::Ident::new(f, proc_macro2::Span::call_site());
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &ident,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit.set_base_audit_trees(effects.iter());
+        audit
+            .audit_trees
+            .get_mut(&effects[1])
+            .unwrap()
+            .set_annotation(SafetyAnnotation::Safe);
+
+        let mut db = ScanDb::open(Path::new(":memory:")).unwrap();
+        db.insert_audit(&audit).unwrap();
+        // Rescanning keeps the audit status
+        db.insert_effects(effects.iter()).unwrap();
+
+        let all = db.query_effects(&EffectQuery::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(all.iter().all(|row| row.crate_name == "test"));
+        let safe =
+            EffectQuery { audit_status: Some("Safe".to_string()), ..Default::default() };
+        let rows = db.query_effects(&safe).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].callee, "libc::fork");
+        let getuid = EffectQuery {
+            callee_prefix: Some("libc::getuid".to_string()),
+            ..Default::default()
+        };
+        let rows = db.query_effects(&getuid).unwrap();
+        assert_eq!(rows[0].audit_status.as_deref(), Some("Unreviewed"));
+        // Prefixes match whole path segments
+        let prefix = |p: &str| EffectQuery {
+            callee_prefix: Some(p.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.query_effects(&prefix("libc")).unwrap().len(), 2);
+        assert_eq!(db.query_effects(&prefix("libc::")).unwrap().len(), 2);
+        assert!(db.query_effects(&prefix("lib")).unwrap().is_empty());
+        assert!(db.query_effects(&prefix("libc::g")).unwrap().is_empty());
+        let ffi =
+            EffectQuery { effect_kind: Some(EffectType::FFICall), ..Default::default() };
+        assert_eq!(db.query_effects(&ffi).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,271 @@
+//! Cache of a crate's resolved dependency graph, keyed by the hash of its
+//! `Cargo.lock` and the manifests of its workspace.
+//!
+//! Resolving the dependencies of a crate with cargo (loading the workspace,
+//! querying the registry, and locating or downloading each crate's sources)
+//! takes seconds even when nothing needs to be downloaded. The result only
+//! changes with the lockfile and the local manifests (the workspace's
+//! `Cargo.toml` and those of its members and path dependencies, whose
+//! features and dev-dependencies the lockfile doesn't record), so it is saved
+//! to `target/cargo-scan/dep-graph.json` in the crate with their SHA-256
+//! hash, and reused while the hash matches and the crate sources are still
+//! there. Commands which only need the package list (e.g. `chain tree`
+//! and `chain check`) read the lockfile directly and don't resolve at all.
+//!
+//! Crates without a lockfile get one on the first resolve.
+
+use crate::metrics::metrics;
+use crate::progress;
+use crate::util::CrateId;
+
+use anyhow::{Context, Result};
+use cargo::core::dependency::DepKind;
+use cargo::core::Workspace;
+use cargo::ops::{fetch, FetchOptions};
+use cargo::util::config;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// A dependency of a resolved crate, as declared in its Cargo.toml
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedDep {
+    pub crate_id: CrateId,
+    /// Name of the dependency in the Cargo.toml, which may be a rename
+    pub name: String,
+    pub version_req: String,
+    pub optional: bool,
+    /// Whether it is only a dev-dependency
+    pub dev_only: bool,
+}
+
+/// A crate of the dependency graph with the directory of its sources
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCrate {
+    pub crate_id: CrateId,
+    pub path: PathBuf,
+    pub deps: Vec<ResolvedDep>,
+    /// The features resolved for the crate
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DepGraph {
+    /// SHA-256 hash of the Cargo.lock and the manifests the graph was
+    /// resolved from
+    pub lock_hash: String,
+    /// The local manifests: the workspace's Cargo.toml, and those of the
+    /// crates with path sources
+    pub manifests: Vec<PathBuf>,
+    pub root: CrateId,
+    /// All crates of the resolve (including those only used by
+    /// dev-dependencies), in the order cargo lists them
+    pub crates: Vec<ResolvedCrate>,
+}
+
+/// The hash of the crate's Cargo.lock and the manifests, or None if it has
+/// no lockfile
+pub fn lock_hash(crate_path: &Path, manifests: &[PathBuf]) -> Result<Option<String>> {
+    let lockfile = crate_path.join("Cargo.lock");
+    if !lockfile.is_file() {
+        return Ok(None);
+    }
+    let mut hasher = Sha256::new();
+    for path in std::iter::once(&lockfile).chain(manifests) {
+        let data = std::fs::read(path).context(format!("Failed to read {:?}", path))?;
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    }
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+/// Where the graph of the crate is cached
+pub fn cache_path(crate_path: &Path) -> PathBuf {
+    crate_path.join("target").join("cargo-scan").join("dep-graph.json")
+}
+
+impl DepGraph {
+    /// Resolve the graph with cargo, downloading the crates if needed
+    pub fn resolve(crate_path: &Path) -> Result<Self> {
+        let crate_path = crate_path.canonicalize()?;
+        let manifest = crate_path.join("Cargo.toml");
+        let config = config::Config::default()?;
+        let _lock = config.acquire_package_cache_lock();
+        let workspace = Workspace::new(&manifest, &config)?;
+        let root = workspace.current()?.package_id();
+        let fetch_options = FetchOptions { config: &config, targets: Vec::new() };
+        let (resolve, package_set) = fetch(&workspace, &fetch_options)?;
+        let mut manifests = BTreeSet::from([workspace.root_manifest().to_path_buf()]);
+        for id in resolve.iter().filter(|id| id.source_id().is_path()) {
+            manifests.insert(package_set.get_one(id)?.manifest_path().to_path_buf());
+        }
+        let manifests = manifests.into_iter().collect::<Vec<_>>();
+        // Hashed after resolving, which creates the lockfile if it is missing
+        let lock_hash = lock_hash(&crate_path, &manifests)?.unwrap_or_default();
+
+        let crate_id = |id: cargo::core::PackageId| {
+            CrateId::new(id.name().to_string(), id.version().clone())
+        };
+        let mut crates = Vec::new();
+        for id in resolve.iter() {
+            let mut deps = Vec::new();
+            for (dep_id, declared) in resolve.deps(id) {
+                let dev_only = declared.iter().all(|d| d.kind() == DepKind::Development);
+                let dep = declared
+                    .iter()
+                    .find(|d| dev_only || d.kind() != DepKind::Development)
+                    .context("Dependency without a declaration")?;
+                deps.push(ResolvedDep {
+                    crate_id: crate_id(dep_id),
+                    name: dep.name_in_toml().to_string(),
+                    version_req: dep.version_req().to_string(),
+                    optional: dep.is_optional(),
+                    dev_only,
+                });
+            }
+            crates.push(ResolvedCrate {
+                crate_id: crate_id(id),
+                path: package_set.get_one(id)?.root().to_path_buf(),
+                deps,
+                features: resolve.features(id).iter().map(|f| f.to_string()).collect(),
+            });
+        }
+        Ok(Self { lock_hash, manifests, root: crate_id(root), crates })
+    }
+
+    /// The graph cached for the crate, if it is up-to-date with the lockfile
+    /// and manifests
+    pub fn cached(crate_path: &Path) -> Result<Option<Self>> {
+        let Ok(json) = read_to_string(cache_path(crate_path)) else {
+            return Ok(None);
+        };
+        // An unreadable cache (e.g. from another version) is resolved again
+        let Ok(graph) = serde_json::from_str::<Self>(&json) else {
+            return Ok(None);
+        };
+        // e.g. a workspace member which has been removed
+        if !graph.manifests.iter().all(|m| m.is_file()) {
+            return Ok(None);
+        }
+        let Some(hash) = lock_hash(crate_path, &graph.manifests)? else {
+            return Ok(None);
+        };
+        let fresh =
+            graph.lock_hash == hash && graph.crates.iter().all(|c| c.path.is_dir());
+        Ok(fresh.then_some(graph))
+    }
+
+    /// The graph of the crate, from the cache if it is up-to-date, and
+    /// otherwise resolved and cached
+    pub fn load(crate_path: &Path) -> Result<Self> {
+        let cached = Self::cached(crate_path)?;
+        metrics().cache_lookup("dep-graph", cached.is_some());
+        if let Some(graph) = cached {
+            return Ok(graph);
+        }
+        progress!("Resolving dependencies...");
+        let graph = metrics().time_phase("resolve-deps", || Self::resolve(crate_path))?;
+        if !graph.lock_hash.is_empty() {
+            graph.save(crate_path)?;
+        }
+        Ok(graph)
+    }
+
+    fn save(&self, crate_path: &Path) -> Result<()> {
+        let path = cache_path(crate_path);
+        create_dir_all(path.parent().unwrap())?;
+        write(&path, serde_json::to_string(self)?)
+            .context(format!("Failed to write {:?}", path))
+    }
+
+    pub fn get(&self, crate_id: &CrateId) -> Option<&ResolvedCrate> {
+        self.crates.iter().find(|c| &c.crate_id == crate_id)
+    }
+
+    /// The crates the root depends on (other than through dev-dependencies),
+    /// starting with the root, in breadth-first order
+    pub fn reachable(&self) -> Vec<&ResolvedCrate> {
+        let mut seen = HashSet::from([&self.root]);
+        let mut queue = VecDeque::from([&self.root]);
+        let mut res = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            let Some(krate) = self.get(id) else {
+                continue;
+            };
+            res.push(krate);
+            for dep in krate.deps.iter().filter(|d| !d.dev_only) {
+                if seen.insert(&dep.crate_id) {
+                    queue.push_back(&dep.crate_id);
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+
+    fn krate(name: &str, path: &Path, deps: &[(&str, bool)]) -> ResolvedCrate {
+        let id = |name: &str| CrateId::new(name.to_string(), Version::new(1, 0, 0));
+        ResolvedCrate {
+            crate_id: id(name),
+            path: path.to_path_buf(),
+            deps: deps
+                .iter()
+                .map(|&(name, dev_only)| ResolvedDep {
+                    crate_id: id(name),
+                    name: name.to_string(),
+                    version_req: "1".to_string(),
+                    optional: false,
+                    dev_only,
+                })
+                .collect(),
+            features: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_dep_graph_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        assert_eq!(DepGraph::cached(dir).unwrap(), None);
+        write(dir.join("Cargo.lock"), "version = 3\n").unwrap();
+        let manifests = vec![dir.join("Cargo.toml")];
+        write(&manifests[0], "[package]\nname = \"app\"\n").unwrap();
+
+        let graph = DepGraph {
+            lock_hash: lock_hash(dir, &manifests).unwrap().unwrap(),
+            manifests,
+            root: CrateId::new("app".to_string(), Version::new(1, 0, 0)),
+            crates: vec![
+                krate("app", dir, &[("libc", false), ("criterion", true)]),
+                krate("criterion", dir, &[("serde", false)]),
+                krate("libc", dir, &[]),
+                krate("serde", dir, &[]),
+            ],
+        };
+        let names = |g: &DepGraph| {
+            g.reachable()
+                .iter()
+                .map(|c| c.crate_id.crate_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&graph), ["app", "libc"]);
+
+        graph.save(dir).unwrap();
+        assert_eq!(DepGraph::cached(dir).unwrap(), Some(graph.clone()));
+        // A new manifest or lockfile invalidates the cache
+        write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\n[features]\n").unwrap();
+        assert_eq!(DepGraph::cached(dir).unwrap(), None);
+        write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        assert_eq!(DepGraph::cached(dir).unwrap(), Some(graph));
+        write(dir.join("Cargo.lock"), "version = 4\n").unwrap();
+        assert_eq!(DepGraph::cached(dir).unwrap(), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! An effect-aware analog of `cargo tree`: the dependency tree of an audit
+//! chain with each crate annotated with its effect count and audit status,
+//! e.g.
+//! ```text
+//! myapp v0.1.0 [2 effects, audited]
+//! ├── native-tls v0.2.11 [3 effects, audited]
+//! │   └── openssl v0.10.57 [212 effects, partially-audited]
+//! │       └── libc v0.2.148 [48 effects, unaudited]
+//! └── openssl v0.10.57 [212 effects, partially-audited] (*)
+//! ```
+//! As in `cargo tree`, the dependencies of a crate which has already been
+//! printed are elided with `(*)`. Inverted, the tree starts from a crate and
+//! lists the crates depending on it up to the root crate, i.e. the paths
+//! pulling it in.
+
+use crate::audit_chain::AuditChain;
+use crate::report::CrateSummary;
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DepTree {
+    /// Node of the root crate
+    root: String,
+    /// Summary of each crate in the chain, by node (`<crate> v<version>`)
+    summaries: BTreeMap<String, CrateSummary>,
+    /// Dependencies of each crate, by node
+    deps: BTreeMap<String, BTreeSet<String>>,
+}
+
+fn node(crate_name: &str, version: impl ToString) -> String {
+    format!("{} v{}", crate_name, version.to_string())
+}
+
+impl DepTree {
+    pub fn new(
+        root: String,
+        summaries: BTreeMap<String, CrateSummary>,
+        deps: BTreeMap<String, BTreeSet<String>>,
+    ) -> Self {
+        Self { root, summaries, deps }
+    }
+
+    pub fn from_audit_chain(chain: &mut AuditChain) -> Result<Self> {
+        let root_id = chain.root_crate()?;
+        let crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+        let mut summaries = BTreeMap::new();
+        for crate_id in crate_ids {
+            if let Some(audit_file) = chain.read_audit_file(&crate_id)? {
+                let summary =
+                    CrateSummary::from_audit_file(crate_id.to_string(), &audit_file);
+                summaries.insert(node(&crate_id.crate_name, &crate_id.version), summary);
+            }
+        }
+
+        let lockfile = chain.load_lockfile()?;
+        let deps = lockfile
+            .packages
+            .iter()
+            .map(|p| {
+                let deps = p
+                    .dependencies
+                    .iter()
+                    .map(|d| node(d.name.as_str(), &d.version))
+                    .collect();
+                (node(p.name.as_str(), &p.version), deps)
+            })
+            .collect();
+
+        Ok(Self::new(node(&root_id.crate_name, &root_id.version), summaries, deps))
+    }
+
+    fn annotation(&self, node: &str) -> String {
+        match self.summaries.get(node) {
+            Some(s) => format!("[{} effects, {}]", s.total_effects, s.audit_status()),
+            None => "[not scanned]".to_string(),
+        }
+    }
+
+    /// The tree from the node, following the edges
+    fn render_from(
+        &self,
+        start: &str,
+        edges: &BTreeMap<String, BTreeSet<String>>,
+    ) -> String {
+        let mut out = String::new();
+        let mut printed = BTreeSet::new();
+        self.render_node(start, edges, "", "", &mut printed, &mut out);
+        out
+    }
+
+    fn render_node(
+        &self,
+        node: &str,
+        edges: &BTreeMap<String, BTreeSet<String>>,
+        branch: &str,
+        prefix: &str,
+        printed: &mut BTreeSet<String>,
+        out: &mut String,
+    ) {
+        let children = edges.get(node).into_iter().flatten().collect::<Vec<_>>();
+        let first = printed.insert(node.to_string());
+        let elided = if !first && !children.is_empty() { " (*)" } else { "" };
+        writeln!(out, "{}{} {}{}", branch, node, self.annotation(node), elided).unwrap();
+        if !first {
+            return;
+        }
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) =
+                if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            self.render_node(
+                child,
+                edges,
+                &format!("{}{}", prefix, branch),
+                &format!("{}{}", prefix, indent),
+                printed,
+                out,
+            );
+        }
+    }
+
+    /// The tree of the root crate's dependencies
+    pub fn render(&self) -> String {
+        self.render_from(&self.root, &self.deps)
+    }
+
+    /// The trees of the crates depending on each version of the crate, given
+    /// as `<crate>` or `<crate>@<version>`, up to the root crate
+    pub fn render_inverted(&self, spec: &str) -> Result<String> {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec, None),
+        };
+        let starts = self
+            .deps
+            .keys()
+            .filter(|n| match version {
+                Some(v) => **n == node(name, v),
+                None => n.split_once(' ').map_or(false, |(n, _)| n == name),
+            })
+            .collect::<Vec<_>>();
+        if starts.is_empty() {
+            return Err(anyhow!("{} isn't in the dependency tree", spec));
+        }
+
+        let mut dependents: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (n, deps) in &self.deps {
+            for dep in deps {
+                dependents.entry(dep.clone()).or_default().insert(n.clone());
+            }
+        }
+        Ok(starts
+            .into_iter()
+            .map(|start| self.render_from(start, &dependents))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(total_effects: usize, unaudited_effects: usize) -> CrateSummary {
+        CrateSummary {
+            crate_id: String::new(),
+            total_effects,
+            unaudited_effects,
+            unsafe_effects: 0,
+            pub_caller_checked: 0,
+            effects_by_type: BTreeMap::new(),
+            risk_score: 0,
+        }
+    }
+
+    #[test]
+    fn test_dep_tree() {
+        let deps =
+            |ds: &[&str]| ds.iter().map(|d| d.to_string()).collect::<BTreeSet<_>>();
+        let tree = DepTree::new(
+            "app v0.1.0".to_string(),
+            BTreeMap::from([
+                ("app v0.1.0".to_string(), summary(2, 0)),
+                ("libc v0.2.0".to_string(), summary(48, 48)),
+                ("openssl v0.10.0".to_string(), summary(212, 10)),
+            ]),
+            BTreeMap::from([
+                ("app v0.1.0".to_string(), deps(&["openssl v0.10.0", "zlib v1.0.0"])),
+                ("openssl v0.10.0".to_string(), deps(&["libc v0.2.0"])),
+                ("zlib v1.0.0".to_string(), deps(&["libc v0.2.0"])),
+                ("libc v0.2.0".to_string(), deps(&[])),
+            ]),
+        );
+        assert_eq!(
+            tree.render(),
+            "\
+app v0.1.0 [2 effects, audited]
+├── openssl v0.10.0 [212 effects, partially-audited]
+│   └── libc v0.2.0 [48 effects, unaudited]
+└── zlib v1.0.0 [not scanned]
+    └── libc v0.2.0 [48 effects, unaudited]
+"
+        );
+        assert_eq!(
+            tree.render_inverted("libc").unwrap(),
+            "\
+libc v0.2.0 [48 effects, unaudited]
+├── openssl v0.10.0 [212 effects, partially-audited]
+│   └── app v0.1.0 [2 effects, audited]
+└── zlib v1.0.0 [not scanned]
+    └── app v0.1.0 [2 effects, audited]
+"
+        );
+        assert!(tree.render_inverted("libc@0.3.0").is_err());
+    }
+}
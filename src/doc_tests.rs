@@ -0,0 +1,155 @@
+//! Extracting the doc-tests (Rust code blocks in `///` and `//!` doc
+//! comments) from a source file, so they can be scanned.
+//!
+//! Each doc-test is returned as source code laid out like the file it came
+//! from: the code stays on its original lines and columns, with the comment
+//! markers replaced by spaces, so locations in the doc-test are locations in
+//! the file. As rustdoc does, the code is wrapped in `fn main() { .. }` (on
+//! the lines of the code fences) unless it declares a `main` itself, and
+//! hidden lines (`# let x = 1;`) are included.
+
+/// Code block attributes for blocks which rustdoc doesn't compile
+const SKIPPED_ATTRS: &[&str] = &["ignore", "text", "compile_fail"];
+
+/// Code block attributes for Rust code other than "rust"
+const RUST_ATTRS: &[&str] =
+    &["should_panic", "no_run", "test_harness", "allow_fail", "standalone_crate"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocTest {
+    /// Line of the opening code fence (1-indexed)
+    pub line: usize,
+    pub source: String,
+}
+
+/// Whether a code block with the info string is a Rust doc-test
+fn is_doc_test(info: &str) -> bool {
+    let attrs = info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect::<Vec<_>>();
+    let skipped =
+        attrs.iter().any(|a| SKIPPED_ATTRS.contains(a) || a.starts_with("ignore-"));
+    let rust = attrs
+        .iter()
+        .all(|a| *a == "rust" || RUST_ATTRS.contains(a) || a.starts_with("edition"));
+    !skipped && (rust || attrs.contains(&"rust"))
+}
+
+/// The text of a doc comment line, and the column it starts at
+fn doc_comment(line: &str) -> Option<(&str, usize)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("////") {
+        return None;
+    }
+    let text = trimmed.strip_prefix("///").or_else(|| trimmed.strip_prefix("//!"))?;
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    Some((text, line.len() - text.len()))
+}
+
+/// A line of a doc-test, with the hidden line marker removed
+fn code_line(text: &str, col: usize) -> String {
+    let indent = text.len() - text.trim_start().len();
+    let code = text.trim_start();
+    let code = match code.strip_prefix("# ") {
+        Some(hidden) => format!("  {}", hidden),
+        None if code == "#" => String::new(),
+        None => code.to_string(),
+    };
+    format!("{}{}", " ".repeat(col + indent), code)
+}
+
+enum Block {
+    /// Not in a code block
+    Outside,
+    /// In a code block which isn't a doc-test
+    Other,
+    /// In a doc-test starting at the line, with its lines so far
+    DocTest(usize, Vec<String>),
+}
+
+/// The doc-tests in the source file
+pub fn extract_doc_tests(src: &str) -> Vec<DocTest> {
+    let mut doc_tests = Vec::new();
+    let mut block = Block::Outside;
+    for (i, line) in src.lines().enumerate() {
+        let Some((text, col)) = doc_comment(line) else {
+            // The doc comment ended inside a code block
+            block = Block::Outside;
+            continue;
+        };
+        let fence = text.trim_start().strip_prefix("```");
+        block = match (block, fence) {
+            (Block::Outside, Some(info)) if is_doc_test(info) => {
+                Block::DocTest(i, Vec::new())
+            }
+            (Block::Outside, Some(_)) => Block::Other,
+            (Block::Other, Some(_)) => Block::Outside,
+            (Block::DocTest(start, code), Some(_)) => {
+                doc_tests.push(doc_test(start, &code));
+                Block::Outside
+            }
+            (Block::DocTest(start, mut code), None) => {
+                code.push(code_line(text, col));
+                Block::DocTest(start, code)
+            }
+            (block, None) => block,
+        };
+    }
+    doc_tests
+}
+
+fn doc_test(start: usize, code: &[String]) -> DocTest {
+    let has_main = code.iter().any(|l| l.contains("fn main"));
+    let (open, close) = if has_main { ("", "") } else { ("fn main() {", "}") };
+    let mut lines = vec![""; start];
+    lines.push(open);
+    lines.extend(code.iter().map(|l| l.as_str()));
+    lines.push(close);
+    DocTest { line: start + 1, source: lines.join("\n") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_doc_tests() {
+        let src = "\
+/// Reads the file
+///
+/// ```
+/// # use std::fs;
+/// let s = fs::read(\"f\");
+/// ```
+///
+/// ```text
+/// not code
+/// ```
+///
+/// ```rust,ignore
+/// ignored();
+/// ```
+pub fn read() {}";
+        let doc_tests = extract_doc_tests(src);
+        assert_eq!(doc_tests.len(), 1);
+        assert_eq!(doc_tests[0].line, 3);
+        let lines = doc_tests[0].source.lines().collect::<Vec<_>>();
+        assert_eq!(lines[2], "fn main() {");
+        assert_eq!(lines[3], "      use std::fs;");
+        assert_eq!(lines[4], "    let s = fs::read(\"f\");");
+        assert_eq!(lines[5], "}");
+        assert!(syn::parse_file(&doc_tests[0].source).is_ok());
+    }
+
+    #[test]
+    fn test_is_doc_test() {
+        assert!(is_doc_test(""));
+        assert!(is_doc_test("rust"));
+        assert!(is_doc_test("no_run"));
+        assert!(is_doc_test("rust,edition2021"));
+        assert!(!is_doc_test("rust,ignore"));
+        assert!(!is_doc_test("compile_fail"));
+        assert!(!is_doc_test("toml"));
+    }
+}
@@ -0,0 +1,298 @@
+//! Rust editions, and the edition-specific rewriting of a crate's syntax
+//! before it is scanned.
+//!
+//! In the 2015 edition, `use` paths and paths starting with `::` are relative
+//! to the crate root unless they start with an extern crate. The scanner
+//! (and the syntactic resolver in particular) assumes 2018 paths, so 2015
+//! files are rewritten to use explicit `crate::` paths. `extern crate a as b`
+//! is rewritten to `use a as b`, so that the alias is resolved.
+
+use crate::effect::EffectInstance;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use parse_display::{Display, FromStr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path as FilePath;
+use syn::visit_mut::{self, VisitMut};
+use toml::value::{Table, Value};
+
+/// Crates which are always in scope, without a dependency or `extern crate`
+const BUILTIN_CRATES: &[&str] = &["std", "core", "alloc", "proc_macro", "test"];
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum Edition {
+    /// The edition of crates which don't set one
+    #[default]
+    #[display("2015")]
+    #[serde(rename = "2015")]
+    E2015,
+    #[display("2018")]
+    #[serde(rename = "2018")]
+    E2018,
+    #[display("2021")]
+    #[serde(rename = "2021")]
+    E2021,
+    #[display("2024")]
+    #[serde(rename = "2024")]
+    E2024,
+}
+
+/// How to treat unsafe operations which are only allowed by the enclosing
+/// `unsafe fn`, outside of any unsafe block. From the 2024 edition, they need
+/// their own unsafe block (the `unsafe_op_in_unsafe_fn` lint warns by default).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnsafeFnOpsPolicy {
+    /// Don't report them
+    Allow,
+    /// Report them as needing cleanup
+    Warn,
+    /// Report them and fail the scan
+    Deny,
+}
+
+impl UnsafeFnOpsPolicy {
+    /// The level of the `unsafe_op_in_unsafe_fn` lint in the edition
+    pub fn edition_default(edition: Edition) -> Self {
+        if edition >= Edition::E2024 {
+            Self::Warn
+        } else {
+            Self::Allow
+        }
+    }
+
+    /// The effects the policy flags
+    pub fn flagged(self, effects: &[EffectInstance]) -> Vec<&EffectInstance> {
+        match self {
+            Self::Allow => Vec::new(),
+            Self::Warn | Self::Deny => {
+                effects.iter().filter(|e| e.in_unsafe_fn_body()).collect()
+            }
+        }
+    }
+}
+
+/// The crate's edition and the names of the extern crates it can refer to
+#[derive(Debug, Clone, Default)]
+pub struct CrateEdition {
+    pub edition: Edition,
+    extern_crates: HashSet<String>,
+}
+
+impl CrateEdition {
+    pub fn new(edition: Edition, extern_crates: HashSet<String>) -> Self {
+        Self { edition, extern_crates }
+    }
+
+    /// Load the edition and the dependencies of the crate from its Cargo.toml
+    pub fn load(crate_path: &FilePath) -> Result<Self> {
+        let toml_string = std::fs::read_to_string(crate_path.join("Cargo.toml"))?;
+        let cargo_toml =
+            toml::from_str::<Table>(&toml_string).context("Couldn't parse Cargo.toml")?;
+
+        let package = cargo_toml.get("package").and_then(Value::as_table);
+        let edition = match package.and_then(|p| p.get("edition")) {
+            None => Edition::default(),
+            Some(Value::String(s)) => {
+                s.parse().map_err(|_| anyhow!("Unknown edition: {}", s))?
+            }
+            // edition.workspace = true
+            Some(_) => {
+                let workspace_edition = cargo_toml
+                    .get("workspace")
+                    .and_then(|w| w.get("package"))
+                    .and_then(|p| p.get("edition"))
+                    .and_then(Value::as_str);
+                match workspace_edition {
+                    Some(s) => {
+                        s.parse().map_err(|_| anyhow!("Unknown edition: {}", s))?
+                    }
+                    None => {
+                        debug!("Couldn't find the workspace edition; assuming 2021");
+                        Edition::E2021
+                    }
+                }
+            }
+        };
+
+        let mut extern_crates = HashSet::new();
+        let mut add_deps = |deps: Option<&Value>| {
+            if let Some(deps) = deps.and_then(Value::as_table) {
+                extern_crates.extend(deps.keys().map(|k| k.replace('-', "_")));
+            }
+        };
+        for deps in ["dependencies", "dev-dependencies", "build-dependencies"] {
+            add_deps(cargo_toml.get(deps));
+        }
+        if let Some(targets) = cargo_toml.get("target").and_then(Value::as_table) {
+            for target in targets.values() {
+                for deps in ["dependencies", "dev-dependencies", "build-dependencies"] {
+                    add_deps(target.get(deps));
+                }
+            }
+        }
+
+        Ok(Self { edition, extern_crates })
+    }
+
+    fn is_extern_crate(&self, i: &syn::Ident) -> bool {
+        BUILTIN_CRATES.iter().any(|c| i == c)
+            || self.extern_crates.contains(&i.to_string())
+    }
+
+    /// Rewrite the file's edition-specific syntax (see the module docs)
+    pub fn normalize(&self, file: &mut syn::File) {
+        if self.edition != Edition::E2015 {
+            return;
+        }
+        let mut normalizer = Normalizer { edition: self.clone() };
+        // Crates declared in the file, possibly renamed, are in scope too
+        for item in &file.items {
+            if let syn::Item::ExternCrate(c) = item {
+                let name = c.rename.as_ref().map_or(&c.ident, |(_, rename)| rename);
+                normalizer.edition.extern_crates.insert(name.to_string());
+            }
+        }
+        normalizer.visit_file_mut(file);
+    }
+}
+
+struct Normalizer {
+    edition: CrateEdition,
+}
+
+impl Normalizer {
+    /// Whether a path starting with this segment is relative to the crate root
+    fn is_crate_relative(&self, i: &syn::Ident) -> bool {
+        let keyword = i == "crate" || i == "self" || i == "super" || i == "Self";
+        !keyword && !self.edition.is_extern_crate(i)
+    }
+
+    fn normalize_use_tree(&self, tree: &mut syn::UseTree) {
+        let first = match tree {
+            syn::UseTree::Path(p) => &p.ident,
+            syn::UseTree::Name(n) => &n.ident,
+            syn::UseTree::Rename(r) => &r.ident,
+            syn::UseTree::Group(g) => {
+                for t in g.items.iter_mut() {
+                    self.normalize_use_tree(t);
+                }
+                return;
+            }
+            syn::UseTree::Glob(_) => return,
+        };
+        if self.is_crate_relative(first) {
+            let crate_root = syn::Ident::new("crate", first.span());
+            let inner = std::mem::replace(
+                tree,
+                syn::UseTree::Glob(syn::UseGlob { star_token: Default::default() }),
+            );
+            *tree = syn::UseTree::Path(syn::UsePath {
+                ident: crate_root,
+                colon2_token: Default::default(),
+                tree: Box::new(inner),
+            });
+        }
+    }
+}
+
+impl VisitMut for Normalizer {
+    fn visit_item_mut(&mut self, item: &mut syn::Item) {
+        match item {
+            syn::Item::ExternCrate(c) if c.ident != "self" => {
+                if let Some((as_token, rename)) = &c.rename {
+                    *item = syn::Item::Use(syn::ItemUse {
+                        attrs: c.attrs.clone(),
+                        vis: c.vis.clone(),
+                        use_token: syn::token::Use(c.extern_token.span),
+                        leading_colon: None,
+                        tree: syn::UseTree::Rename(syn::UseRename {
+                            ident: c.ident.clone(),
+                            as_token: *as_token,
+                            rename: rename.clone(),
+                        }),
+                        semi_token: c.semi_token,
+                    });
+                }
+            }
+            syn::Item::Use(u) => {
+                u.leading_colon = None;
+                self.normalize_use_tree(&mut u.tree);
+            }
+            _ => visit_mut::visit_item_mut(self, item),
+        }
+    }
+
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        if path.leading_colon.is_some()
+            && path.segments.first().map_or(false, |s| self.is_crate_relative(&s.ident))
+        {
+            let span = path.segments[0].ident.span();
+            path.leading_colon = None;
+            path.segments
+                .insert(0, syn::PathSegment::from(syn::Ident::new("crate", span)));
+        }
+        visit_mut::visit_path_mut(self, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    #[test]
+    fn test_normalize_2015() {
+        let edition =
+            CrateEdition::new(Edition::E2015, HashSet::from(["libc".to_string()]));
+        let mut file: syn::File = syn::parse_str(
+            "extern crate libc as c;
+            use fs::{read, write};
+            use std::process;
+            use libc::getuid;
+            fn f() { ::util::run(); ::std::fs::remove_file(\"f\"); }",
+        )
+        .unwrap();
+        edition.normalize(&mut file);
+        let normalized = file
+            .items
+            .iter()
+            .map(|i| i.to_token_stream().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(normalized[0], "use libc as c ;");
+        assert_eq!(normalized[1], "use crate :: fs :: { read , write } ;");
+        assert_eq!(normalized[2], "use std :: process ;");
+        assert_eq!(normalized[3], "use libc :: getuid ;");
+        assert!(normalized[4].contains("crate :: util :: run ()"));
+        assert!(normalized[4].contains(":: std :: fs :: remove_file"));
+    }
+
+    #[test]
+    fn test_edition_parse() {
+        assert_eq!("2021".parse::<Edition>().unwrap(), Edition::E2021);
+        assert_eq!(
+            UnsafeFnOpsPolicy::edition_default(Edition::E2021),
+            UnsafeFnOpsPolicy::Allow
+        );
+        assert_eq!(
+            UnsafeFnOpsPolicy::edition_default(Edition::E2024),
+            UnsafeFnOpsPolicy::Warn
+        );
+    }
+}
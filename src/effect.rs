@@ -6,14 +6,15 @@
 //! - EffectBlock, which represents a block of source code which may contain
 //!     zero or more effects (such as an unsafe block).
 
-use super::ident::{CanonicalPath, IdentPath};
+use super::ident::{CanonicalPath, CanonicalType, Ident, PatternSet};
 use super::sink::Sink;
-use super::util::csv;
+use super::util::{csv, paths};
 
 use log::debug;
 use parse_display::{Display, FromStr};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
 use std::path::{Path as FilePath, PathBuf as FilePathBuf};
 use syn;
@@ -25,7 +26,9 @@ use syn::spanned::Spanned;
 */
 
 /// Data representing a source code location for some identifier, block, or expression
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
 pub struct SrcLoc {
     /// Directory in which the expression occurs
     dir: FilePathBuf,
@@ -46,9 +49,11 @@ impl SrcLoc {
         end_line: usize,
         end_col: usize,
     ) -> Self {
-        // TBD: use unwrap_or_else
-        let dir = filepath.parent().unwrap().to_owned();
-        let file = FilePathBuf::from(filepath.file_name().unwrap());
+        // Paths without a file name (e.g. `..`) are kept whole as the file
+        let (dir, file) = match (filepath.parent(), filepath.file_name()) {
+            (Some(dir), Some(file)) => (dir.to_owned(), FilePathBuf::from(file)),
+            _ => (FilePathBuf::new(), filepath.to_owned()),
+        };
         Self { dir, file, start_line, start_col, end_line, end_col }
     }
 
@@ -69,8 +74,8 @@ impl SrcLoc {
 
     pub fn sub1(&self) -> Self {
         let mut res = self.clone();
-        res.start_line -= 1;
-        res.end_line -= 1;
+        res.start_line = res.start_line.saturating_sub(1);
+        res.end_line = res.end_line.saturating_sub(1);
         res
     }
 
@@ -88,6 +93,11 @@ impl SrcLoc {
         format!("{}, {}, {}, {}", dir, file, self.start_line, self.start_col)
     }
 
+    /// `file:line:col` with a 1-based column, as editors expect
+    pub fn to_quickfix(&self) -> String {
+        format!("{}:{}:{}", self.filepath_string(), self.start_line, self.start_col + 1)
+    }
+
     pub fn dir(&self) -> &FilePathBuf {
         &self.dir
     }
@@ -115,6 +125,24 @@ impl SrcLoc {
     pub fn filepath_string(&self) -> String {
         self.dir.join(&self.file).to_string_lossy().to_string()
     }
+
+    /// The same location in a file with an anonymous name
+    pub fn anonymize(&self, anon: &impl Fn(&str) -> String) -> Self {
+        let file = format!("{}.rs", anon(&self.filepath_string()));
+        let (start_line, start_col) = (self.start_line, self.start_col);
+        Self::new(
+            FilePath::new(&file),
+            start_line,
+            start_col,
+            self.end_line,
+            self.end_col,
+        )
+    }
+
+    /// The path to show to users (see `util::paths::display_path`)
+    pub fn display_path(&self) -> String {
+        paths::display_path(&self.dir.join(&self.file))
+    }
 }
 
 impl fmt::Display for SrcLoc {
@@ -122,7 +150,7 @@ impl fmt::Display for SrcLoc {
         write!(
             f,
             "{}:{}:{}..{}:{}",
-            self.filepath_string(),
+            self.display_path(),
             self.start_line,
             self.start_col,
             self.end_line,
@@ -140,7 +168,7 @@ impl fmt::Display for SrcLoc {
 /// - a sink pattern in the standard library
 /// - an FFI call
 /// - an unsafe operation such as a pointer dereference
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Effect {
     /// Function call (callee path) matching a sink pattern
     SinkCall(Sink),
@@ -156,6 +184,8 @@ pub enum Effect {
     StaticMut(CanonicalPath),
     /// Accessing an external mutable variable
     StaticExt(CanonicalPath),
+    /// Implementing an unsafe trait (trait path)
+    UnsafeTraitImpl(CanonicalPath),
     /// Creation of function pointer
     FnPtrCreation,
     /// Closure creation
@@ -164,6 +194,53 @@ pub enum Effect {
     /// Note: This effect isn't unsafe, and is turned off by default (not included
     /// in the default list of effects to care about)
     RawPtrCast,
+    /// Pointer arithmetic on a raw pointer (method path), e.g. `offset`, `add`,
+    /// or `wrapping_add`
+    /// Note: Only the `wrapping_` methods are safe; the others are calls to
+    /// unsafe functions
+    PtrArithmetic(CanonicalPath),
+    /// Casting between an integer and a raw pointer (either direction), which
+    /// loses or invents pointer provenance
+    /// Note: Like RawPtrCast, this effect isn't unsafe on its own, and is turned
+    /// off by default
+    IntPtrCast(CanonicalPath),
+    /// Call to an API which exposes uninitialized memory (callee path), e.g.
+    /// `MaybeUninit::assume_init`, `mem::zeroed`, or `Vec::set_len`
+    UninitMemory(CanonicalPath),
+    /// Getting a mutable pointer or reference to data behind a shared
+    /// reference (callee or pointer path), e.g. `UnsafeCell::get`,
+    /// `Cell::as_ptr`, or `&mut *(r as *const T as *mut T)`
+    /// Note: This effect is only a risk of aliasing violations, and is turned off
+    /// by default
+    AliasEscape(CanonicalPath),
+    /// Atomic operation with `Ordering::Relaxed`, or a fence, along with the
+    /// memory ordering used (callee path)
+    /// Note: This effect is for concurrency reviews, and is turned off by default
+    Atomic(CanonicalPath, MemOrdering),
+    /// Spawning a thread or task (callee path), e.g. `std::thread::spawn` or
+    /// `tokio::task::spawn_blocking`; the spawned code keeps running after the
+    /// caller returns
+    /// Note: This effect is for concurrency reviews, and is turned off by default
+    ThreadSpawn(CanonicalPath),
+    /// Registering a signal handler or exit hook, or forking (callee path),
+    /// along with the kind of hook; the hook's code runs in signal context or
+    /// in the child process, where most operations aren't safe
+    /// Note: The calls are usually also FFI or sink calls; this effect is
+    /// opt-in to review them as a family
+    OsHook(CanonicalPath, OsHook),
+    /// Mapping memory or changing its protection (callee path), e.g. `mmap`,
+    /// `shm_open`, `VirtualAlloc`, or `memmap2::MmapOptions::map_exec`, along
+    /// with the protection when it is given literally; executable mappings
+    /// are critical
+    /// Note: The calls are usually also FFI or unsafe calls; this effect is
+    /// opt-in to review them as a family
+    MemoryMap(CanonicalPath, Option<MemProtection>),
+    /// User-defined effect kind (see the `taxonomy` module)
+    Custom(CustomEffect),
+    /// Call matching a pattern common in malicious crates (see the `malware`
+    /// module), along with the pattern (callee path)
+    /// Note: These heuristics are opt-in, and are turned off by default
+    Malware(CanonicalPath, MalwarePattern),
 }
 impl Effect {
     fn sink_pattern(&self) -> Option<&Sink> {
@@ -175,11 +252,27 @@ impl Effect {
 
     /// Return true if the type of unsafety is something that Rust considers unsafe.
     fn is_rust_unsafe(&self) -> bool {
-        !matches!(self, Self::SinkCall(_) | Self::FnPtrCreation | Self::ClosureCreation)
+        if let Self::PtrArithmetic(method) = self {
+            return !is_wrapping_ptr_method(method);
+        }
+        !matches!(
+            self,
+            Self::SinkCall(_)
+                | Self::FnPtrCreation
+                | Self::ClosureCreation
+                | Self::IntPtrCast(_)
+                | Self::AliasEscape(_)
+                | Self::Atomic(_, _)
+                | Self::ThreadSpawn(_)
+                | Self::OsHook(_, _)
+                | Self::MemoryMap(_, _)
+                | Self::Custom(_)
+                | Self::Malware(_, _)
+        )
     }
 
-    fn simple_str(&self) -> &str {
-        match self {
+    pub fn simple_str(&self) -> Cow<'_, str> {
+        let s = match self {
             Self::SinkCall(s) => s.as_str(),
             Self::FFICall(_) => "[FFI]",
             Self::UnsafeCall(_) => "[UnsafeCall]",
@@ -187,20 +280,444 @@ impl Effect {
             Self::UnionField(_) => "[UnionField]",
             Self::StaticMut(_) => "[StaticMutVar]",
             Self::StaticExt(_) => "[StaticExtVar]",
+            Self::UnsafeTraitImpl(_) => "[UnsafeTraitImpl]",
             Self::FnPtrCreation => "[FnPtrCreation]",
             Self::ClosureCreation => "[ClosureCreation]",
             Self::RawPtrCast => "[RawPtrCast]",
-        }
+            Self::PtrArithmetic(_) => "[PtrArithmetic]",
+            Self::IntPtrCast(_) => "[IntPtrCast]",
+            Self::UninitMemory(_) => "[UninitMemory]",
+            Self::AliasEscape(_) => "[AliasEscape]",
+            Self::Atomic(_, o) => o.atomic_str(),
+            Self::ThreadSpawn(_) => "[ThreadSpawn]",
+            Self::OsHook(_, h) => h.os_hook_str(),
+            Self::MemoryMap(_, p) => MemProtection::memory_map_str(p),
+            Self::Custom(c) => return Cow::Owned(format!("[{}]", c.kind)),
+            Self::Malware(_, p) => p.malware_str(),
+        };
+        Cow::Borrowed(s)
     }
 
     fn to_csv(&self) -> String {
-        csv::sanitize_comma(self.simple_str())
+        csv::sanitize_comma(&self.simple_str())
+    }
+
+    /// The effect with the path it holds (callee, variable, trait, or
+    /// method path), if any, replaced
+    pub fn map_paths(&self, f: impl Fn(&CanonicalPath) -> CanonicalPath) -> Self {
+        match self {
+            Self::FFICall(p) => Self::FFICall(f(p)),
+            Self::UnsafeCall(p) => Self::UnsafeCall(f(p)),
+            Self::RawPointer(p) => Self::RawPointer(f(p)),
+            Self::UnionField(p) => Self::UnionField(f(p)),
+            Self::StaticMut(p) => Self::StaticMut(f(p)),
+            Self::StaticExt(p) => Self::StaticExt(f(p)),
+            Self::UnsafeTraitImpl(p) => Self::UnsafeTraitImpl(f(p)),
+            Self::PtrArithmetic(p) => Self::PtrArithmetic(f(p)),
+            Self::IntPtrCast(p) => Self::IntPtrCast(f(p)),
+            Self::UninitMemory(p) => Self::UninitMemory(f(p)),
+            Self::AliasEscape(p) => Self::AliasEscape(f(p)),
+            Self::Atomic(p, o) => Self::Atomic(f(p), *o),
+            Self::ThreadSpawn(p) => Self::ThreadSpawn(f(p)),
+            Self::OsHook(p, h) => Self::OsHook(f(p), *h),
+            Self::MemoryMap(p, m) => Self::MemoryMap(f(p), *m),
+            Self::Malware(p, m) => Self::Malware(f(p), *m),
+            Self::SinkCall(_)
+            | Self::FnPtrCreation
+            | Self::ClosureCreation
+            | Self::RawPtrCast
+            | Self::Custom(_) => self.clone(),
+        }
+    }
+
+    /// Which of the unsafe operations (the "unsafe superpowers") the effect
+    /// is, if it is one
+    pub fn unsafe_op(&self) -> Option<UnsafeOp> {
+        match self {
+            Self::RawPointer(_) => Some(UnsafeOp::DerefRawPointer),
+            Self::UnsafeCall(_) | Self::FFICall(_) | Self::UninitMemory(_) => {
+                Some(UnsafeOp::CallUnsafeFn)
+            }
+            Self::StaticMut(_) | Self::StaticExt(_) => Some(UnsafeOp::AccessStatic),
+            Self::UnionField(_) => Some(UnsafeOp::AccessUnionField),
+            Self::UnsafeTraitImpl(_) => Some(UnsafeOp::ImplUnsafeTrait),
+            Self::PtrArithmetic(method) if !is_wrapping_ptr_method(method) => {
+                Some(UnsafeOp::CallUnsafeFn)
+            }
+            Self::SinkCall(_)
+            | Self::FnPtrCreation
+            | Self::ClosureCreation
+            | Self::RawPtrCast
+            | Self::PtrArithmetic(_)
+            | Self::IntPtrCast(_)
+            | Self::AliasEscape(_)
+            | Self::Atomic(_, _)
+            | Self::ThreadSpawn(_)
+            | Self::OsHook(_, _)
+            | Self::MemoryMap(_, _)
+            | Self::Custom(_)
+            | Self::Malware(_, _) => None,
+        }
+    }
+
+    /// Which kind of raw pointer operation the effect is, if it is one
+    pub fn raw_ptr_op(&self) -> Option<RawPtrOp> {
+        match self {
+            Self::RawPointer(_) => Some(RawPtrOp::Deref),
+            Self::PtrArithmetic(_) => Some(RawPtrOp::Arithmetic),
+            Self::IntPtrCast(_) => Some(RawPtrOp::IntCast),
+            Self::RawPtrCast => Some(RawPtrOp::Cast),
+            _ => None,
+        }
+    }
+
+    /// How dangerous the effect is by default. Effects which Rust considers
+    /// unsafe are the most severe, and uninitialized memory APIs (which are
+    /// the most commonly misused) even more so.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UninitMemory(_) => Severity::Critical,
+            Self::MemoryMap(_, Some(p)) if p.exec => Severity::Critical,
+            Self::Custom(c) => c.severity,
+            Self::FFICall(_)
+            | Self::UnsafeCall(_)
+            | Self::RawPointer(_)
+            | Self::UnionField(_)
+            | Self::StaticMut(_)
+            | Self::StaticExt(_)
+            | Self::UnsafeTraitImpl(_)
+            | Self::PtrArithmetic(_)
+            | Self::AliasEscape(_)
+            | Self::OsHook(_, _)
+            | Self::MemoryMap(_, _)
+            | Self::Malware(_, _) => Severity::High,
+            Self::SinkCall(_) | Self::IntPtrCast(_) | Self::Atomic(_, _) => {
+                Severity::Medium
+            }
+            Self::FnPtrCreation
+            | Self::ClosureCreation
+            | Self::RawPtrCast
+            | Self::ThreadSpawn(_) => Severity::Low,
+        }
+    }
+}
+
+/// The operations which are only allowed in unsafe code. Each unsafe effect
+/// is exactly one of these.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum UnsafeOp {
+    /// Dereferencing a raw pointer
+    DerefRawPointer,
+    /// Calling an unsafe or external function
+    CallUnsafeFn,
+    /// Accessing a mutable or external static variable
+    AccessStatic,
+    /// Reading a union field
+    AccessUnionField,
+    /// Implementing an unsafe trait
+    ImplUnsafeTrait,
+}
+
+impl UnsafeOp {
+    pub const ALL: &'static [UnsafeOp] = &[
+        UnsafeOp::DerefRawPointer,
+        UnsafeOp::CallUnsafeFn,
+        UnsafeOp::AccessStatic,
+        UnsafeOp::AccessUnionField,
+        UnsafeOp::ImplUnsafeTrait,
+    ];
+}
+
+/// A user-defined effect kind and its severity
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CustomEffect {
+    pub kind: String,
+    pub severity: Severity,
+}
+
+/// Memory ordering of an atomic operation (`std::sync::atomic::Ordering`)
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum MemOrdering {
+    Relaxed,
+    Release,
+    Acquire,
+    AcqRel,
+    SeqCst,
+}
+
+impl MemOrdering {
+    fn atomic_str(&self) -> &'static str {
+        match self {
+            MemOrdering::Relaxed => "[Atomic(Relaxed)]",
+            MemOrdering::Release => "[Atomic(Release)]",
+            MemOrdering::Acquire => "[Atomic(Acquire)]",
+            MemOrdering::AcqRel => "[Atomic(AcqRel)]",
+            MemOrdering::SeqCst => "[Atomic(SeqCst)]",
+        }
+    }
+}
+
+/// Sub-kinds of raw pointer effects
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum RawPtrOp {
+    /// Dereferencing a raw pointer
+    Deref,
+    /// Offsetting a raw pointer
+    Arithmetic,
+    /// Casting between an integer and a raw pointer
+    IntCast,
+    /// Casting to a raw pointer
+    Cast,
+}
+
+/// Patterns common in malicious crates
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum MalwarePattern {
+    /// An obfuscated byte array decoded and executed, e.g. passed to
+    /// `Command::new` or transmuted to a function pointer
+    DecodedExec,
+    /// A network call in a build script
+    BuildScriptNetwork,
+    /// A base64 blob passed to a process spawn
+    EncodedSpawn,
+    /// A build script writing to the user's home directory
+    HomeWrite,
+}
+
+impl MalwarePattern {
+    fn malware_str(&self) -> &'static str {
+        match self {
+            MalwarePattern::DecodedExec => "[Malware(DecodedExec)]",
+            MalwarePattern::BuildScriptNetwork => "[Malware(BuildScriptNetwork)]",
+            MalwarePattern::EncodedSpawn => "[Malware(EncodedSpawn)]",
+            MalwarePattern::HomeWrite => "[Malware(HomeWrite)]",
+        }
+    }
+}
+
+/// Kinds of low-level OS hooks
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum OsHook {
+    /// Registering a signal handler, e.g. `sigaction` or
+    /// `signal_hook::flag::register`
+    SignalHandler,
+    /// Registering a function to run at process exit, e.g. `atexit`
+    AtExit,
+    /// Forking, or running code in the child process before exec, e.g.
+    /// `fork` or `CommandExt::pre_exec`
+    Fork,
+}
+
+impl OsHook {
+    fn os_hook_str(&self) -> &'static str {
+        match self {
+            OsHook::SignalHandler => "[OsHook(SignalHandler)]",
+            OsHook::AtExit => "[OsHook(AtExit)]",
+            OsHook::Fork => "[OsHook(Fork)]",
+        }
+    }
+}
+
+/// Protection of a memory mapping, when it is given literally (e.g.
+/// `PROT_READ | PROT_EXEC`, `PAGE_EXECUTE_READWRITE`, or implied by the
+/// method, as in `MmapOptions::map_exec`)
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+pub struct MemProtection {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl MemProtection {
+    fn new(read: bool, write: bool, exec: bool) -> Self {
+        Self { read, write, exec }
+    }
+
+    /// The protection given by a flag constant, if it is one
+    fn of_flag(flag: &str) -> Option<Self> {
+        Some(match flag {
+            "PROT_NONE" | "PAGE_NOACCESS" => Self::default(),
+            "PROT_READ" | "PAGE_READONLY" => Self::new(true, false, false),
+            "PROT_WRITE" => Self::new(false, true, false),
+            "PROT_EXEC" | "PAGE_EXECUTE" => Self::new(false, false, true),
+            "PAGE_READWRITE" | "PAGE_WRITECOPY" => Self::new(true, true, false),
+            "PAGE_EXECUTE_READ" => Self::new(true, false, true),
+            "PAGE_EXECUTE_READWRITE" | "PAGE_EXECUTE_WRITECOPY" => {
+                Self::new(true, true, true)
+            }
+            _ => return None,
+        })
+    }
+
+    /// The protection from the flag constants in the arguments of a call, if
+    /// there are any
+    pub fn from_flags<'a, I>(idents: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        idents.into_iter().filter_map(Self::of_flag).reduce(|p1, p2| Self {
+            read: p1.read || p2.read,
+            write: p1.write || p2.write,
+            exec: p1.exec || p2.exec,
+        })
+    }
+
+    /// The protection implied by a `memmap2` method, if any
+    pub fn of_method(method: &str) -> Option<Self> {
+        match method {
+            "map" => Some(Self::new(true, false, false)),
+            "map_mut" | "map_anon" | "map_copy" | "make_mut" => {
+                Some(Self::new(true, true, false))
+            }
+            "map_exec" | "make_exec" => Some(Self::new(true, false, true)),
+            _ => None,
+        }
+    }
+
+    fn memory_map_str(p: &Option<Self>) -> &'static str {
+        let Some(p) = p else {
+            return "[MemoryMap]";
+        };
+        match (p.read, p.write, p.exec) {
+            (false, false, false) => "[MemoryMap(---)]",
+            (true, false, false) => "[MemoryMap(r--)]",
+            (false, true, false) => "[MemoryMap(-w-)]",
+            (true, true, false) => "[MemoryMap(rw-)]",
+            (false, false, true) => "[MemoryMap(--x)]",
+            (true, false, true) => "[MemoryMap(r-x)]",
+            (false, true, true) => "[MemoryMap(-wx)]",
+            (true, true, true) => "[MemoryMap(rwx)]",
+        }
+    }
+}
+
+impl fmt::Display for MemProtection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.exec, 'x')
+        )
+    }
+}
+
+/// Severity class of an effect
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    FromStr,
+)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Weight of an effect of this severity in risk scores
+    pub fn weight(&self) -> u64 {
+        match self {
+            Severity::Low => 1,
+            Severity::Medium => 2,
+            Severity::High => 3,
+            Severity::Critical => 4,
+        }
     }
 }
 
 /// This is a field-less copy of Effect for easy pattern matching and passing
 /// command-line arguments.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display, FromStr)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display, FromStr)]
 pub enum EffectType {
     SinkCall,
     FFICall,
@@ -209,26 +726,54 @@ pub enum EffectType {
     UnionField,
     StaticMut,
     StaticExt,
+    UnsafeTraitImpl,
     FnPtrCreation,
     ClosureCreation,
     RawPtrCast,
+    PtrArithmetic,
+    IntPtrCast,
+    UninitMemory,
+    AliasEscape,
+    Atomic,
+    ThreadSpawn,
+    OsHook,
+    MemoryMap,
+    Custom,
+    Malware,
 }
 
-impl EffectType {
-    pub fn matches_effect(types: &[EffectType], e: &Effect) -> bool {
+impl From<&Effect> for EffectType {
+    fn from(e: &Effect) -> Self {
         match e {
-            Effect::SinkCall(_) => types.contains(&EffectType::SinkCall),
-            Effect::FFICall(_) => types.contains(&EffectType::FFICall),
-            Effect::UnsafeCall(_) => types.contains(&EffectType::UnsafeCall),
-            Effect::RawPointer(_) => types.contains(&EffectType::RawPointer),
-            Effect::UnionField(_) => types.contains(&EffectType::UnionField),
-            Effect::StaticMut(_) => types.contains(&EffectType::StaticMut),
-            Effect::StaticExt(_) => types.contains(&EffectType::StaticExt),
-            Effect::FnPtrCreation => types.contains(&EffectType::FnPtrCreation),
-            Effect::ClosureCreation => types.contains(&EffectType::ClosureCreation),
-            Effect::RawPtrCast => types.contains(&EffectType::RawPtrCast),
+            Effect::SinkCall(_) => EffectType::SinkCall,
+            Effect::FFICall(_) => EffectType::FFICall,
+            Effect::UnsafeCall(_) => EffectType::UnsafeCall,
+            Effect::RawPointer(_) => EffectType::RawPointer,
+            Effect::UnionField(_) => EffectType::UnionField,
+            Effect::StaticMut(_) => EffectType::StaticMut,
+            Effect::StaticExt(_) => EffectType::StaticExt,
+            Effect::UnsafeTraitImpl(_) => EffectType::UnsafeTraitImpl,
+            Effect::FnPtrCreation => EffectType::FnPtrCreation,
+            Effect::ClosureCreation => EffectType::ClosureCreation,
+            Effect::RawPtrCast => EffectType::RawPtrCast,
+            Effect::PtrArithmetic(_) => EffectType::PtrArithmetic,
+            Effect::IntPtrCast(_) => EffectType::IntPtrCast,
+            Effect::UninitMemory(_) => EffectType::UninitMemory,
+            Effect::AliasEscape(_) => EffectType::AliasEscape,
+            Effect::Atomic(_, _) => EffectType::Atomic,
+            Effect::ThreadSpawn(_) => EffectType::ThreadSpawn,
+            Effect::OsHook(_, _) => EffectType::OsHook,
+            Effect::MemoryMap(_, _) => EffectType::MemoryMap,
+            Effect::Custom(_) => EffectType::Custom,
+            Effect::Malware(_, _) => EffectType::Malware,
         }
     }
+}
+
+impl EffectType {
+    pub fn matches_effect(types: &[EffectType], e: &Effect) -> bool {
+        types.contains(&EffectType::from(e))
+    }
 
     pub fn unsafe_effects() -> Vec<EffectType> {
         vec![
@@ -239,14 +784,120 @@ impl EffectType {
             EffectType::UnionField,
             EffectType::StaticMut,
             EffectType::StaticExt,
+            EffectType::UnsafeTraitImpl,
             EffectType::FnPtrCreation,
             EffectType::ClosureCreation,
+            EffectType::PtrArithmetic,
+            EffectType::UninitMemory,
+            EffectType::Custom,
         ]
     }
 }
 
+/// An effect type or a family of effect types, for selecting which effects to
+/// scan for on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectSelector {
+    Type(EffectType),
+    /// DEFAULT_EFFECT_TYPES
+    Default,
+    /// FFI calls and external statics
+    Ffi,
+    /// Effects which can only occur in unsafe code
+    Unsafe,
+    /// Sink calls, including custom effect kinds
+    Sinks,
+    /// All raw pointer effects
+    RawPointer,
+    /// The malware heuristics
+    Malware,
+    /// Signal handlers, exit hooks, and forks
+    OsHooks,
+}
+
+impl fmt::Display for EffectSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Type(t) => write!(f, "{}", t),
+            Self::Default => write!(f, "default"),
+            Self::Ffi => write!(f, "ffi"),
+            Self::Unsafe => write!(f, "unsafe"),
+            Self::Sinks => write!(f, "sinks"),
+            Self::RawPointer => write!(f, "raw-pointer"),
+            Self::Malware => write!(f, "malware"),
+            Self::OsHooks => write!(f, "os-hooks"),
+        }
+    }
+}
+
+impl std::str::FromStr for EffectSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "ffi" => Ok(Self::Ffi),
+            "unsafe" => Ok(Self::Unsafe),
+            "sinks" => Ok(Self::Sinks),
+            "raw-pointer" => Ok(Self::RawPointer),
+            "malware" => Ok(Self::Malware),
+            "os-hooks" => Ok(Self::OsHooks),
+            _ => s.parse::<EffectType>().map(Self::Type).map_err(|_| {
+                format!(
+                    "invalid effect type {}; expected an effect type or one of \
+                    default, ffi, unsafe, sinks, raw-pointer, malware, os-hooks",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+impl EffectSelector {
+    pub fn effect_types(&self) -> Vec<EffectType> {
+        match self {
+            Self::Type(t) => vec![*t],
+            Self::Default => DEFAULT_EFFECT_TYPES.to_vec(),
+            Self::Ffi => vec![EffectType::FFICall, EffectType::StaticExt],
+            Self::Unsafe => vec![
+                EffectType::FFICall,
+                EffectType::UnsafeCall,
+                EffectType::RawPointer,
+                EffectType::UnionField,
+                EffectType::StaticMut,
+                EffectType::StaticExt,
+                EffectType::UnsafeTraitImpl,
+                EffectType::UninitMemory,
+                EffectType::PtrArithmetic,
+            ],
+            Self::Sinks => vec![EffectType::SinkCall, EffectType::Custom],
+            Self::RawPointer => vec![
+                EffectType::RawPointer,
+                EffectType::PtrArithmetic,
+                EffectType::IntPtrCast,
+                EffectType::RawPtrCast,
+            ],
+            Self::Malware => vec![EffectType::Malware],
+            Self::OsHooks => vec![EffectType::OsHook],
+        }
+    }
+
+    /// All the effect types selected, without duplicates
+    pub fn expand(selectors: &[EffectSelector]) -> Vec<EffectType> {
+        let mut types = Vec::new();
+        for t in selectors.iter().flat_map(|s| s.effect_types()) {
+            if !types.contains(&t) {
+                types.push(t);
+            }
+        }
+        types
+    }
+}
+
 // Default effect types that we care about
-// Excludes: RawPtrCast as it is not unsafe
+// Excludes: RawPtrCast, IntPtrCast, AliasEscape, Atomic, and ThreadSpawn as
+// they are not unsafe, Malware as the heuristics are opt-in, and OsHook and
+// MemoryMap as their calls are already FFI, sink, or unsafe calls
 pub const DEFAULT_EFFECT_TYPES: &[EffectType] = &[
     EffectType::SinkCall,
     EffectType::FFICall,
@@ -255,10 +906,263 @@ pub const DEFAULT_EFFECT_TYPES: &[EffectType] = &[
     EffectType::UnionField,
     EffectType::StaticMut,
     EffectType::StaticExt,
+    EffectType::UnsafeTraitImpl,
     EffectType::FnPtrCreation,
     EffectType::ClosureCreation,
+    EffectType::PtrArithmetic,
+    EffectType::UninitMemory,
+    EffectType::Custom,
+];
+
+/// APIs which expose uninitialized memory, as the last two segments of their
+/// path
+const UNINIT_MEMORY_FNS: &[(&str, &str)] = &[
+    ("mem", "zeroed"),
+    ("mem", "uninitialized"),
+    ("MaybeUninit", "assume_init"),
+    ("MaybeUninit", "assume_init_read"),
+    ("MaybeUninit", "assume_init_ref"),
+    ("MaybeUninit", "assume_init_mut"),
+    ("MaybeUninit", "assume_init_drop"),
+    ("Vec", "set_len"),
 ];
 
+/// APIs which get a mutable pointer to data behind a shared reference, as the
+/// last two segments of their path
+const ALIAS_ESCAPE_FNS: &[(&str, &str)] = &[
+    ("UnsafeCell", "get"),
+    ("UnsafeCell", "raw_get"),
+    ("Cell", "as_ptr"),
+    ("RefCell", "as_ptr"),
+];
+
+/// APIs which run a closure or function on another thread or as a separate
+/// task, as the last two segments of their path
+const SPAWN_FNS: &[(&str, &str)] = &[
+    ("thread", "spawn"),
+    ("Builder", "spawn"),
+    ("Builder", "spawn_scoped"),
+    ("Scope", "spawn"),
+    ("rayon", "spawn"),
+    ("rayon", "spawn_fifo"),
+    ("tokio", "spawn"),
+    ("task", "spawn"),
+    ("task", "spawn_blocking"),
+    ("task", "spawn_local"),
+    ("Runtime", "spawn"),
+    ("Runtime", "spawn_blocking"),
+    ("Handle", "spawn"),
+    ("Handle", "spawn_blocking"),
+];
+
+/// Whether the callee is one of the `SPAWN_FNS`
+pub fn is_spawn_fn(callee: &CanonicalPath) -> bool {
+    matches_fn_table(callee, SPAWN_FNS)
+}
+
+/// Whether a function or method with this name may be one of the `SPAWN_FNS`,
+/// to check before resolving the callee
+pub fn may_be_spawn_fn(name: &str) -> bool {
+    SPAWN_FNS.iter().any(|(_, n)| name == *n)
+}
+
+/// APIs registering signal handlers or exit hooks, or forking, by path
+const OS_HOOK_FNS: &[(&str, OsHook)] = &[
+    ("libc::signal", OsHook::SignalHandler),
+    ("libc::sigaction", OsHook::SignalHandler),
+    ("nix::sys::signal::signal", OsHook::SignalHandler),
+    ("nix::sys::signal::sigaction", OsHook::SignalHandler),
+    ("signal_hook::flag::register", OsHook::SignalHandler),
+    ("signal_hook::flag::register_usize", OsHook::SignalHandler),
+    ("signal_hook::flag::register_conditional_shutdown", OsHook::SignalHandler),
+    ("signal_hook::flag::register_conditional_default", OsHook::SignalHandler),
+    ("signal_hook::low_level::register", OsHook::SignalHandler),
+    ("signal_hook_registry::register", OsHook::SignalHandler),
+    ("signal_hook_registry::register_sigaction", OsHook::SignalHandler),
+    ("signal_hook_registry::register_signal_unchecked", OsHook::SignalHandler),
+    ("signal_hook::iterator::Signals::new", OsHook::SignalHandler),
+    ("signal_hook::iterator::SignalsInfo::new", OsHook::SignalHandler),
+    ("ctrlc::set_handler", OsHook::SignalHandler),
+    ("ctrlc::try_set_handler", OsHook::SignalHandler),
+    ("libc::atexit", OsHook::AtExit),
+    ("libc::at_quick_exit", OsHook::AtExit),
+    ("libc::fork", OsHook::Fork),
+    ("libc::vfork", OsHook::Fork),
+    ("libc::pthread_atfork", OsHook::Fork),
+    ("nix::unistd::fork", OsHook::Fork),
+    ("std::os::unix::process::CommandExt::pre_exec", OsHook::Fork),
+];
+
+/// Whether the callee is the item at the path, or the same item defined in
+/// a module under the path's parent and re-exported from it (as the
+/// functions of `libc`, e.g. `libc::unix::linux_like::fork` for `libc::fork`)
+fn is_item_at(callee: &CanonicalPath, path: &str) -> bool {
+    let callee = callee.as_str();
+    if callee == path {
+        return true;
+    }
+    let Some((parent, name)) = path.rsplit_once("::") else {
+        return false;
+    };
+    callee
+        .strip_prefix(parent)
+        .and_then(|rest| rest.strip_prefix("::"))
+        .and_then(|rest| rest.strip_suffix(name))
+        .map_or(false, |module| module.ends_with("::"))
+}
+
+/// The kind of OS hook the callee registers, if it is one of the
+/// `OS_HOOK_FNS`
+pub fn os_hook(callee: &CanonicalPath) -> Option<OsHook> {
+    OS_HOOK_FNS.iter().find(|(path, _)| is_item_at(callee, path)).map(|(_, h)| *h)
+}
+
+/// Whether a function or method with this name may be one of the
+/// `OS_HOOK_FNS`, to check before resolving the callee
+pub fn may_be_os_hook(name: &str) -> bool {
+    OS_HOOK_FNS.iter().any(|(path, _)| path.rsplit("::").next() == Some(name))
+}
+
+/// APIs mapping memory or changing its protection, as the last two segments
+/// of their path
+const MEMORY_MAP_FNS: &[(&str, &str)] = &[
+    ("libc", "mmap"),
+    ("libc", "mmap64"),
+    ("libc", "mprotect"),
+    ("libc", "shm_open"),
+    ("libc", "memfd_create"),
+    ("mman", "mmap"),
+    ("mman", "mmap_anonymous"),
+    ("mman", "mprotect"),
+    ("mman", "shm_open"),
+    ("memoryapi", "VirtualAlloc"),
+    ("memoryapi", "VirtualAllocEx"),
+    ("memoryapi", "VirtualProtect"),
+    ("memoryapi", "MapViewOfFile"),
+    ("Memory", "VirtualAlloc"),
+    ("Memory", "VirtualAllocEx"),
+    ("Memory", "VirtualProtect"),
+    ("Memory", "MapViewOfFile"),
+    ("Mmap", "map"),
+    ("Mmap", "make_exec"),
+    ("Mmap", "make_mut"),
+    ("MmapMut", "map_mut"),
+    ("MmapMut", "map_anon"),
+    ("MmapMut", "make_exec"),
+    ("MmapRaw", "map_raw"),
+    ("MmapOptions", "map"),
+    ("MmapOptions", "map_mut"),
+    ("MmapOptions", "map_exec"),
+    ("MmapOptions", "map_anon"),
+    ("MmapOptions", "map_copy"),
+    ("MmapOptions", "map_raw"),
+];
+
+/// Whether the callee is one of the `MEMORY_MAP_FNS`
+pub fn is_memory_map_fn(callee: &CanonicalPath) -> bool {
+    matches_fn_table(callee, MEMORY_MAP_FNS)
+}
+
+/// Whether a function or method with this name may be one of the
+/// `MEMORY_MAP_FNS`, to check before resolving the callee
+pub fn may_be_memory_map_fn(name: &str) -> bool {
+    MEMORY_MAP_FNS.iter().any(|(_, n)| name == *n)
+}
+
+/// Whether the last two segments of the callee path are in the table
+fn matches_fn_table(callee: &CanonicalPath, table: &[(&str, &str)]) -> bool {
+    let mut segments = callee.as_str().rsplit("::");
+    let (name, parent) = (segments.next(), segments.next());
+    table.iter().any(|(p, n)| name == Some(*n) && parent == Some(*p))
+}
+
+/// Whether the callee is one of the `UNINIT_MEMORY_FNS`. If the callee couldn't
+/// be fully resolved (e.g. a method call on an unknown type), only unsafe calls
+/// with a matching name are considered.
+fn is_uninit_memory_fn(callee: &CanonicalPath, is_unsafe: bool) -> bool {
+    matches_fn_table(callee, UNINIT_MEMORY_FNS)
+        || (is_unsafe
+            && !callee.as_str().contains("::")
+            && UNINIT_MEMORY_FNS.iter().any(|(_, n)| callee.as_str() == *n))
+}
+
+/// Whether the pointer arithmetic method is one of the safe, wrapping ones,
+/// e.g. `wrapping_add`
+fn is_wrapping_ptr_method(method: &CanonicalPath) -> bool {
+    method.as_str().rsplit("::").next().is_some_and(|m| m.starts_with("wrapping_"))
+}
+
+/// The closure an effect occurs in. Effects in closures are attributed to the
+/// enclosing named function; this records which of its closures it was in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ClosureContext {
+    /// The method the closure is passed to, e.g. map for `.map(|x| ..)`
+    pub adapter: Option<String>,
+    /// Index of the closure among the closures in the enclosing function
+    pub index: usize,
+}
+
+impl ClosureContext {
+    /// A name for the closure which doesn't depend on its location, so it
+    /// stays the same when the code around it moves, e.g. map_closure_0
+    pub fn ident(&self) -> Ident {
+        match &self.adapter {
+            Some(adapter) => {
+                Ident::new_owned(format!("{}_closure_{}", adapter, self.index))
+            }
+            None => Ident::new_owned(format!("closure_{}", self.index)),
+        }
+    }
+
+    /// Describe where the closure is, e.g. inside `.map()` closure in `process_files`
+    pub fn describe(&self, enclosing_fn: &CanonicalPath) -> String {
+        let fn_name = enclosing_fn.fn_name();
+        match &self.adapter {
+            Some(adapter) => format!("inside `.{}()` closure in `{}`", adapter, fn_name),
+            None => format!("inside closure #{} in `{}`", self.index + 1, fn_name),
+        }
+    }
+}
+
+/// The crate's own macro an effect was expanded from: the effect's location
+/// is in the macro call, and the code causing it may be in the definition.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct MacroOrigin {
+    /// The macro's name, without the `!`
+    pub name: String,
+    /// Location of the `macro_rules!` definition
+    pub def_loc: SrcLoc,
+}
+
+/// Where the code an effect is in comes from. Effects in examples and
+/// doc-tests are audited like any other, but aren't part of what the crate
+/// exposes to its dependents. Effects in build scripts and proc macros happen
+/// when the crate is compiled, with the privileges of the developer or CI.
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Display,
+)]
+pub enum Provenance {
+    /// The crate's own source (src/)
+    #[default]
+    #[display("library")]
+    Library,
+    /// A file in examples/
+    #[display("example")]
+    Example,
+    /// A code block in a doc comment
+    #[display("doc-test")]
+    DocTest,
+    /// The build script (build.rs), or a proc-macro crate, run at compile time
+    #[display("build-time")]
+    BuildTime,
+}
+
+impl Provenance {
+    pub fn is_library(&self) -> bool {
+        *self == Self::Library
+    }
+}
+
 /// Type representing an Effect instance, with complete context.
 /// This includes a field for which Effect it is an instance of.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -275,6 +1179,29 @@ pub struct EffectInstance {
     /// EffectInstance type
     /// If Sink, this includes the effect pattern -- prefix of callee (effect), e.g. libc.
     eff_type: Effect,
+
+    /// For method calls, the type of the receiver if it could be resolved,
+    /// e.g. std::net::TcpStream for stream.write_all(..)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    receiver_ty: Option<CanonicalType>,
+
+    /// The closure in the caller the effect occurs in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closure: Option<ClosureContext>,
+
+    /// Whether the effect is in the crate's source, an example, a doc-test, or
+    /// code run at compile time
+    #[serde(default, skip_serializing_if = "Provenance::is_library")]
+    provenance: Provenance,
+
+    /// The macro the effect was expanded from, if it is in a macro call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    macro_origin: Option<MacroOrigin>,
+
+    /// Whether the effect is an unsafe operation outside of any unsafe block,
+    /// only allowed by the enclosing unsafe fn
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unsafe_fn_body: bool,
 }
 
 impl EffectInstance {
@@ -288,7 +1215,7 @@ impl EffectInstance {
         callsite: &S,
         is_unsafe: bool,
         ffi: Option<CanonicalPath>,
-        sinks: &HashSet<IdentPath>,
+        sinks: &PatternSet,
     ) -> Option<Self>
     where
         S: Spanned,
@@ -319,12 +1246,26 @@ impl EffectInstance {
         } else if let Some(pat) = Sink::new_match(&callee, sinks) {
             // callee.remove_src_loc();
             Some(Effect::SinkCall(pat))
+        } else if is_uninit_memory_fn(&callee, is_unsafe) {
+            Some(Effect::UninitMemory(callee.clone()))
+        } else if matches_fn_table(&callee, ALIAS_ESCAPE_FNS) {
+            Some(Effect::AliasEscape(callee.clone()))
         } else if is_unsafe {
             Some(Effect::UnsafeCall(callee.clone()))
         } else {
             None
         };
-        Some(Self { caller, call_loc, callee, eff_type: eff_type? })
+        Some(Self {
+            caller,
+            call_loc,
+            callee,
+            eff_type: eff_type?,
+            receiver_ty: None,
+            closure: None,
+            provenance: Provenance::Library,
+            macro_origin: None,
+            unsafe_fn_body: false,
+        })
     }
 
     pub fn new_effect<S>(
@@ -338,13 +1279,59 @@ impl EffectInstance {
         S: Spanned,
     {
         let call_loc = SrcLoc::from_span(filepath, eff_site);
-        Self { caller, call_loc, callee, eff_type }
+        Self {
+            caller,
+            call_loc,
+            callee,
+            eff_type,
+            receiver_ty: None,
+            closure: None,
+            provenance: Provenance::Library,
+            macro_origin: None,
+            unsafe_fn_body: false,
+        }
     }
 
     pub fn caller(&self) -> &CanonicalPath {
         &self.caller
     }
 
+    /// Replace the caller, file, and macro of the effect with anonymous
+    /// names, along with the paths in the caller's crate it refers to (e.g.
+    /// an unsafe fn of the crate it calls, or a `static mut` it accesses).
+    /// Paths outside of the crate and the kind of effect are kept.
+    pub fn anonymize(&mut self, anon: &impl Fn(&str) -> String) {
+        let crate_name = self.caller.crate_name();
+        let anon_path = |p: &CanonicalPath| {
+            if p.crate_name() == crate_name {
+                CanonicalPath::new(&anon(p.as_str()))
+            } else {
+                p.clone()
+            }
+        };
+        self.eff_type = self.eff_type.map_paths(anon_path);
+        self.callee = anon_path(&self.callee);
+        // Types aren't paths, so any which mention the crate are left out
+        let local_prefix = format!("{}::", crate_name);
+        if self
+            .receiver_ty
+            .as_ref()
+            .is_some_and(|t| t.to_string().contains(&local_prefix))
+        {
+            self.receiver_ty = None;
+        }
+        self.caller = CanonicalPath::new(&anon(self.caller_path()));
+        self.call_loc = self.call_loc.anonymize(anon);
+        if let Some(origin) = &mut self.macro_origin {
+            origin.name = anon(&origin.name);
+            origin.def_loc = origin.def_loc.anonymize(anon);
+        }
+    }
+
+    pub(crate) fn set_eff_type(&mut self, eff_type: Effect) {
+        self.eff_type = eff_type;
+    }
+
     pub fn caller_path(&self) -> &str {
         self.caller.as_str()
     }
@@ -376,6 +1363,43 @@ impl EffectInstance {
         format!("{}, {}, {}, {}, {}", crt, caller, callee, effect, call_loc_csv)
     }
 
+    /// A `file:line:col: message` line for Vim's quickfix list and Emacs'
+    /// compilation-mode
+    pub fn to_quickfix(&self) -> String {
+        format!("{}: {}", self.call_loc.to_quickfix(), self.description())
+    }
+
+    /// A `file:line:col: message` line with the path as shown to users, for
+    /// the text output of scans
+    pub fn to_text(&self) -> String {
+        let loc = &self.call_loc;
+        let (line, col) = (loc.start_line(), loc.start_col() + 1);
+        format!("{}:{}:{}: {}", loc.display_path(), line, col, self.description())
+    }
+
+    pub(crate) fn description(&self) -> String {
+        let mut msg = format!(
+            "{} ({}): `{}` in `{}`",
+            EffectType::from(&self.eff_type),
+            self.eff_type.severity(),
+            self.callee,
+            self.caller
+        );
+        if let Some(context) = self.closure_description() {
+            msg.push_str(&format!(", {}", context));
+        }
+        if let Some(origin) = &self.macro_origin {
+            msg.push_str(&format!(
+                ", in expansion of `{}!` defined at {}",
+                origin.name, origin.def_loc
+            ));
+        }
+        if !self.provenance.is_library() {
+            msg.push_str(&format!(" [{}]", self.provenance));
+        }
+        msg
+    }
+
     pub fn eff_type(&self) -> &Effect {
         &self.eff_type
     }
@@ -392,6 +1416,72 @@ impl EffectInstance {
     pub fn call_loc(&self) -> &SrcLoc {
         &self.call_loc
     }
+
+    pub fn receiver_ty(&self) -> Option<&CanonicalType> {
+        self.receiver_ty.as_ref()
+    }
+
+    pub(crate) fn set_receiver_ty(&mut self, ty: CanonicalType) {
+        self.receiver_ty = Some(ty);
+    }
+
+    pub fn closure(&self) -> Option<&ClosureContext> {
+        self.closure.as_ref()
+    }
+
+    pub(crate) fn set_closure(&mut self, closure: ClosureContext) {
+        self.closure = Some(closure);
+    }
+
+    pub fn provenance(&self) -> Provenance {
+        self.provenance
+    }
+
+    pub(crate) fn set_provenance(&mut self, provenance: Provenance) {
+        self.provenance = provenance;
+    }
+
+    pub fn macro_origin(&self) -> Option<&MacroOrigin> {
+        self.macro_origin.as_ref()
+    }
+
+    pub(crate) fn set_macro_origin(&mut self, origin: MacroOrigin) {
+        self.macro_origin = Some(origin);
+    }
+
+    /// Whether the effect is only allowed by the enclosing unsafe fn, and
+    /// needs its own unsafe block from the 2024 edition
+    pub fn in_unsafe_fn_body(&self) -> bool {
+        self.unsafe_fn_body
+    }
+
+    pub(crate) fn set_unsafe_fn_body(&mut self) {
+        self.unsafe_fn_body = true;
+    }
+
+    /// Where in the caller the effect is, if it is inside a closure
+    pub fn closure_description(&self) -> Option<String> {
+        self.closure.as_ref().map(|c| c.describe(&self.caller))
+    }
+}
+
+/// Effect instances are ordered by location first, so sorted output follows
+/// the source code
+impl Ord for EffectInstance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.call_loc, &self.caller, &self.callee, &self.eff_type).cmp(&(
+            &other.call_loc,
+            &other.caller,
+            &other.callee,
+            &other.eff_type,
+        ))
+    }
+}
+
+impl PartialOrd for EffectInstance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 /*
@@ -446,3 +1536,135 @@ impl FnDec {
 fn test_csv_header() {
     assert!(EffectInstance::csv_header().ends_with(SrcLoc::csv_header()));
 }
+
+#[test]
+fn test_uninit_memory_fn() {
+    let fn_path = CanonicalPath::new;
+    assert!(is_uninit_memory_fn(&fn_path("core::mem::zeroed"), false));
+    assert!(is_uninit_memory_fn(&fn_path("alloc::vec::Vec::set_len"), false));
+    assert!(is_uninit_memory_fn(&fn_path("assume_init"), true));
+    assert!(!is_uninit_memory_fn(&fn_path("assume_init"), false));
+    assert!(!is_uninit_memory_fn(&fn_path("mycrate::Foo::set_len"), true));
+}
+
+#[test]
+fn test_ptr_arithmetic_unsafety() {
+    let add = Effect::PtrArithmetic(CanonicalPath::new("UNKNOWN_METHOD::add"));
+    let wrapping_add =
+        Effect::PtrArithmetic(CanonicalPath::new("UNKNOWN_METHOD::wrapping_add"));
+    assert!(add.is_rust_unsafe());
+    assert_eq!(add.unsafe_op(), Some(UnsafeOp::CallUnsafeFn));
+    assert!(!wrapping_add.is_rust_unsafe());
+    assert_eq!(wrapping_add.unsafe_op(), None);
+}
+
+#[test]
+fn test_alias_escape_fn() {
+    let fn_path = CanonicalPath::new;
+    assert!(matches_fn_table(&fn_path("core::cell::UnsafeCell::get"), ALIAS_ESCAPE_FNS));
+    assert!(matches_fn_table(&fn_path("core::cell::Cell::as_ptr"), ALIAS_ESCAPE_FNS));
+    assert!(!matches_fn_table(&fn_path("core::cell::Cell::get"), ALIAS_ESCAPE_FNS));
+}
+
+#[test]
+fn test_effect_selector() {
+    let parse = |s: &str| s.parse::<EffectSelector>();
+    assert_eq!(parse("ffi"), Ok(EffectSelector::Ffi));
+    assert_eq!(
+        parse("PtrArithmetic"),
+        Ok(EffectSelector::Type(EffectType::PtrArithmetic))
+    );
+    assert!(parse("network").is_err());
+
+    let types = EffectSelector::expand(&[
+        EffectSelector::RawPointer,
+        EffectSelector::Type(EffectType::PtrArithmetic),
+        EffectSelector::Ffi,
+    ]);
+    assert_eq!(
+        types,
+        [
+            EffectType::RawPointer,
+            EffectType::PtrArithmetic,
+            EffectType::IntPtrCast,
+            EffectType::RawPtrCast,
+            EffectType::FFICall,
+            EffectType::StaticExt,
+        ]
+    );
+    let callee = CanonicalPath::new("libc::getuid");
+    assert!(EffectType::matches_effect(&types, &Effect::FFICall(callee.clone())));
+    assert!(!EffectType::matches_effect(&types, &Effect::UnsafeCall(callee)));
+}
+
+#[test]
+fn test_closure_context() {
+    let process_files = CanonicalPath::new("my_crate::process_files");
+    let map = ClosureContext { adapter: Some("map".to_string()), index: 0 };
+    assert_eq!(map.ident().as_str(), "map_closure_0");
+    assert_eq!(
+        map.describe(&process_files),
+        "inside `.map()` closure in `process_files`"
+    );
+    let closure = ClosureContext { adapter: None, index: 1 };
+    assert_eq!(closure.ident().as_str(), "closure_1");
+    assert_eq!(closure.describe(&process_files), "inside closure #2 in `process_files`");
+}
+
+#[test]
+fn test_spawn_fn() {
+    let fn_path = CanonicalPath::new;
+    assert!(is_spawn_fn(&fn_path("std::thread::spawn")));
+    assert!(is_spawn_fn(&fn_path("std::thread::Builder::spawn")));
+    assert!(is_spawn_fn(&fn_path("tokio::task::spawn_blocking")));
+    assert!(!is_spawn_fn(&fn_path("std::process::Command::spawn")));
+    assert!(may_be_spawn_fn("spawn_blocking"));
+    assert!(!may_be_spawn_fn("scope"));
+}
+
+#[test]
+fn test_os_hook() {
+    let fn_path = CanonicalPath::new;
+    assert_eq!(os_hook(&fn_path("libc::sigaction")), Some(OsHook::SignalHandler));
+    assert_eq!(os_hook(&fn_path("nix::unistd::fork")), Some(OsHook::Fork));
+    assert_eq!(
+        os_hook(&fn_path("signal_hook::flag::register")),
+        Some(OsHook::SignalHandler)
+    );
+    assert_eq!(os_hook(&fn_path("libc::atexit")), Some(OsHook::AtExit));
+    assert_eq!(os_hook(&fn_path("mycrate::fork")), None);
+    assert_eq!(os_hook(&fn_path("fork")), None);
+    // Defined in a private module of libc
+    assert_eq!(
+        os_hook(&fn_path("libc::unix::linux_like::pthread_atfork")),
+        Some(OsHook::Fork)
+    );
+    assert_eq!(os_hook(&fn_path("mycrate::libc::fork")), None);
+    assert_eq!(os_hook(&fn_path("mycrate::flag::register")), None);
+    assert!(may_be_os_hook("pre_exec"));
+    assert_eq!("os-hooks".parse::<EffectSelector>(), Ok(EffectSelector::OsHooks));
+}
+
+#[test]
+fn test_memory_map() {
+    let fn_path = CanonicalPath::new;
+    assert!(is_memory_map_fn(&fn_path("libc::mmap")));
+    assert!(is_memory_map_fn(&fn_path("memmap2::MmapOptions::map_exec")));
+    assert!(!is_memory_map_fn(&fn_path("std::iter::Iterator::map")));
+    let rwx = MemProtection::from_flags(["PROT_READ", "libc", "PROT_WRITE", "PROT_EXEC"]);
+    assert_eq!(rwx, Some(MemProtection::new(true, true, true)));
+    assert_eq!(MemProtection::from_flags(["len", "fd"]), None);
+    let rx = MemProtection::from_flags(["PAGE_EXECUTE_READ"]).unwrap();
+    assert_eq!(rx.to_string(), "r-x");
+    let eff = Effect::MemoryMap(fn_path("libc::mmap"), rwx);
+    assert_eq!(eff.simple_str(), "[MemoryMap(rwx)]");
+    assert_eq!(eff.severity(), Severity::Critical);
+    let eff = Effect::MemoryMap(fn_path("libc::mmap"), None);
+    assert_eq!(eff.severity(), Severity::High);
+}
+
+#[test]
+fn test_quickfix_loc() {
+    let loc = SrcLoc::new(FilePath::new("src/lib.rs"), 10, 4, 10, 20);
+    assert_eq!(loc.to_quickfix(), "src/lib.rs:10:5");
+}
@@ -0,0 +1,242 @@
+//! Notions of "the same effect", used when comparing two sets of effects: a
+//! scan against a baseline artifact, or a changed crate against its previous
+//! audit.
+//!
+//! - `location` (the default): effects are the same only if they are equal,
+//!   including their source location, so any edit above an effect makes it a
+//!   new one;
+//! - `path`: effects are the same if they have the same caller, callee, and
+//!   kind, so they survive code moving around;
+//! - `snippet`: effects are the same if they have the same callee, kind, and
+//!   source text (ignoring whitespace), so they also survive a renamed or
+//!   moved caller. Effects whose source text isn't known (e.g. the effects of
+//!   an audit file) are compared by path instead, but only paired up if they
+//!   are the only effect left with their path on each side: several calls
+//!   with the same path can't be told apart without their text.
+//!
+//! Effects with the same identity are paired up in order of location, so that
+//! e.g. the second of two calls to `libc::getuid` in a function is matched
+//! with the second such call in the other set.
+
+use crate::effect::EffectInstance;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+#[derive(
+    ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EffectIdentity {
+    /// Equal effects, including their source location
+    #[default]
+    Location,
+    /// Same caller, callee, and effect kind
+    Path,
+    /// Same callee, effect kind, and source text
+    Snippet,
+}
+
+impl EffectIdentity {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The identity of the effect as text, e.g. to refer to it from records
+    /// which outlive the ids of an audit: effects with the same label are the
+    /// same effect. None under a snippet identity without the source text.
+    pub fn label(self, effect: &EffectInstance, snippet: Option<&str>) -> Option<String> {
+        self.key(effect, snippet).map(|key| key.to_string())
+    }
+
+    /// The key effects are compared by, or None if the effect has no key
+    /// under this identity (a snippet identity without the source text)
+    fn key<'a>(
+        self,
+        effect: &'a EffectInstance,
+        snippet: Option<&str>,
+    ) -> Option<Key<'a>> {
+        let kind = effect.eff_type().simple_str();
+        match self {
+            Self::Location => Some(Key::Location(effect)),
+            Self::Path => Some(Key::Path {
+                caller: effect.caller_path(),
+                callee: effect.callee_path(),
+                kind,
+            }),
+            Self::Snippet => Some(Key::Snippet {
+                callee: effect.callee_path(),
+                kind,
+                text: snippet?.split_whitespace().collect(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Key<'a> {
+    Location(&'a EffectInstance),
+    Path { caller: &'a str, callee: &'a str, kind: Cow<'a, str> },
+    Snippet { callee: &'a str, kind: Cow<'a, str>, text: String },
+}
+
+impl fmt::Display for Key<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Location(e) => write!(
+                f,
+                "{} -> {} {} at {}",
+                e.caller_path(),
+                e.callee_path(),
+                e.eff_type().simple_str(),
+                e.call_loc()
+            ),
+            Key::Path { caller, callee, kind } => {
+                write!(f, "{} -> {} {}", caller, callee, kind)
+            }
+            Key::Snippet { callee, kind, text } => {
+                write!(f, "{} {}: {}", callee, kind, text)
+            }
+        }
+    }
+}
+
+/// An effect, with its source lines if they are known
+pub type EffectSource<'a> = (&'a EffectInstance, Option<&'a str>);
+
+/// Pair up the effects of `old` and `new` which are the same effect under the
+/// identity. Returns the pairs of indices (in `old`, in `new`), sorted.
+pub fn match_effects(
+    identity: EffectIdentity,
+    old: &[EffectSource],
+    new: &[EffectSource],
+) -> Vec<(usize, usize)> {
+    fn by_location(effects: &[EffectSource]) -> Vec<usize> {
+        let mut ids = (0..effects.len()).collect::<Vec<_>>();
+        ids.sort_by_key(|&i| effects[i].0);
+        ids
+    }
+    let mut old_left = by_location(old);
+    let mut new_left = by_location(new);
+
+    // The identity of each pass, and whether it only pairs up the effects
+    // which are the only ones with their key on each side
+    let passes: &[(EffectIdentity, bool)] = match identity {
+        EffectIdentity::Snippet => {
+            &[(EffectIdentity::Snippet, false), (EffectIdentity::Path, true)]
+        }
+        EffectIdentity::Path => &[(EffectIdentity::Path, false)],
+        EffectIdentity::Location => &[(EffectIdentity::Location, false)],
+    };
+    let mut pairs = Vec::new();
+    for &(pass, unique) in passes {
+        let mut by_key: HashMap<Key, VecDeque<usize>> = HashMap::new();
+        for &i in &old_left {
+            if let Some(key) = pass.key(old[i].0, old[i].1) {
+                by_key.entry(key).or_default().push_back(i);
+            }
+        }
+        if unique {
+            let mut new_counts: HashMap<Key, usize> = HashMap::new();
+            for &j in &new_left {
+                if let Some(key) = pass.key(new[j].0, new[j].1) {
+                    *new_counts.entry(key).or_default() += 1;
+                }
+            }
+            by_key.retain(|key, ids| ids.len() == 1 && new_counts.get(key) == Some(&1));
+        }
+        let mut matched_old = HashSet::new();
+        new_left.retain(|&j| {
+            let Some(key) = pass.key(new[j].0, new[j].1) else {
+                return true;
+            };
+            match by_key.get_mut(&key).and_then(|ids| ids.pop_front()) {
+                Some(i) => {
+                    matched_old.insert(i);
+                    pairs.push((i, j));
+                    false
+                }
+                None => true,
+            }
+        });
+        old_left.retain(|i| !matched_old.contains(i));
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Indices of the effects of `new` which aren't in `old` under the identity
+pub fn new_effects(
+    identity: EffectIdentity,
+    old: &[EffectSource],
+    new: &[EffectSource],
+) -> Vec<usize> {
+    let mut matched = vec![false; new.len()];
+    for (_, j) in match_effects(identity, old, new) {
+        matched[j] = true;
+    }
+    (0..new.len()).filter(|&j| !matched[j]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::Path;
+
+    /// Effects for the tokens of the source, in order, with the callee
+    /// `libc::<token>`
+    fn effects(callers: &[&str], src: &str) -> Vec<EffectInstance> {
+        let tokens = src.parse::<proc_macro2::TokenStream>().unwrap();
+        tokens
+            .into_iter()
+            .zip(callers)
+            .map(|(t, caller)| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new(caller),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_match_effects() {
+        let old = effects(&["lib::f", "lib::f", "lib::g"], "getuid\ngetuid\nfork");
+        // Two lines were added above f, so no effect is on the same line as
+        // before, and g was renamed to h
+        let new = effects(&["lib::f", "lib::f", "lib::h"], "\n\ngetuid\ngetuid\nfork");
+        let old_src =
+            [(&old[0], Some("getuid()")), (&old[1], None), (&old[2], Some("fork()"))];
+        let new_src =
+            [(&new[0], Some("getuid()")), (&new[1], None), (&new[2], Some("  fork( )"))];
+
+        assert!(match_effects(EffectIdentity::Location, &old_src, &new_src).is_empty());
+        assert_eq!(
+            match_effects(EffectIdentity::Location, &old_src, &old_src),
+            [(0, 0), (1, 1), (2, 2)]
+        );
+        assert_eq!(
+            match_effects(EffectIdentity::Path, &old_src, &new_src),
+            [(0, 0), (1, 1)]
+        );
+        assert_eq!(
+            match_effects(EffectIdentity::Snippet, &old_src, &new_src),
+            [(0, 0), (1, 1), (2, 2)]
+        );
+        assert_eq!(new_effects(EffectIdentity::Path, &old_src, &new_src), [2]);
+
+        // Without their text, the two calls in f can't be told apart
+        let old_src = [(&old[0], None), (&old[1], None), (&old[2], Some("fork()"))];
+        let new_src = [(&new[0], None), (&new[1], None), (&new[2], Some("fork()"))];
+        assert_eq!(match_effects(EffectIdentity::Snippet, &old_src, &new_src), [(2, 2)]);
+    }
+}
@@ -0,0 +1,69 @@
+//! Inverted index of the effects of a scan by callee, for quick lookups such
+//! as every call to `libc::mmap`.
+//!
+//! Effect ids are indices into the (sorted) list of effects the index was
+//! built from, e.g. the effects of a scan artifact.
+
+use crate::effect::EffectInstance;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct CalleeIndex(BTreeMap<String, Vec<usize>>);
+
+impl CalleeIndex {
+    pub fn new<'a, I>(effects: I) -> Self
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let mut index: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (id, e) in effects.into_iter().enumerate() {
+            index.entry(e.callee_path().to_string()).or_default().push(id);
+        }
+        Self(index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Ids of the effects calling exactly this path
+    pub fn get(&self, callee: &str) -> &[usize] {
+        self.0.get(callee).map_or(&[], |ids| ids.as_slice())
+    }
+
+    /// Ids of the effects calling this path or any path under it (e.g. `libc`
+    /// matches `libc::mmap`, but not `libcore`), in order
+    pub fn get_prefix(&self, prefix: &str) -> Vec<usize> {
+        let nested = format!("{}::", prefix);
+        let mut ids = self
+            .0
+            .range(prefix.to_string()..)
+            .take_while(|(callee, _)| callee.starts_with(prefix))
+            .filter(|(callee, _)| *callee == prefix || callee.starts_with(&nested))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callee_index() {
+        let index = CalleeIndex(BTreeMap::from([
+            ("libc::mmap".to_string(), vec![0, 3]),
+            ("libc::munmap".to_string(), vec![1]),
+            ("libcore::mem".to_string(), vec![2]),
+        ]));
+        assert_eq!(index.get("libc::mmap"), [0, 3]);
+        assert!(index.get("libc").is_empty());
+        assert_eq!(index.get_prefix("libc"), [0, 1, 3]);
+        assert_eq!(index.get_prefix("libc::mmap"), [0, 3]);
+    }
+}
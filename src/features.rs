@@ -0,0 +1,710 @@
+//! Which cargo features of a crate gate its effects, e.g.
+//! ```text
+//! disabling feature `vendored` removes 212 FFICall effects
+//! ```
+//! so that users of a dependency can reduce its risk through the features
+//! they select.
+//!
+//! Effects are gated by the `#[cfg(..)]` attributes mentioning features on
+//! the items, statements, and match arms around them, and on the `mod`
+//! declarations of their files. Disabling a feature also disables the
+//! features which enable it (e.g. `default`), and an effect is removed if
+//! one of its cfgs no longer holds. Only the enabled features are reported,
+//! since the code behind the disabled ones isn't scanned.
+//!
+//! Building on this, [`suggest_features`] suggests a minimal feature set
+//! for each direct dependency, as `Cargo.toml` entries: the features gating
+//! effects are dropped, unless the crate calls functions behind them.
+
+use crate::attr_parser::{CfgOpt, CfgPred};
+use crate::dep_cache::DepGraph;
+use crate::effect::{EffectInstance, EffectType, SrcLoc};
+use crate::progress;
+use crate::scanner::{self, ScanConfig};
+use crate::util;
+
+use anyhow::{Context, Result};
+use log::debug;
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::ToTokens;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::visit_mut::{self, VisitMut};
+use toml::value::{Table, Value};
+
+/// The `[features]` of a crate: each feature with the features it enables
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrateFeatures(BTreeMap<String, Vec<String>>);
+
+impl CrateFeatures {
+    pub fn load(crate_path: &Path) -> Result<Self> {
+        let toml_string = std::fs::read_to_string(crate_path.join("Cargo.toml"))?;
+        let cargo_toml =
+            toml::from_str::<Table>(&toml_string).context("Couldn't parse Cargo.toml")?;
+        let features = cargo_toml
+            .get("features")
+            .and_then(Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .map(|(f, enables)| {
+                        let enables = enables
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|e| e.as_str().map(String::from))
+                            .collect();
+                        (f.clone(), enables)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self(features))
+    }
+
+    /// The features disabled along with the feature: itself, and the
+    /// features enabling it, transitively
+    pub fn disabled_with(&self, feature: &str) -> BTreeSet<String> {
+        let mut disabled = BTreeSet::from([feature.to_string()]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (f, enables) in &self.0 {
+                if !disabled.contains(f) && enables.iter().any(|e| disabled.contains(e)) {
+                    disabled.insert(f.clone());
+                    changed = true;
+                }
+            }
+        }
+        disabled
+    }
+
+    /// The enabled features left on after disabling the feature: those
+    /// disabled with it go, and so do the features it turns on (e.g. `std`
+    /// from `default`) which nothing left on enables, as the resolved
+    /// feature set of a crate doesn't say which features were asked for
+    pub fn remaining(&self, enabled: &[String], feature: &str) -> Vec<String> {
+        let enables =
+            |f: &String, e: &String| self.0.get(f).is_some_and(|es| es.contains(e));
+        let disabled = self.disabled_with(feature);
+        let mut implied = BTreeSet::new();
+        let mut todo = vec![feature.to_string()];
+        while let Some(f) = todo.pop() {
+            for e in enabled {
+                if !disabled.contains(e) && enables(&f, e) && implied.insert(e.clone()) {
+                    todo.push(e.clone());
+                }
+            }
+        }
+        // Keep those still enabled by a feature left on
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for f in implied.clone() {
+                let kept = enabled.iter().any(|e| {
+                    !disabled.contains(e) && !implied.contains(e) && enables(e, &f)
+                });
+                if kept {
+                    implied.remove(&f);
+                    changed = true;
+                }
+            }
+        }
+        enabled
+            .iter()
+            .filter(|f| !disabled.contains(*f) && !implied.contains(*f))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Code behind a cfg mentioning a feature
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Gate {
+    /// The file, or the directory of a module's files
+    path: PathBuf,
+    /// The lines of the file, or None for all of the file or directory
+    lines: Option<(usize, usize)>,
+    pred: CfgPred,
+}
+
+impl Gate {
+    fn contains(&self, loc: &SrcLoc) -> bool {
+        let file = loc.dir().join(loc.file());
+        match self.lines {
+            Some((start, end)) => {
+                file == self.path && (start..=end).contains(&loc.start_line())
+            }
+            None => file.starts_with(&self.path),
+        }
+    }
+}
+
+fn mentions_feature(pred: &CfgPred) -> bool {
+    match pred {
+        CfgPred::Option(CfgOpt::Pair { key, .. }) => key == "feature",
+        CfgPred::Option(CfgOpt::Name(_)) | CfgPred::Invalid => false,
+        CfgPred::All(preds) | CfgPred::Any(preds) => preds.iter().any(mentions_feature),
+        CfgPred::Not(pred) => mentions_feature(pred),
+    }
+}
+
+/// The feature cfgs in the outer attributes at the start of the tokens of a
+/// syntax node
+fn leading_cfgs(tokens: TokenStream) -> Vec<CfgPred> {
+    let mut preds = Vec::new();
+    let mut it = tokens.into_iter();
+    while let (Some(TokenTree::Punct(p)), Some(TokenTree::Group(g))) =
+        (it.next(), it.next())
+    {
+        if p.as_char() != '#' || g.delimiter() != Delimiter::Bracket {
+            break;
+        }
+        let mut attr = g.stream().into_iter();
+        if let (Some(TokenTree::Ident(i)), Some(TokenTree::Group(args))) =
+            (attr.next(), attr.next())
+        {
+            if i == "cfg" {
+                preds.push(CfgPred::parse(&args.stream()));
+            }
+        }
+    }
+    preds.retain(mentions_feature);
+    preds
+}
+
+/// Where the files of the module declared with `mod <name>;` in the file are
+fn module_path(file: &Path, name: &str) -> [PathBuf; 2] {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let stem = file.file_stem().unwrap_or_default();
+    let base = if ["lib", "main", "mod"].iter().any(|s| stem == *s) {
+        dir.to_path_buf()
+    } else {
+        dir.join(stem)
+    };
+    [base.join(format!("{}.rs", name)), base.join(name)]
+}
+
+struct GateCollector<'a> {
+    file: &'a Path,
+    gates: Vec<Gate>,
+}
+
+impl GateCollector<'_> {
+    fn add<T: ToTokens + Spanned>(&mut self, node: &T) {
+        for pred in leading_cfgs(node.to_token_stream()) {
+            let span = node.span();
+            let lines = (span.start().line, span.end().line);
+            self.gates.push(Gate {
+                path: self.file.to_path_buf(),
+                lines: Some(lines),
+                pred,
+            });
+        }
+    }
+}
+
+impl VisitMut for GateCollector<'_> {
+    fn visit_item_mut(&mut self, i: &mut syn::Item) {
+        if let syn::Item::Mod(m) = i {
+            if m.content.is_none() {
+                for pred in leading_cfgs(m.to_token_stream()) {
+                    for path in module_path(self.file, &m.ident.to_string()) {
+                        self.gates.push(Gate { path, lines: None, pred: pred.clone() });
+                    }
+                }
+            }
+        }
+        self.add(i);
+        visit_mut::visit_item_mut(self, i);
+    }
+
+    fn visit_impl_item_mut(&mut self, i: &mut syn::ImplItem) {
+        self.add(i);
+        visit_mut::visit_impl_item_mut(self, i);
+    }
+
+    fn visit_trait_item_mut(&mut self, i: &mut syn::TraitItem) {
+        self.add(i);
+        visit_mut::visit_trait_item_mut(self, i);
+    }
+
+    fn visit_foreign_item_mut(&mut self, i: &mut syn::ForeignItem) {
+        self.add(i);
+        visit_mut::visit_foreign_item_mut(self, i);
+    }
+
+    fn visit_stmt_mut(&mut self, s: &mut syn::Stmt) {
+        self.add(s);
+        visit_mut::visit_stmt_mut(self, s);
+    }
+
+    fn visit_arm_mut(&mut self, a: &mut syn::Arm) {
+        self.add(a);
+        visit_mut::visit_arm_mut(self, a);
+    }
+}
+
+/// The feature cfgs of a crate's source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureGates {
+    gates: Vec<Gate>,
+}
+
+impl FeatureGates {
+    pub fn from_files(files: &[PathBuf]) -> Self {
+        let file_gates = files
+            .par_iter()
+            .map(|file| {
+                let mut gates = Self::default();
+                match util::fs::read_source(file) {
+                    Ok(src) => gates.add_source(file, &src.text),
+                    Err(err) => debug!("Couldn't read {:?} for its cfgs ({})", file, err),
+                }
+                gates
+            })
+            .collect::<Vec<_>>();
+        Self { gates: file_gates.into_iter().flat_map(|g| g.gates).collect() }
+    }
+
+    fn add_source(&mut self, file: &Path, src: &str) {
+        let Ok(mut syntax_tree) = syn::parse_file(src) else {
+            debug!("Couldn't parse {:?} for its cfgs", file);
+            return;
+        };
+        // #![cfg(..)] gates the whole file
+        for attr in &syntax_tree.attrs {
+            if let syn::Meta::List(l) = &attr.meta {
+                let pred = CfgPred::parse(&l.tokens);
+                if l.path.is_ident("cfg") && mentions_feature(&pred) {
+                    self.gates.push(Gate { path: file.to_path_buf(), lines: None, pred });
+                }
+            }
+        }
+        let mut collector = GateCollector { file, gates: Vec::new() };
+        collector.visit_file_mut(&mut syntax_tree);
+        self.gates.extend(collector.gates);
+    }
+
+    /// Whether the code at the location is removed when only the features
+    /// are on
+    fn removes(
+        &self,
+        loc: &SrcLoc,
+        on: Vec<String>,
+        enabled_cfg: &HashMap<String, Vec<String>>,
+    ) -> bool {
+        let mut cfg = enabled_cfg.clone();
+        cfg.insert("feature".to_string(), on);
+        self.gates.iter().any(|g| g.contains(loc) && !g.pred.is_enabled(&cfg))
+    }
+
+    /// The enabled features whose disabling removes the code at the location
+    pub fn gating_features(
+        &self,
+        loc: &SrcLoc,
+        features: &CrateFeatures,
+        enabled_cfg: &HashMap<String, Vec<String>>,
+    ) -> BTreeSet<String> {
+        if !self.gates.iter().any(|g| g.contains(loc)) {
+            return BTreeSet::new();
+        }
+        let enabled = enabled_cfg.get("feature").cloned().unwrap_or_default();
+        enabled
+            .iter()
+            .filter(|f| {
+                let on = features.remaining(&enabled, f);
+                self.removes(loc, on, enabled_cfg)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// The effects removed by disabling each enabled feature of a crate
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureReport {
+    pub removed: BTreeMap<String, Vec<EffectInstance>>,
+    features: CrateFeatures,
+    gates: FeatureGates,
+    enabled_cfg: HashMap<String, Vec<String>>,
+}
+
+/// A smaller feature set for a crate: `default-features = false` with only
+/// the `keep` features, which drops the `drop` features and the effects
+/// behind them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimalFeatures {
+    pub keep: BTreeSet<String>,
+    pub drop: BTreeSet<String>,
+    /// Number of effects removed
+    pub removed: usize,
+}
+
+impl FeatureReport {
+    pub fn new(
+        gates: FeatureGates,
+        features: CrateFeatures,
+        enabled_cfg: HashMap<String, Vec<String>>,
+        effects: &[EffectInstance],
+    ) -> Self {
+        let mut removed: BTreeMap<String, Vec<EffectInstance>> = BTreeMap::new();
+        for e in effects {
+            for f in gates.gating_features(e.call_loc(), &features, &enabled_cfg) {
+                removed.entry(f).or_default().push(e.clone());
+            }
+        }
+        Self { removed, features, gates, enabled_cfg }
+    }
+
+    /// The features to keep so that the code at the locations (e.g. of the
+    /// functions a dependent calls) stays, dropping as many of the features
+    /// gating effects as possible, those removing the most effects first.
+    /// None if none of them can be dropped. `default` itself is always
+    /// dropped, and the features it enables are kept explicitly unless
+    /// dropped.
+    pub fn minimal_features(&self, used: &[&SrcLoc]) -> Option<MinimalFeatures> {
+        let enabled = self.enabled_cfg.get("feature").cloned().unwrap_or_default();
+        let mut candidates =
+            self.removed.iter().filter(|(f, _)| *f != "default").collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, effects)| std::cmp::Reverse(effects.len()));
+        let mut drop = BTreeSet::new();
+        let mut disabled = BTreeSet::new();
+        for (f, _) in candidates {
+            let mut with_f = disabled.clone();
+            with_f.extend(self.features.disabled_with(f));
+            let on = enabled
+                .iter()
+                .filter(|e| !with_f.contains(*e))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !used
+                .iter()
+                .any(|loc| self.gates.removes(loc, on.clone(), &self.enabled_cfg))
+            {
+                drop.insert(f.clone());
+                disabled = with_f;
+            }
+        }
+        if drop.is_empty() {
+            return None;
+        }
+        let keep = enabled
+            .into_iter()
+            .filter(|f| f != "default" && !disabled.contains(f))
+            .collect();
+        let removed = disabled
+            .iter()
+            .filter(|f| *f != "default")
+            .filter_map(|f| self.removed.get(f))
+            .flatten()
+            .collect::<HashSet<_>>()
+            .len();
+        Some(MinimalFeatures { keep, drop, removed })
+    }
+}
+
+impl fmt::Display for FeatureReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Features removing the most effects first
+        let mut removed = self.removed.iter().collect::<Vec<_>>();
+        removed.sort_by_key(|(_, effects)| std::cmp::Reverse(effects.len()));
+        for (feature, effects) in removed {
+            let mut counts = BTreeMap::new();
+            for e in effects {
+                *counts.entry(EffectType::from(e.eff_type()).to_string()).or_insert(0) +=
+                    1;
+            }
+            let counts = counts
+                .iter()
+                .map(|(kind, n)| format!("{} {} effects", n, kind))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "disabling feature `{}` removes {}", feature, counts)?;
+        }
+        Ok(())
+    }
+}
+
+/// A direct dependency of a crate, as declared in its Cargo.toml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectDependency {
+    /// Name of the dependency in the Cargo.toml, which may be a rename
+    pub name: String,
+    pub package: String,
+    pub version_req: String,
+    pub optional: bool,
+    /// The features the crate resolves for the dependency
+    pub features: Vec<String>,
+    /// Directory of the dependency's source
+    pub path: PathBuf,
+}
+
+/// The direct dependencies of the crate (other than dev-dependencies),
+/// downloading them if needed
+pub fn direct_dependencies(crate_path: &Path) -> Result<Vec<DirectDependency>> {
+    let graph = DepGraph::load(crate_path)?;
+    let root = graph.get(&graph.root).context("Missing root crate in the resolve")?;
+    let mut deps = Vec::new();
+    for dep in root.deps.iter().filter(|d| !d.dev_only) {
+        let resolved = graph
+            .get(&dep.crate_id)
+            .context(format!("Missing {} in the resolve", dep.crate_id))?;
+        deps.push(DirectDependency {
+            name: dep.name.clone(),
+            package: dep.crate_id.crate_name.clone(),
+            version_req: dep.version_req.clone(),
+            optional: dep.optional,
+            features: resolved.features.clone(),
+            path: resolved.path.clone(),
+        });
+    }
+    deps.sort_by(|d1, d2| d1.name.cmp(&d2.name));
+    Ok(deps)
+}
+
+/// A smaller feature set for a direct dependency which keeps the functions
+/// the crate calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureSuggestion {
+    pub dep: DirectDependency,
+    pub features: MinimalFeatures,
+    /// Number of called functions which couldn't be found in the dependency
+    /// (e.g. re-exports), so may be behind a dropped feature
+    pub unlocated: usize,
+}
+
+impl FeatureSuggestion {
+    /// The dependency's entry in the `[dependencies]` of Cargo.toml
+    pub fn to_toml(&self) -> String {
+        let mut fields = Vec::new();
+        if self.dep.package != self.dep.name {
+            fields.push(format!("package = \"{}\"", self.dep.package));
+        }
+        fields.push(format!("version = \"{}\"", self.dep.version_req));
+        fields.push("default-features = false".to_string());
+        if !self.features.keep.is_empty() {
+            let keep = self
+                .features
+                .keep
+                .iter()
+                .map(|f| format!("\"{}\"", f))
+                .collect::<Vec<_>>()
+                .join(", ");
+            fields.push(format!("features = [{}]", keep));
+        }
+        if self.dep.optional {
+            fields.push("optional = true".to_string());
+        }
+        format!("{} = {{ {} }}", self.dep.name, fields.join(", "))
+    }
+}
+
+impl fmt::Display for FeatureSuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let drop = self
+            .features
+            .drop
+            .iter()
+            .map(|feature| format!("`{}`", feature))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            f,
+            "# {}: drops {} (removes {} effects)",
+            self.dep.name, drop, self.features.removed
+        )?;
+        if self.unlocated > 0 {
+            writeln!(
+                f,
+                "# {} called functions couldn't be located; check that it builds",
+                self.unlocated
+            )?;
+        }
+        writeln!(f, "{}", self.to_toml())
+    }
+}
+
+/// Suggest a minimal feature set for each direct dependency of the crate:
+/// the features gating the dependency's effects are dropped, unless the
+/// crate calls functions behind them. The dependencies are scanned with the
+/// feature report on (see [`ScanConfig::feature_report`]).
+pub fn suggest_features(
+    crate_path: &Path,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) -> Result<Vec<FeatureSuggestion>> {
+    let mut suggestions = Vec::new();
+    for dep in direct_dependencies(crate_path)? {
+        progress!("Scanning {}...", dep.package);
+        let dep_config = ScanConfig {
+            feature_report: true,
+            enabled_features: Some(dep.features.clone()),
+            ..config.clone()
+        };
+        let results = scanner::scan_crate(&dep.path, relevant_effects, &dep_config)?;
+        let Some(report) = &results.feature_report else {
+            continue;
+        };
+        if report.removed.is_empty() {
+            continue;
+        }
+
+        // The functions of the dependency called by the crate, found as sinks
+        let sinks = results.pub_fns.iter().map(|f| f.clone().to_path()).collect();
+        let uses = scanner::scan_crate_with_sinks(
+            crate_path,
+            sinks,
+            &[EffectType::SinkCall],
+            config,
+        )?;
+        let prefix = format!("{}::", dep.package.replace('-', "_"));
+        let called = uses
+            .effects
+            .iter()
+            .map(|e| e.callee_path())
+            .filter(|c| c.starts_with(&prefix))
+            .collect::<BTreeSet<_>>();
+        let fn_locs = results
+            .fn_locs
+            .iter()
+            .map(|(f, loc)| (f.as_str(), loc))
+            .collect::<HashMap<_, _>>();
+        let used =
+            called.iter().filter_map(|c| fn_locs.get(c).copied()).collect::<Vec<_>>();
+
+        if let Some(features) = report.minimal_features(&used) {
+            let unlocated = called.len() - used.len();
+            suggestions.push(FeatureSuggestion { dep, features, unlocated });
+        }
+    }
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+
+    #[test]
+    fn test_feature_gates() {
+        let features = CrateFeatures(BTreeMap::from([
+            ("default".to_string(), vec!["std".to_string()]),
+            ("std".to_string(), vec![]),
+            ("vendored".to_string(), vec!["dep:openssl-src".to_string()]),
+        ]));
+        assert_eq!(
+            features.disabled_with("std"),
+            BTreeSet::from(["default".into(), "std".into()])
+        );
+        assert_eq!(
+            features.disabled_with("vendored"),
+            BTreeSet::from(["vendored".into()])
+        );
+
+        let file = Path::new("src/lib.rs");
+        let src = r#"
+#[cfg(feature = "vendored")]
+mod vendored;
+
+pub fn f() {
+    #[cfg(all(unix, feature = "std"))]
+    libc::getuid();
+    libc::fork();
+}
+"#;
+        let mut gates = FeatureGates::default();
+        gates.add_source(file, src);
+        let enabled_cfg = HashMap::from([
+            ("unix".to_string(), vec![]),
+            (
+                "feature".to_string(),
+                vec!["default".into(), "std".into(), "vendored".into()],
+            ),
+        ]);
+        let gating = |path: &str, line| {
+            let loc = SrcLoc::new(Path::new(path), line, 4, line, 10);
+            gates.gating_features(&loc, &features, &enabled_cfg)
+        };
+        assert_eq!(
+            gating("src/lib.rs", 7),
+            BTreeSet::from(["default".into(), "std".into()])
+        );
+        assert!(gating("src/lib.rs", 8).is_empty());
+        assert_eq!(gating("src/vendored.rs", 1), BTreeSet::from(["vendored".into()]));
+        assert_eq!(gating("src/vendored/ffi.rs", 1), BTreeSet::from(["vendored".into()]));
+    }
+
+    fn effect(path: &str, line: usize) -> EffectInstance {
+        let site = syn::parse_str::<syn::Expr>(&format!("{}f()", "\n".repeat(line - 1)))
+            .unwrap();
+        let callee = CanonicalPath::new("libc::f");
+        EffectInstance::new_effect(
+            Path::new(path),
+            CanonicalPath::new("c::g"),
+            callee.clone(),
+            &site,
+            Effect::FFICall(callee),
+        )
+    }
+
+    #[test]
+    fn test_minimal_features() {
+        let features = CrateFeatures(BTreeMap::from([
+            ("default".to_string(), vec!["std".to_string(), "vendored".to_string()]),
+            ("std".to_string(), vec![]),
+            ("vendored".to_string(), vec![]),
+        ]));
+        let src = r#"
+#[cfg(feature = "vendored")]
+mod vendored;
+
+#[cfg(feature = "std")]
+pub fn read() {
+    libc::read();
+}
+"#;
+        let mut gates = FeatureGates::default();
+        gates.add_source(Path::new("src/lib.rs"), src);
+        let enabled_cfg = HashMap::from([(
+            "feature".to_string(),
+            vec!["default".into(), "std".into(), "vendored".into()],
+        )]);
+        let effects = [effect("src/vendored.rs", 3), effect("src/lib.rs", 7)];
+        let report = FeatureReport::new(gates, features, enabled_cfg, &effects);
+        assert_eq!(report.removed["vendored"].len(), 1);
+        assert_eq!(report.removed["std"].len(), 1);
+
+        // Calling read() needs std
+        let read = SrcLoc::new(Path::new("src/lib.rs"), 6, 0, 8, 1);
+        let minimal = report.minimal_features(&[&read]).unwrap();
+        assert_eq!(minimal.keep, BTreeSet::from(["std".into()]));
+        assert_eq!(minimal.drop, BTreeSet::from(["vendored".into()]));
+        assert_eq!(minimal.removed, 1);
+
+        let minimal = report.minimal_features(&[]).unwrap();
+        assert!(minimal.keep.is_empty());
+        assert_eq!(minimal.removed, 2);
+
+        let dep = DirectDependency {
+            name: "ssl".to_string(),
+            package: "openssl".to_string(),
+            version_req: "^0.10".to_string(),
+            optional: false,
+            features: vec!["default".into(), "std".into(), "vendored".into()],
+            path: PathBuf::new(),
+        };
+        let suggestion = FeatureSuggestion {
+            dep,
+            features: report.minimal_features(&[&read]).unwrap(),
+            unlocated: 0,
+        };
+        assert_eq!(
+            suggestion.to_toml(),
+            r#"ssl = { package = "openssl", version = "^0.10", default-features = false, features = ["std"] }"#
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Fuzzing entry points and proptest strategies for identifiers, patterns,
+//! and audit files, which sink matching and audit serialization rely on.
+//!
+//! Each entry point takes arbitrary input and panics if one of the
+//! properties of the types breaks; they are run by the cargo-fuzz targets in
+//! `fuzz/`, e.g.
+//! ```text
+//! cargo +nightly fuzz run ident_path
+//! ```
+//! The strategies generate well-formed values for property-based tests, and
+//! are available to other crates with the `proptest` feature.
+
+use crate::audit_file::AuditFile;
+use crate::ident::{CanonicalPath, IdentPath, Pattern, PatternRelation};
+
+/// Identifiers, patterns, and matching for a path given as a string
+pub fn fuzz_ident_path(s: &str) {
+    let path = IdentPath::new(s);
+    assert_eq!(path.len(), path.idents().count());
+    if path.invariant() {
+        assert_eq!(IdentPath::from_idents(path.idents()), path);
+    }
+    assert_eq!(path.first_ident().is_none(), path.is_empty());
+    assert_eq!(path.last_ident().is_none(), path.is_empty());
+
+    // The path matches each of its prefixes, which are nested
+    let patterns = path.patterns().collect::<Vec<_>>();
+    assert_eq!(patterns.len(), path.len());
+    for p in &patterns {
+        assert!(path.matches(p), "{} doesn't match {}", path, p);
+    }
+    for w in patterns.windows(2) {
+        assert!(w[1].subset(&w[0]), "{} isn't in {}", w[1], w[0]);
+    }
+
+    let mut popped = path.clone();
+    while popped.pop_ident().is_some() {}
+    let canonical = CanonicalPath::new(s);
+    let _ = canonical.crate_name();
+}
+
+/// The order between two patterns given as strings
+pub fn fuzz_patterns(a: &str, b: &str) {
+    let (p, q) = (Pattern::new(a), Pattern::new(b));
+    assert_eq!(p.subset(&q), q.superset(&p));
+    let reversed = match q.relation(&p) {
+        PatternRelation::Subset => PatternRelation::Superset,
+        PatternRelation::Superset => PatternRelation::Subset,
+        r => r,
+    };
+    assert_eq!(p.relation(&q), reversed);
+
+    // The path of a pattern is in its set, and so in the supersets
+    let path = IdentPath::new(a);
+    assert!(path.matches(&p));
+    if p.subset(&q) {
+        assert!(path.matches(&q), "{} matches {} but not {}", path, p, q);
+    }
+}
+
+/// Loading an audit file, which must save and load back unchanged
+pub fn fuzz_audit_file(data: &[u8]) {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(audit_file) = AuditFile::from_json(json) else {
+        return;
+    };
+    let saved = audit_file.to_canonical_json().expect("loaded audit file can't be saved");
+    let reloaded =
+        AuditFile::from_json(&saved).expect("saved audit file can't be loaded");
+    assert_eq!(reloaded.to_canonical_json().unwrap(), saved);
+    let _ = (audit_file.rejected_effects(), audit_file.audit_coverage(None));
+}
+
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies {
+    use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+    use crate::effect::{Effect, EffectInstance, SrcLoc};
+    use crate::ident::{CanonicalPath, Ident, IdentPath, Pattern};
+
+    use proc_macro2::Span;
+    use proptest::prelude::*;
+    use std::path::PathBuf;
+
+    /// Plain and raw identifiers
+    pub fn ident() -> impl Strategy<Value = Ident> {
+        prop_oneof!["[a-z_][a-z0-9_]{0,6}", "r#[a-z]{1,6}"].prop_map(|s| Ident::new(&s))
+    }
+
+    pub fn ident_path() -> impl Strategy<Value = IdentPath> {
+        prop::collection::vec(ident(), 0..5)
+            .prop_map(|is| IdentPath::from_idents(is.into_iter()))
+    }
+
+    /// Patterns of idents and glob segments, sometimes with a terminal `::**`
+    pub fn pattern() -> impl Strategy<Value = Pattern> {
+        let segment = prop_oneof![
+            3 => ident().prop_map(|i| i.to_string()),
+            1 => Just("*".to_string()),
+            1 => "[a-z]{0,2}\\*[a-z]{0,2}",
+        ];
+        (prop::collection::vec(segment, 0..5), any::<bool>()).prop_map(|(segs, deep)| {
+            let mut s = segs.join("::");
+            if deep {
+                s.push_str(if s.is_empty() { "**" } else { "::**" });
+            }
+            Pattern::new_owned(s)
+        })
+    }
+
+    pub fn canonical_path() -> impl Strategy<Value = CanonicalPath> {
+        prop::collection::vec(ident(), 1..5).prop_map(|is| {
+            CanonicalPath::from_path(
+                IdentPath::from_idents(is.into_iter()),
+                SrcLoc::default(),
+            )
+        })
+    }
+
+    pub fn src_loc() -> impl Strategy<Value = SrcLoc> {
+        (ident(), 0..1000usize, 0..200usize).prop_map(|(file, line, col)| {
+            let path = PathBuf::from(format!("src/{}.rs", file));
+            SrcLoc::new(&path, line, col, line, col + 1)
+        })
+    }
+
+    /// FFI call effects between crate functions
+    pub fn effect_instance() -> impl Strategy<Value = EffectInstance> {
+        (ident(), canonical_path(), canonical_path()).prop_map(
+            |(file, caller, callee)| {
+                let site = proc_macro2::Ident::new("f", Span::call_site());
+                let path = PathBuf::from(format!("src/{}.rs", file));
+                EffectInstance::new_effect(
+                    &path,
+                    caller,
+                    callee.clone(),
+                    &site,
+                    Effect::FFICall(callee),
+                )
+            },
+        )
+    }
+
+    pub fn safety_annotation() -> impl Strategy<Value = SafetyAnnotation> {
+        prop::sample::select(SafetyAnnotation::ALL)
+    }
+
+    /// Audit files of base effects with any annotations
+    pub fn audit_file() -> impl Strategy<Value = AuditFile> {
+        prop::collection::vec((effect_instance(), safety_annotation()), 0..8).prop_map(
+            |effects| {
+                let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![])
+                    .expect("missing crates have an empty hash");
+                audit_file.set_base_audit_trees(effects.iter().map(|(e, _)| e));
+                for (e, a) in &effects {
+                    if let Some(EffectTree::Leaf(_, annotation)) =
+                        audit_file.audit_trees.get_mut(e)
+                    {
+                        *annotation = *a;
+                    }
+                }
+                audit_file
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strategies::*;
+    use super::*;
+    use crate::effect::SrcLoc;
+    use proptest::prelude::*;
+    use std::path::Path;
+
+    proptest! {
+        #[test]
+        fn prop_ident_path(s in "[a-z_:#-]{0,16}") {
+            fuzz_ident_path(&s);
+        }
+
+        #[test]
+        fn prop_patterns(p in pattern(), q in pattern()) {
+            fuzz_patterns(p.as_str(), q.as_str());
+        }
+
+        #[test]
+        fn prop_pattern_order(p in pattern(), q in pattern(), r in pattern()) {
+            if p.subset(&q) && q.subset(&r) {
+                prop_assert!(p.subset(&r));
+            }
+        }
+
+        #[test]
+        fn prop_idents_round_trip(path in ident_path()) {
+            prop_assert!(path.invariant());
+            prop_assert_eq!(IdentPath::from_idents(path.idents()), path.clone());
+            let json = serde_json::to_string(&path).unwrap();
+            prop_assert_eq!(serde_json::from_str::<IdentPath>(&json).unwrap(), path);
+        }
+
+        #[test]
+        fn prop_audit_file_round_trip(audit_file in audit_file()) {
+            let json = audit_file.to_canonical_json().unwrap();
+            let loaded = AuditFile::from_json(&json).unwrap();
+            prop_assert_eq!(loaded.to_canonical_json().unwrap(), json.clone());
+            fuzz_audit_file(json.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_fuzz_regressions() {
+        // Single idents have a first and last ident, and paths only match
+        // patterns on ident boundaries
+        fuzz_ident_path("std");
+        fuzz_ident_path("");
+        fuzz_patterns("std::fsx", "std::fs");
+        fuzz_patterns("std::*::read", "std::f*");
+        fuzz_patterns("tokio::**", "tokio");
+        fuzz_audit_file(b"{\"audit_trees\": 1}");
+        fuzz_audit_file(&[0xff, 0xfe]);
+        // Locations of paths without a file name, before the first line
+        SrcLoc::new(Path::new(".."), 0, 0, 0, 0).sub1();
+    }
+}
@@ -55,7 +55,7 @@ fn infer_module(filepath: &FilePath) -> Vec<String> {
     post_src
 }
 
-fn infer_fully_qualified_prefix(crate_name: &str, filepath: &FilePath) -> String {
+pub fn infer_fully_qualified_prefix(crate_name: &str, filepath: &FilePath) -> String {
     let mut prefix_vec = vec![crate_name.to_string()];
     let mut mod_vec = infer_module(filepath);
     prefix_vec.append(&mut mod_vec);
@@ -80,9 +80,14 @@ pub struct HackyResolver<'a> {
     scope_fun: Vec<&'a syn::Ident>,
     scope_fun_lens: Vec<usize>,
     scope_impl_adds: Vec<usize>,
+    // full path of the Self type of each enclosing impl block
+    // (empty if it isn't a plain path)
+    scope_self: Vec<Vec<&'a syn::Ident>>,
 
     // use name lookups
     use_names: HashMap<&'a syn::Ident, Vec<&'a syn::Ident>>,
+    // type alias lookups, e.g. type Io = std::fs::File
+    type_aliases: HashMap<&'a syn::Ident, Vec<&'a syn::Ident>>,
     ffi_decls: HashMap<&'a syn::Ident, CanonicalPath>,
 
     // TBD: unused
@@ -96,6 +101,7 @@ impl<'a> Resolve<'a> for HackyResolver<'a> {
         debug_assert!(self.scope_fun.is_empty());
         debug_assert!(self.scope_fun_lens.is_empty());
         debug_assert!(self.scope_impl_adds.is_empty());
+        debug_assert!(self.scope_self.is_empty());
     }
 
     fn push_mod(&mut self, mod_ident: &'a syn::Ident) {
@@ -117,6 +123,8 @@ impl<'a> Resolve<'a> for HackyResolver<'a> {
     }
 
     fn push_impl(&mut self, impl_stmt: &'a syn::ItemImpl) {
+        let self_path = self.impl_self_path(&impl_stmt.self_ty);
+        self.scope_self.push(self_path);
         if let Some((_, tr, _)) = &impl_stmt.trait_ {
             // scope trait impls under trait name
             let scope_adds = self.scan_impl_trait_path(tr);
@@ -133,6 +141,7 @@ impl<'a> Resolve<'a> for HackyResolver<'a> {
         for _ in 0..scope_adds {
             self.scope_mods.pop();
         }
+        self.scope_self.pop();
     }
 
     fn push_fn(&mut self, fn_ident: &'a syn::Ident) {
@@ -155,6 +164,18 @@ impl<'a> Resolve<'a> for HackyResolver<'a> {
         self.ffi_decls.insert(fn_name, fn_path);
     }
 
+    fn scan_type_alias(&mut self, t: &'a syn::ItemType) {
+        // only aliases of plain paths can be normalized; generic arguments
+        // are dropped, as they are everywhere else in this resolver
+        match t.ty.as_ref() {
+            syn::Type::Path(x) if x.qself.is_none() => {
+                let fullpath = self.lookup_path_vec(&x.path);
+                self.type_aliases.insert(&t.ident, fullpath);
+            }
+            _ => debug!("Skipping type alias of a non-path type: {}", t.ident),
+        }
+    }
+
     fn resolve_ident(&self, i: &'a syn::Ident) -> CanonicalPath {
         Self::aggregate_path(self.filepath, self.lookup_ident_vec(&i))
     }
@@ -260,12 +281,19 @@ impl<'a> HackyResolver<'a> {
             scope_fun: Vec::new(),
             scope_fun_lens: Vec::new(),
             scope_impl_adds: Vec::new(),
+            scope_self: Vec::new(),
             use_names: HashMap::new(),
+            type_aliases: HashMap::new(),
             ffi_decls: HashMap::new(),
             use_globs: Vec::new(),
         })
     }
 
+    /// Override the module inferred from the file path
+    pub fn set_modpath(&mut self, modpath: CanonicalPath) {
+        self.modpath = modpath;
+    }
+
     /// Reusable warning logger
     fn syn_warning<S: Spanned + Debug>(&self, msg: &str, syn_node: S) {
         let loc = SrcLoc::from_span(self.filepath, &syn_node);
@@ -372,6 +400,15 @@ impl<'a> HackyResolver<'a> {
         // Tuple(x) => {}
     }
 
+    fn impl_self_path(&self, ty: &'a syn::Type) -> Vec<&'a syn::Ident> {
+        match ty {
+            syn::Type::Group(x) => self.impl_self_path(&x.elem),
+            syn::Type::Paren(x) => self.impl_self_path(&x.elem),
+            syn::Type::Path(x) if x.qself.is_none() => self.lookup_path_vec(&x.path),
+            _ => Vec::new(),
+        }
+    }
+
     fn scan_impl_type_path(&mut self, ty: &'a syn::Path) -> usize {
         // return: the number of items added to scope_mods
         let fullpath = self.lookup_path_vec(ty);
@@ -413,12 +450,16 @@ impl<'a> HackyResolver<'a> {
 
     // weird signature: need a double reference on i because i is owned by cur function
     // all hail the borrow checker for catching this error
+    // Self and type aliases are normalized to the path of the underlying type,
+    // so that sink patterns on the real path match
     fn lookup_ident_vec<'c>(&'c self, i: &'c &'a syn::Ident) -> &'c [&'a syn::Ident]
     where
         'a: 'c,
     {
-        self.use_names
-            .get(i)
+        let self_path = self.scope_self.last().filter(|p| **i == "Self" && !p.is_empty());
+        self_path
+            .or_else(|| self.type_aliases.get(i))
+            .or_else(|| self.use_names.get(i))
             .map(|v| v.as_slice())
             .unwrap_or_else(|| std::slice::from_ref(i))
     }
@@ -461,3 +502,52 @@ impl<'a> HackyResolver<'a> {
         CanonicalType::new_owned_string(result.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_path(stmt: &syn::Stmt) -> &syn::Path {
+        match stmt {
+            syn::Stmt::Expr(syn::Expr::Call(call), _) => match call.func.as_ref() {
+                syn::Expr::Path(p) => &p.path,
+                _ => panic!("expected a path call"),
+            },
+            _ => panic!("expected a call"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_alias_and_self() {
+        let file: syn::File = syn::parse_str(
+            "use std::fs::File;
+            type Io = File;
+            struct S;
+            impl S {
+                fn f() {
+                    Self::g();
+                    Io::open();
+                }
+            }",
+        )
+        .unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let mut resolver = HackyResolver::new("test", filepath).unwrap();
+
+        let syn::Item::Use(u) = &file.items[0] else { panic!() };
+        let syn::Item::Type(t) = &file.items[1] else { panic!() };
+        let syn::Item::Impl(imp) = &file.items[3] else { panic!() };
+        let syn::ImplItem::Fn(f) = &imp.items[0] else { panic!() };
+        resolver.scan_use(u);
+        resolver.scan_type_alias(t);
+        resolver.push_impl(imp);
+
+        let self_call = resolver.resolve_path(call_path(&f.block.stmts[0]));
+        assert_eq!(self_call.as_str(), "S::g");
+        let alias_call = resolver.resolve_path(call_path(&f.block.stmts[1]));
+        assert_eq!(alias_call.as_str(), "std::fs::File::open");
+
+        resolver.pop_impl();
+        resolver.assert_top_level_invariant();
+    }
+}
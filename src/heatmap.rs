@@ -0,0 +1,232 @@
+//! Effect heatmaps: where in a crate its effects (and risk) are concentrated.
+//!
+//! The effects are grouped into a tree of directories and files (modules),
+//! with the number of effects, the risk score (the effects weighted by
+//! severity, as in `report`) and the share of the crate's risk at each node.
+//! Nodes are colored by the highest severity of the effects under them, e.g.
+//! ```text
+//! src/                 12 effects  risk 30  100%
+//!   sys/               10 effects  risk 27   90%
+//!     unix.rs           7 effects  risk 21   70%
+//! ```
+
+use crate::effect::{EffectInstance, Severity};
+
+use clap::ValueEnum;
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatmapFormat {
+    Terminal,
+    Html,
+    /// The effects per crate, module, function, and effect kind, as JSON for
+    /// d3 sunburst and treemap layouts (see `treemap`)
+    TreemapJson,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeatmapNode {
+    pub effects: usize,
+    pub risk: u64,
+    pub max_severity: Option<Severity>,
+    /// Subdirectories and files, by name
+    pub children: BTreeMap<String, HeatmapNode>,
+}
+
+impl HeatmapNode {
+    fn add(&mut self, severity: Severity) {
+        self.effects += 1;
+        self.risk += severity.weight();
+        self.max_severity = self.max_severity.max(Some(severity));
+    }
+
+    fn is_file(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Heatmap {
+    pub root: HeatmapNode,
+    /// Shown above the tree in HTML, e.g. the crate's capabilities
+    pub summary: Option<String>,
+    /// SVG image shown below the tree in HTML, e.g. a treemap of the effects
+    pub treemap: Option<String>,
+}
+
+impl Heatmap {
+    /// Build the heatmap from the file (relative to the crate) and severity
+    /// of each effect
+    pub fn from_entries<'a, I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a Path, Severity)>,
+    {
+        let mut root = HeatmapNode::default();
+        for (path, severity) in entries {
+            root.add(severity);
+            let mut node = &mut root;
+            for c in path.components() {
+                let Component::Normal(name) = c else {
+                    continue;
+                };
+                node =
+                    node.children.entry(name.to_string_lossy().to_string()).or_default();
+                node.add(severity);
+            }
+        }
+        Self { root, summary: None, treemap: None }
+    }
+
+    pub fn new(effects: &[EffectInstance], crate_path: &Path) -> Self {
+        let paths = effects
+            .iter()
+            .map(|e| {
+                let loc = e.call_loc();
+                let path = loc.dir().join(loc.file());
+                let path = match path.strip_prefix(crate_path) {
+                    Ok(p) => p.to_path_buf(),
+                    Err(_) => path,
+                };
+                (path, e.eff_type().severity())
+            })
+            .collect::<Vec<_>>();
+        Self::from_entries(paths.iter().map(|(p, s)| (p.as_path(), *s)))
+    }
+
+    fn percent(&self, node: &HeatmapNode) -> f64 {
+        if self.root.risk == 0 {
+            return 0.0;
+        }
+        100.0 * node.risk as f64 / self.root.risk as f64
+    }
+
+    /// The nodes in display order (depth first, riskiest first), with their
+    /// depth and display name
+    fn rows(&self) -> Vec<(usize, String, &HeatmapNode)> {
+        fn walk<'a>(
+            node: &'a HeatmapNode,
+            depth: usize,
+            rows: &mut Vec<(usize, String, &'a HeatmapNode)>,
+        ) {
+            let mut children = node.children.iter().collect::<Vec<_>>();
+            children.sort_by(|(n1, c1), (n2, c2)| c2.risk.cmp(&c1.risk).then(n1.cmp(n2)));
+            for (name, child) in children {
+                let name =
+                    if child.is_file() { name.clone() } else { format!("{}/", name) };
+                rows.push((depth, name, child));
+                walk(child, depth + 1, rows);
+            }
+        }
+        let mut rows = Vec::new();
+        walk(&self.root, 0, &mut rows);
+        rows
+    }
+
+    pub fn to_terminal(&self) -> String {
+        let rows = self.rows();
+        let width = rows.iter().map(|(d, name, _)| 2 * d + name.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for (depth, name, node) in rows {
+            let label = format!("{}{}", "  ".repeat(depth), name);
+            let line = format!(
+                "{:width$}  {:>4} effects  risk {:>4}  {:>3.0}%",
+                label,
+                node.effects,
+                node.risk,
+                self.percent(node),
+                width = width
+            );
+            let line = match node.max_severity {
+                Some(Severity::Critical) => line.red().bold(),
+                Some(Severity::High) => line.red(),
+                Some(Severity::Medium) => line.yellow(),
+                Some(Severity::Low) | None => line.green(),
+            };
+            writeln!(out, "{}", line).unwrap();
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>cargo-scan effect heatmap</title>\n<style>\n\
+            body { font-family: monospace; }\n\
+            ul { list-style: none; padding-left: 1.5em; }\n\
+            .Critical { background: #e05050; }\n\
+            .High { background: #f09090; }\n\
+            .Medium { background: #f5d76e; }\n\
+            .Low { background: #b5e0a0; }\n\
+            </style>\n</head>\n<body>\n",
+        );
+        if let Some(summary) = &self.summary {
+            writeln!(out, "<h2>{}</h2>", html_escape(summary)).unwrap();
+        }
+        writeln!(out, "<p>{} effects, risk {}</p>", self.root.effects, self.root.risk)
+            .unwrap();
+        let rows = self.rows();
+        let mut depth = 0;
+        out.push_str("<ul>\n");
+        for (d, name, node) in rows {
+            while depth < d {
+                out.push_str("<ul>\n");
+                depth += 1;
+            }
+            while depth > d {
+                out.push_str("</ul>\n");
+                depth -= 1;
+            }
+            let class = node.max_severity.map(|s| s.to_string()).unwrap_or_default();
+            writeln!(
+                out,
+                "<li><span class=\"{}\">{}</span> {} effects, risk {} ({:.0}%)</li>",
+                class,
+                html_escape(&name),
+                node.effects,
+                node.risk,
+                self.percent(node)
+            )
+            .unwrap();
+        }
+        for _ in 0..=depth {
+            out.push_str("</ul>\n");
+        }
+        if let Some(treemap) = &self.treemap {
+            out.push_str(treemap);
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heatmap_tree() {
+        let heatmap = Heatmap::from_entries([
+            (Path::new("src/sys/unix.rs"), Severity::High),
+            (Path::new("src/sys/unix.rs"), Severity::Critical),
+            (Path::new("src/lib.rs"), Severity::Low),
+        ]);
+        assert_eq!(heatmap.root.effects, 3);
+        let src = &heatmap.root.children["src"];
+        let sys = &src.children["sys"];
+        assert_eq!(sys.effects, 2);
+        assert_eq!(sys.max_severity, Some(Severity::Critical));
+        assert_eq!(src.children["lib.rs"].max_severity, Some(Severity::Low));
+
+        let rows = heatmap.rows();
+        let names = rows.iter().map(|(d, n, _)| (*d, n.as_str())).collect::<Vec<_>>();
+        assert_eq!(names, [(0, "src/"), (1, "sys/"), (2, "unix.rs"), (1, "lib.rs")]);
+    }
+}
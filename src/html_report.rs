@@ -0,0 +1,234 @@
+//! Static HTML reports of a crate's audit (`audit report`), for sharing audit
+//! results with people who don't use the command line.
+//!
+//! A report has the audit's badges, the effect counts per crate and per effect
+//! kind, a treemap of the base effects (see `treemap`), and each base effect
+//! with its audit status and source lines (as in the plain audit output, see
+//! `auditing::info`). Effects are numbered by
+//! location, with the same ids as `audit list`.
+
+use crate::audit_file::{AuditFile, EffectTree};
+use crate::auditing::info::{effect_excerpt, ExcerptLine};
+use crate::badge::{Badge, BadgeKind};
+use crate::effect::{EffectInstance, EffectType};
+use crate::heatmap::html_escape;
+use crate::report::count_leaves;
+use crate::treemap::Treemap;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Context lines shown around each effect, as in the audit by default
+const LINES_BEFORE: usize = 4;
+const LINES_AFTER: usize = 1;
+
+/// Audit status of an effect, or of a group of effects
+fn audit_status(tree: &EffectTree) -> &'static str {
+    match count_leaves(tree) {
+        (_, unsafe_) if unsafe_ > 0 => "unsafe",
+        (0, _) => "audited",
+        _ => "unaudited",
+    }
+}
+
+/// Numbers of effects, unaudited effects, and unsafe effects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Counts {
+    effects: usize,
+    unaudited: usize,
+    unsafe_: usize,
+}
+
+impl Counts {
+    fn add(&mut self, tree: &EffectTree) {
+        let (unaudited, unsafe_) = count_leaves(tree);
+        self.effects += 1;
+        self.unaudited += (unaudited > 0) as usize;
+        self.unsafe_ += (unsafe_ > 0) as usize;
+    }
+}
+
+pub struct HtmlReport<'a> {
+    crate_id: String,
+    audit_file: &'a AuditFile,
+    /// Whether the audit is up to date with the crate's source
+    up_to_date: bool,
+    /// Whether to include the source lines of the effects
+    snippets: bool,
+}
+
+impl<'a> HtmlReport<'a> {
+    pub fn new(crate_id: String, audit_file: &'a AuditFile, up_to_date: bool) -> Self {
+        Self { crate_id, audit_file, up_to_date, snippets: true }
+    }
+
+    pub fn without_snippets(mut self) -> Self {
+        self.snippets = false;
+        self
+    }
+
+    fn counts_by<F>(&self, key: F) -> BTreeMap<String, Counts>
+    where
+        F: Fn(&EffectInstance) -> String,
+    {
+        let mut counts: BTreeMap<String, Counts> = BTreeMap::new();
+        for (e, t) in &self.audit_file.audit_trees {
+            counts.entry(key(e)).or_default().add(t);
+        }
+        counts
+    }
+
+    fn write_counts(out: &mut String, heading: &str, counts: &BTreeMap<String, Counts>) {
+        writeln!(out, "<h2>{}</h2>", heading).unwrap();
+        writeln!(
+            out,
+            "<table>\n<tr><th></th><th>effects</th><th>unaudited</th><th>unsafe</th></tr>"
+        )
+        .unwrap();
+        for (name, c) in counts {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_escape(name),
+                c.effects,
+                c.unaudited,
+                c.unsafe_
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n");
+    }
+
+    fn write_excerpt(out: &mut String, lines: &[ExcerptLine]) {
+        out.push_str("<pre>");
+        for line in lines {
+            let text = html_escape(&line.text);
+            if line.in_effect {
+                writeln!(out, "<mark>{:>5} | {}</mark>", line.lineno, text).unwrap();
+            } else {
+                writeln!(out, "{:>5} | {}", line.lineno, text).unwrap();
+            }
+        }
+        out.push_str("</pre>\n");
+    }
+
+    fn write_effect(
+        &self,
+        out: &mut String,
+        id: usize,
+        e: &EffectInstance,
+        t: &EffectTree,
+    ) {
+        let status = audit_status(t);
+        writeln!(
+            out,
+            "<div class=\"effect\">\n<h3>{}. <code>{}</code> &rarr; <code>{}</code> \
+            {} <span class=\"{}\">{}</span></h3>",
+            id,
+            html_escape(e.caller_path()),
+            html_escape(e.callee_path()),
+            html_escape(&e.eff_type().simple_str()),
+            status,
+            status
+        )
+        .unwrap();
+        writeln!(out, "<p>{}</p>", html_escape(&e.call_loc().to_string())).unwrap();
+        if self.snippets {
+            match effect_excerpt(e.call_loc(), LINES_BEFORE, LINES_AFTER) {
+                Ok(lines) => Self::write_excerpt(out, &lines),
+                Err(_) => out.push_str("<p><em>Source not available</em></p>\n"),
+            }
+        }
+        out.push_str("</div>\n");
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        writeln!(
+            out,
+            "<title>cargo-scan audit report: {}</title>",
+            html_escape(&self.crate_id)
+        )
+        .unwrap();
+        out.push_str(
+            "<style>\n\
+            body { font-family: sans-serif; }\n\
+            td, th { padding: 0 1em; text-align: right; }\n\
+            td:first-child { text-align: left; }\n\
+            pre { background: #f6f6f6; padding: 0.5em; }\n\
+            mark { background: #f5d76e; }\n\
+            .audited { background: #b5e0a0; }\n\
+            .unaudited { background: #f5d76e; }\n\
+            .unsafe { background: #f09090; }\n\
+            </style>\n</head>\n<body>\n",
+        );
+        writeln!(out, "<h1>{}</h1>", html_escape(&self.crate_id)).unwrap();
+        out.push_str("<p>\n");
+        for kind in BadgeKind::ALL {
+            out.push_str(&Badge::from_audit(kind, self.audit_file).to_svg());
+        }
+        out.push_str("</p>\n");
+        if !self.up_to_date {
+            out.push_str(
+                "<p class=\"unaudited\">The crate has changed since it was audited</p>\n",
+            );
+        }
+
+        let by_crate = self.counts_by(|e| e.caller().crate_name().to_string());
+        Self::write_counts(&mut out, "Effects by crate", &by_crate);
+        let by_kind = self.counts_by(|e| EffectType::from(e.eff_type()).to_string());
+        Self::write_counts(&mut out, "Effects by kind", &by_kind);
+
+        out.push_str("<h2>Effect treemap</h2>\n");
+        let treemap = Treemap::new(self.audit_file.audit_trees.keys());
+        out.push_str(&treemap.to_svg(960.0, 600.0));
+
+        out.push_str("<h2>Effects</h2>\n");
+        for (id, (e, t)) in
+            self.audit_file.base_effects_by_location().into_iter().enumerate()
+        {
+            self.write_effect(&mut out, id, e, t);
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit_file::{EffectInfo, SafetyAnnotation};
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_html_report() {
+        let callee = CanonicalPath::new("libc::getuid");
+        let effect = EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("mycrate::f"),
+            callee.clone(),
+            &"getuid".parse::<proc_macro2::TokenStream>().unwrap(),
+            Effect::FFICall(callee),
+        );
+        let mut audit_file =
+            AuditFile::empty(PathBuf::from("mycrate"), Vec::new()).unwrap();
+        let tree = EffectTree::Leaf(
+            EffectInfo::from_instance(&effect),
+            SafetyAnnotation::Unsafe,
+        );
+        audit_file.audit_trees.insert(effect, tree);
+
+        let html = HtmlReport::new("mycrate".to_string(), &audit_file, false)
+            .without_snippets()
+            .to_html();
+        assert!(html.contains("<h1>mycrate</h1>"));
+        assert!(html.contains("<tr><td>mycrate</td><td>1</td><td>0</td><td>1</td></tr>"));
+        assert!(html.contains("<code>libc::getuid</code> [FFI] <span class=\"unsafe\">"));
+        assert!(html.contains("has changed since it was audited"));
+        assert!(html.contains("aria-label=\"unsafe: 1\""));
+        assert!(html.contains("<h2>Effect treemap</h2>\n<svg"));
+    }
+}
@@ -0,0 +1,239 @@
+//! A minimal HTTP/1.1 server, for the audit server (server.rs) and the
+//! metrics endpoint (metrics.rs).
+//!
+//! Connections are handled one at a time, and each carries a single request:
+//! the request line and headers are read (request bodies aren't supported),
+//! the handler's response is written, and the connection is closed. A client
+//! which stops sending times out rather than blocking the other connections.
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// How long to wait for a client to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request line or header accepted
+const MAX_LINE_LEN: u64 = 8 << 10;
+
+/// Most headers accepted in a request
+const MAX_HEADERS: usize = 100;
+
+pub struct Request {
+    pub method: String,
+    /// The segments of the path, e.g. `["effects", "3"]` for `/effects/3`
+    pub path: Vec<String>,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok<T: Serialize>(body: &T) -> Result<Self> {
+        let body = serde_json::to_string(body)?;
+        Ok(Self { status: 200, content_type: "application/json", body })
+    }
+
+    pub fn html(body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    pub fn svg(body: String) -> Self {
+        Self { status: 200, content_type: "image/svg+xml", body }
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: String) -> Self {
+        Self { status, content_type, body }
+    }
+
+    pub fn error(status: u16, msg: &str) -> Self {
+        let body = serde_json::json!({ "error": msg }).to_string();
+        Self { status, content_type: "application/json", body }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Serve the connections to the listener until the process is killed,
+/// answering each request with the handler
+pub fn serve(listener: TcpListener, mut handler: impl FnMut(&Request) -> Response) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => {
+                if let Err(e) = handle_connection(s, &mut handler) {
+                    warn!("Error handling request: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed connection: {}", e),
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    handler: &mut impl FnMut(&Request) -> Response,
+) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(&stream)? {
+        Some(req) => {
+            info!("{} /{}", req.method, req.path.join("/"));
+            handler(&req)
+        }
+        None => Response::error(400, "malformed request"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    Ok(())
+}
+
+/// Read the request line and headers of a request, or None if they are
+/// malformed or too long
+fn read_request(stream: &TcpStream) -> Result<Option<Request>> {
+    let mut reader = BufReader::new(stream.take(MAX_LINE_LEN * (MAX_HEADERS as u64 + 1)));
+    let mut read_line = |line: &mut String| -> Result<bool> {
+        let n = reader.by_ref().take(MAX_LINE_LEN).read_line(line)?;
+        Ok(n > 0 && line.ends_with('\n'))
+    };
+    let mut request_line = String::new();
+    if !read_line(&mut request_line)? {
+        return Ok(None);
+    }
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        if !read_line(&mut header)? || headers.len() > MAX_HEADERS {
+            return Ok(None);
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(parse_request(&request_line, headers))
+}
+
+pub fn parse_request(
+    request_line: &str,
+    headers: HashMap<String, String>,
+) -> Option<Request> {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.split('/').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect();
+    let query = query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_string(), percent_decode(v)))
+        .collect();
+    Some(Request { method, path, query, headers })
+}
+
+/// Decode a URL-encoded query value (e.g. `checked%20by+hand`)
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+
+    fn roundtrip(request: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        handle_connection(server, &mut |req: &Request| {
+            let body =
+                format!("{} {:?} {:?}", req.method, req.path, req.headers.get("host"));
+            Response::text(200, "text/plain", body)
+        })
+        .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_handle_connection() {
+        let response =
+            roundtrip(b"GET /effects/3?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(
+            response.ends_with("\r\n\r\nGET [\"effects\", \"3\"] Some(\"localhost\")")
+        );
+
+        // Requests which end early or have overlong headers are malformed
+        assert!(roundtrip(b"GET / HTTP/1.1\r\nHost: loc").starts_with("HTTP/1.1 400"));
+        let long = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "x".repeat(10_000));
+        assert!(roundtrip(long.as_bytes()).starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request(
+            "POST /effects/0/decision?status=safe&justification=checked%20by+hand HTTP/1.1",
+            HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, ["effects", "0", "decision"]);
+        assert_eq!(req.query["justification"], "checked by hand");
+        assert!(parse_request("", HashMap::new()).is_none());
+    }
+}
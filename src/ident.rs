@@ -3,7 +3,8 @@
 //! Ident: std, fs, File
 //! IdentPath: std, std::fs, fs::File, super::fs::File, std::fs::File
 //! CanonicalPath: crate::fs::File
-//! Pattern: std::fs, std::fs::*
+//! Pattern: std::fs, std::fs::*, std::*::read, tokio::net::Tcp*, tokio::**
+//! PatternSet: std::fs, !std::fs::remove_dir_all
 
 use log::warn;
 use serde::{Deserialize, Serialize};
@@ -36,7 +37,7 @@ fn test_replace_hyphens() {
 /// An Rust name identifier, without colons
 /// E.g.: env
 /// Should be a nonempty string
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Ident(String);
 impl Display for Ident {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -49,7 +50,7 @@ impl Ident {
         c.is_ascii_alphanumeric() || c == '_'
     }
 
-    fn str_ok(s: &str) -> bool {
+    pub(crate) fn str_ok(s: &str) -> bool {
         let skips = if s.starts_with("r#") { 2 } else { 0 };
         s.chars().skip(skips).all(Self::char_ok) && !s.is_empty()
     }
@@ -83,7 +84,7 @@ impl Ident {
 /// A Rust path identifier, with colons
 /// E.g.: std::env::var_os
 /// Semantically a (possibly empty) sequence of Idents
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct IdentPath(String);
 impl Display for IdentPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -153,13 +154,11 @@ impl IdentPath {
     }
 
     pub fn last_ident(&self) -> Option<Ident> {
-        let (_, i) = self.0.rsplit_once("::")?;
-        Some(Ident::new(i))
+        self.idents().last()
     }
 
     pub fn first_ident(&self) -> Option<Ident> {
-        let (i, _) = self.0.split_once("::")?;
-        Some(Ident::new(i))
+        self.idents().next()
     }
 
     pub fn append(&mut self, other: &Self) {
@@ -172,9 +171,9 @@ impl IdentPath {
         }
     }
 
-    /// Iterator over identifiers in the path
+    /// Iterator over identifiers in the path (none if it is empty)
     pub fn idents(&self) -> impl Iterator<Item = Ident> + '_ {
-        self.0.split("::").map(Ident::new)
+        self.0.split("::").filter(|_| !self.is_empty()).map(Ident::new)
     }
 
     /// O(n) length check
@@ -200,8 +199,20 @@ impl IdentPath {
         results.drain(..).fresh_iter()
     }
 
+    /// Whether the pattern's segments match a prefix of the path's idents,
+    /// e.g. `std::fs::read` matches `std::fs` and `std::*::read` but
+    /// `std::fsx` doesn't match `std::fs` (see `Pattern`)
     pub fn matches(&self, pattern: &Pattern) -> bool {
-        self.0.starts_with(pattern.as_str())
+        if !pattern.is_glob() {
+            let pattern = pattern.as_str();
+            return self.0.strip_prefix(pattern).map_or(false, |rest| {
+                pattern.is_empty() || rest.is_empty() || rest.starts_with("::")
+            });
+        }
+        let mut idents = self.0.split("::").filter(|_| !self.is_empty());
+        pattern.segments().all(|p| {
+            idents.next().map_or(false, |i| glob_matches(p.as_bytes(), i.as_bytes()))
+        })
     }
 
     pub fn as_str(&self) -> &str {
@@ -218,7 +229,7 @@ impl Default for IdentPath {
 /// Type representing a *canonical* path of Rust idents.
 /// i.e. from the root
 /// Should not be empty.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct CanonicalPath {
     ident_path: IdentPath,
     src_loc: SrcLoc,
@@ -272,7 +283,13 @@ impl CanonicalPath {
     }
 
     pub fn crate_name(&self) -> Ident {
-        self.ident_path.idents().next().unwrap()
+        Ident::new(self.as_str().split("::").next().unwrap_or_default())
+    }
+
+    /// The last segment, e.g. the name of a function without its module.
+    /// Paths which break the invariant may have none, and are shown whole.
+    pub fn fn_name(&self) -> String {
+        self.ident_path.last_ident().map_or_else(|| self.to_string(), |i| i.to_string())
     }
 
     pub fn to_path(self) -> IdentPath {
@@ -424,12 +441,38 @@ impl CanonicalType {
     }
 }
 
+/// How the sets of paths denoted by two patterns are related
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternRelation {
+    Equal,
+    Subset,
+    Superset,
+    /// Neither set contains the other
+    Incomparable,
+}
+
+/// Whether a segment matches a glob segment, where `*` stands for any
+/// sequence of characters. A `*` in the segment is only matched by a `*` of
+/// the glob, so this is also whether the glob segment matches every segment
+/// the other glob segment matches.
+fn glob_matches(glob: &[u8], s: &[u8]) -> bool {
+    match glob.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => (0..=s.len()).any(|i| glob_matches(rest, &s[i..])),
+        Some((c, rest)) => s.first() == Some(c) && glob_matches(rest, &s[1..]),
+    }
+}
+
 /// Type representing a pattern over paths
 ///
-/// Currently supported: only patterns of the form
-/// <path>::* (includes <path> itself)
-/// The ::* is left implicit and should not be provided
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A pattern matches the paths whose leading idents match its segments, so
+/// `<path>` denotes `<path>` and everything under it (the `::*` of
+/// `<path>::*` is left implicit and should not be provided). Segments can
+/// be globs, where `*` stands for any sequence of characters: `std::*::read`
+/// matches `std::fs::read` and `std::io::read`, and `tokio::net::Tcp*`
+/// matches `tokio::net::TcpStream`. A terminal `::**` spells out the implicit
+/// deep match, so `tokio::**` is the same pattern as `tokio`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Pattern(IdentPath);
 impl Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -437,8 +480,15 @@ impl Display for Pattern {
     }
 }
 impl Pattern {
+    /// Segments are idents or globs of ident characters and `*`
     pub fn invariant(&self) -> bool {
-        self.0.invariant()
+        let glob_ok = |seg: &str| {
+            !seg.is_empty()
+                && !seg.contains("**")
+                && seg.chars().all(|c| c == '*' || Ident::char_ok(c))
+        };
+        self.0.is_empty()
+            || self.0.as_str().split("::").all(|seg| Ident::str_ok(seg) || glob_ok(seg))
     }
 
     pub fn check_invariant(&self) {
@@ -448,19 +498,39 @@ impl Pattern {
     }
 
     pub fn new(s: &str) -> Self {
-        Self::from_path(IdentPath::new(s))
+        Self::new_owned(s.to_string())
     }
 
-    pub fn new_owned(s: String) -> Self {
-        Self::from_path(IdentPath::new_owned(s))
+    pub fn new_owned(mut s: String) -> Self {
+        replace_hyphens(&mut s);
+        // The deep match is implicit
+        if s == "**" {
+            s.clear();
+        } else if s.ends_with("::**") {
+            s.truncate(s.len() - "::**".len());
+        }
+        let result = Self(IdentPath(s));
+        result.check_invariant();
+        result
+    }
+
+    /// Whether any segment is a glob
+    pub fn is_glob(&self) -> bool {
+        self.as_str().contains('*')
+    }
+
+    /// Iterator over the segments of the pattern (none if it is empty)
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.as_str().split("::").filter(|_| !self.0.is_empty())
     }
 
     pub fn from_ident(i: Ident) -> Self {
         Self::from_path(IdentPath::from_ident(i))
     }
 
+    /// The first segment, unless it is a glob
     pub fn first_ident(&self) -> Option<Ident> {
-        self.0.first_ident()
+        self.segments().next().filter(|seg| !seg.contains('*')).map(Ident::new)
     }
 
     pub fn from_path(p: IdentPath) -> Self {
@@ -474,7 +544,9 @@ impl Pattern {
     }
 
     /// Return true if the set of paths denoted by self is
-    /// a subset of those denoted by other
+    /// a subset of those denoted by other: other has at most as many
+    /// segments, and each matches all that self's segment matches, which
+    /// is whether other matches self as a path with `*` taken literally
     pub fn subset(&self, other: &Self) -> bool {
         self.0.matches(other)
     }
@@ -484,6 +556,114 @@ impl Pattern {
     pub fn superset(&self, other: &Self) -> bool {
         other.subset(self)
     }
+
+    /// Compare the sets of paths denoted by self and other.
+    /// Patterns are only partially ordered, so the result may be
+    /// `Incomparable`; new pattern forms should extend `subset`
+    /// so that this stays a partial order.
+    pub fn relation(&self, other: &Self) -> PatternRelation {
+        match (self.subset(other), other.subset(self)) {
+            (true, true) => PatternRelation::Equal,
+            (true, false) => PatternRelation::Subset,
+            (false, true) => PatternRelation::Superset,
+            (false, false) => PatternRelation::Incomparable,
+        }
+    }
+}
+
+/// Type representing a set of paths given by patterns to include and
+/// patterns to exclude from them, written with a leading `!`: `std::fs` and
+/// `!std::fs::remove_dir_all` denote the paths under `std::fs` other than
+/// `std::fs::remove_dir_all` (and the paths under it). A path is in the set
+/// if it matches an included pattern and no excluded one, whatever the order
+/// of the patterns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternSet {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+impl PatternSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pattern of a negated pattern string, e.g. `std::fs` for
+    /// `!std::fs`, or None if it isn't negated
+    pub fn negated(s: &str) -> Option<&str> {
+        s.strip_prefix('!')
+    }
+
+    pub fn from_strs<'a>(pats: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut result = Self::new();
+        for s in pats {
+            result.add(s);
+        }
+        result
+    }
+
+    /// Add an included pattern, or an excluded one if it starts with `!`
+    pub fn add(&mut self, s: &str) {
+        match Self::negated(s) {
+            Some(s) => Self::push(&mut self.exclude, Pattern::new(s)),
+            None => Self::push(&mut self.include, Pattern::new(s)),
+        }
+    }
+
+    fn push(pats: &mut Vec<Pattern>, pat: Pattern) {
+        if !pats.contains(&pat) {
+            pats.push(pat);
+        }
+    }
+
+    /// Add the paths as included patterns
+    pub fn include_paths(&mut self, paths: impl IntoIterator<Item = IdentPath>) {
+        for p in paths {
+            Self::push(&mut self.include, Pattern::from_path(p));
+        }
+    }
+
+    /// Add the included and excluded patterns of another set
+    pub fn extend(&mut self, other: &Self) {
+        for pat in &other.include {
+            Self::push(&mut self.include, pat.clone());
+        }
+        for pat in &other.exclude {
+            Self::push(&mut self.exclude, pat.clone());
+        }
+    }
+
+    pub fn includes(&self) -> &[Pattern] {
+        &self.include
+    }
+
+    pub fn excludes(&self) -> &[Pattern] {
+        &self.exclude
+    }
+
+    pub fn is_excluded(&self, path: &IdentPath) -> bool {
+        self.exclude.iter().any(|p| path.matches(p))
+    }
+
+    /// The included patterns the path matches, or none if it is excluded
+    pub fn matching(&self, path: &IdentPath) -> Vec<&Pattern> {
+        if self.is_excluded(path) {
+            return vec![];
+        }
+        self.include.iter().filter(|p| path.matches(p)).collect()
+    }
+
+    pub fn matches(&self, path: &IdentPath) -> bool {
+        !self.matching(path).is_empty()
+    }
+
+    /// The patterns as strings, sorted, with the excluded ones written
+    /// `!<pattern>`
+    pub fn to_strs(&self) -> Vec<String> {
+        let mut strs = self.include.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        strs.extend(self.exclude.iter().map(|p| format!("!{}", p)));
+        strs.sort();
+        strs
+    }
 }
 
 #[cfg(test)]
@@ -510,6 +690,76 @@ mod tests {
         assert!(p.matches(&pat2));
         assert!(!p.matches(&pat3));
         assert!(!p.matches(&pat4));
+        assert!(!IdentPath::new("std::fsx").matches(&pat2));
+        assert_eq!(IdentPath::new("std").last_ident(), Some(Ident::new("std")));
+    }
+
+    #[test]
+    fn test_fn_name() {
+        assert_eq!(CanonicalPath::new("my_crate::m::f").fn_name(), "f");
+        // Paths without any segments break the invariant, but still get a name
+        assert_eq!(CanonicalPath::new("").fn_name(), "");
+    }
+
+    #[test]
+    fn test_glob_patterns() {
+        let read = Pattern::new("std::*::read");
+        assert!(read.is_glob());
+        assert!(read.invariant());
+        assert!(IdentPath::new("std::fs::read").matches(&read));
+        assert!(IdentPath::new("std::io::read::inner").matches(&read));
+        assert!(!IdentPath::new("std::read").matches(&read));
+        assert!(!IdentPath::new("std::fs::read_to_string").matches(&read));
+
+        let tcp = Pattern::new("tokio::net::Tcp*");
+        assert!(IdentPath::new("tokio::net::TcpStream::connect").matches(&tcp));
+        assert!(IdentPath::new("tokio::net::Tcp").matches(&tcp));
+        assert!(!IdentPath::new("tokio::net::UdpSocket").matches(&tcp));
+        assert_eq!(tcp.first_ident(), Some(Ident::new("tokio")));
+        assert_eq!(Pattern::new("*::fs").first_ident(), None);
+
+        // A terminal ** is the implicit deep match
+        assert_eq!(Pattern::new("tokio::**"), Pattern::new("tokio"));
+        assert_eq!(Pattern::new("**"), Pattern::new(""));
+        assert!(!Pattern::new("tokio::**::net").invariant());
+
+        assert!(Pattern::new("std::fs::read").subset(&read));
+        assert!(read.subset(&Pattern::new("std::*")));
+        assert!(Pattern::new("std::*").subset(&Pattern::new("std")));
+        assert!(!Pattern::new("std").subset(&Pattern::new("std::*")));
+        assert!(tcp.subset(&Pattern::new("tokio::*::T*")));
+        assert!(!Pattern::new("tokio::*::T*").subset(&tcp));
+        assert_eq!(
+            Pattern::new("tokio::net::TcpStream").relation(&tcp),
+            PatternRelation::Subset
+        );
+        assert_eq!(
+            Pattern::new("a*").relation(&Pattern::new("*b")),
+            PatternRelation::Incomparable
+        );
+    }
+
+    #[test]
+    fn test_pattern_set() {
+        let set = PatternSet::from_strs(["!std::fs::remove_dir_all", "std::fs", "libc"]);
+        assert_eq!(set.includes().len(), 2);
+        assert_eq!(set.excludes(), [Pattern::new("std::fs::remove_dir_all")]);
+        assert!(set.matches(&IdentPath::new("std::fs::read")));
+        assert!(set.matches(&IdentPath::new("libc::getuid")));
+        assert!(!set.matches(&IdentPath::new("std::fs::remove_dir_all")));
+        assert!(!set.matches(&IdentPath::new("std::fs::remove_dir_all::inner")));
+        assert!(!set.matches(&IdentPath::new("std::net::TcpStream")));
+        assert_eq!(set.matching(&IdentPath::new("std::fs")), [&Pattern::new("std::fs")]);
+
+        // Exclusions can be globs, and only exclude
+        let set = PatternSet::from_strs(["tokio::net", "!tokio::net::Udp*", "!std"]);
+        assert!(set.matches(&IdentPath::new("tokio::net::TcpStream")));
+        assert!(!set.matches(&IdentPath::new("tokio::net::UdpSocket")));
+        assert!(!set.matches(&IdentPath::new("std::fs")));
+        assert_eq!(PatternSet::negated("!std"), Some("std"));
+        let set = PatternSet::from_strs(["!tokio-util", "tokio-util", "!tokio-util"]);
+        assert_eq!(set.excludes(), [Pattern::new("tokio_util")]);
+        assert_eq!(set.to_strs(), ["!tokio_util", "tokio_util"]);
     }
 
     #[test]
@@ -535,4 +785,74 @@ mod tests {
         assert!(!pat2.subset(&pat4));
         assert!(!pat4.subset(&pat2));
     }
+
+    /// All paths of up to `depth` segments over a few overlapping idents
+    fn small_paths(depth: usize) -> Vec<String> {
+        let idents = ["a", "b", "ab"];
+        let mut paths: Vec<String> = idents.iter().map(|i| i.to_string()).collect();
+        let mut last = paths.clone();
+        for _ in 1..depth {
+            last = last
+                .iter()
+                .flat_map(|p| idents.iter().map(move |i| format!("{}::{}", p, i)))
+                .collect();
+            paths.extend(last.iter().cloned());
+        }
+        paths
+    }
+
+    #[test]
+    fn test_pattern_lattice() {
+        let paths = small_paths(3);
+        let pats: Vec<Pattern> = paths.iter().map(|p| Pattern::new(p)).collect();
+        let paths: Vec<IdentPath> = paths.iter().map(|p| IdentPath::new(p)).collect();
+
+        for p in &pats {
+            assert_eq!(p.relation(p), PatternRelation::Equal);
+        }
+        for p in &pats {
+            for q in &pats {
+                // subset agrees with the paths each pattern matches
+                let contained =
+                    paths.iter().all(|path| !path.matches(p) || path.matches(q));
+                assert_eq!(p.subset(q), contained, "{} <= {}", p, q);
+                assert_eq!(p.subset(q), q.superset(p));
+
+                let expected = match p.relation(q) {
+                    PatternRelation::Equal => PatternRelation::Equal,
+                    PatternRelation::Subset => PatternRelation::Superset,
+                    PatternRelation::Superset => PatternRelation::Subset,
+                    PatternRelation::Incomparable => PatternRelation::Incomparable,
+                };
+                assert_eq!(q.relation(p), expected);
+                if p.relation(q) == PatternRelation::Equal {
+                    assert_eq!(p, q);
+                }
+
+                for r in &pats {
+                    if p.subset(q) && q.subset(r) {
+                        assert!(p.subset(r), "{} <= {} <= {}", p, q, r);
+                    }
+                }
+            }
+        }
+
+        // Glob patterns contain at most the paths they are subsets of
+        let globs = ["*", "a*", "*b", "a::*", "*::b", "a*::*::b", "a::b*::**"];
+        let globs: Vec<Pattern> = globs.iter().map(|g| Pattern::new(g)).collect();
+        for p in globs.iter().chain(&pats) {
+            for q in globs.iter().chain(&pats) {
+                if p.subset(q) {
+                    assert!(paths.iter().all(|path| !path.matches(p) || path.matches(q)));
+                }
+            }
+        }
+
+        let std = Pattern::new("std");
+        assert_eq!(std.relation(&Pattern::new("std::fs")), PatternRelation::Superset);
+        assert_eq!(
+            Pattern::new("std::fs").relation(&Pattern::new("std::os")),
+            PatternRelation::Incomparable
+        );
+    }
 }
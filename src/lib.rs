@@ -1,18 +1,72 @@
+// The rustc resolver backend links against the compiler's own crates
+#![cfg_attr(feature = "rustc-resolver", feature(rustc_private))]
+
+pub mod artifact;
+pub mod attestation;
 pub mod audit_chain;
+pub mod audit_diff;
 pub mod audit_file;
+pub mod audit_query;
 pub mod auditing;
+pub mod badge;
+pub mod capability;
+pub mod compare;
+pub mod db;
+pub mod dep_cache;
+pub mod dep_tree;
+pub mod doc_tests;
 pub mod download_crate;
+pub mod edition;
 pub mod effect;
+pub mod effect_identity;
+pub mod effect_index;
+pub mod features;
+pub mod fuzzing;
+pub mod heatmap;
+pub mod html_report;
+pub mod http;
 pub mod ident;
 pub mod loc_tracker;
+pub mod macro_expand;
+pub mod malware;
+pub mod memory;
+pub mod metrics;
+pub mod module_map;
+pub mod name_check;
+pub mod osv;
+pub mod output;
+pub mod policy;
+pub mod project;
+pub mod quick_scan;
+pub mod ratchet;
+pub mod remediation;
+pub mod report;
+pub mod sarif;
+pub mod sbom;
+pub mod scan_ignore;
+pub mod scan_manifest;
 pub mod scanner;
+pub mod server;
 pub mod sink;
+pub mod soundness;
+pub mod summary;
+pub mod taxonomy;
+pub mod template;
+pub mod trait_summary;
+pub mod treemap;
 pub mod util;
+pub mod workspace;
 
 // Name resolution
 pub mod hacky_resolver;
 pub mod name_resolution;
 pub mod resolve;
+#[cfg(feature = "rustc-resolver")]
+pub mod rustc_resolution;
 
 // Attribute parser
 mod attr_parser;
+
+// Memory is accounted per crate for the scan budgets
+#[global_allocator]
+static GLOBAL: memory::CountingAlloc = memory::CountingAlloc;
@@ -39,6 +39,13 @@ impl LoCTracker {
         }
     }
 
+    /// Add the spans counted by another tracker
+    pub fn absorb(&mut self, other: &Self) {
+        self.instances += other.instances;
+        self.lines += other.lines;
+        self.zero_size_lines += other.zero_size_lines;
+    }
+
     /// Return true if no spans were added
     pub fn is_empty(&self) -> bool {
         self.instances == 0
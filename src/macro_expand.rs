@@ -0,0 +1,652 @@
+//! Expansion of the `macro_rules!` macros defined in the scanned crate.
+//!
+//! Declarative macros are statically expandable, so calls to the crate's own
+//! macros are expanded and the expansion is scanned in place of the call,
+//! instead of the call being skipped. In the expansion:
+//! - tokens passed to the macro keep their spans, so effects in them are at
+//!   their precise location in the call
+//! - tokens from the macro definition get the span of the macro name at the
+//!   call site, and effects in them link to the definition (`MacroOrigin`)
+//!
+//! Rules are tried in order and the first one which matches is used. Matching
+//! doesn't backtrack into a repetition, which is enough for the macros rustc
+//! accepts without ambiguity errors.
+
+use crate::effect::{MacroOrigin, SrcLoc};
+
+use log::debug;
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path as FilePath, PathBuf as FilePathBuf};
+use std::rc::Rc;
+use syn::ext::IdentExt;
+use syn::parse::discouraged::Speculative;
+use syn::parse::{ParseStream, Parser};
+use typed_arena::Arena;
+
+/// A part of a macro_rules matcher
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// A token which must appear as is
+    Token(TokenTree),
+    Group(Delimiter, Vec<Matcher>),
+    /// `$name:kind`
+    Fragment(String, String),
+    /// `$( .. ) sep op`, where op is `*`, `+`, or `?`
+    Repeat(Vec<Matcher>, Option<TokenTree>, char),
+}
+
+#[derive(Debug, Clone)]
+struct MacroRule {
+    matcher: Vec<Matcher>,
+    transcriber: TokenStream,
+}
+
+/// A `macro_rules!` definition. Tokens can't be shared between threads, so
+/// the rules are kept as source, which each expander parses again.
+#[derive(Debug, Clone)]
+pub struct MacroDef {
+    pub name: String,
+    pub loc: SrcLoc,
+    rules: String,
+}
+
+impl MacroDef {
+    pub fn origin(&self) -> MacroOrigin {
+        MacroOrigin { name: self.name.clone(), def_loc: self.loc.clone() }
+    }
+}
+
+fn is_punct(t: Option<&TokenTree>, c: char) -> bool {
+    matches!(t, Some(TokenTree::Punct(p)) if p.as_char() == c)
+}
+
+fn parse_matcher(tokens: TokenStream) -> Option<Vec<Matcher>> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut matchers = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (TokenTree::Punct(p), Some(TokenTree::Ident(name))) if p.as_char() == '$' => {
+                if !is_punct(tokens.get(i + 2), ':') {
+                    return None;
+                }
+                let Some(TokenTree::Ident(kind)) = tokens.get(i + 3) else {
+                    return None;
+                };
+                matchers.push(Matcher::Fragment(name.to_string(), kind.to_string()));
+                i += 4;
+            }
+            (TokenTree::Punct(p), Some(TokenTree::Group(g))) if p.as_char() == '$' => {
+                let inner = parse_matcher(g.stream())?;
+                let (sep, op) = match (tokens.get(i + 2), tokens.get(i + 3)) {
+                    (Some(TokenTree::Punct(op)), _) if "*+?".contains(op.as_char()) => {
+                        i += 3;
+                        (None, op.as_char())
+                    }
+                    (Some(sep), Some(TokenTree::Punct(op)))
+                        if "*+?".contains(op.as_char()) =>
+                    {
+                        i += 4;
+                        (Some(sep.clone()), op.as_char())
+                    }
+                    _ => return None,
+                };
+                matchers.push(Matcher::Repeat(inner, sep, op));
+            }
+            (TokenTree::Group(g), _) => {
+                matchers.push(Matcher::Group(g.delimiter(), parse_matcher(g.stream())?));
+                i += 1;
+            }
+            (t, _) => {
+                matchers.push(Matcher::Token(t.clone()));
+                i += 1;
+            }
+        }
+    }
+    Some(matchers)
+}
+
+/// The rules of a macro_rules body: `(matcher) => { transcriber };` ...
+fn parse_rules(body: TokenStream) -> Option<Vec<MacroRule>> {
+    let tokens = body.into_iter().collect::<Vec<_>>();
+    let mut rules = Vec::new();
+    for rule in tokens.split(|t| is_punct(Some(t), ';')) {
+        match rule {
+            [] => (),
+            [TokenTree::Group(m), eq, gt, TokenTree::Group(t)]
+                if is_punct(Some(eq), '=') && is_punct(Some(gt), '>') =>
+            {
+                let matcher = parse_matcher(m.stream())?;
+                rules.push(MacroRule { matcher, transcriber: t.stream() });
+            }
+            _ => return None,
+        }
+    }
+    Some(rules)
+}
+
+/// The macro_rules definitions in the items, in order
+fn item_macros(filepath: &FilePath, items: &[syn::Item], defs: &mut Vec<MacroDef>) {
+    for item in items {
+        match item {
+            syn::Item::Macro(m) => defs.extend(macro_def(filepath, m)),
+            syn::Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    item_macros(filepath, items, defs);
+                }
+            }
+            syn::Item::Fn(f) => {
+                let items = f.block.stmts.iter().filter_map(|s| match s {
+                    syn::Stmt::Item(i) => Some(i.clone()),
+                    _ => None,
+                });
+                item_macros(filepath, &items.collect::<Vec<_>>(), defs);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn macro_def(filepath: &FilePath, m: &syn::ItemMacro) -> Option<MacroDef> {
+    let ident = m.ident.as_ref()?;
+    if !m.mac.path.is_ident("macro_rules") {
+        return None;
+    }
+    let name = ident.to_string();
+    if parse_rules(m.mac.tokens.clone()).is_none() {
+        debug!("Unsupported macro_rules syntax in {}", name);
+        return None;
+    }
+    let loc = SrcLoc::from_span(filepath, m);
+    Some(MacroDef { name, loc, rules: m.mac.tokens.to_string() })
+}
+
+/// The `macro_rules!` macros defined in a crate, by name
+#[derive(Debug, Default)]
+pub struct MacroDefs(HashMap<String, MacroDef>);
+
+impl MacroDefs {
+    /// Collect the macros defined in the files, parsing them in parallel.
+    /// Files which can't be parsed are skipped; they are reported when they
+    /// are scanned.
+    pub fn from_files(files: &[FilePathBuf]) -> Self {
+        let file_defs = files
+            .par_iter()
+            .map(|file| {
+                let mut defs = Vec::new();
+                let Ok(src) = crate::util::fs::read_source(file) else {
+                    return defs;
+                };
+                if let Ok(syntax_tree) = syn::parse_file(&src.text) {
+                    item_macros(file, &syntax_tree.items, &mut defs);
+                }
+                defs
+            })
+            .collect::<Vec<_>>();
+        let mut defs = Self::default();
+        for def in file_defs.into_iter().flatten() {
+            defs.add(def);
+        }
+        defs
+    }
+
+    /// Add a definition, unless a macro with the same name is already
+    /// defined: the first definition wins, as macros with the same name in
+    /// different modules aren't told apart
+    fn add(&mut self, def: MacroDef) {
+        self.0.entry(def.name.clone()).or_insert(def);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MacroDef> {
+        self.0.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// What each macro variable matched
+#[derive(Debug, Clone)]
+enum Binding {
+    Fragment(TokenStream),
+    /// One binding per iteration of the repetition the variable is in
+    Repeated(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+fn same_token(a: &TokenTree, b: &TokenTree) -> bool {
+    match (a, b) {
+        (TokenTree::Ident(a), TokenTree::Ident(b)) => a == b,
+        (TokenTree::Punct(a), TokenTree::Punct(b)) => a.as_char() == b.as_char(),
+        (TokenTree::Literal(a), TokenTree::Literal(b)) => a.to_string() == b.to_string(),
+        _ => false,
+    }
+}
+
+fn match_token(input: ParseStream, expected: &TokenTree) -> syn::Result<()> {
+    input.step(|cursor| match cursor.token_tree() {
+        Some((t, rest)) if same_token(&t, expected) => Ok(((), rest)),
+        _ => Err(cursor.error(format!("expected `{}`", expected))),
+    })
+}
+
+/// Parse a fragment of the kind, returning its tokens
+fn parse_fragment(input: ParseStream, kind: &str) -> syn::Result<TokenStream> {
+    let tokens = match kind {
+        // Keep the precedence of the expression wherever it is substituted
+        "expr" => {
+            let e = input.parse::<syn::Expr>()?;
+            let mut group = Group::new(Delimiter::None, e.to_token_stream());
+            group.set_span(syn::spanned::Spanned::span(&e));
+            TokenTree::Group(group).into()
+        }
+        "ident" => input.call(syn::Ident::parse_any)?.into_token_stream(),
+        "ty" => input.parse::<syn::Type>()?.into_token_stream(),
+        "path" => input.parse::<syn::Path>()?.into_token_stream(),
+        "pat" => syn::Pat::parse_multi_with_leading_vert(input)?.into_token_stream(),
+        "pat_param" => syn::Pat::parse_single(input)?.into_token_stream(),
+        "block" => input.parse::<syn::Block>()?.into_token_stream(),
+        "stmt" => input.parse::<syn::Stmt>()?.into_token_stream(),
+        "item" => input.parse::<syn::Item>()?.into_token_stream(),
+        "vis" => input.parse::<syn::Visibility>()?.into_token_stream(),
+        "lifetime" => input.parse::<syn::Lifetime>()?.into_token_stream(),
+        "meta" => input.parse::<syn::Meta>()?.into_token_stream(),
+        "literal" => {
+            let minus = input.parse::<Option<syn::Token![-]>>()?;
+            let lit = input.parse::<syn::Lit>()?;
+            quote::quote!(#minus #lit)
+        }
+        "tt" => input.parse::<TokenTree>()?.into(),
+        _ => return Err(input.error(format!("unsupported fragment kind: {}", kind))),
+    };
+    Ok(tokens)
+}
+
+fn fragment_names(matchers: &[Matcher], names: &mut Vec<String>) {
+    for m in matchers {
+        match m {
+            Matcher::Fragment(name, _) => names.push(name.clone()),
+            Matcher::Group(_, inner) | Matcher::Repeat(inner, _, _) => {
+                fragment_names(inner, names)
+            }
+            Matcher::Token(_) => (),
+        }
+    }
+}
+
+fn match_group(
+    input: ParseStream,
+    delimiter: Delimiter,
+    matchers: &[Matcher],
+    bindings: &mut Bindings,
+) -> syn::Result<()> {
+    let content;
+    match delimiter {
+        Delimiter::Parenthesis => {
+            syn::parenthesized!(content in input);
+        }
+        Delimiter::Brace => {
+            syn::braced!(content in input);
+        }
+        Delimiter::Bracket => {
+            syn::bracketed!(content in input);
+        }
+        Delimiter::None => return Err(input.error("unsupported invisible group")),
+    }
+    match_seq(&content, matchers, bindings)?;
+    if !content.is_empty() {
+        return Err(content.error("unexpected tokens in group"));
+    }
+    Ok(())
+}
+
+fn match_repeat(
+    input: ParseStream,
+    matchers: &[Matcher],
+    sep: Option<&TokenTree>,
+    op: char,
+    bindings: &mut Bindings,
+) -> syn::Result<()> {
+    let mut iterations = Vec::new();
+    while !input.is_empty() {
+        let fork = input.fork();
+        if let (Some(sep), false) = (sep, iterations.is_empty()) {
+            if match_token(&fork, sep).is_err() {
+                break;
+            }
+        }
+        let mut iteration = Bindings::new();
+        if match_seq(&fork, matchers, &mut iteration).is_err() {
+            break;
+        }
+        // Stop at repetitions which can match nothing
+        let progress = fork.cursor() != input.cursor();
+        input.advance_to(&fork);
+        iterations.push(iteration);
+        if op == '?' || !progress {
+            break;
+        }
+    }
+    if op == '+' && iterations.is_empty() {
+        return Err(input.error("expected at least one repetition"));
+    }
+    let mut names = Vec::new();
+    fragment_names(matchers, &mut names);
+    for name in names {
+        let values = iterations
+            .iter_mut()
+            .map(|b| b.remove(&name).unwrap_or(Binding::Repeated(Vec::new())))
+            .collect();
+        bindings.insert(name, Binding::Repeated(values));
+    }
+    Ok(())
+}
+
+fn match_seq(
+    input: ParseStream,
+    matchers: &[Matcher],
+    bindings: &mut Bindings,
+) -> syn::Result<()> {
+    for m in matchers {
+        match m {
+            Matcher::Token(t) => match_token(input, t)?,
+            Matcher::Group(delimiter, inner) => {
+                match_group(input, *delimiter, inner, bindings)?
+            }
+            Matcher::Fragment(name, kind) => {
+                let tokens = parse_fragment(input, kind)?;
+                bindings.insert(name.clone(), Binding::Fragment(tokens));
+            }
+            Matcher::Repeat(inner, sep, op) => {
+                match_repeat(input, inner, sep.as_ref(), *op, bindings)?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn with_span(t: &TokenTree, span: Span) -> TokenTree {
+    let mut t = t.clone();
+    t.set_span(span);
+    t
+}
+
+/// The variables used in the template
+fn template_vars(tokens: &TokenStream, vars: &mut Vec<String>) {
+    let tokens = tokens.clone().into_iter().collect::<Vec<_>>();
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            TokenTree::Ident(name) if i > 0 && is_punct(tokens.get(i - 1), '$') => {
+                vars.push(name.to_string())
+            }
+            TokenTree::Group(g) => template_vars(&g.stream(), vars),
+            _ => (),
+        }
+    }
+}
+
+/// Substitute the bindings into the template, giving the template's own
+/// tokens the span
+fn transcribe(
+    template: &TokenStream,
+    bindings: &Bindings,
+    span: Span,
+) -> Option<TokenStream> {
+    let tokens = template.clone().into_iter().collect::<Vec<_>>();
+    let mut res = TokenStream::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (TokenTree::Punct(p), Some(TokenTree::Ident(name))) if p.as_char() == '$' => {
+                match bindings.get(&name.to_string()) {
+                    Some(Binding::Fragment(tokens)) => res.extend(tokens.clone()),
+                    Some(Binding::Repeated(_)) => return None,
+                    None if name == "crate" => {
+                        res.extend([TokenTree::Ident(Ident::new("crate", span))])
+                    }
+                    None => res.extend([
+                        with_span(&tokens[i], span),
+                        with_span(&tokens[i + 1], span),
+                    ]),
+                }
+                i += 2;
+            }
+            (TokenTree::Punct(p), Some(TokenTree::Group(g))) if p.as_char() == '$' => {
+                let (sep, skip) = match (tokens.get(i + 2), tokens.get(i + 3)) {
+                    (Some(TokenTree::Punct(op)), _) if "*+?".contains(op.as_char()) => {
+                        (None, 3)
+                    }
+                    (Some(sep), Some(TokenTree::Punct(op)))
+                        if "*+?".contains(op.as_char()) =>
+                    {
+                        (Some(sep), 4)
+                    }
+                    _ => return None,
+                };
+                res.extend(transcribe_repeat(&g.stream(), sep, bindings, span)?);
+                i += skip;
+            }
+            (TokenTree::Group(g), _) => {
+                let mut group =
+                    Group::new(g.delimiter(), transcribe(&g.stream(), bindings, span)?);
+                group.set_span(span);
+                res.extend([TokenTree::Group(group)]);
+                i += 1;
+            }
+            (t, _) => {
+                res.extend([with_span(t, span)]);
+                i += 1;
+            }
+        }
+    }
+    Some(res)
+}
+
+/// Transcribe a repetition once for each iteration of the variables in it
+fn transcribe_repeat(
+    template: &TokenStream,
+    sep: Option<&TokenTree>,
+    bindings: &Bindings,
+    span: Span,
+) -> Option<TokenStream> {
+    let mut vars = Vec::new();
+    template_vars(template, &mut vars);
+    let repeated = vars
+        .iter()
+        .filter_map(|v| match bindings.get(v) {
+            Some(Binding::Repeated(values)) => Some((v, values)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let count = repeated.first()?.1.len();
+    if repeated.iter().any(|(_, values)| values.len() != count) {
+        return None;
+    }
+    let mut res = TokenStream::new();
+    for k in 0..count {
+        if let (Some(sep), true) = (sep, k > 0) {
+            res.extend([with_span(sep, span)]);
+        }
+        let mut iteration = bindings.clone();
+        for (v, values) in &repeated {
+            iteration.insert(v.to_string(), values[k].clone());
+        }
+        res.extend(transcribe(template, &iteration, span)?);
+    }
+    Some(res)
+}
+
+/// The parsed expansion of a macro call
+#[derive(Debug)]
+pub enum Expansion {
+    Stmts(Vec<syn::Stmt>),
+    Items(Vec<syn::Item>),
+}
+
+/// Expands calls to the crate's macros. Expansions are kept for as long as
+/// the expander, so the scanner can hold references into them as it does for
+/// the rest of the syntax tree.
+pub struct MacroExpander<'d> {
+    defs: &'d MacroDefs,
+    arena: Arena<Expansion>,
+    /// The rules of the macros called so far
+    rules: RefCell<HashMap<String, Rc<Vec<MacroRule>>>>,
+}
+
+impl fmt::Debug for MacroExpander<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The expansions are only kept alive for the scanner
+        f.debug_struct("MacroExpander").field("defs", &self.defs).finish_non_exhaustive()
+    }
+}
+
+impl<'d> MacroExpander<'d> {
+    pub fn new(defs: &'d MacroDefs) -> Self {
+        Self { defs, arena: Arena::new(), rules: RefCell::default() }
+    }
+
+    fn rules(&self, def: &MacroDef) -> Option<Rc<Vec<MacroRule>>> {
+        if let Some(rules) = self.rules.borrow().get(&def.name) {
+            return Some(rules.clone());
+        }
+        let rules = Rc::new(parse_rules(def.rules.parse().ok()?)?);
+        self.rules.borrow_mut().insert(def.name.clone(), rules.clone());
+        Some(rules)
+    }
+
+    /// The macro called and the tokens of its expansion, if the macro is one
+    /// of the crate's own and the call matches one of its rules
+    fn expand(&self, mac: &syn::Macro) -> Option<(&'d MacroDef, TokenStream)> {
+        // `name!` or `crate::name!` (from `$crate::name!` in other macros)
+        let name = match mac.path.segments.len() {
+            1 => mac.path.segments.last()?,
+            2 if mac.path.segments[0].ident == "crate" => mac.path.segments.last()?,
+            _ => return None,
+        };
+        let def = self.defs.get(&name.ident.to_string())?;
+        let span = name.ident.span();
+        for rule in self.rules(def)?.iter() {
+            let mut bindings = Bindings::new();
+            let matcher = |input: ParseStream| {
+                match_seq(input, &rule.matcher, &mut bindings)?;
+                if !input.is_empty() {
+                    return Err(input.error("unexpected tokens"));
+                }
+                Ok(())
+            };
+            if matcher.parse2(mac.tokens.clone()).is_ok() {
+                return Some((def, transcribe(&rule.transcriber, &bindings, span)?));
+            }
+        }
+        debug!("No rule of {}! matched at {:?}", def.name, span.start());
+        None
+    }
+
+    /// Expand a macro call in statement or expression position
+    pub fn expand_stmts(&self, mac: &syn::Macro) -> Option<(&'d MacroDef, &[syn::Stmt])> {
+        let (def, tokens) = self.expand(mac)?;
+        let stmts = syn::Block::parse_within.parse2(tokens).ok()?;
+        match self.arena.alloc(Expansion::Stmts(stmts)) {
+            Expansion::Stmts(stmts) => Some((def, stmts)),
+            Expansion::Items(_) => unreachable!(),
+        }
+    }
+
+    /// Expand a macro call in item position
+    pub fn expand_items(&self, mac: &syn::Macro) -> Option<(&'d MacroDef, &[syn::Item])> {
+        let (def, tokens) = self.expand(mac)?;
+        let items = syn::parse2::<syn::File>(tokens).ok()?.items;
+        match self.arena.alloc(Expansion::Items(items)) {
+            Expansion::Items(items) => Some((def, items)),
+            Expansion::Stmts(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::spanned::Spanned;
+
+    const SRC: &str = "
+macro_rules! read_all {
+    () => {};
+    ($($path:expr),+ $(,)?) => {
+        $($crate::fs::read($path).unwrap();)+
+    };
+}
+";
+
+    fn expander_defs() -> MacroDefs {
+        let mut defs = MacroDefs::default();
+        let file = syn::parse_file(SRC).unwrap();
+        let mut macros = Vec::new();
+        item_macros(FilePath::new("src/lib.rs"), &file.items, &mut macros);
+        for def in macros {
+            defs.add(def);
+        }
+        defs
+    }
+
+    fn call_path(s: &syn::Stmt) -> (&syn::ExprPath, &syn::Expr) {
+        let syn::Stmt::Expr(syn::Expr::MethodCall(m), _) = s else {
+            panic!("expected a method call: {:?}", s);
+        };
+        let syn::Expr::Call(c) = &*m.receiver else {
+            panic!("expected a call: {:?}", m.receiver);
+        };
+        let syn::Expr::Path(p) = &*c.func else {
+            panic!("expected a path: {:?}", c.func);
+        };
+        (p, &c.args[0])
+    }
+
+    #[test]
+    fn test_expand_repetition() {
+        let defs = expander_defs();
+        let expander = MacroExpander::new(&defs);
+        let call = syn::parse_str::<syn::Macro>("read_all!(\"a\", \"b\",)").unwrap();
+        let (def, stmts) = expander.expand_stmts(&call).unwrap();
+        assert_eq!(def.name, "read_all");
+        assert_eq!(def.loc.start_line(), 2);
+        assert_eq!(stmts.len(), 2);
+        let (path, arg) = call_path(&stmts[1]);
+        assert_eq!(path.to_token_stream().to_string(), "crate :: fs :: read");
+        assert!(arg.to_token_stream().to_string().contains("\"b\""));
+
+        let empty = syn::parse_str::<syn::Macro>("read_all!()").unwrap();
+        assert!(expander.expand_stmts(&empty).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_expansion_spans() {
+        let defs = expander_defs();
+        let expander = MacroExpander::new(&defs);
+        let call = syn::parse_str::<syn::Macro>("read_all!(\n\n  \"a\")").unwrap();
+        let (_, stmts) = expander.expand_stmts(&call).unwrap();
+        let (path, arg) = call_path(&stmts[0]);
+        // Tokens from the definition are at the macro name, and tokens from
+        // the call keep their location
+        assert_eq!(path.span().start().line, 1);
+        assert_eq!(path.span().start().column, 0);
+        assert_eq!(arg.span().start().line, 3);
+        assert_eq!(arg.span().start().column, 2);
+    }
+
+    #[test]
+    fn test_no_expansion() {
+        let defs = expander_defs();
+        let expander = MacroExpander::new(&defs);
+        let unknown = syn::parse_str::<syn::Macro>("println!(\"a\")").unwrap();
+        assert!(expander.expand_stmts(&unknown).is_none());
+        let unmatched = syn::parse_str::<syn::Macro>("read_all!(;)").unwrap();
+        assert!(expander.expand_stmts(&unmatched).is_none());
+    }
+}
@@ -0,0 +1,281 @@
+//! Heuristics for patterns common in malicious crates.
+//!
+//! These are opt-in (`--effect-types malware`), and each produces a
+//! `Malware` effect with its own pattern:
+//! - `DecodedExec`: an obfuscated byte array (a long array of byte literals)
+//!   flowing into a process spawn or a `transmute`
+//! - `EncodedSpawn`: a base64 blob flowing into a process spawn
+//! - `BuildScriptNetwork`: any network call in the build script (`build.rs`,
+//!   or the one the manifest declares)
+//! - `HomeWrite`: a file write in the build script to a path under the user's
+//!   home directory
+//!
+//! The data flow is purely syntactic and local to a function: a value is
+//! tainted if it is a suspicious literal, a local variable or constant
+//! initialized with a tainted value, or a call or method call with a tainted
+//! argument or receiver, e.g. `String::from_utf8(decode(BLOB))`.
+
+use crate::ident::CanonicalPath;
+use crate::util;
+
+use std::collections::HashMap;
+use std::path::Path as FilePath;
+use syn::punctuated::Punctuated;
+
+/// Byte arrays at least this long are considered obfuscated
+const MIN_BYTE_ARRAY_LEN: usize = 32;
+
+/// String literals at least this long which look like base64 are considered
+/// encoded blobs
+const MIN_BASE64_LEN: usize = 40;
+
+/// Strings which refer to the user's home directory or files in it
+const HOME_MARKERS: &[&str] =
+    &["$HOME", "~/", ".ssh", ".bashrc", ".zshrc", ".profile", ".bash_profile"];
+
+/// Functions which spawn a process or execute a value, as the last two segments
+/// of their path
+const EXEC_FNS: &[(&str, &str)] = &[
+    ("Command", "new"),
+    ("Command", "arg"),
+    ("Command", "args"),
+    ("mem", "transmute"),
+    ("libc", "system"),
+    ("libc", "popen"),
+    ("libc", "execv"),
+    ("libc", "execve"),
+    ("libc", "execvp"),
+    ("libc", "execl"),
+    ("libc", "execlp"),
+];
+
+/// Methods which add arguments to a process spawn, for receivers whose type
+/// couldn't be resolved
+const EXEC_METHODS: &[&str] = &["arg", "args"];
+
+/// Crates and modules whose calls are network calls
+const NETWORK_PREFIXES: &[&str] = &[
+    "std::net::",
+    "reqwest::",
+    "ureq::",
+    "curl::",
+    "hyper::",
+    "attohttpc::",
+    "isahc::",
+    "surf::",
+];
+
+/// Functions which write to a file, as the last two segments of their path
+const FILE_WRITE_FNS: &[(&str, &str)] = &[
+    ("fs", "write"),
+    ("fs", "copy"),
+    ("fs", "rename"),
+    ("fs", "create_dir"),
+    ("fs", "create_dir_all"),
+    ("File", "create"),
+    ("OpenOptions", "open"),
+];
+
+/// What a value may have been derived from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Taints {
+    pub byte_array: bool,
+    pub base64: bool,
+    pub home: bool,
+}
+
+impl Taints {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            byte_array: self.byte_array || other.byte_array,
+            base64: self.base64 || other.base64,
+            home: self.home || other.home,
+        }
+    }
+}
+
+/// Whether the file is a build script: the one the nearest `Cargo.toml` above
+/// it declares (`package.build`, by default a `build.rs` next to it)
+pub fn is_build_script(filepath: &FilePath) -> bool {
+    let Some(crate_path) =
+        filepath.ancestors().skip(1).find(|d| d.join("Cargo.toml").is_file())
+    else {
+        return false;
+    };
+    util::build_script_path(crate_path)
+        .ok()
+        .flatten()
+        .map_or(false, |build_script| build_script == filepath)
+}
+
+fn last_two(callee: &CanonicalPath) -> (Option<&str>, Option<&str>) {
+    let mut segments = callee.as_str().rsplit("::");
+    let name = segments.next();
+    (segments.next(), name)
+}
+
+fn in_fn_table(callee: &CanonicalPath, table: &[(&str, &str)]) -> bool {
+    let (parent, name) = last_two(callee);
+    table.iter().any(|(p, n)| parent == Some(*p) && name == Some(*n))
+}
+
+pub fn is_exec_fn(callee: &CanonicalPath) -> bool {
+    in_fn_table(callee, EXEC_FNS)
+}
+
+pub fn is_exec_method(method: &syn::Ident) -> bool {
+    EXEC_METHODS.iter().any(|m| method == m)
+}
+
+pub fn is_network_fn(callee: &CanonicalPath) -> bool {
+    NETWORK_PREFIXES.iter().any(|p| callee.as_str().starts_with(p))
+        || in_fn_table(callee, &[("TcpStream", "connect"), ("UdpSocket", "bind")])
+}
+
+pub fn is_file_write_fn(callee: &CanonicalPath) -> bool {
+    in_fn_table(callee, FILE_WRITE_FNS)
+}
+
+fn looks_base64(s: &str) -> bool {
+    let body = s.trim_end_matches('=');
+    s.len() >= MIN_BASE64_LEN
+        && s.len() - body.len() <= 2
+        && body.chars().all(|c| c.is_ascii_alphanumeric() || "+/-_".contains(c))
+        && body.chars().any(|c| c.is_ascii_uppercase())
+        && body.chars().any(|c| c.is_ascii_lowercase())
+        && body.chars().any(|c| c.is_ascii_digit())
+}
+
+fn refers_to_home(s: &str) -> bool {
+    s == "HOME" || s == "USERPROFILE" || HOME_MARKERS.iter().any(|m| s.contains(m))
+}
+
+fn lit_taints(l: &syn::Lit) -> Taints {
+    match l {
+        syn::Lit::ByteStr(b) => Taints {
+            byte_array: b.value().len() >= MIN_BYTE_ARRAY_LEN,
+            ..Taints::default()
+        },
+        syn::Lit::Str(s) => {
+            let s = s.value();
+            Taints {
+                base64: looks_base64(&s),
+                home: refers_to_home(&s),
+                ..Taints::default()
+            }
+        }
+        _ => Taints::default(),
+    }
+}
+
+fn is_int_lit(x: &syn::Expr) -> bool {
+    matches!(x, syn::Expr::Lit(l) if matches!(l.lit, syn::Lit::Int(_)))
+}
+
+fn all_taints<'a, I>(exprs: I, vars: &impl Fn(&syn::Ident) -> Taints) -> Taints
+where
+    I: IntoIterator<Item = &'a syn::Expr>,
+{
+    exprs.into_iter().fold(Taints::default(), |t, x| t.union(expr_taints(x, vars)))
+}
+
+/// What the expression may have been derived from, given the taints of the
+/// variables in scope
+pub fn expr_taints(x: &syn::Expr, vars: &impl Fn(&syn::Ident) -> Taints) -> Taints {
+    match x {
+        syn::Expr::Lit(l) => lit_taints(&l.lit),
+        syn::Expr::Array(a) => {
+            let byte_array =
+                a.elems.len() >= MIN_BYTE_ARRAY_LEN && a.elems.iter().all(is_int_lit);
+            Taints { byte_array, ..Taints::default() }.union(all_taints(&a.elems, vars))
+        }
+        syn::Expr::Path(p) => p.path.get_ident().map(vars).unwrap_or_default(),
+        syn::Expr::Call(c) => {
+            let home_dir = match &*c.func {
+                syn::Expr::Path(p) => {
+                    p.path.segments.last().map_or(false, |s| s.ident == "home_dir")
+                }
+                _ => false,
+            };
+            Taints { home: home_dir, ..Taints::default() }
+                .union(all_taints(&c.args, vars))
+        }
+        syn::Expr::MethodCall(m) => {
+            Taints { home: m.method == "home_dir", ..Taints::default() }
+                .union(expr_taints(&m.receiver, vars))
+                .union(all_taints(&m.args, vars))
+        }
+        syn::Expr::Macro(m) => m
+            .mac
+            .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+            .map(|args| all_taints(&args, vars))
+            .unwrap_or_default(),
+        syn::Expr::Binary(b) => {
+            expr_taints(&b.left, vars).union(expr_taints(&b.right, vars))
+        }
+        syn::Expr::Reference(r) => expr_taints(&r.expr, vars),
+        syn::Expr::Paren(p) => expr_taints(&p.expr, vars),
+        syn::Expr::Group(g) => expr_taints(&g.expr, vars),
+        syn::Expr::Cast(c) => expr_taints(&c.expr, vars),
+        syn::Expr::Try(t) => expr_taints(&t.expr, vars),
+        syn::Expr::Field(f) => expr_taints(&f.base, vars),
+        syn::Expr::Index(i) => expr_taints(&i.expr, vars),
+        _ => Taints::default(),
+    }
+}
+
+/// Record the taints of the variables bound by a pattern
+pub fn bind_pattern(pat: &syn::Pat, taints: Taints, vars: &mut HashMap<String, Taints>) {
+    match pat {
+        syn::Pat::Ident(i) => {
+            if taints.is_empty() {
+                vars.remove(&i.ident.to_string());
+            } else {
+                vars.insert(i.ident.to_string(), taints);
+            }
+        }
+        syn::Pat::Type(t) => bind_pattern(&t.pat, taints, vars),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taints(src: &str, vars: &HashMap<String, Taints>) -> Taints {
+        let x = syn::parse_str::<syn::Expr>(src).unwrap();
+        expr_taints(&x, &|i: &syn::Ident| {
+            vars.get(&i.to_string()).copied().unwrap_or_default()
+        })
+    }
+
+    #[test]
+    fn test_expr_taints() {
+        let blob = "\"aGVsbG8gd29ybGQsIHRoaXMgaXMgYSBwYXlsb2FkIQ==\"";
+        let mut vars = HashMap::new();
+        assert!(taints(&format!("decode({})", blob), &vars).base64);
+        assert!(!taints("\"not base64, just a sentence\"", &vars).base64);
+
+        let bytes = format!("[{}]", vec!["0x41"; MIN_BYTE_ARRAY_LEN].join(", "));
+        bind_pattern(&syn::parse_quote!(payload), taints(&bytes, &vars), &mut vars);
+        assert!(taints("String::from_utf8(payload.to_vec()).unwrap()", &vars).byte_array);
+        assert!(taints("[1, 2, 3]", &vars).is_empty());
+
+        assert!(taints("format!(\"{}/.bashrc\", dir)", &vars).home);
+        assert!(taints("dirs::home_dir().unwrap().join(\"x\")", &vars).home);
+        assert!(taints("env::var(\"HOME\")", &vars).home);
+    }
+
+    #[test]
+    fn test_classify_calls() {
+        assert!(is_exec_fn(&CanonicalPath::new("std::process::Command::new")));
+        assert!(is_network_fn(&CanonicalPath::new("reqwest::blocking::get")));
+        assert!(is_file_write_fn(&CanonicalPath::new("std::fs::write")));
+        assert!(!is_file_write_fn(&CanonicalPath::new("std::fs::read")));
+    }
+}
@@ -0,0 +1,138 @@
+//! Memory accounting for the per-crate scan budget (see
+//! `scanner::ScanBudget`).
+//!
+//! Crates are scanned in parallel, so the memory of the whole process says
+//! little about any one of them, and a budget checked against it would cut
+//! scans short depending on what else happens to run. Instead, the global
+//! allocator counts the bytes each thread allocates and frees against the
+//! account it has entered: the threads scanning a crate enter the crate's
+//! account, so its balance is the memory held by that scan alone. Threads
+//! the scan doesn't run itself (e.g. the file loader of rust-analyzer) aren't
+//! counted.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    /// The balance of the account the thread has entered, if any
+    static CURRENT: Cell<*const AtomicIsize> = const { Cell::new(ptr::null()) };
+}
+
+fn record(delta: isize) {
+    // The thread-local is gone while the thread exits
+    let _ = CURRENT.try_with(|current| {
+        let balance = current.get();
+        if !balance.is_null() {
+            // SAFETY: the pointer is only set while an AccountGuard, which
+            // keeps the account alive, is in scope on this thread
+            unsafe { &*balance }.fetch_add(delta, Ordering::Relaxed);
+        }
+    });
+}
+
+/// The system allocator, counting allocations against the account the
+/// current thread has entered
+pub struct CountingAlloc;
+
+// SAFETY: allocation is delegated to the system allocator; the accounting
+// doesn't allocate
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            record(layout.size() as isize);
+        }
+        p
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc_zeroed(layout);
+        if !p.is_null() {
+            record(layout.size() as isize);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, p: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(p, layout, new_size);
+        if !new.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        new
+    }
+}
+
+/// The memory held by the work of the threads which entered the account
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAccount(Arc<AtomicIsize>);
+
+impl MemoryAccount {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the allocations of the current thread against the account
+    /// until the guard is dropped
+    pub fn enter(&self) -> AccountGuard {
+        let balance = Arc::as_ptr(&self.0);
+        let prev = CURRENT.with(|current| current.replace(balance));
+        AccountGuard { _account: self.clone(), prev }
+    }
+
+    /// Memory held, in MB. Memory allocated before entering the account and
+    /// freed after isn't held by it, so the balance may dip below zero.
+    pub fn used_mb(&self) -> u64 {
+        self.0.load(Ordering::Relaxed).max(0) as u64 >> 20
+    }
+}
+
+/// The current thread's allocations are counted against the account while
+/// the guard lives; it can't leave the thread
+pub struct AccountGuard {
+    _account: MemoryAccount,
+    prev: *const AtomicIsize,
+}
+
+impl Drop for AccountGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.prev));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_account() {
+        let account = MemoryAccount::new();
+        let other = MemoryAccount::new();
+        let guard = account.enter();
+        let held = vec![1u8; 8 << 20];
+        {
+            // Nested accounts only count their own allocations
+            let _guard = other.enter();
+            let _more = vec![1u8; 4 << 20];
+            assert!(other.used_mb() >= 4);
+        }
+        assert_eq!(other.used_mb(), 0);
+        assert!((8..12).contains(&account.used_mb()));
+        drop(held);
+        assert_eq!(account.used_mb(), 0);
+        drop(guard);
+
+        // Memory allocated outside of the account isn't held by it
+        let held = vec![1u8; 8 << 20];
+        let _guard = other.enter();
+        drop(held);
+        assert_eq!(other.used_mb(), 0);
+    }
+}
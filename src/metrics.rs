@@ -0,0 +1,266 @@
+//! Metrics of long scans (e.g. `chain create` over a large dependency tree),
+//! in the Prometheus text format, for monitoring ecosystem-scale runs.
+//!
+//! The scanner counts the crates scanned and the effects found, the caches
+//! count their hits and misses, and the phases of a scan (loading the crate
+//! for name resolution, scanning its files, post-processing the results, and
+//! resolving dependencies) add up their time. With `--metrics-file <path>`,
+//! the metrics are written to the file after each crate is scanned (e.g. for
+//! the node_exporter textfile collector); with `--metrics-addr <host:port>`,
+//! they are served at `GET /metrics` for the run's duration:
+//! ```text
+//! cargo_scan_crates_scanned_total 42
+//! cargo_scan_effects_found_total 1337
+//! cargo_scan_cache_hits_total{cache="dep-graph"} 1
+//! cargo_scan_cache_hit_ratio{cache="dep-graph"} 1
+//! cargo_scan_phase_seconds_total{phase="scan"} 12.5
+//! ```
+
+use crate::http::{self, Response};
+use crate::progress;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Where to export the metrics of the run, if anywhere
+#[derive(Parser, Debug, Clone, Default)]
+pub struct MetricsArgs {
+    /// Write Prometheus metrics of the run (crates scanned, effects found,
+    /// cache hit rates, phase timings) to this file after each crate scanned
+    #[clap(long)]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Serve Prometheus metrics of the run at http://<address>/metrics, e.g.
+    /// 127.0.0.1:9100
+    #[clap(long)]
+    pub metrics_addr: Option<String>,
+}
+
+impl MetricsArgs {
+    /// Start serving the metrics, if the arguments give an address
+    pub fn init(&self) -> Result<()> {
+        if let Some(addr) = &self.metrics_addr {
+            serve_metrics(addr)?;
+        }
+        Ok(())
+    }
+
+    /// Write the metrics to the metrics file, if there is one. The file is
+    /// replaced rather than written in place, so it is never read half-written.
+    pub fn write_file(&self) -> Result<()> {
+        let Some(path) = &self.metrics_file else {
+            return Ok(());
+        };
+        // Crates scanned in parallel finish at the same time
+        static WRITING: Mutex<()> = Mutex::new(());
+        let _lock = WRITING.lock().unwrap();
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, METRICS.to_prometheus())
+            .context(format!("Couldn't write metrics to {:?}", tmp))?;
+        fs::rename(&tmp, path)
+            .context(format!("Couldn't write metrics to {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct PhaseTime {
+    runs: u64,
+    seconds: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    crates_scanned: AtomicU64,
+    effects_found: AtomicU64,
+    /// Hits and misses, by cache
+    caches: Mutex<BTreeMap<&'static str, (u64, u64)>>,
+    phases: Mutex<BTreeMap<&'static str, PhaseTime>>,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            crates_scanned: AtomicU64::new(0),
+            effects_found: AtomicU64::new(0),
+            caches: Mutex::new(BTreeMap::new()),
+            phases: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn add_effects(&self, n: usize) {
+        self.effects_found.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_crate_scanned(&self) {
+        self.crates_scanned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a lookup in the cache
+    pub fn cache_lookup(&self, cache: &'static str, hit: bool) {
+        let mut caches = self.caches.lock().unwrap();
+        let (hits, misses) = caches.entry(cache).or_default();
+        if hit {
+            *hits += 1;
+        } else {
+            *misses += 1;
+        }
+    }
+
+    pub fn add_phase_time(&self, phase: &'static str, elapsed: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        let time = phases.entry(phase).or_default();
+        time.runs += 1;
+        time.seconds += elapsed.as_secs_f64();
+    }
+
+    /// Run `f`, adding its time to the phase
+    pub fn time_phase<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add_phase_time(phase, start.elapsed());
+        result
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric =
+            |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+                writeln!(out, "# HELP {} {}", name, help).unwrap();
+                writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+                for (labels, value) in samples {
+                    writeln!(out, "{}{} {}", name, labels, value).unwrap();
+                }
+            };
+        let unlabeled =
+            |n: &AtomicU64| vec![(String::new(), n.load(Ordering::Relaxed).to_string())];
+        metric(
+            "cargo_scan_crates_scanned_total",
+            "counter",
+            "Crates scanned",
+            &unlabeled(&self.crates_scanned),
+        );
+        metric(
+            "cargo_scan_effects_found_total",
+            "counter",
+            "Effects found in the crates scanned",
+            &unlabeled(&self.effects_found),
+        );
+
+        let caches = self.caches.lock().unwrap();
+        let by_cache = |f: &dyn Fn(u64, u64) -> String| {
+            caches
+                .iter()
+                .map(|(cache, &(hits, misses))| {
+                    (format!("{{cache=\"{}\"}}", cache), f(hits, misses))
+                })
+                .collect::<Vec<_>>()
+        };
+        metric(
+            "cargo_scan_cache_hits_total",
+            "counter",
+            "Lookups found in the cache",
+            &by_cache(&|hits, _| hits.to_string()),
+        );
+        metric(
+            "cargo_scan_cache_misses_total",
+            "counter",
+            "Lookups not found in the cache",
+            &by_cache(&|_, misses| misses.to_string()),
+        );
+        metric(
+            "cargo_scan_cache_hit_ratio",
+            "gauge",
+            "Share of the lookups found in the cache",
+            &by_cache(&|hits, misses| (hits as f64 / (hits + misses) as f64).to_string()),
+        );
+
+        let phases = self.phases.lock().unwrap();
+        let by_phase = |f: &dyn Fn(&PhaseTime) -> String| {
+            phases
+                .iter()
+                .map(|(phase, time)| (format!("{{phase=\"{}\"}}", phase), f(time)))
+                .collect::<Vec<_>>()
+        };
+        metric(
+            "cargo_scan_phase_seconds_total",
+            "counter",
+            "Time spent in each phase of the scans",
+            &by_phase(&|t| t.seconds.to_string()),
+        );
+        metric(
+            "cargo_scan_phase_runs_total",
+            "counter",
+            "Runs of each phase of the scans",
+            &by_phase(&|t| t.runs.to_string()),
+        );
+        out
+    }
+}
+
+static METRICS: Metrics = Metrics::new();
+
+/// The metrics of this run
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Record a crate scanned, and update the metrics file
+pub fn crate_scanned(args: &MetricsArgs) {
+    METRICS.add_crate_scanned();
+    args.write_file().unwrap_or_else(|e| warn!("{}", e));
+}
+
+/// Serve the metrics at `GET /metrics` on the address, in the background
+pub fn serve_metrics(addr: &str) -> Result<()> {
+    let listener =
+        TcpListener::bind(addr).context(format!("Couldn't serve metrics on {}", addr))?;
+    progress!("Serving metrics on http://{}/metrics", addr);
+    std::thread::spawn(move || {
+        http::serve(listener, |req| match (req.method.as_str(), &req.path[..]) {
+            ("GET", [metrics]) if metrics == "metrics" => {
+                Response::text(200, "text/plain; version=0.0.4", METRICS.to_prometheus())
+            }
+            _ => Response::text(404, "text/plain", "not found\n".to_string()),
+        })
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::new();
+        metrics.add_crate_scanned();
+        metrics.add_effects(3);
+        metrics.cache_lookup("dep-graph", true);
+        metrics.cache_lookup("dep-graph", true);
+        metrics.cache_lookup("dep-graph", false);
+        metrics.cache_lookup("chain", false);
+        assert_eq!(metrics.time_phase("scan", || 7), 7);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains(
+            "# TYPE cargo_scan_crates_scanned_total counter\n\
+             cargo_scan_crates_scanned_total 1\n"
+        ));
+        assert!(text.contains("cargo_scan_effects_found_total 3\n"));
+        assert!(text.contains("cargo_scan_cache_hits_total{cache=\"dep-graph\"} 2\n"));
+        assert!(text.contains("cargo_scan_cache_misses_total{cache=\"chain\"} 1\n"));
+        assert!(text.contains("cargo_scan_cache_hit_ratio{cache=\"chain\"} 0\n"));
+        assert!(text.contains("cargo_scan_phase_runs_total{phase=\"scan\"} 1\n"));
+    }
+}
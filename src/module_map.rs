@@ -0,0 +1,197 @@
+//! Which module of the crate each source file is, following the `mod`
+//! declarations from the crate roots, including their `#[path = ".."]`
+//! attributes, and the files pulled in with `include!("..")`.
+//!
+//! The scanner scans each file on its own, and without this infers the
+//! module from the file's path, which is wrong for `#[path]` modules (e.g.
+//! `#[path = "unix.rs"] mod sys;` is `sys`, not `unix`) and for included
+//! files, which are part of the module including them. Included files
+//! outside of `src/` wouldn't be scanned at all. With the map, effects keep
+//! the location of the file they are actually in, with the right module.
+//!
+//! Includes of paths computed at build time (e.g.
+//! `include!(concat!(env!("OUT_DIR"), "/bindings.rs"))`) can't be followed,
+//! since the generated files don't exist until the crate is built.
+
+use crate::util;
+
+use log::debug;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleMap {
+    /// Module path of each file, below the crate root
+    modules: HashMap<PathBuf, Vec<String>>,
+}
+
+/// Remove the `..` components of the path (e.g. of `#[path = "../sys.rs"]`),
+/// so that each file has a single path
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::ParentDir
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether nested modules of the file are in its directory, rather than in
+/// a directory named after it
+fn is_mod_rs(file: &Path) -> bool {
+    let name = file.file_name().and_then(|n| n.to_str());
+    matches!(name, Some("lib.rs" | "main.rs" | "mod.rs"))
+}
+
+/// The string value of a `#[path = ".."]` attribute
+fn path_attr(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(nv) if nv.path.is_ident("path") => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(s), .. }) => Some(s.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// The file of an `include!("..")` item, relative to the including file
+fn include_path(m: &syn::ItemMacro) -> Option<String> {
+    if !m.mac.path.is_ident("include") {
+        return None;
+    }
+    match m.mac.parse_body::<syn::LitStr>() {
+        Ok(s) => Some(s.value()),
+        Err(_) => {
+            debug!("Can't follow include!({})", m.mac.tokens);
+            None
+        }
+    }
+}
+
+impl ModuleMap {
+    /// The modules of the files reachable from the crate roots (e.g.
+    /// `src/lib.rs`)
+    pub fn from_roots(roots: &[PathBuf]) -> Self {
+        let mut map = Self::default();
+        for root in roots {
+            map.add_file(root, Vec::new());
+        }
+        map
+    }
+
+    fn add_file(&mut self, file: &Path, module: Vec<String>) {
+        let file = &normalize(file);
+        if self.modules.contains_key(file) || !file.is_file() {
+            return;
+        }
+        self.modules.insert(file.to_path_buf(), module.clone());
+        let Ok(src) = util::fs::read_source(file) else {
+            return;
+        };
+        let Ok(syntax_tree) = syn::parse_file(&src.text) else {
+            debug!("Couldn't parse {:?} for its modules", file);
+            return;
+        };
+        // Directory of the file's own submodules
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let mod_dir = if is_mod_rs(file) {
+            dir.to_path_buf()
+        } else {
+            dir.join(file.file_stem().unwrap_or_default())
+        };
+        self.add_items(file, &syntax_tree.items, &module, &mod_dir, &[]);
+    }
+
+    /// `inline` are the inline modules the items are in, within the file
+    fn add_items(
+        &mut self,
+        file: &Path,
+        items: &[syn::Item],
+        module: &[String],
+        mod_dir: &Path,
+        inline: &[String],
+    ) {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for item in items {
+            match item {
+                syn::Item::Mod(m) => {
+                    let name = m.ident.to_string();
+                    let mut submodule = module.to_vec();
+                    submodule.push(name.clone());
+                    let nested_dir =
+                        inline.iter().fold(mod_dir.to_path_buf(), |d, m| d.join(m));
+                    match (&m.content, path_attr(&m.attrs)) {
+                        (Some((_, items)), _) => {
+                            let mut inline = inline.to_vec();
+                            inline.push(name);
+                            self.add_items(file, items, &submodule, mod_dir, &inline);
+                        }
+                        // Outside of inline modules, paths are relative to
+                        // the file's directory
+                        (None, Some(path)) if inline.is_empty() => {
+                            self.add_file(&dir.join(path), submodule)
+                        }
+                        (None, Some(path)) => {
+                            self.add_file(&nested_dir.join(path), submodule)
+                        }
+                        (None, None) => {
+                            let flat = nested_dir.join(format!("{}.rs", name));
+                            let nested = nested_dir.join(&name).join("mod.rs");
+                            let file = if flat.is_file() { flat } else { nested };
+                            self.add_file(&file, submodule);
+                        }
+                    }
+                }
+                syn::Item::Macro(m) => {
+                    if let Some(path) = include_path(m) {
+                        self.add_file(&dir.join(path), module.to_vec());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The module path of the file (e.g. `my_crate::sys`), if it is in the
+    /// crate's module tree
+    pub fn module_of(&self, crate_name: &str, file: &Path) -> Option<String> {
+        let module = self.modules.get(file)?;
+        let mut path = vec![crate_name];
+        path.extend(module.iter().map(String::as_str));
+        Some(path.join("::"))
+    }
+
+    /// The files in the crate's module tree
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.modules.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_map() {
+        let dir = PathBuf::from("data/test-packages/module-paths/src");
+        let map = ModuleMap::from_roots(&[dir.join("lib.rs")]);
+        let module = |f: &str| map.module_of("module_paths", &dir.join(f));
+        assert_eq!(module("lib.rs").as_deref(), Some("module_paths"));
+        assert_eq!(module("unix.rs").as_deref(), Some("module_paths::sys"));
+        assert_eq!(module("net/mod.rs").as_deref(), Some("module_paths::net"));
+        assert_eq!(module("net/tcp.rs").as_deref(), Some("module_paths::net::tcp"));
+        // Included from an inline module, outside of src
+        let ffi = dir.parent().unwrap().join("generated/ffi.rs");
+        assert_eq!(
+            map.module_of("module_paths", &ffi).as_deref(),
+            Some("module_paths::ffi")
+        );
+        assert_eq!(module("orphan.rs"), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! Checks of dependency names for typosquatting and dependency confusion.
+//!
+//! - A dependency from crates.io is a possible typosquat if its name is a
+//!   small edit distance from (but not the same as) the name of a popular
+//!   crate, e.g. `serde_jsn` or `tokoi`. Names are compared with `-` and `_`
+//!   treated as the same, as crates.io does.
+//! - A dependency from crates.io is a possible dependency confusion if its
+//!   name looks internal (e.g. `acme-internal-auth`), since an internal crate
+//!   would normally come from a private registry, git, or a path.
+
+use crate::util::CrateId;
+
+use cargo_lock::Package;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Some of the most downloaded crates on crates.io
+const POPULAR_CRATES: &[&str] = &[
+    "ahash",
+    "anyhow",
+    "async-trait",
+    "base64",
+    "bitflags",
+    "byteorder",
+    "bytes",
+    "cfg-if",
+    "chrono",
+    "clap",
+    "crossbeam",
+    "digest",
+    "either",
+    "env_logger",
+    "futures",
+    "getrandom",
+    "hashbrown",
+    "http",
+    "hyper",
+    "indexmap",
+    "itertools",
+    "itoa",
+    "lazy_static",
+    "libc",
+    "memchr",
+    "num-traits",
+    "once_cell",
+    "openssl",
+    "parking_lot",
+    "percent-encoding",
+    "proc-macro2",
+    "quote",
+    "rand",
+    "rand_core",
+    "rayon",
+    "regex",
+    "reqwest",
+    "ring",
+    "rustls",
+    "ryu",
+    "semver",
+    "serde",
+    "serde_derive",
+    "serde_json",
+    "sha2",
+    "smallvec",
+    "socket2",
+    "syn",
+    "tempfile",
+    "thiserror",
+    "time",
+    "tokio",
+    "toml",
+    "tracing",
+    "unicode-ident",
+    "url",
+    "uuid",
+    "winapi",
+];
+
+/// Name segments which suggest a crate is internal to an organization
+const INTERNAL_MARKERS: &[&str] =
+    &["internal", "private", "corp", "intranet", "proprietary", "priv"];
+
+/// Names shorter than this aren't checked for typosquatting, as too many
+/// legitimate short names are a single edit from each other
+const MIN_TYPOSQUAT_LEN: usize = 5;
+
+/// Names at least this long are flagged up to two edits from a popular crate,
+/// and shorter names up to one edit
+const LONG_NAME_LEN: usize = 9;
+
+/// A suspicious dependency name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum NameWarning {
+    /// The name is close to the name of a popular crate
+    Typosquat { crate_id: String, similar_to: String },
+    /// The name looks internal, but the crate comes from crates.io
+    DependencyConfusion { crate_id: String },
+}
+
+impl fmt::Display for NameWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Typosquat { crate_id, similar_to } => {
+                write!(f, "{}: possible typosquat of {}", crate_id, similar_to)
+            }
+            Self::DependencyConfusion { crate_id } => {
+                write!(f, "{}: internal-looking name resolved from crates.io", crate_id)
+            }
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.to_lowercase().replace('-', "_")
+}
+
+/// Edit distance counting insertions, deletions, substitutions, and
+/// transpositions of adjacent characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The popular crate the name may be a typosquat of, if any
+pub fn typosquat_target(name: &str) -> Option<&'static str> {
+    let name = normalize(name);
+    if name.len() < MIN_TYPOSQUAT_LEN
+        || POPULAR_CRATES.iter().any(|p| normalize(p) == name)
+    {
+        return None;
+    }
+    let max_distance = if name.len() >= LONG_NAME_LEN { 2 } else { 1 };
+    POPULAR_CRATES
+        .iter()
+        .map(|p| (edit_distance(&name, &normalize(p)), *p))
+        .filter(|(d, _)| *d <= max_distance)
+        .min()
+        .map(|(_, p)| p)
+}
+
+/// Whether the name looks like the name of an internal crate
+pub fn looks_internal(name: &str) -> bool {
+    normalize(name).split('_').any(|s| INTERNAL_MARKERS.contains(&s))
+}
+
+/// Check the names of the packages from crates.io
+pub fn check_packages(packages: &[Package]) -> Vec<NameWarning> {
+    let mut warnings = Vec::new();
+    for p in packages {
+        if !p.source.as_ref().map_or(false, |s| s.is_default_registry()) {
+            continue;
+        }
+        let crate_id = CrateId::from(p).to_string();
+        if let Some(similar_to) = typosquat_target(p.name.as_str()) {
+            warnings.push(NameWarning::Typosquat {
+                crate_id: crate_id.clone(),
+                similar_to: similar_to.to_string(),
+            });
+        }
+        if looks_internal(p.name.as_str()) {
+            warnings.push(NameWarning::DependencyConfusion { crate_id });
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typosquat_target() {
+        assert_eq!(edit_distance("tokio", "tokoi"), 1);
+        assert_eq!(typosquat_target("tokoi"), Some("tokio"));
+        assert_eq!(typosquat_target("serde-jsonn"), Some("serde_json"));
+        assert_eq!(typosquat_target("serdejsn"), None);
+        assert_eq!(typosquat_target("serde-json"), None);
+        assert_eq!(typosquat_target("lib"), None);
+        assert_eq!(typosquat_target("cargo-scan"), None);
+    }
+
+    #[test]
+    fn test_looks_internal() {
+        assert!(looks_internal("acme-internal-auth"));
+        assert!(looks_internal("Corp_Utils"));
+        assert!(!looks_internal("privacy"));
+    }
+}
@@ -11,6 +11,7 @@ use std::path::Path;
 
 use super::effect::SrcLoc;
 use super::ident::{CanonicalPath, CanonicalType, Ident, TypeKind};
+use super::resolve::Resolver;
 
 use ra_ap_hir::{
     Adt, AsAssocItem, AssocItem, AssocItemContainer, CfgAtom, Crate, DefWithBody,
@@ -42,13 +43,14 @@ fn name_to_string(n: ra_ap_hir::Name) -> String {
     n.to_smol_str().to_string()
 }
 
+/// Name resolution with rust-analyzer (the default backend)
 #[derive(Debug)]
-pub struct Resolver {
+pub struct RaResolver {
     host: AnalysisHost,
     vfs: Vfs,
     // sems: Semantics<'a, RootDatabase>
 }
-impl Resolver {
+impl RaResolver {
     fn cargo_config() -> CargoConfig {
         // List of features to activate (or deactivate).
         let features = CargoFeatures::All;
@@ -98,7 +100,7 @@ impl Resolver {
         }
     }
 
-    pub fn new(crate_path: &Path) -> Result<Resolver> {
+    pub fn new(crate_path: &Path) -> Result<RaResolver> {
         debug!("Creating resolver with path {:?}", crate_path);
 
         // Make sure the path is a crate
@@ -134,7 +136,7 @@ impl Resolver {
 
         debug!("...created");
 
-        Ok(Resolver { host, vfs })
+        Ok(RaResolver { host, vfs })
     }
 
     fn get_db(&self) -> &RootDatabase {
@@ -244,7 +246,27 @@ impl Resolver {
         get_token(src_file, offset, i)
     }
 
-    pub fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
+    fn get_ra_diagnostics(&self, file_id: FileId, token: &SyntaxToken) -> Vec<String> {
+        let diags = self.host.analysis().diagnostics(
+            &ra_ap_ide::DiagnosticsConfig::test_sample(),
+            ra_ap_ide::AssistResolveStrategy::None,
+            file_id,
+        );
+
+        diags
+            .unwrap_or_default()
+            .iter()
+            .filter(|d| {
+                d.range.contains_range(token.text_range())
+                    || d.code.as_str().eq_ignore_ascii_case("unlinked-file")
+            })
+            .map(|d| d.code.as_str().to_string())
+            .collect_vec()
+    }
+}
+
+impl Resolver for RaResolver {
+    fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
         let db = self.get_db();
         let sems = self.get_semantics();
         let file_id = self.get_file_id(&s, &sems)?;
@@ -261,7 +283,7 @@ impl Resolver {
             })
     }
 
-    pub fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
+    fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
         let db = self.get_db();
         let sems = self.get_semantics();
         let file_id = self.get_file_id(&s, &sems)?;
@@ -272,7 +294,7 @@ impl Resolver {
         get_canonical_type(&sems, db, &def)
     }
 
-    pub fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+    fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
         let db = self.get_db();
         let sems = self.get_semantics();
         let file_id = self.get_file_id(&s, &sems)?;
@@ -303,7 +325,7 @@ impl Resolver {
         }
     }
 
-    pub fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+    fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
         let db = self.get_db();
         let sems = self.get_semantics();
         let file_id = self.get_file_id(&s, &sems)?;
@@ -323,29 +345,7 @@ impl Resolver {
         }
     }
 
-    fn get_ra_diagnostics(&self, file_id: FileId, token: &SyntaxToken) -> Vec<String> {
-        let diags = self.host.analysis().diagnostics(
-            &ra_ap_ide::DiagnosticsConfig::test_sample(),
-            ra_ap_ide::AssistResolveStrategy::None,
-            file_id,
-        );
-
-        diags
-            .unwrap_or_default()
-            .iter()
-            .filter(|d| {
-                d.range.contains_range(token.text_range())
-                    || d.code.as_str().eq_ignore_ascii_case("unlinked-file")
-            })
-            .map(|d| d.code.as_str().to_string())
-            .collect_vec()
-    }
-
-    pub fn is_const_or_immutable_static_ident(
-        &self,
-        s: SrcLoc,
-        i: Ident,
-    ) -> Result<bool> {
+    fn is_const_or_immutable_static_ident(&self, s: SrcLoc, i: Ident) -> Result<bool> {
         let db = self.get_db();
         let sems = self.get_semantics();
         let file_id = self.get_file_id(&s, &sems)?;
@@ -363,7 +363,7 @@ impl Resolver {
         Ok(matches!(def, Definition::Const(_)) || is_immutable_static(def))
     }
 
-    pub fn all_impl_methods_for_trait_method(
+    fn all_impl_methods_for_trait_method(
         &self,
         s: SrcLoc,
         i: Ident,
@@ -401,9 +401,9 @@ impl Resolver {
         Ok(impl_methods_for_trait_method)
     }
 
-    pub fn get_cfg_options_for_crate(
+    fn get_cfg_options_for_crate(
         &self,
-        name: &String,
+        name: &str,
     ) -> Result<HashMap<String, Vec<String>>> {
         let db = self.get_db();
         let mut crate_opts: HashMap<String, Vec<String>> = HashMap::default();
@@ -497,11 +497,23 @@ fn build_path_to_root(module: Module, db: &RootDatabase) -> Vec<Module> {
     path
 }
 
+// Type aliases and Self resolve to their own definitions; normalize them to
+// the underlying type so the path is the one sink patterns are written against
+fn normalize_type_def(db: &RootDatabase, def: &Definition) -> Definition {
+    let adt = match def {
+        Definition::TypeAlias(it) => it.ty(db).as_adt(),
+        Definition::SelfType(it) => it.self_ty(db).as_adt(),
+        _ => None,
+    };
+    adt.map(Definition::Adt).unwrap_or(*def)
+}
+
 fn canonical_path(
     sems: &Semantics<RootDatabase>,
     db: &RootDatabase,
     def: &Definition,
 ) -> Option<CanonicalPath> {
+    let def = &normalize_type_def(db, def);
     if let Definition::BuiltinType(b) = def {
         return Some(CanonicalPath::new(name_to_string(b.name()).as_str()));
     }
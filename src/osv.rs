@@ -0,0 +1,335 @@
+//! Export of the findings of an audit chain as OSV entries
+//! (https://ossf.github.io/osv-schema/), for vulnerability management systems.
+//!
+//! Each finding is an entry, with
+//! - the crate as the affected package (`crates.io` ecosystem), and the
+//!   audited version as the affected version range;
+//! - a summary and details generated from the effect and its audit;
+//! - the external references of the decision (e.g. issue URLs), and the
+//!   crate's page on crates.io;
+//! - the effect kind, severity, location, and verdict under
+//!   `database_specific`.
+//!
+//! Findings are the effects marked unsafe, and optionally the unaudited
+//! effects of at least a given severity. Entry ids are derived from the
+//! crate and the effect, so they stay the same across exports.
+
+use crate::audit_chain::AuditChain;
+use crate::audit_file::{AuditFile, EffectInfo, SafetyAnnotation};
+use crate::auditing::analytics::timestamp;
+use crate::effect::{EffectInstance, EffectType, Severity};
+use crate::sbom::purl;
+use crate::util::CrateId;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA_VERSION: &str = "1.6.0";
+const ECOSYSTEM: &str = "crates.io";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Package {
+    pub ecosystem: String,
+    pub name: String,
+    pub purl: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Event {
+    #[serde(rename = "introduced")]
+    Introduced(String),
+    #[serde(rename = "last_affected")]
+    LastAffected(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Range {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub events: Vec<Event>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Affected {
+    pub package: Package,
+    pub ranges: Vec<Range>,
+    pub versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reference {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+}
+
+/// The cargo-scan data of a finding
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub effect_type: EffectType,
+    pub severity: Severity,
+    pub caller: String,
+    pub callee: String,
+    /// `file:line:col` of the effect
+    pub location: String,
+    pub verdict: SafetyAnnotation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub justification: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OsvEntry {
+    pub schema_version: String,
+    pub id: String,
+    /// RFC 3339 time of the export
+    pub modified: String,
+    pub summary: String,
+    pub details: String,
+    pub affected: Vec<Affected>,
+    pub references: Vec<Reference>,
+    pub database_specific: Finding,
+}
+
+impl OsvEntry {
+    /// The entry for a finding in the crate: the base effect, the location
+    /// it was decided at (which is up the effect's tree for effects marked
+    /// unsafe in a caller), and the verdict
+    pub fn new(
+        crate_id: &CrateId,
+        audit_file: &AuditFile,
+        effect: &EffectInstance,
+        info: &EffectInfo,
+        verdict: SafetyAnnotation,
+        modified: i64,
+    ) -> Self {
+        let name = &crate_id.crate_name;
+        let version = crate_id.version.to_string();
+        let loc = &info.callee_loc;
+        let location = format!(
+            "{}:{}:{}",
+            loc.filepath_string(),
+            loc.start_line(),
+            loc.start_col() + 1
+        );
+        let effect_type = EffectType::from(effect.eff_type());
+        let severity = effect.eff_type().severity();
+        let justification = audit_file.justifications.get(info).cloned();
+
+        let key = format!(
+            "{}@{} {} {} {}",
+            name,
+            version,
+            effect.caller_path(),
+            location,
+            effect.callee_path()
+        );
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        let id = format!("CARGO-SCAN-{}-{}", name, &hash[..16]);
+
+        let summary = match verdict {
+            SafetyAnnotation::Unsafe => {
+                format!("Unsafe {} effect in {} {}", effect_type, name, version)
+            }
+            _ => format!("Unaudited {} effect in {} {}", effect_type, name, version),
+        };
+        let mut details = format!(
+            "cargo-scan found a {} effect ({} severity) calling `{}` in `{}`, at {}.",
+            effect_type,
+            severity,
+            effect.callee_path(),
+            effect.caller_path(),
+            effect.call_loc().to_quickfix()
+        );
+        match verdict {
+            SafetyAnnotation::Unsafe if info.caller_path == *effect.caller() => {
+                details.push_str(" An auditor marked it unsafe.")
+            }
+            SafetyAnnotation::Unsafe => details.push_str(&format!(
+                " An auditor marked its use in `{}` unsafe, at {}.",
+                info.caller_path,
+                loc.to_quickfix()
+            )),
+            _ => details
+                .push_str(&format!(" It is {}.", verdict.to_string().to_lowercase())),
+        }
+        if let Some(j) = &justification {
+            details.push_str(&format!("\n\nJustification: {}", j));
+        }
+
+        let mut references = audit_file
+            .references
+            .get(info)
+            .into_iter()
+            .flatten()
+            .map(|url| Reference { kind: "REPORT".to_string(), url: url.clone() })
+            .collect::<Vec<_>>();
+        references.push(Reference {
+            kind: "PACKAGE".to_string(),
+            url: format!("https://crates.io/crates/{}/{}", name, version),
+        });
+
+        Self {
+            schema_version: SCHEMA_VERSION.to_string(),
+            id,
+            modified: timestamp(modified),
+            summary,
+            details,
+            affected: vec![Affected {
+                package: Package {
+                    ecosystem: ECOSYSTEM.to_string(),
+                    name: name.clone(),
+                    purl: purl(name, &version),
+                },
+                ranges: vec![Range {
+                    kind: "SEMVER".to_string(),
+                    events: vec![
+                        Event::Introduced(version.clone()),
+                        Event::LastAffected(version.clone()),
+                    ],
+                }],
+                versions: vec![version],
+            }],
+            references,
+            database_specific: Finding {
+                effect_type,
+                severity,
+                caller: info.caller_path.to_string(),
+                callee: effect.callee_path().to_string(),
+                location,
+                verdict,
+                justification,
+            },
+        }
+    }
+}
+
+/// The findings of an audit as OSV entries: the effects marked unsafe, and
+/// the unaudited base effects of at least `min_severity`, if given
+pub fn audit_findings(
+    crate_id: &CrateId,
+    audit_file: &AuditFile,
+    min_severity: Option<Severity>,
+    modified: i64,
+) -> Vec<OsvEntry> {
+    let mut entries = audit_file
+        .rejected_effects()
+        .into_iter()
+        .map(|(e, info)| {
+            OsvEntry::new(
+                crate_id,
+                audit_file,
+                e,
+                info,
+                SafetyAnnotation::Unsafe,
+                modified,
+            )
+        })
+        .collect::<Vec<_>>();
+    if let Some(min_severity) = min_severity {
+        for (e, tree) in audit_file.base_effects_by_location() {
+            let Some(annotation) = tree.get_leaf_annotation() else {
+                continue;
+            };
+            if !annotation.is_audited() && e.eff_type().severity() >= min_severity {
+                let info = EffectInfo::from_instance(e);
+                entries.push(OsvEntry::new(
+                    crate_id, audit_file, e, &info, annotation, modified,
+                ));
+            }
+        }
+    }
+    entries
+}
+
+/// The findings of all the crates in the chain, sorted by id
+pub fn from_audit_chain(
+    chain: &mut AuditChain,
+    min_severity: Option<Severity>,
+) -> Result<Vec<OsvEntry>> {
+    let modified = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+    let mut entries = Vec::new();
+    for crate_id in crate_ids {
+        let audit_file = chain
+            .read_audit_file(&crate_id)?
+            .context(format!("Couldn't find audit for {}", crate_id))?;
+        entries.extend(audit_findings(&crate_id, &audit_file, min_severity, modified));
+    }
+    entries.sort_by(|e1, e2| e1.id.cmp(&e2.id));
+    Ok(entries)
+}
+
+/// Save the entries as a JSON list
+pub fn save_to_file(entries: &[OsvEntry], path: &Path) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(entries)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_osv_findings() {
+        let tokens = "fork getuid".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("nix::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees(effects.iter());
+        let fork = EffectInfo::from_instance(&effects[0]);
+        audit_file
+            .audit_trees
+            .get_mut(&effects[0])
+            .unwrap()
+            .set_annotation(SafetyAnnotation::Unsafe);
+        audit_file.justifications.insert(fork.clone(), "forks the process".to_string());
+        audit_file.add_reference(&fork, "https://github.com/org/repo/issues/7").unwrap();
+
+        let crate_id = CrateId::new("nix".to_string(), "0.26.2".parse().unwrap());
+        let entries = audit_findings(&crate_id, &audit_file, None, 86400 + 3661);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert!(entry.id.starts_with("CARGO-SCAN-nix-"));
+        assert_eq!(entry.modified, "1970-01-02T01:01:01Z");
+        assert_eq!(entry.summary, "Unsafe FFICall effect in nix 0.26.2");
+        assert!(entry.details.contains("Justification: forks the process"));
+        assert_eq!(entry.references[0].url, "https://github.com/org/repo/issues/7");
+        assert_eq!(entry.database_specific.callee, "libc::fork");
+
+        // Ids are stable, and unaudited effects are findings above the severity
+        let all = audit_findings(&crate_id, &audit_file, Some(Severity::High), 0);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].id, entry.id);
+        assert_eq!(all[1].database_specific.verdict, SafetyAnnotation::Unreviewed);
+        assert_eq!(
+            audit_findings(&crate_id, &audit_file, Some(Severity::Critical), 0).len(),
+            1
+        );
+
+        let json = serde_json::to_value(entry).unwrap();
+        assert_eq!(json["affected"][0]["package"]["ecosystem"], "crates.io");
+        assert_eq!(json["affected"][0]["ranges"][0]["events"][0]["introduced"], "0.26.2");
+        assert_eq!(
+            json["affected"][0]["ranges"][0]["events"][1]["last_affected"],
+            "0.26.2"
+        );
+        assert_eq!(json["database_specific"]["effect_type"], "FFICall");
+    }
+}
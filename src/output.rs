@@ -0,0 +1,237 @@
+//! Output formats of the scan results (`scan --format`).
+//!
+//! Each format is an `OutputSink`, which writes the results to stdout or to
+//! a file, so one scan can write several formats (e.g. `--format csv
+//! --format sarif=results.sarif`). New formats are added to `OutputFormat`
+//! with a sink, without changes to the scan driver.
+
+use crate::artifact::{EffectContext, ScanArtifact};
+use crate::capability::CapabilitySummary;
+use crate::effect::EffectInstance;
+use crate::heatmap::Heatmap;
+use crate::remediation;
+use crate::sarif::SarifLog;
+use crate::scanner::ScanResults;
+use crate::treemap::Treemap;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The scan results and what the sinks need to know about the scan
+pub struct ScanOutput<'a> {
+    pub results: &'a ScanResults,
+    pub crate_path: &'a Path,
+    pub capabilities: &'a CapabilitySummary,
+}
+
+pub trait OutputSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()>;
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    Quickfix,
+    Text,
+    Sarif,
+    Ndjson,
+    Json,
+    Html,
+}
+
+impl OutputFormat {
+    pub fn sink(self) -> Box<dyn OutputSink> {
+        match self {
+            Self::Csv => Box::new(CsvSink),
+            Self::Quickfix => Box::new(QuickfixSink),
+            Self::Text => Box::new(TextSink),
+            Self::Sarif => Box::new(SarifSink),
+            Self::Ndjson => Box::new(NdjsonSink),
+            Self::Json => Box::new(JsonSink),
+            Self::Html => Box::new(HtmlSink),
+        }
+    }
+
+    /// Whether the output includes the capability summary; for the other
+    /// formats, it is printed to stderr so the output stays machine-readable
+    pub fn shows_capabilities(self) -> bool {
+        matches!(self, Self::Text | Self::Html)
+    }
+}
+
+/// An output format, and the file to write it to (stdout if none), e.g.
+/// `sarif=results.sarif`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSpec {
+    pub format: OutputFormat,
+    pub path: Option<PathBuf>,
+}
+
+impl FromStr for OutputSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (format, path) = match s.split_once('=') {
+            Some((format, path)) => (format, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let format = OutputFormat::from_str(format, true).map_err(|e| anyhow!(e))?;
+        Ok(Self { format, path })
+    }
+}
+
+impl OutputSpec {
+    /// Check that at most one output goes to stdout, and that the streamed
+    /// (ndjson) output does
+    pub fn check_all(specs: &[OutputSpec]) -> Result<()> {
+        if specs.iter().filter(|s| s.path.is_none()).count() > 1 {
+            return Err(anyhow!(
+                "Only one --format can be printed; write the others to files, \
+                e.g. --format sarif=results.sarif"
+            ));
+        }
+        if specs.iter().any(|s| s.format == OutputFormat::Ndjson && s.path.is_some()) {
+            return Err(anyhow!("--format ndjson is streamed to stdout"));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, output: &ScanOutput) -> Result<()> {
+        let sink = self.format.sink();
+        match &self.path {
+            None => sink.write_results(output, &mut io::stdout().lock()),
+            Some(path) => {
+                let file = File::create(path)
+                    .context(format!("Couldn't create output file {:?}", path))?;
+                let mut w = BufWriter::new(file);
+                sink.write_results(output, &mut w)?;
+                Ok(w.flush()?)
+            }
+        }
+    }
+}
+
+pub struct CsvSink;
+
+impl OutputSink for CsvSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "{}", EffectInstance::csv_header())?;
+        for effect in &output.results.effects {
+            writeln!(w, "{}", effect.to_csv())?;
+        }
+        Ok(())
+    }
+}
+
+/// `file:line:col: message` lines for Vim's quickfix list and Emacs'
+/// compilation-mode
+pub struct QuickfixSink;
+
+impl OutputSink for QuickfixSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        for effect in &output.results.effects {
+            writeln!(w, "{}", effect.to_quickfix())?;
+        }
+        Ok(())
+    }
+}
+
+/// The capability summary, then the effects under a header for each crate,
+/// with suggestions of safer alternatives
+pub struct TextSink;
+
+impl OutputSink for TextSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "{}\n", output.capabilities)?;
+        let mut by_crate: BTreeMap<String, Vec<&EffectInstance>> = BTreeMap::new();
+        for e in &output.results.effects {
+            by_crate.entry(e.caller().crate_name().to_string()).or_default().push(e);
+        }
+        for (i, (crate_name, effects)) in by_crate.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            writeln!(w, "== {} ({} effects) ==", crate_name, effects.len())?;
+            for e in effects {
+                writeln!(w, "{}", e.to_text())?;
+                if let Some(hint) = remediation::remediation(e) {
+                    writeln!(w, "    suggestion: {}", hint)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct SarifSink;
+
+impl OutputSink for SarifSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        let log = SarifLog::from_effects(&output.results.effects, output.crate_path);
+        writeln!(w, "{}", log.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// The effects are streamed as they are found (see `scanner::ndjson_line`),
+/// so there is nothing left to write at the end of the scan
+pub struct NdjsonSink;
+
+impl OutputSink for NdjsonSink {
+    fn write_results(&self, _output: &ScanOutput, _w: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The results as a scan artifact (see `artifact`), with source snippets
+pub struct JsonSink;
+
+impl OutputSink for JsonSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        let artifact = ScanArtifact::from_results(output.results, EffectContext::Lines);
+        serde_json::to_writer_pretty(&mut *w, &artifact)?;
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+/// The HTML heatmap, with the capability summary and a treemap of the effects
+pub struct HtmlSink;
+
+impl OutputSink for HtmlSink {
+    fn write_results(&self, output: &ScanOutput, w: &mut dyn Write) -> Result<()> {
+        let effects = &output.results.effects;
+        let mut heatmap = Heatmap::new(effects, output.crate_path);
+        heatmap.summary = Some(output.capabilities.to_string());
+        heatmap.treemap = Some(Treemap::new(effects).to_svg(960.0, 600.0));
+        write!(w, "{}", heatmap.to_html())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_spec() {
+        let spec = "sarif=out/results.sarif".parse::<OutputSpec>().unwrap();
+        assert_eq!(spec.format, OutputFormat::Sarif);
+        assert_eq!(spec.path, Some(PathBuf::from("out/results.sarif")));
+        let spec = "CSV".parse::<OutputSpec>().unwrap();
+        assert_eq!(spec, OutputSpec { format: OutputFormat::Csv, path: None });
+        assert!("yaml".parse::<OutputSpec>().is_err());
+
+        let csv = OutputSpec { format: OutputFormat::Csv, path: None };
+        let json = OutputSpec { format: OutputFormat::Json, path: None };
+        assert!(OutputSpec::check_all(&[csv.clone()]).is_ok());
+        assert!(OutputSpec::check_all(&[csv, json]).is_err());
+        let ndjson = "ndjson=effects.jsonl".parse::<OutputSpec>().unwrap();
+        assert!(OutputSpec::check_all(&[ndjson]).is_err());
+    }
+}
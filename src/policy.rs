@@ -0,0 +1,229 @@
+//! What-if simulation of policy changes.
+//!
+//! A policy file adds sink patterns and defines custom effect kinds (in the
+//! same format as `--custom-effects`, see `taxonomy`), e.g.
+//! ```toml
+//! sinks = ["reqwest", "!std::env::var"]
+//!
+//! [[effect]]
+//! kind = "Crypto"
+//! patterns = ["ring", "openssl"]
+//! severity = "High"
+//! ```
+//! Simulating a policy re-evaluates the effects stored in a scan artifact
+//! (such as the baseline saved by `init`) as if they had been scanned with the
+//! policy, without re-scanning the crate, and reports the effects whose
+//! classification or severity would change. The custom effect kinds in the
+//! policy replace the ones the artifact was scanned with; its sinks are added
+//! to the ones of the scan (the built-in ones, the project's, and those of
+//! build-time code if scanned with `build_time`). A sink pattern
+//! starting with `!` excludes the callees it matches from the sinks, so that
+//! e.g. `!std::env::var` stops reporting calls to `std::env::var`.
+//!
+//! Only effects already in the artifact can be reclassified: calls which
+//! weren't effects when the crate was scanned aren't stored, so new sink
+//! patterns only pick up calls which were effects of another kind (e.g.
+//! unsafe calls).
+
+use crate::artifact::ScanArtifact;
+use crate::effect::{Effect, EffectInstance, EffectType, Severity};
+use crate::ident::{CanonicalPath, PatternSet};
+use crate::scanner::{self, ScanConfig};
+use crate::sink::Sink;
+use crate::taxonomy::CustomEffects;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Policy {
+    /// Sink patterns in addition to the built-in ones, and exclusions from
+    /// all of them (`!<pattern>`)
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    #[serde(flatten)]
+    pub custom: CustomEffects,
+}
+
+impl Policy {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let policy: Self = toml::from_str(s)?;
+        policy.custom.validate()?;
+        if policy.sinks.iter().any(|s| PatternSet::negated(s).unwrap_or(s).is_empty()) {
+            return Err(anyhow!("Sink patterns can't be empty"));
+        }
+        Ok(policy)
+    }
+
+    pub fn read_from_file(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .context(format!("Couldn't read policy from {:?}", path))?;
+        Self::from_toml(&s)
+    }
+
+    /// The sinks of a scan of the crate with the configuration (see
+    /// `scanner::scan_sinks`) and this policy. The policy's custom effect
+    /// kinds replace those of the configuration.
+    fn sink_patterns(
+        &self,
+        crate_path: &Path,
+        config: &ScanConfig,
+    ) -> Result<PatternSet> {
+        let config = ScanConfig { custom_effects: None, ..config.clone() };
+        let mut sinks = scanner::scan_sinks(crate_path, HashSet::new(), &config)?;
+        sinks.extend(&PatternSet::from_strs(self.sinks.iter().map(String::as_str)));
+        sinks.extend(&self.custom.sink_patterns());
+        Ok(sinks)
+    }
+
+    /// Classify an effect as if it had been found with this policy. Returns
+    /// None if the call wouldn't be an effect at all.
+    fn reclassify(
+        &self,
+        effect: &Effect,
+        callee: &CanonicalPath,
+        sinks: &PatternSet,
+    ) -> Option<Effect> {
+        // Mirrors the classification of call sites in the scanner: FFI calls
+        // take precedence over sinks, and sinks over the other call effects
+        let sink_call = || {
+            let sink = Sink::new_match(callee, sinks)?;
+            Some(match self.custom.classify(callee) {
+                Some(custom) => Effect::Custom(custom),
+                None => Effect::SinkCall(sink),
+            })
+        };
+        match effect {
+            Effect::SinkCall(_) | Effect::Custom(_) => sink_call(),
+            Effect::UninitMemory(_) | Effect::AliasEscape(_) | Effect::UnsafeCall(_) => {
+                sink_call().or_else(|| Some(effect.clone()))
+            }
+            _ => Some(effect.clone()),
+        }
+    }
+}
+
+/// An effect whose classification would change under the simulated policy
+#[derive(Debug, Clone)]
+pub struct Reclassification {
+    pub effect: EffectInstance,
+    /// None if the call would no longer be an effect
+    pub new_effect: Option<Effect>,
+}
+
+impl Reclassification {
+    pub fn old_severity(&self) -> Severity {
+        self.effect.eff_type().severity()
+    }
+
+    pub fn new_severity(&self) -> Option<Severity> {
+        self.new_effect.as_ref().map(|e| e.severity())
+    }
+}
+
+fn effect_str(e: &Effect) -> String {
+    match e {
+        Effect::SinkCall(s) => format!("SinkCall({})", s),
+        Effect::Custom(c) => format!("Custom({})", c.kind),
+        e => EffectType::from(e).to_string(),
+    }
+}
+
+impl fmt::Display for Reclassification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let old = self.effect.eff_type();
+        let new = match &self.new_effect {
+            Some(e) => format!("{} [{}]", effect_str(e), e.severity()),
+            None => "not an effect".to_string(),
+        };
+        write!(
+            f,
+            "{} -> {} ({}): {} [{}] => {}",
+            self.effect.caller(),
+            self.effect.callee(),
+            self.effect.call_loc(),
+            effect_str(old),
+            old.severity(),
+            new
+        )
+    }
+}
+
+/// Re-evaluate the effects in the artifact of the crate, scanned with the
+/// configuration, against the policy, returning the effects whose
+/// classification would change
+pub fn simulate(
+    artifact: &ScanArtifact,
+    policy: &Policy,
+    crate_path: &Path,
+    config: &ScanConfig,
+) -> Result<Vec<Reclassification>> {
+    let sinks = policy.sink_patterns(crate_path, config)?;
+    Ok(artifact
+        .effects
+        .iter()
+        .filter_map(|record| {
+            let effect = &record.effect;
+            let new_effect =
+                policy.reclassify(effect.eff_type(), effect.callee(), &sinks);
+            (new_effect.as_ref() != Some(effect.eff_type()))
+                .then(|| Reclassification { effect: effect.clone(), new_effect })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reclassify() {
+        let policy = Policy::from_toml(
+            r#"
+            sinks = ["mylib::danger", "!std::fs::metadata"]
+
+            [[effect]]
+            kind = "Crypto"
+            patterns = ["ring"]
+            severity = "High"
+            "#,
+        )
+        .unwrap();
+        let config = ScanConfig {
+            project_sinks: PatternSet::from_strs(["mylib::net"]),
+            ..Default::default()
+        };
+        let sinks = policy.sink_patterns(Path::new("."), &config).unwrap();
+
+        // An unsafe call matching a new sink becomes a sink call
+        let callee = CanonicalPath::new("mylib::danger::run");
+        let new = policy.reclassify(&Effect::UnsafeCall(callee.clone()), &callee, &sinks);
+        assert_eq!(new.map(|e| EffectType::from(&e)), Some(EffectType::SinkCall));
+
+        // So does one matching a sink of the project
+        let callee = CanonicalPath::new("mylib::net::connect");
+        let new = policy.reclassify(&Effect::UnsafeCall(callee.clone()), &callee, &sinks);
+        assert_eq!(new.map(|e| EffectType::from(&e)), Some(EffectType::SinkCall));
+
+        // A custom effect which isn't in the policy anymore isn't an effect
+        let callee = CanonicalPath::new("openssl::encrypt");
+        let old = Effect::Custom(crate::effect::CustomEffect {
+            kind: "Crypto".to_string(),
+            severity: Severity::High,
+        });
+        assert_eq!(policy.reclassify(&old, &callee, &sinks), None);
+
+        // Unaffected effects stay the same
+        let callee = CanonicalPath::new("std::fs::read");
+        let sink = Sink::new_match(&callee, &sinks).unwrap();
+        let old = Effect::SinkCall(sink);
+        assert_eq!(policy.reclassify(&old, &callee, &sinks), Some(old.clone()));
+
+        // An excluded sink call isn't an effect anymore
+        let callee = CanonicalPath::new("std::fs::metadata");
+        assert_eq!(policy.reclassify(&old, &callee, &sinks), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! Project configuration, created by the `init` binary.
+//!
+//! The config lives in `cargo-scan.toml` at the root of the crate and records
+//! the choices made at setup, e.g.
+//! ```toml
+//! audit_dir = ".cargo-scan"
+//! effect_types = ["SinkCall", "FFICall", "UnsafeCall"]
+//! severity_threshold = "High"
+//! min_audit_coverage = 80.0
+//! ci = true
+//! effect_identity = "path"
+//! exclude = ["src/generated/"]
+//!
+//! [keys]
+//! preset = "vim"
+//! ```
+//! The audit directory holds the audit chain (`chain.manifest`, with the
+//! default audits in `audits/` and the downloaded dependencies in `crates/`),
+//! the baseline scan of the crate (`baseline.json`), and the project's sink
+//! patterns (`sinks.toml`, edited with `policy sinks`). The `keys` table
+//! configures the keys of the audit prompts (see `auditing::keys`).
+
+use crate::auditing::check::CoverageRequirements;
+use crate::auditing::keys::KeyBindings;
+use crate::effect::{EffectType, Severity};
+use crate::effect_identity::EffectIdentity;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_CONFIG_FILE: &str = "cargo-scan.toml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectConfig {
+    /// Relative to the crate root
+    pub audit_dir: PathBuf,
+    pub effect_types: Vec<EffectType>,
+    /// Effects of this severity or higher must be fully audited
+    pub severity_threshold: Severity,
+    /// Required percentage of audited locations in each crate, from 0 to 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_audit_coverage: Option<f64>,
+    /// Running in CI: tools should fail rather than prompt
+    #[serde(default)]
+    pub ci: bool,
+    /// TOML file defining additional effect kinds (see `taxonomy`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_effects: Option<PathBuf>,
+    /// When two effects are the same, e.g. an effect of the baseline and of
+    /// a new scan (see `effect_identity`)
+    #[serde(default, skip_serializing_if = "EffectIdentity::is_default")]
+    pub effect_identity: EffectIdentity,
+    /// Files and directories to leave out of scans, in gitignore syntax;
+    /// merged with the crate's `.scanignore` (see `scan_ignore`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Keys of the interactive audit prompts
+    #[serde(default, skip_serializing_if = "KeyBindings::is_default")]
+    pub keys: KeyBindings,
+}
+
+impl ProjectConfig {
+    pub fn config_path(crate_path: &Path) -> PathBuf {
+        crate_path.join(PROJECT_CONFIG_FILE)
+    }
+
+    pub fn read(crate_path: &Path) -> Result<Option<Self>> {
+        let path = Self::config_path(crate_path);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let toml_string = std::fs::read_to_string(&path)?;
+        let config: Self = toml::from_str(&toml_string)
+            .map_err(|e| anyhow!("Invalid project config {:?}: {}", path, e))?;
+        config
+            .validate()
+            .map_err(|e| anyhow!("Invalid project config {:?}: {}", path, e))?;
+        Ok(Some(config))
+    }
+
+    /// Check the settings which the TOML types don't constrain
+    pub fn validate(&self) -> Result<()> {
+        if let Some(coverage) = self.min_audit_coverage {
+            if !(0.0..=100.0).contains(&coverage) {
+                return Err(anyhow!(
+                    "min_audit_coverage must be a percentage from 0 to 100, not {}",
+                    coverage
+                ));
+            }
+        }
+        self.keys.validate()
+    }
+
+    pub fn save(&self, crate_path: &Path) -> Result<()> {
+        std::fs::write(Self::config_path(crate_path), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn manifest_path(&self, crate_path: &Path) -> PathBuf {
+        crate_path.join(&self.audit_dir).join("chain.manifest")
+    }
+
+    pub fn audits_path(&self, crate_path: &Path) -> PathBuf {
+        crate_path.join(&self.audit_dir).join("audits")
+    }
+
+    pub fn crates_path(&self, crate_path: &Path) -> PathBuf {
+        crate_path.join(&self.audit_dir).join("crates")
+    }
+
+    pub fn baseline_path(&self, crate_path: &Path) -> PathBuf {
+        crate_path.join(&self.audit_dir).join("baseline.json")
+    }
+
+    /// The project's sink patterns, in addition to the built-in ones (see
+    /// `sink::SinkFile`)
+    pub fn sinks_path(&self, crate_path: &Path) -> PathBuf {
+        crate_path.join(&self.audit_dir).join("sinks.toml")
+    }
+
+    /// The coverage `chain check` should require: full coverage of the
+    /// effects at or above the severity threshold, and the minimum coverage
+    /// overall if there is one
+    pub fn coverage_requirements(&self) -> CoverageRequirements {
+        let severity =
+            [Severity::Low, Severity::Medium, Severity::High, Severity::Critical]
+                .into_iter()
+                .filter(|s| *s >= self.severity_threshold)
+                .map(|s| (s, 100.0))
+                .collect();
+        CoverageRequirements {
+            min_audit_coverage: self.min_audit_coverage,
+            crates: Default::default(),
+            severity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = ProjectConfig {
+            audit_dir: PathBuf::from(".cargo-scan"),
+            effect_types: vec![EffectType::FFICall, EffectType::UnsafeCall],
+            severity_threshold: Severity::High,
+            min_audit_coverage: Some(80.0),
+            ci: true,
+            custom_effects: None,
+            effect_identity: EffectIdentity::Path,
+            exclude: vec!["src/generated/".to_string()],
+            keys: toml::from_str("preset = \"vim\"").unwrap(),
+        };
+        let s = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<ProjectConfig>(&s).unwrap(), config);
+
+        let coverage = config.coverage_requirements();
+        assert_eq!(
+            coverage.severity.keys().collect::<Vec<_>>(),
+            [&Severity::High, &Severity::Critical]
+        );
+        assert_eq!(coverage.min_audit_coverage, Some(80.0));
+    }
+
+    #[test]
+    fn test_validate_coverage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let write = |coverage: &str| {
+            let config = format!(
+                "audit_dir = \".cargo-scan\"\neffect_types = []\n\
+                severity_threshold = \"High\"\nmin_audit_coverage = {}\n",
+                coverage
+            );
+            std::fs::write(ProjectConfig::config_path(tmp.path()), config).unwrap();
+        };
+        write("100");
+        assert!(ProjectConfig::read(tmp.path()).unwrap().is_some());
+        for invalid in ["150", "-1", "nan"] {
+            write(invalid);
+            assert!(ProjectConfig::read(tmp.path()).is_err());
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Quick estimates of the effects of a crate and each of its dependencies,
+//! for deciding where a full scan is worth the time (`scan --quick`).
+//!
+//! Instead of resolving names and building the call graph, each source file a
+//! scan would read (see `scanner::crate_sources`) is only tokenized, counting
+//! - `unsafe` keywords (blocks, functions, impls, and traits);
+//! - `extern` blocks, whose functions are called through FFI;
+//! - paths starting with a sink pattern (e.g. `std::fs::read`), including the
+//!   project's sinks and those of custom effect kinds (see `sink`).
+//!
+//! The estimate of a crate is the sum of these counts. It is only a rough
+//! guide: calls through imported names (`use std::fs; fs::read(..)`) aren't
+//! counted, nor are the effects a crate gets from its dependencies, and an
+//! `unsafe` block may contain any number of effects, or none.
+
+use crate::dep_cache::DepGraph;
+use crate::ident::PatternSet;
+use crate::scanner::{self, ScanConfig};
+use crate::sink;
+use crate::util::{self, CrateId};
+
+use anyhow::Result;
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Token counts of a crate's sources
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenCounts {
+    pub unsafe_keywords: usize,
+    pub extern_blocks: usize,
+    pub sink_paths: usize,
+    /// Source files which couldn't be tokenized, and aren't counted
+    pub skipped_files: usize,
+}
+
+impl TokenCounts {
+    /// The estimated number of effects
+    pub fn estimate(&self) -> usize {
+        self.unsafe_keywords + self.extern_blocks + self.sink_paths
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.unsafe_keywords += other.unsafe_keywords;
+        self.extern_blocks += other.extern_blocks;
+        self.sink_paths += other.sink_paths;
+        self.skipped_files += other.skipped_files;
+    }
+
+    fn count_tokens(&mut self, tokens: TokenStream, sinks: &[Vec<String>]) {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                TokenTree::Ident(id) if id == "unsafe" => self.unsafe_keywords += 1,
+                TokenTree::Ident(id) if id == "extern" => {
+                    // extern "C" { .. } or extern { .. }, but not extern fn
+                    // or extern crate
+                    let next = match tokens.get(i + 1) {
+                        Some(TokenTree::Literal(_)) => tokens.get(i + 2),
+                        next => next,
+                    };
+                    if matches!(next, Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace)
+                    {
+                        self.extern_blocks += 1;
+                    }
+                }
+                TokenTree::Ident(_) => {
+                    let (path, len) = path_at(&tokens[i..]);
+                    if sinks.iter().any(|s| path.starts_with(s)) {
+                        self.sink_paths += 1;
+                    }
+                    i += len;
+                    continue;
+                }
+                TokenTree::Group(g) => self.count_tokens(g.stream(), sinks),
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+}
+
+/// The path starting at the tokens (e.g. `std :: fs :: read`), as its
+/// segments, and the number of tokens it spans
+fn path_at(tokens: &[TokenTree]) -> (Vec<String>, usize) {
+    let mut path = Vec::new();
+    let mut i = 0;
+    while let Some(TokenTree::Ident(id)) = tokens.get(i) {
+        path.push(id.to_string());
+        i += 1;
+        match (tokens.get(i), tokens.get(i + 1)) {
+            (Some(TokenTree::Punct(p1)), Some(TokenTree::Punct(p2)))
+                if p1.as_char() == ':' && p2.as_char() == ':' =>
+            {
+                i += 2
+            }
+            _ => break,
+        }
+    }
+    (path, i.max(1))
+}
+
+/// Count the tokens of a source file. Returns None if it doesn't tokenize.
+pub fn count_source(src: &str, sinks: &[Vec<String>]) -> Option<TokenCounts> {
+    let tokens = src.parse::<TokenStream>().ok()?;
+    let mut counts = TokenCounts::default();
+    counts.count_tokens(tokens, sinks);
+    Some(counts)
+}
+
+/// The sink patterns of the crate's project, as path segments
+fn sink_paths(crate_path: &Path, config: &ScanConfig) -> Result<Vec<Vec<String>>> {
+    // Exclusions aren't counted: they only make the estimate a bit high
+    let mut sinks = sink::project_inventory(crate_path, config.custom_effects.as_ref())?
+        .into_iter()
+        .filter(|(p, _)| PatternSet::negated(p).is_none())
+        .map(|(p, _)| p.replace('-', "_").split("::").map(String::from).collect())
+        .collect::<Vec<_>>();
+    sinks.dedup();
+    Ok(sinks)
+}
+
+/// Count the tokens of the source files a scan of the crate would read (see
+/// `scanner::crate_sources`)
+pub fn count_crate(
+    crate_path: &Path,
+    sinks: &[Vec<String>],
+    config: &ScanConfig,
+) -> Result<TokenCounts> {
+    let mut counts = TokenCounts::default();
+    for file in scanner::crate_sources(crate_path, config)?.files {
+        let file_counts = util::fs::read_source(&file)
+            .ok()
+            .and_then(|src| count_source(&src.text, sinks))
+            .unwrap_or(TokenCounts { skipped_files: 1, ..Default::default() });
+        counts.add(&file_counts);
+    }
+    Ok(counts)
+}
+
+/// The estimate for a crate of the dependency tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickEstimate {
+    pub crate_id: CrateId,
+    pub path: PathBuf,
+    /// Whether this is the scanned crate rather than a dependency
+    pub is_root: bool,
+    pub counts: TokenCounts,
+}
+
+impl fmt::Display for QuickEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = &self.counts;
+        write!(
+            f,
+            "{} v{}: ~{} effects (estimate: {} unsafe, {} extern blocks, {} sink paths)",
+            self.crate_id.crate_name,
+            self.crate_id.version,
+            c.estimate(),
+            c.unsafe_keywords,
+            c.extern_blocks,
+            c.sink_paths
+        )?;
+        if self.is_root {
+            write!(f, " [this crate]")?;
+        }
+        if c.skipped_files > 0 {
+            write!(f, " [{} files not tokenized]", c.skipped_files)?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimate the effects of the crate and each of its dependencies, most
+/// effects first, counting the sinks and files a scan with the configuration
+/// would
+pub fn estimate_dependencies(
+    crate_path: &Path,
+    config: &ScanConfig,
+) -> Result<Vec<QuickEstimate>> {
+    let sinks = sink_paths(crate_path, config)?;
+    let graph = DepGraph::load(crate_path)?;
+    let mut estimates = graph
+        .reachable()
+        .into_iter()
+        .map(|c| {
+            Ok(QuickEstimate {
+                crate_id: c.crate_id.clone(),
+                path: c.path.clone(),
+                is_root: c.crate_id == graph.root,
+                counts: count_crate(&c.path, &sinks, config)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    estimates.sort_by(|e1, e2| {
+        e2.counts
+            .estimate()
+            .cmp(&e1.counts.estimate())
+            .then_with(|| e1.crate_id.crate_name.cmp(&e2.crate_id.crate_name))
+    });
+    Ok(estimates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_source() {
+        let sinks =
+            vec![vec!["std".to_string(), "fs".to_string()], vec!["libc".to_string()]];
+        let src = r#"
+            extern crate libc;
+            extern "C" {
+                fn abs(x: i32) -> i32;
+            }
+            extern "C" fn callback() {}
+            pub fn f() -> i32 {
+                // unsafe in a comment, and std::fs::read in a "string"
+                let _ = std::fs::read("unsafe std::fs");
+                let _ = std::fsx::read("x");
+                unsafe { abs(libc::getuid() as i32) }
+            }
+            unsafe impl Send for S {}
+        "#;
+        let counts = count_source(src, &sinks).unwrap();
+        assert_eq!(counts.unsafe_keywords, 2);
+        assert_eq!(counts.extern_blocks, 1);
+        // `extern crate libc` is a path starting with libc, too
+        assert_eq!(counts.sink_paths, 3);
+        assert_eq!(counts.estimate(), 6);
+        assert!(count_source("fn f() { (", &sinks).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! Ratchet files: upper bounds on the number of effects of each kind, which
+//! only ever go down.
+//!
+//! `scan --ratchet` compares the effect counts of a scan against the bounds
+//! recorded in the ratchet file, e.g.
+//! ```toml
+//! [bounds]
+//! FFICall = 3
+//! UnsafeCall = 12
+//! ```
+//! The scan fails if any count went over its bound (kinds which aren't in the
+//! file have a bound of 0). Otherwise, bounds are tightened to the new counts,
+//! so a project can drive its unsafe usage down monotonically. Only the
+//! bounds of the effect kinds the scan looked for are tightened: a scan with
+//! `--effect-types ffi` leaves the other bounds alone. If the file doesn't
+//! exist yet, it is created with the current counts.
+
+use crate::effect::{EffectInstance, EffectType};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Ratchet {
+    pub bounds: BTreeMap<String, usize>,
+}
+
+/// A change in the effect count of a kind, relative to its bound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundChange {
+    pub kind: String,
+    pub bound: usize,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RatchetOutcome {
+    /// Kinds whose counts went over their bounds
+    pub increased: Vec<BoundChange>,
+    /// Kinds whose bounds were tightened
+    pub tightened: Vec<BoundChange>,
+}
+
+impl RatchetOutcome {
+    pub fn passed(&self) -> bool {
+        self.increased.is_empty()
+    }
+}
+
+/// Number of effects of each kind
+pub fn effect_counts<'a, I>(effects: I) -> BTreeMap<String, usize>
+where
+    I: IntoIterator<Item = &'a EffectInstance>,
+{
+    let mut counts = BTreeMap::new();
+    for e in effects {
+        *counts.entry(EffectType::from(e.eff_type()).to_string()).or_default() += 1;
+    }
+    counts
+}
+
+impl Ratchet {
+    pub fn from_counts(counts: BTreeMap<String, usize>) -> Self {
+        Self { bounds: counts }
+    }
+
+    pub fn read_from_file(path: &Path) -> Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let s = std::fs::read_to_string(path)?;
+        let ratchet =
+            toml::from_str(&s).context(format!("Invalid ratchet file {:?}", path))?;
+        Ok(Some(ratchet))
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Check the counts of a scan for the effect types against the bounds. If
+    /// none of them increased, the bounds of the scanned kinds are tightened
+    /// to the counts.
+    pub fn check(
+        &mut self,
+        counts: &BTreeMap<String, usize>,
+        scanned: &[EffectType],
+    ) -> RatchetOutcome {
+        let scanned = scanned.iter().map(|t| t.to_string()).collect::<BTreeSet<_>>();
+        let mut outcome = RatchetOutcome::default();
+        for (kind, &count) in counts {
+            let bound = self.bounds.get(kind).copied().unwrap_or(0);
+            if count > bound {
+                outcome.increased.push(BoundChange { kind: kind.clone(), bound, count });
+            }
+        }
+        for (kind, &bound) in &self.bounds {
+            if !scanned.contains(kind) && !counts.contains_key(kind) {
+                continue;
+            }
+            let count = counts.get(kind).copied().unwrap_or(0);
+            if count < bound {
+                outcome.tightened.push(BoundChange { kind: kind.clone(), bound, count });
+            }
+        }
+        if outcome.passed() {
+            for change in &outcome.tightened {
+                self.bounds.insert(change.kind.clone(), change.count);
+            }
+        } else {
+            outcome.tightened.clear();
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(cs: &[(&str, usize)]) -> BTreeMap<String, usize> {
+        cs.iter().map(|(k, n)| (k.to_string(), *n)).collect()
+    }
+
+    #[test]
+    fn test_ratchet() {
+        let mut ratchet =
+            Ratchet::from_counts(counts(&[("FFICall", 3), ("UnsafeCall", 5)]));
+        let all = [EffectType::FFICall, EffectType::UnsafeCall, EffectType::SinkCall];
+
+        // Fewer unsafe calls: the bound is tightened
+        let outcome = ratchet.check(&counts(&[("FFICall", 3), ("UnsafeCall", 4)]), &all);
+        assert!(outcome.passed());
+        assert_eq!(
+            outcome.tightened,
+            [BoundChange { kind: "UnsafeCall".to_string(), bound: 5, count: 4 }]
+        );
+        assert_eq!(ratchet.bounds["UnsafeCall"], 4);
+
+        // A new kind of effect fails, and nothing is tightened
+        let outcome = ratchet.check(&counts(&[("FFICall", 2), ("SinkCall", 1)]), &all);
+        assert!(!outcome.passed());
+        assert_eq!(outcome.increased[0].kind, "SinkCall");
+        assert!(outcome.tightened.is_empty());
+        assert_eq!(ratchet.bounds["FFICall"], 3);
+
+        // A scan for FFI calls only leaves the bound of unsafe calls alone
+        let outcome = ratchet.check(&counts(&[("FFICall", 2)]), &[EffectType::FFICall]);
+        assert!(outcome.passed());
+        assert_eq!(
+            outcome.tightened,
+            [BoundChange { kind: "FFICall".to_string(), bound: 3, count: 2 }]
+        );
+        assert_eq!(ratchet.bounds["UnsafeCall"], 4);
+    }
+}
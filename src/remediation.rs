@@ -0,0 +1,130 @@
+//! Remediation hints for common effects: safer alternatives a reviewer can
+//! suggest upstream (or use instead) when auditing a call.
+//!
+//! Hints are keyed by callee pattern: the last segments of the callee path,
+//! so `str::from_utf8_unchecked` matches both `core::str::from_utf8_unchecked`
+//! and `std::str::from_utf8_unchecked`, and `get_unchecked` matches the method
+//! on any type.
+
+use crate::effect::EffectInstance;
+
+/// Callee patterns and their hints, sorted by pattern
+const REMEDIATIONS: &[(&str, &str)] = &[
+    (
+        "Command::new",
+        "pass arguments with `.arg()` rather than through a shell (`sh -c`), and \
+         avoid user-controlled program paths",
+    ),
+    (
+        "String::from_utf8_unchecked",
+        "prefer `OsString` APIs, or the checked `String::from_utf8`, for data \
+         which may not be UTF-8",
+    ),
+    (
+        "Vec::set_len",
+        "prefer `resize`, `extend`, or writing through `spare_capacity_mut` over \
+         `set_len` on elements which may be uninitialized",
+    ),
+    (
+        "env::set_var",
+        "setting environment variables is unsound in multithreaded programs; pass \
+         the configuration explicitly instead",
+    ),
+    (
+        "fs::remove_dir_all",
+        "check the path is inside the expected directory before removing it \
+         recursively",
+    ),
+    (
+        "get_unchecked",
+        "prefer indexing or `get` unless profiling shows the bounds check matters",
+    ),
+    (
+        "get_unchecked_mut",
+        "prefer indexing or `get_mut` unless profiling shows the bounds check matters",
+    ),
+    (
+        "hint::unreachable_unchecked",
+        "prefer `unreachable!()` unless profiling shows the check matters",
+    ),
+    (
+        "libc::system",
+        "use `std::process::Command` with separate arguments instead of a shell \
+         command line",
+    ),
+    (
+        "mem::transmute",
+        "prefer a checked conversion (`from_ne_bytes`, `bytemuck::cast`, `as` for \
+         numbers, `ptr::cast` for pointers) over `transmute`",
+    ),
+    (
+        "mem::uninitialized",
+        "use `MaybeUninit` instead of the deprecated `mem::uninitialized`",
+    ),
+    (
+        "mem::zeroed",
+        "use `Default`, or `MaybeUninit::zeroed` if all-zero bytes must be checked \
+         to be a valid value",
+    ),
+    (
+        "ptr::copy_nonoverlapping",
+        "prefer `copy_from_slice`, which checks the lengths and overlap",
+    ),
+    (
+        "ptr::write_bytes",
+        "to clear secrets, consider `zeroize` instead of a manual memset via \
+         `ptr::write_bytes`, which the compiler may optimize away",
+    ),
+    (
+        "slice::from_raw_parts",
+        "if the pointer comes from a slice or Vec, keep the original slice (or use \
+         `split_at`/`chunks`) instead of rebuilding it with `from_raw_parts`",
+    ),
+    (
+        "slice::from_raw_parts_mut",
+        "if the pointer comes from a slice or Vec, keep the original slice (or use \
+         `split_at_mut`/`chunks_mut`) instead of rebuilding it",
+    ),
+    (
+        "str::from_utf8_unchecked",
+        "prefer `OsStr` APIs over `from_utf8_unchecked`, or the checked \
+         `str::from_utf8`, for data which may not be UTF-8",
+    ),
+];
+
+/// Whether the last segments of the callee are the pattern's segments
+fn matches(callee: &str, pattern: &str) -> bool {
+    let mut callee = callee.rsplit("::");
+    pattern.rsplit("::").all(|p| callee.next() == Some(p))
+}
+
+/// The remediation hint for calls to the callee, if there is one
+pub fn remediation_for(callee: &str) -> Option<&'static str> {
+    REMEDIATIONS.iter().find(|(p, _)| matches(callee, p)).map(|(_, hint)| *hint)
+}
+
+/// The remediation hint for the effect, if there is one
+pub fn remediation(effect: &EffectInstance) -> Option<&'static str> {
+    remediation_for(effect.callee_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remediation_for() {
+        let hint = remediation_for("core::ptr::write_bytes").unwrap();
+        assert!(hint.contains("zeroize"));
+        let hint = remediation_for("std::str::from_utf8_unchecked").unwrap();
+        assert!(hint.contains("OsStr"));
+        assert!(remediation_for("core::slice::<[T]>::get_unchecked").is_some());
+        assert!(remediation_for("my_crate::write_bytes").is_none());
+        assert!(remediation_for("libc::getuid").is_none());
+    }
+
+    #[test]
+    fn test_remediations_sorted() {
+        assert!(REMEDIATIONS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
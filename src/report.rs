@@ -0,0 +1,366 @@
+//! Aggregate summary reports for dashboards.
+//!
+//! A report summarizes the audits in one or more audit chains (typically one
+//! per repository): per-crate effect and unaudited counts, a risk score, and
+//! the most dangerous dependencies of each repository. Reports from many
+//! repositories can be merged into a single dataset. The risk score only
+//! weighs the effects in code dependents run: effects in examples and
+//! doc-tests are counted, but don't add to it.
+//!
+//! The top callees report lists the callees flagged most often across the
+//! crates of a chain, i.e. the callee-level decisions (and default sink
+//! policies) which cover the most effects.
+
+use crate::audit_chain::AuditChain;
+use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+use crate::effect::{EffectType, Provenance};
+use crate::name_check::{self, NameWarning};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Number of dependencies listed in `top_dangerous_deps`
+const TOP_DEPS: usize = 5;
+
+/// Number of callees listed in the top callees report by default
+pub const TOP_CALLEES: usize = 20;
+
+/// Summary of a single crate's audit
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CrateSummary {
+    pub crate_id: String,
+    pub total_effects: usize,
+    pub unaudited_effects: usize,
+    pub unsafe_effects: usize,
+    pub pub_caller_checked: usize,
+    pub effects_by_type: BTreeMap<String, usize>,
+    pub risk_score: u64,
+}
+
+/// Summary of all crates audited for a single repository
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RepoSummary {
+    pub repo: String,
+    pub total_effects: usize,
+    pub unaudited_effects: usize,
+    pub risk_score: u64,
+    pub top_dangerous_deps: Vec<String>,
+    /// Possible typosquats and dependency confusions among the dependencies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_warnings: Vec<NameWarning>,
+    pub crates: Vec<CrateSummary>,
+}
+
+/// A dataset of repository summaries
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Report {
+    pub repos: Vec<RepoSummary>,
+}
+
+/// Returns the number of unaudited and unsafe leaves in the tree
+pub(crate) fn count_leaves(t: &EffectTree) -> (usize, usize) {
+    match t {
+        EffectTree::Leaf(_, SafetyAnnotation::Unsafe) => (0, 1),
+        EffectTree::Leaf(_, a) if !a.is_audited() => (1, 0),
+        EffectTree::Leaf(_, _) => (0, 0),
+        EffectTree::Branch(_, ts) => ts
+            .iter()
+            .map(count_leaves)
+            .fold((0, 0), |(skipped, unsafe_), (s, u)| (skipped + s, unsafe_ + u)),
+    }
+}
+
+impl CrateSummary {
+    pub fn from_audit_file(crate_id: String, audit_file: &AuditFile) -> Self {
+        let mut unaudited_effects = 0;
+        let mut unsafe_effects = 0;
+        let mut risk_score = 0;
+        let mut effects_by_type = BTreeMap::new();
+        for (e, t) in audit_file.audit_trees.iter() {
+            *effects_by_type
+                .entry(EffectType::from(e.eff_type()).to_string())
+                .or_insert(0) += 1;
+            let (skipped, unsafe_) = count_leaves(t);
+            if skipped > 0 {
+                unaudited_effects += 1;
+            }
+            if unsafe_ > 0 {
+                unsafe_effects += 1;
+            }
+            // Effects in examples and doc-tests are audited, but dependents
+            // don't run them
+            if matches!(e.provenance(), Provenance::Example | Provenance::DocTest) {
+                continue;
+            }
+            // Unsafe effects count double, since they have been confirmed
+            risk_score +=
+                e.eff_type().severity().weight() * (skipped + 2 * unsafe_) as u64;
+        }
+
+        Self {
+            crate_id,
+            total_effects: audit_file.audit_trees.len(),
+            unaudited_effects,
+            unsafe_effects,
+            pub_caller_checked: audit_file.pub_caller_checked.len(),
+            effects_by_type,
+            risk_score,
+        }
+    }
+
+    /// Audit status of the crate: unsafe if any effect has been marked
+    /// unsafe, otherwise how much of it has been audited
+    pub fn audit_status(&self) -> &'static str {
+        if self.unsafe_effects > 0 {
+            "unsafe"
+        } else if self.unaudited_effects == 0 {
+            "audited"
+        } else if self.unaudited_effects < self.total_effects {
+            "partially-audited"
+        } else {
+            "unaudited"
+        }
+    }
+}
+
+impl RepoSummary {
+    pub fn new(repo: String, mut crates: Vec<CrateSummary>) -> Self {
+        crates.sort_by(|c1, c2| c1.crate_id.cmp(&c2.crate_id));
+
+        let mut by_risk = crates.iter().filter(|c| c.risk_score > 0).collect::<Vec<_>>();
+        by_risk.sort_by_key(|c| Reverse(c.risk_score));
+        let top_dangerous_deps =
+            by_risk.iter().take(TOP_DEPS).map(|c| c.crate_id.clone()).collect();
+
+        Self {
+            repo,
+            total_effects: crates.iter().map(|c| c.total_effects).sum(),
+            unaudited_effects: crates.iter().map(|c| c.unaudited_effects).sum(),
+            risk_score: crates.iter().map(|c| c.risk_score).sum(),
+            top_dangerous_deps,
+            name_warnings: Vec::new(),
+            crates,
+        }
+    }
+
+    /// Summarize all crates in an audit chain
+    pub fn from_audit_chain(repo: String, chain: &mut AuditChain) -> Result<Self> {
+        let crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+        let mut crates = Vec::new();
+        for crate_id in crate_ids {
+            let audit_file = chain
+                .read_audit_file(&crate_id)?
+                .context(format!("Couldn't find audit for {}", crate_id))?;
+            crates.push(CrateSummary::from_audit_file(crate_id.to_string(), &audit_file));
+        }
+        let mut summary = Self::new(repo, crates);
+        summary.name_warnings =
+            name_check::check_packages(&chain.load_lockfile()?.packages);
+        Ok(summary)
+    }
+}
+
+impl Report {
+    pub fn new(repos: Vec<RepoSummary>) -> Self {
+        let mut report = Self::default();
+        report.merge(Report { repos });
+        report
+    }
+
+    /// Merge another report into this one. Summaries for the same repository
+    /// are replaced by the ones in `other`.
+    pub fn merge(&mut self, other: Report) {
+        for repo in other.repos {
+            self.repos.retain(|r| r.repo != repo.repo);
+            self.repos.push(repo);
+        }
+        self.repos.sort_by(|r1, r2| r1.repo.cmp(&r2.repo));
+    }
+
+    pub fn read_report(path: &Path) -> Result<Self> {
+        let json_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json_string)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut f = File::create(path)?;
+        f.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A callee flagged as an effect, with how often and in which crates
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CalleeCount {
+    pub callee: String,
+    pub effects: usize,
+    pub unaudited_effects: usize,
+    pub crates: BTreeSet<String>,
+}
+
+impl fmt::Display for CalleeCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let crates = self.crates.iter().cloned().collect::<Vec<_>>().join(", ");
+        write!(
+            f,
+            "{:>6} {:>10}  {} ({})",
+            self.effects, self.unaudited_effects, self.callee, crates
+        )
+    }
+}
+
+/// The `n` callees of the most effects in the audits, most frequent first
+pub fn top_callees<'a, I>(audits: I, n: usize) -> Vec<CalleeCount>
+where
+    I: IntoIterator<Item = (String, &'a AuditFile)>,
+{
+    let mut counts: BTreeMap<String, CalleeCount> = BTreeMap::new();
+    for (crate_id, audit_file) in audits {
+        for (e, t) in audit_file.audit_trees.iter() {
+            let callee = e.callee_path().to_string();
+            let count = counts.entry(callee.clone()).or_insert_with(|| CalleeCount {
+                callee,
+                effects: 0,
+                unaudited_effects: 0,
+                crates: BTreeSet::new(),
+            });
+            count.effects += 1;
+            if count_leaves(t).0 > 0 {
+                count.unaudited_effects += 1;
+            }
+            count.crates.insert(crate_id.clone());
+        }
+    }
+    // Sorted by callee for ties, since the map is
+    let mut counts = counts.into_values().collect::<Vec<_>>();
+    counts.sort_by_key(|c| Reverse(c.effects));
+    counts.truncate(n);
+    counts
+}
+
+/// The top callees across all the crates in an audit chain
+pub fn top_callees_in_chain(
+    chain: &mut AuditChain,
+    n: usize,
+) -> Result<Vec<CalleeCount>> {
+    let crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+    let mut audits = Vec::new();
+    for crate_id in crate_ids {
+        let audit_file = chain
+            .read_audit_file(&crate_id)?
+            .context(format!("Couldn't find audit for {}", crate_id))?;
+        audits.push((crate_id.to_string(), audit_file));
+    }
+    Ok(top_callees(audits.iter().map(|(id, a)| (id.clone(), a)), n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::{Effect, EffectInstance};
+    use crate::ident::CanonicalPath;
+    use std::path::PathBuf;
+
+    fn crate_summary(crate_id: &str, risk_score: u64) -> CrateSummary {
+        CrateSummary {
+            crate_id: crate_id.to_string(),
+            total_effects: 1,
+            unaudited_effects: 1,
+            unsafe_effects: 0,
+            pub_caller_checked: 0,
+            effects_by_type: BTreeMap::new(),
+            risk_score,
+        }
+    }
+
+    #[test]
+    fn test_merge_reports() {
+        let r1 = RepoSummary::new("a".to_string(), vec![crate_summary("libc-0.2.0", 3)]);
+        let r2 = RepoSummary::new("b".to_string(), vec![crate_summary("libc-0.2.0", 1)]);
+        let r3 =
+            RepoSummary::new("a".to_string(), vec![crate_summary("memchr-2.0.0", 0)]);
+
+        let mut report = Report::new(vec![r2.clone(), r1]);
+        report.merge(Report::new(vec![r3.clone()]));
+        assert_eq!(report.repos, vec![r3, r2]);
+    }
+
+    #[test]
+    fn test_top_dangerous_deps() {
+        let repo = RepoSummary::new(
+            "a".to_string(),
+            vec![
+                crate_summary("x-1.0.0", 1),
+                crate_summary("y-1.0.0", 0),
+                crate_summary("z-1.0.0", 5),
+            ],
+        );
+        assert_eq!(repo.top_dangerous_deps, vec!["z-1.0.0", "x-1.0.0"]);
+        assert_eq!(repo.risk_score, 6);
+    }
+
+    #[test]
+    fn test_risk_score_provenance() {
+        let effects = ["a", "b", "c"]
+            .iter()
+            .zip([Provenance::Library, Provenance::Example, Provenance::DocTest])
+            .map(|(f, provenance)| {
+                let callee = CanonicalPath::new("libc::fork");
+                let mut e = EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new(&format!("x::{}", f)),
+                    callee.clone(),
+                    &proc_macro2::Ident::new(f, proc_macro2::Span::call_site()),
+                    Effect::FFICall(callee),
+                );
+                e.set_provenance(provenance);
+                e
+            })
+            .collect::<Vec<_>>();
+        let mut audit_file = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit_file.set_base_audit_trees(&effects);
+
+        // Only the library effect counts towards the risk score
+        let summary = CrateSummary::from_audit_file("x".to_string(), &audit_file);
+        assert_eq!(summary.total_effects, 3);
+        assert_eq!(summary.unaudited_effects, 3);
+        assert_eq!(
+            summary.risk_score,
+            Effect::FFICall(CanonicalPath::new("libc::fork")).severity().weight()
+        );
+    }
+    #[test]
+    fn test_top_callees() {
+        let tokens = "mmap mmap getuid".parse::<proc_macro2::TokenStream>().unwrap();
+        let effects = tokens
+            .into_iter()
+            .map(|t| {
+                let callee = CanonicalPath::new(&format!("libc::{}", t));
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("x::f"),
+                    callee.clone(),
+                    &t,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut x = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        x.set_base_audit_trees(&effects);
+        let mut y = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        y.set_base_audit_trees(&effects[..1]);
+
+        let top = top_callees([("x".to_string(), &x), ("y".to_string(), &y)], 1);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].callee, "libc::mmap");
+        assert_eq!(top[0].effects, 3);
+        assert_eq!(top[0].crates, BTreeSet::from(["x".to_string(), "y".to_string()]));
+    }
+}
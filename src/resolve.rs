@@ -1,18 +1,27 @@
 //! Interface for name resolution for Rust identifiers.
 //!
-//! The type FileResolver is a wrapper around Resolver from name_resolution.rs
-//! with the needed functionality.
+//! The trait Resolver abstracts over the name resolution backends:
+//! rust-analyzer (name_resolution.rs, the default) and rustc_driver
+//! (rustc_resolution.rs, behind the rustc-resolver feature). The type
+//! FileResolver is a wrapper around a Resolver with the needed functionality.
 
-pub use super::name_resolution::Resolver;
+pub use super::name_resolution::RaResolver;
+#[cfg(feature = "rustc-resolver")]
+pub use super::rustc_resolution::RustcResolver;
 
 use super::effect::SrcLoc;
 use super::hacky_resolver::HackyResolver;
 use super::ident::{CanonicalPath, CanonicalType, Ident};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
 use log::{debug, info, warn};
-use std::fmt::Display;
+use parse_display::Display;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
 use std::path::Path as FilePath;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
 use syn::{self, spanned::Spanned};
 
 /*
@@ -23,6 +32,142 @@ pub fn ident_from_syn(i: &syn::Ident) -> Ident {
     Ident::new_owned(i.to_string())
 }
 
+/// Name resolution backend for a crate, queried by the location and name of
+/// an identifier in one of its files
+pub trait Resolver: Debug + Send {
+    fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath>;
+    fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType>;
+    fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool>;
+    fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool>;
+    fn is_const_or_immutable_static_ident(&self, s: SrcLoc, i: Ident) -> Result<bool>;
+    fn all_impl_methods_for_trait_method(
+        &self,
+        s: SrcLoc,
+        i: Ident,
+        m: String,
+    ) -> Result<Vec<CanonicalPath>>;
+    fn get_cfg_options_for_crate(
+        &self,
+        name: &str,
+    ) -> Result<HashMap<String, Vec<String>>>;
+}
+
+/// A Resolver shared by the threads scanning the files of a crate in
+/// parallel: the backend answers one query at a time, while the files are
+/// parsed and walked concurrently
+#[derive(Debug)]
+pub struct SharedResolver(Mutex<Box<dyn Resolver>>);
+
+impl SharedResolver {
+    pub fn new(resolver: Box<dyn Resolver>) -> Self {
+        Self(Mutex::new(resolver))
+    }
+
+    fn get(&self) -> MutexGuard<'_, Box<dyn Resolver>> {
+        // A query which panicked leaves the backend as usable as before
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Resolver for SharedResolver {
+    fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
+        self.get().resolve_ident(s, i)
+    }
+    fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
+        self.get().resolve_type(s, i)
+    }
+    fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        self.get().is_ffi(s, i)
+    }
+    fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        self.get().is_unsafe_call(s, i)
+    }
+    fn is_const_or_immutable_static_ident(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        self.get().is_const_or_immutable_static_ident(s, i)
+    }
+    fn all_impl_methods_for_trait_method(
+        &self,
+        s: SrcLoc,
+        i: Ident,
+        m: String,
+    ) -> Result<Vec<CanonicalPath>> {
+        self.get().all_impl_methods_for_trait_method(s, i, m)
+    }
+    fn get_cfg_options_for_crate(
+        &self,
+        name: &str,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        self.get().get_cfg_options_for_crate(name)
+    }
+}
+
+/// Resolver for crates which no backend could load: it resolves nothing, so
+/// FileResolver falls back to the syntactic resolver for every name
+#[derive(Debug)]
+pub struct NoResolver;
+
+impl Resolver for NoResolver {
+    fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn is_const_or_immutable_static_ident(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn all_impl_methods_for_trait_method(
+        &self,
+        s: SrcLoc,
+        i: Ident,
+        _m: String,
+    ) -> Result<Vec<CanonicalPath>> {
+        Err(anyhow!("No name resolution for '{}' at {}", i, s))
+    }
+    fn get_cfg_options_for_crate(
+        &self,
+        name: &str,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        Err(anyhow!("No cfg options for crate: {:?}", name))
+    }
+}
+
+/// The available Resolver backends
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display)]
+pub enum ResolverBackend {
+    /// rust-analyzer; works on a stable toolchain
+    #[default]
+    #[display("rust-analyzer")]
+    RustAnalyzer,
+    /// rustc_driver; more precise (it sees the crate exactly as rustc
+    /// compiles it), but needs a nightly toolchain with the rustc-dev
+    /// component, and cargo-scan built with the rustc-resolver feature
+    #[display("rustc")]
+    Rustc,
+}
+
+impl ResolverBackend {
+    /// Load the crate at the path with the backend
+    pub fn load(self, crate_path: &FilePath) -> Result<Box<dyn Resolver>> {
+        match self {
+            Self::RustAnalyzer => Ok(Box::new(RaResolver::new(crate_path)?)),
+            #[cfg(feature = "rustc-resolver")]
+            Self::Rustc => Ok(Box::new(RustcResolver::new(crate_path)?)),
+            #[cfg(not(feature = "rustc-resolver"))]
+            Self::Rustc => Err(anyhow!(
+                "The rustc resolver needs cargo-scan to be built with the \
+                rustc-resolver feature on a nightly toolchain (`make rustc-resolver`)"
+            )),
+        }
+    }
+}
+
 /// Common interface for FileResolver and HackyResolver
 ///
 /// Abstracts the functionality for resolution that is needed by Scanner.
@@ -73,24 +218,53 @@ pub trait Resolve<'a>: Sized {
     fn pop_fn(&mut self);
     fn scan_use(&mut self, use_stmt: &'a syn::ItemUse);
     fn scan_foreign_fn(&mut self, f: &'a syn::ForeignItemFn);
+    fn scan_type_alias(&mut self, t: &'a syn::ItemType);
 }
 
 #[derive(Debug)]
 pub struct FileResolver<'a> {
     filepath: &'a FilePath,
-    resolver: &'a Resolver,
+    resolver: &'a dyn Resolver,
     backup: HackyResolver<'a>,
+    /// After the deadline, only the syntactic backup resolver is used
+    deadline: Option<Instant>,
+    /// Only use the syntactic backup resolver (e.g. in macro expansions, where
+    /// tokens don't have their own locations in the file)
+    syntactic: bool,
 }
 
 impl<'a> FileResolver<'a> {
     pub fn new(
         crate_name: &'a str,
-        resolver: &'a Resolver,
+        resolver: &'a dyn Resolver,
         filepath: &'a FilePath,
     ) -> Result<Self> {
         debug!("Creating FileResolver for file: {:?}", filepath);
         let backup = HackyResolver::new(crate_name, filepath)?;
-        Ok(Self { filepath, resolver, backup })
+        Ok(Self { filepath, resolver, backup, deadline: None, syntactic: false })
+    }
+
+    /// Set the module the file's definitions are in, for code outside of the
+    /// crate's module tree (examples and doc-tests) or in files whose module
+    /// isn't their path (`#[path]` modules and included files). Only used by
+    /// the syntactic resolver.
+    pub fn set_modpath(&mut self, modpath: CanonicalPath) {
+        self.backup.set_modpath(modpath);
+    }
+
+    /// Stop using name resolution after the deadline. A deadline in the past
+    /// makes the resolver purely syntactic.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Only use the syntactic resolver until this is set back to false
+    pub fn set_syntactic(&mut self, syntactic: bool) {
+        self.syntactic = syntactic;
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline.map_or(false, |d| Instant::now() >= d)
     }
 
     fn resolve_core(&self, i: &syn::Ident) -> Result<CanonicalPath> {
@@ -161,10 +335,13 @@ impl<'a> FileResolver<'a> {
 
     fn resolve_or_else<S, R, F, T>(&self, i: &S, try_resolve: R, fallback: F) -> T
     where
-        S: Display + Spanned,
+        S: fmt::Display + Spanned,
         R: FnOnce() -> Result<T>,
         F: FnOnce() -> T,
     {
+        if self.syntactic || self.past_deadline() {
+            return fallback();
+        }
         try_resolve().unwrap_or_else(|err| {
             let s = SrcLoc::from_span(self.filepath, i);
             // Temporarily suppressing this warning.
@@ -270,6 +447,10 @@ impl<'a> Resolve<'a> for FileResolver<'a> {
         self.backup.scan_foreign_fn(f)
     }
 
+    fn scan_type_alias(&mut self, t: &'a syn::ItemType) {
+        self.backup.scan_type_alias(t)
+    }
+
     fn resolve_method(&self, i: &'a syn::Ident) -> CanonicalPath {
         self.resolve_ident_or_else(i, || self.backup.resolve_method(i))
     }
@@ -0,0 +1,554 @@
+//! Name resolution with rustc_driver (the `rustc` resolver backend).
+//!
+//! The crate is compiled once, in process, after `cargo check` has built its
+//! dependencies; the resolution of every path, method call, and field access
+//! in the crate's own files is recorded by the location of its identifier,
+//! and queries look the identifier up by location. Tokens produced by macros
+//! aren't recorded, so queries for them fall back to the syntactic resolver.
+//!
+//! The crate's dependencies are built by running `cargo check` on it, which
+//! runs their build scripts and proc macros (and the crate's own), so this
+//! backend shouldn't be used on untrusted crates outside of a sandbox.
+//!
+//! This needs the nightly toolchain pinned in the Makefile, with the
+//! rustc-dev component, which cargo-scan is built with by
+//! `make rustc-resolver`.
+
+extern crate rustc_driver;
+extern crate rustc_hir;
+extern crate rustc_interface;
+extern crate rustc_middle;
+extern crate rustc_span;
+
+use super::effect::SrcLoc;
+use super::ident::{CallableKind, CanonicalPath, CanonicalType, Ident, TypeKind};
+use super::resolve::Resolver;
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use rustc_driver::{Callbacks, Compilation, RunCompiler};
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::DefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::Mutability;
+use rustc_interface::{interface::Compiler, Queries};
+use rustc_middle::hir::nested_filter;
+use rustc_middle::ty::{self, Ty, TyCtxt, TypeckResults};
+use rustc_span::{FileName, RealFileName, Span};
+use std::collections::HashMap;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What rustc resolved an identifier to
+#[derive(Debug, Clone, Default)]
+struct Resolution {
+    path: Option<CanonicalPath>,
+    ty: Option<CanonicalType>,
+    ffi: bool,
+    unsafe_fn: bool,
+    const_or_static: bool,
+}
+
+/// Location of an identifier: file, line, and column (both 1-indexed, as
+/// FileResolver queries them)
+type Key = (PathBuf, usize, usize);
+
+#[derive(Debug, Default)]
+pub struct RustcResolver {
+    /// The identifiers in the crate's files, with what they resolved to
+    names: HashMap<Key, (String, Resolution)>,
+    /// The methods in the impls of each trait referenced in the crate, by
+    /// trait path
+    trait_impls: HashMap<CanonicalPath, Vec<(String, CanonicalPath)>>,
+    /// The crate's cfg options
+    cfg: HashMap<String, Vec<String>>,
+}
+
+impl RustcResolver {
+    pub fn new(crate_path: &Path) -> Result<Self> {
+        debug!("Creating rustc resolver with path {:?}", crate_path);
+        let args = rustc_args(crate_path)?;
+        debug!("rustc args: {:?}", args);
+
+        let mut collector = Collector(Self::default());
+        rustc_driver::catch_fatal_errors(|| {
+            RunCompiler::new(&args, &mut collector).run()
+        })
+        .map_err(|_| anyhow!("rustc panicked on {:?}", crate_path))?
+        .map_err(|_| anyhow!("rustc failed to compile {:?}", crate_path))?;
+
+        debug!("...created");
+        Ok(collector.0)
+    }
+
+    fn lookup(&self, s: &SrcLoc, i: &Ident) -> Result<&Resolution> {
+        let mut filepath = s.dir().clone();
+        filepath.push(s.file().as_path());
+        let key = (canonicalize(&filepath)?, s.start_line(), s.start_col());
+        match self.names.get(&key) {
+            Some((name, res)) if name == i.as_str() => Ok(res),
+            _ => Err(anyhow!("No rustc resolution for '{}' at {}", i, s)),
+        }
+    }
+}
+
+impl Resolver for RustcResolver {
+    fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
+        let res = self.lookup(&s, &i)?;
+        res.path.clone().ok_or_else(|| anyhow!("'{}' at {} is not an item", i, s))
+    }
+
+    fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
+        let res = self.lookup(&s, &i)?;
+        res.ty.clone().ok_or_else(|| anyhow!("No type for '{}' at {}", i, s))
+    }
+
+    fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Ok(self.lookup(&s, &i)?.ffi)
+    }
+
+    fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Ok(self.lookup(&s, &i)?.unsafe_fn)
+    }
+
+    fn is_const_or_immutable_static_ident(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+        Ok(self.lookup(&s, &i)?.const_or_static)
+    }
+
+    fn all_impl_methods_for_trait_method(
+        &self,
+        s: SrcLoc,
+        i: Ident,
+        m: String,
+    ) -> Result<Vec<CanonicalPath>> {
+        let trait_path = self.resolve_ident(s, i.clone())?;
+        let impls = self.trait_impls.get(&trait_path).ok_or_else(|| {
+            anyhow!(
+                "No trait definition found for {:?}. Can not look for impl methods.",
+                i
+            )
+        })?;
+        Ok(impls.iter().filter(|(name, _)| *name == m).map(|(_, p)| p.clone()).collect())
+    }
+
+    fn get_cfg_options_for_crate(
+        &self,
+        _name: &str,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        Ok(self.cfg.clone())
+    }
+}
+
+/// The arguments to compile the crate with: its root file, edition, and
+/// features (all of them, as with rust-analyzer), and its dependencies as
+/// built by `cargo check`
+fn rustc_args(crate_path: &Path) -> Result<Vec<String>> {
+    let crate_path = canonicalize(crate_path)?;
+    let manifest_path = crate_path.join("Cargo.toml");
+    let manifest = cargo_toml::Manifest::from_path(&manifest_path)?;
+    let package = manifest.package.as_ref().context("No package in Cargo.toml")?;
+    let (root, crate_type) = if crate_path.join("src/lib.rs").is_file() {
+        (crate_path.join("src/lib.rs"), "lib")
+    } else {
+        (crate_path.join("src/main.rs"), "bin")
+    };
+
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .current_dir(&crate_path)
+        .output()?;
+    let mut args = vec![
+        "rustc".to_string(),
+        root.to_string_lossy().to_string(),
+        format!("--crate-name={}", package.name.replace('-', "_")),
+        format!("--crate-type={}", crate_type),
+        format!("--edition={}", edition(package.edition.get()?)),
+        format!("--sysroot={}", String::from_utf8(sysroot.stdout)?.trim()),
+        "--cap-lints=allow".to_string(),
+        "--emit=metadata".to_string(),
+        format!("--out-dir={}", std::env::temp_dir().to_string_lossy()),
+    ];
+    for feature in manifest.features.keys() {
+        args.push(format!("--cfg=feature=\"{}\"", feature));
+    }
+
+    let output = Command::new("cargo")
+        .args(["check", "--all-features", "--message-format=json"])
+        .current_dir(&crate_path)
+        .output()?;
+    for line in String::from_utf8(output.stdout)?.lines() {
+        let Ok(msg) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        let own = msg["package_id"].as_str().map_or(false, |id| {
+            // name 0.1.0 (path+file:///path) or path+file:///path#name@0.1.0
+            let url = format!("path+file://{}", crate_path.display());
+            id.contains(&format!("({})", url)) || id.starts_with(&format!("{}#", url))
+        });
+        match msg["reason"].as_str() {
+            Some("compiler-artifact") if !own => args.extend(extern_args(&msg)),
+            Some("build-script-executed") if own => {
+                for cfg in msg["cfgs"].as_array().into_iter().flatten() {
+                    args.push(format!("--cfg={}", cfg.as_str().unwrap_or_default()));
+                }
+                // For env!("OUT_DIR") in the crate: rustc looks the variables
+                // set with --env-set up before those of the process
+                if let Some(out_dir) = msg["out_dir"].as_str() {
+                    args.push("-Zunstable-options".to_string());
+                    args.push(format!("--env-set=OUT_DIR={}", out_dir));
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(args)
+}
+
+fn edition(edition: &cargo_toml::Edition) -> &'static str {
+    match edition {
+        cargo_toml::Edition::E2015 => "2015",
+        cargo_toml::Edition::E2018 => "2018",
+        cargo_toml::Edition::E2021 => "2021",
+    }
+}
+
+/// The `--extern` and `-L` arguments for a dependency built by cargo
+fn extern_args(msg: &serde_json::Value) -> Vec<String> {
+    let target = &msg["target"];
+    let is_lib = target["kind"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|k| matches!(k.as_str(), Some("lib" | "rlib" | "proc-macro")));
+    let Some(name) = target["name"].as_str().filter(|_| is_lib) else {
+        return Vec::new();
+    };
+    let files = msg["filenames"].as_array().into_iter().flatten();
+    let files = files.filter_map(|f| f.as_str()).collect::<Vec<_>>();
+    let Some(file) = files.iter().find(|f| f.ends_with(".rmeta")).or(files.first())
+    else {
+        return Vec::new();
+    };
+    let deps_dir = Path::new(file).parent().unwrap_or(Path::new("."));
+    vec![
+        format!("--extern={}={}", name.replace('-', "_"), file),
+        format!("-Ldependency={}", deps_dir.display()),
+    ]
+}
+
+struct Collector(RustcResolver);
+
+impl Callbacks for Collector {
+    fn after_analysis<'tcx>(
+        &mut self,
+        _compiler: &Compiler,
+        queries: &'tcx Queries<'tcx>,
+    ) -> Compilation {
+        queries.global_ctxt().unwrap().enter(|tcx| {
+            for (name, value) in tcx.sess.parse_sess.config.iter() {
+                let values = self.0.cfg.entry(name.to_string()).or_default();
+                values.extend(value.map(|v| v.to_string()));
+            }
+            let mut visitor = NameVisitor { tcx, resolver: &mut self.0, typeck: None };
+            tcx.hir().walk_toplevel_module(&mut visitor);
+        });
+        Compilation::Stop
+    }
+}
+
+struct NameVisitor<'tcx, 'r> {
+    tcx: TyCtxt<'tcx>,
+    resolver: &'r mut RustcResolver,
+    /// Type information for the innermost body
+    typeck: Option<&'tcx TypeckResults<'tcx>>,
+}
+
+impl<'tcx> NameVisitor<'tcx, '_> {
+    fn key(&self, span: Span) -> Option<Key> {
+        if span.from_expansion() {
+            return None;
+        }
+        let loc = self.tcx.sess.source_map().lookup_char_pos(span.lo());
+        let FileName::Real(RealFileName::LocalPath(path)) = &loc.file.name else {
+            return None;
+        };
+        Some((path.clone(), loc.line, loc.col.0 + 1))
+    }
+
+    fn src_loc(&self, span: Span) -> Option<SrcLoc> {
+        let source_map = self.tcx.sess.source_map();
+        let (start, end) = (
+            source_map.lookup_char_pos(span.lo()),
+            source_map.lookup_char_pos(span.hi()),
+        );
+        let FileName::Real(RealFileName::LocalPath(path)) = &start.file.name else {
+            return None;
+        };
+        Some(SrcLoc::new(path, start.line, start.col.0 + 1, end.line, end.col.0 + 1))
+    }
+
+    fn entry(&mut self, ident: rustc_span::symbol::Ident) -> Option<&mut Resolution> {
+        let key = self.key(ident.span)?;
+        let (_, res) = self
+            .resolver
+            .names
+            .entry(key)
+            .or_insert_with(|| (ident.to_string(), Resolution::default()));
+        Some(res)
+    }
+
+    fn record_res(&mut self, ident: rustc_span::symbol::Ident, res: Res) {
+        match res {
+            Res::Def(_, def_id) => self.record_def(ident, def_id),
+            Res::PrimTy(p) => {
+                if let Some(entry) = self.entry(ident) {
+                    entry.path = Some(CanonicalPath::new(p.name_str()));
+                    entry.ty = Some(CanonicalType::new(p.name_str()));
+                }
+            }
+            // Self resolves to the type, as with rust-analyzer
+            Res::SelfTyAlias { alias_to, .. } => {
+                let self_ty = self.tcx.type_of(alias_to).instantiate_identity();
+                if let ty::Adt(adt, _) = self_ty.kind() {
+                    self.record_def(ident, adt.did());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn record_def(&mut self, ident: rustc_span::symbol::Ident, def_id: DefId) {
+        let tcx = self.tcx;
+        let def_id = normalize_type_def(tcx, def_id);
+        let kind = tcx.def_kind(def_id);
+        // Paths carry the location of the definition, as with rust-analyzer
+        let def_loc =
+            self.src_loc(tcx.def_span(def_id)).or_else(|| self.src_loc(ident.span));
+        let mut path = canonical_path(tcx, def_id);
+        if let Some(def_loc) = def_loc {
+            path = path.add_src_loc(def_loc);
+        }
+        if kind == DefKind::Trait && !self.resolver.trait_impls.contains_key(&path) {
+            let impls = impl_methods(tcx, def_id);
+            self.resolver.trait_impls.insert(path.clone(), impls);
+        }
+        let is_fn = matches!(kind, DefKind::Fn | DefKind::AssocFn);
+        let resolution = Resolution {
+            path: Some(path),
+            ty: def_type(tcx, def_id, kind),
+            ffi: tcx.is_foreign_item(def_id),
+            unsafe_fn: is_fn
+                && tcx.fn_sig(def_id).skip_binder().unsafety() == hir::Unsafety::Unsafe,
+            const_or_static: matches!(kind, DefKind::Const | DefKind::AssocConst)
+                || tcx.static_mutability(def_id) == Some(Mutability::Not),
+        };
+        if let Some(entry) = self.entry(ident) {
+            *entry = resolution;
+        }
+    }
+
+    fn record_ty(&mut self, ident: rustc_span::symbol::Ident, ty: Ty<'tcx>) {
+        let ty = canonical_type(self.tcx, ty, TypeKind::Plain);
+        if let Some(entry) = self.entry(ident) {
+            entry.ty.get_or_insert(ty);
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for NameVisitor<'tcx, '_> {
+    type NestedFilter = nested_filter::All;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let outer = self.typeck.replace(self.tcx.typeck_body(id));
+        self.visit_body(self.tcx.hir().body(id));
+        self.typeck = outer;
+    }
+
+    fn visit_item(&mut self, item: &'tcx hir::Item<'tcx>) {
+        if !item.ident.as_str().is_empty() {
+            self.record_def(item.ident, item.owner_id.to_def_id());
+        }
+        intravisit::walk_item(self, item);
+    }
+
+    fn visit_impl_item(&mut self, item: &'tcx hir::ImplItem<'tcx>) {
+        self.record_def(item.ident, item.owner_id.to_def_id());
+        intravisit::walk_impl_item(self, item);
+    }
+
+    fn visit_trait_item(&mut self, item: &'tcx hir::TraitItem<'tcx>) {
+        self.record_def(item.ident, item.owner_id.to_def_id());
+        intravisit::walk_trait_item(self, item);
+    }
+
+    fn visit_foreign_item(&mut self, item: &'tcx hir::ForeignItem<'tcx>) {
+        self.record_def(item.ident, item.owner_id.to_def_id());
+        intravisit::walk_foreign_item(self, item);
+    }
+
+    fn visit_field_def(&mut self, field: &'tcx hir::FieldDef<'tcx>) {
+        self.record_def(field.ident, field.def_id.to_def_id());
+        intravisit::walk_field_def(self, field);
+    }
+
+    fn visit_path(&mut self, path: &hir::Path<'tcx>, _id: hir::HirId) {
+        for segment in path.segments {
+            self.record_res(segment.ident, segment.res);
+        }
+        intravisit::walk_path(self, path);
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        if let Some(typeck) = self.typeck {
+            match expr.kind {
+                // Methods and associated functions of types (Vec::new) are
+                // only resolved with the type information
+                hir::ExprKind::MethodCall(segment, ..)
+                | hir::ExprKind::Path(hir::QPath::TypeRelative(_, segment)) => {
+                    if let Some(def_id) = typeck.type_dependent_def_id(expr.hir_id) {
+                        self.record_def(segment.ident, def_id);
+                    }
+                }
+                hir::ExprKind::Field(base, ident) => {
+                    let base_ty = typeck.expr_ty_adjusted(base).peel_refs();
+                    if let ty::Adt(adt, _) = base_ty.kind() {
+                        if !adt.is_enum() {
+                            let idx = typeck.field_index(expr.hir_id);
+                            self.record_def(
+                                ident,
+                                adt.non_enum_variant().fields[idx].did,
+                            );
+                        }
+                    }
+                    self.record_ty(ident, typeck.expr_ty(expr));
+                }
+                hir::ExprKind::Path(hir::QPath::Resolved(_, path)) => {
+                    if let (Res::Local(_), Some(segment)) =
+                        (path.res, path.segments.last())
+                    {
+                        self.record_ty(segment.ident, typeck.expr_ty(expr));
+                    }
+                }
+                _ => (),
+            }
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+/// Type aliases resolve to their own definitions; normalize them to the
+/// underlying type so the path is the one sink patterns are written against
+fn normalize_type_def(tcx: TyCtxt<'_>, def_id: DefId) -> DefId {
+    if !matches!(tcx.def_kind(def_id), DefKind::TyAlias { .. }) {
+        return def_id;
+    }
+    match tcx.type_of(def_id).instantiate_identity().kind() {
+        ty::Adt(adt, _) => adt.did(),
+        _ => def_id,
+    }
+}
+
+/// The path segments of a definition, laid out as rust-analyzer has them:
+/// the crate name, the modules, the container (the self type for items in
+/// impls), and the name
+fn path_segments(tcx: TyCtxt<'_>, def_id: DefId) -> Vec<String> {
+    if def_id.is_crate_root() {
+        return vec![tcx.crate_name(def_id.krate).to_string()];
+    }
+    if let DefKind::Impl { .. } = tcx.def_kind(def_id) {
+        if let ty::Adt(adt, _) = tcx.type_of(def_id).instantiate_identity().kind() {
+            return path_segments(tcx, adt.did());
+        }
+    }
+    let mut segments = path_segments(tcx, tcx.parent(def_id));
+    if let Some(name) = tcx.opt_item_name(def_id) {
+        segments.push(name.to_string());
+    }
+    segments
+}
+
+fn canonical_path(tcx: TyCtxt<'_>, def_id: DefId) -> CanonicalPath {
+    CanonicalPath::new(&path_segments(tcx, def_id).join("::"))
+}
+
+fn canonical_type<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ty: Ty<'tcx>,
+    kind: TypeKind,
+) -> CanonicalType {
+    let ty = ty.peel_refs();
+    let kind = match ty.kind() {
+        ty::RawPtr(_) => TypeKind::RawPointer,
+        ty::FnPtr(_) => TypeKind::Callable(CallableKind::FnPtr),
+        ty::Closure(..) => TypeKind::Callable(CallableKind::Closure),
+        ty::Dynamic(..) => TypeKind::DynTrait,
+        ty::Param(_) => TypeKind::Generic,
+        _ => kind,
+    };
+    let name = match ty.kind() {
+        ty::Adt(adt, _) => path_segments(tcx, adt.did()).join("::"),
+        _ => ty.to_string(),
+    };
+    let bounds = match ty.kind() {
+        ty::Dynamic(preds, ..) => {
+            preds.principal_def_id().map(|t| canonical_path(tcx, t)).into_iter().collect()
+        }
+        _ => Vec::new(),
+    };
+    CanonicalType::new_owned(name, bounds, kind)
+}
+
+/// The type of a definition: the return type for functions, as with
+/// rust-analyzer
+fn def_type(tcx: TyCtxt<'_>, def_id: DefId, kind: DefKind) -> Option<CanonicalType> {
+    match kind {
+        DefKind::Fn | DefKind::AssocFn => {
+            let ret = tcx.fn_sig(def_id).instantiate_identity().output().skip_binder();
+            Some(canonical_type(tcx, ret, TypeKind::Function))
+        }
+        DefKind::Static(Mutability::Mut) => {
+            let ty = tcx.type_of(def_id).instantiate_identity();
+            Some(canonical_type(tcx, ty, TypeKind::StaticMut))
+        }
+        DefKind::Field => {
+            let parent_kind = tcx.def_kind(tcx.parent(def_id));
+            let kind = if parent_kind == DefKind::Union {
+                TypeKind::UnionFld
+            } else {
+                TypeKind::Plain
+            };
+            let ty = tcx.type_of(def_id).instantiate_identity();
+            Some(canonical_type(tcx, ty, kind))
+        }
+        DefKind::Struct
+        | DefKind::Enum
+        | DefKind::Union
+        | DefKind::Const
+        | DefKind::AssocConst
+        | DefKind::Static(_) => {
+            let ty = tcx.type_of(def_id).instantiate_identity();
+            Some(canonical_type(tcx, ty, TypeKind::Plain))
+        }
+        DefKind::Variant => Some(CanonicalType::new_owned(
+            path_segments(tcx, def_id).join("::"),
+            Vec::new(),
+            TypeKind::Plain,
+        )),
+        _ => None,
+    }
+}
+
+/// The methods in all the impls of the trait, with their names
+fn impl_methods(tcx: TyCtxt<'_>, trait_id: DefId) -> Vec<(String, CanonicalPath)> {
+    tcx.all_impls(trait_id)
+        .flat_map(|impl_id| tcx.associated_items(impl_id).in_definition_order())
+        .filter(|item| item.kind == ty::AssocKind::Fn)
+        .map(|item| (item.name.to_string(), canonical_path(tcx, item.def_id)))
+        .collect()
+}
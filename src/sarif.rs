@@ -0,0 +1,281 @@
+//! Export of scan results as SARIF 2.1.0
+//! (https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html), for
+//! uploading to GitHub code scanning (`scan --format sarif`).
+//!
+//! Each effect is a result, with
+//! - a rule per effect kind (`SinkCall`, `FFICall`, ...), listed in the
+//!   tool's rules for the kinds found;
+//! - the effect's location, relative to the scanned crate (`%SRCROOT%`) when
+//!   it is in the crate;
+//! - a level from the effect's severity (`error` for high and critical,
+//!   `warning` for medium, `note` for low), and the `security-severity`
+//!   property GitHub uses to rank security alerts.
+//!
+//! Columns are 1-based in SARIF, while `SrcLoc` columns are 0-based.
+
+use crate::effect::{EffectInstance, EffectType, Severity};
+use crate::scan_manifest::SCANNER_VERSION;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+const SRCROOT: &str = "%SRCROOT%";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactLocation {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    pub region: Region,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResultProperties {
+    pub caller: String,
+    pub callee: String,
+    pub severity: Severity,
+    #[serde(rename = "security-severity")]
+    pub security_severity: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub level: String,
+    pub message: Message,
+    pub locations: Vec<Location>,
+    pub properties: ResultProperties,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub id: String,
+    pub name: String,
+    pub short_description: Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: String,
+    pub version: String,
+    pub information_uri: String,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: String,
+    pub runs: Vec<Run>,
+}
+
+/// What the effects of a kind are, for the rule's description
+fn rule_description(t: EffectType) -> &'static str {
+    match t {
+        EffectType::SinkCall => "Call to a function matching a sink pattern",
+        EffectType::FFICall => "Call to a foreign function",
+        EffectType::UnsafeCall => "Call to an unsafe function or method",
+        EffectType::RawPointer => "Dereference of a raw pointer",
+        EffectType::UnionField => "Read of a union field",
+        EffectType::StaticMut => "Access to a mutable static variable",
+        EffectType::StaticExt => "Access to an external static variable",
+        EffectType::UnsafeTraitImpl => "Implementation of an unsafe trait",
+        EffectType::FnPtrCreation => "Creation of a function pointer",
+        EffectType::ClosureCreation => "Creation of a closure",
+        EffectType::RawPtrCast => "Cast to a raw pointer",
+        EffectType::PtrArithmetic => "Arithmetic on a raw pointer",
+        EffectType::IntPtrCast => "Cast between an integer and a raw pointer",
+        EffectType::UninitMemory => "Call to an API exposing uninitialized memory",
+        EffectType::AliasEscape => "Mutable access to data behind a shared reference",
+        EffectType::Atomic => "Relaxed atomic operation or fence",
+        EffectType::ThreadSpawn => "Spawn of a thread or task",
+        EffectType::OsHook => "Registration of a signal handler or exit hook, or a fork",
+        EffectType::MemoryMap => "Memory mapping or protection change",
+        EffectType::Custom => "Call matching a user-defined effect kind",
+        EffectType::Malware => "Call matching a pattern common in malicious crates",
+    }
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low => "note",
+    }
+}
+
+/// The score GitHub ranks security alerts by (critical is over 9.0, high
+/// over 7.0, medium over 4.0)
+fn security_severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "9.5",
+        Severity::High => "8.0",
+        Severity::Medium => "5.0",
+        Severity::Low => "2.0",
+    }
+}
+
+/// The location of an effect, relative to the crate if it is in it
+fn location(effect: &EffectInstance, crate_path: &Path) -> Location {
+    let loc = effect.call_loc();
+    let path = loc.dir().join(loc.file());
+    let artifact_location = match path.strip_prefix(crate_path) {
+        Ok(relative) => ArtifactLocation {
+            uri: relative.to_string_lossy().replace('\\', "/"),
+            uri_base_id: Some(SRCROOT.to_string()),
+        },
+        Err(_) => ArtifactLocation {
+            uri: path.to_string_lossy().replace('\\', "/"),
+            uri_base_id: None,
+        },
+    };
+    Location {
+        physical_location: PhysicalLocation {
+            artifact_location,
+            region: Region {
+                start_line: loc.start_line().max(1),
+                start_column: loc.start_col() + 1,
+                end_line: loc.end_line().max(loc.start_line()).max(1),
+                end_column: loc.end_col() + 1,
+            },
+        },
+    }
+}
+
+impl SarifLog {
+    /// The log of the effects found in the crate at the path
+    pub fn from_effects(effects: &[EffectInstance], crate_path: &Path) -> Self {
+        let mut rules = BTreeMap::new();
+        let results = effects
+            .iter()
+            .map(|e| {
+                let effect_type = EffectType::from(e.eff_type());
+                let rule_id = effect_type.to_string();
+                rules.entry(rule_id.clone()).or_insert_with(|| Rule {
+                    id: rule_id.clone(),
+                    name: rule_id.clone(),
+                    short_description: Message {
+                        text: rule_description(effect_type).to_string(),
+                    },
+                });
+                let severity = e.eff_type().severity();
+                SarifResult {
+                    rule_id,
+                    level: level(severity).to_string(),
+                    message: Message { text: e.description() },
+                    locations: vec![location(e, crate_path)],
+                    properties: ResultProperties {
+                        caller: e.caller().to_string(),
+                        callee: e.callee().to_string(),
+                        severity,
+                        security_severity: security_severity(severity).to_string(),
+                    },
+                }
+            })
+            .collect();
+        let driver = Driver {
+            name: "cargo-scan".to_string(),
+            version: SCANNER_VERSION.to_string(),
+            information_uri: "https://github.com/PLSysSec/cargo-scan".to_string(),
+            rules: rules.into_values().collect(),
+        };
+        Self {
+            schema: SCHEMA.to_string(),
+            version: SARIF_VERSION.to_string(),
+            runs: vec![Run { tool: Tool { driver }, results }],
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+
+    #[test]
+    fn test_sarif() {
+        let tokens = "getuid".parse::<proc_macro2::TokenStream>().unwrap();
+        let site = tokens.into_iter().next().unwrap();
+        let callee = CanonicalPath::new("libc::getuid");
+        let effect = EffectInstance::new_effect(
+            Path::new("/work/mycrate/src/lib.rs"),
+            CanonicalPath::new("mycrate::uid"),
+            callee.clone(),
+            &site,
+            Effect::FFICall(callee),
+        );
+        let log = SarifLog::from_effects(&[effect.clone()], Path::new("/work/mycrate"));
+        let run = &log.runs[0];
+        assert_eq!(run.tool.driver.rules.len(), 1);
+        assert_eq!(run.tool.driver.rules[0].id, "FFICall");
+        let result = &run.results[0];
+        assert_eq!(result.rule_id, "FFICall");
+        assert_eq!(result.level, "error");
+        assert_eq!(result.properties.security_severity, "8.0");
+        let artifact = &result.locations[0].physical_location.artifact_location;
+        assert_eq!(artifact.uri, "src/lib.rs");
+        assert_eq!(artifact.uri_base_id.as_deref(), Some("%SRCROOT%"));
+        let region = &result.locations[0].physical_location.region;
+        assert_eq!(region.start_column, effect.call_loc().start_col() + 1);
+
+        // Locations outside the crate are kept as they are
+        let log = SarifLog::from_effects(&[effect], Path::new("/elsewhere"));
+        let artifact =
+            &log.runs[0].results[0].locations[0].physical_location.artifact_location;
+        assert_eq!(artifact.uri, "/work/mycrate/src/lib.rs");
+        assert_eq!(artifact.uri_base_id, None);
+        let json = log.to_json().unwrap();
+        assert!(json.contains("\"$schema\""));
+        assert!(json.contains("\"ruleId\": \"FFICall\""));
+    }
+}
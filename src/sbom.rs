@@ -0,0 +1,242 @@
+//! CycloneDX SBOMs of the dependency tree of an audit chain, enriched with
+//! cargo-scan properties.
+//!
+//! Each crate in the chain is a component (identified by its `pkg:cargo`
+//! package URL) with the properties
+//! - `cargo-scan:effects`, and `cargo-scan:effects:<kind>` for each kind
+//! - `cargo-scan:unaudited-effects` and `cargo-scan:unsafe-effects`
+//! - `cargo-scan:audit-status`: one of `audited`, `partially-audited`,
+//!   `unaudited`, or `unsafe` (some effect was marked unsafe)
+//! - `cargo-scan:risk-score`, as in `report`
+//!
+//! The root crate is the component in the metadata, and the dependencies
+//! between the crates are taken from the lockfile.
+
+use crate::audit_chain::AuditChain;
+use crate::report::CrateSummary;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+const SPEC_VERSION: &str = "1.5";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+}
+
+impl Property {
+    fn new(name: &str, value: impl ToString) -> Self {
+        Self { name: format!("cargo-scan:{}", name), value: value.to_string() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+    pub version: String,
+    pub purl: String,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyEntry {
+    #[serde(rename = "ref")]
+    pub bom_ref: String,
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tool {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub tools: Vec<Tool>,
+    pub component: Component,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Sbom {
+    pub bom_format: String,
+    pub spec_version: String,
+    pub version: u32,
+    pub metadata: Metadata,
+    pub components: Vec<Component>,
+    pub dependencies: Vec<DependencyEntry>,
+}
+
+/// Package URL of a crate
+pub fn purl(name: &str, version: &str) -> String {
+    format!("pkg:cargo/{}@{}", name, version)
+}
+
+impl Component {
+    pub fn new(name: &str, version: &str, summary: &CrateSummary) -> Self {
+        let mut properties = vec![Property::new("effects", summary.total_effects)];
+        for (kind, n) in &summary.effects_by_type {
+            properties.push(Property::new(&format!("effects:{}", kind), n));
+        }
+        properties.extend([
+            Property::new("unaudited-effects", summary.unaudited_effects),
+            Property::new("unsafe-effects", summary.unsafe_effects),
+            Property::new("audit-status", summary.audit_status()),
+            Property::new("risk-score", summary.risk_score),
+        ]);
+        Self {
+            kind: "library".to_string(),
+            bom_ref: purl(name, version),
+            name: name.to_string(),
+            version: version.to_string(),
+            purl: purl(name, version),
+            properties,
+        }
+    }
+}
+
+impl Sbom {
+    /// An SBOM of the root component and its dependencies, given the
+    /// dependencies of each component by package URL. Dependencies on
+    /// packages which aren't components are left out.
+    pub fn new(
+        root: Component,
+        mut components: Vec<Component>,
+        depends_on: &BTreeMap<String, Vec<String>>,
+    ) -> Self {
+        components.sort_by(|c1, c2| c1.bom_ref.cmp(&c2.bom_ref));
+        let refs = components
+            .iter()
+            .chain([&root])
+            .map(|c| c.bom_ref.as_str())
+            .collect::<BTreeSet<_>>();
+        let dependencies = refs
+            .iter()
+            .map(|r| DependencyEntry {
+                bom_ref: r.to_string(),
+                depends_on: depends_on
+                    .get(*r)
+                    .into_iter()
+                    .flatten()
+                    .filter(|d| refs.contains(d.as_str()))
+                    .cloned()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            bom_format: "CycloneDX".to_string(),
+            spec_version: SPEC_VERSION.to_string(),
+            version: 1,
+            metadata: Metadata {
+                tools: vec![Tool {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                }],
+                component: root,
+            },
+            components,
+            dependencies,
+        }
+    }
+
+    pub fn from_audit_chain(chain: &mut AuditChain) -> Result<Self> {
+        let root_id = chain.root_crate()?;
+        let crate_ids = chain.all_crates().into_iter().cloned().collect::<Vec<_>>();
+        let mut root = None;
+        let mut components = Vec::new();
+        for crate_id in crate_ids {
+            let audit_file = chain
+                .read_audit_file(&crate_id)?
+                .context(format!("Couldn't find audit for {}", crate_id))?;
+            let summary =
+                CrateSummary::from_audit_file(crate_id.to_string(), &audit_file);
+            let version = crate_id.version.to_string();
+            let component = Component::new(&crate_id.crate_name, &version, &summary);
+            if crate_id == root_id {
+                root = Some(component);
+            } else {
+                components.push(component);
+            }
+        }
+        let root = root.context(format!("Couldn't find audit for {}", root_id))?;
+
+        let lockfile = chain.load_lockfile()?;
+        let depends_on = lockfile
+            .packages
+            .iter()
+            .map(|p| {
+                let deps = p
+                    .dependencies
+                    .iter()
+                    .map(|d| purl(d.name.as_str(), &d.version.to_string()))
+                    .collect();
+                (purl(p.name.as_str(), &p.version.to_string()), deps)
+            })
+            .collect();
+
+        Ok(Self::new(root, components, &depends_on))
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(total_effects: usize, unaudited_effects: usize) -> CrateSummary {
+        CrateSummary {
+            crate_id: String::new(),
+            total_effects,
+            unaudited_effects,
+            unsafe_effects: 0,
+            pub_caller_checked: 0,
+            effects_by_type: BTreeMap::from([("FFICall".to_string(), total_effects)]),
+            risk_score: 3 * unaudited_effects as u64,
+        }
+    }
+
+    #[test]
+    fn test_sbom() {
+        let root = Component::new("app", "0.1.0", &summary(0, 0));
+        let libc = Component::new("libc", "0.2.147", &summary(4, 1));
+        let depends_on = BTreeMap::from([(
+            purl("app", "0.1.0"),
+            vec![purl("libc", "0.2.147"), purl("not-scanned", "1.0.0")],
+        )]);
+        let sbom = Sbom::new(root, vec![libc], &depends_on);
+
+        let libc = &sbom.components[0];
+        assert_eq!(libc.purl, "pkg:cargo/libc@0.2.147");
+        let prop = |name: &str| {
+            libc.properties.iter().find(|p| p.name == name).map(|p| p.value.as_str())
+        };
+        assert_eq!(prop("cargo-scan:effects:FFICall"), Some("4"));
+        assert_eq!(prop("cargo-scan:audit-status"), Some("partially-audited"));
+        assert_eq!(prop("cargo-scan:risk-score"), Some("3"));
+
+        assert_eq!(sbom.dependencies[0].bom_ref, "pkg:cargo/app@0.1.0");
+        assert_eq!(sbom.dependencies[0].depends_on, ["pkg:cargo/libc@0.2.147"]);
+
+        let json = serde_json::to_value(&sbom).unwrap();
+        assert_eq!(json["bomFormat"], "CycloneDX");
+        assert_eq!(json["components"][0]["bom-ref"], "pkg:cargo/libc@0.2.147");
+        assert_eq!(json["dependencies"][0]["dependsOn"][0], "pkg:cargo/libc@0.2.147");
+    }
+}
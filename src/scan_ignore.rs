@@ -0,0 +1,166 @@
+//! Excluding files from scans with a `.scanignore` file at the root of the
+//! project, in gitignore syntax, e.g.
+//! ```text
+//! # Generated bindings
+//! src/bindings/
+//! *_generated.rs
+//! !src/schema_generated.rs
+//! ```
+//! The `exclude` patterns of the project config are added before the
+//! patterns of `.scanignore`, so the latter can re-include files.
+//!
+//! The rules are read from the root project only, and apply to the scans of
+//! its dependencies too, relative to each crate's root. The ignore files of
+//! the scanned crates themselves are never read, as a dependency could
+//! otherwise hide its own files from the scan.
+//!
+//! Supported: `#` comments, `!` negations (the last matching pattern wins),
+//! a trailing `/` to match only directories, patterns anchored to the crate
+//! root by a leading or inner `/` (otherwise they match at any depth), and
+//! the wildcards `*`, `?`, and `**`. As in git, files in an ignored directory
+//! can't be re-included.
+
+use crate::project::ProjectConfig;
+
+use anyhow::Result;
+use std::path::{Component, Path};
+
+pub const SCAN_IGNORE_FILE: &str = ".scanignore";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// Pattern segments, split on `/`
+    segments: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        // Patterns without a slash match at any depth
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let mut segments = if anchored { Vec::new() } else { vec!["**".to_string()] };
+        segments.extend(pattern.split('/').map(String::from));
+        Some(Self { segments, negated, dir_only })
+    }
+
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && match_segments(&self.segments, path)
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((p, rest)) if p == "**" => {
+            (0..=path.len()).any(|i| match_segments(rest, &path[i..]))
+        }
+        Some((p, rest)) => match path.split_first() {
+            Some((s, path_rest)) => {
+                match_wildcard(p.as_bytes(), s.as_bytes())
+                    && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match a path segment against a pattern segment with `*` and `?`
+fn match_wildcard(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => (0..=s.len()).any(|i| match_wildcard(rest, &s[i..])),
+        Some((b'?', rest)) => !s.is_empty() && match_wildcard(rest, &s[1..]),
+        Some((c, rest)) => s.first() == Some(c) && match_wildcard(rest, &s[1..]),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanIgnore {
+    rules: Vec<Rule>,
+}
+
+impl ScanIgnore {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        Self { rules: patterns.into_iter().filter_map(Rule::parse).collect() }
+    }
+
+    /// The config excludes and the `.scanignore` patterns of the root
+    /// project, if any
+    pub fn load(crate_path: &Path) -> Result<Self> {
+        let config_excludes =
+            ProjectConfig::read(crate_path)?.map(|c| c.exclude).unwrap_or_default();
+        let ignore_path = crate_path.join(SCAN_IGNORE_FILE);
+        let ignore_file = if ignore_path.is_file() {
+            std::fs::read_to_string(ignore_path)?
+        } else {
+            String::new()
+        };
+        Ok(Self::new(
+            config_excludes.iter().map(String::as_str).chain(ignore_file.lines()),
+        ))
+    }
+
+    fn is_ignored_entry(&self, path: &[&str], is_dir: bool) -> bool {
+        let last_match = self.rules.iter().rev().find(|r| r.matches(path, is_dir));
+        last_match.map_or(false, |r| !r.negated)
+    }
+
+    /// Whether the file, relative to the crate root, is ignored
+    pub fn is_ignored(&self, file: &Path) -> bool {
+        let path = file
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => s.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (1..path.len()).any(|i| self.is_ignored_entry(&path[..i], true))
+            || self.is_ignored_entry(&path, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_ignore() {
+        let ignore = ScanIgnore::new(
+            "# Generated code\n\
+             src/bindings/\n\
+             *_generated.rs\n\
+             !src/keep_generated.rs\n\
+             /src/tests/**/fixture?.rs\n\
+             !src/bindings/mod.rs\n"
+                .lines(),
+        );
+        let ignored = |f: &str| ignore.is_ignored(Path::new(f));
+        assert!(ignored("src/bindings/ffi.rs"));
+        assert!(ignored("src/bindings/mod.rs"));
+        assert!(ignored("src/a/b_generated.rs"));
+        assert!(!ignored("src/keep_generated.rs"));
+        assert!(ignored("src/tests/fixture1.rs"));
+        assert!(ignored("src/tests/a/b/fixture2.rs"));
+        assert!(!ignored("src/tests/fixture10.rs"));
+        assert!(!ignored("src/lib.rs"));
+        // Directory patterns don't match files
+        assert!(!ScanIgnore::new(["lib.rs/"]).is_ignored(Path::new("src/lib.rs")));
+    }
+}
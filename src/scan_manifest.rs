@@ -0,0 +1,210 @@
+//! The inputs of a crate's scan: the files scanned (and the crate's
+//! Cargo.toml) with their hashes, the enabled features, and the version and
+//! configuration of the scanner. Saved with the scan results and audits, so
+//! that a result can be checked to be up-to-date with the crate (e.g. by
+//! `chain create`, which keeps the audits of crates which haven't changed, or
+//! by `chain check`, which refuses audits of crates whose sources changed
+//! since they were scanned).
+
+use crate::effect::EffectType;
+use crate::ident::IdentPath;
+use crate::scanner::{self, ScanConfig};
+
+use anyhow::Result;
+use parse_display::Display;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Version of cargo-scan, as recorded in new manifests
+pub const SCANNER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanManifest {
+    pub scanner_version: String,
+    /// Hash of the scan configuration (see `scanner::scan_config`)
+    pub config_hash: String,
+    /// SHA-256 hash of each scanned file, and of Cargo.toml, by path
+    /// relative to the crate
+    pub files: BTreeMap<PathBuf, String>,
+    /// The features the crate was scanned with, sorted (missing in older
+    /// manifests)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+/// How a file differs from the manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[display(style = "lowercase")]
+pub enum FileChange {
+    Modified,
+    Removed,
+    /// A source file the scan would now include
+    Added,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: PathBuf,
+    pub change: FileChange,
+}
+
+impl fmt::Display for ChangedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.path.to_string_lossy(), self.change)
+    }
+}
+
+impl ScanManifest {
+    /// The manifest of the files scanned in the crate, with the description
+    /// of the scan configuration and the enabled features. Files which can't
+    /// be read weren't scanned either, so they are left out.
+    pub fn new(
+        crate_path: &Path,
+        files: &[PathBuf],
+        config: &str,
+        mut features: Vec<String>,
+    ) -> Self {
+        let mut hashes = BTreeMap::new();
+        let cargo_toml = crate_path.join("Cargo.toml");
+        for file in files.iter().chain([&cargo_toml]) {
+            let Ok(data) = std::fs::read(file) else {
+                continue;
+            };
+            let path = file.strip_prefix(crate_path).unwrap_or(file);
+            hashes.insert(path.to_path_buf(), sha256_hex(&data));
+        }
+        Self {
+            scanner_version: SCANNER_VERSION.to_string(),
+            config_hash: sha256_hex(config.as_bytes()),
+            files: hashes,
+            features: {
+                features.sort();
+                features.dedup();
+                features
+            },
+        }
+    }
+
+    /// The files of the crate which changed since the scan: the files read
+    /// which were modified or removed, and the new files a scan with the
+    /// configuration would read (see `scanner::scanned_files`)
+    pub fn changed_files(
+        &self,
+        crate_path: &Path,
+        relevant_effects: &[EffectType],
+        config: &ScanConfig,
+    ) -> Result<Vec<ChangedFile>> {
+        let mut changed = Vec::new();
+        for (path, hash) in &self.files {
+            let change = match std::fs::read(crate_path.join(path)) {
+                Ok(data) if sha256_hex(&data) == *hash => continue,
+                Ok(_) => FileChange::Modified,
+                Err(_) => FileChange::Removed,
+            };
+            changed.push(ChangedFile { path: path.clone(), change });
+        }
+
+        for file in scanner::scanned_files(crate_path, relevant_effects, config)? {
+            let path = file.strip_prefix(crate_path).unwrap_or(&file);
+            if !self.files.contains_key(path) {
+                changed.push(ChangedFile {
+                    path: path.to_path_buf(),
+                    change: FileChange::Added,
+                });
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Whether a scan of the crate with the extra sinks, this version of the
+    /// scanner, and the configuration would have the same inputs. The
+    /// features are compared if the configuration sets them; otherwise they
+    /// are resolved from the manifest, whose hash is compared.
+    pub fn is_up_to_date(
+        &self,
+        crate_path: &Path,
+        extra_sinks: HashSet<IdentPath>,
+        relevant_effects: &[EffectType],
+        config: &ScanConfig,
+    ) -> Result<bool> {
+        let description =
+            scanner::scan_description(crate_path, extra_sinks, relevant_effects, config)?;
+        let same_features = config.enabled_features.as_ref().map_or(true, |features| {
+            let mut features = features.clone();
+            features.sort();
+            features.dedup();
+            features == self.features
+        });
+        Ok(self.scanner_version == SCANNER_VERSION
+            && self.config_hash == sha256_hex(description.as_bytes())
+            && same_features
+            && self.changed_files(crate_path, relevant_effects, config)?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_manifest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("examples")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"c\"").unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "mod a;").unwrap();
+        std::fs::write(dir.join("src/a.rs"), "fn f() {}").unwrap();
+
+        let config = ScanConfig::default();
+        let up_to_date = |manifest: &ScanManifest, config: &ScanConfig| {
+            manifest.is_up_to_date(dir, HashSet::new(), &[], config).unwrap()
+        };
+        let files = [dir.join("src/lib.rs"), dir.join("src/a.rs")];
+        let description = scanner::scan_description(dir, HashSet::new(), &[], &config);
+        let manifest = ScanManifest::new(dir, &files, &description.unwrap(), vec![]);
+        assert!(manifest.files.contains_key(Path::new("src/a.rs")));
+        assert!(manifest.files.contains_key(Path::new("Cargo.toml")));
+        assert!(up_to_date(&manifest, &config));
+        let mut with_std = config.clone();
+        with_std.enabled_features = Some(vec!["std".to_string()]);
+        assert!(!up_to_date(&manifest, &with_std));
+        let mut examples = config.clone();
+        examples.include_examples = true;
+        assert!(!up_to_date(&manifest, &examples));
+
+        // Examples are only new files if they are scanned
+        std::fs::write(dir.join("examples/demo.rs"), "fn main() {}").unwrap();
+        assert!(up_to_date(&manifest, &config));
+        let changes = |config: &ScanConfig| {
+            manifest
+                .changed_files(dir, &[], config)
+                .unwrap()
+                .iter()
+                .map(ChangedFile::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(changes(&examples), ["examples/demo.rs (added)"]);
+
+        std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"d\"").unwrap();
+        std::fs::write(dir.join("src/a.rs"), "fn f() { g() }").unwrap();
+        std::fs::remove_file(dir.join("src/lib.rs")).unwrap();
+        std::fs::write(dir.join("src/b.rs"), "").unwrap();
+        assert_eq!(
+            changes(&config),
+            [
+                "Cargo.toml (modified)",
+                "src/a.rs (modified)",
+                "src/lib.rs (removed)",
+                "src/b.rs (added)"
+            ]
+        );
+    }
+}
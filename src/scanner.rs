@@ -6,28 +6,284 @@
 use crate::attr_parser::CfgPred;
 use crate::audit_file::EffectInfo;
 
-use super::effect::{Effect, EffectInstance, EffectType, FnDec, SrcLoc, Visibility};
-use super::ident::{CanonicalPath, IdentPath};
+use super::doc_tests;
+use super::edition::{CrateEdition, Edition};
+use super::effect::{
+    is_memory_map_fn, is_spawn_fn, may_be_memory_map_fn, may_be_os_hook, may_be_spawn_fn,
+    os_hook, ClosureContext, Effect, EffectInstance, EffectType, FnDec, MacroOrigin,
+    MalwarePattern, MemOrdering, MemProtection, Provenance, SrcLoc, UnsafeOp, Visibility,
+};
+use super::effect_index::CalleeIndex;
+use super::features::{CrateFeatures, FeatureGates, FeatureReport};
+use super::hacky_resolver;
+use super::ident::{CanonicalPath, CanonicalType, IdentPath, PatternSet};
 use super::loc_tracker::LoCTracker;
-use super::resolve::{FileResolver, Resolve, Resolver};
-use super::sink::Sink;
+use super::macro_expand::{MacroDef, MacroDefs, MacroExpander};
+use super::malware::{self, Taints};
+use super::memory::MemoryAccount;
+use super::metrics::{self, MetricsArgs};
+use super::module_map::ModuleMap;
+use super::resolve::{
+    FileResolver, NoResolver, Resolve, Resolver, ResolverBackend, SharedResolver,
+};
+use super::scan_ignore::ScanIgnore;
+use super::scan_manifest::ScanManifest;
+use super::sink::{self, Sink};
+use super::soundness::{self, SoundnessFinding};
+use super::taxonomy::CustomEffects;
+use super::trait_summary::{self, TraitImpl};
 use super::util;
+use super::util::fs::SymlinkPolicy;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
+use clap::Parser;
 use log::{debug, info, warn};
+use parse_display::Display;
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
-use proc_macro2::{TokenStream, TokenTree};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
 use quote::ToTokens;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fmt::Debug;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path as FilePath;
+use std::fmt::{self, Debug};
+use std::io::Write;
+use std::path::{Path as FilePath, PathBuf as FilePathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use syn::parse::discouraged::Speculative;
+use syn::parse::ParseStream;
 use syn::spanned::Spanned;
 
+/// Time and memory limits for scanning a single crate. Once a crate goes over
+/// budget, the rest of it is scanned in syntactic mode (without name
+/// resolution) and it is marked as partially scanned.
+#[derive(Parser, Debug, Clone, Copy, Default)]
+pub struct ScanBudget {
+    /// Time limit for scanning each crate, in seconds
+    #[clap(long = "scan-timeout")]
+    pub timeout_secs: Option<u64>,
+
+    /// Memory limit for scanning each crate: how much memory the threads
+    /// scanning it may hold, in MB (see `memory`)
+    #[clap(long = "scan-memory-limit")]
+    pub max_memory_mb: Option<u64>,
+}
+
+impl ScanBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.timeout_secs.is_none() && self.max_memory_mb.is_none()
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+}
+
+/// How crates are scanned, besides the sinks and effect types to look for.
+/// The binaries which scan crates share these options (with
+/// `#[clap(flatten)]`), and pass them to [`scan_crate`] and the other scans.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct ScanConfig {
+    /// TOML file defining additional effect kinds (see `taxonomy`)
+    #[clap(long, value_parser = |p: &str| CustomEffects::load(FilePath::new(p)))]
+    pub custom_effects: Option<CustomEffects>,
+
+    #[clap(flatten)]
+    pub budget: ScanBudget,
+
+    #[clap(flatten)]
+    pub metrics: MetricsArgs,
+
+    /// Trace effects in operator and Deref impls of the crate's types to the
+    /// expressions using the operators (slower: resolves operand types)
+    #[clap(long, default_value_t = false)]
+    pub attribute_operators: bool,
+
+    /// Keep calls from private functions which are never called, and from
+    /// #[test] functions, in the call graph instead of pruning them
+    #[clap(long, default_value_t = false)]
+    pub conservative_call_graph: bool,
+
+    /// Also scan examples/ and doc-tests; their effects are marked as coming
+    /// from an example or doc-test, and don't count towards the risk of the
+    /// crate to its dependents
+    #[clap(long, default_value_t = false)]
+    pub include_examples: bool,
+
+    /// Also scan build scripts, and proc-macro crates, which run at compile
+    /// time; they are scanned with more sinks, and their effects are marked
+    /// as build-time
+    #[clap(long, default_value_t = false)]
+    pub build_time: bool,
+
+    /// Name resolution backend: rust-analyzer, or rustc (needs cargo-scan
+    /// built with the rustc-resolver feature on a nightly toolchain)
+    #[clap(long, value_enum, default_value_t = ResolverBackend::RustAnalyzer)]
+    pub resolver: ResolverBackend,
+
+    /// Which symlinks to follow in crate sources: skip (the default; safest
+    /// for untrusted packages), within-root (those resolving inside the
+    /// crate), or all
+    #[clap(long, value_enum, default_value_t = SymlinkPolicy::Skip)]
+    pub symlinks: SymlinkPolicy,
+
+    /// Also find which of the crate's enabled features gate its effects, in
+    /// [`ScanResults::feature_report`]
+    #[clap(skip)]
+    pub feature_report: bool,
+
+    /// The features to scan the crate with, instead of those the resolver
+    /// enables (e.g. the features a dependent resolves for it)
+    #[clap(skip)]
+    pub enabled_features: Option<Vec<String>>,
+
+    /// Print each effect to stdout as a JSON line once the file it is in has
+    /// been scanned, instead of keeping it in the results, so memory doesn't
+    /// grow with the number of effects. Calls from unreachable functions
+    /// aren't pruned from the streamed effects, since they are printed before
+    /// the rest of the crate is scanned.
+    #[clap(skip)]
+    pub stream_effects: bool,
+
+    /// Files to leave out of the scans, from the root project (see
+    /// [`ScanConfig::load_project`])
+    #[clap(skip)]
+    pub scan_ignore: ScanIgnore,
+
+    /// Sink patterns of the root project, added to the scans of all of its
+    /// crates (see [`ScanConfig::load_project`])
+    #[clap(skip)]
+    pub project_sinks: PatternSet,
+}
+
+impl ScanConfig {
+    /// Read the settings of the root project which apply to the scans of all
+    /// of its crates, including its dependencies: the scanned crates' own
+    /// settings are never read, so a dependency can't hide its files or
+    /// effects
+    pub fn load_project(&mut self, root: &FilePath) -> Result<()> {
+        self.scan_ignore = ScanIgnore::load(root)?;
+        self.project_sinks = sink::project_sinks(root)?;
+        Ok(())
+    }
+
+    /// Description of the configuration, for the scan manifest: scans of the
+    /// same files with the same configuration (and scanner version) have the
+    /// same results
+    pub fn describe(
+        &self,
+        sinks: &PatternSet,
+        relevant_effects: &[EffectType],
+    ) -> String {
+        let sinks = sinks.to_strs();
+        let mut effects =
+            relevant_effects.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        effects.sort();
+        format!(
+            "effects={:?} sinks={:?} resolver={} examples={} build_time={} symlinks={} \
+             budget={:?} operators={} conservative={} custom={:?}",
+            effects,
+            sinks,
+            self.resolver,
+            self.include_examples,
+            self.build_time,
+            self.symlinks,
+            self.budget,
+            self.attribute_operators,
+            self.conservative_call_graph,
+            self.custom_effects,
+        )
+    }
+}
+
+/// Set the number of threads scanning crates, and the files of each crate, in
+/// parallel (by default, one per CPU). Each crate scan loads its own name
+/// resolution database, so fewer jobs use less memory; the files of a crate
+/// share its database, which answers one query at a time (see
+/// `SharedResolver`). Errors if it has already been set.
+pub fn set_scan_jobs(jobs: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build_global()
+        .map_err(|_| anyhow!("Scan jobs have already been set"))
+}
+
+static STREAMED_EFFECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of effects streamed so far (see [`ScanConfig::stream_effects`])
+pub fn streamed_effects() -> usize {
+    STREAMED_EFFECTS.load(Ordering::Relaxed)
+}
+
+/// An effect as a line of JSON Lines output
+pub fn ndjson_line(effect: &EffectInstance) -> Result<String> {
+    Ok(serde_json::to_string(effect)?)
+}
+
+/// Print and drop the effects found so far, if streaming
+fn stream_new_effects(
+    scan_results: &mut ScanResults,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) -> Result<()> {
+    if !config.stream_effects {
+        return Ok(());
+    }
+    let mut effects = std::mem::take(&mut scan_results.effects);
+    if let Some(custom) = &config.custom_effects {
+        custom.classify_effects(&mut effects);
+    }
+    let mut out = std::io::stdout().lock();
+    for e in effects
+        .iter()
+        .filter(|e| EffectType::matches_effect(relevant_effects, e.eff_type()))
+    {
+        writeln!(out, "{}", ndjson_line(e)?)?;
+        STREAMED_EFFECTS.fetch_add(1, Ordering::Relaxed);
+        metrics::metrics().add_effects(1);
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The limit a partially scanned crate ran into
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ScanLimit {
+    #[display("time limit")]
+    Timeout,
+    #[display("memory limit")]
+    Memory,
+    #[display("macro expansion limit")]
+    MacroExpansions,
+}
+
+/// A part of a crate which couldn't be scanned: a file which couldn't be read
+/// or parsed, an item with a syntax error, or the whole crate if it couldn't
+/// be loaded for name resolution (in which case it is scanned syntactically)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScanWarning {
+    pub file: FilePathBuf,
+    pub reason: String,
+}
+
+impl ScanWarning {
+    pub fn new(file: &FilePath, reason: String) -> Self {
+        Self { file: file.to_path_buf(), reason }
+    }
+}
+
+impl fmt::Display for ScanWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.reason)
+    }
+}
+
 /// Results of a scan
 ///
 /// Holds the intermediate state between scans which doesn't hold references
@@ -40,9 +296,44 @@ pub struct ScanResults {
     pub pub_fns: HashSet<CanonicalPath>,
     pub fn_locs: HashMap<CanonicalPath, SrcLoc>,
     pub trait_meths: HashSet<CanonicalPath>,
+    /// Methods of operator trait (e.g. Index, Add) and Deref/DerefMut impls,
+    /// with the name of the trait
+    pub operator_meths: HashMap<CanonicalPath, String>,
+    /// Methods of impls of widely-used traits (e.g. Read, Future), with the
+    /// impl they belong to (see `trait_summary`)
+    pub trait_impl_meths: HashMap<CanonicalPath, TraitImpl>,
 
     pub call_graph: DiGraph<CanonicalPath, SrcLoc>,
     pub node_idxs: HashMap<CanonicalPath, NodeIndex>,
+    /// Calls found by the scan which aren't in the call graph yet: they are
+    /// added once the whole crate is scanned (see `link_calls`), as the
+    /// callee may be defined in a file scanned later or in parallel
+    pub pending_calls: Vec<(CanonicalPath, CanonicalPath, SrcLoc)>,
+
+    /// Calls from functions of the crate to functions outside of it, e.g. in
+    /// its dependencies, by caller and callee
+    pub external_calls: HashSet<(CanonicalPath, CanonicalPath)>,
+
+    /// Functions which can only be called from inside the crate: private
+    /// functions outside of trait impls, other than `main` and exported
+    /// (`extern` or `#[no_mangle]`) functions
+    pub private_fns: HashSet<CanonicalPath>,
+    /// Names used other than as the function of a call (e.g. as function
+    /// pointers, or in macros); private functions with these names may be
+    /// called without an edge in the call graph
+    pub referenced_names: HashSet<String>,
+    /// Functions whose code runs on a spawned thread or task: the functions
+    /// passed to a spawn function, and the functions creating the closures
+    /// passed to one (the calls in a closure are edges from the function
+    /// creating it). The spawned code is a new root of the call graph.
+    pub spawn_roots: HashSet<CanonicalPath>,
+    /// Test and benchmark functions (`#[test]`, `#[tokio::test]`, `#[bench]`,
+    /// ...): their effects are reported, but nothing in the crate calls them,
+    /// and their calls are pruned from the call graph
+    pub test_fns: HashSet<CanonicalPath>,
+
+    /// Effects which match a known unsoundness pattern
+    pub soundness_findings: Vec<SoundnessFinding>,
 
     /* Tracking lines of code (LoC) and skipped/unsupported cases */
     pub total_loc: LoCTracker,
@@ -53,6 +344,33 @@ pub struct ScanResults {
     pub skipped_other: LoCTracker,
     pub unsafe_traits: LoCTracker,
     pub unsafe_impls: LoCTracker,
+    pub unsafe_blocks: LoCTracker,
+
+    /// Set if the crate went over its scan budget, in which case part of it
+    /// was only scanned syntactically (or, for the macro expansion budget,
+    /// the rest of its macro calls weren't expanded)
+    pub scan_limit: Option<ScanLimit>,
+
+    /// Parts of the crate which were skipped or only scanned syntactically
+    /// because they are broken
+    pub warnings: Vec<ScanWarning>,
+
+    /// Number of files skipped because of `.scanignore` or the config
+    /// excludes
+    pub ignored_files: usize,
+
+    /// Edition of the crate
+    pub edition: Edition,
+
+    /// Effects by callee, built once the effects are final
+    pub callee_index: CalleeIndex,
+
+    /// The effects removed by disabling each enabled feature, if requested
+    pub feature_report: Option<FeatureReport>,
+
+    /// The files scanned and the scanner configuration, to check whether the
+    /// results are up-to-date
+    pub manifest: ScanManifest,
 
     // TODO other cases:
     pub _effects_loc: LoCTracker,
@@ -68,11 +386,77 @@ impl ScanResults {
         self.effects.iter().collect::<HashSet<_>>()
     }
 
+    /// Number of effects of each kind of unsafe operation
+    pub fn unsafe_op_counts(&self) -> BTreeMap<UnsafeOp, usize> {
+        let mut counts = BTreeMap::new();
+        for op in self.effects.iter().filter_map(|e| e.eff_type().unsafe_op()) {
+            *counts.entry(op).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Add the results of scanning another file of the crate, merging its
+    /// call graph by function
+    pub fn absorb(&mut self, other: ScanResults) {
+        self.effects.extend(other.effects);
+        self.pub_fns.extend(other.pub_fns);
+        self.fn_locs.extend(other.fn_locs);
+        self.trait_meths.extend(other.trait_meths);
+        self.operator_meths.extend(other.operator_meths);
+        self.trait_impl_meths.extend(other.trait_impl_meths);
+        let graph = other.call_graph;
+        let nodes = graph
+            .node_indices()
+            .map(|n| self.update_call_graph(graph[n].clone()))
+            .collect::<Vec<_>>();
+        for e in graph.edge_references() {
+            let (caller, callee) = (nodes[e.source().index()], nodes[e.target().index()]);
+            self.call_graph.add_edge(caller, callee, e.weight().clone());
+        }
+        self.pending_calls.extend(other.pending_calls);
+        self.external_calls.extend(other.external_calls);
+        self.private_fns.extend(other.private_fns);
+        self.referenced_names.extend(other.referenced_names);
+        self.spawn_roots.extend(other.spawn_roots);
+        self.test_fns.extend(other.test_fns);
+        self.soundness_findings.extend(other.soundness_findings);
+        self.total_loc.absorb(&other.total_loc);
+        self.skipped_macros.absorb(&other.skipped_macros);
+        self.skipped_conditional_code.absorb(&other.skipped_conditional_code);
+        self.skipped_fn_calls.absorb(&other.skipped_fn_calls);
+        self.skipped_fn_ptrs.absorb(&other.skipped_fn_ptrs);
+        self.skipped_other.absorb(&other.skipped_other);
+        self.unsafe_traits.absorb(&other.unsafe_traits);
+        self.unsafe_impls.absorb(&other.unsafe_impls);
+        self.unsafe_blocks.absorb(&other.unsafe_blocks);
+        self.scan_limit = self.scan_limit.or(other.scan_limit);
+        self.warnings.extend(other.warnings);
+    }
+
+    /// Add the pending calls to the call graph, for the callees which are
+    /// functions of the crate, and keep the others as external calls
+    pub fn link_calls(&mut self) {
+        for (caller, callee, loc) in std::mem::take(&mut self.pending_calls) {
+            let Some(&caller_node) = self.node_idxs.get(&caller) else {
+                continue;
+            };
+            match self.node_idxs.get(&callee) {
+                Some(&callee_node) => {
+                    self.call_graph.add_edge(caller_node, callee_node, loc);
+                }
+                None => {
+                    self.external_calls.insert((caller, callee));
+                }
+            }
+        }
+    }
+
+    /// Callers which aren't functions in the call graph, e.g. the type of an
+    /// `unsafe impl`, have no callers
     pub fn get_callers(&self, callee: &CanonicalPath) -> Result<HashSet<EffectInfo>> {
-        let callee_node = self
-            .node_idxs
-            .get(callee)
-            .context("Missing callee for get_callers in results graph")?;
+        let Some(callee_node) = self.node_idxs.get(callee) else {
+            return Ok(HashSet::new());
+        };
         let effects = self
             .call_graph
             .edges_directed(*callee_node, Direction::Incoming)
@@ -86,6 +470,90 @@ impl ScanResults {
         Ok(effects)
     }
 
+    /// Get the callers sorted by caller and location, so that effect trees
+    /// built from them are deterministic
+    pub fn get_sorted_callers(&self, callee: &CanonicalPath) -> Result<Vec<EffectInfo>> {
+        let mut callers = self.get_callers(callee)?.into_iter().collect::<Vec<_>>();
+        callers.sort();
+        Ok(callers)
+    }
+
+    /// The effects a function reaches, directly or through calls to other
+    /// functions in the crate
+    pub fn reachable_effects(&self, f: &CanonicalPath) -> Vec<&EffectInstance> {
+        let Some(&node) = self.node_idxs.get(f) else {
+            return vec![];
+        };
+        let mut reachable = HashSet::new();
+        let mut dfs = petgraph::visit::Dfs::new(&self.call_graph, node);
+        while let Some(n) = dfs.next(&self.call_graph) {
+            reachable.insert(&self.call_graph[n]);
+        }
+        self.effects.iter().filter(|e| reachable.contains(e.caller())).collect()
+    }
+
+    /// Operator and Deref impl methods which reach effects (directly or
+    /// through calls to other functions in the crate), with their effects
+    pub fn effectful_operator_meths(
+        &self,
+    ) -> Vec<(&CanonicalPath, &str, Vec<&EffectInstance>)> {
+        let mut result = Vec::new();
+        for (meth, tr) in &self.operator_meths {
+            let effects = self.reachable_effects(meth);
+            if !effects.is_empty() {
+                result.push((meth, tr.as_str(), effects));
+            }
+        }
+        result.sort_by(|a, b| a.0.cmp(b.0));
+        result
+    }
+
+    /// Private functions which aren't reachable in the call graph from any
+    /// function which can be called from outside the crate, or from code
+    /// spawned on another thread. Tests don't count as callers.
+    pub fn unreachable_fns(&self) -> HashSet<&CanonicalPath> {
+        let is_root = |f: &CanonicalPath| {
+            !self.test_fns.contains(f)
+                && (!self.private_fns.contains(f)
+                    || self.spawn_roots.contains(f)
+                    || f.as_str()
+                        .rsplit("::")
+                        .next()
+                        .map_or(true, |name| self.referenced_names.contains(name)))
+        };
+        let mut dfs = petgraph::visit::Dfs::empty(&self.call_graph);
+        let mut reached = HashSet::new();
+        for (f, &node) in &self.node_idxs {
+            if is_root(f) && !reached.contains(&node) {
+                dfs.move_to(node);
+                while let Some(n) = dfs.next(&self.call_graph) {
+                    reached.insert(n);
+                }
+            }
+        }
+        self.private_fns
+            .iter()
+            .filter(|f| self.node_idxs.get(*f).map_or(true, |n| !reached.contains(n)))
+            .collect()
+    }
+
+    /// Remove the calls made by unreachable functions and by tests from the
+    /// call graph, so that they aren't presented as callers to audit. Returns
+    /// the number of calls removed.
+    pub fn prune_unreachable_calls(&mut self) -> usize {
+        let unreachable = self
+            .unreachable_fns()
+            .into_iter()
+            .chain(&self.test_fns)
+            .filter_map(|f| self.node_idxs.get(f).copied())
+            .collect::<HashSet<_>>();
+        let before = self.call_graph.edge_count();
+        self.call_graph.retain_edges(|g, e| {
+            g.edge_endpoints(e).map_or(true, |(caller, _)| !unreachable.contains(&caller))
+        });
+        before - self.call_graph.edge_count()
+    }
+
     pub fn add_fn_dec(&mut self, f: FnDec) {
         let fn_name = f.fn_name;
 
@@ -125,6 +593,11 @@ pub struct Scanner<'a> {
     /// (includes only unsafe blocks and fn decls -- not traits and trait impls)
     scope_unsafe: usize,
 
+    /// Number of explicit unsafe blocks the current scope is nested inside;
+    /// unsafe operations outside of them are only allowed by the enclosing
+    /// unsafe fn (see the unsafe_op_in_unsafe_fn lint)
+    scope_unsafe_blocks: usize,
+
     /// Number of effects found in the current unsafe block
     /// Used only for sanity check / debugging purposes
     scope_unsafe_effects: usize,
@@ -138,14 +611,53 @@ pub struct Scanner<'a> {
     /// Functions inside
     scope_fns: Vec<FnDec>,
 
+    /// Impl blocks inside (innermost last)
+    scope_impls: Vec<&'a syn::ItemImpl>,
+
+    /// Closures inside (within the innermost function)
+    scope_closures: Vec<ClosureContext>,
+
+    /// Number of closures seen so far in each function, to name them
+    closure_counts: HashMap<CanonicalPath, usize>,
+
+    /// Taints of the local variables (within the innermost function) and of
+    /// the constants and statics in the file, for the malware heuristics
+    scope_taints: HashMap<String, Taints>,
+    const_taints: HashMap<String, Taints>,
+
+    /// Local variables (within the innermost function) which are raw
+    /// pointers by their declaration, e.g. `let p: *const u8 = ..`, for when
+    /// the resolver doesn't know their type
+    scope_raw_ptrs: HashSet<String>,
+
+    /// Whether the file is the crate's build script
+    build_script: bool,
+
+    /// Expander for the crate's own macro_rules macros, if any
+    macros: Option<&'a MacroExpander<'a>>,
+
+    /// Macro expansions inside (innermost last)
+    scope_macros: Vec<MacroOrigin>,
+
+    /// Number of macro calls expanded in the file so far
+    macro_expansions: usize,
+
     /// Target to accumulate scan results
     data: &'a mut ScanResults,
 
-    /// The list of sinks to look for
-    sinks: HashSet<IdentPath>,
+    /// The sinks to look for, built once per scan and shared by its files
+    sinks: Cow<'a, PatternSet>,
 
     /// The set of enabled cfg options for this crate.
     enabled_cfg: &'a HashMap<String, Vec<String>>,
+
+    /// The effect types to scan for, or None to scan for all effects.
+    /// Checks for effects which aren't selected are skipped entirely.
+    effect_types: Option<Vec<EffectType>>,
+
+    /// Whether to add call graph edges for overloaded operators (see
+    /// `ScanConfig`)
+    attribute_operators: bool,
 }
 
 impl<'a> Scanner<'a> {
@@ -164,12 +676,25 @@ impl<'a> Scanner<'a> {
             filepath,
             resolver,
             scope_unsafe: 0,
+            scope_unsafe_blocks: 0,
             scope_unsafe_effects: 0,
             scope_assign_lhs: false,
             scope_fns: Vec::new(),
+            scope_impls: Vec::new(),
+            scope_closures: Vec::new(),
+            closure_counts: HashMap::new(),
+            scope_taints: HashMap::new(),
+            scope_raw_ptrs: HashSet::new(),
+            const_taints: HashMap::new(),
+            build_script: malware::is_build_script(filepath),
+            macros: None,
+            scope_macros: Vec::new(),
+            macro_expansions: 0,
             data,
-            sinks: Sink::default_sinks(),
+            sinks: Cow::Owned(Sink::default_sinks()),
             enabled_cfg,
+            effect_types: None,
+            attribute_operators: false,
         }
     }
 
@@ -177,12 +702,38 @@ impl<'a> Scanner<'a> {
     pub fn assert_top_level_invariant(&self) {
         self.resolver.assert_top_level_invariant();
         debug_assert!(self.scope_fns.is_empty());
+        debug_assert!(self.scope_macros.is_empty());
         debug_assert_eq!(self.scope_unsafe, 0);
+        debug_assert_eq!(self.scope_unsafe_blocks, 0);
         debug_assert_eq!(self.scope_unsafe_effects, 0);
     }
 
-    pub fn add_sinks(&mut self, new_sinks: HashSet<IdentPath>) {
-        self.sinks.extend(new_sinks);
+    pub fn add_sinks(&mut self, new_sinks: &PatternSet) {
+        self.sinks.to_mut().extend(new_sinks);
+    }
+
+    /// Look for the sinks instead of the default ones
+    pub fn set_sinks(&mut self, sinks: &'a PatternSet) {
+        self.sinks = Cow::Borrowed(sinks);
+    }
+
+    /// Only scan for the given effect types
+    pub fn set_effect_types(&mut self, effect_types: &[EffectType]) {
+        self.effect_types = Some(effect_types.to_vec());
+    }
+
+    /// Scan with the options of the configuration
+    pub fn set_config(&mut self, config: &ScanConfig) {
+        self.attribute_operators = config.attribute_operators;
+    }
+
+    /// Expand calls to the crate's macro_rules macros and scan the expansions
+    pub fn set_macros(&mut self, macros: &'a MacroExpander<'a>) {
+        self.macros = Some(macros);
+    }
+
+    fn enabled(&self, t: EffectType) -> bool {
+        self.effect_types.as_ref().map_or(true, |ts| ts.contains(&t))
     }
 
     /*
@@ -211,14 +762,24 @@ impl<'a> Scanner<'a> {
             syn::Item::Fn(fun) => self.scan_fn_decl(fun),
             syn::Item::Trait(t) => self.scan_trait(t),
             syn::Item::ForeignMod(fm) => self.scan_foreign_mod(fm),
+            syn::Item::Type(t) => self.resolver.scan_type_alias(t),
             syn::Item::Macro(m) => {
+                if let Some((def, items)) = self.expand_items(&m.mac) {
+                    self.scan_expansion(def, |s| {
+                        for i in items {
+                            s.scan_item(i);
+                        }
+                    });
+                    return;
+                }
+                self.scan_referenced_names(m.mac.tokens.clone());
                 self.data.skipped_macros.add(m);
             }
+            syn::Item::Const(c) => self.scan_const(&c.ident, &c.expr),
+            syn::Item::Static(s) => self.scan_const(&s.ident, &s.expr),
             _ => (),
             // For all syntax elements see
             // https://docs.rs/syn/latest/syn/enum.Item.html
-            // Potentially interesting:
-            // Const(ItemConst), Static(ItemStatic) -- for information flow
         }
     }
 
@@ -340,6 +901,7 @@ impl<'a> Scanner<'a> {
                     self.scan_trait_method(m, &t.vis, impl_methods);
                 }
                 syn::TraitItem::Macro(m) => {
+                    self.scan_referenced_names(m.mac.tokens.clone());
                     self.data.skipped_macros.add(m);
                 }
                 syn::TraitItem::Verbatim(v) => {
@@ -359,17 +921,42 @@ impl<'a> Scanner<'a> {
         }
 
         self.resolver.push_impl(imp);
+        self.scope_impls.push(imp);
 
+        let mut operator_trait = None;
+        let mut summarized_impl = None;
         if let Some((_, tr, _)) = &imp.trait_ {
             self.scan_impl_trait_path(tr, imp);
+            let tr_name = tr.segments.last().map(|s| s.ident.to_string());
+            operator_trait =
+                tr_name.clone().filter(|t| OPERATOR_TRAITS.contains(&t.as_str()));
+            if tr_name
+                .map_or(false, |t| trait_summary::SUMMARIZED_TRAITS.contains(&t.as_str()))
+            {
+                let self_ty = match imp.self_ty.as_ref() {
+                    syn::Type::Path(p) => self.resolver.resolve_path(&p.path).to_string(),
+                    ty => ty.to_token_stream().to_string(),
+                };
+                summarized_impl =
+                    Some(TraitImpl::new(self.resolver.resolve_path(tr), self_ty));
+            }
         }
 
         for item in &imp.items {
             match item {
                 syn::ImplItem::Fn(m) => {
-                    self.scan_method(m);
+                    if let Some(tr) = &operator_trait {
+                        let f_name = self.resolver.resolve_def(&m.sig.ident);
+                        self.data.operator_meths.insert(f_name, tr.clone());
+                    }
+                    if let Some(trait_impl) = &summarized_impl {
+                        let f_name = self.resolver.resolve_def(&m.sig.ident);
+                        self.data.trait_impl_meths.insert(f_name, trait_impl.clone());
+                    }
+                    self.scan_method(m, imp.trait_.is_some());
                 }
                 syn::ImplItem::Macro(m) => {
+                    self.scan_referenced_names(m.mac.tokens.clone());
                     self.data.skipped_macros.add(m);
                 }
                 syn::ImplItem::Verbatim(v) => {
@@ -381,29 +968,33 @@ impl<'a> Scanner<'a> {
             }
         }
 
+        self.scope_impls.pop();
         self.resolver.pop_impl();
     }
 
     fn scan_impl_trait_path(&mut self, tr: &'a syn::Path, imp: &'a syn::ItemImpl) {
         if imp.unsafety.is_some() {
             // we found an `unsafe impl` declaration
-            // let tr_name = self.resolver.resolve_path(tr);
-            // let self_ty = imp
-            //     .self_ty
-            //     .to_token_stream()
-            //     .into_iter()
-            //     .filter_map(|token| match token {
-            //         TokenTree::Ident(i) => Some(i),
-            //         _ => None,
-            //     })
-            //     .last();
-            // // resolve the implementing type of the trait, if there is one
-            // let tr_type = match &self_ty {
-            //     Some(ident) => Some(self.resolver.resolve_ident(ident)),
-            //     _ => None,
-            // };
-
             self.data.unsafe_impls.add(tr);
+            if !self.enabled(EffectType::UnsafeTraitImpl) {
+                return;
+            }
+
+            // There is no containing function, so attribute the effect to the
+            // implementing type (or the trait if the type isn't a path)
+            let tr_name = self.resolver.resolve_path(tr);
+            let self_ty = match imp.self_ty.as_ref() {
+                syn::Type::Path(p) => self.resolver.resolve_path(&p.path),
+                _ => tr_name.clone(),
+            };
+            let eff = EffectInstance::new_effect(
+                self.filepath,
+                self_ty,
+                tr_name.clone(),
+                &imp.impl_token,
+                Effect::UnsafeTraitImpl(tr_name),
+            );
+            self.data.effects.push(eff);
         }
     }
 
@@ -419,7 +1010,8 @@ impl<'a> Scanner<'a> {
             return;
         }
 
-        self.scan_fn(&f.sig, &f.block, &f.vis);
+        let private = is_private(&f.vis, &f.sig, &f.attrs);
+        self.scan_fn(&f.sig, &f.block, &f.vis, private, is_test(&f.attrs));
     }
 
     fn scan_trait_method(
@@ -437,7 +1029,7 @@ impl<'a> Scanner<'a> {
         // Otherwise, just create a node in the call graph for the abstract trait method.
         let f_name = self.resolver.resolve_def(&m.sig.ident);
         if let Some(body) = &m.default {
-            self.scan_fn(&m.sig, body, vis);
+            self.scan_fn(&m.sig, body, vis, false, false);
         } else {
             // Update call graph
             self.data.update_call_graph(f_name.clone());
@@ -468,14 +1060,15 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn scan_method(&mut self, m: &'a syn::ImplItemFn) {
+    fn scan_method(&mut self, m: &'a syn::ImplItemFn, trait_impl: bool) {
         if self.skip_attrs(&m.attrs) {
             self.data.skipped_conditional_code.add(m);
             return;
         }
 
         // NB: may or may not be a method, if there is no self keyword
-        self.scan_fn(&m.sig, &m.block, &m.vis);
+        let private = !trait_impl && is_private(&m.vis, &m.sig, &m.attrs);
+        self.scan_fn(&m.sig, &m.block, &m.vis, private, is_test(&m.attrs));
     }
 
     fn scan_fn(
@@ -483,11 +1076,19 @@ impl<'a> Scanner<'a> {
         f_sig: &'a syn::Signature,
         body: &'a syn::Block,
         vis: &'a syn::Visibility,
+        private: bool,
+        test: bool,
     ) {
         // Create fn decl
         let f_ident = &f_sig.ident;
         let f_name = self.resolver.resolve_def(f_ident);
         let fn_dec = FnDec::new(self.filepath, f_sig, f_name.clone(), vis);
+        if private {
+            self.data.private_fns.insert(f_name.clone());
+        }
+        if test {
+            self.data.test_fns.insert(f_name.clone());
+        }
 
         // Always push the new function declaration before scanning the
         // body so we have access to the function its in
@@ -505,12 +1106,22 @@ impl<'a> Scanner<'a> {
             self.scope_unsafe += 1;
         }
 
+        // Closures around a nested function aren't part of its context
+        let scope_closures = std::mem::take(&mut self.scope_closures);
+        let scope_taints = std::mem::take(&mut self.scope_taints);
+        let scope_raw_ptrs = std::mem::take(&mut self.scope_raw_ptrs);
+
         // ***** Scan body *****
+        let first_effect = self.data.effects.len();
         for s in &body.stmts {
             self.scan_fn_statement(s);
         }
+        self.check_soundness(f_sig, body, &f_name, first_effect);
 
         // Reset state
+        self.scope_closures = scope_closures;
+        self.scope_taints = scope_taints;
+        self.scope_raw_ptrs = scope_raw_ptrs;
         self.scope_fns.pop();
         self.resolver.pop_fn();
 
@@ -525,15 +1136,104 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// Check the function's effects (those after `first_effect`, other than
+    /// the effects of nested functions) for unsoundness patterns
+    fn check_soundness(
+        &mut self,
+        f_sig: &syn::Signature,
+        body: &syn::Block,
+        f_name: &CanonicalPath,
+        first_effect: usize,
+    ) {
+        let findings = self.data.effects[first_effect..]
+            .iter()
+            .filter(|e| e.caller() == f_name)
+            .filter_map(|e| {
+                let self_ty = self.scope_impls.last().map(|imp| imp.self_ty.as_ref());
+                let lint = soundness::check_effect(f_sig, self_ty, body, e)?;
+                Some(SoundnessFinding { lint, effect: e.clone() })
+            })
+            .collect::<Vec<_>>();
+        self.data.soundness_findings.extend(findings);
+    }
+
     fn scan_fn_statement(&mut self, s: &'a syn::Stmt) {
         match s {
             syn::Stmt::Local(l) => self.scan_fn_local(l),
             syn::Stmt::Expr(e, _semi) => self.scan_expr(e),
             syn::Stmt::Item(i) => self.scan_item_in_fn(i),
-            syn::Stmt::Macro(m) => {
-                self.data.skipped_macros.add(m);
+            syn::Stmt::Macro(m) => self.scan_macro_call(&m.mac, m),
+        }
+    }
+
+    /// Scan a macro call in a function: its expansion if it is one of the
+    /// crate's macros, otherwise the names it references
+    fn scan_macro_call<S>(&mut self, mac: &'a syn::Macro, m: &S)
+    where
+        S: ToTokens,
+    {
+        if let Some((def, stmts)) = self.expand_stmts(mac) {
+            self.scan_expansion(def, |s| {
+                for st in stmts {
+                    s.scan_fn_statement(st);
+                }
+            });
+            return;
+        }
+        self.scan_referenced_names(mac.tokens.clone());
+        self.data.skipped_macros.add(m);
+    }
+
+    fn expand_stmts(
+        &mut self,
+        mac: &'a syn::Macro,
+    ) -> Option<(&'a MacroDef, &'a [syn::Stmt])> {
+        let macros = self.macros?;
+        self.take_expansion_budget().then(|| macros.expand_stmts(mac))?
+    }
+
+    fn expand_items(
+        &mut self,
+        mac: &'a syn::Macro,
+    ) -> Option<(&'a MacroDef, &'a [syn::Item])> {
+        let macros = self.macros?;
+        self.take_expansion_budget().then(|| macros.expand_items(mac))?
+    }
+
+    /// Whether another macro call can be expanded: the nesting depth is
+    /// bounded, and so is the number of expansions in the file, since a
+    /// macro calling itself twice expands exponentially many times. Over the
+    /// budget, the crate is marked as partially scanned.
+    fn take_expansion_budget(&mut self) -> bool {
+        if self.scope_macros.len() >= MAX_MACRO_DEPTH {
+            return false;
+        }
+        if self.macro_expansions >= MAX_MACRO_EXPANSIONS {
+            if self.data.scan_limit.is_none() {
+                warn!(
+                    "Macro expansion limit reached in {}; the remaining macro calls \
+                    are not expanded",
+                    self.filepath.to_string_lossy()
+                );
+                self.data.scan_limit = Some(ScanLimit::MacroExpansions);
             }
+            return false;
         }
+        self.macro_expansions += 1;
+        true
+    }
+
+    /// Scan a macro expansion. Tokens in the expansion don't all have their own
+    /// locations, so names in it are resolved syntactically.
+    fn scan_expansion<F>(&mut self, def: &MacroDef, scan: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.scope_macros.push(def.origin());
+        self.resolver.set_syntactic(true);
+        scan(self);
+        self.scope_macros.pop();
+        self.resolver.set_syntactic(!self.scope_macros.is_empty());
     }
 
     fn scan_item_in_fn(&mut self, i: &'a syn::Item) {
@@ -552,6 +1252,31 @@ impl<'a> Scanner<'a> {
             if let Some((_, else_expr)) = &let_expr.diverge {
                 self.scan_expr(else_expr);
             }
+            if self.enabled(EffectType::Malware) {
+                let taints = self.expr_taints(&let_expr.expr);
+                malware::bind_pattern(&l.pat, taints, &mut self.scope_taints);
+            }
+        }
+        self.bind_raw_ptr(&l.pat, l.init.as_ref().map(|i| &*i.expr));
+    }
+
+    /// Record whether the local variable is a raw pointer, by its type or
+    /// its initializer
+    fn bind_raw_ptr(&mut self, pat: &'a syn::Pat, init: Option<&'a syn::Expr>) {
+        let (ident, is_raw_ptr) = match pat {
+            syn::Pat::Ident(i) => {
+                (&i.ident, init.is_some_and(|e| self.expr_is_raw_ptr(e)))
+            }
+            syn::Pat::Type(t) => match &*t.pat {
+                syn::Pat::Ident(i) => (&i.ident, matches!(*t.ty, syn::Type::Ptr(_))),
+                _ => return,
+            },
+            _ => return,
+        };
+        if is_raw_ptr {
+            self.scope_raw_ptrs.insert(ident.to_string());
+        } else {
+            self.scope_raw_ptrs.remove(&ident.to_string());
         }
     }
 
@@ -582,6 +1307,9 @@ impl<'a> Scanner<'a> {
                 self.scan_expr(&x.base);
             }
             syn::Expr::Binary(x) => {
+                if let Some(method) = binop_method(&x.op) {
+                    self.push_operator_call(x, &x.left, method);
+                }
                 self.scan_expr(&x.left);
                 self.scan_expr(&x.right);
             }
@@ -606,19 +1334,14 @@ impl<'a> Scanner<'a> {
                 self.scan_expr_call_args(&x.args);
                 // Function call
                 self.scan_expr_call(&x.func);
+                self.scan_fence(x);
+                self.scan_spawn_call(x);
+                self.scan_os_hook_call(x);
+                self.scan_memory_map_call(x);
+                self.scan_malware_call(x);
             }
             syn::Expr::Cast(x) => {
-                // If we see a cast to a raw pointer, add the effect
-                if let syn::Type::Ptr(_) = *x.ty {
-                    let mut tokens: TokenStream = TokenStream::new();
-                    (*x.expr).to_tokens(&mut tokens);
-                    tokens.into_iter().for_each(|tt| {
-                        if let TokenTree::Ident(i) = tt {
-                            let p = self.resolver.resolve_field(&i);
-                            self.push_effect(x.span(), p, Effect::RawPtrCast)
-                        }
-                    });
-                }
+                self.scan_cast(x);
                 self.scan_expr(&x.expr);
             }
             syn::Expr::Closure(x) => {
@@ -626,7 +1349,7 @@ impl<'a> Scanner<'a> {
                 // Note that the body expression doesn't get evaluated yet,
                 // and may be evaluated somewhere else.
                 // May need to do something more special here.
-                self.scan_closure(x);
+                self.scan_closure(x, None);
             }
             syn::Expr::Continue(_) => (),
             syn::Expr::Field(x) => {
@@ -652,6 +1375,8 @@ impl<'a> Scanner<'a> {
                 }
             }
             syn::Expr::Index(x) => {
+                let method = if self.scope_assign_lhs { "index_mut" } else { "index" };
+                self.push_operator_call(x, &x.expr, method);
                 self.scan_expr(&x.expr);
                 self.scan_expr(&x.index);
             }
@@ -664,9 +1389,7 @@ impl<'a> Scanner<'a> {
                     self.scan_fn_statement(s);
                 }
             }
-            syn::Expr::Macro(m) => {
-                self.data.skipped_macros.add(m);
-            }
+            syn::Expr::Macro(m) => self.scan_macro_call(&m.mac, m),
             syn::Expr::Match(x) => {
                 self.scan_expr(&x.expr);
                 for a in &x.arms {
@@ -680,10 +1403,22 @@ impl<'a> Scanner<'a> {
                 // ***** THE SECOND IMPORTANT CASE *****
                 // Receiver object
                 self.scan_expr(&x.receiver);
-                // Arguments
-                self.scan_expr_call_args(&x.args);
+                // Arguments; closures passed directly are named after the
+                // method, e.g. the closure in .map(|x| ..)
+                for y in x.args.iter() {
+                    match y {
+                        syn::Expr::Closure(c) => self.scan_closure(c, Some(&x.method)),
+                        _ => self.scan_expr(y),
+                    }
+                }
                 // Function call
-                self.scan_expr_call_method(&x.method);
+                self.scan_expr_call_method(x);
+                self.scan_ptr_arithmetic(x);
+                self.scan_relaxed_atomic(x);
+                self.scan_spawn_method(x);
+                self.scan_os_hook_method(x);
+                self.scan_memory_map_method(x);
+                self.scan_malware_method(x);
             }
             syn::Expr::Paren(x) => {
                 self.scan_expr(&x.expr);
@@ -701,6 +1436,7 @@ impl<'a> Scanner<'a> {
                 }
             }
             syn::Expr::Reference(x) => {
+                self.scan_mut_reborrow(x);
                 self.scan_expr(&x.expr);
             }
             syn::Expr::Repeat(x) => {
@@ -740,6 +1476,16 @@ impl<'a> Scanner<'a> {
                 }
             }
             syn::Expr::Unary(x) => {
+                let method = match x.op {
+                    syn::UnOp::Deref(_) if self.scope_assign_lhs => "deref_mut",
+                    syn::UnOp::Deref(_) => "deref",
+                    syn::UnOp::Not(_) => "not",
+                    syn::UnOp::Neg(_) => "neg",
+                    _ => "",
+                };
+                if !method.is_empty() {
+                    self.push_operator_call(x, &x.expr, method);
+                }
                 if let syn::UnOp::Deref(_) = x.op {
                     self.scan_deref(&x.expr);
                 }
@@ -769,9 +1515,20 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan_path(&mut self, x: &'a syn::Path) {
-        let ty = self.resolver.resolve_path_type(x);
+        if let Some(s) = x.segments.last() {
+            self.data.referenced_names.insert(s.ident.to_string());
+        }
+        let fn_ptrs = self.enabled(EffectType::FnPtrCreation);
+        let static_mut = self.enabled(EffectType::StaticMut);
+        // Resolving the type is expensive, so skip it if neither effect that
+        // needs it is being scanned for
+        let ty = (fn_ptrs || static_mut).then(|| self.resolver.resolve_path_type(x));
+        let Some(ty) = ty else {
+            self.scan_static_ext(x);
+            return;
+        };
         // Function pointer creation
-        if ty.is_function() || ty.is_fn_ptr() {
+        if fn_ptrs && (ty.is_function() || ty.is_fn_ptr()) {
             // Skip constant or immutable static function pointers
             if self.resolver.resolve_const_or_static(x) {
                 self.syn_info("Skipping const or static item", x);
@@ -782,34 +1539,64 @@ impl<'a> Scanner<'a> {
             }
         }
         // Accessing a mutable global variable
-        if ty.is_mut_static() {
+        if static_mut && ty.is_mut_static() {
             let cp = self.resolver.resolve_path(x);
             // NOTE: Can only be done in an unsafe block
             self.push_effect(x.span(), cp.clone(), Effect::StaticMut(cp));
         }
-        // Accessing an external static variable
-        if self.resolver.resolve_ffi(x).is_some() {
+        self.scan_static_ext(x);
+    }
+
+    // Accessing an external static variable
+    fn scan_static_ext(&mut self, x: &'a syn::Path) {
+        if self.enabled(EffectType::StaticExt) && self.resolver.resolve_ffi(x).is_some() {
             let cp = self.resolver.resolve_path(x);
             // NOTE: Can only be done in an unsafe block
             self.push_effect(x.span(), cp.clone(), Effect::StaticExt(cp));
         }
     }
 
-    fn scan_closure(&mut self, x: &'a syn::ExprClosure) {
+    fn scan_closure(&mut self, x: &'a syn::ExprClosure, adapter: Option<&'a syn::Ident>) {
         self.syn_debug("scanning closure", x);
 
+        // Effects in the closure are attributed to the enclosing function,
+        // with the closure recorded as their context
+        let enclosing_fn = self.scope_fns.last().map(|f| f.fn_name.clone());
+        let closure = enclosing_fn.as_ref().map(|f| {
+            let count = self.closure_counts.entry(f.clone()).or_default();
+            let index = *count;
+            *count += 1;
+            ClosureContext { adapter: adapter.map(|a| a.to_string()), index }
+        });
+        if let Some(closure) = &closure {
+            self.scope_closures.push(closure.clone());
+        }
+
         let effects_num = self.data.effects.len();
         // Scan closure's body first. If it does not contain
         // any effects, the closure is not dangerous and
         // we do not create a new effect instance for it.
         self.scan_expr(&x.body);
+
+        if closure.is_some() {
+            self.scope_closures.pop();
+        }
         if self.data.effects.len() > effects_num {
-            let cl_name = self.resolver.resolve_closure(x);
+            let cl_name = match (enclosing_fn, &closure) {
+                (Some(mut f), Some(closure)) => {
+                    f.push_ident(&closure.ident());
+                    f.add_src_loc(SrcLoc::from_span(self.filepath, x))
+                }
+                _ => self.resolver.resolve_closure(x),
+            };
             self.push_effect(x.span(), cl_name, Effect::ClosureCreation);
         }
     }
 
     fn scan_deref(&mut self, x: &'a syn::Expr) {
+        if !self.enabled(EffectType::RawPointer) {
+            return;
+        }
         let mut tokens: TokenStream = TokenStream::new();
         x.to_tokens(&mut tokens);
         tokens.into_iter().for_each(|tt| {
@@ -824,72 +1611,508 @@ impl<'a> Scanner<'a> {
         });
     }
 
-    // Check if the field being accessed is a Union field
-    fn scan_field_access(&mut self, x: &'a syn::ExprField) {
-        if let syn::Member::Named(i) = &x.member {
-            let ty = self.resolver.resolve_field_type(i);
-            if !ty.is_union_field() || self.scope_assign_lhs {
-                return;
-            }
-            let cp = self.resolver.resolve_field(i);
-            // NOTE: Can only be done in an unsafe block
-            self.push_effect(x.span(), cp.clone(), Effect::UnionField(cp));
+    fn scan_cast(&mut self, x: &'a syn::ExprCast) {
+        let to_ptr = matches!(*x.ty, syn::Type::Ptr(_));
+        let int_ptr_cast = self.enabled(EffectType::IntPtrCast)
+            && ((to_ptr && self.expr_is_int(&x.expr))
+                || (is_int_type(&x.ty) && self.expr_is_raw_ptr(&x.expr)));
+
+        if int_ptr_cast {
+            // Integer <-> raw pointer casts are reported once, for the value
+            // being cast
+            let val = match &*x.expr {
+                syn::Expr::Path(p) => self.resolver.resolve_path(&p.path),
+                other => CanonicalPath::new(&other.to_token_stream().to_string()),
+            };
+            self.push_effect(x.span(), val.clone(), Effect::IntPtrCast(val));
+        } else if to_ptr && self.enabled(EffectType::RawPtrCast) {
+            // If we see a cast to a raw pointer, add the effect
+            let mut tokens: TokenStream = TokenStream::new();
+            (*x.expr).to_tokens(&mut tokens);
+            tokens.into_iter().for_each(|tt| {
+                if let TokenTree::Ident(i) = tt {
+                    let p = self.resolver.resolve_field(&i);
+                    self.push_effect(x.span(), p, Effect::RawPtrCast)
+                }
+            });
         }
     }
 
-    fn scan_unsafe_block(&mut self, x: &'a syn::ExprUnsafe) {
-        self.scope_unsafe += 1;
-        for s in &x.block.stmts {
-            self.scan_fn_statement(s);
+    // Check for `&mut *p` where `p` is a `*const` pointer cast to `*mut`,
+    // which is how a `&mut` is commonly derived from a shared reference
+    fn scan_mut_reborrow(&mut self, x: &'a syn::ExprReference) {
+        if x.mutability.is_none() || !self.enabled(EffectType::AliasEscape) {
+            return;
         }
-
-        // Reset unsafety
-        debug_assert!(self.scope_unsafe >= 1);
-        self.scope_unsafe -= 1;
-        if self.scope_unsafe_effects == 0 {
-            self.syn_debug("unsafe block without any unsafe effects", x)
+        let syn::Expr::Unary(syn::ExprUnary { op: syn::UnOp::Deref(_), expr, .. }) =
+            &*x.expr
+        else {
+            return;
+        };
+        if let Some(ptr) = self.const_to_mut_ptr(expr) {
+            self.push_effect(x.span(), ptr.clone(), Effect::AliasEscape(ptr));
         }
-        self.scope_unsafe_effects = 0;
     }
 
-    /*
-        Function calls --what we're interested in
-    */
-    fn scan_expr_call_args(
-        &mut self,
-        a: &'a syn::punctuated::Punctuated<syn::Expr, syn::token::Comma>,
-    ) {
-        for y in a.iter() {
-            self.scan_expr(y);
+    /// If the expression casts a shared reference or `*const` pointer to a
+    /// `*mut` pointer, returns the path of the value being cast
+    fn const_to_mut_ptr(&self, x: &'a syn::Expr) -> Option<CanonicalPath> {
+        match x {
+            syn::Expr::Paren(p) => self.const_to_mut_ptr(&p.expr),
+            syn::Expr::Cast(c) => {
+                let to_mut =
+                    matches!(&*c.ty, syn::Type::Ptr(p) if p.mutability.is_some());
+                let from_const = match &*c.expr {
+                    syn::Expr::Cast(inner) => {
+                        matches!(&*inner.ty, syn::Type::Ptr(p) if p.const_token.is_some())
+                    }
+                    syn::Expr::Reference(r) => r.mutability.is_none(),
+                    _ => false,
+                };
+                (to_mut && from_const).then(|| self.innermost_path(&c.expr))
+            }
+            syn::Expr::MethodCall(m) if m.method == "cast_mut" => {
+                Some(self.innermost_path(&m.receiver))
+            }
+            _ => None,
         }
     }
 
-    /// Push an effect into the current `EffectBlock`. Should be used when
-    /// pushing an effect in an unsafe block so all effects can be captured at
-    /// the same time.
-    fn push_effect<S>(&mut self, eff_span: S, callee: CanonicalPath, eff_type: Effect)
-    where
-        S: Debug + Spanned,
-    {
-        let containing_fn = self.scope_fns.last().expect("not inside a function!");
-        let caller = &containing_fn.fn_name;
+    /// Resolves the innermost path of a chain of casts and references, e.g.
+    /// `x` in `&x as *const T`, or the field in `&self.x as *const T`
+    fn innermost_path(&self, x: &'a syn::Expr) -> CanonicalPath {
+        match x {
+            syn::Expr::Cast(c) => self.innermost_path(&c.expr),
+            syn::Expr::Reference(r) => self.innermost_path(&r.expr),
+            syn::Expr::Paren(p) => self.innermost_path(&p.expr),
+            syn::Expr::Unary(u) => self.innermost_path(&u.expr),
+            syn::Expr::Path(p) => self.resolver.resolve_path(&p.path),
+            syn::Expr::Field(f) => match &f.member {
+                syn::Member::Named(i) => self.resolver.resolve_field(i),
+                syn::Member::Unnamed(idx) => self.resolver.resolve_field_index(idx),
+            },
+            syn::Expr::MethodCall(m) => self.resolver.resolve_method(&m.method),
+            syn::Expr::Call(c) => self.innermost_path(&c.func),
+            other => {
+                // Named by its identifiers: the tokens would print as e.g.
+                // `a . b`, which isn't a path
+                let idents = other
+                    .to_token_stream()
+                    .into_iter()
+                    .filter_map(|tt| match tt {
+                        TokenTree::Ident(i) => Some(i.to_string()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                CanonicalPath::new(&idents.join("::"))
+            }
+        }
+    }
 
-        let eff = EffectInstance::new_effect(
-            self.filepath,
-            caller.clone(),
-            callee,
-            &eff_span,
-            eff_type,
-        );
+    // Check for memory fences, e.g. `fence(Ordering::SeqCst)`
+    fn scan_fence(&mut self, x: &'a syn::ExprCall) {
+        if !self.enabled(EffectType::Atomic) {
+            return;
+        }
+        let syn::Expr::Path(p) = &*x.func else {
+            return;
+        };
+        let is_fence = p
+            .path
+            .segments
+            .last()
+            .map_or(false, |s| s.ident == "fence" || s.ident == "compiler_fence");
+        if !is_fence {
+            return;
+        }
+        if let Some(ordering) = x.args.iter().find_map(mem_ordering) {
+            let cp = self.resolver.resolve_path(&p.path);
+            self.push_effect(x.span(), cp.clone(), Effect::Atomic(cp, ordering));
+        }
+    }
 
-        if self.scope_unsafe > 0 && eff.is_rust_unsafe() {
-            self.scope_unsafe_effects += 1;
+    // Check for calls spawning a thread or task, e.g. `thread::spawn(|| ..)`
+    fn scan_spawn_call(&mut self, x: &'a syn::ExprCall) {
+        let syn::Expr::Path(p) = &*x.func else {
+            return;
+        };
+        let Some(name) = p.path.segments.last() else {
+            return;
+        };
+        if !may_be_spawn_fn(&name.ident.to_string()) {
+            return;
+        }
+        let cp = self.resolver.resolve_path(&p.path);
+        if is_spawn_fn(&cp) {
+            self.push_spawn(x.span(), cp, &x.args);
         }
-        self.data.effects.push(eff);
     }
 
-    /// push an Effect to the list of results based on this call site.
-    fn push_callsite<S>(
+    // Check for method calls spawning a thread or task, e.g.
+    // `thread::Builder::new().spawn(|| ..)` or `runtime.spawn_blocking(..)`
+    fn scan_spawn_method(&mut self, x: &'a syn::ExprMethodCall) {
+        if !may_be_spawn_fn(&x.method.to_string()) {
+            return;
+        }
+        let cp = self.resolver.resolve_method(&x.method);
+        if is_spawn_fn(&cp) {
+            self.push_spawn(&x.method, cp, &x.args);
+        }
+    }
+
+    // Check for calls registering a signal handler or exit hook, or forking,
+    // e.g. `libc::sigaction(..)` or `signal_hook::flag::register(..)`
+    fn scan_os_hook_call(&mut self, x: &'a syn::ExprCall) {
+        if !self.enabled(EffectType::OsHook) {
+            return;
+        }
+        let syn::Expr::Path(p) = &*x.func else {
+            return;
+        };
+        let Some(name) = p.path.segments.last() else {
+            return;
+        };
+        if !may_be_os_hook(&name.ident.to_string()) {
+            return;
+        }
+        let cp = self.resolver.resolve_path(&p.path);
+        if let Some(hook) = os_hook(&cp) {
+            self.push_effect(x.span(), cp.clone(), Effect::OsHook(cp, hook));
+        }
+    }
+
+    // Check for method calls which are OS hooks, e.g. `cmd.pre_exec(..)`
+    fn scan_os_hook_method(&mut self, x: &'a syn::ExprMethodCall) {
+        if !self.enabled(EffectType::OsHook) || !may_be_os_hook(&x.method.to_string()) {
+            return;
+        }
+        let cp = self.resolver.resolve_method(&x.method);
+        if let Some(hook) = os_hook(&cp) {
+            self.push_effect(&x.method, cp.clone(), Effect::OsHook(cp, hook));
+        }
+    }
+
+    // Check for calls mapping memory, e.g. `libc::mmap(.., PROT_READ | PROT_EXEC, ..)`
+    fn scan_memory_map_call(&mut self, x: &'a syn::ExprCall) {
+        if !self.enabled(EffectType::MemoryMap) {
+            return;
+        }
+        let syn::Expr::Path(p) = &*x.func else {
+            return;
+        };
+        let Some(name) = p.path.segments.last() else {
+            return;
+        };
+        let name = name.ident.to_string();
+        if !may_be_memory_map_fn(&name) {
+            return;
+        }
+        let cp = self.resolver.resolve_path(&p.path);
+        if is_memory_map_fn(&cp) {
+            let prot =
+                mem_protection(&x.args).or_else(|| MemProtection::of_method(&name));
+            self.push_effect(x.span(), cp.clone(), Effect::MemoryMap(cp, prot));
+        }
+    }
+
+    // Check for method calls mapping memory, e.g. `opts.map_exec(&file)`
+    fn scan_memory_map_method(&mut self, x: &'a syn::ExprMethodCall) {
+        let name = x.method.to_string();
+        if !self.enabled(EffectType::MemoryMap) || !may_be_memory_map_fn(&name) {
+            return;
+        }
+        let cp = self.resolver.resolve_method(&x.method);
+        if is_memory_map_fn(&cp) {
+            let prot =
+                mem_protection(&x.args).or_else(|| MemProtection::of_method(&name));
+            self.push_effect(&x.method, cp.clone(), Effect::MemoryMap(cp, prot));
+        }
+    }
+
+    /// Record a spawn call, and the spawned code as a root of the call graph
+    fn push_spawn<S>(
+        &mut self,
+        site: S,
+        callee: CanonicalPath,
+        args: &'a syn::punctuated::Punctuated<syn::Expr, syn::token::Comma>,
+    ) where
+        S: Debug + Spanned,
+    {
+        let Some(containing_fn) = self.scope_fns.last() else {
+            return;
+        };
+        let caller = containing_fn.fn_name.clone();
+        for arg in args {
+            match arg {
+                syn::Expr::Closure(_) | syn::Expr::Async(_) => {
+                    self.data.spawn_roots.insert(caller.clone());
+                }
+                syn::Expr::Path(p) => {
+                    let f = self.resolver.resolve_path(&p.path);
+                    self.data.spawn_roots.insert(f);
+                }
+                _ => (),
+            }
+        }
+        self.push_effect(site, callee.clone(), Effect::ThreadSpawn(callee));
+    }
+
+    // Check for atomic operations using `Ordering::Relaxed`
+    fn scan_relaxed_atomic(&mut self, x: &'a syn::ExprMethodCall) {
+        if !self.enabled(EffectType::Atomic) {
+            return;
+        }
+        if x.args.iter().filter_map(mem_ordering).any(|o| o == MemOrdering::Relaxed) {
+            let cp = self.resolver.resolve_method(&x.method);
+            self.push_effect(
+                &x.method,
+                cp.clone(),
+                Effect::Atomic(cp, MemOrdering::Relaxed),
+            );
+        }
+    }
+
+    fn scan_const(&mut self, ident: &'a syn::Ident, expr: &'a syn::Expr) {
+        self.scan_referenced_names(expr.to_token_stream());
+        if !self.enabled(EffectType::Malware) {
+            return;
+        }
+        let taints = self.expr_taints(expr);
+        if !taints.is_empty() {
+            self.const_taints.insert(ident.to_string(), taints);
+        }
+    }
+
+    /// Record the identifiers in tokens which aren't scanned as expressions,
+    /// since they may refer to functions
+    fn scan_referenced_names(&mut self, tokens: TokenStream) {
+        for t in tokens {
+            match t {
+                TokenTree::Ident(i) => {
+                    self.data.referenced_names.insert(i.to_string());
+                }
+                TokenTree::Group(g) => self.scan_referenced_names(g.stream()),
+                _ => (),
+            }
+        }
+    }
+
+    fn expr_taints(&self, x: &'a syn::Expr) -> Taints {
+        malware::expr_taints(x, &|i: &syn::Ident| {
+            let i = i.to_string();
+            self.scope_taints
+                .get(&i)
+                .or_else(|| self.const_taints.get(&i))
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Check a call with the given (tainted) arguments against the malware
+    /// heuristics
+    fn push_malware<S>(
+        &mut self,
+        site: S,
+        callee: CanonicalPath,
+        is_exec: bool,
+        args: Taints,
+    ) where
+        S: Debug + Spanned,
+    {
+        let pattern = if is_exec && args.byte_array {
+            MalwarePattern::DecodedExec
+        } else if is_exec && args.base64 {
+            MalwarePattern::EncodedSpawn
+        } else if self.build_script && malware::is_network_fn(&callee) {
+            MalwarePattern::BuildScriptNetwork
+        } else if self.build_script && args.home && malware::is_file_write_fn(&callee) {
+            MalwarePattern::HomeWrite
+        } else {
+            return;
+        };
+        self.push_effect(site, callee.clone(), Effect::Malware(callee, pattern));
+    }
+
+    // Check calls against the malware heuristics, e.g. `Command::new(decoded)`
+    fn scan_malware_call(&mut self, x: &'a syn::ExprCall) {
+        if !self.enabled(EffectType::Malware) {
+            return;
+        }
+        let syn::Expr::Path(p) = &*x.func else {
+            return;
+        };
+        let callee = self.resolver.resolve_path(&p.path);
+        let args =
+            x.args.iter().fold(Taints::default(), |t, a| t.union(self.expr_taints(a)));
+        let is_exec = malware::is_exec_fn(&callee);
+        self.push_malware(p, callee, is_exec, args);
+    }
+
+    // Check method calls against the malware heuristics, e.g.
+    // `cmd.arg(decoded)`. Only the arguments count, so a tainted command isn't
+    // reported again for each method called on it.
+    fn scan_malware_method(&mut self, x: &'a syn::ExprMethodCall) {
+        if !self.enabled(EffectType::Malware) {
+            return;
+        }
+        let callee = self.resolver.resolve_method(&x.method);
+        let args =
+            x.args.iter().fold(Taints::default(), |t, a| t.union(self.expr_taints(a)));
+        let is_exec = malware::is_exec_fn(&callee) || malware::is_exec_method(&x.method);
+        self.push_malware(&x.method, callee, is_exec, args);
+    }
+
+    // Check for pointer arithmetic methods called on a raw pointer
+    fn scan_ptr_arithmetic(&mut self, x: &'a syn::ExprMethodCall) {
+        if !self.enabled(EffectType::PtrArithmetic) {
+            return;
+        }
+        let method = x.method.to_string();
+        if PTR_ARITHMETIC_METHODS.contains(&method.as_str())
+            && self.expr_is_raw_ptr(&x.receiver)
+        {
+            let cp = self.resolver.resolve_method(&x.method);
+            self.push_effect(&x.method, cp.clone(), Effect::PtrArithmetic(cp));
+        }
+    }
+
+    /// Best-effort check (without full type information) of whether the
+    /// expression is a raw pointer
+    fn expr_is_raw_ptr(&self, x: &'a syn::Expr) -> bool {
+        match x {
+            syn::Expr::Cast(c) => matches!(*c.ty, syn::Type::Ptr(_)),
+            syn::Expr::Paren(p) => self.expr_is_raw_ptr(&p.expr),
+            syn::Expr::Path(p) => match p.path.get_ident() {
+                Some(i) => {
+                    self.scope_raw_ptrs.contains(&i.to_string())
+                        || self.resolver.resolve_field_type(i).is_raw_ptr()
+                }
+                None => false,
+            },
+            syn::Expr::MethodCall(m) => {
+                PTR_METHODS.contains(&m.method.to_string().as_str())
+                    || (PTR_ARITHMETIC_METHODS.contains(&m.method.to_string().as_str())
+                        && self.expr_is_raw_ptr(&m.receiver))
+            }
+            _ => false,
+        }
+    }
+
+    /// Best-effort check (without full type information) of whether the
+    /// expression is an integer
+    fn expr_is_int(&self, x: &'a syn::Expr) -> bool {
+        match x {
+            syn::Expr::Lit(l) => matches!(l.lit, syn::Lit::Int(_)),
+            syn::Expr::Cast(c) => is_int_type(&c.ty),
+            syn::Expr::Paren(p) => self.expr_is_int(&p.expr),
+            // Arithmetic on an integer, e.g. `addr + 1`
+            syn::Expr::Binary(b) => {
+                matches!(
+                    b.op,
+                    syn::BinOp::Add(_)
+                        | syn::BinOp::Sub(_)
+                        | syn::BinOp::Mul(_)
+                        | syn::BinOp::Div(_)
+                        | syn::BinOp::Rem(_)
+                        | syn::BinOp::BitAnd(_)
+                        | syn::BinOp::BitOr(_)
+                        | syn::BinOp::BitXor(_)
+                        | syn::BinOp::Shl(_)
+                        | syn::BinOp::Shr(_)
+                ) && (self.expr_is_int(&b.left) || self.expr_is_int(&b.right))
+            }
+            syn::Expr::Path(p) => match p.path.get_ident() {
+                Some(i) => is_int_type_name(self.resolver.resolve_field_type(i).as_str()),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Check if the field being accessed is a Union field
+    fn scan_field_access(&mut self, x: &'a syn::ExprField) {
+        if !self.enabled(EffectType::UnionField) {
+            return;
+        }
+        if let syn::Member::Named(i) = &x.member {
+            let ty = self.resolver.resolve_field_type(i);
+            if !ty.is_union_field() || self.scope_assign_lhs {
+                return;
+            }
+            let cp = self.resolver.resolve_field(i);
+            // NOTE: Can only be done in an unsafe block
+            self.push_effect(x.span(), cp.clone(), Effect::UnionField(cp));
+        }
+    }
+
+    fn scan_unsafe_block(&mut self, x: &'a syn::ExprUnsafe) {
+        self.data.unsafe_blocks.add(x);
+        self.scope_unsafe += 1;
+        self.scope_unsafe_blocks += 1;
+        for s in &x.block.stmts {
+            self.scan_fn_statement(s);
+        }
+
+        // Reset unsafety
+        debug_assert!(self.scope_unsafe >= 1);
+        self.scope_unsafe -= 1;
+        self.scope_unsafe_blocks -= 1;
+        if self.scope_unsafe_effects == 0 {
+            self.syn_debug("unsafe block without any unsafe effects", x)
+        }
+        self.scope_unsafe_effects = 0;
+    }
+
+    /*
+        Function calls --what we're interested in
+    */
+    fn scan_expr_call_args(
+        &mut self,
+        a: &'a syn::punctuated::Punctuated<syn::Expr, syn::token::Comma>,
+    ) {
+        for y in a.iter() {
+            self.scan_expr(y);
+        }
+    }
+
+    /// Push an effect into the current `EffectBlock`. Should be used when
+    /// pushing an effect in an unsafe block so all effects can be captured at
+    /// the same time.
+    fn push_effect<S>(&mut self, eff_span: S, callee: CanonicalPath, eff_type: Effect)
+    where
+        S: Debug + Spanned,
+    {
+        if !self.enabled(EffectType::from(&eff_type)) {
+            return;
+        }
+        let containing_fn = self.scope_fns.last().expect("not inside a function!");
+        let caller = &containing_fn.fn_name;
+
+        let mut eff = EffectInstance::new_effect(
+            self.filepath,
+            caller.clone(),
+            callee,
+            &eff_span,
+            eff_type,
+        );
+
+        if self.scope_unsafe > 0 && eff.is_rust_unsafe() {
+            self.scope_unsafe_effects += 1;
+            if self.scope_unsafe_blocks == 0 {
+                eff.set_unsafe_fn_body();
+            }
+        }
+        if let Some(closure) = self.scope_closures.last() {
+            eff.set_closure(closure.clone());
+        }
+        if let Some(origin) = self.scope_macros.last() {
+            eff.set_macro_origin(origin.clone());
+        }
+        self.data.effects.push(eff);
+    }
+
+    /// push an Effect to the list of results based on this call site.
+    fn push_callsite<S>(
         &mut self,
         callee_span: S,
         callee: CanonicalPath,
@@ -901,17 +2124,13 @@ impl<'a> Scanner<'a> {
         let containing_fn = self.scope_fns.last().expect("not inside a function!");
         let caller = &containing_fn.fn_name;
 
-        if let Some(caller_node_idx) = self.data.node_idxs.get(caller) {
-            if let Some(callee_node_idx) = self.data.node_idxs.get(&callee) {
-                self.data.call_graph.add_edge(
-                    *caller_node_idx,
-                    *callee_node_idx,
-                    SrcLoc::from_span(self.filepath, &callee_span.span()),
-                );
-            }
-        }
+        self.data.pending_calls.push((
+            caller.clone(),
+            callee.clone(),
+            SrcLoc::from_span(self.filepath, &callee_span.span()),
+        ));
 
-        let Some(eff) = EffectInstance::new_call(
+        let Some(mut eff) = EffectInstance::new_call(
             self.filepath,
             caller.clone(),
             callee,
@@ -922,9 +2141,25 @@ impl<'a> Scanner<'a> {
         ) else {
             return;
         };
+        // Sink calls may be reclassified as custom effects after the scan
+        let eff_type = EffectType::from(eff.eff_type());
+        if !self.enabled(eff_type)
+            && !(eff_type == EffectType::SinkCall && self.enabled(EffectType::Custom))
+        {
+            return;
+        }
 
         if self.scope_unsafe > 0 && eff.is_rust_unsafe() {
             self.scope_unsafe_effects += 1;
+            if self.scope_unsafe_blocks == 0 {
+                eff.set_unsafe_fn_body();
+            }
+        }
+        if let Some(closure) = self.scope_closures.last() {
+            eff.set_closure(closure.clone());
+        }
+        if let Some(origin) = self.scope_macros.last() {
+            eff.set_macro_origin(origin.clone());
         }
         self.data.effects.push(eff);
     }
@@ -949,6 +2184,7 @@ impl<'a> Scanner<'a> {
                 self.scan_expr_call_field(&x.member)
             }
             syn::Expr::Macro(m) => {
+                self.scan_referenced_names(m.mac.tokens.clone());
                 self.data.skipped_macros.add(m);
             }
             other => {
@@ -978,33 +2214,285 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn scan_expr_call_method(&mut self, i: &'a syn::Ident) {
+    fn scan_expr_call_method(&mut self, m: &'a syn::ExprMethodCall) {
+        let i = &m.method;
         let is_unsafe = self.resolver.resolve_unsafe_ident(i) && self.scope_unsafe > 0;
+        let n_effects = self.data.effects.len();
         self.push_callsite(i, self.resolver.resolve_method(i), None, is_unsafe);
+        // Resolving the type is expensive, so only do it if the call was an effect
+        if self.data.effects.len() > n_effects {
+            if let Some(ty) = self.receiver_type(&m.receiver) {
+                self.data.effects.last_mut().unwrap().set_receiver_ty(ty);
+            }
+        }
+    }
+
+    /// Add a call graph edge from the current function to the operator impl
+    /// for the operand's type, if operator attribution is on and the type is
+    /// defined in this crate
+    fn push_operator_call<S>(&mut self, site: &S, operand: &'a syn::Expr, method: &str)
+    where
+        S: Spanned,
+    {
+        if !self.attribute_operators {
+            return;
+        }
+        let Some(containing_fn) = self.scope_fns.last() else {
+            return;
+        };
+        let caller = containing_fn.fn_name.clone();
+        let Some(ty) = self.receiver_type(operand) else {
+            return;
+        };
+        // Drop references and generic arguments to get the path of the type
+        let ty = ty.as_str().trim_start_matches('&').trim_start_matches("mut ");
+        let ty = ty.split('<').next().unwrap_or_default();
+        let crate_prefix = format!("{}::", caller.crate_name());
+        if !ty.starts_with(&crate_prefix) {
+            return;
+        }
+        let callee = CanonicalPath::new(&format!("{}::{}", ty, method));
+        let caller_idx = self.data.update_call_graph(caller);
+        let callee_idx = self.data.update_call_graph(callee);
+        self.data.call_graph.add_edge(
+            caller_idx,
+            callee_idx,
+            SrcLoc::from_span(self.filepath, &site.span()),
+        );
+    }
+
+    /// Best-effort type of a method call receiver (without full type
+    /// information): resolved for variables and fields only
+    fn receiver_type(&self, x: &'a syn::Expr) -> Option<CanonicalType> {
+        let ty = match x {
+            syn::Expr::Paren(p) => return self.receiver_type(&p.expr),
+            syn::Expr::Reference(r) => return self.receiver_type(&r.expr),
+            syn::Expr::Path(p) => self.resolver.resolve_field_type(p.path.get_ident()?),
+            syn::Expr::Field(f) => match &f.member {
+                syn::Member::Named(i) => self.resolver.resolve_field_type(i),
+                syn::Member::Unnamed(_) => return None,
+            },
+            _ => return None,
+        };
+        // The fallback resolver has no type information
+        (!ty.as_str().starts_with("UNKNOWN_TYPE::")).then_some(ty)
+    }
+}
+
+/// The memory ordering named by the expression, if it is a path to one of the
+/// variants of `atomic::Ordering`
+fn mem_ordering(x: &syn::Expr) -> Option<MemOrdering> {
+    let syn::Expr::Path(p) = x else {
+        return None;
+    };
+    let mut segments = p.path.segments.iter().rev();
+    let ordering = segments.next()?.ident.to_string().parse::<MemOrdering>().ok()?;
+    match segments.next() {
+        Some(s) if s.ident != "Ordering" => None,
+        _ => Some(ordering),
+    }
+}
+
+/// The memory protection given literally by the flag constants in the
+/// arguments of a call, e.g. `PROT_READ | PROT_EXEC`
+fn mem_protection(
+    args: &syn::punctuated::Punctuated<syn::Expr, syn::token::Comma>,
+) -> Option<MemProtection> {
+    fn idents(tokens: TokenStream, out: &mut Vec<String>) {
+        for t in tokens {
+            match t {
+                TokenTree::Ident(i) => out.push(i.to_string()),
+                TokenTree::Group(g) => idents(g.stream(), out),
+                _ => (),
+            }
+        }
+    }
+    let mut out = Vec::new();
+    idents(args.to_token_stream(), &mut out);
+    MemProtection::from_flags(out.iter().map(String::as_str))
+}
+
+/// Depth of nested macro expansions after which calls are skipped, to stop
+/// at recursive macros which don't terminate
+const MAX_MACRO_DEPTH: usize = 32;
+/// Most macro calls expanded in a file
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+/// Traits whose impls are called implicitly by operators, including
+/// dereferencing
+const OPERATOR_TRAITS: &[&str] = &[
+    "Add",
+    "Sub",
+    "Mul",
+    "Div",
+    "Rem",
+    "Neg",
+    "Not",
+    "BitAnd",
+    "BitOr",
+    "BitXor",
+    "Shl",
+    "Shr",
+    "AddAssign",
+    "SubAssign",
+    "MulAssign",
+    "DivAssign",
+    "RemAssign",
+    "BitAndAssign",
+    "BitOrAssign",
+    "BitXorAssign",
+    "ShlAssign",
+    "ShrAssign",
+    "Index",
+    "IndexMut",
+    "Deref",
+    "DerefMut",
+];
+
+/// The operator trait method called by a binary operator, if it can be
+/// overloaded (comparisons are left out)
+fn binop_method(op: &syn::BinOp) -> Option<&'static str> {
+    use syn::BinOp::*;
+    Some(match op {
+        Add(_) => "add",
+        Sub(_) => "sub",
+        Mul(_) => "mul",
+        Div(_) => "div",
+        Rem(_) => "rem",
+        BitAnd(_) => "bitand",
+        BitOr(_) => "bitor",
+        BitXor(_) => "bitxor",
+        Shl(_) => "shl",
+        Shr(_) => "shr",
+        AddAssign(_) => "add_assign",
+        SubAssign(_) => "sub_assign",
+        MulAssign(_) => "mul_assign",
+        DivAssign(_) => "div_assign",
+        RemAssign(_) => "rem_assign",
+        BitAndAssign(_) => "bitand_assign",
+        BitOrAssign(_) => "bitor_assign",
+        BitXorAssign(_) => "bitxor_assign",
+        ShlAssign(_) => "shl_assign",
+        ShrAssign(_) => "shr_assign",
+        _ => return None,
+    })
+}
+
+/// Methods on raw pointers which offset the pointer
+const PTR_ARITHMETIC_METHODS: &[&str] = &[
+    "offset",
+    "add",
+    "sub",
+    "wrapping_offset",
+    "wrapping_add",
+    "wrapping_sub",
+    "byte_offset",
+    "byte_add",
+    "byte_sub",
+    "offset_from",
+];
+
+/// Methods which return a raw pointer
+const PTR_METHODS: &[&str] = &["as_ptr", "as_mut_ptr", "cast", "cast_mut", "cast_const"];
+
+fn is_int_type_name(ty: &str) -> bool {
+    let name = ty.rsplit("::").next().unwrap_or(ty);
+    matches!(
+        name,
+        "usize"
+            | "isize"
+            | "u8"
+            | "u16"
+            | "u32"
+            | "u64"
+            | "u128"
+            | "i8"
+            | "i16"
+            | "i32"
+            | "i64"
+            | "i128"
+    )
+}
+
+fn is_int_type(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) => {
+            p.path.get_ident().map_or(false, |i| is_int_type_name(&i.to_string()))
+        }
+        _ => false,
     }
 }
 
-/// Load the Rust file at the filepath and scan it
+/// Whether a function is a test or benchmark, run by a test harness: its
+/// attribute is `test` or `bench`, or ends with them (e.g. `#[tokio::test]`)
+fn is_test(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|a| {
+        a.path()
+            .segments
+            .last()
+            .map_or(false, |s| s.ident == "test" || s.ident == "bench")
+    })
+}
+
+/// Whether a function can only be called from inside the crate (as far as
+/// its declaration shows)
+fn is_private(
+    vis: &syn::Visibility,
+    sig: &syn::Signature,
+    attrs: &[syn::Attribute],
+) -> bool {
+    let exported = attrs.iter().any(|a| {
+        let path = a.path();
+        path.is_ident("no_mangle")
+            || path.is_ident("export_name")
+            || path.is_ident("unsafe")
+    });
+    Visibility::from(vis) == Visibility::Private
+        && sig.ident != "main"
+        && sig.abi.is_none()
+        && !exported
+}
+
+/// Load the Rust file at the filepath and scan it, as the module `modpath`
+/// if given rather than the one inferred from the path. Name resolution is
+/// only used until the deadline, if there is one.
+#[allow(clippy::too_many_arguments)]
 pub fn scan_file(
     crate_name: &str,
     filepath: &FilePath,
-    resolver: &Resolver,
+    modpath: Option<CanonicalPath>,
+    resolver: &dyn Resolver,
     scan_results: &mut ScanResults,
-    sinks: HashSet<IdentPath>,
+    sinks: &PatternSet,
     enabled_cfg: &HashMap<String, Vec<String>>,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+    deadline: Option<Instant>,
+    macro_defs: &MacroDefs,
+    edition: &CrateEdition,
 ) -> Result<()> {
     info!("Scanning file: {:?}", filepath);
 
     // Load file contents
-    let mut file = File::open(filepath)?;
-    let mut src = String::new();
-    file.read_to_string(&mut src)?;
-    let syntax_tree = syn::parse_file(&src)?;
+    let src = util::fs::read_source(filepath)?;
+    if let Some(warning) = src.warning {
+        scan_results.warnings.push(ScanWarning::new(filepath, warning));
+    }
+    let mut syntax_tree = parse_file_skipping_errors(filepath, &src.text, scan_results)?;
+    edition.normalize(&mut syntax_tree);
 
     // Initialize data structures
-    let file_resolver = FileResolver::new(crate_name, resolver, filepath)?;
+    let mut file_resolver = FileResolver::new(crate_name, resolver, filepath)?;
+    file_resolver.set_deadline(deadline);
+    if let Some(modpath) = modpath {
+        file_resolver.set_modpath(modpath);
+    }
+    let macros = MacroExpander::new(macro_defs);
     let mut scanner = Scanner::new(filepath, file_resolver, scan_results, enabled_cfg);
-    scanner.add_sinks(sinks);
+    scanner.set_sinks(sinks);
+    scanner.set_effect_types(relevant_effects);
+    scanner.set_config(config);
+    scanner.set_macros(&macros);
 
     // Scan file contents
     scanner.scan_file(&syntax_tree);
@@ -1012,28 +2500,461 @@ pub fn scan_file(
     Ok(())
 }
 
+/// Parse the source file, skipping the top-level items which don't parse, up
+/// to the next `;` or braced block (e.g. a function with a syntax error in its
+/// body), and recording a warning for each of them. Fails only if the file
+/// can't be split into tokens (e.g. unbalanced delimiters).
+fn parse_file_skipping_errors(
+    filepath: &FilePath,
+    src: &str,
+    scan_results: &mut ScanResults,
+) -> Result<syn::File> {
+    if let Ok(syntax_tree) = syn::parse_file(src) {
+        return Ok(syntax_tree);
+    }
+    let tokens = src.parse::<TokenStream>().map_err(|e| anyhow!("{}", e))?;
+    let mut errors = Vec::new();
+    let parser = |input: ParseStream| {
+        let attrs = input.call(syn::Attribute::parse_inner)?;
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            let fork = input.fork();
+            match fork.parse::<syn::Item>() {
+                Ok(item) => {
+                    input.advance_to(&fork);
+                    items.push(item);
+                }
+                Err(err) => {
+                    errors.push(err);
+                    while let Ok(t) = input.parse::<TokenTree>() {
+                        match t {
+                            TokenTree::Punct(p) if p.as_char() == ';' => break,
+                            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
+                                break
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(syn::File { shebang: None, attrs, items })
+    };
+    let syntax_tree = syn::parse::Parser::parse2(parser, tokens)?;
+    for err in errors {
+        let reason = format!(
+            "syntax error at line {} ({}); skipped the item",
+            err.span().start().line,
+            err
+        );
+        warn!("Skipping part of {}: {}", filepath.to_string_lossy(), reason);
+        scan_results.warnings.push(ScanWarning::new(filepath, reason));
+    }
+    Ok(syntax_tree)
+}
+
 /// Try to run scan_file, reporting any errors back to the user
+#[allow(clippy::too_many_arguments)]
 pub fn try_scan_file(
     crate_name: &str,
     filepath: &FilePath,
-    resolver: &Resolver,
+    modpath: Option<CanonicalPath>,
+    resolver: &dyn Resolver,
     scan_results: &mut ScanResults,
-    sinks: HashSet<IdentPath>,
+    sinks: &PatternSet,
     enabled_cfg: &HashMap<String, Vec<String>>,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+    deadline: Option<Instant>,
+    macro_defs: &MacroDefs,
+    edition: &CrateEdition,
 ) {
-    scan_file(crate_name, filepath, resolver, scan_results, sinks, enabled_cfg)
-        .unwrap_or_else(|err| {
-            warn!("Failed to scan file: {} ({})", filepath.to_string_lossy(), err);
-        });
+    scan_file(
+        crate_name,
+        filepath,
+        modpath,
+        resolver,
+        scan_results,
+        sinks,
+        enabled_cfg,
+        relevant_effects,
+        config,
+        deadline,
+        macro_defs,
+        edition,
+    )
+    .unwrap_or_else(|err| {
+        warn!("Failed to scan file: {} ({})", filepath.to_string_lossy(), err);
+        let reason = format!("skipped the file ({})", err);
+        scan_results.warnings.push(ScanWarning::new(filepath, reason));
+    });
+}
+
+/// Scan code outside of the crate's module tree (an example, a doc-test, or
+/// the build script) syntactically, as the module `modpath`, and mark its effects with the
+/// provenance
+#[allow(clippy::too_many_arguments)]
+fn scan_extra_source(
+    crate_name: &str,
+    filepath: &FilePath,
+    src: &str,
+    modpath: CanonicalPath,
+    provenance: Provenance,
+    resolver: &dyn Resolver,
+    scan_results: &mut ScanResults,
+    sinks: &PatternSet,
+    enabled_cfg: &HashMap<String, Vec<String>>,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+    macro_defs: &MacroDefs,
+    edition: &CrateEdition,
+) -> Result<()> {
+    let mut syntax_tree = parse_file_skipping_errors(filepath, src, scan_results)?;
+    edition.normalize(&mut syntax_tree);
+    let mut file_resolver = FileResolver::new(crate_name, resolver, filepath)?;
+    file_resolver.set_deadline(Some(Instant::now()));
+    file_resolver.set_modpath(modpath.clone());
+    let macros = MacroExpander::new(macro_defs);
+
+    let first_effect = scan_results.effects.len();
+    let mut scanner = Scanner::new(filepath, file_resolver, scan_results, enabled_cfg);
+    scanner.set_sinks(sinks);
+    scanner.set_effect_types(relevant_effects);
+    scanner.set_config(config);
+    scanner.set_macros(&macros);
+    scanner.scan_file(&syntax_tree);
+
+    for e in &mut scan_results.effects[first_effect..] {
+        e.set_provenance(provenance);
+    }
+    let prefix = format!("{}::", modpath);
+    scan_results.pub_fns.retain(|f| !f.as_str().starts_with(&prefix));
+    Ok(())
+}
+
+/// The module an example is scanned as, e.g. my_crate::examples::demo for
+/// examples/demo.rs or examples/demo/main.rs
+fn example_modpath(crate_name: &str, examples_dir: &FilePath, file: &FilePath) -> String {
+    let relative = file.strip_prefix(examples_dir).unwrap_or(file);
+    let mut modpath = vec![crate_name.to_string(), "examples".to_string()];
+    modpath.extend(
+        relative
+            .iter()
+            .filter(|x| *x != "main.rs")
+            .map(|x| x.to_string_lossy().trim_end_matches(".rs").to_string()),
+    );
+    modpath.join("::")
+}
+
+/// The examples of the crate
+fn example_files(crate_path: &FilePath, config: &ScanConfig) -> Vec<FilePathBuf> {
+    let examples_dir = crate_path.join(FilePath::new("examples"));
+    if examples_dir.is_dir() {
+        util::fs::walk_files_with_extension(&examples_dir, "rs", config.symlinks)
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// The sinks a scan of the crate looks for: the default ones, the extra
+/// sinks, the project's, those of build-time code for a proc-macro crate, and
+/// those of the custom effect kinds
+pub fn scan_sinks(
+    crate_path: &FilePath,
+    extra_sinks: HashSet<IdentPath>,
+    config: &ScanConfig,
+) -> Result<PatternSet> {
+    let mut sinks = Sink::default_sinks();
+    sinks.include_paths(extra_sinks);
+    sinks.extend(&config.project_sinks);
+    if config.build_time && util::is_proc_macro_crate(crate_path)? {
+        sinks.extend(&Sink::build_time_sinks());
+    }
+    // Calls to user-defined effect kinds are found as sinks and reclassified
+    // after the scan
+    if let Some(custom) = &config.custom_effects {
+        sinks.extend(&custom.sink_patterns());
+    }
+    Ok(sinks)
+}
+
+/// Description of the configuration of a scan of the crate with the extra
+/// sinks, as recorded in its manifest (see `ScanConfig::describe`)
+pub fn scan_description(
+    crate_path: &FilePath,
+    extra_sinks: HashSet<IdentPath>,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) -> Result<String> {
+    let sinks = scan_sinks(crate_path, extra_sinks, config)?;
+    Ok(config.describe(&sinks, relevant_effects))
+}
+
+/// The files a scan of the crate reads: its sources (see `crate_sources`),
+/// its examples if the configuration includes them, and its build script if
+/// it is scanned
+pub fn scanned_files(
+    crate_path: &FilePath,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) -> Result<Vec<FilePathBuf>> {
+    let mut files = crate_sources(crate_path, config)?.files;
+    if config.include_examples {
+        files.extend(example_files(crate_path, config));
+    }
+    if config.build_time || relevant_effects.contains(&EffectType::Malware) {
+        files.extend(build_script(crate_path, config)?);
+    }
+    Ok(files)
+}
+
+/// The build script of the crate (build.rs, or the one its manifest
+/// declares), if it has one the symlink policy allows
+fn build_script(
+    crate_path: &FilePath,
+    config: &ScanConfig,
+) -> Result<Option<FilePathBuf>> {
+    let Some(build_script) = util::build_script_path(crate_path)? else {
+        return Ok(None);
+    };
+    let allowed = build_script.is_file()
+        && util::fs::is_allowed(
+            &build_script,
+            &crate_path.canonicalize()?,
+            config.symlinks,
+        );
+    Ok(allowed.then_some(build_script))
+}
+
+/// Scan the examples, and the doc-tests in the files. Returns the examples.
+#[allow(clippy::too_many_arguments)]
+fn scan_examples_and_doc_tests(
+    crate_name: &str,
+    crate_path: &FilePath,
+    files: &[FilePathBuf],
+    resolver: &dyn Resolver,
+    scan_results: &mut ScanResults,
+    sinks: &PatternSet,
+    enabled_cfg: &HashMap<String, Vec<String>>,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+    macro_defs: &MacroDefs,
+    edition: &CrateEdition,
+) -> Vec<FilePathBuf> {
+    let examples_dir = crate_path.join(FilePath::new("examples"));
+    let examples = example_files(crate_path, config);
+    for file in &examples {
+        let modpath = example_modpath(crate_name, &examples_dir, file);
+        util::fs::read_source(file)
+            .and_then(|src| {
+                scan_extra_source(
+                    crate_name,
+                    file,
+                    &src.text,
+                    CanonicalPath::new(&modpath),
+                    Provenance::Example,
+                    resolver,
+                    scan_results,
+                    sinks,
+                    enabled_cfg,
+                    relevant_effects,
+                    config,
+                    macro_defs,
+                    edition,
+                )
+            })
+            .unwrap_or_else(|err| {
+                warn!("Failed to scan example: {} ({})", file.to_string_lossy(), err);
+                let reason = format!("skipped the example ({})", err);
+                scan_results.warnings.push(ScanWarning::new(file, reason));
+            });
+    }
+
+    for file in files {
+        let Ok(src) = util::fs::read_source(file).map(|s| s.text) else {
+            continue;
+        };
+        let file_modpath = hacky_resolver::infer_fully_qualified_prefix(crate_name, file);
+        for doc_test in doc_tests::extract_doc_tests(&src) {
+            let modpath = format!("{}::doctest_{}", file_modpath, doc_test.line);
+            scan_extra_source(
+                crate_name,
+                file,
+                &doc_test.source,
+                CanonicalPath::new(&modpath),
+                Provenance::DocTest,
+                resolver,
+                scan_results,
+                sinks,
+                enabled_cfg,
+                relevant_effects,
+                config,
+                macro_defs,
+                edition,
+            )
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to scan doc-test: {}:{} ({})",
+                    file.to_string_lossy(),
+                    doc_test.line,
+                    err
+                );
+                let reason =
+                    format!("skipped the doc-test at line {} ({})", doc_test.line, err);
+                scan_results.warnings.push(ScanWarning::new(file, reason));
+            });
+        }
+    }
+    examples
+}
+
+/// Largest file which is scanned once a crate is over its scan budget
+const MAX_FILE_SIZE_PAST_DEADLINE: u64 = 1 << 20;
+
+/// Number of files of a crate scanned in parallel between checks of the
+/// memory budget
+const FILE_BATCH_SIZE: usize = 32;
+
+/// Number of resolver loads which timed out and are still running
+static ABANDONED_LOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Most timed-out resolver loads left running at once; further crates over
+/// their deadline are scanned syntactically without starting another load
+const MAX_ABANDONED_LOADS: usize = 2;
+
+/// Load the crate for name resolution with the resolver backend, giving up at
+/// the deadline.
+///
+/// rust-analyzer can't cancel a load, so a load which times out is left to
+/// finish in the background, and the database it built is dropped when it
+/// does. At most MAX_ABANDONED_LOADS are left running: while that many are,
+/// loads with a deadline fail without starting, so a run over many slow
+/// crates doesn't pile up loader threads and their memory.
+fn load_resolver(
+    crate_path: &FilePath,
+    backend: ResolverBackend,
+    deadline: Option<Instant>,
+    memory: &MemoryAccount,
+) -> Result<Box<dyn Resolver>> {
+    let Some(deadline) = deadline else {
+        return backend.load(crate_path);
+    };
+    if ABANDONED_LOADS.load(Ordering::SeqCst) >= MAX_ABANDONED_LOADS {
+        return Err(anyhow!("earlier loads which timed out are still running"));
+    }
+    let (tx, rx) = mpsc::channel();
+    let crate_path = crate_path.to_path_buf();
+    let memory = memory.clone();
+    // Set by whichever of the load finishing and the timeout comes first
+    let settled = Arc::new(AtomicBool::new(false));
+    let load_settled = settled.clone();
+    std::thread::spawn(move || {
+        let _account = memory.enter();
+        let res = backend.load(&crate_path);
+        if load_settled.swap(true, Ordering::SeqCst) {
+            // Timed out: nobody is waiting for the result
+            ABANDONED_LOADS.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            let _ = tx.send(res);
+        }
+    });
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(res) => res,
+        Err(_) => {
+            ABANDONED_LOADS.fetch_add(1, Ordering::SeqCst);
+            if settled.swap(true, Ordering::SeqCst) {
+                // The load finished just after the timeout
+                ABANDONED_LOADS.fetch_sub(1, Ordering::SeqCst);
+                if let Ok(res) = rx.try_recv() {
+                    return res;
+                }
+            }
+            Err(anyhow!("timed out"))
+        }
+    }
+}
+
+/// The source files of a crate which a scan reads
+pub struct CrateSources {
+    /// The files to scan, in scan order
+    pub files: Vec<FilePathBuf>,
+    /// Files left out by the project's ignore rules
+    pub ignored: Vec<FilePathBuf>,
+    /// The module each file is in
+    pub module_map: ModuleMap,
+}
+
+/// Find the source files of a crate to scan: the files under src (or a lone
+/// lib.rs), and the files of its module tree outside of src, other than those
+/// the project ignores
+pub fn crate_sources(crate_path: &FilePath, config: &ScanConfig) -> Result<CrateSources> {
+    // Files read outside of the src walk are checked against the symlink
+    // policy too (see util::fs::SymlinkPolicy)
+    let crate_root = crate_path.canonicalize()?;
+
+    // TODO: For now, only walking through the src dir, but might want to
+    //       include others (e.g. might codegen in other dirs)
+    let src_dir = crate_path.join(FilePath::new("src"));
+    let mut files = if src_dir.is_dir() {
+        util::fs::walk_files_with_extension(&src_dir, "rs", config.symlinks)
+            .collect::<Vec<_>>()
+    } else {
+        info!("crate has no src dir; looking for a single lib.rs file instead");
+        let lib_file = crate_path.join(FilePath::new("lib.rs"));
+        if lib_file.is_file()
+            && util::fs::is_allowed(&lib_file, &crate_root, config.symlinks)
+        {
+            vec![lib_file]
+        } else {
+            warn!(
+                "unable to find src dir or lib.rs file; \
+                no files scanned! In crate {:?}",
+                crate_path
+            );
+            Vec::new()
+        }
+    };
+    // Files in the module tree outside of src (e.g. included generated code)
+    // are scanned too, and each file is scanned as the module it is in rather
+    // than the one its path suggests (see ModuleMap)
+    let roots = if src_dir.is_dir() {
+        vec![src_dir.join("lib.rs"), src_dir.join("main.rs")]
+    } else {
+        vec![crate_path.join("lib.rs")]
+    };
+    let module_map = ModuleMap::from_roots(&roots);
+    let mut extra_files = module_map
+        .files()
+        .filter(|f| !files.contains(f))
+        .filter(|f| {
+            util::fs::resolves_within(f, &crate_root)
+                && util::fs::is_allowed(f, &crate_root, config.symlinks)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    extra_files.sort();
+    files.extend(extra_files);
+    let (ignored, files): (Vec<_>, Vec<_>) = files.into_iter().partition(|f| {
+        f.strip_prefix(crate_path).map_or(false, |f| config.scan_ignore.is_ignored(f))
+    });
+    Ok(CrateSources { files, ignored, module_map })
 }
 
 /// Scan the supplied crate with an additional list of sinks
 pub fn scan_crate_with_sinks(
     crate_path: &FilePath,
-    sinks: HashSet<IdentPath>,
+    extra_sinks: HashSet<IdentPath>,
     relevant_effects: &[EffectType],
+    config: &ScanConfig,
 ) -> Result<ScanResults> {
     info!("Scanning crate: {:?}", crate_path);
+    let budget = config.budget;
+    let mut deadline = budget.timeout().map(|t| Instant::now() + t);
+    // Other crates may be scanned in parallel, so the memory limit is on
+    // what the threads scanning this one hold
+    let memory = MemoryAccount::new();
+    let _account = memory.enter();
 
     // Make sure the path is a crate
     if !crate_path.is_dir() {
@@ -1048,51 +2969,310 @@ pub fn scan_crate_with_sinks(
 
     let crate_name = util::load_cargo_toml(crate_path)?.crate_name;
 
-    let resolver = Resolver::new(crate_path)?;
-
     let mut scan_results = ScanResults::new();
 
-    let enabled_cfg = resolver.get_cfg_options_for_crate(&crate_name).unwrap_or_default();
+    // Crates which can't be loaded (e.g. because they don't compile) are
+    // scanned syntactically rather than not at all
+    let load = metrics::metrics().time_phase("load", || {
+        load_resolver(crate_path, config.resolver, deadline, &memory)
+    });
+    let resolver = load.unwrap_or_else(|err| {
+        warn!("Failed to load {} for name resolution ({})", crate_name, err);
+        let reason = format!(
+            "couldn't load the crate for name resolution ({}); scanned syntactically",
+            err
+        );
+        scan_results.warnings.push(ScanWarning::new(crate_path, reason));
+        Box::new(NoResolver) as Box<dyn Resolver>
+    });
+
+    let sinks = scan_sinks(crate_path, extra_sinks, config)?;
+    // All of a proc-macro crate runs at compile time
+    let proc_macro = config.build_time && util::is_proc_macro_crate(crate_path)?;
+    if proc_macro {
+        info!("{} is a proc-macro crate; scanning it as build-time code", crate_name);
+    }
 
-    // TODO: For now, only walking through the src dir, but might want to
-    //       include others (e.g. might codegen in other dirs)
-    let src_dir = crate_path.join(FilePath::new("src"));
-    if src_dir.is_dir() {
-        for entry in util::fs::walk_files_with_extension(&src_dir, "rs") {
-            try_scan_file(
-                &crate_name,
-                entry.as_path(),
-                &resolver,
-                &mut scan_results,
-                sinks.clone(),
-                &enabled_cfg,
-            );
-        }
-    } else {
-        info!("crate has no src dir; looking for a single lib.rs file instead");
-        let lib_file = crate_path.join(FilePath::new("lib.rs"));
-        if lib_file.is_file() {
-            try_scan_file(
-                &crate_name,
-                lib_file.as_path(),
-                &resolver,
-                &mut scan_results,
-                sinks,
-                &enabled_cfg,
-            );
-        } else {
+    let mut enabled_cfg =
+        resolver.get_cfg_options_for_crate(&crate_name).unwrap_or_default();
+    if let Some(features) = &config.enabled_features {
+        enabled_cfg.insert("feature".to_string(), features.clone());
+    }
+
+    let CrateSources { files, ignored, module_map } = crate_sources(crate_path, config)?;
+    if !ignored.is_empty() {
+        info!("Skipping {} files ignored in {}", ignored.len(), crate_name);
+        scan_results.ignored_files = ignored.len();
+    }
+
+    // Macros can be used in any file after they are defined, so the crate's
+    // macro_rules definitions are collected before scanning
+    let macro_defs = MacroDefs::from_files(&files);
+
+    let edition = CrateEdition::load(crate_path).unwrap_or_else(|err| {
+        warn!("Failed to load the edition of {} ({}); assuming 2015", crate_name, err);
+        CrateEdition::default()
+    });
+    info!("{} uses the {} edition", crate_name, edition.edition);
+    scan_results.edition = edition.edition;
+
+    let resolver = SharedResolver::new(resolver);
+    let scan_start = Instant::now();
+    // The files are scanned in parallel, a batch at a time, and the budgets
+    // are checked between batches. Batches don't depend on the number of
+    // jobs, so neither do the files a memory limit leaves to syntactic mode.
+    for batch in files.chunks(FILE_BATCH_SIZE) {
+        // Once over the memory budget, switch to syntactic mode for the rest
+        // of the crate
+        let over_memory =
+            budget.max_memory_mb.map_or(false, |max| memory.used_mb() > max);
+        if over_memory && scan_results.scan_limit.is_none() {
             warn!(
-                "unable to find src dir or lib.rs file; \
-                no files scanned! In crate {:?}",
-                crate_path
+                "Memory limit reached; scanning the rest of {} syntactically",
+                crate_name
             );
+            scan_results.scan_limit = Some(ScanLimit::Memory);
+            deadline = Some(Instant::now());
+        }
+        let batch_results = batch
+            .par_iter()
+            .map(|file| {
+                let _account = memory.enter();
+                let mut file_results = ScanResults::new();
+                // Parsing can't be interrupted, so past the deadline the files
+                // which would take long to parse are skipped
+                let past_deadline = deadline.map_or(false, |d| Instant::now() >= d);
+                let size = file.metadata().map_or(0, |m| m.len());
+                if past_deadline && size > MAX_FILE_SIZE_PAST_DEADLINE {
+                    warn!(
+                        "Skipping {:?} ({} bytes), as the scan is over budget",
+                        file, size
+                    );
+                    let reason = format!(
+                        "skipped the file ({} bytes) after the scan budget",
+                        size
+                    );
+                    file_results.warnings.push(ScanWarning::new(file, reason));
+                    return file_results;
+                }
+                try_scan_file(
+                    &crate_name,
+                    file.as_path(),
+                    module_map
+                        .module_of(&crate_name, file)
+                        .map(|m| CanonicalPath::new(&m)),
+                    &resolver,
+                    &mut file_results,
+                    &sinks,
+                    &enabled_cfg,
+                    relevant_effects,
+                    config,
+                    deadline,
+                    &macro_defs,
+                    &edition,
+                );
+                if proc_macro {
+                    for e in &mut file_results.effects {
+                        e.set_provenance(Provenance::BuildTime);
+                    }
+                }
+                file_results
+            })
+            .collect::<Vec<_>>();
+        for file_results in batch_results {
+            scan_results.absorb(file_results);
+            stream_new_effects(&mut scan_results, relevant_effects, config)?;
         }
     }
+    let mut scanned_files = files.clone();
+    if config.include_examples {
+        let examples = scan_examples_and_doc_tests(
+            &crate_name,
+            crate_path,
+            &files,
+            &resolver,
+            &mut scan_results,
+            &sinks,
+            &enabled_cfg,
+            relevant_effects,
+            config,
+            &macro_defs,
+            &edition,
+        );
+        scanned_files.extend(examples);
+    }
+    // The build script isn't part of the crate's module tree, so it is
+    // scanned syntactically, as build-time code or only for the malware
+    // heuristics
+    let build_script = build_script(crate_path, config)?;
+    if let (Some(build_script), true) = (&build_script, config.build_time) {
+        let mut build_sinks = sinks.clone();
+        build_sinks.extend(&Sink::build_time_sinks());
+        let modpath = format!("{}::build_script", crate_name);
+        util::fs::read_source(build_script)
+            .and_then(|src| {
+                scan_extra_source(
+                    &crate_name,
+                    build_script,
+                    &src.text,
+                    CanonicalPath::new(&modpath),
+                    Provenance::BuildTime,
+                    &resolver,
+                    &mut scan_results,
+                    &build_sinks,
+                    &enabled_cfg,
+                    relevant_effects,
+                    config,
+                    &MacroDefs::from_files(&[build_script.clone()]),
+                    &edition,
+                )
+            })
+            .unwrap_or_else(|err| {
+                warn!("Failed to scan build script of {} ({})", crate_name, err);
+                let reason = format!("skipped the build script ({})", err);
+                scan_results.warnings.push(ScanWarning::new(build_script, reason));
+            });
+        scanned_files.push(build_script.clone());
+    } else if let (Some(build_script), true) =
+        (&build_script, relevant_effects.contains(&EffectType::Malware))
+    {
+        try_scan_file(
+            &crate_name,
+            build_script.as_path(),
+            None,
+            &resolver,
+            &mut scan_results,
+            &sinks,
+            &enabled_cfg,
+            &[EffectType::Malware],
+            config,
+            Some(Instant::now()),
+            &MacroDefs::from_files(&[build_script.clone()]),
+            &edition,
+        );
+        scanned_files.push(build_script.clone());
+    }
+    metrics::metrics().add_phase_time("scan", scan_start.elapsed());
+    if scan_results.scan_limit.is_none()
+        && deadline.map_or(false, |d| Instant::now() >= d)
+    {
+        warn!("Time limit reached; {} was partially scanned syntactically", crate_name);
+        scan_results.scan_limit = Some(ScanLimit::Timeout);
+    }
+    stream_new_effects(&mut scan_results, relevant_effects, config)?;
+
+    metrics::metrics().time_phase("finish", || {
+        finish_scan(&mut scan_results, &crate_name, relevant_effects, config)
+    });
+    let description = config.describe(&sinks, relevant_effects);
+    let features = enabled_cfg.get("feature").cloned().unwrap_or_default();
+    scan_results.manifest =
+        ScanManifest::new(crate_path, &scanned_files, &description, features);
+    if config.feature_report {
+        let features = CrateFeatures::load(crate_path)?;
+        let gates = FeatureGates::from_files(&files);
+        scan_results.feature_report =
+            Some(FeatureReport::new(gates, features, enabled_cfg, &scan_results.effects));
+    }
+    // Streamed effects were counted as they were streamed
+    metrics::metrics().add_effects(scan_results.effects.len());
+    metrics::crate_scanned(&config.metrics);
+    Ok(scan_results)
+}
 
+/// Post-process the results of scanning a crate: prune the calls from
+/// unreachable functions, classify custom effects, keep the relevant effects,
+/// and sort them
+fn finish_scan(
+    scan_results: &mut ScanResults,
+    crate_name: &str,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) {
+    scan_results.link_calls();
+    // Reachability needs the calls in every function, which a partial scan
+    // may have missed
+    if !config.conservative_call_graph && scan_results.scan_limit.is_none() {
+        let pruned = scan_results.prune_unreachable_calls();
+        info!("Pruned {} calls from unreachable functions in {}", pruned, crate_name);
+    }
+
+    if let Some(custom) = &config.custom_effects {
+        custom.classify_effects(&mut scan_results.effects);
+    }
     scan_results
         .effects
         .retain(|e| EffectType::matches_effect(relevant_effects, e.eff_type()));
+    // Sort so the results are deterministic regardless of scan order
+    scan_results.effects.sort();
+    let effects = scan_results.effects.iter().cloned().collect::<HashSet<_>>();
+    scan_results.soundness_findings.retain(|f| effects.contains(&f.effect));
+    scan_results.soundness_findings.sort_by(|f1, f2| f1.effect.cmp(&f2.effect));
+    scan_results.callee_index = CalleeIndex::new(&scan_results.effects);
+}
+
+/// The crate a HIR dump is for, from its file name: `<crate>.hir` or
+/// `<crate>.hir.rs`. None for other files (e.g. plain Rust sources).
+fn hir_dump_crate_name(dump: &FilePath) -> Option<String> {
+    let name = dump.file_name()?.to_str()?;
+    let name = name.strip_suffix(".hir").or_else(|| name.strip_suffix(".hir.rs"))?;
+    Some(name.replace('-', "_"))
+}
 
+/// Scan the `-Zunpretty=hir` dumps in the directory instead of a crate's
+/// source, one file per crate named `<crate>.hir` or `<crate>.hir.rs` (e.g.
+/// from `cargo +nightly rustc -- -Zunpretty=hir > dumps/mycrate.hir`).
+/// The dumps are fully macro-expanded with their cfgs evaluated, so the
+/// effects in macro expansions (including procedural macros) are found.
+/// Names are resolved syntactically from the paths in the dump, which the
+/// expansions write out in full, and effect locations are lines in the dumps.
+pub fn scan_hir_dumps(
+    dir: &FilePath,
+    relevant_effects: &[EffectType],
+    config: &ScanConfig,
+) -> Result<ScanResults> {
+    if !dir.is_dir() {
+        return Err(anyhow!("Path is not a directory of HIR dumps: {:?}", dir));
+    }
+    let mut dumps = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|p| p.is_file())
+        .filter_map(|p| Some((hir_dump_crate_name(&p)?, p)))
+        .collect::<Vec<_>>();
+    dumps.sort_by(|(_, p1), (_, p2)| p1.cmp(p2));
+    if dumps.is_empty() {
+        return Err(anyhow!("No HIR dumps (.hir or .hir.rs files) in {:?}", dir));
+    }
+
+    let mut scan_results = ScanResults::new();
+    let mut sinks = Sink::default_sinks();
+    sinks.extend(&config.project_sinks);
+    if let Some(custom) = &config.custom_effects {
+        sinks.extend(&custom.sink_patterns());
+    }
+    // Expanded code has no 2015-style paths left to rewrite
+    let edition = CrateEdition::new(Edition::E2021, HashSet::new());
+    for (crate_name, dump) in &dumps {
+        info!("Scanning the HIR dump of {}", crate_name);
+        let src = util::fs::read_source(dump)?.text;
+        let mut syntax_tree = parse_file_skipping_errors(dump, &src, &mut scan_results)?;
+        edition.normalize(&mut syntax_tree);
+        // The whole crate is in the dump, with its modules inline
+        let mut file_resolver = FileResolver::new(crate_name, &NoResolver, dump)?;
+        file_resolver.set_modpath(CanonicalPath::new(crate_name));
+        let macro_defs = MacroDefs::default();
+        let macros = MacroExpander::new(&macro_defs);
+        let cfg = HashMap::new();
+        let mut scanner = Scanner::new(dump, file_resolver, &mut scan_results, &cfg);
+        scanner.set_sinks(&sinks);
+        scanner.set_effect_types(relevant_effects);
+        scanner.set_config(config);
+        scanner.set_macros(&macros);
+        scanner.scan_file(&syntax_tree);
+    }
+    finish_scan(&mut scan_results, "the HIR dumps", relevant_effects, config);
     Ok(scan_results)
 }
 
@@ -1100,6 +3280,389 @@ pub fn scan_crate_with_sinks(
 pub fn scan_crate(
     crate_path: &FilePath,
     relevant_effects: &[EffectType],
+    config: &ScanConfig,
 ) -> Result<ScanResults> {
-    scan_crate_with_sinks(crate_path, HashSet::new(), relevant_effects)
+    scan_crate_with_sinks(crate_path, HashSet::new(), relevant_effects, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::{MemProtection, OsHook, Severity};
+    use crate::ident::{CanonicalType, Ident};
+    use crate::resolve::Resolver;
+
+    #[test]
+    fn test_callers_outside_call_graph() {
+        // e.g. the self type of an `unsafe impl`, which isn't a function
+        let res = ScanResults::new();
+        let callers = res.get_sorted_callers(&CanonicalPath::new("my_crate::X")).unwrap();
+        assert!(callers.is_empty());
+    }
+
+    #[test]
+    fn test_scan_hir_dumps() {
+        let dir = FilePath::new("data/test-packages/hir-dumps");
+        let res = scan_hir_dumps(
+            dir,
+            crate::effect::DEFAULT_EFFECT_TYPES,
+            &ScanConfig::default(),
+        )
+        .unwrap();
+        let effects = res
+            .effects
+            .iter()
+            .map(|e| (e.caller_path(), e.callee_path()))
+            .collect::<HashSet<_>>();
+        // The println! is expanded in the dump
+        assert!(effects.contains(&("hir_ex::cleanup", "std::io::_print")));
+        assert!(effects.contains(&("hir_ex::cleanup", "std::fs::remove_file")));
+        let crate_name = |p: &str| hir_dump_crate_name(FilePath::new(p));
+        assert_eq!(crate_name("my-crate.hir.rs").as_deref(), Some("my_crate"));
+        assert_eq!(crate_name("my-crate.hir").as_deref(), Some("my_crate"));
+        assert_eq!(crate_name("lib.rs"), None);
+    }
+
+    #[test]
+    fn test_prune_unreachable_calls() {
+        let mut res = ScanResults::new();
+        let loc = SrcLoc::new(FilePath::new("src/lib.rs"), 1, 0, 1, 0);
+        let mut call = |caller: &str, callee: &str| {
+            let caller = res.update_call_graph(CanonicalPath::new(caller));
+            let callee = res.update_call_graph(CanonicalPath::new(callee));
+            res.call_graph.add_edge(caller, callee, loc.clone());
+        };
+        call("my_crate::api", "my_crate::helper");
+        call("my_crate::helper", "libc::getuid");
+        call("my_crate::dead", "libc::getuid");
+        call("my_crate::callback", "libc::getuid");
+        call("my_crate::tests::t", "my_crate::tested");
+        call("my_crate::tested", "libc::getuid");
+        for f in [
+            "my_crate::helper",
+            "my_crate::dead",
+            "my_crate::callback",
+            "my_crate::tests::t",
+            "my_crate::tested",
+        ] {
+            res.private_fns.insert(CanonicalPath::new(f));
+        }
+        // Used as a function pointer
+        res.referenced_names.insert("callback".to_string());
+        // Called by the test harness only
+        res.referenced_names.insert("t".to_string());
+        res.test_fns.insert(CanonicalPath::new("my_crate::tests::t"));
+
+        let unreachable = res.unreachable_fns();
+        let expected = ["my_crate::dead", "my_crate::tests::t", "my_crate::tested"]
+            .map(CanonicalPath::new);
+        assert_eq!(unreachable, expected.iter().collect());
+        assert_eq!(res.prune_unreachable_calls(), 3);
+        let callers = res.get_callers(&CanonicalPath::new("libc::getuid")).unwrap();
+        assert_eq!(callers.len(), 2);
+    }
+    /// Scan the source as the file src/lib.rs of the crate `test`, for the
+    /// effect types if given
+    fn scan_src(src: &str, effect_types: Option<&[EffectType]>) -> ScanResults {
+        let file: syn::File = syn::parse_str(src).unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let mut res = ScanResults::new();
+        let resolver = FileResolver::new("test", &NoResolver, filepath).unwrap();
+        let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+        if let Some(effect_types) = effect_types {
+            scanner.set_effect_types(effect_types);
+        }
+        scanner.scan_file(&file);
+        res
+    }
+    #[test]
+    fn test_test_fns() {
+        let src = "fn helper() { unsafe { getuid(); } }\n\
+                   #[test]\n\
+                   fn t() { helper(); unsafe { fork(); } }\n\
+                   #[tokio::test]\n\
+                   async fn t2() { unsafe { kill(); } }\n\
+                   #[bench]\n\
+                   fn b() {}\n";
+        let res = scan_src(src, None);
+        // The effects of tests are kept; only their calls are pruned
+        let callees = res.effects.iter().map(|e| e.callee_path()).collect::<Vec<_>>();
+        assert_eq!(callees, ["getuid", "fork", "kill"]);
+        let mut tests = res.test_fns.iter().map(|f| f.as_str()).collect::<Vec<_>>();
+        tests.sort();
+        assert_eq!(tests, ["test::b", "test::t", "test::t2"]);
+    }
+    #[test]
+    fn test_parse_skipping_errors() {
+        let src = "use std::fs;\n\
+                   fn broken() { let x = ; }\n\
+                   struct Broken<;\n\
+                   fn ok() { fs::remove_file(\"f\").unwrap(); }\n";
+        let mut res = ScanResults::new();
+        let file = parse_file_skipping_errors(FilePath::new("src/lib.rs"), src, &mut res)
+            .unwrap();
+        assert_eq!(file.items.len(), 2);
+        assert_eq!(res.warnings.len(), 2);
+        assert!(res.warnings[0].reason.starts_with("syntax error at line 2"));
+    }
+    #[test]
+    fn test_unsafe_fn_body() {
+        let file: syn::File =
+            syn::parse_str("unsafe fn f() { g(); unsafe { h(); } }").unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let mut res = ScanResults::new();
+        let resolver = FileResolver::new("test", &NoResolver, filepath).unwrap();
+        let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+        scanner.scan_file(&file);
+        let in_fn_body = res
+            .effects
+            .iter()
+            .map(|e| (e.callee_path().to_string(), e.in_unsafe_fn_body()))
+            .collect::<Vec<_>>();
+        assert_eq!(in_fn_body, [("g".to_string(), true), ("h".to_string(), false)]);
+    }
+    #[test]
+    fn test_ndjson_line() {
+        let file: syn::File = syn::parse_str("fn f() { unsafe { g(); } }").unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let mut res = ScanResults::new();
+        let resolver = FileResolver::new("test", &NoResolver, filepath).unwrap();
+        let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+        scanner.scan_file(&file);
+        assert!(!res.effects.is_empty());
+        for effect in &res.effects {
+            let line = ndjson_line(effect).unwrap();
+            assert!(!line.contains('\n'));
+            let parsed: EffectInstance = serde_json::from_str(&line).unwrap();
+            assert_eq!(&parsed, effect);
+        }
+    }
+    #[test]
+    fn test_spawn_roots() {
+        let src = "use std::thread;\n\
+                   fn start() { thread::spawn(|| work()); }\n\
+                   fn run() { thread::spawn(task); }\n\
+                   fn work() {}\n\
+                   fn task() {}\n";
+        let file: syn::File = syn::parse_str(src).unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let mut res = ScanResults::new();
+        let resolver = FileResolver::new("test", &NoResolver, filepath).unwrap();
+        let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+        scanner.set_effect_types(&[EffectType::ThreadSpawn]);
+        scanner.scan_file(&file);
+        assert_eq!(res.effects.len(), 2);
+        assert!(res.effects.iter().all(|e| e.callee_path().ends_with("thread::spawn")));
+        assert!(res.spawn_roots.iter().any(|f| f.as_str().ends_with("task")));
+        // The closure's calls are attributed to start, which is now a root
+        let start = res.private_fns.iter().find(|f| f.as_str().ends_with("start"));
+        assert!(res.spawn_roots.contains(start.unwrap()));
+        assert!(!res.unreachable_fns().iter().any(|f| f.as_str().ends_with("start")));
+    }
+    #[test]
+    fn test_os_hooks() {
+        let src = "fn hooks() {\n\
+                       unsafe { libc::fork(); }\n\
+                       unsafe { libc::pthread_atfork(None, None, Some(child)); }\n\
+                       ctrlc::set_handler(|| ());\n\
+                       unsafe { mylib::libc::fork(); }\n\
+                       flag::register(SIGTERM, term);\n\
+                   }\n";
+        let res = scan_src(src, Some(&[EffectType::OsHook]));
+        let hooks = res
+            .effects
+            .iter()
+            .filter_map(|e| match e.eff_type() {
+                Effect::OsHook(p, h) => Some((p.as_str(), *h)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Only the full paths of the hooks match, not functions which happen
+        // to have the same last segments
+        assert_eq!(
+            hooks,
+            [
+                ("libc::fork", OsHook::Fork),
+                ("libc::pthread_atfork", OsHook::Fork),
+                ("ctrlc::set_handler", OsHook::SignalHandler),
+            ]
+        );
+    }
+    #[test]
+    fn test_memory_maps() {
+        let src = "fn jit(len: usize, prot: i32) {\n\
+                       unsafe {\n\
+                           let p = libc::mmap(null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, \
+                                              MAP_PRIVATE | MAP_ANON, -1, 0);\n\
+                           libc::mprotect(p, len, libc::PROT_READ | libc::PROT_EXEC);\n\
+                           libc::mprotect(p, len, PROT_NONE);\n\
+                           libc::mmap(null_mut(), len, prot, MAP_PRIVATE, -1, 0);\n\
+                       }\n\
+                   }\n";
+        let res = scan_src(src, Some(&[EffectType::MemoryMap]));
+        let prot = |read, write, exec| Some(MemProtection { read, write, exec });
+        let maps = res
+            .effects
+            .iter()
+            .filter_map(|e| match e.eff_type() {
+                Effect::MemoryMap(p, m) => Some((p.as_str(), *m)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            maps,
+            [
+                ("libc::mmap", prot(true, true, false)),
+                ("libc::mprotect", prot(true, false, true)),
+                ("libc::mprotect", prot(false, false, false)),
+                // The protection isn't known
+                ("libc::mmap", None),
+            ]
+        );
+        // Executable mappings are the most severe
+        let exec =
+            res.effects.iter().find(|e| e.eff_type().severity() == Severity::Critical);
+        assert_eq!(exec.map(|e| e.call_loc().start_line()), Some(4));
+    }
+    /// A resolver which only knows the names and types of the identifiers it
+    /// is given, as rust-analyzer would resolve them
+    #[derive(Debug, Default)]
+    struct StubResolver {
+        paths: HashMap<&'static str, &'static str>,
+        types: HashMap<&'static str, &'static str>,
+    }
+
+    impl Resolver for StubResolver {
+        fn resolve_ident(&self, s: SrcLoc, i: Ident) -> Result<CanonicalPath> {
+            match self.paths.get(i.to_string().as_str()) {
+                Some(path) => Ok(CanonicalPath::new(path)),
+                None => NoResolver.resolve_ident(s, i),
+            }
+        }
+        fn resolve_type(&self, s: SrcLoc, i: Ident) -> Result<CanonicalType> {
+            match self.types.get(i.to_string().as_str()) {
+                Some(ty) => Ok(CanonicalType::new(ty)),
+                None => NoResolver.resolve_type(s, i),
+            }
+        }
+        fn is_ffi(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+            NoResolver.is_ffi(s, i)
+        }
+        fn is_unsafe_call(&self, s: SrcLoc, i: Ident) -> Result<bool> {
+            NoResolver.is_unsafe_call(s, i)
+        }
+        fn is_const_or_immutable_static_ident(
+            &self,
+            s: SrcLoc,
+            i: Ident,
+        ) -> Result<bool> {
+            NoResolver.is_const_or_immutable_static_ident(s, i)
+        }
+        fn all_impl_methods_for_trait_method(
+            &self,
+            s: SrcLoc,
+            i: Ident,
+            m: String,
+        ) -> Result<Vec<CanonicalPath>> {
+            NoResolver.all_impl_methods_for_trait_method(s, i, m)
+        }
+        fn get_cfg_options_for_crate(
+            &self,
+            name: &str,
+        ) -> Result<HashMap<String, Vec<String>>> {
+            NoResolver.get_cfg_options_for_crate(name)
+        }
+    }
+
+    #[test]
+    fn test_operator_calls() {
+        let src = "use std::ops::{Add, Index};\n\
+                   pub struct Meters(u64);\n\
+                   pub struct Log;\n\
+                   impl Add for Meters {\n\
+                       type Output = Meters;\n\
+                       fn add(self, o: Meters) -> Meters { unsafe { report(); } Meters(0) }\n\
+                   }\n\
+                   impl Index<usize> for Log {\n\
+                       type Output = u8;\n\
+                       fn index(&self, i: usize) -> &u8 { std::fs::remove_file(\"x\"); &0 }\n\
+                   }\n\
+                   pub fn total(a: Meters, b: Meters) -> Meters { a + b }\n\
+                   pub fn first(log: Log) -> u8 { log[0] }\n";
+        let file: syn::File = syn::parse_str(src).unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let stub = StubResolver {
+            paths: HashMap::from([
+                ("add", "test::Meters::add"),
+                ("index", "test::Log::index"),
+            ]),
+            types: HashMap::from([("a", "test::Meters"), ("log", "test::Log")]),
+        };
+        let scan = |attribute_operators| {
+            let mut res = ScanResults::new();
+            let resolver = FileResolver::new("test", &stub, filepath).unwrap();
+            let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+            scanner.attribute_operators = attribute_operators;
+            scanner.scan_file(&file);
+            res
+        };
+        let reached = |res: &ScanResults, f: &str| {
+            let mut callees = res
+                .reachable_effects(&CanonicalPath::new(f))
+                .iter()
+                .map(|e| e.callee_path().to_string())
+                .collect::<Vec<_>>();
+            callees.sort();
+            callees
+        };
+
+        // The impls reach effects whether or not their uses are attributed
+        let res = scan(false);
+        let meths = res
+            .effectful_operator_meths()
+            .iter()
+            .map(|(m, tr, _)| (m.as_str(), *tr))
+            .collect::<Vec<_>>();
+        assert_eq!(meths, [("test::Log::index", "Index"), ("test::Meters::add", "Add")]);
+        assert!(reached(&res, "test::total").is_empty());
+
+        // With attribution, `a + b` and `log[0]` are calls to the impls
+        let res = scan(true);
+        let callers = res.get_callers(&CanonicalPath::new("test::Meters::add")).unwrap();
+        assert_eq!(
+            callers.iter().map(|c| c.caller_path.as_str()).collect::<Vec<_>>(),
+            ["test::total"]
+        );
+        assert_eq!(reached(&res, "test::total"), ["report"]);
+        assert_eq!(reached(&res, "test::first"), ["std::fs::remove_file"]);
+    }
+    #[test]
+    fn test_alias_escape_paths() {
+        let src = "impl Cell {\n\
+                       fn get_mut(&self) -> &mut u8 {\n\
+                           unsafe { &mut *(&self.byte as *const u8 as *mut u8) }\n\
+                       }\n\
+                   }\n\
+                   fn reborrow(x: &u8) -> &mut u8 { unsafe { &mut *(x as *const u8).cast_mut() } }\n";
+        let file: syn::File = syn::parse_str(src).unwrap();
+        let filepath = FilePath::new("src/lib.rs");
+        let enabled_cfg = HashMap::new();
+        let stub = StubResolver {
+            paths: HashMap::from([("byte", "test::Cell::byte")]),
+            ..Default::default()
+        };
+        let mut res = ScanResults::new();
+        let resolver = FileResolver::new("test", &stub, filepath).unwrap();
+        let mut scanner = Scanner::new(filepath, resolver, &mut res, &enabled_cfg);
+        scanner.set_effect_types(&[EffectType::AliasEscape]);
+        scanner.scan_file(&file);
+        // The escaping places are named by their paths, not their tokens
+        let callees = res.effects.iter().map(|e| e.callee_path()).collect::<Vec<_>>();
+        assert_eq!(callees, ["test::Cell::byte", "x"]);
+    }
 }
@@ -0,0 +1,481 @@
+//! A small HTTP API over an audit file, with a web UI for reviewing it.
+//!
+//! `GET /` serves the review UI (`review_ui.html`): a queue of the effects,
+//! the source around the selected effect, buttons to record decisions, and
+//! its call chain. The other endpoints respond with JSON:
+//! - `GET /effects` lists the base effects in the audit, optionally filtered
+//!   with the query parameters `effect_kind`, `callee_prefix` (matched by
+//!   whole path segments), and `status`
+//! - `GET /effects/<id>` gets a single effect
+//! - `GET /effects/<id>/snippet` gets the source lines around an effect;
+//!   the `context` query parameter controls how many lines (default 4)
+//! - `POST /effects/<id>/decision?status=<annotation>` records an audit
+//!   decision for an effect and saves the audit file; the `justification`
+//!   query parameter is required for unsafe, deferred, and false positive
+//!   decisions, and the `ref` query parameter attaches an issue tracker URL
+//! - `GET /effects/<id>/chain` gets the call chain of an effect: the callers
+//!   added to the audit when it was marked caller-checked, recursively
+//!
+//! - `GET /badges/<kind>.svg` renders a badge for the audit (see badge.rs),
+//!   e.g. `/badges/audited.svg`, for READMEs to link to
+//!
+//! Effect ids are indices into the effects sorted by source location, so
+//! they are stable for a given audit file.
+//!
+//! The server only answers requests whose `Host` is the address it is bound
+//! to, so other sites can't reach it by rebinding their domain to it. POST
+//! requests must also come from that origin and carry the token printed at
+//! startup in the `X-Audit-Token` header; the review UI picks the token up
+//! from the `?token=` parameter of the URL it is opened with.
+
+use crate::audit_file::{validate_reference, AuditFile, EffectTree, SafetyAnnotation};
+use crate::badge::{Badge, BadgeKind};
+use crate::effect::{EffectInstance, EffectType};
+use crate::http::{self, Request, Response};
+use crate::progress;
+use crate::util;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct EffectJson<'a> {
+    id: usize,
+    crate_name: String,
+    caller: &'a str,
+    callee: &'a str,
+    effect_kind: String,
+    effect: Cow<'a, str>,
+    /// e.g. inside `.map()` closure in `process_files`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
+    file: String,
+    line: usize,
+    col: usize,
+    status: String,
+    justification: Option<&'a str>,
+    /// Issue tracker URLs for the decision
+    references: &'a [String],
+}
+
+#[derive(Serialize)]
+struct SnippetJson {
+    id: usize,
+    file: String,
+    start_line: usize,
+    lines: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ChainJson<'a> {
+    caller: &'a str,
+    file: String,
+    line: usize,
+    status: String,
+    /// Callers to check, if the effect is caller-checked
+    callers: Vec<ChainJson<'a>>,
+}
+
+impl<'a> ChainJson<'a> {
+    fn new(tree: &'a EffectTree) -> Self {
+        let (info, status, callers) = match tree {
+            EffectTree::Leaf(info, a) => (info, *a, Vec::new()),
+            EffectTree::Branch(info, ts) => (
+                info,
+                SafetyAnnotation::CallerChecked,
+                ts.iter().map(Self::new).collect(),
+            ),
+        };
+        Self {
+            caller: info.caller_path.as_str(),
+            file: info.callee_loc.filepath_string(),
+            line: info.callee_loc.start_line(),
+            status: status.to_string(),
+            callers,
+        }
+    }
+}
+
+/// The review UI
+const REVIEW_UI: &str = include_str!("review_ui.html");
+
+pub struct AuditServer {
+    audit_file: AuditFile,
+    audit_file_path: PathBuf,
+    /// Base effects, sorted by location so ids are stable
+    effects: Vec<EffectInstance>,
+    /// Required on requests that change the audit; fresh for each run
+    token: String,
+    /// `Host` headers that name the address the server is bound to
+    hosts: Vec<String>,
+}
+
+impl AuditServer {
+    pub fn new(audit_file: AuditFile, audit_file_path: PathBuf) -> Self {
+        let mut effects = audit_file.audit_trees.keys().cloned().collect::<Vec<_>>();
+        effects.sort();
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let token = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        Self { audit_file, audit_file_path, effects, token, hosts: Vec::new() }
+    }
+
+    /// Serve requests on the given address until the process is killed
+    pub fn serve(&mut self, addr: &str) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        self.hosts = bound_hosts(local_addr);
+        progress!("Serving audit on http://{}/?token={}", local_addr, self.token);
+        http::serve(listener, |req| {
+            self.route(req).unwrap_or_else(|e| Response::error(500, &e.to_string()))
+        });
+        Ok(())
+    }
+
+    fn route(&mut self, req: &Request) -> Result<Response> {
+        if let Some(denied) = self.check_request(req) {
+            return Ok(denied);
+        }
+        let path = req.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        match (req.method.as_str(), &path[..]) {
+            ("GET", []) => Ok(Response::html(REVIEW_UI)),
+            ("GET", ["effects"]) => self.list_effects(&req.query),
+            ("GET", ["effects", id]) => match self.effect_id(id) {
+                Some(id) => Response::ok(&self.effect_json(id)),
+                None => Ok(Response::error(404, "no such effect")),
+            },
+            ("GET", ["effects", id, "snippet"]) => match self.effect_id(id) {
+                Some(id) => self.snippet(id, &req.query),
+                None => Ok(Response::error(404, "no such effect")),
+            },
+            ("GET", ["effects", id, "chain"]) => match self.effect_id(id) {
+                Some(id) => self.chain(id),
+                None => Ok(Response::error(404, "no such effect")),
+            },
+            ("POST", ["effects", id, "decision"]) => match self.effect_id(id) {
+                Some(id) => self.record_decision(id, &req.query),
+                None => Ok(Response::error(404, "no such effect")),
+            },
+            ("GET", ["badges", file]) => {
+                let kind = file
+                    .strip_suffix(".svg")
+                    .and_then(|kind| BadgeKind::from_str(kind, false).ok());
+                match kind {
+                    Some(kind) => Ok(Response::svg(
+                        Badge::from_audit(kind, &self.audit_file).to_svg(),
+                    )),
+                    None => Ok(Response::error(404, "no such badge")),
+                }
+            }
+            (_, ["effects", ..]) => Ok(Response::error(405, "method not allowed")),
+            _ => Ok(Response::error(404, "unknown endpoint")),
+        }
+    }
+
+    /// Reject requests from other sites: any request for another host, and
+    /// state-changing requests from another origin or without the token
+    fn check_request(&self, req: &Request) -> Option<Response> {
+        let host = req.headers.get("host");
+        if !host.is_some_and(|h| self.hosts.contains(h)) {
+            return Some(Response::error(403, "unexpected Host"));
+        }
+        if req.method == "GET" {
+            return None;
+        }
+        if let Some(origin) = req.headers.get("origin") {
+            let origin_host = origin.strip_prefix("http://");
+            if !origin_host.is_some_and(|h| self.hosts.iter().any(|host| host == h)) {
+                return Some(Response::error(403, "unexpected Origin"));
+            }
+        }
+        if req.headers.get("x-audit-token") != Some(&self.token) {
+            return Some(Response::error(403, "missing or invalid X-Audit-Token"));
+        }
+        None
+    }
+
+    fn effect_id(&self, id: &str) -> Option<usize> {
+        id.parse::<usize>().ok().filter(|id| *id < self.effects.len())
+    }
+
+    fn status(&self, effect: &EffectInstance) -> String {
+        match self.audit_file.audit_trees.get(effect) {
+            Some(EffectTree::Leaf(_, a)) => a.to_string(),
+            Some(EffectTree::Branch(_, _)) => SafetyAnnotation::CallerChecked.to_string(),
+            None => SafetyAnnotation::Unreviewed.to_string(),
+        }
+    }
+
+    fn effect_json(&self, id: usize) -> EffectJson {
+        let e = &self.effects[id];
+        EffectJson {
+            id,
+            crate_name: e.caller().crate_name().to_string(),
+            caller: e.caller_path(),
+            callee: e.callee_path(),
+            effect_kind: EffectType::from(e.eff_type()).to_string(),
+            effect: e.eff_type().simple_str(),
+            context: e.closure_description(),
+            file: e.call_loc().filepath_string(),
+            line: e.call_loc().start_line(),
+            col: e.call_loc().start_col(),
+            status: self.status(e),
+            justification: match self.audit_file.audit_trees.get(e) {
+                Some(EffectTree::Leaf(info, _)) => {
+                    self.audit_file.justifications.get(info).map(|j| j.as_str())
+                }
+                _ => None,
+            },
+            references: match self.audit_file.audit_trees.get(e) {
+                Some(EffectTree::Leaf(info, _) | EffectTree::Branch(info, _)) => {
+                    self.audit_file.references.get(info).map_or(&[], |r| r.as_slice())
+                }
+                None => &[],
+            },
+        }
+    }
+
+    fn list_effects(&self, query: &HashMap<String, String>) -> Result<Response> {
+        let effects = (0..self.effects.len())
+            .map(|id| self.effect_json(id))
+            .filter(|e| query.get("effect_kind").map_or(true, |k| &e.effect_kind == k))
+            .filter(|e| {
+                query.get("callee_prefix").map_or(true, |p| {
+                    let p = p.trim_end_matches("::");
+                    e.callee == p || e.callee.starts_with(&format!("{}::", p))
+                })
+            })
+            .filter(|e| query.get("status").map_or(true, |s| &e.status == s))
+            .collect::<Vec<_>>();
+        Response::ok(&effects)
+    }
+
+    fn snippet(&self, id: usize, query: &HashMap<String, String>) -> Result<Response> {
+        let context = match query.get("context").map(|c| c.parse::<usize>()) {
+            Some(Ok(c)) => c,
+            Some(Err(_)) => return Ok(Response::error(400, "invalid context")),
+            None => 4,
+        };
+        let loc = self.effects[id].call_loc();
+        let src = util::fs::read_source(Path::new(&loc.filepath_string()))?.text;
+        // SrcLocs are 1-indexed
+        let start_line = loc.start_line().saturating_sub(context).max(1);
+        let lines = src
+            .lines()
+            .skip(start_line - 1)
+            .take(loc.end_line() + context + 1 - start_line)
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+        Response::ok(&SnippetJson { id, file: loc.filepath_string(), start_line, lines })
+    }
+
+    fn chain(&self, id: usize) -> Result<Response> {
+        let tree = self
+            .audit_file
+            .audit_trees
+            .get(&self.effects[id])
+            .ok_or_else(|| anyhow!("Missing audit tree for effect"))?;
+        Response::ok(&ChainJson::new(tree))
+    }
+
+    fn record_decision(
+        &mut self,
+        id: usize,
+        query: &HashMap<String, String>,
+    ) -> Result<Response> {
+        let annotation = match query.get("status").map(|s| s.parse::<SafetyAnnotation>())
+        {
+            // Marking an effect caller-checked requires expanding its callers
+            // from a fresh scan, which has to be done in the interactive audit
+            Some(Ok(SafetyAnnotation::CallerChecked)) => {
+                return Ok(Response::error(
+                    400,
+                    "caller-checked decisions must be made with the audit tool",
+                ))
+            }
+            Some(Ok(a)) => a,
+            _ => return Ok(Response::error(400, "invalid or missing status")),
+        };
+
+        let tree = self
+            .audit_file
+            .audit_trees
+            .get_mut(&self.effects[id])
+            .ok_or_else(|| anyhow!("Missing audit tree for effect"))?;
+        let info = match tree {
+            EffectTree::Leaf(info, _) => info.clone(),
+            EffectTree::Branch(_, _) => {
+                return Ok(Response::error(
+                    400,
+                    "effect has already been marked caller-checked",
+                ))
+            }
+        };
+        let reference = query.get("ref");
+        if let Some(Err(e)) = reference.map(|r| validate_reference(r)) {
+            return Ok(Response::error(400, &e.to_string()));
+        }
+        let justification = query.get("justification").cloned();
+        if let Err(e) = AuditFile::set_justification(
+            &mut self.audit_file.justifications,
+            &info,
+            annotation,
+            justification,
+        ) {
+            return Ok(Response::error(400, &e.to_string()));
+        }
+        tree.set_annotation(annotation);
+        if let Some(r) = reference {
+            self.audit_file.add_reference(&info, r)?;
+        }
+        self.audit_file.save_to_file(self.audit_file_path.clone())?;
+
+        Response::ok(&self.effect_json(id))
+    }
+}
+
+/// The `Host` headers a browser sends for the address
+fn bound_hosts(addr: SocketAddr) -> Vec<String> {
+    let mut hosts = vec![addr.to_string()];
+    if addr.ip().is_loopback() {
+        hosts.push(format!("localhost:{}", addr.port()));
+    }
+    hosts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auditing::decision_log;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+
+    fn ffi_server(audit_file_path: PathBuf) -> AuditServer {
+        let effects = ["getuid", "fork"]
+            .iter()
+            .map(|f| {
+                let callee = CanonicalPath::new(&format!("libc::{}", f));
+                let ident = syn::Ident::new(f, proc_macro2::Span::call_site());
+                EffectInstance::new_effect(
+                    Path::new("src/lib.rs"),
+                    CanonicalPath::new("test::f"),
+                    callee.clone(),
+                    &ident,
+                    Effect::FFICall(callee),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut audit = AuditFile::empty(PathBuf::from("missing"), vec![]).unwrap();
+        audit.set_base_audit_trees(effects.iter());
+        let mut server = AuditServer::new(audit, audit_file_path);
+        server.hosts = bound_hosts("127.0.0.1:8080".parse().unwrap());
+        server
+    }
+
+    fn request(server: &AuditServer, line: &str, headers: &[(&str, &str)]) -> Request {
+        let mut headers = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        headers.entry("host".to_string()).or_insert("localhost:8080".to_string());
+        if headers.get("x-audit-token").is_some_and(|t| t == "<token>") {
+            headers.insert("x-audit-token".to_string(), server.token.clone());
+        }
+        http::parse_request(line, headers).unwrap()
+    }
+
+    fn get(server: &mut AuditServer, line: &str) -> Response {
+        let req = request(server, line, &[]);
+        server.route(&req).unwrap()
+    }
+
+    #[test]
+    fn test_routes() {
+        let mut server = ffi_server(PathBuf::from("missing.audit"));
+        let resp = get(&mut server, "GET /effects HTTP/1.1");
+        assert_eq!(resp.status, 200);
+        let effects: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(effects.as_array().unwrap().len(), 2);
+
+        let resp = get(&mut server, "GET /effects?callee_prefix=libc%3A%3Afork HTTP/1.1");
+        let effects: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(effects[0]["callee"], "libc::fork");
+        assert_eq!(effects[0]["status"], "Unreviewed");
+        let resp = get(&mut server, "GET /effects?callee_prefix=libc%3A%3Af HTTP/1.1");
+        assert_eq!(resp.body, "[]");
+
+        assert_eq!(get(&mut server, "GET /effects/1 HTTP/1.1").status, 200);
+        assert_eq!(get(&mut server, "GET /effects/2 HTTP/1.1").status, 404);
+        assert_eq!(get(&mut server, "GET /effects/0/chain HTTP/1.1").status, 200);
+        assert_eq!(get(&mut server, "GET /badges/audited.svg HTTP/1.1").status, 200);
+        assert_eq!(get(&mut server, "GET /badges/none.svg HTTP/1.1").status, 404);
+        let delete = request(
+            &server,
+            "DELETE /effects/0 HTTP/1.1",
+            &[("x-audit-token", "<token>")],
+        );
+        assert_eq!(server.route(&delete).unwrap().status, 405);
+        assert_eq!(get(&mut server, "GET /nothing HTTP/1.1").status, 404);
+        assert!(get(&mut server, "GET / HTTP/1.1").body.contains("<html"));
+    }
+
+    #[test]
+    fn test_rejects_other_sites() {
+        let mut server = ffi_server(PathBuf::from("missing.audit"));
+        let rebound = request(&server, "GET /effects HTTP/1.1", &[("host", "evil.com")]);
+        assert_eq!(server.route(&rebound).unwrap().status, 403);
+
+        let decision = "POST /effects/0/decision?status=safe HTTP/1.1";
+        let no_token = request(&server, decision, &[]);
+        assert_eq!(server.route(&no_token).unwrap().status, 403);
+        let wrong_token = request(&server, decision, &[("x-audit-token", "guess")]);
+        assert_eq!(server.route(&wrong_token).unwrap().status, 403);
+        let cross_origin = request(
+            &server,
+            decision,
+            &[("x-audit-token", "<token>"), ("origin", "http://evil.com")],
+        );
+        assert_eq!(server.route(&cross_origin).unwrap().status, 403);
+        assert_eq!(server.status(&server.effects[0]), "Unreviewed");
+    }
+
+    #[test]
+    fn test_record_decision() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.audit");
+        let mut server = ffi_server(path.clone());
+        let headers = [("x-audit-token", "<token>"), ("origin", "http://localhost:8080")];
+        let mut decide = |query: &str| {
+            let line = format!("POST /effects/1/decision?{} HTTP/1.1", query);
+            let req = request(&server, &line, &headers);
+            server.route(&req).unwrap()
+        };
+
+        assert_eq!(decide("status=bogus").status, 400);
+        assert_eq!(decide("status=caller-checked").status, 400);
+        // Unsafe decisions need a justification
+        assert_eq!(decide("status=unsafe").status, 400);
+        assert_eq!(decide("status=unsafe&ref=not%20a%20url").status, 400);
+        let resp = decide(
+            "status=unsafe&justification=forks+the+process\
+             &ref=https%3A%2F%2Fgithub.com%2Forg%2Frepo%2Fissues%2F1",
+        );
+        assert_eq!(resp.status, 200);
+        let effect: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(effect["status"], "Unsafe");
+        assert_eq!(effect["justification"], "forks the process");
+        assert_eq!(effect["references"][0], "https://github.com/org/repo/issues/1");
+
+        let saved = AuditFile::read_audit_file(path.clone()).unwrap().unwrap();
+        assert!(decision_log::log_path(&path).exists());
+        let saved = AuditServer::new(saved, path);
+        assert_eq!(saved.status(&saved.effects[1]), "Unsafe");
+        assert_eq!(saved.status(&saved.effects[0]), "Unreviewed");
+    }
+}
@@ -1,14 +1,27 @@
 //! Hard-coded list of function patterns of interest, a.k.a. sinks.
+//!
+//! Projects can add sink patterns in the `sinks.toml` file of their audit
+//! directory (see `project`), e.g.
+//! ```toml
+//! sinks = ["reqwest", "rusqlite::Connection", "!rusqlite::Connection::open_in_memory"]
+//! ```
+//! which is also a policy file (see `policy`). A pattern starting with `!`
+//! excludes the paths it matches from all the sinks (see `PatternSet`). Scans of the project's crate
+//! look for the built-in sinks, the project's, and the patterns of the custom
+//! effect kinds (see `taxonomy`); `policy sinks list` shows them all.
 
 use crate::ident::Ident;
+use crate::project::ProjectConfig;
+use crate::taxonomy::CustomEffects;
 
-use super::ident::{CanonicalPath, IdentPath, Pattern};
+use super::ident::{CanonicalPath, Pattern, PatternSet};
 
+use anyhow::{anyhow, Context, Result};
 use log::warn;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
     fmt::{self, Display},
+    path::Path,
 };
 
 // TODO: Convert these examples to canonical paths
@@ -43,7 +56,26 @@ const SINK_PATTERNS: &[&str] = &[
     "socket2",
 ];
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Sink patterns added for code run at compile time (build scripts and proc
+/// macros; see `scanner::ScanConfig::build_time`), on top of the hard-coded
+/// ones: a build has little reason to download files, run native builds,
+/// or depend on the time
+const BUILD_TIME_SINK_PATTERNS: &[&str] = &[
+    "std::thread",
+    "std::time::SystemTime",
+    "cc",
+    "cmake",
+    "pkg_config",
+    "reqwest",
+    "ureq",
+    "curl",
+    "attohttpc",
+    "git2",
+    "tar",
+    "zip",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Sink(Pattern);
 
 impl Display for Sink {
@@ -53,20 +85,17 @@ impl Display for Sink {
 }
 
 impl Sink {
-    /// Get the sink pattern matching a callee.
-    pub fn new_match(callee: &CanonicalPath, sinks: &HashSet<IdentPath>) -> Option<Self> {
+    /// Get the sink pattern matching a callee, unless an exclusion matches it.
+    pub fn new_match(callee: &CanonicalPath, sinks: &PatternSet) -> Option<Self> {
         let mut result = None;
-        for pat_raw in sinks {
-            let pat = Pattern::new(pat_raw.as_str());
-            if callee.matches(&pat) {
-                if let Some(x) = result {
-                    warn!(
+        for pat in sinks.matching(callee.as_path()) {
+            if let Some(x) = result {
+                warn!(
                     "Found multiple patterns of interest for {} (overwriting {} with {})",
                     callee, x, pat
                 );
-                }
-                result = Some(pat)
             }
+            result = Some(pat.clone())
         }
         Some(Self(result?))
     }
@@ -80,7 +109,184 @@ impl Sink {
         self.0.as_str()
     }
 
-    pub fn default_sinks() -> HashSet<IdentPath> {
-        SINK_PATTERNS.iter().map(|x| IdentPath::new(x)).collect::<HashSet<_>>()
+    pub fn default_sinks() -> PatternSet {
+        PatternSet::from_strs(SINK_PATTERNS.iter().copied())
+    }
+
+    /// The patterns added to the sinks for code run at compile time
+    pub fn build_time_sinks() -> PatternSet {
+        PatternSet::from_strs(BUILD_TIME_SINK_PATTERNS.iter().copied())
+    }
+}
+
+/// Check that a sink pattern is a path of identifiers, e.g. `std::net` or
+/// `tokio_util::udp` (hyphens in crate names are allowed), or an exclusion
+/// of one, e.g. `!std::net::lookup_host`
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    let path = PatternSet::negated(pattern).unwrap_or(pattern);
+    if path.replace('-', "_").split("::").all(Ident::str_ok) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid sink pattern {:?}: expected a path like std::net or libc, \
+            or !<path> to exclude one",
+            pattern
+        ))
+    }
+}
+
+/// The sink patterns of a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SinkFile {
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+impl SinkFile {
+    /// Read the file, or no patterns if it doesn't exist
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let s = std::fs::read_to_string(path)
+            .context(format!("Couldn't read sinks from {:?}", path))?;
+        let file: Self =
+            toml::from_str(&s).map_err(|e| anyhow!("Invalid sinks {:?}: {}", path, e))?;
+        for pattern in &file.sinks {
+            validate_pattern(pattern).context(format!("Invalid sinks {:?}", path))?;
+        }
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Add a pattern; returns false if the file already has it
+    pub fn add(&mut self, pattern: &str) -> Result<bool> {
+        validate_pattern(pattern)?;
+        if self.sinks.iter().any(|s| s == pattern) {
+            return Ok(false);
+        }
+        self.sinks.push(pattern.to_string());
+        self.sinks.sort();
+        Ok(true)
+    }
+
+    /// Remove a pattern; returns false if the file doesn't have it
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.sinks.len();
+        self.sinks.retain(|s| s != pattern);
+        self.sinks.len() < len
+    }
+
+    pub fn sink_patterns(&self) -> PatternSet {
+        PatternSet::from_strs(self.sinks.iter().map(String::as_str))
+    }
+}
+
+/// The project's sink patterns, if the crate has a project config
+pub fn project_sinks(crate_path: &Path) -> Result<PatternSet> {
+    match ProjectConfig::read(crate_path)? {
+        Some(config) => {
+            Ok(SinkFile::read(&config.sinks_path(crate_path))?.sink_patterns())
+        }
+        None => Ok(PatternSet::new()),
+    }
+}
+
+/// Where a sink pattern of the inventory comes from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SinkSource {
+    BuiltIn,
+    Project,
+    /// A pattern of the custom effect kind
+    CustomEffect(String),
+}
+
+impl Display for SinkSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkSource::BuiltIn => write!(f, "built-in"),
+            SinkSource::Project => write!(f, "project"),
+            SinkSource::CustomEffect(kind) => write!(f, "custom effect {}", kind),
+        }
+    }
+}
+
+/// The sink patterns scans look for, with where each comes from, sorted by
+/// pattern. A pattern from several sources is listed for each.
+pub fn inventory(
+    project: &SinkFile,
+    custom: Option<&CustomEffects>,
+) -> Vec<(String, SinkSource)> {
+    let mut res = SINK_PATTERNS
+        .iter()
+        .map(|p| (p.to_string(), SinkSource::BuiltIn))
+        .chain(project.sinks.iter().map(|p| (p.clone(), SinkSource::Project)))
+        .collect::<Vec<_>>();
+    for def in custom.map_or(&[][..], |c| &c.effects[..]) {
+        for p in &def.patterns {
+            res.push((p.clone(), SinkSource::CustomEffect(def.kind.clone())));
+        }
+    }
+    res.sort();
+    res
+}
+
+/// The sink inventory of the crate's project: its sink file, and the given
+/// custom effects or else those of the project config
+pub fn project_inventory(
+    crate_path: &Path,
+    custom: Option<&CustomEffects>,
+) -> Result<Vec<(String, SinkSource)>> {
+    let config = ProjectConfig::read(crate_path)?;
+    let project = match &config {
+        Some(config) => SinkFile::read(&config.sinks_path(crate_path))?,
+        None => SinkFile::default(),
+    };
+    let custom = match (custom, config.and_then(|c| c.custom_effects)) {
+        (Some(custom), _) => Some(custom.clone()),
+        (None, Some(path)) => Some(CustomEffects::load(&path)?),
+        (None, None) => None,
+    };
+    Ok(inventory(&project, custom.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_file() {
+        assert!(validate_pattern("tokio-util::udp").is_ok());
+        assert!(validate_pattern("r#async::spawn").is_ok());
+        assert!(validate_pattern("!std::fs::remove_dir_all").is_ok());
+        for bad in ["", "std::", "::std", "std::net::*", "std net", "!", "!!std"] {
+            assert!(validate_pattern(bad).is_err(), "{:?}", bad);
+        }
+
+        let mut file: SinkFile = toml::from_str("sinks = [\"reqwest\"]").unwrap();
+        assert!(file.add("rusqlite::Connection").unwrap());
+        assert!(!file.add("reqwest").unwrap());
+        assert!(file.add("reqwest::*").is_err());
+        assert!(file.remove("reqwest"));
+        assert!(!file.remove("reqwest"));
+        assert_eq!(file.sinks, ["rusqlite::Connection"]);
+
+        let custom = CustomEffects::from_toml(
+            "[[effect]]\nkind = \"Crypto\"\npatterns = [\"ring\", \"std::fs\"]",
+        )
+        .unwrap();
+        let inventory = inventory(&file, Some(&custom));
+        assert_eq!(inventory.len(), SINK_PATTERNS.len() + 3);
+        let fs = inventory.iter().filter(|(p, _)| p == "std::fs").collect::<Vec<_>>();
+        assert_eq!(
+            fs.iter().map(|(_, s)| s.to_string()).collect::<Vec<_>>(),
+            ["built-in", "custom effect Crypto"]
+        );
+        assert!(inventory
+            .contains(&("rusqlite::Connection".to_string(), SinkSource::Project)));
     }
 }
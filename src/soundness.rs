@@ -0,0 +1,455 @@
+//! Soundness lints: known unsoundness patterns, found by combining an effect
+//! with the syntax of the function it is in.
+//!
+//! - `SetLenBeforeInit`: `Vec::set_len` growing a vector whose elements
+//!   weren't written first (through `as_mut_ptr`, `spare_capacity_mut`,
+//!   `resize`, ...), e.g. `Vec::with_capacity(n)` then `set_len(n)`
+//! - `UnboundedLifetime`: `slice::from_raw_parts` in a function returning a
+//!   reference whose lifetime is `'static` or not tied to any argument, so the
+//!   slice can outlive the data it points to. The lifetimes of the impl block's
+//!   self type are tied to the receiver of a method.
+//! - `TransmuteSizeMismatch`: `transmute` of a pointer or reference to one to
+//!   a larger type, or `transmute_copy` to a larger type, so reads go past the
+//!   end of the source
+//!
+//! The checks are syntactic and local to the function, so they can miss
+//! cases (e.g. initialization in a helper function) and only know the sizes
+//! of primitive types and arrays of them.
+
+use crate::effect::{EffectInstance, SrcLoc};
+
+use parse_display::Display;
+use proc_macro2::{LineColumn, TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use syn::punctuated::Punctuated;
+
+/// Methods which write to a vector's elements, or give access to write them
+const INIT_METHODS: &[&str] = &[
+    "as_mut_ptr",
+    "spare_capacity_mut",
+    "resize",
+    "resize_with",
+    "extend",
+    "extend_from_slice",
+    "push",
+    "fill",
+];
+
+/// Size in bytes of pointers and `usize`; the checks assume a 64-bit target
+const PTR_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Display)]
+pub enum SoundnessLint {
+    SetLenBeforeInit,
+    UnboundedLifetime,
+    TransmuteSizeMismatch,
+}
+
+impl SoundnessLint {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::SetLenBeforeInit => {
+                "`Vec::set_len` exposes elements which haven't been initialized"
+            }
+            Self::UnboundedLifetime => {
+                "`from_raw_parts` result returned with a lifetime not tied to any \
+                 argument"
+            }
+            Self::TransmuteSizeMismatch => {
+                "`transmute` to a larger type reads past the end of the source"
+            }
+        }
+    }
+
+    /// Where to read about the pattern
+    pub fn reference(&self) -> &'static str {
+        match self {
+            Self::SetLenBeforeInit => {
+                "https://doc.rust-lang.org/std/vec/struct.Vec.html#method.set_len"
+            }
+            Self::UnboundedLifetime => {
+                "https://doc.rust-lang.org/nomicon/unbounded-lifetimes.html"
+            }
+            Self::TransmuteSizeMismatch => {
+                "https://doc.rust-lang.org/std/mem/fn.transmute_copy.html"
+            }
+        }
+    }
+}
+
+/// A soundness lint triggered by an effect
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct SoundnessFinding {
+    pub lint: SoundnessLint,
+    pub effect: EffectInstance,
+}
+
+impl SoundnessFinding {
+    pub fn loc(&self) -> &SrcLoc {
+        self.effect.call_loc()
+    }
+}
+
+impl fmt::Display for SoundnessFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} in `{}` (see {})",
+            self.loc().to_quickfix(),
+            self.lint.description(),
+            self.effect.caller(),
+            self.lint.reference()
+        )
+    }
+}
+
+/// The last segment of the callee path, if the segment before it is one of
+/// the parents (or the path has a single segment)
+fn callee_name<'e>(effect: &'e EffectInstance, parents: &[&str]) -> Option<&'e str> {
+    let mut segments = effect.callee_path().rsplit("::");
+    let name = segments.next()?;
+    match segments.next() {
+        Some(parent) if !parents.contains(&parent) => None,
+        _ => Some(name),
+    }
+}
+
+/// Call each time the ident appears (at any depth) in the tokens on one of
+/// the lines, with the tokens at its level and its index in them
+fn for_each_ident<F>(tokens: TokenStream, ident: &str, lines: (usize, usize), f: &mut F)
+where
+    F: FnMut(&[TokenTree], usize),
+{
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            TokenTree::Ident(id) if id == ident => {
+                let line = id.span().start().line;
+                if lines.0 <= line && line <= lines.1 {
+                    f(&tokens, i);
+                }
+            }
+            TokenTree::Group(g) => for_each_ident(g.stream(), ident, lines, f),
+            _ => (),
+        }
+    }
+}
+
+fn is_punct(t: Option<&TokenTree>, c: char) -> bool {
+    matches!(t, Some(TokenTree::Punct(p)) if p.as_char() == c)
+}
+
+/// Method calls `recv.method` in the tokens, with their position
+fn method_calls(tokens: TokenStream, calls: &mut Vec<(String, String, LineColumn)>) {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    for (i, t) in tokens.iter().enumerate() {
+        if let TokenTree::Group(g) = t {
+            method_calls(g.stream(), calls);
+        }
+        if i < 2 || !is_punct(tokens.get(i - 1), '.') {
+            continue;
+        }
+        if let (TokenTree::Ident(recv), TokenTree::Ident(method)) = (&tokens[i - 2], t) {
+            calls.push((recv.to_string(), method.to_string(), method.span().start()));
+        }
+    }
+}
+
+fn lines(effect: &EffectInstance) -> (usize, usize) {
+    (effect.call_loc().start_line(), effect.call_loc().end_line())
+}
+
+fn before(a: LineColumn, b: LineColumn) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+/// Whether a `recv.set_len(len)` call on the effect's lines grows the vector
+/// without its elements being written first
+fn set_len_before_init(body: &syn::Block, effect: &EffectInstance) -> bool {
+    let mut calls = Vec::new();
+    method_calls(body.to_token_stream(), &mut calls);
+    let mut found = false;
+    let mut check = |tokens: &[TokenTree], i: usize| {
+        let (Some(TokenTree::Ident(recv)), Some(TokenTree::Group(args))) =
+            (i.checked_sub(2).and_then(|j| tokens.get(j)), tokens.get(i + 1))
+        else {
+            return;
+        };
+        // Shrinking (e.g. set_len(0) or set_len(len - 1)) is fine
+        let args = args.stream().to_string();
+        if args.trim() == "0" || args.contains('-') || args.contains("min") {
+            return;
+        }
+        let pos = tokens[i].span().start();
+        let recv = recv.to_string();
+        found |= !calls.iter().any(|(r, m, p)| {
+            *r == recv && INIT_METHODS.contains(&m.as_str()) && before(*p, pos)
+        });
+    };
+    for_each_ident(body.to_token_stream(), "set_len", lines(effect), &mut check);
+    found
+}
+
+/// The named lifetimes in the tokens
+fn lifetimes(tokens: TokenStream, res: &mut BTreeSet<String>) {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            TokenTree::Punct(p) if p.as_char() == '\'' => {
+                if let Some(TokenTree::Ident(l)) = tokens.get(i + 1) {
+                    res.insert(l.to_string());
+                }
+            }
+            TokenTree::Group(g) => lifetimes(g.stream(), res),
+            _ => (),
+        }
+    }
+}
+
+/// Whether the function returns a reference with a lifetime which is
+/// `'static` or doesn't appear in its arguments, including the self type of a
+/// method's impl block (e.g. `'a` in `impl<'a> Buf<'a>`)
+fn unbounded_lifetime(sig: &syn::Signature, self_ty: Option<&syn::Type>) -> bool {
+    let syn::ReturnType::Type(_, output) = &sig.output else {
+        return false;
+    };
+    let mut out = BTreeSet::new();
+    lifetimes(output.to_token_stream(), &mut out);
+    let mut inputs = BTreeSet::new();
+    for arg in &sig.inputs {
+        lifetimes(arg.to_token_stream(), &mut inputs);
+    }
+    if let (Some(self_ty), Some(_)) = (self_ty, sig.receiver()) {
+        lifetimes(self_ty.to_token_stream(), &mut inputs);
+    }
+    out.iter().any(|l| l != "_" && !inputs.contains(l))
+}
+
+/// Size in bytes of a primitive type, or an array of them
+fn type_size(ty: &syn::Type) -> Option<usize> {
+    match ty {
+        syn::Type::Path(p) => match p.path.get_ident()?.to_string().as_str() {
+            "u8" | "i8" | "bool" => Some(1),
+            "u16" | "i16" => Some(2),
+            "u32" | "i32" | "f32" | "char" => Some(4),
+            "u64" | "i64" | "f64" => Some(8),
+            "u128" | "i128" => Some(16),
+            "usize" | "isize" => Some(PTR_SIZE),
+            _ => None,
+        },
+        syn::Type::Array(a) => {
+            let syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(n), .. }) = &a.len
+            else {
+                return None;
+            };
+            Some(type_size(&a.elem)? * n.base10_parse::<usize>().ok()?)
+        }
+        syn::Type::Tuple(t) if t.elems.is_empty() => Some(0),
+        syn::Type::Paren(p) => type_size(&p.elem),
+        _ => None,
+    }
+}
+
+/// The type a pointer, reference, or Box points to
+fn pointee(ty: &syn::Type) -> Option<&syn::Type> {
+    match ty {
+        syn::Type::Reference(r) => Some(&r.elem),
+        syn::Type::Ptr(p) => Some(&p.elem),
+        syn::Type::Path(p) => {
+            let last = p.path.segments.last()?;
+            let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+                return None;
+            };
+            match args.args.first()? {
+                syn::GenericArgument::Type(t) if last.ident == "Box" => Some(t),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The type arguments of a turbofish `::<A, B>` starting at the tokens
+fn turbofish(tokens: &[TokenTree]) -> Option<Vec<syn::Type>> {
+    if !is_punct(tokens.first(), ':') || !is_punct(tokens.get(2), '<') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut end = None;
+    for (i, t) in tokens.iter().enumerate().skip(2) {
+        match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            // Not the end of a `->` in a fn type
+            TokenTree::Punct(p)
+                if p.as_char() == '>' && !is_punct(tokens.get(i - 1), '-') =>
+            {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let args = tokens[3..end?].iter().cloned().collect::<TokenStream>();
+    let parser = Punctuated::<syn::Type, syn::Token![,]>::parse_terminated;
+    Some(syn::parse::Parser::parse2(parser, args).ok()?.into_iter().collect())
+}
+
+/// Whether a `transmute::<A, B>` on the effect's lines reads more than the
+/// source: B points to a larger type than A for `transmute`, or B is larger
+/// than A for `transmute_copy`
+fn transmute_size_mismatch(
+    body: &syn::Block,
+    name: &str,
+    effect: &EffectInstance,
+) -> bool {
+    let mut found = false;
+    let mut check = |tokens: &[TokenTree], i: usize| {
+        let Some(types) = turbofish(&tokens[i + 1..]) else {
+            return;
+        };
+        let [src, dst] = &types[..] else {
+            return;
+        };
+        let (src, dst) = if name == "transmute" {
+            match (pointee(src), pointee(dst)) {
+                (Some(src), Some(dst)) => (type_size(src), type_size(dst)),
+                _ => return,
+            }
+        } else {
+            (type_size(src), type_size(dst))
+        };
+        found |= matches!((src, dst), (Some(src), Some(dst)) if dst > src);
+    };
+    for_each_ident(body.to_token_stream(), name, lines(effect), &mut check);
+    found
+}
+
+/// The soundness lint the effect triggers, given the function it is in and,
+/// for a function in an impl block, the block's self type
+pub fn check_effect(
+    sig: &syn::Signature,
+    self_ty: Option<&syn::Type>,
+    body: &syn::Block,
+    effect: &EffectInstance,
+) -> Option<SoundnessLint> {
+    if callee_name(effect, &["Vec"]) == Some("set_len") {
+        return set_len_before_init(body, effect)
+            .then_some(SoundnessLint::SetLenBeforeInit);
+    }
+    if let Some("from_raw_parts" | "from_raw_parts_mut") = callee_name(effect, &["slice"])
+    {
+        return unbounded_lifetime(sig, self_ty)
+            .then_some(SoundnessLint::UnboundedLifetime);
+    }
+    match callee_name(effect, &["mem"]) {
+        Some(name @ ("transmute" | "transmute_copy"))
+            if transmute_size_mismatch(body, name, effect) =>
+        {
+            Some(SoundnessLint::TransmuteSizeMismatch)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::Path;
+
+    /// The lint triggered by a call to the callee on the second line of the
+    /// function
+    fn check(callee: &str, src: &str) -> Option<SoundnessLint> {
+        let f = syn::parse_str::<syn::ItemFn>(src).unwrap();
+        check_in(callee, &f.sig, None, &f.block)
+    }
+
+    fn check_in(
+        callee: &str,
+        sig: &syn::Signature,
+        self_ty: Option<&syn::Type>,
+        body: &syn::Block,
+    ) -> Option<SoundnessLint> {
+        let callee = CanonicalPath::new(callee);
+        let site = syn::parse_str::<syn::Ident>("\nx").unwrap();
+        let effect = EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("my_crate::f"),
+            callee.clone(),
+            &site,
+            Effect::UnsafeCall(callee),
+        );
+        check_effect(sig, self_ty, body, &effect)
+    }
+
+    #[test]
+    fn test_set_len_before_init() {
+        let uninit = "fn f(n: usize) -> Vec<u8> {
+            let mut v = Vec::with_capacity(n); unsafe { v.set_len(n) }; v }";
+        assert_eq!(
+            check("alloc::vec::Vec::set_len", uninit),
+            Some(SoundnessLint::SetLenBeforeInit)
+        );
+        let init = "fn f(n: usize) -> Vec<u8> {
+            let mut v = Vec::with_capacity(n);
+            unsafe { ptr::write_bytes(v.as_mut_ptr(), 0, n); v.set_len(n) }; v }";
+        assert_eq!(check("alloc::vec::Vec::set_len", init), None);
+    }
+
+    #[test]
+    fn test_unbounded_lifetime() {
+        let unbounded = "fn f<'a>(p: *const u8, n: usize) -> &'a [u8] {
+            unsafe { slice::from_raw_parts(p, n) } }";
+        assert_eq!(
+            check("core::slice::from_raw_parts", unbounded),
+            Some(SoundnessLint::UnboundedLifetime)
+        );
+        let bounded = "fn f<'a>(&'a self) -> &'a [u8] {
+            unsafe { slice::from_raw_parts(self.p, self.n) } }";
+        assert_eq!(check("core::slice::from_raw_parts", bounded), None);
+
+        // The lifetimes of the impl's self type are tied to the receiver
+        let imp = syn::parse_str::<syn::ItemImpl>(
+            "impl<'a> Buf<'a> {
+                fn get(&self) -> &'a [u8] {
+                    unsafe { slice::from_raw_parts(self.p, self.n) } }
+                fn new(p: *const u8, n: usize) -> &'a [u8] {
+                    unsafe { slice::from_raw_parts(p, n) } } }",
+        )
+        .unwrap();
+        let lints = imp
+            .items
+            .iter()
+            .map(|item| match item {
+                syn::ImplItem::Fn(m) => check_in(
+                    "core::slice::from_raw_parts",
+                    &m.sig,
+                    Some(&imp.self_ty),
+                    &m.block,
+                ),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lints, [None, Some(SoundnessLint::UnboundedLifetime)]);
+    }
+
+    #[test]
+    fn test_transmute_size_mismatch() {
+        let larger = "fn f(x: &[u8; 4]) -> &u64 {
+            unsafe { mem::transmute::<&[u8; 4], &u64>(x) } }";
+        assert_eq!(
+            check("core::mem::transmute", larger),
+            Some(SoundnessLint::TransmuteSizeMismatch)
+        );
+        let same = "fn f(x: &[u8; 8]) -> &u64 {
+            unsafe { mem::transmute::<&[u8; 8], &u64>(x) } }";
+        assert_eq!(check("core::mem::transmute", same), None);
+    }
+}
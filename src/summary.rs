@@ -0,0 +1,122 @@
+//! One-line run summaries for quiet mode.
+//!
+//! With `-q`, the binaries print nothing but a single line of space-separated
+//! `key=value` fields when they finish, so runs can be scraped from logs:
+//! ```text
+//! cargo-scan bin=scan status=ok effects=3 FFICall=1 SinkCall=2 reason="done"
+//! ```
+//! `status` is `ok` or `error`, the effect counts are per effect kind, and
+//! `audited=<audited>/<total>` gives the audit coverage when there is an audit
+//! file. `ignored=<n>` counts the files skipped because of `.scanignore` or the
+//! config excludes. The `reason` always comes last and is quoted.
+
+use crate::audit_file::AuditFile;
+use crate::effect::{EffectInstance, EffectType};
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone)]
+pub struct Summary {
+    bin: &'static str,
+    effect_counts: Option<BTreeMap<String, usize>>,
+    coverage: Option<(usize, usize)>,
+    ignored_files: Option<usize>,
+    reason: Option<String>,
+}
+
+impl Summary {
+    pub fn new(bin: &'static str) -> Self {
+        Self {
+            bin,
+            effect_counts: None,
+            coverage: None,
+            ignored_files: None,
+            reason: None,
+        }
+    }
+
+    pub fn add_effects<'a, I>(&mut self, effects: I)
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let counts = self.effect_counts.get_or_insert_with(BTreeMap::new);
+        for e in effects {
+            *counts.entry(EffectType::from(e.eff_type()).to_string()).or_default() += 1;
+        }
+    }
+
+    /// Count the base effects of the audit file and record its coverage
+    pub fn add_audit(&mut self, audit_file: &AuditFile) {
+        self.add_effects(audit_file.audit_trees.keys());
+        let (audited, total) = self.coverage.unwrap_or_default();
+        let (new_audited, new_total) = audit_file.audit_coverage(None);
+        self.coverage = Some((audited + new_audited, total + new_total));
+    }
+
+    /// Count files skipped because of `.scanignore` or the config excludes
+    pub fn add_ignored_files(&mut self, n: usize) {
+        *self.ignored_files.get_or_insert(0) += n;
+    }
+
+    /// Why the run ended, for successful runs. Defaults to "done".
+    pub fn set_reason(&mut self, reason: impl Display) {
+        self.reason = Some(reason.to_string());
+    }
+
+    pub fn line<T, E: Display>(&self, res: &Result<T, E>) -> String {
+        let mut fields = vec![format!("bin={}", self.bin)];
+        let reason = match res {
+            Ok(_) => {
+                fields.push("status=ok".to_string());
+                self.reason.clone().unwrap_or_else(|| "done".to_string())
+            }
+            Err(e) => {
+                fields.push("status=error".to_string());
+                e.to_string()
+            }
+        };
+        if let Some(counts) = &self.effect_counts {
+            fields.push(format!("effects={}", counts.values().sum::<usize>()));
+            fields.extend(counts.iter().map(|(k, n)| format!("{}={}", k, n)));
+        }
+        if let Some((audited, total)) = self.coverage {
+            fields.push(format!("audited={}/{}", audited, total));
+        }
+        if let Some(n) = self.ignored_files {
+            fields.push(format!("ignored={}", n));
+        }
+        fields.push(format!("reason={:?}", reason));
+        format!("cargo-scan {}", fields.join(" "))
+    }
+
+    /// Print the summary line if running in quiet mode
+    pub fn print<T, E: Display>(&self, res: &Result<T, E>) {
+        if crate::util::verbosity().quiet {
+            println!("{}", self.line(res));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        let mut summary = Summary::new("scan");
+        summary.add_effects(std::iter::empty());
+        summary.set_reason("partially scanned");
+        let ok: Result<(), String> = Ok(());
+        assert_eq!(
+            summary.line(&ok),
+            "cargo-scan bin=scan status=ok effects=0 reason=\"partially scanned\""
+        );
+
+        let err: Result<(), String> = Err("no Cargo.toml".to_string());
+        assert_eq!(
+            Summary::new("audit").line(&err),
+            "cargo-scan bin=audit status=error reason=\"no Cargo.toml\""
+        );
+    }
+}
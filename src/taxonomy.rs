@@ -0,0 +1,145 @@
+//! User-defined effect kinds.
+//!
+//! The built-in effect taxonomy can be extended with a TOML file mapping
+//! callee patterns to new effect kinds, e.g.
+//! ```toml
+//! [[effect]]
+//! kind = "Crypto"
+//! patterns = ["ring", "openssl"]
+//! severity = "High"
+//! ```
+//! Calls matching one of the patterns are reported as `Effect::Custom`
+//! effects of that kind instead of sink calls, except those matching one of
+//! the kind's exclusions, written `!<pattern>` (e.g. `"!ring::rand"`). The
+//! custom effects are loaded with `CustomEffects::load`, and scans use those
+//! of their `ScanConfig`.
+
+use crate::effect::{CustomEffect, Effect, EffectInstance, Severity};
+use crate::ident::{CanonicalPath, PatternSet};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomEffectDef {
+    pub kind: String,
+    /// Callee path prefixes, like sink patterns
+    pub patterns: Vec<String>,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+}
+
+fn default_severity() -> Severity {
+    Severity::Medium
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CustomEffects {
+    #[serde(default, rename = "effect")]
+    pub effects: Vec<CustomEffectDef>,
+}
+
+impl CustomEffects {
+    /// Load the custom effect kinds defined in a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .context(format!("Couldn't read custom effects from {:?}", path))?;
+        Self::from_toml(&s)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let custom: Self = toml::from_str(s)?;
+        custom.validate()?;
+        Ok(custom)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for def in &self.effects {
+            if def.kind.is_empty() || def.patterns.is_empty() {
+                return Err(anyhow!(
+                    "Custom effect kinds need a name and at least one pattern"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The patterns of the kinds, as sinks. Exclusions (`!<pattern>`) only
+    /// apply to their kind, so they aren't sinks.
+    pub fn sink_patterns(&self) -> PatternSet {
+        PatternSet::from_strs(
+            self.effects
+                .iter()
+                .flat_map(|def| def.patterns.iter())
+                .filter(|p| PatternSet::negated(p).is_none())
+                .map(String::as_str),
+        )
+    }
+
+    /// Get the custom effect for a callee. If several patterns match, the
+    /// longest one wins; a kind doesn't match the callees its exclusions
+    /// match.
+    pub fn classify(&self, callee: &CanonicalPath) -> Option<CustomEffect> {
+        self.effects
+            .iter()
+            .flat_map(|def| {
+                let set = PatternSet::from_strs(def.patterns.iter().map(String::as_str));
+                set.matching(callee.as_path())
+                    .into_iter()
+                    .map(|p| (def, p.as_str().len()))
+                    .collect::<Vec<_>>()
+            })
+            .max_by_key(|&(_, len)| len)
+            .map(|(def, _)| CustomEffect {
+                kind: def.kind.clone(),
+                severity: def.severity,
+            })
+    }
+
+    /// Reclassify the sink calls which match a custom effect
+    pub fn classify_effects(&self, effects: &mut [EffectInstance]) {
+        for e in effects {
+            if let Effect::SinkCall(_) = e.eff_type() {
+                if let Some(custom) = self.classify(e.callee()) {
+                    e.set_eff_type(Effect::Custom(custom));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let custom = CustomEffects::from_toml(
+            r#"
+            [[effect]]
+            kind = "Network"
+            patterns = ["std::net", "!std::net::Ipv4Addr"]
+
+            [[effect]]
+            kind = "Tcp"
+            patterns = ["std::net::TcpStream"]
+            severity = "High"
+            "#,
+        )
+        .unwrap();
+
+        let tcp = custom.classify(&CanonicalPath::new("std::net::TcpStream::connect"));
+        assert_eq!(
+            tcp,
+            Some(CustomEffect { kind: "Tcp".to_string(), severity: Severity::High })
+        );
+        let udp = custom.classify(&CanonicalPath::new("std::net::UdpSocket::bind"));
+        let udp = udp.unwrap();
+        assert_eq!(udp.severity, Severity::Medium);
+        assert_eq!(Effect::Custom(udp).simple_str(), "[Network]");
+        assert_eq!(custom.classify(&CanonicalPath::new("std::fs::read")), None);
+        assert_eq!(custom.classify(&CanonicalPath::new("std::net::Ipv4Addr::new")), None);
+        assert_eq!(custom.sink_patterns().includes().len(), 2);
+    }
+}
@@ -0,0 +1,169 @@
+//! User-provided templates for rendering scan and audit results.
+//!
+//! Templates use the Jinja2 syntax (rendered with minijinja), e.g.
+//! ```text
+//! {% for e in effects %}
+//! {{ e.file }}:{{ e.line }} {{ e.effect_kind }} {{ e.callee }} ({{ e.status }})
+//! {% endfor %}
+//! ```
+//! Both scan and audit templates get `crate_path`, `effects` (one `EffectView`
+//! per effect) and `counts` (the number of effects of each kind). Scan
+//! templates also get the full scan artifact as `scan`, and audit templates
+//! the full audit file as `audit`.
+
+use crate::artifact::ScanArtifact;
+use crate::audit_file::{AuditFile, EffectTree, SafetyAnnotation};
+use crate::effect::{EffectInstance, EffectType};
+use crate::ratchet::effect_counts;
+use crate::remediation;
+
+use anyhow::{Context, Result};
+use minijinja::Environment;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// An effect, flattened for templates
+#[derive(Serialize, Debug, Clone)]
+pub struct EffectView<'a> {
+    pub crate_name: String,
+    pub caller: &'a str,
+    pub callee: &'a str,
+    pub effect_kind: String,
+    pub effect: Cow<'a, str>,
+    pub severity: String,
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    /// e.g. inside `.map()` closure in `process_files`
+    pub context: Option<String>,
+    pub receiver_ty: Option<String>,
+    /// A safer alternative to suggest, for common effects
+    pub remediation: Option<&'static str>,
+    pub snippet: Option<&'a str>,
+    /// Audit status; only set for audits
+    pub status: Option<String>,
+    pub justification: Option<&'a str>,
+    /// The full effect instance
+    pub raw: &'a EffectInstance,
+}
+
+impl<'a> EffectView<'a> {
+    pub fn new(effect: &'a EffectInstance) -> Self {
+        let loc = effect.call_loc();
+        Self {
+            crate_name: effect.caller().crate_name().to_string(),
+            caller: effect.caller_path(),
+            callee: effect.callee_path(),
+            effect_kind: EffectType::from(effect.eff_type()).to_string(),
+            effect: effect.eff_type().simple_str(),
+            severity: effect.eff_type().severity().to_string(),
+            file: loc.filepath_string(),
+            line: loc.start_line(),
+            col: loc.start_col(),
+            context: effect.closure_description(),
+            receiver_ty: effect.receiver_ty().map(|t| t.to_string()),
+            remediation: remediation::remediation(effect),
+            snippet: None,
+            status: None,
+            justification: None,
+            raw: effect,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScanContext<'a> {
+    pub crate_path: &'a Path,
+    pub effects: Vec<EffectView<'a>>,
+    pub counts: BTreeMap<String, usize>,
+    pub scan: &'a ScanArtifact,
+}
+
+impl<'a> ScanContext<'a> {
+    pub fn new(crate_path: &'a Path, scan: &'a ScanArtifact) -> Self {
+        let effects = scan
+            .effects
+            .iter()
+            .map(|r| EffectView {
+                snippet: r.snippet.as_deref(),
+                ..EffectView::new(&r.effect)
+            })
+            .collect();
+        let counts = effect_counts(scan.effects.iter().map(|r| &r.effect));
+        Self { crate_path, effects, counts, scan }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditContext<'a> {
+    pub crate_path: &'a Path,
+    /// Base effects of the audit, sorted by location
+    pub effects: Vec<EffectView<'a>>,
+    pub counts: BTreeMap<String, usize>,
+    /// Number of annotated effects, and the total
+    pub audited: usize,
+    pub total: usize,
+    pub audit: &'a AuditFile,
+}
+
+impl<'a> AuditContext<'a> {
+    pub fn new(crate_path: &'a Path, audit: &'a AuditFile) -> Self {
+        let mut base_effects = audit.audit_trees.iter().collect::<Vec<_>>();
+        base_effects.sort_by(|(e1, _), (e2, _)| e1.cmp(e2));
+        let effects = base_effects
+            .into_iter()
+            .map(|(e, tree)| {
+                let (status, justification) = match tree {
+                    EffectTree::Leaf(info, a) => {
+                        (*a, audit.justifications.get(info).map(|j| j.as_str()))
+                    }
+                    EffectTree::Branch(_, _) => (SafetyAnnotation::CallerChecked, None),
+                };
+                EffectView {
+                    status: Some(status.to_string()),
+                    justification,
+                    ..EffectView::new(e)
+                }
+            })
+            .collect();
+        let counts = effect_counts(audit.audit_trees.keys());
+        let (audited, total) = audit.audit_coverage(None);
+        Self { crate_path, effects, counts, audited, total, audit }
+    }
+}
+
+/// Render a template string with the given context
+pub fn render_str<S: Serialize>(template: &str, context: S) -> Result<String> {
+    let mut env = Environment::new();
+    env.add_template("report", template)?;
+    Ok(env.get_template("report")?.render(context)?)
+}
+
+/// Render the template in a file with the given context
+pub fn render_file<S: Serialize>(path: &Path, context: S) -> Result<String> {
+    let template = std::fs::read_to_string(path)
+        .context(format!("Failed to read template {:?}", path))?;
+    render_str(&template, context)
+        .context(format!("Failed to render template {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_scan() {
+        let scan = ScanArtifact::default();
+        let context = ScanContext::new(Path::new("my_crate"), &scan);
+        let out = render_str(
+            "{{ crate_path }}: {{ effects | length }} effects\
+            {% for k, n in counts | items %} {{ k }}={{ n }}{% endfor %}",
+            &context,
+        )
+        .unwrap();
+        assert_eq!(out, "my_crate: 0 effects");
+        assert!(render_str("{% for e in effects %}", &context).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Effect summaries of a crate's impls of widely-used traits, e.g.
+//! ```text
+//! impl futures::Future for mycrate::Download: 2 effects (1 FFICall, 1 SinkCall)
+//!   poll: 2 effects
+//!     mycrate::Download::poll -> libc::recv (src/lib.rs:40:13)
+//!     mycrate::Download::poll -> std::fs::write (src/lib.rs:52:9)
+//! ```
+//! Code using a crate mostly calls it through these traits (polling a
+//! future, reading from a reader, iterating), so the effects reachable from
+//! the methods of each impl answer "what happens when I poll this Future"
+//! directly, without going through the whole crate's effects.
+//!
+//! Impls are recognized by the last segment of the trait's path, so impls
+//! of `AsyncRead` from tokio and from futures are both summarized.
+
+use crate::effect::{EffectInstance, EffectType};
+use crate::ident::CanonicalPath;
+use crate::scanner::ScanResults;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The traits whose impls are summarized
+pub const SUMMARIZED_TRAITS: &[&str] = &[
+    "Read",
+    "Write",
+    "BufRead",
+    "Seek",
+    "Iterator",
+    "Future",
+    "Stream",
+    "AsyncRead",
+    "AsyncWrite",
+    "AsyncBufRead",
+    "AsyncSeek",
+];
+
+/// An impl of a trait for a type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct TraitImpl {
+    pub trait_path: CanonicalPath,
+    /// The implementing type, as a path if it is one
+    pub self_ty: String,
+}
+
+impl TraitImpl {
+    pub fn new(trait_path: CanonicalPath, self_ty: String) -> Self {
+        Self { trait_path, self_ty }
+    }
+
+    /// The trait's name, e.g. `Future` for `std::future::Future`
+    pub fn trait_name(&self) -> &str {
+        self.trait_path.as_str().rsplit("::").next().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for TraitImpl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "impl {} for {}", self.trait_path, self.self_ty)
+    }
+}
+
+/// The effects reachable from the methods of a trait impl
+#[derive(Debug, Clone)]
+pub struct TraitImplSummary<'a> {
+    pub trait_impl: &'a TraitImpl,
+    /// Each method of the impl, with the effects it reaches, by method name
+    pub methods: Vec<(&'a CanonicalPath, Vec<&'a EffectInstance>)>,
+}
+
+impl<'a> TraitImplSummary<'a> {
+    /// The effects reachable from any of the methods (counted once if
+    /// several reach them)
+    pub fn effects(&self) -> BTreeSet<&'a EffectInstance> {
+        self.methods.iter().flat_map(|(_, effects)| effects.iter().copied()).collect()
+    }
+
+    /// Number of effects of each kind
+    pub fn effect_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for e in self.effects() {
+            *counts.entry(EffectType::from(e.eff_type()).to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+impl fmt::Display for TraitImplSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = self.effect_counts();
+        write!(f, "{}: {} effects", self.trait_impl, self.effects().len())?;
+        if !counts.is_empty() {
+            let counts = counts
+                .iter()
+                .map(|(kind, n)| format!("{} {}", n, kind))
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(f, " ({})", counts)?;
+        }
+        for (meth, effects) in &self.methods {
+            let name = meth.as_str().rsplit("::").next().unwrap_or_default();
+            write!(f, "\n  {}: {} effects", name, effects.len())?;
+            for e in effects {
+                write!(f, "\n    {} -> {} ({})", e.caller(), e.callee(), e.call_loc())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The summaries of the crate's impls of the summarized traits, sorted by
+/// impl. Impls whose methods reach no effects are included, since that is
+/// an answer too.
+pub fn summarize(results: &ScanResults) -> Vec<TraitImplSummary> {
+    let mut by_impl: BTreeMap<&TraitImpl, Vec<&CanonicalPath>> = BTreeMap::new();
+    for (meth, trait_impl) in &results.trait_impl_meths {
+        by_impl.entry(trait_impl).or_default().push(meth);
+    }
+    by_impl
+        .into_iter()
+        .map(|(trait_impl, mut meths)| {
+            meths.sort();
+            let methods =
+                meths.into_iter().map(|m| (m, results.reachable_effects(m))).collect();
+            TraitImplSummary { trait_impl, methods }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::{Effect, SrcLoc};
+    use std::path::Path;
+
+    #[test]
+    fn test_summarize() {
+        // poll -> fetch -> libc::recv, and next has no effects
+        let mut results = ScanResults::new();
+        let future = TraitImpl::new(
+            CanonicalPath::new("std::future::Future"),
+            "c::Download".to_string(),
+        );
+        let iter = TraitImpl::new(
+            CanonicalPath::new("std::iter::Iterator"),
+            "c::Chunks".to_string(),
+        );
+        let mut node = |name: &str| {
+            let f = CanonicalPath::new(name);
+            let n = results.call_graph.add_node(f.clone());
+            results.node_idxs.insert(f, n);
+            n
+        };
+        let [poll, fetch, _] =
+            ["c::Download::poll", "c::fetch", "c::Chunks::next"].map(&mut node);
+        results.call_graph.add_edge(poll, fetch, SrcLoc::default());
+        results.trait_impl_meths.insert(CanonicalPath::new("c::Download::poll"), future);
+        results.trait_impl_meths.insert(CanonicalPath::new("c::Chunks::next"), iter);
+        let tokens = "recv".parse::<proc_macro2::TokenStream>().unwrap();
+        let site = tokens.into_iter().next().unwrap();
+        let recv = CanonicalPath::new("libc::recv");
+        results.effects.push(EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new("c::fetch"),
+            recv.clone(),
+            &site,
+            Effect::FFICall(recv),
+        ));
+
+        let summaries = summarize(&results);
+        assert_eq!(summaries.len(), 2);
+        let (future, iter) = (&summaries[0], &summaries[1]);
+        assert_eq!(future.trait_impl.trait_name(), "Future");
+        assert_eq!(future.effects().len(), 1);
+        assert_eq!(future.effect_counts(), BTreeMap::from([("FFICall".to_string(), 1)]));
+        assert!(future.to_string().starts_with(
+            "impl std::future::Future for c::Download: 1 effects (1 FFICall)\n  poll: 1 effects\n    c::fetch -> libc::recv"
+        ));
+        assert_eq!(
+            iter.to_string(),
+            "impl std::iter::Iterator for c::Chunks: 0 effects\n  next: 0 effects"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Effect treemaps: the effects of a crate as a hierarchy of crates, modules,
+//! functions, and effect kinds, weighted by the number of effects.
+//!
+//! The JSON export is in the format of `d3.hierarchy`, so it can be fed
+//! directly to d3 sunburst and treemap layouts (with `.sum(d => d.value)`):
+//! ```text
+//! {"name":"effects","children":[{"name":"mycrate","children":[
+//!   {"name":"net","children":[{"name":"fetch","children":[
+//!     {"name":"[FFI]","value":2,"severity":"High"}]}]}]}]}
+//! ```
+//! The static SVG treemap (in the HTML heatmap) uses a slice-and-dice layout,
+//! with the effects colored by severity.
+
+use crate::effect::{EffectInstance, Severity};
+
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TreemapNode {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreemapNode>,
+    /// Number of effects, for the effect kinds (the leaves)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<usize>,
+    /// Severity of the effect kind, for the leaves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+}
+
+impl TreemapNode {
+    fn named(name: &str) -> Self {
+        Self { name: name.to_string(), ..Default::default() }
+    }
+
+    fn child(&mut self, name: &str) -> &mut Self {
+        let i = match self.children.iter().position(|c| c.name == name) {
+            Some(i) => i,
+            None => {
+                self.children.push(Self::named(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[i]
+    }
+
+    /// Number of effects under the node
+    pub fn total(&self) -> usize {
+        self.value.unwrap_or(0) + self.children.iter().map(Self::total).sum::<usize>()
+    }
+
+    fn sort(&mut self) {
+        self.children.sort_by(|a, b| a.name.cmp(&b.name));
+        self.children.iter_mut().for_each(Self::sort);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Treemap {
+    pub root: TreemapNode,
+}
+
+impl Treemap {
+    pub fn new<'a, I>(effects: I) -> Self
+    where
+        I: IntoIterator<Item = &'a EffectInstance>,
+    {
+        let mut root = TreemapNode::named("effects");
+        for effect in effects {
+            let mut node = &mut root;
+            // crate, modules, and function
+            for segment in effect.caller_path().split("::") {
+                node = node.child(segment);
+            }
+            let leaf = node.child(&effect.eff_type().simple_str());
+            let severity = effect.eff_type().severity();
+            leaf.value = Some(leaf.value.unwrap_or(0) + 1);
+            leaf.severity = leaf.severity.max(Some(severity));
+        }
+        root.sort();
+        Self { root }
+    }
+
+    /// Render the treemap as an SVG image of the given size
+    pub fn to_svg(&self, width: f64, height: f64) -> String {
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+            viewBox=\"0 0 {w} {h}\" font-family=\"monospace\" font-size=\"10\">\n",
+            w = width,
+            h = height
+        );
+        let rect = Rect { x: 0.0, y: 0.0, w: width, h: height };
+        // The root is the same for all effects, so it isn't part of the paths
+        for (child, rect) in rect.split(&self.root.children, false) {
+            layout(child, rect, &child.name, true, &mut out);
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+impl Rect {
+    /// Split the rectangle among the nodes in proportion to their effects,
+    /// vertically or horizontally
+    fn split(self, nodes: &[TreemapNode], vertical: bool) -> Vec<(&TreemapNode, Rect)> {
+        let total = nodes.iter().map(TreemapNode::total).sum::<usize>().max(1) as f64;
+        let mut offset = 0.0;
+        let mut rects = Vec::new();
+        for node in nodes {
+            let share = node.total() as f64 / total;
+            let rect = if vertical {
+                let h = self.h * share;
+                Rect { y: self.y + offset, h, ..self }
+            } else {
+                let w = self.w * share;
+                Rect { x: self.x + offset, w, ..self }
+            };
+            offset += if vertical { rect.h } else { rect.w };
+            rects.push((node, rect));
+        }
+        rects
+    }
+}
+
+fn severity_color(severity: Option<Severity>) -> &'static str {
+    // Same colors as the HTML heatmap
+    match severity {
+        Some(Severity::Critical) => "#e05050",
+        Some(Severity::High) => "#f09090",
+        Some(Severity::Medium) => "#f5d76e",
+        Some(Severity::Low) | None => "#b5e0a0",
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn layout(node: &TreemapNode, rect: Rect, path: &str, vertical: bool, out: &mut String) {
+    if node.children.is_empty() {
+        let count = node.value.unwrap_or(0);
+        writeln!(
+            out,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+            fill=\"{}\" stroke=\"white\"><title>{}: {} effects</title></rect>",
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+            severity_color(node.severity),
+            xml_escape(path),
+            count
+        )
+        .unwrap();
+        // Label the effect kinds with room for it
+        if rect.w > 6.0 * node.name.len() as f64 + 6.0 && rect.h > 14.0 {
+            writeln!(
+                out,
+                "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                rect.x + 3.0,
+                rect.y + 12.0,
+                xml_escape(&node.name)
+            )
+            .unwrap();
+        }
+        return;
+    }
+    for (child, rect) in rect.split(&node.children, vertical) {
+        let path = format!("{}::{}", path, child.name);
+        layout(child, rect, &path, !vertical, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::ident::CanonicalPath;
+    use std::path::Path;
+
+    fn effect(caller: &str, callee: &str) -> EffectInstance {
+        let callee = CanonicalPath::new(callee);
+        EffectInstance::new_effect(
+            Path::new("src/lib.rs"),
+            CanonicalPath::new(caller),
+            callee.clone(),
+            &"f".parse::<proc_macro2::TokenStream>().unwrap(),
+            Effect::FFICall(callee),
+        )
+    }
+
+    #[test]
+    fn test_treemap() {
+        let effects = [
+            effect("mycrate::net::fetch", "libc::recv"),
+            effect("mycrate::net::fetch", "libc::send"),
+            effect("mycrate::init", "libc::getuid"),
+        ];
+        let treemap = Treemap::new(&effects);
+        assert_eq!(treemap.root.total(), 3);
+        let mycrate = &treemap.root.children[0];
+        assert_eq!(mycrate.name, "mycrate");
+        let names = mycrate.children.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["init", "net"]);
+        let fetch = &mycrate.children[1].children[0];
+        assert_eq!(fetch.children[0].value, Some(2));
+
+        let json = serde_json::to_string(&treemap).unwrap();
+        assert!(
+            json.starts_with("{\"name\":\"effects\",\"children\":[{\"name\":\"mycrate\"")
+        );
+        assert!(json.contains("{\"name\":\"[FFI]\",\"value\":2,\"severity\":\"High\"}"));
+
+        let svg = treemap.to_svg(300.0, 200.0);
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert!(svg.contains("<title>mycrate::net::fetch::[FFI]: 2 effects</title>"));
+    }
+}
@@ -1,18 +1,72 @@
 //! Utility functions
 
+use clap::{ArgAction, Args};
+use std::sync::OnceLock;
+
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// Output flags shared by all the binaries: verbosity, and how paths are shown
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct Verbosity {
+    /// Only print a one-line summary at the end (plus errors)
+    #[clap(short, long, default_value_t = false, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print more information: -v for info logs, -vv for debug logs
+    #[clap(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Show full paths, instead of paths relative to the crate root (or to the
+    /// registry cache for downloaded crates)
+    #[clap(long, default_value_t = false)]
+    pub absolute_paths: bool,
+}
+
+impl Verbosity {
+    fn log_level(&self) -> Option<&'static str> {
+        match (self.quiet, self.verbose) {
+            (true, _) => Some("error"),
+            (false, 0) => None,
+            (false, 1) => Some("info"),
+            (false, _) => Some("debug"),
+        }
+    }
+}
+
+/// The verbosity the process was started with (see `init_logging`)
+pub fn verbosity() -> Verbosity {
+    VERBOSITY.get().copied().unwrap_or_default()
+}
+
+/// Print a progress message, unless running in quiet mode
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::util::verbosity().quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
 /// Initialize logging for all of cargo_scan
 ///
-/// To change the log level, run with e.g.:
+/// To change the log level, pass -q, -v, or -vv, or run with e.g.:
 /// RUST_LOG=debug cargo run --bin scan ...
 /// RUST_LOG=info cargo run --bin scan ...
-pub fn init_logging() {
+/// The flags take precedence over RUST_LOG.
+pub fn init_logging(verbosity: &Verbosity) {
     use env_logger::Builder;
     use std::env;
 
+    let _ = VERBOSITY.set(*verbosity);
+
     // wish there was a nicer way to do this, env_logger doesn't make it easy
     // to disable non-cargo_scan logging
-    let filters = "warn,cargo_scan=".to_string()
-        + env::var("RUST_LOG").as_deref().unwrap_or("warn");
+    let level = match verbosity.log_level() {
+        Some(level) => level.to_string(),
+        None => env::var("RUST_LOG").unwrap_or_else(|_| "warn".to_string()),
+    };
+    let filters = "warn,cargo_scan=".to_string() + &level;
 
     Builder::new().parse_filters(&filters).init();
 }
@@ -39,6 +93,49 @@ pub mod csv {
     }
 }
 
+/// Displaying paths to source files
+pub mod paths {
+    use std::path::{Path, PathBuf};
+
+    /// The root of the crate containing the path: the closest directory with
+    /// a Cargo.toml
+    pub fn crate_root(path: &Path) -> Option<&Path> {
+        path.ancestors().find(|d| d.join("Cargo.toml").is_file())
+    }
+
+    /// The registry cache containing the path, if any: the directory for an
+    /// index in `~/.cargo/registry/src`, whose entries are downloaded crates
+    pub fn registry_root(path: &Path) -> Option<&Path> {
+        path.ancestors().find(|d| {
+            let parent = d.parent();
+            parent.and_then(|p| p.file_name()).map_or(false, |n| n == "src")
+                && parent
+                    .and_then(|p| p.parent())
+                    .and_then(|p| p.file_name())
+                    .map_or(false, |n| n == "registry")
+        })
+    }
+
+    fn absolute(path: &Path) -> PathBuf {
+        path.canonicalize().unwrap_or_else(|_| {
+            std::env::current_dir().map_or_else(|_| path.to_path_buf(), |d| d.join(path))
+        })
+    }
+
+    /// How to show a path to a source file: relative to the registry cache
+    /// for downloaded crates (e.g. `serde-1.0.188/src/de.rs`), relative to
+    /// the crate root otherwise (e.g. `src/de.rs`), or in full with
+    /// `--absolute-paths`
+    pub fn display_path(path: &Path) -> String {
+        if super::verbosity().absolute_paths {
+            return absolute(path).to_string_lossy().to_string();
+        }
+        let root = registry_root(path).or_else(|| crate_root(path));
+        let relative = root.and_then(|r| path.strip_prefix(r).ok());
+        relative.unwrap_or(path).to_string_lossy().to_string()
+    }
+}
+
 /// Iterator util
 pub mod iter {
     use log::warn;
@@ -69,16 +166,75 @@ pub mod iter {
 
 /// Filesystem util
 pub mod fs {
+    use anyhow::Result;
+    use clap::ValueEnum;
+    use log::warn;
+    use parse_display::Display;
+    use std::borrow::Cow;
     use std::fs::File;
     use std::io::{BufRead, BufReader};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use walkdir::{DirEntry, WalkDir};
 
-    pub fn walk_files(p: &PathBuf) -> impl Iterator<Item = PathBuf> {
+    /// Which symlinks the source walker follows. Packages can be
+    /// attacker-controlled, so by default none are: a symlink to a file
+    /// outside the crate (e.g. `src/x.rs -> /etc/shadow`) could otherwise
+    /// leak it into the scan results.
+    #[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Display)]
+    pub enum SymlinkPolicy {
+        /// Skip symlinks
+        #[default]
+        #[display("skip")]
+        Skip,
+        /// Follow symlinks which resolve to paths inside the walked directory
+        #[display("within-root")]
+        WithinRoot,
+        /// Follow all symlinks
+        #[display("all")]
+        All,
+    }
+
+    /// Whether the path is under the (canonical) root once symlinks are
+    /// resolved
+    pub fn resolves_within(path: &Path, root: &Path) -> bool {
+        path.canonicalize().map_or(false, |p| p.starts_with(root))
+    }
+
+    /// Whether a path under the root may be read under the symlink policy
+    /// (and is UTF-8), warning if not
+    pub fn is_allowed(path: &Path, root: &Path, symlinks: SymlinkPolicy) -> bool {
+        if path.to_str().is_none() {
+            warn!("Skipping non-UTF-8 path {:?}", path);
+            return false;
+        }
+        let is_symlink = path.symlink_metadata().map_or(false, |m| m.is_symlink());
+        match symlinks {
+            SymlinkPolicy::Skip if is_symlink => {
+                warn!("Skipping symlink {:?}", path);
+                false
+            }
+            SymlinkPolicy::WithinRoot if !resolves_within(path, root) => {
+                warn!("Skipping {:?}, which resolves outside {:?}", path, root);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// The files under the directory, following symlinks according to the
+    /// symlink policy. Symlink cycles are reported and skipped, as are paths
+    /// which aren't UTF-8, since they can't be shown faithfully in results.
+    pub fn walk_files(
+        p: &PathBuf,
+        symlinks: SymlinkPolicy,
+    ) -> impl Iterator<Item = PathBuf> {
         debug_assert!(p.is_dir());
+        let root = p.canonicalize().unwrap_or_else(|_| p.clone());
         WalkDir::new(p)
+            .follow_links(symlinks != SymlinkPolicy::Skip)
             .sort_by_file_name()
             .into_iter()
+            .filter_entry(move |entry| is_allowed(entry.path(), &root, symlinks))
             .filter_map(super::iter::warn_ok)
             .map(DirEntry::into_path)
     }
@@ -86,8 +242,9 @@ pub mod fs {
     pub fn walk_files_with_extension<'a>(
         p: &'a PathBuf,
         ext: &'a str,
+        symlinks: SymlinkPolicy,
     ) -> impl Iterator<Item = PathBuf> + 'a {
-        walk_files(p)
+        walk_files(p, symlinks)
             .filter(|entry| entry.is_file())
             .filter(|entry| entry.extension().map_or(false, |x| x.to_str() == Some(ext)))
     }
@@ -97,6 +254,157 @@ pub mod fs {
         let reader = BufReader::new(file).lines();
         reader.map(|line| line.unwrap())
     }
+
+    /// A source file, decoded leniently
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Source {
+        /// The contents, without a byte order mark and with `\n` line endings
+        pub text: String,
+        /// Set if the file wasn't UTF-8, with how it was decoded
+        pub warning: Option<String>,
+    }
+
+    impl Source {
+        pub fn decode(bytes: &[u8]) -> Self {
+            let utf16 = |be: bool| {
+                let units = bytes[2..]
+                    .chunks(2)
+                    .map(|c| match (c, be) {
+                        ([b1, b2], true) => u16::from_be_bytes([*b1, *b2]),
+                        ([b1, b2], false) => u16::from_le_bytes([*b1, *b2]),
+                        _ => char::REPLACEMENT_CHARACTER as u16,
+                    })
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&units)
+            };
+            let (text, warning) = match bytes {
+                [0xEF, 0xBB, 0xBF, rest @ ..] => (String::from_utf8_lossy(rest), None),
+                [0xFF, 0xFE, ..] => (utf16(false).into(), Some("decoded from UTF-16LE")),
+                [0xFE, 0xFF, ..] => (utf16(true).into(), Some("decoded from UTF-16BE")),
+                _ => (String::from_utf8_lossy(bytes), None),
+            };
+            let warning = match (&text, warning) {
+                (Cow::Owned(_), None) => Some("invalid UTF-8 replaced with U+FFFD"),
+                (_, warning) => warning,
+            };
+            Self { text: text.replace("\r\n", "\n"), warning: warning.map(String::from) }
+        }
+    }
+
+    /// Read a source file, tolerating byte order marks, CRLF line endings,
+    /// UTF-16, and invalid UTF-8, so that the scanner and the views of the
+    /// source agree on its lines
+    pub fn read_source(p: &Path) -> Result<Source> {
+        let source = Source::decode(&std::fs::read(p)?);
+        if let Some(warning) = &source.warning {
+            warn!("{:?}: {}", p, warning);
+        }
+        Ok(source)
+    }
+}
+
+/// Serde helpers for serializing hash-based collections in sorted order, so
+/// that serialized artifacts are deterministic and produce stable diffs
+pub mod sorted {
+    use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+    use serde::ser::{Serialize, Serializer};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::fmt;
+    use std::hash::Hash;
+    use std::marker::PhantomData;
+
+    /// Serialize a map as a sequence of (key, value) pairs sorted by key
+    pub fn serialize_pairs<K, V, S>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter().collect::<BTreeMap<_, _>>())
+    }
+
+    /// Serialize a map of sets as a sequence of (key, set) pairs, with both
+    /// the keys and the set elements sorted
+    pub fn serialize_set_pairs<K, T, S>(
+        map: &HashMap<K, HashSet<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        T: Ord + Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            map.iter()
+                .map(|(k, v)| (k, v.iter().collect::<BTreeSet<_>>()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    /// Serialize a map with its entries sorted by key
+    pub fn serialize_map<K, V, S>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+    }
+
+    /// Deserialize a map from a sequence of (key, value) pairs. A serialized
+    /// map is also accepted, for compatibility with older files.
+    pub fn deserialize_pairs<'de, K, V, D>(
+        deserializer: D,
+    ) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Eq + Hash + Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        struct PairsVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for PairsVisitor<K, V>
+        where
+            K: Eq + Hash + Deserialize<'de>,
+            V: Deserialize<'de>,
+        {
+            type Value = HashMap<K, V>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of key-value pairs")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut map = HashMap::new();
+                while let Some((k, v)) = seq.next_element::<(K, V)>()? {
+                    map.insert(k, v);
+                }
+                Ok(map)
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut map = HashMap::new();
+                while let Some((k, v)) = access.next_entry::<K, V>()? {
+                    map.insert(k, v);
+                }
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_any(PairsVisitor(PhantomData))
+    }
 }
 
 /// Parse Cargo TOML
@@ -108,10 +416,10 @@ use serde::de::{self, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::fs::read_to_string;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use toml::{self, value::Table};
 
-#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+#[derive(Eq, Hash, PartialEq, PartialOrd, Ord, Debug, Clone)]
 pub struct CrateId {
     pub crate_name: String,
     pub version: Version,
@@ -247,3 +555,113 @@ pub fn load_cargo_toml(crate_path: &Path) -> Result<CrateId> {
     debug!("Loaded: {:?}", result);
     Ok(result)
 }
+
+/// Whether the crate is a proc-macro crate (`proc-macro = true` in the
+/// [lib] section of its Cargo.toml), whose code runs at compile time
+pub fn is_proc_macro_crate(crate_path: &Path) -> Result<bool> {
+    let toml_string = read_to_string(crate_path.join("Cargo.toml"))?;
+    let cargo_toml =
+        toml::from_str::<Table>(&toml_string).context("Couldn't parse Cargo.toml")?;
+    Ok(lib_is_proc_macro(&cargo_toml))
+}
+
+/// The path of the crate's build script: `package.build` in its Cargo.toml,
+/// or build.rs by default. None if the manifest turns build scripts off with
+/// `build = false`; the file may not exist.
+pub fn build_script_path(crate_path: &Path) -> Result<Option<PathBuf>> {
+    let toml_string = read_to_string(crate_path.join("Cargo.toml"))?;
+    let cargo_toml =
+        toml::from_str::<Table>(&toml_string).context("Couldn't parse Cargo.toml")?;
+    Ok(package_build(&cargo_toml).map(|p| crate_path.join(p)))
+}
+
+fn package_build(cargo_toml: &Table) -> Option<PathBuf> {
+    match cargo_toml.get("package").and_then(|p| p.get("build")) {
+        Some(toml::Value::String(path)) => Some(PathBuf::from(path)),
+        Some(toml::Value::Boolean(false)) => None,
+        _ => Some(PathBuf::from("build.rs")),
+    }
+}
+
+fn lib_is_proc_macro(cargo_toml: &Table) -> bool {
+    let lib = cargo_toml.get("lib").and_then(|lib| lib.as_table());
+    // Cargo accepts the deprecated proc_macro spelling too
+    lib.and_then(|lib| lib.get("proc-macro").or_else(|| lib.get("proc_macro")))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_files_symlink_policies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let src = dir.join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("lib.rs"), "").unwrap();
+        std::fs::write(dir.join("outside.rs"), "").unwrap();
+        std::os::unix::fs::symlink(src.join("lib.rs"), src.join("alias.rs")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside.rs"), src.join("leak.rs")).unwrap();
+        // A cycle, which is skipped even when following symlinks
+        std::os::unix::fs::symlink(&src, src.join("loop")).unwrap();
+
+        let walk = |symlinks| {
+            fs::walk_files_with_extension(&src, "rs", symlinks).collect::<Vec<_>>()
+        };
+        assert_eq!(walk(fs::SymlinkPolicy::Skip), [src.join("lib.rs")]);
+        assert_eq!(
+            walk(fs::SymlinkPolicy::WithinRoot),
+            [src.join("alias.rs"), src.join("lib.rs")]
+        );
+        assert_eq!(
+            walk(fs::SymlinkPolicy::All),
+            [src.join("alias.rs"), src.join("leak.rs"), src.join("lib.rs")]
+        );
+    }
+
+    #[test]
+    fn test_decode_source() {
+        let decode = |bytes: &[u8]| fs::Source::decode(bytes);
+        let plain = decode(b"fn f() {}\r\nfn g() {}\n");
+        assert_eq!(plain.text, "fn f() {}\nfn g() {}\n");
+        assert_eq!(plain.warning, None);
+
+        let bom = decode(b"\xEF\xBB\xBFfn f() {}");
+        assert_eq!(bom.text, "fn f() {}");
+        assert_eq!(bom.warning, None);
+
+        let invalid = decode(b"// \xFF\nfn f() {}");
+        assert_eq!(invalid.text, "// \u{FFFD}\nfn f() {}");
+        assert!(invalid.warning.is_some());
+
+        let utf16 = decode(b"\xFF\xFEf\0n\0");
+        assert_eq!(utf16.text, "fn");
+        assert!(utf16.warning.is_some());
+    }
+
+    #[test]
+    fn test_lib_is_proc_macro() {
+        let toml = |s: &str| toml::from_str::<Table>(s).unwrap();
+        assert!(lib_is_proc_macro(&toml("[lib]\nproc-macro = true")));
+        assert!(lib_is_proc_macro(&toml("[lib]\nproc_macro = true")));
+        assert!(!lib_is_proc_macro(&toml("[lib]\nproc-macro = false")));
+        assert!(!lib_is_proc_macro(&toml("[package]\nname = \"foo\"")));
+    }
+
+    #[test]
+    fn test_package_build() {
+        let toml = |s: &str| toml::from_str::<Table>(s).unwrap();
+        let build = |s: &str| package_build(&toml(s));
+        assert_eq!(build("[package]\nname = \"foo\""), Some(PathBuf::from("build.rs")));
+        assert_eq!(
+            build("[package]\nbuild = \"build/main.rs\""),
+            Some(PathBuf::from("build/main.rs"))
+        );
+        assert_eq!(build("[package]\nbuild = true"), Some(PathBuf::from("build.rs")));
+        assert_eq!(build("[package]\nbuild = false"), None);
+    }
+}
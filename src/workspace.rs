@@ -0,0 +1,198 @@
+//! Partial scans of large workspaces: map the files changed since a git
+//! revision to the workspace members containing them, then add the members
+//! depending on those (directly or transitively), since their effects may
+//! change too. Scanning just these members makes CI scan time scale with
+//! the change instead of the repository.
+//!
+//! Dev-dependencies don't make a member affected: effects reached only
+//! from tests aren't part of the member's effects. A change to the
+//! workspace's own Cargo.toml or Cargo.lock affects every member.
+
+use anyhow::{anyhow, Context, Result};
+use cargo::core::dependency::DepKind;
+use cargo::core::Workspace;
+use cargo::util::config;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceMember {
+    pub name: String,
+    /// Directory of the member's Cargo.toml
+    pub path: PathBuf,
+    /// The other workspace members it depends on (not as a dev-dependency)
+    pub deps: Vec<String>,
+}
+
+/// The members of the workspace at `root`, with absolute paths
+pub fn read_members(root: &Path) -> Result<Vec<WorkspaceMember>> {
+    let manifest = root.canonicalize()?.join("Cargo.toml");
+    let config = config::Config::default()?;
+    let workspace = Workspace::new(&manifest, &config)?;
+    let names =
+        workspace.members().map(|p| p.name().to_string()).collect::<BTreeSet<_>>();
+    Ok(workspace
+        .members()
+        .map(|p| WorkspaceMember {
+            name: p.name().to_string(),
+            path: p.root().to_path_buf(),
+            deps: p
+                .dependencies()
+                .iter()
+                .filter(|d| d.kind() != DepKind::Development)
+                .map(|d| d.package_name().to_string())
+                .filter(|d| names.contains(d))
+                .collect(),
+        })
+        .collect())
+}
+
+/// The files changed since the git revision (including uncommitted changes
+/// and new files which aren't ignored), as absolute paths under `root`
+pub fn changed_files(root: &Path, since: &str) -> Result<Vec<PathBuf>> {
+    let root = root.canonicalize()?;
+    let mut changed = git_files(&root, &["diff", "--name-only", "--relative", since])?;
+    changed.extend(git_files(&root, &["ls-files", "--others", "--exclude-standard"])?);
+    Ok(changed)
+}
+
+/// Run a git command under `root` which lists files relative to it
+fn git_files(root: &Path, args: &[&str]) -> Result<Vec<PathBuf>> {
+    // -z, so that unusual file names aren't quoted
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .arg("-z")
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?
+        .split_terminator('\0')
+        .map(|f| root.join(f))
+        .collect())
+}
+
+/// The members affected by the changed files: the members containing them,
+/// and the members depending on those. Returned in workspace order.
+pub fn affected_members<'a>(
+    root: &Path,
+    members: &'a [WorkspaceMember],
+    changed: &[PathBuf],
+) -> Vec<&'a WorkspaceMember> {
+    let workspace_files = [root.join("Cargo.toml"), root.join("Cargo.lock")];
+    if changed.iter().any(|f| workspace_files.contains(f)) {
+        return members.iter().collect();
+    }
+
+    let mut affected = BTreeSet::new();
+    for file in changed {
+        // Members can be nested (e.g. the root package of the workspace), so
+        // a file belongs to the innermost member containing it
+        let owner = members
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| file.starts_with(&m.path))
+            .max_by_key(|(_, m)| m.path.components().count());
+        if let Some((i, _)) = owner {
+            affected.insert(i);
+        }
+    }
+
+    let mut dependents: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, m) in members.iter().enumerate() {
+        for dep in &m.deps {
+            dependents.entry(dep.as_str()).or_default().push(i);
+        }
+    }
+    let mut queue = affected.iter().copied().collect::<VecDeque<_>>();
+    while let Some(i) = queue.pop_front() {
+        for &j in dependents.get(members[i].name.as_str()).into_iter().flatten() {
+            if affected.insert(j) {
+                queue.push_back(j);
+            }
+        }
+    }
+    affected.into_iter().map(|i| &members[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, path: &str, deps: &[&str]) -> WorkspaceMember {
+        WorkspaceMember {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn names(members: Vec<&WorkspaceMember>) -> Vec<&str> {
+        members.into_iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_affected_members() {
+        let root = Path::new("/repo");
+        let members = [
+            member("app", "/repo", &["server"]),
+            member("core", "/repo/crates/core", &[]),
+            member("server", "/repo/crates/server", &["core"]),
+            member("cli", "/repo/crates/cli", &["core"]),
+            member("tools", "/repo/crates/tools", &[]),
+        ];
+        let affected = |files: &[&str]| {
+            let files = files.iter().map(PathBuf::from).collect::<Vec<_>>();
+            names(affected_members(root, &members, &files))
+        };
+
+        assert_eq!(
+            affected(&["/repo/crates/core/src/lib.rs"]),
+            ["app", "core", "server", "cli"]
+        );
+        assert_eq!(affected(&["/repo/crates/server/src/main.rs"]), ["app", "server"]);
+        assert_eq!(affected(&["/repo/crates/tools/Cargo.toml"]), ["tools"]);
+        assert_eq!(affected(&["/repo/src/main.rs"]), ["app"]);
+        assert!(affected(&[]).is_empty());
+        assert_eq!(affected(&["/repo/Cargo.lock"]).len(), members.len());
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        std::fs::write(dir.join("lib.rs"), "fn f() {}").unwrap();
+        std::fs::write(dir.join(".gitignore"), "target\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "init"]);
+        std::fs::write(dir.join("lib.rs"), "fn g() {}").unwrap();
+        std::fs::write(dir.join("new \"file\".rs"), "").unwrap();
+        std::fs::create_dir(dir.join("target")).unwrap();
+        std::fs::write(dir.join("target/out.rs"), "").unwrap();
+
+        let mut changed = changed_files(dir, "HEAD").unwrap();
+        changed.sort();
+        let root = dir.canonicalize().unwrap();
+        assert_eq!(changed, [root.join("lib.rs"), root.join("new \"file\".rs")]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Golden-file (snapshot) tests of the scan output formats, on the fixture
+//! crates in data/test-packages/snapshots, which exercise each effect kind.
+//!
+//! Each output is compared with its snapshot, tests/snapshots/<fixture>.<format>.snap.
+//! If it changed, the new output is saved next to the snapshot as
+//! `.snap.new` for review (e.g. with `diff`) and the test fails. To accept
+//! the new outputs, run
+//! ```text
+//! UPDATE_SNAPSHOTS=1 cargo test --test snapshot_test
+//! ```
+//! and commit the snapshots. Missing snapshots fail the test too (their output
+//! is saved as `.snap.new`), so a snapshot that wasn't committed can't pass
+//! by being created; run with `UPDATE_SNAPSHOTS=1` to create them.
+//!
+//! New output formats are added to `Format`, and new fixtures with a test
+//! calling `check_fixture`.
+
+use anyhow::{anyhow, Result};
+use assert_cmd::prelude::*;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const FIXTURES: &str = "data/test-packages/snapshots";
+const SNAPSHOTS: &str = "tests/snapshots";
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Csv,
+    Text,
+    Quickfix,
+    Json,
+    Sarif,
+    Ndjson,
+    Html,
+}
+
+impl Format {
+    const ALL: &'static [Format] = &[
+        Format::Csv,
+        Format::Text,
+        Format::Quickfix,
+        Format::Json,
+        Format::Sarif,
+        Format::Ndjson,
+        Format::Html,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Text => "text",
+            Format::Quickfix => "quickfix",
+            Format::Json => "json",
+            Format::Sarif => "sarif",
+            Format::Ndjson => "ndjson",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// Run the scan binary in the repository root, returning its stdout
+fn run_scan(args: &[&str]) -> Result<String> {
+    let output = Command::cargo_bin("scan")?.args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "scan {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The scan output for the fixture in the format. Things which change
+/// without the output format changing (the scanner version, and the
+/// absolute paths of the checkout and toolchain in the call graph) are
+/// redacted.
+fn render(fixture: &str, args: &[&str], format: Format) -> Result<String> {
+    let crate_path = format!("{}/{}", FIXTURES, fixture);
+    let mut scan_args = vec![crate_path.as_str()];
+    scan_args.extend(args);
+    match format {
+        Format::Json => {
+            let tmp = tempfile::tempdir()?;
+            let json_path = tmp.path().join(format!("{}.json", fixture));
+            let json_arg = json_path.to_string_lossy().to_string();
+            scan_args.extend(["-q", "--json", &json_arg]);
+            run_scan(&scan_args)?;
+            let mut value: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+            if let Some(manifest) = value.get_mut("manifest") {
+                manifest["scanner_version"] = "[version]".into();
+            }
+            let root = format!("{}/", env::current_dir()?.display());
+            redact_paths(&mut value, &root);
+            Ok(serde_json::to_string_pretty(&value)? + "\n")
+        }
+        Format::Sarif => {
+            scan_args.extend(["--format", "sarif"]);
+            let mut value: serde_json::Value =
+                serde_json::from_str(&run_scan(&scan_args)?)?;
+            for run in value["runs"].as_array_mut().into_iter().flatten() {
+                run["tool"]["driver"]["version"] = "[version]".into();
+            }
+            Ok(serde_json::to_string_pretty(&value)? + "\n")
+        }
+        Format::Ndjson => {
+            scan_args.extend(["--format", "ndjson"]);
+            let root = format!("{}/", env::current_dir()?.display());
+            let mut output = String::new();
+            for line in run_scan(&scan_args)?.lines() {
+                let mut value: serde_json::Value = serde_json::from_str(line)?;
+                redact_paths(&mut value, &root);
+                output += &(serde_json::to_string(&value)? + "\n");
+            }
+            Ok(output)
+        }
+        format => {
+            scan_args.extend(["--format", format.name()]);
+            run_scan(&scan_args)
+        }
+    }
+}
+
+/// Make the locations in the checkout relative to it, and replace the ones
+/// outside it (e.g. in the standard library) with `[external]`
+fn redact_paths(value: &mut serde_json::Value, root: &str) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(dir) = map.get("dir").and_then(|d| d.as_str()).map(str::to_string)
+            {
+                match dir.strip_prefix(root) {
+                    Some(rel) => map["dir"] = rel.into(),
+                    None if dir.starts_with('/') => {
+                        *value = "[external]".into();
+                        return;
+                    }
+                    None => (),
+                }
+            }
+            map.values_mut().for_each(|v| redact_paths(v, root));
+        }
+        serde_json::Value::Array(values) => {
+            values.iter_mut().for_each(|v| redact_paths(v, root))
+        }
+        _ => (),
+    }
+}
+
+/// Compare the output with its snapshot (see above)
+fn assert_snapshot(name: &str, output: &str) -> Result<()> {
+    let path = Path::new(SNAPSHOTS).join(format!("{}.snap", name));
+    let new_path = Path::new(SNAPSHOTS).join(format!("{}.snap.new", name));
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let expected = fs::read_to_string(&path).ok();
+    if expected.as_deref() == Some(output) {
+        let _ = fs::remove_file(&new_path);
+        return Ok(());
+    }
+
+    fs::create_dir_all(SNAPSHOTS)?;
+    if update {
+        fs::write(&path, output)?;
+        let _ = fs::remove_file(&new_path);
+        eprintln!("Saved snapshot {:?}", path);
+        return Ok(());
+    }
+    fs::write(&new_path, output)?;
+    let Some(expected) = expected else {
+        return Err(anyhow!(
+            "Missing snapshot {:?}; review {:?}, and run with UPDATE_SNAPSHOTS=1 \
+            to accept it",
+            path,
+            new_path
+        ));
+    };
+    let line = expected
+        .lines()
+        .zip(output.lines())
+        .position(|(e, o)| e != o)
+        .unwrap_or_else(|| expected.lines().count().min(output.lines().count()));
+    Err(anyhow!(
+        "Snapshot {} changed from line {}; compare {:?} with {:?}, and run with \
+        UPDATE_SNAPSHOTS=1 to accept the new output",
+        name,
+        line + 1,
+        path,
+        new_path
+    ))
+}
+
+/// Check every output format of a scan of the fixture against the snapshots
+fn check_fixture(fixture: &str, args: &[&str]) -> Result<()> {
+    let mut failures = Vec::new();
+    for format in Format::ALL {
+        let output = render(fixture, args, *format)?;
+        if let Err(err) =
+            assert_snapshot(&format!("{}.{}", fixture, format.name()), &output)
+        {
+            failures.push(err.to_string());
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(failures.join("\n")))
+    }
+}
+
+#[test]
+fn snapshot_sink_calls() -> Result<()> {
+    check_fixture("sink-calls", &[])
+}
+
+#[test]
+fn snapshot_ffi() -> Result<()> {
+    check_fixture("ffi", &["--effect-types", "ffi"])
+}
+
+#[test]
+fn snapshot_unsafe_ops() -> Result<()> {
+    check_fixture("unsafe-ops", &["--effect-types", "unsafe"])
+}
+
+#[test]
+fn snapshot_pointers() -> Result<()> {
+    check_fixture(
+        "pointers",
+        &["--effect-types", "raw-pointer,FnPtrCreation,ClosureCreation"],
+    )
+}
+
+#[test]
+fn snapshot_memory() -> Result<()> {
+    check_fixture("memory", &["--effect-types", "UninitMemory,AliasEscape,Atomic"])
+}
+
+#[test]
+fn snapshot_custom() -> Result<()> {
+    check_fixture(
+        "custom",
+        &[
+            "--custom-effects",
+            "data/test-packages/snapshots/custom/effects.toml",
+            "--effect-types",
+            "sinks",
+        ],
+    )
+}
+
+#[test]
+fn snapshot_malware() -> Result<()> {
+    check_fixture("malware", &["--effect-types", "malware"])
+}